@@ -0,0 +1,320 @@
+//! `--listen`: accept NDJSON streams from any number of producers over TCP
+//! or a Unix socket instead of stdin. Each connection is read on its own
+//! task, so a slow producer never holds up the others, and lines from one
+//! producer keep their order. TCP producers beyond loopback must open with
+//! the bridge's token.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, warn};
+
+/// Longest line accepted; a producer sending more is disconnected
+pub const MAX_LINE: usize = 1024 * 1024;
+
+/// Producers connected at once to one address; more are turned away
+pub const MAX_CONNECTIONS: usize = 256;
+
+/// How long a TCP producer has to send the token
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to accept producers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listen {
+    /// `tcp://HOST:PORT`, or `tcp://:PORT` for loopback
+    Tcp(String),
+    /// `unix:///PATH`
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = value.strip_prefix("tcp://") {
+            if let Some(port) = addr.strip_prefix(':') {
+                if port.parse::<u16>().is_ok() {
+                    return Ok(Listen::Tcp(format!("127.0.0.1:{}", port)));
+                }
+            } else if addr
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+            {
+                return Ok(Listen::Tcp(addr.to_string()));
+            }
+        } else if let Some(path) = value.strip_prefix("unix://") {
+            if path.starts_with('/') {
+                return Ok(Listen::Unix(PathBuf::from(path)));
+            }
+        }
+        Err("expected tcp://HOST:PORT, tcp://:PORT or unix:///PATH".to_string())
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Listen::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Next line without its terminator, or `None` at end of stream
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE {
+        bail!("line exceeds the {} byte limit", MAX_LINE);
+    }
+    let line = String::from_utf8(line)?;
+    Ok(Some(line.trim_end().to_string()))
+}
+
+/// Pass every line from `reader` to `tx` until the producer disconnects
+pub async fn read_lines<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    peer: String,
+    tx: mpsc::Sender<String>,
+) {
+    loop {
+        match read_line(&mut reader).await {
+            Ok(Some(line)) => {
+                if tx.send(line).await.is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {
+                debug!("{} disconnected", peer);
+                return;
+            }
+            Err(e) => {
+                warn!("Closing connection from {}: {}", peer, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Wait for a producer's first line and check it is `token`, in constant
+/// time so the token cannot be guessed byte by byte
+async fn authenticate<R: AsyncBufRead + Unpin>(reader: &mut R, token: &str) -> Result<()> {
+    match tokio::time::timeout(TOKEN_TIMEOUT, read_line(reader)).await {
+        Ok(Ok(Some(line))) if bool::from(line.as_bytes().ct_eq(token.as_bytes())) => Ok(()),
+        Ok(Err(e)) => Err(e),
+        Err(_) => bail!("no token within {} seconds", TOKEN_TIMEOUT.as_secs()),
+        Ok(_) => bail!("wrong token"),
+    }
+}
+
+/// A connection slot for `peer`, unless `MAX_CONNECTIONS` are taken
+fn claim(slots: &Arc<Semaphore>, peer: &str) -> Option<OwnedSemaphorePermit> {
+    let slot = slots.clone().try_acquire_owned().ok();
+    if slot.is_none() {
+        warn!(
+            "Refusing {}: {} producers are already connected",
+            peer, MAX_CONNECTIONS
+        );
+    }
+    slot
+}
+
+async fn serve_tcp(listener: TcpListener, token: Option<Arc<str>>, tx: mpsc::Sender<String>) {
+    let slots = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let peer = peer.to_string();
+                let Some(slot) = claim(&slots, &peer) else {
+                    continue;
+                };
+                debug!("Producer connected from {}", peer);
+                let (token, tx) = (token.clone(), tx.clone());
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    if let Some(token) = token {
+                        if let Err(e) = authenticate(&mut reader, &token).await {
+                            warn!("Closing connection from {}: {}", peer, e);
+                            return;
+                        }
+                    }
+                    read_lines(reader, peer, tx).await;
+                    drop(slot);
+                });
+            }
+            Err(e) => warn!("Accept failed: {}", e),
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn serve_unix(listener: tokio::net::UnixListener, path: PathBuf, tx: mpsc::Sender<String>) {
+    let slots = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    let mut connections = 0u64;
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                // Unix peers are unnamed; number them for the logs
+                connections += 1;
+                let peer = format!("{}#{}", path.display(), connections);
+                let Some(slot) = claim(&slots, &peer) else {
+                    continue;
+                };
+                debug!("Producer connected on {}", peer);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    read_lines(BufReader::new(stream), peer, tx).await;
+                    drop(slot);
+                });
+            }
+            Err(e) => warn!("Accept failed: {}", e),
+        }
+    }
+}
+
+/// Bind `listen` and feed lines from every producer that connects into `tx`.
+/// With a `token`, TCP producers must send it as their first line; without
+/// one, TCP is only accepted on loopback.
+pub async fn start(listen: &Listen, token: Option<&str>, tx: mpsc::Sender<String>) -> Result<()> {
+    match listen {
+        Listen::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            let local = listener.local_addr()?;
+            if token.is_none() && !local.ip().is_loopback() {
+                bail!(
+                    "GUARDIAN_BRIDGE_TOKEN must be set to accept producers on {}",
+                    local
+                );
+            }
+            info!("Accepting events on tcp://{}", local);
+            tokio::spawn(serve_tcp(listener, token.map(Arc::from), tx));
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            // Anyone connected can write to the database
+            let listener = guardian_common::ipc::bind_private(path)?;
+            info!("Accepting events on {}", listen);
+            tokio::spawn(serve_unix(listener, path.clone(), tx));
+        }
+        #[cfg(not(unix))]
+        Listen::Unix(_) => bail!("{} needs Unix domain sockets", listen),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "tcp://0.0.0.0:7070".parse(),
+            Ok(Listen::Tcp("0.0.0.0:7070".to_string()))
+        );
+        assert_eq!(
+            "tcp://[::1]:7070".parse(),
+            Ok(Listen::Tcp("[::1]:7070".to_string()))
+        );
+        assert_eq!(
+            "tcp://:7070".parse(),
+            Ok(Listen::Tcp("127.0.0.1:7070".to_string()))
+        );
+        assert_eq!(
+            "unix:///run/guardian/bridge.sock".parse(),
+            Ok(Listen::Unix(PathBuf::from("/run/guardian/bridge.sock")))
+        );
+        for bad in [
+            "0.0.0.0:7070",
+            "tcp://0.0.0.0",
+            "tcp://:http",
+            "unix://bridge.sock",
+            "udp://x:1",
+        ] {
+            assert!(bad.parse::<Listen>().is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_concurrent_producers() {
+        use tokio::io::AsyncWriteExt;
+
+        let path =
+            std::env::temp_dir().join(format!("guardian-bridge-{}.sock", std::process::id()));
+        let (tx, mut rx) = mpsc::channel(16);
+        start(&Listen::Unix(path.clone()), None, tx).await.unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Two producers connected at once, each sending in turn
+        let mut first = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let mut second = tokio::net::UnixStream::connect(&path).await.unwrap();
+        first.write_all(b"{\"a\":1}\n").await.unwrap();
+        second.write_all(b"{\"b\":1}\n{\"b\":2}\n").await.unwrap();
+        first.write_all(b"{\"a\":2}\r\n").await.unwrap();
+        drop((first, second));
+
+        let mut lines = Vec::new();
+        for _ in 0..4 {
+            lines.push(rx.recv().await.unwrap());
+        }
+        let from = |producer: &str| -> Vec<&String> {
+            lines
+                .iter()
+                .filter(|line| line.contains(producer))
+                .collect()
+        };
+        assert_eq!(from("\"a\""), ["{\"a\":1}", "{\"a\":2}"]);
+        assert_eq!(from("\"b\""), ["{\"b\":1}", "{\"b\":2}"]);
+
+        // A line past the limit disconnects the producer
+        let mut flood = tokio::net::UnixStream::connect(&path).await.unwrap();
+        flood.write_all(&vec![b'x'; MAX_LINE + 1]).await.unwrap();
+        // The bridge may already have hung up
+        let _ = flood.write_all(b"\n{\"c\":1}\n").await;
+        let _ = flood.shutdown().await;
+        let next = tokio::time::timeout(std::time::Duration::from_millis(200), rx.recv()).await;
+        assert!(next.is_err(), "received {:?}", next);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tcp_token() {
+        let (tx, _rx) = mpsc::channel(16);
+        let open = Listen::Tcp("0.0.0.0:0".to_string());
+        assert!(start(&open, None, tx.clone()).await.is_err());
+        start(&open, Some("s3cret"), tx.clone()).await.unwrap();
+        start(&"tcp://:0".parse().unwrap(), None, tx).await.unwrap();
+
+        let mut good: &[u8] = b"s3cret\n{\"a\":1}\n";
+        authenticate(&mut good, "s3cret").await.unwrap();
+        assert_eq!(
+            read_line(&mut good).await.unwrap().as_deref(),
+            Some("{\"a\":1}")
+        );
+        let mut bad: &[u8] = b"{\"a\":1}\n";
+        assert!(authenticate(&mut bad, "s3cret").await.is_err());
+        let mut close: &[u8] = b"s3cres\n";
+        assert!(authenticate(&mut close, "s3cret").await.is_err());
+        let mut empty: &[u8] = b"";
+        assert!(authenticate(&mut empty, "s3cret").await.is_err());
+    }
+}
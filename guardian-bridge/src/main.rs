@@ -1,13 +1,52 @@
-use anyhow::Result;
-use guardian_common::LogEvent;
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
-use std::io::{self, BufRead};
+mod listen;
+mod spill;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use guardian_common::store::{self, EventRow, PayloadEncoder};
+use guardian_common::{AgentHello, LogEvent, StatsRollup};
+use listen::Listen;
+use spill::SpillQueue;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+use sqlx::SqlitePool;
 use std::path::PathBuf;
-use tracing::{error, info};
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// SQLite's result code for a file that is not a database, which is how a
+/// wrong SQLCipher key shows up
+const SQLITE_NOTADB: &str = "26";
+
+/// Primary SQLite result codes that pass once the database is reachable
+/// again: another writer holds it (`BUSY`, `LOCKED`) or the file cannot be
+/// read or opened (`IOERR`, `CANTOPEN`)
+const SQLITE_TRANSIENT: [i32; 4] = [5, 6, 10, 14];
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "guardian-bridge",
+    version,
+    about = "Store Guardian events from stdin or producers in the Sentinel database"
+)]
+struct Cli {
+    /// Accept NDJSON from producers at tcp://HOST:PORT, tcp://:PORT
+    /// (loopback) or unix:///PATH instead of reading stdin; repeat to listen
+    /// on several. TCP beyond loopback needs GUARDIAN_BRIDGE_TOKEN.
+    #[arg(long, value_name = "URL")]
+    listen: Vec<Listen>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -19,80 +58,557 @@ async fn main() -> Result<()> {
     info!("Guardian Event Bridge starting...");
 
     // Get database path from environment or use default
-    let db_path = std::env::var("GUARDIAN_DB_PATH")
-        .unwrap_or_else(|_| {
-            let home = std::env::var("HOME").expect("HOME not set");
-            format!("{}/.local/share/com.guardian.sentinel/guardian.db", home)
-        });
+    let db_path = std::env::var("GUARDIAN_DB_PATH").unwrap_or_else(|_| {
+        let home = std::env::var("HOME").expect("HOME not set");
+        format!("{}/.local/share/com.guardian.sentinel/guardian.db", home)
+    });
+    let db_path_buf = PathBuf::from(&db_path);
 
-    info!("Connecting to database: {}", db_path);
+    // Same settings as Sentinel so both can write at once
+    let options = SqliteConnectOptions::new()
+        .filename(&db_path_buf)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(Duration::from_secs(5))
+        .pragma("mmap_size", (256 * 1024 * 1024).to_string());
+    let key = guardian_common::dbkey::database_key()
+        .context("Failed to read the database encryption key")?;
+    let options = match &key {
+        Some(key) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+        None => options,
+    };
+    // With a signing key set, events and rollups the agent didn't sign are dropped
+    let signer =
+        guardian_common::EventSigner::from_env().context("Failed to read the event signing key")?;
+    let database = Database {
+        path: db_path_buf.clone(),
+        options,
+        keyed: key.is_some(),
+    };
+    let encoder = store::compression_enabled().then(|| {
+        info!("Compressing event payloads");
+        PayloadEncoder::new()
+    });
 
-    // Ensure parent directory exists
-    let db_path_buf = PathBuf::from(&db_path);
-    if let Some(parent) = db_path_buf.parent() {
-        tokio::fs::create_dir_all(parent).await?;
+    // Records the database could not take wait here, next to it by default
+    let spill_dir = std::env::var_os("GUARDIAN_SPILL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| db_path_buf.with_file_name("bridge-spill"));
+    let mut spill = SpillQueue::open(&spill_dir)
+        .with_context(|| format!("Failed to open the spill queue in {}", spill_dir.display()))?;
+    if !spill.is_empty() {
+        info!(
+            "{} records spilled by an earlier run are stored first",
+            spill.len()
+        );
+    }
+    if key.is_some() {
+        warn!(
+            "The database is encrypted, so records it cannot take are dropped \
+             rather than spilled to {} unencrypted",
+            spill_dir.display()
+        );
+        spill.seal();
     }
 
-    // Connect to database
-    let db_url = format!("sqlite://{}?mode=rwc", db_path);
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&db_url)
-        .await?;
+    // Connect in the loop, so stdin is spilled rather than lost until the
+    // database can be reached
+    let mut pool = None;
+    let mut backoff = Backoff::new();
 
-    info!("Database connected successfully");
+    // Lines from stdin, or from every connected producer, in arrival order
+    let (lines_tx, mut lines) = mpsc::channel::<String>(1000);
+    if cli.listen.is_empty() {
+        tokio::spawn(listen::read_lines(
+            BufReader::new(tokio::io::stdin()),
+            "stdin".to_string(),
+            lines_tx,
+        ));
+    } else {
+        let token = std::env::var("GUARDIAN_BRIDGE_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        for listen in &cli.listen {
+            listen::start(listen, token.as_deref(), lines_tx.clone())
+                .await
+                .with_context(|| format!("Failed to listen on {}", listen))?;
+        }
+        drop(lines_tx);
+    }
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
 
-    // Read JSON events from stdin and store in database
-    let stdin = io::stdin();
-    let reader = stdin.lock();
+    loop {
+        let line = tokio::select! {
+            line = lines.recv() => match line {
+                Some(line) => line,
+                None => break,
+            },
+            _ = &mut shutdown => {
+                info!("Shutting down");
+                break;
+            }
+            _ = backoff.wait(), if backoff.is_pending() => {
+                match retry(&database, &mut pool, &mut spill, encoder.as_ref()).await {
+                    Ok(()) => backoff.reset(),
+                    Err(e) if is_fatal(&e) => return Err(e),
+                    Err(e) => {
+                        if is_pool_failure(&e) {
+                            pool = None;
+                        }
+                        let delay = backoff.schedule();
+                        warn!("Database unavailable, retrying in {:.1?}: {}", delay, e);
+                    }
+                }
+                continue;
+            }
+        };
 
-    for line in reader.lines() {
-        let line = line?;
-        
         // Skip non-JSON lines (log messages)
         if !line.trim_start().starts_with('{') {
             continue;
         }
 
-        match LogEvent::from_json(&line) {
+        let record = match LogEvent::from_json_any_version(&line) {
+            // Heartbeats only matter to a console tracking agent liveness
+            Ok(event) if event.event_type.kind() == "heartbeat" => continue,
             Ok(event) => {
-                info!("Received event: {:?} - {:?}", event.severity, event.event_type);
-                
-                if let Err(e) = insert_event(&pool, &event).await {
-                    error!("Failed to store event: {}", e);
+                if let Some(Err(e)) = signer.as_ref().map(|signer| signer.verify(&event)) {
+                    warn!("Dropping event: {}", e);
+                    continue;
                 }
+                info!(
+                    "Received event: {:?} - {:?}",
+                    event.severity, event.event_type
+                );
+                Record::Event(Box::new(event))
             }
-            Err(e) => {
-                error!("Failed to parse event JSON: {} - Line: {}", e, line);
+            Err(e) => match StatsRollup::from_json(&line) {
+                Ok(rollup) => {
+                    if let Some(Err(e)) = signer.as_ref().map(|signer| signer.verify(&rollup)) {
+                        warn!("Dropping rollup: {}", e);
+                        continue;
+                    }
+                    info!(
+                        "Received rollup: {} events from {}",
+                        rollup.total(),
+                        rollup.hostname
+                    );
+                    Record::Rollup(rollup)
+                }
+                Err(_) => {
+                    match AgentHello::from_json(&line) {
+                        Ok(hello) => match signer.as_ref().map(|signer| signer.verify(&hello)) {
+                            Some(Err(e)) => warn!("Ignoring hello: {}", e),
+                            _ => info!(
+                                "Agent {} v{} connected",
+                                hello.hostname, hello.agent_version
+                            ),
+                        },
+                        Err(_) => error!("Failed to parse event JSON: {} - Line: {}", e, line),
+                    }
+                    continue;
+                }
+            },
+        };
+
+        if let Err(e) = deliver(pool.as_ref(), encoder.as_ref(), &mut spill, &line, record).await {
+            if is_pool_failure(&e) {
+                pool = None;
             }
+            if !backoff.is_pending() {
+                let delay = backoff.schedule();
+                warn!(
+                    "Database unavailable, spilling to {} and retrying in {:.1?}: {}",
+                    spill.dir().display(),
+                    delay,
+                    e
+                );
+            }
+        }
+    }
+
+    // One last try before leaving the rest for the next run
+    if !spill.is_empty() {
+        if let Err(e) = retry(&database, &mut pool, &mut spill, encoder.as_ref()).await {
+            warn!("Database unavailable: {}", e);
         }
     }
+    if !spill.is_empty() {
+        warn!(
+            "{} records remain in {} and are stored when the bridge next starts",
+            spill.len(),
+            spill.dir().display()
+        );
+    }
 
     Ok(())
 }
 
-/// Insert a log event into the database
-async fn insert_event(pool: &SqlitePool, event: &LogEvent) -> Result<()> {
-    let event_type = serde_json::to_string(&event.event_type)?;
-    let tags = serde_json::to_string(&event.tags)?;
+/// Where and how to open the database
+struct Database {
+    path: PathBuf,
+    options: SqliteConnectOptions,
+    keyed: bool,
+}
+
+/// A database key is configured but SQLite would ignore it, storing events
+/// unencrypted
+#[derive(Debug)]
+struct MissingSqlCipher;
+
+impl std::fmt::Display for MissingSqlCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            "A database key is configured but this build lacks SQLCipher; \
+             rebuild with the `sqlcipher` feature",
+        )
+    }
+}
+
+impl std::error::Error for MissingSqlCipher {}
+
+impl Database {
+    async fn connect(&self) -> Result<SqlitePool> {
+        info!("Connecting to database: {}", self.path.display());
+
+        // Ensure parent directory exists
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let pool = store::pool_options()
+            .max_connections(5)
+            .connect_with(self.options.clone())
+            .await?;
+
+        // Plain SQLite ignores the key, which would store events unencrypted
+        if self.keyed {
+            let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+                .fetch_optional(&pool)
+                .await?;
+            if version.is_none() {
+                return Err(MissingSqlCipher.into());
+            }
+        }
+
+        // Report corruption at startup rather than on some later write
+        let problems: Vec<String> = sqlx::query_scalar("PRAGMA quick_check")
+            .fetch_all(&pool)
+            .await?;
+        if problems != ["ok"] {
+            for problem in &problems {
+                error!("Database integrity check: {}", problem);
+            }
+        }
+
+        info!("Database connected successfully");
+        Ok(pool)
+    }
+}
+
+/// Exponential backoff with jitter between attempts to reach the database
+struct Backoff {
+    delay: Duration,
+    /// When the next attempt is due, if one is
+    next: Option<Instant>,
+}
+
+impl Backoff {
+    /// The first attempt is due straight away
+    fn new() -> Self {
+        Self {
+            delay: MIN_BACKOFF,
+            next: Some(Instant::now()),
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.next.is_some()
+    }
+
+    async fn wait(&self) {
+        if let Some(next) = self.next {
+            tokio::time::sleep_until(next).await;
+        }
+    }
+
+    /// Plan the next attempt, between half and all of the current delay so
+    /// bridges that lost the same database don't retry in step
+    fn schedule(&mut self) -> Duration {
+        let fraction = 0.5 + (uuid::Uuid::new_v4().as_u128() % 1000) as f64 / 2000.0;
+        let delay = self.delay.mul_f64(fraction);
+        self.next = Some(Instant::now() + delay);
+        self.delay = (self.delay * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    /// The database is back; nothing is due
+    fn reset(&mut self) {
+        self.delay = MIN_BACKOFF;
+        self.next = None;
+    }
+}
+
+/// Connect if there is no pool, then store everything spilled
+async fn retry(
+    database: &Database,
+    pool: &mut Option<SqlitePool>,
+    spill: &mut SpillQueue,
+    encoder: Option<&PayloadEncoder>,
+) -> Result<()> {
+    let pool = match pool {
+        Some(pool) => pool,
+        None => pool.insert(database.connect().await?),
+    };
+    drain(pool, encoder, spill).await
+}
+
+/// A line the database stores
+enum Record {
+    Event(Box<LogEvent>),
+    Rollup(StatsRollup),
+}
+
+impl Record {
+    fn parse(line: &str) -> Option<Self> {
+        match LogEvent::from_json_any_version(line) {
+            Ok(event) => Some(Record::Event(Box::new(event))),
+            Err(_) => StatsRollup::from_json(line).ok().map(Record::Rollup),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Record::Event(_) => "event",
+            Record::Rollup(_) => "rollup",
+        }
+    }
+
+    async fn store(&self, pool: &SqlitePool, encoder: Option<&PayloadEncoder>) -> Result<()> {
+        match self {
+            Record::Event(event) => insert_event(pool, encoder, event).await,
+            Record::Rollup(rollup) => insert_rollup(pool, rollup).await,
+        }
+    }
+}
 
-    sqlx::query(
-        r#"
-        INSERT INTO events (id, timestamp, severity, event_type, event_data, hostname, tags, rule_triggered, rule_name)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-        "#,
+/// Errors retrying cannot fix: a wrong key, or a key SQLite would ignore
+fn is_fatal(e: &anyhow::Error) -> bool {
+    e.is::<MissingSqlCipher>()
+        || matches!(
+            e.downcast_ref::<sqlx::Error>(),
+            Some(sqlx::Error::Database(e)) if e.code().as_deref() == Some(SQLITE_NOTADB)
+        )
+}
+
+/// Whether `e` means the database could not be reached for now, rather
+/// than that this record was rejected
+fn is_unavailable(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<sqlx::Error>() {
+        // Extended codes carry the primary code in their low byte
+        Some(sqlx::Error::Database(e)) => e
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| SQLITE_TRANSIENT.contains(&(code & 0xff))),
+        _ => is_pool_failure(e),
+    }
+}
+
+/// Log a record the database rejected and set it aside
+fn reject(spill: &SpillQueue, kind: &str, line: &str, e: &anyhow::Error) {
+    error!(
+        "Failed to store {}, moving it to {}: {}",
+        kind,
+        spill.quarantine_path().display(),
+        e
+    );
+    if let Err(e) = spill.quarantine(line) {
+        error!("Failed to set aside {}, dropping it: {}", kind, e);
+    }
+}
+
+/// Whether the pool's connections are unusable, so it has to be reopened
+/// rather than retried
+fn is_pool_failure(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<sqlx::Error>(),
+        Some(
+            sqlx::Error::Io(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed
+        )
     )
-    .bind(event.id.to_string())
-    .bind(event.timestamp.to_rfc3339())
-    .bind(serde_json::to_string(&event.severity).unwrap_or_default().trim_matches('"').to_string())
-    .bind(serde_json::to_string(&event.event_type).unwrap_or_default())
-    .bind(event_type)
-    .bind(&event.hostname)
-    .bind(tags)
-    .bind(event.rule_triggered as i32)
-    .bind(&event.rule_name)
-    .execute(pool)
-    .await?;
+}
+
+/// Store a record, or spill it while the database is unavailable or older
+/// records are still waiting, so records are stored in the order they
+/// arrived. An error means the database is unavailable; the record was spilled.
+/// A sealed queue takes nothing, so records are then stored as they come and
+/// dropped while the database is unavailable.
+async fn deliver(
+    pool: Option<&SqlitePool>,
+    encoder: Option<&PayloadEncoder>,
+    spill: &mut SpillQueue,
+    line: &str,
+    record: Record,
+) -> Result<()> {
+    let result = match pool {
+        Some(pool) if spill.is_empty() || spill.is_sealed() => {
+            match record.store(pool, encoder).await {
+                Ok(()) => return Ok(()),
+                Err(e) if is_unavailable(&e) => Err(e),
+                Err(e) => {
+                    reject(spill, record.kind(), line, &e);
+                    return Ok(());
+                }
+            }
+        }
+        _ => Ok(()),
+    };
+
+    if let Err(e) = spill.push(line) {
+        error!("Failed to spill {}, dropping it: {}", record.kind(), e);
+    }
+    result
+}
 
+/// Store spilled records oldest first, stopping with an error while the
+/// database is still unavailable
+async fn drain(
+    pool: &SqlitePool,
+    encoder: Option<&PayloadEncoder>,
+    spill: &mut SpillQueue,
+) -> Result<()> {
+    let mut stored = 0;
+    let result = loop {
+        let line = match spill.peek() {
+            Ok(Some(line)) => line,
+            Ok(None) => break Ok(()),
+            Err(e) => {
+                break Err(anyhow::Error::new(e).context(format!(
+                    "Failed to read the spill queue in {}",
+                    spill.dir().display()
+                )))
+            }
+        };
+
+        match Record::parse(&line) {
+            Some(record) => match record.store(pool, encoder).await {
+                Ok(()) => stored += 1,
+                Err(e) if is_unavailable(&e) => break Err(e),
+                Err(e) => reject(spill, record.kind(), &line, &e),
+            },
+            None => warn!("Skipping unreadable spilled line: {}", line),
+        }
+        spill.pop();
+    };
+
+    if stored > 0 {
+        info!(
+            "Stored {} spilled records, {} still waiting",
+            stored,
+            spill.len()
+        );
+    }
+    result
+}
+
+/// Insert a log event into the database, its payload compressed when there
+/// is an encoder; events stored before are skipped
+async fn insert_event(
+    pool: &SqlitePool,
+    encoder: Option<&PayloadEncoder>,
+    event: &LogEvent,
+) -> Result<()> {
+    let mut row = EventRow::new(event)?;
+    if let Some(encoder) = encoder {
+        if let Err(e) = encoder.maintain(pool).await {
+            warn!("Failed to update the payload dictionary: {}", e);
+        }
+        row = row.compress(encoder)?;
+    }
+    let mut tx = pool.begin().await?;
+    row.insert(&mut tx).await?;
+    tx.commit().await?;
     Ok(())
 }
+
+/// Insert an agent rollup into the database, replacing any earlier copy of the same period
+async fn insert_rollup(pool: &SqlitePool, rollup: &StatsRollup) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    store::insert_rollup(&mut tx, rollup).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new();
+        assert!(backoff.is_pending());
+        backoff.reset();
+        assert!(!backoff.is_pending());
+
+        // Each delay falls between half and all of a doubling step, up to the cap
+        let mut step = MIN_BACKOFF;
+        for _ in 0..10 {
+            let delay = backoff.schedule();
+            assert!(
+                delay >= step / 2 && delay <= step,
+                "{:?} outside {:?}",
+                delay,
+                step
+            );
+            step = (step * 2).min(MAX_BACKOFF);
+        }
+        assert_eq!(backoff.delay, MAX_BACKOFF);
+        assert!(backoff.is_pending());
+
+        backoff.reset();
+        assert_eq!(backoff.delay, MIN_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors() {
+        let dir = std::env::temp_dir().join(format!("guardian-bridge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = SqliteConnectOptions::new()
+            .filename(dir.join("test.db"))
+            .create_if_missing(true)
+            .busy_timeout(Duration::ZERO);
+        let holder = SqlitePool::connect_with(options.clone()).await.unwrap();
+        let writer = SqlitePool::connect_with(options).await.unwrap();
+
+        // A missing table rejects the record; the database itself is fine
+        let missing = sqlx::query("INSERT INTO events (id) VALUES ('x')")
+            .execute(&writer)
+            .await
+            .unwrap_err();
+        assert!(!is_unavailable(&missing.into()));
+
+        // Another connection holding the write lock passes
+        let mut locked = holder.acquire().await.unwrap();
+        sqlx::query("BEGIN EXCLUSIVE")
+            .execute(&mut *locked)
+            .await
+            .unwrap();
+        let busy = sqlx::query("CREATE TABLE t (n INTEGER)")
+            .execute(&writer)
+            .await
+            .unwrap_err();
+        assert!(is_unavailable(&busy.into()));
+
+        writer.close().await;
+        let closed = sqlx::query("SELECT 1").execute(&writer).await.unwrap_err();
+        assert!(is_unavailable(&closed.into()));
+        drop(locked);
+        holder.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
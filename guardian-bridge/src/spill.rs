@@ -0,0 +1,308 @@
+//! Durable queue for records the database could not take. Lines are appended
+//! to numbered NDJSON segment files and read back oldest first; a segment is
+//! deleted once every line in it has been stored. Lines the database rejects
+//! are set aside in `rejected.ndjson` instead. The directory and its files
+//! are readable by the owner only, since they hold whole events.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Size at which a new segment is started, so stored lines free disk space
+/// without rewriting files
+const SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Segment currently appended to
+struct Writer {
+    seq: u64,
+    file: File,
+    bytes: u64,
+}
+
+pub struct SpillQueue {
+    dir: PathBuf,
+    segment_bytes: u64,
+    /// Segments on disk, oldest first
+    segments: VecDeque<u64>,
+    /// Position in the oldest segment
+    reader: Option<BufReader<File>>,
+    /// Line returned by `peek` and not yet popped
+    head: Option<String>,
+    writer: Option<Writer>,
+    len: usize,
+    /// Whether new lines are refused, see `seal`
+    sealed: bool,
+}
+
+impl SpillQueue {
+    /// Open the queue in `dir`, picking up segments an earlier run left
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        Self::with_segment_bytes(dir, SEGMENT_BYTES)
+    }
+
+    fn with_segment_bytes(dir: &Path, segment_bytes: u64) -> io::Result<Self> {
+        create_private_dir(dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "ndjson") {
+                if let Some(seq) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                {
+                    segments.push(seq);
+                }
+            }
+        }
+        segments.sort_unstable();
+
+        let mut len = 0;
+        for seq in &segments {
+            let reader = BufReader::new(File::open(segment_path(dir, *seq))?);
+            for line in reader.lines() {
+                if !line?.trim().is_empty() {
+                    len += 1;
+                }
+            }
+        }
+
+        // New lines go to a fresh segment, never after a line a crash may
+        // have cut short
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segment_bytes,
+            segments: segments.into(),
+            reader: None,
+            head: None,
+            writer: None,
+            len,
+            sealed: false,
+        })
+    }
+
+    /// Refuse new lines, while still handing out those already queued.
+    /// Spilled lines are plaintext, so they must not pile up beside an
+    /// encrypted database.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Lines waiting to be stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append a line after everything already queued
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        self.check_sealed()?;
+        if self
+            .writer
+            .as_ref()
+            .is_none_or(|writer| writer.bytes >= self.segment_bytes)
+        {
+            let seq = self.segments.back().map_or(0, |seq| seq + 1);
+            let file = private_options()
+                .create_new(true)
+                .open(segment_path(&self.dir, seq))?;
+            self.segments.push_back(seq);
+            self.writer = Some(Writer {
+                seq,
+                file,
+                bytes: 0,
+            });
+        }
+
+        let writer = self.writer.as_mut().expect("writer opened above");
+        let record = format!("{}\n", line.trim_end());
+        writer.file.write_all(record.as_bytes())?;
+        writer.bytes += record.len() as u64;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The oldest line, left in the queue until `pop`
+    pub fn peek(&mut self) -> io::Result<Option<String>> {
+        while self.head.is_none() {
+            let Some(&seq) = self.segments.front() else {
+                return Ok(None);
+            };
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => self
+                    .reader
+                    .insert(BufReader::new(File::open(segment_path(&self.dir, seq))?)),
+            };
+
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                // Every line in the segment has been taken
+                self.reader = None;
+                self.segments.pop_front();
+                if self.writer.as_ref().is_some_and(|writer| writer.seq == seq) {
+                    self.writer = None;
+                }
+                fs::remove_file(segment_path(&self.dir, seq))?;
+                continue;
+            }
+            if !line.trim().is_empty() {
+                self.head = Some(line.trim_end().to_string());
+            }
+        }
+        Ok(self.head.clone())
+    }
+
+    /// Set aside a line the database rejected, for an operator to look at
+    pub fn quarantine(&self, line: &str) -> io::Result<()> {
+        self.check_sealed()?;
+        let mut file = private_options()
+            .create(true)
+            .open(self.quarantine_path())?;
+        file.write_all(format!("{}\n", line.trim_end()).as_bytes())
+    }
+
+    pub fn quarantine_path(&self) -> PathBuf {
+        self.dir.join("rejected.ndjson")
+    }
+
+    /// Remove the line returned by `peek`
+    pub fn pop(&mut self) {
+        if self.head.take().is_some() {
+            self.len -= 1;
+        }
+    }
+
+    fn check_sealed(&self) -> io::Result<()> {
+        if self.sealed {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the database is encrypted, so nothing is written to disk unencrypted",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Create `dir` if need be and make it private to the owner
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+/// Options to append to a file readable by the owner only
+fn private_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:020}.ndjson", seq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_keep_order() {
+        let dir = std::env::temp_dir().join(format!("guardian-spill-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let segments = |dir: &Path| fs::read_dir(dir).unwrap().count();
+
+        // Small segments, so a few lines span several files
+        let mut queue = SpillQueue::with_segment_bytes(&dir, 8).unwrap();
+        for line in ["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"] {
+            queue.push(line).unwrap();
+        }
+        assert_eq!(queue.len(), 3);
+        assert_eq!(segments(&dir), 3);
+
+        assert_eq!(queue.peek().unwrap().as_deref(), Some("{\"n\":1}"));
+        // Peeking again without popping returns the same line
+        assert_eq!(queue.peek().unwrap().as_deref(), Some("{\"n\":1}"));
+        queue.pop();
+        assert_eq!(queue.peek().unwrap().as_deref(), Some("{\"n\":2}"));
+        // The first segment is deleted once read past
+        assert_eq!(segments(&dir), 2);
+
+        // A restart picks up the rest, and new lines queue behind it
+        drop(queue);
+        let mut queue = SpillQueue::with_segment_bytes(&dir, 1024).unwrap();
+        assert_eq!(queue.len(), 2);
+        queue.push("{\"n\":4}").unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = queue.peek().unwrap() {
+            lines.push(line);
+            queue.pop();
+        }
+        assert_eq!(lines, ["{\"n\":2}", "{\"n\":3}", "{\"n\":4}"]);
+        assert!(queue.is_empty());
+        assert_eq!(segments(&dir), 0);
+
+        // Rejected lines are kept apart and never read back
+        queue.quarantine("{\"n\":5}").unwrap();
+        assert_eq!(
+            fs::read_to_string(queue.quarantine_path()).unwrap(),
+            "{\"n\":5}\n"
+        );
+        drop(queue);
+        let mut queue = SpillQueue::with_segment_bytes(&dir, 1024).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek().unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spill_is_private_and_can_be_sealed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("guardian-spill-{}", uuid::Uuid::new_v4()));
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let mut queue = SpillQueue::open(&dir).unwrap();
+        queue.push("{\"n\":1}").unwrap();
+        queue.quarantine("{\"n\":2}").unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&segment_path(&dir, 0)), 0o600);
+        assert_eq!(mode(&queue.quarantine_path()), 0o600);
+
+        // A sealed queue still hands out what it holds, but takes nothing new
+        queue.seal();
+        assert!(queue.push("{\"n\":3}").is_err());
+        assert!(queue.quarantine("{\"n\":4}").is_err());
+        assert_eq!(queue.peek().unwrap().as_deref(), Some("{\"n\":1}"));
+        queue.pop();
+        assert!(queue.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+//! Read-only queries over the event database Sentinel and the bridge write.
+//! Filters go through `guardian_common::store`, as Sentinel's search does,
+//! so both find the same events.
+
+use anyhow::{bail, Context, Result};
+use guardian_common::store;
+use guardian_common::{LogEvent, SearchFilter};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteRow};
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const COLUMNS: &str =
+    "rowid, id, timestamp, severity, event_json(event_data, event_payload) AS event_data, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version";
+
+/// Database Sentinel uses when `GUARDIAN_DB_PATH` is not set
+pub fn default_path() -> PathBuf {
+    match std::env::var("GUARDIAN_DB_PATH") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let home = std::env::var("HOME").unwrap_or_default();
+            PathBuf::from(home).join(".local/share/com.guardian.sentinel/guardian.db")
+        }
+    }
+}
+
+/// Open an existing database, decrypting it when a key is configured. The
+/// schema belongs to Sentinel, so nothing is created or migrated here.
+pub async fn open(path: &Path) -> Result<SqlitePool> {
+    if !path.exists() {
+        bail!(
+            "No event database at {}; set GUARDIAN_DB_PATH or --db",
+            path.display()
+        );
+    }
+
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .busy_timeout(Duration::from_secs(5));
+    let key = guardian_common::dbkey::database_key()
+        .context("Failed to read the database encryption key")?;
+    let options = match &key {
+        Some(key) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+        None => options,
+    };
+    let pool = store::pool_options()
+        .max_connections(2)
+        .connect_with(options)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    // Plain SQLite ignores the key and would fail later with "file is not a database"
+    if key.is_some() {
+        let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+            .fetch_optional(&pool)
+            .await?;
+        if version.is_none() {
+            bail!(
+                "A database key is configured but this build lacks SQLCipher; \
+                 rebuild with the `sqlcipher` feature"
+            );
+        }
+    }
+    sqlx::query("SELECT 1 FROM events LIMIT 1")
+        .fetch_optional(&pool)
+        .await
+        .context("Failed to read events; is the key right?")?;
+
+    Ok(pool)
+}
+
+/// Where a row sits, for reading on after it: in `(timestamp, id)` order
+/// for ranges, or in insertion order for following new rows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub rowid: i64,
+    pub timestamp: String,
+    pub id: String,
+}
+
+/// Events read by one query
+#[derive(Debug, Default)]
+pub struct Page {
+    pub events: Vec<LogEvent>,
+    /// Position of the last row, to read on from; `None` when there were no rows
+    pub last: Option<Position>,
+}
+
+/// Build a page from rows, skipping any whose event fails to parse
+fn page(rows: Vec<SqliteRow>) -> Page {
+    // The position follows the last row even if its event fails to parse
+    let last = rows.last().map(|row| Position {
+        rowid: row.get("rowid"),
+        timestamp: row.get("timestamp"),
+        id: row.get("id"),
+    });
+
+    let mut events = Vec::new();
+    for row in &rows {
+        match store::event_from_row(row) {
+            Ok(event) => events.push(event),
+            Err(e) => eprintln!("Skipping event {}: {}", row.get::<String, _>("id"), e),
+        }
+    }
+    Page { events, last }
+}
+
+/// The newest `limit` events matching `filter`, newest first
+pub async fn search(pool: &SqlitePool, filter: &SearchFilter, limit: i64) -> Result<Page> {
+    let mut sql = format!("SELECT {} FROM events WHERE 1 = 1", COLUMNS);
+    store::push_filter(&mut sql, filter);
+    sql.push_str(" ORDER BY timestamp DESC, id DESC LIMIT ?");
+
+    let rows = store::bind_filter(sqlx::query(&sql), filter)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(page(rows))
+}
+
+/// Up to `limit` events matching `filter` after `position`, oldest first
+pub async fn after(
+    pool: &SqlitePool,
+    filter: &SearchFilter,
+    position: Option<&Position>,
+    limit: i64,
+) -> Result<Page> {
+    let mut sql = format!("SELECT {} FROM events WHERE 1 = 1", COLUMNS);
+    store::push_filter(&mut sql, filter);
+    if position.is_some() {
+        sql.push_str(" AND (timestamp > ? OR (timestamp = ? AND id > ?))");
+    }
+    sql.push_str(" ORDER BY timestamp ASC, id ASC LIMIT ?");
+
+    let mut query = store::bind_filter(sqlx::query(&sql), filter);
+    if let Some(position) = position {
+        query = query
+            .bind(&position.timestamp)
+            .bind(&position.timestamp)
+            .bind(&position.id);
+    }
+    let rows = query.bind(limit).fetch_all(pool).await?;
+    Ok(page(rows))
+}
+
+/// Up to `limit` events matching `filter` stored after row `rowid`, in the
+/// order they were stored. Forwarded events can arrive long after their
+/// timestamp, so following the database goes by row rather than by time.
+pub async fn stored_after(
+    pool: &SqlitePool,
+    filter: &SearchFilter,
+    rowid: i64,
+    limit: i64,
+) -> Result<Page> {
+    let mut sql = format!("SELECT {} FROM events WHERE rowid > ?", COLUMNS);
+    store::push_filter(&mut sql, filter);
+    sql.push_str(" ORDER BY rowid ASC LIMIT ?");
+
+    let rows = store::bind_filter(sqlx::query(&sql).bind(rowid), filter)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(page(rows))
+}
+
+/// Row of the most recently stored event, 0 when there is none
+pub async fn last_rowid(pool: &SqlitePool) -> Result<i64> {
+    let rowid: Option<i64> = sqlx::query_scalar("SELECT MAX(rowid) FROM events")
+        .fetch_one(pool)
+        .await?;
+    Ok(rowid.unwrap_or(0))
+}
+
+/// Event counts for everything matching a filter
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Stats {
+    pub total: i64,
+    pub rules_triggered: i64,
+    pub by_severity: BTreeMap<String, i64>,
+    pub by_type: BTreeMap<String, i64>,
+    pub by_host: BTreeMap<String, i64>,
+    pub by_rule: BTreeMap<String, i64>,
+}
+
+/// Counts of events matching `filter` grouped by `column`
+async fn counts_by(
+    pool: &SqlitePool,
+    filter: &SearchFilter,
+    column: &str,
+) -> Result<BTreeMap<String, i64>> {
+    let mut sql = format!(
+        "SELECT {column} AS value, COUNT(*) AS count FROM events WHERE {column} IS NOT NULL"
+    );
+    store::push_filter(&mut sql, filter);
+    sql.push_str(&format!(" GROUP BY {}", column));
+
+    let rows = store::bind_filter(sqlx::query(&sql), filter)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("value"), row.get("count")))
+        .collect())
+}
+
+pub async fn stats(pool: &SqlitePool, filter: &SearchFilter) -> Result<Stats> {
+    let by_severity = counts_by(pool, filter, "severity").await?;
+    let by_rule = counts_by(pool, filter, "rule_name").await?;
+    Ok(Stats {
+        total: by_severity.values().sum(),
+        rules_triggered: by_rule.values().sum(),
+        by_severity,
+        by_type: counts_by(pool, filter, "event_type").await?,
+        by_host: counts_by(pool, filter, "hostname").await?,
+        by_rule,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::store::{EventRow, PayloadEncoder};
+    use guardian_common::{EventType, FileOperation, Severity};
+
+    async fn insert(pool: &SqlitePool, event: &LogEvent) {
+        let mut conn = pool.acquire().await.unwrap();
+        EventRow::new(event)
+            .unwrap()
+            .insert(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    fn file_event(path: &str, hostname: &str, minutes_ago: i64) -> LogEvent {
+        let mut event = LogEvent::new(
+            Severity::Low,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            hostname.to_string(),
+        );
+        event.timestamp -= chrono::Duration::minutes(minutes_ago);
+        event
+    }
+
+    #[tokio::test]
+    async fn test_queries() {
+        let pool = store::pool_options()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../guardian-sentinel/src-tauri/migrations")
+            .run(&pool)
+            .await
+            .unwrap();
+
+        let shadow = file_event("/etc/shadow", "web-1", 3).with_rule("critical_file_modification");
+        let hosts = file_event("/etc/hosts", "web-2", 2);
+        let motd = file_event("/etc/motd", "web-1", 1);
+        for event in [&shadow, &hosts, &motd] {
+            insert(&pool, event).await;
+        }
+
+        let filter = SearchFilter {
+            hostname: Some("web-1".to_string()),
+            ..Default::default()
+        };
+        let found = search(&pool, &filter, 10).await.unwrap();
+        let ids: Vec<_> = found.events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, [motd.id, shadow.id]);
+        assert_eq!(
+            found.events[1].rule_name.as_deref(),
+            Some("critical_file_modification")
+        );
+        assert_eq!(
+            found.events[1].rules_matched,
+            ["critical_file_modification"]
+        );
+
+        let all = SearchFilter::default();
+        let first = after(&pool, &all, None, 1).await.unwrap();
+        assert_eq!(first.events[0].id, shadow.id);
+        let rest = after(&pool, &all, first.last.as_ref(), 10).await.unwrap();
+        let ids: Vec<_> = rest.events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, [hosts.id, motd.id]);
+
+        let last = last_rowid(&pool).await.unwrap();
+        assert!(stored_after(&pool, &all, last, 10)
+            .await
+            .unwrap()
+            .last
+            .is_none());
+        let late = file_event("/etc/group", "web-2", 60);
+        insert(&pool, &late).await;
+        let new = stored_after(&pool, &all, last, 10).await.unwrap();
+        assert_eq!(new.events.len(), 1);
+        assert_eq!(new.events[0].id, late.id);
+
+        let stats = stats(&pool, &all).await.unwrap();
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.rules_triggered, 1);
+        assert_eq!(stats.by_host["web-1"], 2);
+        assert_eq!(stats.by_type["file_integrity"], 4);
+
+        // Compressed payloads are searched and read back like the rest
+        let passwd = file_event("/etc/passwd", "web-3", 0);
+        let mut conn = pool.acquire().await.unwrap();
+        EventRow::new(&passwd)
+            .unwrap()
+            .compress(&PayloadEncoder::new())
+            .unwrap()
+            .insert(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+        let filter = SearchFilter {
+            query: Some("/etc/passwd".to_string()),
+            ..Default::default()
+        };
+        let found = search(&pool, &filter, 10).await.unwrap();
+        assert_eq!(found.events.len(), 1);
+        assert_eq!(found.events[0].event_type, passwd.event_type);
+    }
+}
@@ -0,0 +1,354 @@
+//! Query the event database from a terminal: follow new events, search,
+//! count and export, for servers without the Sentinel window. Events print
+//! as JSON lines, the same shape the daemon emits.
+
+mod db;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser, Subcommand};
+use guardian_common::{CompiledRule, LogEvent, RuleDefinition, RuleTest, SearchFilter, Severity};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Rows read per query by `tail` and `export`
+const BATCH: i64 = 1000;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "guardian-cli",
+    version,
+    about = "Query the Guardian event database"
+)]
+struct Cli {
+    /// Event database; defaults to GUARDIAN_DB_PATH, then Sentinel's
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the latest events, then new ones as they are stored
+    Tail {
+        /// Events to print before following
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: i64,
+        /// Seconds between checks for new events
+        #[arg(long, default_value_t = 1)]
+        interval: u64,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Print the newest matching events, newest first
+    Search {
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Count matching events by severity, type, host and rule
+    Stats {
+        /// Print the counts as one JSON object
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Write every matching event, oldest first
+    Export {
+        /// File to write; standard output by default
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Try a candidate rule against stored events without changing them
+    TestRule {
+        /// JSON file holding the rule definition
+        rule: PathBuf,
+        /// Earliest time, in the same forms as elsewhere; 7 days ago by default
+        #[arg(long, value_parser = parse_time)]
+        from: Option<DateTime<Utc>>,
+        /// Latest time (exclusive); now by default
+        #[arg(long, value_parser = parse_time)]
+        to: Option<DateTime<Utc>>,
+        /// Matching events to print as samples
+        #[arg(long, default_value_t = 10)]
+        samples: usize,
+        /// Print the result as one JSON object
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Criteria shared by every command, as in Sentinel's search
+#[derive(Debug, Default, Args)]
+struct FilterArgs {
+    /// Text to find in event data, hostnames and tags
+    query: Option<String>,
+    #[arg(long, value_parser = parse_severity)]
+    severity: Option<Severity>,
+    /// Earliest time: RFC 3339, or an age such as `30m`, `12h` or `7d`
+    #[arg(long, value_parser = parse_time)]
+    from: Option<DateTime<Utc>>,
+    /// Latest time (exclusive), in the same forms as `--from`
+    #[arg(long, value_parser = parse_time)]
+    to: Option<DateTime<Utc>>,
+    /// Event kind, e.g. `file_integrity`
+    #[arg(long = "type")]
+    event_type: Option<String>,
+    #[arg(long)]
+    host: Option<String>,
+    #[arg(long)]
+    tag: Option<String>,
+    #[arg(long)]
+    rule: Option<String>,
+    /// Username or uid
+    #[arg(long)]
+    user: Option<String>,
+}
+
+impl From<FilterArgs> for SearchFilter {
+    fn from(args: FilterArgs) -> Self {
+        SearchFilter {
+            query: args.query,
+            severity: args.severity,
+            from: args.from,
+            to: args.to,
+            event_type: args.event_type,
+            hostname: args.host,
+            tag: args.tag,
+            rule_name: args.rule,
+            user: args.user,
+        }
+    }
+}
+
+fn parse_severity(value: &str) -> Result<Severity, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_uppercase()))
+        .map_err(|_| "expected info, low, medium, high or critical".to_string())
+}
+
+/// An RFC 3339 time, or an age counted back from now
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let unit_len = value.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = value.split_at(value.len() - unit_len);
+    let age = count.parse::<i64>().ok().and_then(|n| match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        _ => None,
+    });
+    age.map(|age| Utc::now() - age)
+        .ok_or_else(|| "expected an RFC 3339 time or an age such as 12h".to_string())
+}
+
+fn print_events<'a>(
+    out: &mut dyn Write,
+    events: impl IntoIterator<Item = &'a LogEvent>,
+) -> Result<()> {
+    for event in events {
+        writeln!(out, "{}", event.to_json()?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let pool = db::open(&cli.db.unwrap_or_else(db::default_path)).await?;
+    let mut stdout = std::io::stdout().lock();
+
+    match cli.command {
+        Command::Tail {
+            lines,
+            interval,
+            filter,
+        } => {
+            let filter = SearchFilter::from(filter);
+            // Read the row before the backlog so nothing stored in between is missed
+            let mut rowid = db::last_rowid(&pool).await?;
+            let recent = db::search(&pool, &filter, lines).await?;
+            print_events(&mut stdout, recent.events.iter().rev())?;
+
+            loop {
+                let page = db::stored_after(&pool, &filter, rowid, BATCH).await?;
+                print_events(&mut stdout, &page.events)?;
+                match page.last {
+                    Some(last) => rowid = last.rowid,
+                    None => tokio::time::sleep(std::time::Duration::from_secs(interval)).await,
+                }
+            }
+        }
+        Command::Search { limit, filter } => {
+            let page = db::search(&pool, &filter.into(), limit).await?;
+            print_events(&mut stdout, &page.events)?;
+        }
+        Command::Stats { json, filter } => {
+            let stats = db::stats(&pool, &filter.into()).await?;
+            if json {
+                writeln!(stdout, "{}", serde_json::to_string(&stats)?)?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{} event(s), {} triggered a rule",
+                    stats.total, stats.rules_triggered
+                )?;
+                for (title, counts) in [
+                    ("Severity", &stats.by_severity),
+                    ("Type", &stats.by_type),
+                    ("Host", &stats.by_host),
+                    ("Rule", &stats.by_rule),
+                ] {
+                    if counts.is_empty() {
+                        continue;
+                    }
+                    writeln!(stdout, "\n{}:", title)?;
+                    let mut counts: Vec<_> = counts.iter().collect();
+                    counts.sort_by(|a, b| b.1.cmp(a.1));
+                    for (value, count) in counts {
+                        writeln!(stdout, "  {:>8}  {}", count, value)?;
+                    }
+                }
+            }
+        }
+        Command::Export { out, filter } => {
+            let filter = SearchFilter::from(filter);
+            let mut file;
+            let out: &mut dyn Write = match &out {
+                Some(path) => {
+                    file = std::io::BufWriter::new(
+                        std::fs::File::create(path)
+                            .with_context(|| format!("Failed to create {}", path.display()))?,
+                    );
+                    &mut file
+                }
+                None => &mut stdout,
+            };
+
+            let mut position = None;
+            let mut total = 0;
+            loop {
+                let page = db::after(&pool, &filter, position.as_ref(), BATCH).await?;
+                print_events(out, &page.events)?;
+                total += page.events.len();
+                match page.last {
+                    Some(last) => position = Some(last),
+                    None => break,
+                }
+            }
+            eprintln!("Exported {} event(s)", total);
+        }
+        Command::TestRule {
+            rule: path,
+            from,
+            to,
+            samples,
+            json,
+        } => {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let definition: RuleDefinition = serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid rule in {}", path.display()))?;
+            let rule = CompiledRule::new(definition)?;
+
+            // Only events of the rule's type carrying its first tag are read
+            let filter = SearchFilter {
+                from: Some(from.unwrap_or_else(|| Utc::now() - Duration::days(7))),
+                to,
+                event_type: rule.definition.event_type.clone(),
+                tag: rule.definition.tags.first().cloned(),
+                ..SearchFilter::default()
+            };
+            let mut test = RuleTest::new(samples);
+            let mut position = None;
+            loop {
+                let page = db::after(&pool, &filter, position.as_ref(), BATCH).await?;
+                for event in &page.events {
+                    test.observe(&rule, event);
+                }
+                match page.last {
+                    Some(last) => position = Some(last),
+                    None => break,
+                }
+            }
+
+            if json {
+                writeln!(stdout, "{}", serde_json::to_string(&test)?)?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{} of {} event(s) matched {}, {} already triggered a rule",
+                    test.matched, test.evaluated, rule.definition.name, test.already_triggered
+                )?;
+                if !test.matched_by_host.is_empty() {
+                    writeln!(stdout, "\nHost:")?;
+                    let mut counts: Vec<_> = test.matched_by_host.iter().collect();
+                    counts.sort_by(|a, b| b.1.cmp(a.1));
+                    for (host, count) in counts {
+                        writeln!(stdout, "  {:>8}  {}", count, host)?;
+                    }
+                }
+                if !test.samples.is_empty() {
+                    writeln!(stdout, "\nSamples:")?;
+                    print_events(&mut stdout, &test.samples)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::try_parse_from([
+            "guardian-cli",
+            "search",
+            "shadow",
+            "--severity",
+            "high",
+            "--from",
+            "2h",
+            "--to",
+            "2026-01-02T03:04:05Z",
+            "--type",
+            "file_integrity",
+        ])
+        .unwrap();
+        let Command::Search { limit, filter } = cli.command else {
+            panic!("expected search");
+        };
+        assert_eq!(limit, 50);
+        let filter = SearchFilter::from(filter);
+        assert_eq!(filter.query.as_deref(), Some("shadow"));
+        assert_eq!(filter.severity, Some(Severity::High));
+        let age = Utc::now() - filter.from.unwrap();
+        assert!((Duration::minutes(119)..Duration::minutes(121)).contains(&age));
+        assert_eq!(filter.to.unwrap().to_rfc3339(), "2026-01-02T03:04:05+00:00");
+        assert_eq!(filter.event_type.as_deref(), Some("file_integrity"));
+
+        assert!(Cli::try_parse_from(["guardian-cli", "stats", "--from", "soon"]).is_err());
+        assert!(Cli::try_parse_from(["guardian-cli", "search", "--severity", "dire"]).is_err());
+        assert!(Cli::try_parse_from(["guardian-cli", "tail", "--db", "/tmp/g.db"]).is_ok());
+
+        let cli = Cli::try_parse_from(["guardian-cli", "test-rule", "rule.json", "--from", "1d"])
+            .unwrap();
+        let Command::TestRule { rule, samples, .. } = cli.command else {
+            panic!("expected test-rule");
+        };
+        assert_eq!(rule, PathBuf::from("rule.json"));
+        assert_eq!(samples, 10);
+    }
+}
@@ -0,0 +1,56 @@
+//! Per-event cost of the conversions every event goes through on its way
+//! from the daemon to the console and SIEMs. `guardian-daemon bench` covers
+//! rule evaluation and database inserts.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use guardian_common::ipc::Frame;
+use guardian_common::{EventType, EventUser, FileOperation, LogEvent, Severity};
+use std::hint::black_box;
+
+fn sample_event() -> LogEvent {
+    LogEvent::new(
+        Severity::High,
+        EventType::FileIntegrity {
+            path: "/etc/shadow".to_string(),
+            operation: FileOperation::Modify,
+            hash: Some("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".into()),
+            user: Some(EventUser {
+                uid: 0,
+                username: Some("root".to_string()),
+            }),
+            executable: None,
+            process: None,
+            diff: None,
+        },
+        "web-1".to_string(),
+    )
+    .with_tag("etc")
+    .with_rule("critical_file_modification")
+}
+
+fn serialization(c: &mut Criterion) {
+    let event = sample_event();
+    let json = event.to_json().unwrap();
+    let frame = Frame::Event(event.clone()).encode().unwrap();
+
+    c.bench_function("to_json", |b| b.iter(|| black_box(&event).to_json()));
+    c.bench_function("from_json_any_version", |b| {
+        b.iter(|| LogEvent::from_json_any_version(black_box(&json)))
+    });
+    c.bench_function("frame_encode", |b| {
+        b.iter(|| Frame::Event(black_box(&event).clone()).encode())
+    });
+    c.bench_function("frame_decode", |b| {
+        b.iter(|| Frame::decode(black_box(&frame[4..])))
+    });
+}
+
+fn siem_formats(c: &mut Criterion) {
+    let event = sample_event();
+    c.bench_function("to_cef", |b| b.iter(|| black_box(&event).to_cef()));
+    c.bench_function("to_ecs", |b| b.iter(|| black_box(&event).to_ecs()));
+    c.bench_function("to_gelf", |b| b.iter(|| black_box(&event).to_gelf()));
+}
+
+criterion_group!(benches, serialization, siem_formats);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+fn main() {
+    // Protobuf types are optional; protox compiles the schema without needing protoc
+    #[cfg(feature = "proto")]
+    {
+        println!("cargo:rerun-if-changed=proto/event.proto");
+        let descriptors = protox::compile(["proto/event.proto"], ["proto"])
+            .expect("Failed to parse proto/event.proto");
+        prost_build::Config::new()
+            .compile_fds(descriptors)
+            .expect("Failed to generate protobuf types");
+    }
+}
@@ -0,0 +1,174 @@
+//! Checked construction of events, so every collector fills them in the same
+//! way: the hostname is required, tags are normalized and capped, and the
+//! severity falls back to [`EventType::default_severity`].
+
+use crate::{EventType, GuardianError, LogEvent, Severity};
+use chrono::{DateTime, Utc};
+
+/// Most tags an event may carry once duplicates are dropped
+pub const MAX_TAGS: usize = 64;
+
+/// `tag` with surrounding whitespace removed and inner runs of whitespace
+/// replaced by `_`, so `" user: alice "` and `"user:_alice"` cannot both
+/// exist; `None` when nothing is left
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join("_");
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Builds a [`LogEvent`], checking it in [`build`](Self::build)
+#[derive(Debug, Clone)]
+pub struct LogEventBuilder {
+    event_type: EventType,
+    hostname: String,
+    severity: Option<Severity>,
+    timestamp: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+}
+
+impl LogEventBuilder {
+    pub fn new(event_type: EventType, hostname: impl Into<String>) -> Self {
+        Self {
+            event_type,
+            hostname: hostname.into(),
+            severity: None,
+            timestamp: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Severity other than the default for the event type
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    /// When the event happened, for events read from history; now by default
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn tags<T: Into<String>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// The event, or why it is invalid: a blank hostname, or more than
+    /// [`MAX_TAGS`] distinct tags. Blank tags are dropped and repeated ones
+    /// kept once, in the order first given.
+    pub fn build(self) -> Result<LogEvent, GuardianError> {
+        let hostname = self.hostname.trim();
+        if hostname.is_empty() {
+            return Err(GuardianError::InvalidEvent(format!(
+                "{} event has no hostname",
+                self.event_type.kind()
+            )));
+        }
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.iter().filter_map(|tag| normalize_tag(tag)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(GuardianError::InvalidEvent(format!(
+                "{} event has {} tags (at most {})",
+                self.event_type.kind(),
+                tags.len(),
+                MAX_TAGS
+            )));
+        }
+
+        let severity = self
+            .severity
+            .unwrap_or_else(|| self.event_type.default_severity());
+        let mut event = LogEvent::new(severity, self.event_type, hostname.to_string());
+        if let Some(timestamp) = self.timestamp {
+            event.timestamp = timestamp;
+        }
+        event.tags = tags;
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthOutcome, FileOperation};
+
+    fn login(outcome: AuthOutcome) -> EventType {
+        EventType::AuthEvent {
+            user: "alice".to_string(),
+            source_ip: None,
+            method: "password".to_string(),
+            outcome,
+            tty: None,
+        }
+    }
+
+    #[test]
+    fn test_builder_defaults_and_normalizes() {
+        let event = LogEvent::builder(login(AuthOutcome::Failure), " web-1 ")
+            .tag("auth_log")
+            .tags(["  user: alice ", "auth_log", " "])
+            .build()
+            .unwrap();
+        assert_eq!(event.hostname, "web-1");
+        assert_eq!(event.severity, Severity::Low);
+        assert_eq!(event.tags, vec!["auth_log", "user:_alice"]);
+
+        let event = LogEvent::builder(login(AuthOutcome::Success), "web-1")
+            .build()
+            .unwrap();
+        assert_eq!(event.severity, Severity::Info);
+        let event = LogEvent::builder(login(AuthOutcome::Success), "web-1")
+            .severity(Severity::High)
+            .build()
+            .unwrap();
+        assert_eq!(event.severity, Severity::High);
+
+        let access = EventType::FileIntegrity {
+            path: "/etc/shadow".to_string(),
+            operation: FileOperation::Access,
+            hash: None,
+            user: None,
+            executable: None,
+            process: None,
+            diff: None,
+        };
+        assert_eq!(access.default_severity(), Severity::Info);
+        let error = EventType::SystemLog {
+            source: "kernel".to_string(),
+            level: "err".to_string(),
+            message: "I/O error".to_string(),
+        };
+        assert_eq!(error.default_severity(), Severity::Medium);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_events() {
+        let error = LogEvent::builder(login(AuthOutcome::Success), "  ")
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, GuardianError::InvalidEvent(_)));
+        assert_eq!(error.to_string(), "auth_event event has no hostname");
+
+        let tags = (0..=MAX_TAGS).map(|i| format!("tag{}", i));
+        assert!(LogEvent::builder(login(AuthOutcome::Success), "web-1")
+            .tags(tags)
+            .build()
+            .is_err());
+        let repeated = std::iter::repeat_n("same", MAX_TAGS + 1);
+        assert!(LogEvent::builder(login(AuthOutcome::Success), "web-1")
+            .tags(repeated)
+            .build()
+            .is_ok());
+    }
+}
@@ -0,0 +1,148 @@
+//! CEL conditions for rules: declarative like field tests, but with boolean
+//! logic, arithmetic, string functions and list membership. The expression
+//! sees the event as `event`, a map from the same dotted paths field
+//! conditions use to their values, e.g.
+//!
+//! ```text
+//! event.path.endsWith(".sh")
+//!     && event["ancestry.0.name"] in ["nginx", "httpd"]
+//! ```
+
+use crate::{GuardianError, LogEvent};
+use cel_interpreter::{Context, Program, Value};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+
+/// A compiled CEL condition
+pub struct CelCondition {
+    program: Program,
+}
+
+impl std::fmt::Debug for CelCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CelCondition").finish_non_exhaustive()
+    }
+}
+
+impl CelCondition {
+    /// Compile `source`, failing on syntax errors. The parser panics on
+    /// some malformed input rather than returning an error, so a panic is
+    /// caught and reported as one; a bad rule must not stop the daemon.
+    pub fn compile(source: &str) -> Result<Self, GuardianError> {
+        let program = std::panic::catch_unwind(|| Program::compile(source))
+            .map_err(|_| GuardianError::config(format!("Invalid CEL expression: {}", source)))?
+            .map_err(|e| GuardianError::config(e.to_string()))?;
+        Ok(Self { program })
+    }
+
+    /// Whether the expression is `true` for `event`; errors, such as a
+    /// missing field, and results that are not booleans count as no match
+    pub fn matches(&self, event: &CelEvent) -> bool {
+        let Some(value) = &event.0 else {
+            return false;
+        };
+        let mut context = Context::default();
+        context.add_variable_from_value("event", value.clone());
+        matches!(self.program.execute(&context), Ok(Value::Bool(true)))
+    }
+}
+
+/// An event flattened and converted once, for every CEL condition evaluated
+/// against it
+pub struct CelEvent(Option<Value>);
+
+impl CelEvent {
+    pub fn new(event: &LogEvent) -> Self {
+        Self(cel_interpreter::to_value(flatten(event)).ok())
+    }
+}
+
+/// Every value of the serialized event under its dotted path. Lists are kept
+/// whole as well as by index, so `"backup" in event.tags` and
+/// `event["tags.0"]` both work.
+pub fn flatten(event: &LogEvent) -> BTreeMap<String, Json> {
+    let mut fields = BTreeMap::new();
+    if let Ok(Json::Object(map)) = serde_json::to_value(event) {
+        for (key, value) in map {
+            insert(&mut fields, key, value);
+        }
+    }
+    fields
+}
+
+fn insert(fields: &mut BTreeMap<String, Json>, path: String, value: Json) {
+    match value {
+        Json::Null => {}
+        Json::Object(map) => {
+            for (key, value) in map {
+                insert(fields, format!("{}.{}", path, key), value);
+            }
+        }
+        Json::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                insert(fields, format!("{}.{}", path, i), item.clone());
+            }
+            fields.insert(path, Json::Array(items));
+        }
+        value => {
+            fields.insert(path, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, ProcessAncestor, Severity};
+
+    fn shell(parent: &str) -> LogEvent {
+        LogEvent::new(
+            Severity::High,
+            EventType::ProcessMonitor {
+                pid: 4242,
+                name: "bash".to_string(),
+                cpu_usage: 97.5,
+                memory_usage: 0,
+                ancestry: vec![ProcessAncestor {
+                    pid: 1,
+                    name: parent.to_string(),
+                }],
+                user: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_tag("process")
+    }
+
+    #[test]
+    fn test_flatten_uses_field_paths() {
+        let fields = flatten(&shell("nginx"));
+        assert_eq!(fields["type"], "process_monitor");
+        assert_eq!(fields["ancestry.0.name"], "nginx");
+        assert_eq!(fields["tags"], serde_json::json!(["process"]));
+        assert_eq!(fields["tags.0"], "process");
+        // Unset values are left out rather than null
+        assert!(!fields.contains_key("rule_name"));
+    }
+
+    #[test]
+    fn test_cel_condition() {
+        let condition = CelCondition::compile(
+            r#"event.name.startsWith("ba") && event.cpu_usage > 90.0
+                && event["ancestry.0.name"] in ["nginx", "httpd"]
+                && "process" in event.tags && event.severity == "HIGH""#,
+        )
+        .unwrap();
+        assert!(condition.matches(&CelEvent::new(&shell("nginx"))));
+        assert!(!condition.matches(&CelEvent::new(&shell("sshd"))));
+
+        // Missing fields and results that are not booleans do not match
+        // One conversion of the event serves every condition
+        let event = CelEvent::new(&shell("nginx"));
+        assert!(!CelCondition::compile("event.diff.size() > 0")
+            .unwrap()
+            .matches(&event));
+        assert!(!CelCondition::compile("event.name").unwrap().matches(&event));
+        assert!(CelCondition::compile("event.name ==").is_err());
+    }
+}
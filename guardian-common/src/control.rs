@@ -0,0 +1,113 @@
+use crate::GuardianError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Upper bound on how long a host may stay isolated without re-confirmation
+pub const MAX_ISOLATION_SECS: u64 = 7 * 24 * 3600;
+
+/// Commands the console sends to a daemon over its control channel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Contain the host by dropping all traffic except to the listed peers
+    IsolateHost {
+        /// Peers that stay reachable, as `ip`, `ip:port` or `ip/prefix`
+        allow: Vec<String>,
+        /// Seconds until the policy is lifted automatically
+        duration_secs: u64,
+        /// Operator who confirmed the action
+        requested_by: String,
+    },
+    /// Lift an active isolation policy
+    ReleaseHost {
+        /// Operator who released the host
+        requested_by: String,
+    },
+    /// Keep only one in `rate` low-value events (Info/Low); 1 disables sampling
+    SetSampling { rate: u32 },
+    /// Scan everything under a path with the YARA rules now
+    ScanPath {
+        /// Chosen by the console; every event of the scan is tagged `scan:<id>`
+        scan_id: Uuid,
+        path: String,
+        /// Operator who started the scan
+        requested_by: String,
+    },
+    /// Write a stored version of a protected file back over it
+    RestoreFile {
+        path: String,
+        /// SHA-256 of the version, as on the `file_integrity` event for it
+        hash: String,
+        /// Operator who confirmed the restore
+        requested_by: String,
+    },
+    /// Send the rule hit counts as a `rule_stats` frame now
+    ReportRuleStats,
+    /// Apply the config file as it is now, as SIGHUP does
+    ReloadConfig {
+        /// Operator who asked for the reload
+        requested_by: String,
+    },
+}
+
+impl ControlCommand {
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String, GuardianError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserialize from JSON string
+    pub fn from_json(json: &str) -> Result<Self, GuardianError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_command_round_trip() {
+        let command = ControlCommand::IsolateHost {
+            allow: vec!["10.0.0.5:8443".to_string()],
+            duration_secs: 3600,
+            requested_by: "local".to_string(),
+        };
+
+        let json = command.to_json().unwrap();
+        assert!(json.contains(r#""command":"isolate_host""#));
+        assert_eq!(ControlCommand::from_json(&json).unwrap(), command);
+
+        let scan = ControlCommand::ScanPath {
+            scan_id: Uuid::new_v4(),
+            path: "/srv/uploads".to_string(),
+            requested_by: "alice".to_string(),
+        };
+        let json = scan.to_json().unwrap();
+        assert!(json.contains(r#""command":"scan_path""#));
+        assert_eq!(ControlCommand::from_json(&json).unwrap(), scan);
+
+        let restore = ControlCommand::RestoreFile {
+            path: "/etc/sudoers".to_string(),
+            hash: "ab".repeat(32),
+            requested_by: "alice".to_string(),
+        };
+        let json = restore.to_json().unwrap();
+        assert!(json.contains(r#""command":"restore_file""#));
+        assert_eq!(ControlCommand::from_json(&json).unwrap(), restore);
+
+        let json = ControlCommand::ReportRuleStats.to_json().unwrap();
+        assert_eq!(json, r#"{"command":"report_rule_stats"}"#);
+        assert_eq!(
+            ControlCommand::from_json(&json).unwrap(),
+            ControlCommand::ReportRuleStats
+        );
+
+        let reload = ControlCommand::ReloadConfig {
+            requested_by: "alice".to_string(),
+        };
+        let json = reload.to_json().unwrap();
+        assert!(json.contains(r#""command":"reload_config""#));
+        assert_eq!(ControlCommand::from_json(&json).unwrap(), reload);
+    }
+}
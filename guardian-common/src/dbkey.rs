@@ -0,0 +1,95 @@
+//! Encryption key for the event database. Sentinel and the bridge open the
+//! same file, so both look the key up the same way.
+
+use std::io;
+use std::process::Command;
+
+/// Passphrase for the event database
+pub const KEY_ENV: &str = "GUARDIAN_DB_KEY";
+
+/// Shell command printing the passphrase, e.g. an OS keychain lookup
+pub const KEY_COMMAND_ENV: &str = "GUARDIAN_DB_KEY_COMMAND";
+
+/// Key from `GUARDIAN_DB_KEY`, else from running `GUARDIAN_DB_KEY_COMMAND`;
+/// `None` when neither is set and the database is stored unencrypted
+pub fn database_key() -> io::Result<Option<String>> {
+    resolve(
+        std::env::var(KEY_ENV).ok(),
+        std::env::var(KEY_COMMAND_ENV).ok(),
+        KEY_COMMAND_ENV,
+    )
+}
+
+/// `passphrase`, else the output of running `command`, which errors name as
+/// `source`; also used for the event signing key
+pub(crate) fn resolve(
+    passphrase: Option<String>,
+    command: Option<String>,
+    source: &str,
+) -> io::Result<Option<String>> {
+    if let Some(passphrase) = passphrase.filter(|p| !p.is_empty()) {
+        return Ok(Some(passphrase));
+    }
+    let Some(command) = command.filter(|c| !c.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", &command]).output()?
+    } else {
+        Command::new("sh").args(["-c", &command]).output()?
+    };
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            source, output.status
+        )));
+    }
+
+    // Keychain tools end their output with a newline that is not part of the key
+    let key = String::from_utf8(output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .trim_end_matches(['\r', '\n'])
+        .to_string();
+    if key.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} printed an empty key", source),
+        ));
+    }
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_sources() {
+        assert_eq!(resolve(None, None, KEY_COMMAND_ENV).unwrap(), None);
+        assert_eq!(
+            resolve(
+                Some("s3cret".to_string()),
+                Some("exit 1".to_string()),
+                KEY_COMMAND_ENV
+            )
+            .unwrap(),
+            Some("s3cret".to_string())
+        );
+
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                resolve(
+                    Some(String::new()),
+                    Some("echo 'from keychain'".to_string()),
+                    KEY_COMMAND_ENV
+                )
+                .unwrap(),
+                Some("from keychain".to_string())
+            );
+            assert!(resolve(None, Some("exit 1".to_string()), KEY_COMMAND_ENV).is_err());
+            assert!(resolve(None, Some("true".to_string()), KEY_COMMAND_ENV).is_err());
+        }
+    }
+}
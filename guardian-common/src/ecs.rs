@@ -0,0 +1,432 @@
+//! Elastic Common Schema documents for events, so Kibana dashboards and
+//! detection rules written against ECS field names work on Guardian data.
+
+use crate::siem::severity_score;
+use crate::{
+    AuthOutcome, EventType, ExecutableFormat, FileOperation, LogEvent, PackageAction, ScanOutcome,
+    Severity,
+};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+
+/// ECS release the documents follow
+pub const ECS_VERSION: &str = "8.11.0";
+
+/// Set a dotted field such as `file.hash.sha256`, creating parent objects.
+/// A field whose parent already holds a plain value is left out.
+fn set(doc: &mut Map<String, Value>, field: &str, value: impl Into<Value>) {
+    let mut parts = field.split('.').peekable();
+    let mut object = doc;
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            object.insert(part.to_string(), value.into());
+            return;
+        }
+        let Some(parent) = object
+            .entry(part)
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+        else {
+            return;
+        };
+        object = parent;
+    }
+}
+
+/// Set `<prefix>.ip` and `<prefix>.port` from an `ip:port` string
+fn set_endpoint(doc: &mut Map<String, Value>, prefix: &str, addr: &str) {
+    match addr.parse::<SocketAddr>() {
+        Ok(socket) => {
+            set(doc, &format!("{}.ip", prefix), socket.ip().to_string());
+            set(doc, &format!("{}.port", prefix), socket.port());
+        }
+        Err(_) => set(doc, &format!("{}.address", prefix), addr),
+    }
+}
+
+fn log_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "info",
+        Severity::Low => "notice",
+        Severity::Medium => "warning",
+        Severity::High => "error",
+        Severity::Critical => "critical",
+    }
+}
+
+impl LogEvent {
+    /// Render as an ECS document, ready to index
+    pub fn to_ecs(&self) -> Value {
+        let mut doc = Map::new();
+        set(&mut doc, "@timestamp", self.timestamp.to_rfc3339());
+        set(&mut doc, "ecs.version", ECS_VERSION);
+        set(&mut doc, "event.id", self.id.to_string());
+        set(&mut doc, "event.module", "guardian");
+        set(
+            &mut doc,
+            "event.dataset",
+            format!("guardian.{}", self.event_type.kind()),
+        );
+        set(&mut doc, "event.severity", severity_score(self.severity));
+        set(&mut doc, "log.level", log_level(self.severity));
+        set(&mut doc, "host.name", self.hostname.clone());
+        set(&mut doc, "host.hostname", self.hostname.clone());
+        set(&mut doc, "agent.type", "guardian");
+        if !self.tags.is_empty() {
+            set(&mut doc, "tags", self.tags.clone());
+        }
+        if let Some(rule) = &self.rule_name {
+            set(&mut doc, "rule.name", rule.clone());
+        }
+
+        let alert = self.rule_triggered || matches!(self.event_type, EventType::Alert { .. });
+        let (category, kind) = self.ecs_categorize(&mut doc);
+        set(
+            &mut doc,
+            "event.kind",
+            if alert { "alert" } else { "event" },
+        );
+        set(&mut doc, "event.category", json!([category]));
+        set(&mut doc, "event.type", json!([kind]));
+        Value::Object(doc)
+    }
+
+    /// Fill type-specific fields; returns `event.category` and `event.type`
+    fn ecs_categorize(&self, doc: &mut Map<String, Value>) -> (&'static str, &'static str) {
+        let set_user = |doc: &mut Map<String, Value>, user: &Option<crate::EventUser>| {
+            if let Some(user) = user {
+                set(doc, "user.id", user.uid.to_string());
+                if let Some(name) = &user.username {
+                    set(doc, "user.name", name.clone());
+                }
+            }
+        };
+
+        match &self.event_type {
+            EventType::FileIntegrity {
+                path,
+                operation,
+                hash,
+                user,
+                executable,
+                process,
+                diff,
+            } => {
+                set(doc, "file.path", path.clone());
+                let file = std::path::Path::new(path);
+                if let Some(name) = file.file_name() {
+                    set(doc, "file.name", name.to_string_lossy().to_string());
+                }
+                if let Some(dir) = file.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    set(doc, "file.directory", dir.to_string_lossy().to_string());
+                }
+                if let Some(hash) = hash {
+                    set(doc, "file.hash.sha256", hash.clone());
+                }
+                if let Some(diff) = diff {
+                    set(doc, "guardian.file.diff", diff.clone());
+                }
+                set_user(doc, user);
+                if let Some(process) = process {
+                    set(doc, "process.pid", process.pid);
+                    set(doc, "process.name", process.name.clone());
+                }
+                if let Some(executable) = executable {
+                    let prefix = match executable.format {
+                        ExecutableFormat::Elf => "file.elf",
+                        ExecutableFormat::Pe => "file.pe",
+                    };
+                    let named = |names: &[String]| -> Value {
+                        names.iter().map(|name| json!({ "name": name })).collect()
+                    };
+                    set(
+                        doc,
+                        &format!("{}.architecture", prefix),
+                        executable.arch.clone(),
+                    );
+                    set(
+                        doc,
+                        &format!("{}.imports", prefix),
+                        named(&executable.libraries),
+                    );
+                    set(
+                        doc,
+                        &format!("{}.sections", prefix),
+                        named(&executable.sections),
+                    );
+                    if let Some(signed) = executable.signed {
+                        set(doc, "file.code_signature.exists", signed);
+                    }
+                }
+                set(doc, "event.action", crate::siem::variant_name(operation));
+                let kind = match operation {
+                    FileOperation::Create => "creation",
+                    FileOperation::Delete => "deletion",
+                    FileOperation::Modify | FileOperation::Rename | FileOperation::Chmod => {
+                        "change"
+                    }
+                    FileOperation::Access => "access",
+                    FileOperation::Scan => "info",
+                };
+                ("file", kind)
+            }
+            EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                remote_host,
+                protocol,
+                state,
+                traffic,
+                process,
+            } => {
+                set_endpoint(doc, "source", local_addr);
+                if let Some(remote) = remote_addr {
+                    set_endpoint(doc, "destination", remote);
+                }
+                if let Some(host) = remote_host {
+                    set(doc, "destination.domain", host.clone());
+                }
+                set(doc, "network.transport", protocol.to_lowercase());
+                set(doc, "event.action", state.clone());
+                if let Some(process) = process {
+                    set(doc, "process.pid", process.pid);
+                    set(doc, "process.name", process.name.clone());
+                }
+                if let Some(traffic) = traffic {
+                    set(doc, "source.bytes", traffic.bytes_sent);
+                    set(doc, "destination.bytes", traffic.bytes_received);
+                    set(
+                        doc,
+                        "network.bytes",
+                        traffic.bytes_sent + traffic.bytes_received,
+                    );
+                    set(doc, "network.packets", traffic.packets);
+                    if let Some(sni) = &traffic.sni {
+                        set(doc, "tls.client.server_name", sni.clone());
+                    }
+                    if let Some(host) = &traffic.http_host {
+                        set(doc, "url.domain", host.clone());
+                    }
+                }
+                ("network", "connection")
+            }
+            EventType::SystemLog {
+                source,
+                level,
+                message,
+            } => {
+                set(doc, "process.name", source.clone());
+                set(doc, "log.syslog.severity.name", level.clone());
+                set(doc, "message", message.clone());
+                ("host", "info")
+            }
+            EventType::ProcessMonitor {
+                pid,
+                name,
+                cpu_usage,
+                memory_usage,
+                ancestry,
+                user,
+            } => {
+                set(doc, "process.pid", *pid);
+                set(doc, "process.name", name.clone());
+                set(doc, "process.cpu.pct", f64::from(*cpu_usage) / 100.0);
+                set(doc, "process.memory.bytes", *memory_usage);
+                if let Some(parent) = ancestry.first() {
+                    set(doc, "process.parent.pid", parent.pid);
+                    set(doc, "process.parent.name", parent.name.clone());
+                }
+                set_user(doc, user);
+                ("process", "info")
+            }
+            EventType::AuthEvent {
+                user,
+                source_ip,
+                method,
+                outcome,
+                tty,
+            } => {
+                set(doc, "user.name", user.clone());
+                if let Some(ip) = source_ip {
+                    set(doc, "source.ip", ip.clone());
+                }
+                if let Some(tty) = tty {
+                    set(doc, "process.tty.name", tty.clone());
+                }
+                set(doc, "event.action", method.clone());
+                let outcome = match outcome {
+                    AuthOutcome::Success => "success",
+                    AuthOutcome::Failure => "failure",
+                };
+                set(doc, "event.outcome", outcome);
+                ("authentication", "start")
+            }
+            EventType::PackageChange {
+                name,
+                version,
+                action,
+                manager,
+            } => {
+                set(doc, "package.name", name.clone());
+                if let Some(version) = version {
+                    set(doc, "package.version", version.clone());
+                }
+                set(doc, "package.type", manager.clone());
+                set(doc, "event.action", crate::siem::variant_name(action));
+                let kind = match action {
+                    PackageAction::Install => "installation",
+                    PackageAction::Remove => "deletion",
+                    PackageAction::Upgrade | PackageAction::Downgrade => "change",
+                };
+                ("package", kind)
+            }
+            EventType::Heartbeat {
+                agent_version,
+                uptime_secs,
+                ..
+            } => {
+                set(doc, "agent.version", agent_version.clone());
+                set(doc, "host.uptime", *uptime_secs);
+                ("host", "info")
+            }
+            EventType::Alert {
+                title,
+                description,
+                related_event_ids,
+                technique,
+            } => {
+                set(doc, "message", title.clone());
+                set(doc, "event.reason", description.clone());
+                let related: Vec<String> =
+                    related_event_ids.iter().map(|id| id.to_string()).collect();
+                set(doc, "guardian.related_event_ids", related);
+                if let Some(technique) = technique {
+                    set(doc, "threat.framework", "MITRE ATT&CK");
+                    set(doc, "threat.technique.id", json!([technique]));
+                }
+                ("intrusion_detection", "indicator")
+            }
+            EventType::ScanProgress {
+                scan_id,
+                path,
+                files_scanned,
+                files_total,
+                current_path,
+                percent,
+            } => {
+                set(doc, "guardian.scan.id", scan_id.to_string());
+                set(doc, "guardian.scan.path", path.clone());
+                set(doc, "guardian.scan.files_scanned", *files_scanned);
+                if let Some(total) = files_total {
+                    set(doc, "guardian.scan.files_total", *total);
+                }
+                if let Some(current) = current_path {
+                    set(doc, "file.path", current.clone());
+                }
+                if let Some(percent) = percent {
+                    set(doc, "guardian.scan.percent", *percent);
+                }
+                ("malware", "info")
+            }
+            EventType::ScanSummary {
+                scan_id,
+                path,
+                requested_by,
+                outcome,
+                files_scanned,
+                files_matched,
+                dirs_skipped,
+                duration_ms,
+                error,
+            } => {
+                set(doc, "guardian.scan.id", scan_id.to_string());
+                set(doc, "guardian.scan.path", path.clone());
+                set(doc, "user.name", requested_by.clone());
+                set(doc, "guardian.scan.files_scanned", *files_scanned);
+                set(doc, "guardian.scan.files_matched", *files_matched);
+                set(doc, "guardian.scan.dirs_skipped", *dirs_skipped);
+                // ECS durations are in nanoseconds
+                set(doc, "event.duration", duration_ms.saturating_mul(1_000_000));
+                let outcome = match outcome {
+                    ScanOutcome::Completed => "success",
+                    ScanOutcome::Failed => "failure",
+                };
+                set(doc, "event.outcome", outcome);
+                if let Some(error) = error {
+                    set(doc, "error.message", error.clone());
+                }
+                ("malware", "info")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecs_fields() {
+        let event = LogEvent::new(
+            Severity::High,
+            EventType::FileIntegrity {
+                path: "/etc/passwd".to_string(),
+                operation: FileOperation::Modify,
+                hash: Some("abc123".to_string()),
+                user: Some(crate::EventUser {
+                    uid: 0,
+                    username: Some("root".to_string()),
+                }),
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("Passwd changed")
+        .with_tag("fim");
+
+        let doc = event.to_ecs();
+        assert_eq!(doc["ecs"]["version"], ECS_VERSION);
+        assert_eq!(doc["event"]["id"], event.id.to_string());
+        assert_eq!(doc["event"]["kind"], "alert");
+        assert_eq!(doc["event"]["category"], json!(["file"]));
+        assert_eq!(doc["event"]["type"], json!(["change"]));
+        assert_eq!(doc["event"]["severity"], 8);
+        assert_eq!(doc["file"]["name"], "passwd");
+        assert_eq!(doc["file"]["directory"], "/etc");
+        assert_eq!(doc["file"]["hash"]["sha256"], "abc123");
+        assert_eq!(doc["user"]["name"], "root");
+        assert_eq!(doc["host"]["name"], "web-1");
+        assert_eq!(doc["rule"]["name"], "Passwd changed");
+        assert_eq!(doc["tags"], json!(["fim"]));
+
+        let socket = LogEvent::new(
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: "10.0.0.5:22".to_string(),
+                remote_addr: Some("203.0.113.9:50022".to_string()),
+                remote_host: Some("scanner.example".to_string()),
+                protocol: "TCP".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "web-1".to_string(),
+        )
+        .to_ecs();
+        assert_eq!(socket["event"]["kind"], "event");
+        assert_eq!(socket["source"]["port"], 22);
+        assert_eq!(socket["destination"]["ip"], "203.0.113.9");
+        assert_eq!(socket["destination"]["domain"], "scanner.example");
+        assert_eq!(socket["network"]["transport"], "tcp");
+    }
+
+    #[test]
+    fn test_field_under_plain_value_is_skipped() {
+        let mut doc = Map::new();
+        set(&mut doc, "source.address", "not-an-endpoint");
+        set(&mut doc, "source.address.ip", "203.0.113.9");
+        assert_eq!(doc["source"]["address"], "not-an-endpoint");
+    }
+}
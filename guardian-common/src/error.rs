@@ -0,0 +1,140 @@
+//! The error type of the public APIs across the Guardian crates. Each
+//! variant names what failed, so callers can tell a malformed event from a
+//! broken database without matching on messages; the message itself is
+//! the one the underlying error gives.
+
+/// Any error, kept as the source of a [`GuardianError`]
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuardianError {
+    /// JSON, CBOR or protobuf that could not be written or parsed, such as
+    /// an event or frame
+    #[error(transparent)]
+    Serialization(BoxError),
+    /// The event database could not be opened, read or written
+    #[error(transparent)]
+    Storage(BoxError),
+    /// YARA rules that do not compile, or a file that could not be scanned
+    #[error(transparent)]
+    Scanner(BoxError),
+    /// Settings that cannot be read or are invalid, including rules
+    #[error(transparent)]
+    Config(BoxError),
+    /// An event that fails validation, such as one without a hostname
+    #[error("{0}")]
+    InvalidEvent(String),
+    /// A request that fails validation, such as an empty time range or a
+    /// page cursor no query handed out
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+/// What was being done when an error happened, keeping that error as the
+/// source so the chain stays intact
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+struct Context {
+    message: String,
+    #[source]
+    source: BoxError,
+}
+
+impl GuardianError {
+    pub fn storage(error: impl Into<BoxError>) -> Self {
+        Self::Storage(error.into())
+    }
+
+    pub fn scanner(error: impl Into<BoxError>) -> Self {
+        Self::Scanner(error.into())
+    }
+
+    pub fn config(error: impl Into<BoxError>) -> Self {
+        Self::Config(error.into())
+    }
+
+    /// Scanner error saying what failed, with `source` as the cause
+    pub fn scanner_context(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self::scanner(Context::new(message, source))
+    }
+
+    /// Config error saying what failed, with `source` as the cause
+    pub fn config_context(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self::config(Context::new(message, source))
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::InvalidInput(message.into())
+    }
+}
+
+impl Context {
+    fn new(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self {
+            message: message.into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for GuardianError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Serialization(error.into())
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for GuardianError {
+    fn from(error: ciborium::ser::Error<std::io::Error>) -> Self {
+        Self::Serialization(error.into())
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for GuardianError {
+    fn from(error: ciborium::de::Error<std::io::Error>) -> Self {
+        Self::Serialization(error.into())
+    }
+}
+
+#[cfg(feature = "proto")]
+impl From<prost::DecodeError> for GuardianError {
+    fn from(error: prost::DecodeError) -> Self {
+        Self::Serialization(error.into())
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for GuardianError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Storage(error.into())
+    }
+}
+
+/// Result of the public APIs across the Guardian crates
+pub type Result<T, E = GuardianError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogEvent;
+
+    #[test]
+    fn test_errors_keep_their_kind_and_message() {
+        let error = LogEvent::from_json("{").unwrap_err();
+        assert!(matches!(error, GuardianError::Serialization(_)));
+
+        let error = GuardianError::config("Rule x has no conditions");
+        assert!(matches!(error, GuardianError::Config(_)));
+        assert_eq!(error.to_string(), "Rule x has no conditions");
+
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "events.db");
+        let error = GuardianError::storage(io);
+        assert_eq!(error.to_string(), "events.db");
+
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "guardian.toml");
+        let error = GuardianError::config_context("Failed to read config", io);
+        assert!(matches!(error, GuardianError::Config(_)));
+        assert_eq!(error.to_string(), "Failed to read config");
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "guardian.toml");
+    }
+}
@@ -0,0 +1,132 @@
+//! Graylog Extended Log Format messages for events. Type-specific fields
+//! become GELF additional fields, so Graylog streams and extractors can
+//! match on them without parsing the message text.
+
+use crate::{EventType, LogEvent, Severity};
+use serde_json::{Map, Value};
+
+/// GELF specification version the messages follow
+pub const GELF_VERSION: &str = "1.1";
+
+/// Syslog level GELF expects in `level`
+fn level(severity: Severity) -> u8 {
+    match severity {
+        Severity::Critical => 2,
+        Severity::High => 3,
+        Severity::Medium => 4,
+        Severity::Low => 5,
+        Severity::Info => 6,
+    }
+}
+
+/// Add `value` as `_<prefix>`, flattening objects into `_<prefix>_<key>`.
+/// GELF values are strings or numbers, so other values are written as text.
+fn add_field(message: &mut Map<String, Value>, prefix: &str, value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::Object(object) => {
+            for (key, value) in object {
+                add_field(message, &format!("{}_{}", prefix, key), value);
+            }
+        }
+        Value::Number(_) | Value::String(_) => {
+            message.insert(format!("_{}", prefix), value.clone());
+        }
+        Value::Bool(flag) => {
+            message.insert(format!("_{}", prefix), Value::from(u8::from(*flag)));
+        }
+        Value::Array(_) => {
+            message.insert(format!("_{}", prefix), Value::from(value.to_string()));
+        }
+    }
+}
+
+impl LogEvent {
+    /// One-line summary for `short_message`
+    fn gelf_short_message(&self) -> String {
+        match &self.event_type {
+            EventType::SystemLog { message, .. } => message.clone(),
+            EventType::Alert { title, .. } => title.clone(),
+            _ => self.siem_name(),
+        }
+    }
+
+    /// Render as a GELF 1.1 message
+    pub fn to_gelf(&self) -> Value {
+        let mut message = Map::new();
+        message.insert("version".into(), GELF_VERSION.into());
+        message.insert("host".into(), self.hostname.clone().into());
+        message.insert("short_message".into(), self.gelf_short_message().into());
+        if let EventType::Alert { description, .. } = &self.event_type {
+            message.insert("full_message".into(), description.clone().into());
+        }
+        message.insert(
+            "timestamp".into(),
+            (self.timestamp.timestamp_millis() as f64 / 1000.0).into(),
+        );
+        message.insert("level".into(), level(self.severity).into());
+
+        // `_id` is reserved by GELF
+        message.insert("_event_id".into(), self.id.to_string().into());
+        message.insert("_event_type".into(), self.event_type.kind().into());
+        message.insert(
+            "_severity".into(),
+            format!("{:?}", self.severity).to_uppercase().into(),
+        );
+        if let Some(rule) = &self.rule_name {
+            message.insert("_rule".into(), rule.clone().into());
+        }
+        if !self.tags.is_empty() {
+            message.insert("_tags".into(), self.tags.join(",").into());
+        }
+
+        if let Ok(Value::Object(fields)) = serde_json::to_value(&self.event_type) {
+            for (key, value) in fields.iter().filter(|(key, _)| *key != "type") {
+                add_field(&mut message, key, value);
+            }
+        }
+        Value::Object(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventUser, FileOperation};
+
+    #[test]
+    fn test_gelf_additional_fields() {
+        let event = LogEvent::new(
+            Severity::High,
+            EventType::FileIntegrity {
+                path: "/etc/passwd".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: Some(EventUser {
+                    uid: 0,
+                    username: Some("root".to_string()),
+                }),
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("Passwd changed");
+
+        let gelf = event.to_gelf();
+        assert_eq!(gelf["version"], "1.1");
+        assert_eq!(gelf["host"], "web-1");
+        assert_eq!(gelf["short_message"], "Passwd changed");
+        assert_eq!(gelf["level"], 3);
+        assert_eq!(gelf["_event_id"], event.id.to_string());
+        assert_eq!(gelf["_event_type"], "file_integrity");
+        assert_eq!(gelf["_severity"], "HIGH");
+        assert_eq!(gelf["_path"], "/etc/passwd");
+        assert_eq!(gelf["_operation"], "modify");
+        assert_eq!(gelf["_user_uid"], 0);
+        assert_eq!(gelf["_user_username"], "root");
+        assert!(gelf.get("_hash").is_none());
+        assert!(gelf.get("_type").is_none());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::GuardianError;
+use serde::{Deserialize, Serialize};
+
+/// First line a daemon writes on stdout, describing what this agent can do so
+/// the console can hide actions it does not support
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentHello {
+    pub hostname: String,
+
+    /// Daemon package version
+    pub agent_version: String,
+
+    /// `LogEvent` layout version the agent writes
+    pub schema_version: u32,
+
+    pub capabilities: Capabilities,
+
+    /// HMAC-SHA256 of the rest in hex, when the agent signs what it emits
+    /// (see [`crate::EventSigner`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Features enabled on an agent, named as they appear on the wire
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Running event sources (`file_monitor`, `auth_log`, ...)
+    pub collectors: Vec<String>,
+
+    /// Where events are delivered (`stdout`, ...)
+    pub sinks: Vec<String>,
+
+    /// `EventType` kinds the agent may emit
+    pub event_types: Vec<String>,
+
+    /// `ControlCommand`s the agent accepts (`isolate_host`, ...)
+    pub commands: Vec<String>,
+
+    /// Socket frame encodings the agent writes (`cbor`, `json`); empty for
+    /// agents that only write JSON
+    pub encodings: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn supports_command(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c == command)
+    }
+
+    pub fn emits(&self, event_type: &str) -> bool {
+        self.event_types.iter().any(|t| t == event_type)
+    }
+}
+
+impl AgentHello {
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String, GuardianError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserialize from JSON string
+    pub fn from_json(json: &str) -> Result<Self, GuardianError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogEvent, StatsRollup};
+
+    #[test]
+    fn test_hello_is_distinct_from_other_lines() {
+        let hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: crate::SCHEMA_VERSION,
+            capabilities: Capabilities {
+                collectors: vec!["file_monitor".to_string()],
+                sinks: vec!["stdout".to_string()],
+                event_types: vec!["file_integrity".to_string()],
+                commands: vec!["set_sampling".to_string()],
+                encodings: vec!["json".to_string()],
+            },
+            signature: None,
+        };
+
+        let json = hello.to_json().unwrap();
+        assert_eq!(AgentHello::from_json(&json).unwrap(), hello);
+        assert!(LogEvent::from_json_any_version(&json).is_err());
+        assert!(StatsRollup::from_json(&json).is_err());
+
+        assert!(hello.capabilities.supports_command("set_sampling"));
+        assert!(!hello.capabilities.supports_command("isolate_host"));
+        assert!(hello.capabilities.emits("file_integrity"));
+    }
+}
@@ -0,0 +1,388 @@
+//! Framing for the daemon's local socket: every frame is a big-endian `u32`
+//! byte count followed by that many bytes of JSON, so a stray write can never
+//! be mistaken for an event the way a line on stdout can. Clients may offer
+//! CBOR in their `ClientHello`; when the daemon accepts, every frame after
+//! its `Hello` uses it in both directions.
+
+use crate::{
+    schema, AgentHello, ControlCommand, GuardianError, LogEvent, RuleStatsReport, StatsRollup,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Bumped when frames change incompatibly; checked during the handshake
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Frames larger than this are treated as a protocol error
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// How frame bodies are written once the handshake is done. The handshake
+/// itself is always JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Json,
+    /// Smaller and faster to parse, for event streams
+    Cbor,
+}
+
+impl Encoding {
+    /// Every encoding, preferred first
+    pub const SUPPORTED: [Encoding; 2] = [Encoding::Cbor, Encoding::Json];
+
+    /// Name in `ClientHello` and the agent's capabilities
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Cbor => "cbor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::SUPPORTED.into_iter().find(|e| e.name() == name)
+    }
+
+    /// Names of every encoding, preferred first, to offer or advertise
+    pub fn supported() -> Vec<String> {
+        Self::SUPPORTED
+            .iter()
+            .map(|e| e.name().to_string())
+            .collect()
+    }
+
+    /// The first of the client's `offered` encodings that the daemon
+    /// `accepted`, else JSON, which every peer reads
+    pub fn negotiate(offered: &[String], accepted: &[String]) -> Self {
+        offered
+            .iter()
+            .filter(|name| accepted.contains(name))
+            .find_map(|name| Self::from_name(name))
+            .unwrap_or_default()
+    }
+}
+
+/// Messages exchanged over the socket
+// Nearly every frame is an event, so boxing it would only add an allocation
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "body", rename_all = "snake_case")]
+pub enum Frame {
+    /// First frame from the console on every connection
+    ClientHello {
+        protocol_version: u32,
+        /// Name of the connecting program, for the daemon's log
+        client: String,
+        /// Encodings the client reads, preferred first; none means JSON only
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        encodings: Vec<String>,
+    },
+    /// Daemon's answer to an accepted `ClientHello`
+    Hello(AgentHello),
+    /// Daemon's answer to an incompatible `ClientHello`; the connection is closed after it
+    Reject {
+        reason: String,
+    },
+    Event(LogEvent),
+    Rollup(StatsRollup),
+    /// Rule hit counts, sent daily and when the console asks
+    RuleStats(RuleStatsReport),
+    /// Console-to-daemon command
+    Control(ControlCommand),
+    /// Frame relayed from an agent to a remote collector, numbered so the
+    /// collector can acknowledge it
+    Forwarded {
+        seq: u64,
+        frame: Box<Frame>,
+    },
+    /// Collector's acknowledgement of every forwarded frame up to and including `seq`
+    Ack {
+        seq: u64,
+    },
+    /// First frame from a daemon without a client certificate asking a
+    /// collector to issue one, authorized by a one-time token
+    Enroll {
+        token: String,
+        hostname: String,
+        /// PEM certificate signing request for the daemon's own key
+        csr: String,
+    },
+    /// Collector's answer to an accepted `Enroll`; the connection is closed after it
+    Enrolled {
+        /// PEM client certificate to present when forwarding
+        certificate: String,
+    },
+}
+
+impl Frame {
+    /// Length prefix followed by the JSON body
+    pub fn encode(&self) -> Result<Vec<u8>, GuardianError> {
+        self.encode_as(Encoding::Json)
+    }
+
+    /// Length prefix followed by the body in `encoding`
+    pub fn encode_as(&self, encoding: Encoding) -> Result<Vec<u8>, GuardianError> {
+        let body = match encoding {
+            Encoding::Json => serde_json::to_vec(self)?,
+            Encoding::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(self, &mut body)?;
+                body
+            }
+        };
+        let mut frame = Vec::with_capacity(body.len() + 4);
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&body);
+        Ok(frame)
+    }
+
+    /// Parse a frame body (without its length prefix), upgrading events
+    /// written with an older schema
+    pub fn decode(body: &[u8]) -> Result<Self, GuardianError> {
+        let mut value: Value = serde_json::from_slice(body)?;
+        upgrade_events(&mut value);
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Parse a frame body in `encoding`. CBOR is only negotiated by agents
+    /// writing the current schema, so its events need no upgrade.
+    pub fn decode_as(body: &[u8], encoding: Encoding) -> Result<Self, GuardianError> {
+        match encoding {
+            Encoding::Json => Self::decode(body),
+            Encoding::Cbor => Ok(ciborium::from_reader(body)?),
+        }
+    }
+}
+
+/// Upgrade the event in an `event` frame, including one wrapped in `forwarded`
+fn upgrade_events(frame: &mut Value) {
+    match frame.get("kind").and_then(Value::as_str) {
+        Some("event") => {
+            if let Some(event) = frame.get_mut("body") {
+                schema::upgrade(event);
+            }
+        }
+        Some("forwarded") => {
+            if let Some(inner) = frame.pointer_mut("/body/frame") {
+                upgrade_events(inner);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(feature = "async")]
+mod io {
+    use super::{Encoding, Frame, MAX_FRAME_LEN};
+    use std::io::{Error, ErrorKind};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    /// Read the next frame; `None` when the peer closed the connection between frames
+    pub async fn read_frame<R: AsyncRead + Unpin>(
+        reader: &mut R,
+    ) -> std::io::Result<Option<Frame>> {
+        read_frame_as(reader, Encoding::Json).await
+    }
+
+    /// Read the next frame in the negotiated `encoding`
+    pub async fn read_frame_as<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        encoding: Encoding,
+    ) -> std::io::Result<Option<Frame>> {
+        let len = match reader.read_u32().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if len > MAX_FRAME_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("frame of {} bytes exceeds limit", len),
+            ));
+        }
+
+        let mut body = vec![0; len as usize];
+        reader.read_exact(&mut body).await?;
+        Frame::decode_as(&body, encoding)
+            .map(Some)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub async fn write_frame<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        frame: &Frame,
+    ) -> std::io::Result<()> {
+        write_frame_as(writer, frame, Encoding::Json).await
+    }
+
+    pub async fn write_frame_as<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        frame: &Frame,
+        encoding: Encoding,
+    ) -> std::io::Result<()> {
+        let bytes = frame
+            .encode_as(encoding)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        writer.write_all(&bytes).await?;
+        writer.flush().await
+    }
+
+    /// Bind a Unix socket at `path`, replacing a stale one, readable and
+    /// writable by the owner only. The socket is bound in a directory only
+    /// the owner can enter and moved into place once its mode is set, so it
+    /// is never reachable by others in between.
+    #[cfg(unix)]
+    pub fn bind_private(path: &std::path::Path) -> std::io::Result<tokio::net::UnixListener> {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+        std::fs::create_dir_all(parent)?;
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        let staging = parent.join(format!(".{}", uuid::Uuid::new_v4().simple()));
+        std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+        let staged = staging.join("socket");
+        let bound = (|| {
+            let listener = tokio::net::UnixListener::bind(&staged)?;
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, path)?;
+            Ok(listener)
+        })();
+        let _ = std::fs::remove_dir_all(&staging);
+        bound
+    }
+}
+
+#[cfg(all(feature = "async", unix))]
+pub use io::bind_private;
+#[cfg(feature = "async")]
+pub use io::{read_frame, read_frame_as, write_frame, write_frame_as};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, Severity};
+
+    #[test]
+    fn test_frame_encoding_and_legacy_event_upgrade() {
+        let frame = Frame::Control(ControlCommand::SetSampling { rate: 4 });
+        let bytes = frame.encode().unwrap();
+        let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, bytes.len() - 4);
+        assert!(matches!(
+            Frame::decode(&bytes[4..]).unwrap(),
+            Frame::Control(ControlCommand::SetSampling { rate: 4 })
+        ));
+
+        // An event body from an agent predating `schema_version`
+        let mut event = serde_json::to_value(LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "kernel".to_string(),
+                level: "info".to_string(),
+                message: "ok".to_string(),
+            },
+            "web-1".to_string(),
+        ))
+        .unwrap();
+        event.as_object_mut().unwrap().remove("schema_version");
+        let body = serde_json::json!({ "kind": "event", "body": event }).to_string();
+
+        let Frame::Event(event) = Frame::decode(body.as_bytes()).unwrap() else {
+            panic!("expected an event frame");
+        };
+        assert_eq!(event.schema_version, crate::SCHEMA_VERSION);
+
+        // The same legacy event relayed by a forwarder
+        let mut event = serde_json::to_value(&event).unwrap();
+        event.as_object_mut().unwrap().remove("schema_version");
+        let body = serde_json::json!({
+            "kind": "forwarded",
+            "body": { "seq": 7, "frame": { "kind": "event", "body": event } },
+        })
+        .to_string();
+        match Frame::decode(body.as_bytes()).unwrap() {
+            Frame::Forwarded { seq: 7, frame } => {
+                assert!(
+                    matches!(*frame, Frame::Event(e) if e.schema_version == crate::SCHEMA_VERSION)
+                )
+            }
+            other => panic!("expected a forwarded frame, got {:?}", other),
+        }
+    }
+    #[test]
+    fn test_encoding_negotiation_and_cbor_frames() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let daemon = Encoding::supported();
+        assert_eq!(
+            Encoding::negotiate(&names(&["cbor", "json"]), &daemon),
+            Encoding::Cbor
+        );
+        assert_eq!(
+            Encoding::negotiate(&names(&["msgpack", "json"]), &daemon),
+            Encoding::Json
+        );
+        assert_eq!(Encoding::negotiate(&[], &daemon), Encoding::Json);
+        // A daemon predating encodings advertises none
+        assert_eq!(Encoding::negotiate(&names(&["cbor"]), &[]), Encoding::Json);
+
+        // Older daemons still read a hello without encodings
+        let hello = Frame::ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            client: "bridge".to_string(),
+            encodings: Vec::new(),
+        };
+        let body = hello.encode().unwrap();
+        assert!(!std::str::from_utf8(&body[4..])
+            .unwrap()
+            .contains("encodings"));
+
+        let event = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "kernel".to_string(),
+                level: "info".to_string(),
+                message: "ok".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        let frame = Frame::Forwarded {
+            seq: 3,
+            frame: Box::new(Frame::Event(event.clone())),
+        };
+        let cbor = frame.encode_as(Encoding::Cbor).unwrap();
+        assert!(cbor.len() < frame.encode().unwrap().len());
+        match Frame::decode_as(&cbor[4..], Encoding::Cbor).unwrap() {
+            Frame::Forwarded { seq: 3, frame } => {
+                assert!(matches!(*frame, Frame::Event(e) if e.id == event.id))
+            }
+            other => panic!("expected a forwarded frame, got {:?}", other),
+        }
+        assert!(Frame::decode_as(&cbor[4..], Encoding::Json).is_err());
+    }
+
+    #[cfg(all(feature = "async", unix))]
+    #[tokio::test]
+    async fn test_bind_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("guardian-ipc-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("guardian.sock");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&path, b"stale").unwrap();
+
+        let _listener = bind_private(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the socket is left, where the stale file was
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        tokio::net::UnixStream::connect(&path).await.unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,8 +2,44 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub mod builder;
+#[cfg(feature = "cel")]
+pub mod cel;
+pub mod control;
+pub mod dbkey;
+pub mod ecs;
+pub mod error;
+pub mod gelf;
+pub mod hello;
+pub mod ipc;
+#[cfg(feature = "proto")]
+pub mod proto;
+pub mod rollup;
+pub mod rule;
+pub mod rulestats;
+pub mod schema;
+#[cfg(feature = "rhai")]
+pub mod script;
+pub mod search;
+pub mod siem;
+pub mod signing;
+#[cfg(feature = "sqlx")]
+pub mod store;
+
+pub use builder::LogEventBuilder;
+pub use control::ControlCommand;
+pub use error::GuardianError;
+pub use hello::{AgentHello, Capabilities};
+pub use rollup::StatsRollup;
+pub use rule::{CompiledRule, RuleActions, RuleDefinition, RuleResponse, RuleSet, RuleTest};
+pub use rulestats::{RuleStats, RuleStatsReport};
+pub use schema::SCHEMA_VERSION;
+pub use search::SearchFilter;
+pub use signing::EventSigner;
+
 /// Severity levels for security events
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Severity {
     Info,
@@ -13,6 +49,18 @@ pub enum Severity {
     Critical,
 }
 
+impl Severity {
+    /// One level more severe; Critical stays Critical
+    pub fn raised(self) -> Self {
+        match self {
+            Severity::Info => Severity::Low,
+            Severity::Low => Severity::Medium,
+            Severity::Medium => Severity::High,
+            Severity::High | Severity::Critical => Severity::Critical,
+        }
+    }
+}
+
 /// Types of events the Guardian daemon can collect
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -22,13 +70,36 @@ pub enum EventType {
         path: String,
         operation: FileOperation,
         hash: Option<String>,
+        /// Owner of the file, when it still exists
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<EventUser>,
+        /// Header details, when the file is an executable
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        executable: Option<ExecutableInfo>,
+        /// Process that had the file open, when it could be found
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        process: Option<ProcessAncestor>,
+        /// Unified diff against the previous contents, for modified files
+        /// whose contents are kept
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        diff: Option<String>,
     },
     /// Network socket events
     NetworkSocket {
         local_addr: String,
         remote_addr: Option<String>,
+        /// Reverse-DNS name of the remote address, when resolved
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        remote_host: Option<String>,
         protocol: String,
         state: String,
+        /// Byte counts and application details seen on the wire, for
+        /// connections observed by packet capture
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        traffic: Option<Traffic>,
+        /// Process owning the socket, when it could be found
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        process: Option<ProcessAncestor>,
     },
     /// System log events
     SystemLog {
@@ -42,7 +113,235 @@ pub enum EventType {
         name: String,
         cpu_usage: f32,
         memory_usage: u64,
+        /// Parent process chain, nearest parent first
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ancestry: Vec<ProcessAncestor>,
+        /// User the process runs as
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<EventUser>,
+    },
+    /// Authentication attempts (SSH, sudo, su, console logins)
+    AuthEvent {
+        /// Account the attempt was for
+        user: String,
+        source_ip: Option<String>,
+        /// How the user authenticated, e.g. `password`, `publickey`, `sudo`
+        method: String,
+        outcome: AuthOutcome,
+        tty: Option<String>,
+    },
+    /// Software installed, upgraded or removed through the package manager
+    PackageChange {
+        name: String,
+        /// Version installed, or removed for `Remove`
+        version: Option<String>,
+        action: PackageAction,
+        /// `dpkg`, `rpm` or `pacman`
+        manager: String,
     },
+    /// Periodic sign of life, so a silent agent can be told from a quiet one
+    Heartbeat {
+        agent_version: String,
+        uptime_secs: u64,
+        /// Seconds until the next heartbeat is due
+        interval_secs: u64,
+        /// Collector queue, when the agent forwards events
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        forward: Option<ForwardStats>,
+    },
+    /// Finding synthesized from several raw events, e.g. by correlation
+    Alert {
+        title: String,
+        description: String,
+        /// Ids of the raw events the alert was derived from
+        related_event_ids: Vec<Uuid>,
+        /// MITRE ATT&CK technique id, e.g. `T1110`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        technique: Option<String>,
+    },
+    /// How far an on-demand scan has got, sent while it runs
+    ScanProgress {
+        scan_id: Uuid,
+        /// Path the scan was asked to cover
+        path: String,
+        files_scanned: u64,
+        /// Files the scan will cover, when counted up front
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        files_total: Option<u64>,
+        /// File being scanned when the event was sent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        current_path: Option<String>,
+        /// Share of `files_total` scanned, 0 to 100
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percent: Option<f32>,
+    },
+    /// Outcome of an on-demand scan, sent once when it ends
+    ScanSummary {
+        scan_id: Uuid,
+        path: String,
+        /// Operator who started the scan
+        requested_by: String,
+        outcome: ScanOutcome,
+        files_scanned: u64,
+        /// Files with at least one YARA match
+        files_matched: u64,
+        /// Directories that could not be read and were left out
+        #[serde(default)]
+        dirs_skipped: u64,
+        duration_ms: u64,
+        /// Why the scan failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// User owning the file or process behind an event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EventUser {
+    pub uid: u32,
+    /// Account name, when the uid resolves to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Traffic of one connection over a capture interval
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Traffic {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets: u64,
+    /// Server name from the TLS ClientHello
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// `Host` header of a plain HTTP request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_host: Option<String>,
+}
+
+/// What the headers of an executable file say about it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExecutableInfo {
+    pub format: ExecutableFormat,
+    /// Target machine, e.g. `x86_64` or `aarch64`
+    pub arch: String,
+    /// Shared library or DLL rather than a program
+    #[serde(default)]
+    pub library: bool,
+    /// Shared libraries or DLLs it links against
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libraries: Vec<String>,
+    /// Section names, in file order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<String>,
+    /// Whether it carries an embedded code signature, which is not
+    /// verified; `None` for formats without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed: Option<bool>,
+    /// Shannon entropy of the whole file in bits per byte, 0 to 8; packed
+    /// or encrypted code sits near 8
+    #[serde(default)]
+    pub entropy: f32,
+}
+
+/// Executable file formats whose headers are parsed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutableFormat {
+    Elf,
+    Pe,
+}
+
+/// Forwarding queue state reported in heartbeats
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ForwardStats {
+    /// Frames not yet acknowledged by the collector
+    pub pending: u64,
+    /// Frames dropped because the buffer was full
+    pub dropped: u64,
+}
+
+/// One process in a parent chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessAncestor {
+    pub pid: u32,
+    pub name: String,
+}
+
+impl EventType {
+    /// Stable snake_case name of this event type, as used in the serialized `type` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EventType::FileIntegrity { .. } => "file_integrity",
+            EventType::NetworkSocket { .. } => "network_socket",
+            EventType::SystemLog { .. } => "system_log",
+            EventType::ProcessMonitor { .. } => "process_monitor",
+            EventType::AuthEvent { .. } => "auth_event",
+            EventType::PackageChange { .. } => "package_change",
+            EventType::Heartbeat { .. } => "heartbeat",
+            EventType::Alert { .. } => "alert",
+            EventType::ScanProgress { .. } => "scan_progress",
+            EventType::ScanSummary { .. } => "scan_summary",
+        }
+    }
+
+    /// Severity of an event of this type when its collector sets none:
+    /// changes, failed logins and syslog warnings rank LOW, syslog errors
+    /// and failed scans MEDIUM, alerts and scans that matched HIGH, and
+    /// anything else INFO
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            EventType::FileIntegrity { operation, .. } => match operation {
+                FileOperation::Access | FileOperation::Scan => Severity::Info,
+                _ => Severity::Low,
+            },
+            EventType::SystemLog { level, .. } => match level.as_str() {
+                "emerg" | "alert" | "crit" | "critical" => Severity::High,
+                "err" | "error" => Severity::Medium,
+                "warning" | "warn" => Severity::Low,
+                _ => Severity::Info,
+            },
+            EventType::AuthEvent {
+                outcome: AuthOutcome::Failure,
+                ..
+            } => Severity::Low,
+            EventType::PackageChange { .. } => Severity::Low,
+            EventType::Alert { .. } => Severity::High,
+            EventType::ScanSummary {
+                outcome: ScanOutcome::Failed,
+                ..
+            } => Severity::Medium,
+            EventType::ScanSummary { files_matched, .. } if *files_matched > 0 => Severity::High,
+            _ => Severity::Info,
+        }
+    }
+}
+
+/// Result of an authentication attempt
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthOutcome {
+    Success,
+    Failure,
+}
+
+/// How an on-demand scan ended
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanOutcome {
+    /// Every file found was scanned
+    Completed,
+    /// The path could not be scanned at all
+    Failed,
+}
+
+/// What happened to a package
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageAction {
+    Install,
+    Upgrade,
+    Downgrade,
+    Remove,
 }
 
 /// File operations for integrity monitoring
@@ -54,43 +353,66 @@ pub enum FileOperation {
     Delete,
     Rename,
     Chmod,
+    /// Opened or read without being changed
+    Access,
+    /// Examined by an on-demand scan; the file did not necessarily change
+    Scan,
 }
 
 /// Core log event structure shared between daemon and frontend
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEvent {
+    /// Layout version this event was written with
+    #[serde(default = "schema::legacy_schema_version")]
+    pub schema_version: u32,
+
     /// Unique identifier for this event
     pub id: Uuid,
-    
+
     /// Timestamp when the event occurred
     pub timestamp: DateTime<Utc>,
-    
+
     /// Severity level of the event
     pub severity: Severity,
-    
+
     /// The actual event data
     #[serde(flatten)]
     pub event_type: EventType,
-    
+
     /// Hostname where the event originated
     pub hostname: String,
-    
+
     /// Optional tags for categorization
     #[serde(default)]
     pub tags: Vec<String>,
-    
+
     /// Whether this event triggered any rules
     #[serde(default)]
     pub rule_triggered: bool,
-    
-    /// Optional rule name that was triggered
+
+    /// Optional rule name that was triggered; the most severe of `rules_matched`
     pub rule_name: Option<String>,
+
+    /// Every rule the event triggered, most severe first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules_matched: Vec<String>,
+
+    /// HMAC-SHA256 of the rest of the event in hex, when the agent signs
+    /// events (see [`EventSigner`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 impl LogEvent {
+    /// Start an event of `event_type` from `hostname`, checked when built
+    pub fn builder(event_type: EventType, hostname: impl Into<String>) -> LogEventBuilder {
+        LogEventBuilder::new(event_type, hostname)
+    }
+
     /// Create a new log event
     pub fn new(severity: Severity, event_type: EventType, hostname: String) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             id: Uuid::new_v4(),
             timestamp: Utc::now(),
             severity,
@@ -99,30 +421,75 @@ impl LogEvent {
             tags: Vec::new(),
             rule_triggered: false,
             rule_name: None,
+            rules_matched: Vec::new(),
+            signature: None,
         }
     }
-    
+
     /// Add a tag to this event
     pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
         self.tags.push(tag.into());
         self
     }
-    
+
     /// Mark this event as having triggered a rule
-    pub fn with_rule(mut self, rule_name: impl Into<String>) -> Self {
-        self.rule_triggered = true;
-        self.rule_name = Some(rule_name.into());
+    pub fn with_rule(self, rule_name: impl Into<String>) -> Self {
+        self.with_rules([rule_name.into()])
+    }
+
+    /// Mark this event as having triggered `rules`, most severe first. Rules
+    /// it already carried are kept after them; the first becomes `rule_name`.
+    pub fn with_rules(mut self, rules: impl IntoIterator<Item = String>) -> Self {
+        let mut matched: Vec<String> = rules.into_iter().collect();
+        let earlier = std::mem::take(&mut self.rules_matched);
+        for rule in earlier.into_iter().chain(self.rule_name.take()) {
+            if !matched.contains(&rule) {
+                matched.push(rule);
+            }
+        }
+        self.rule_triggered = !matched.is_empty();
+        self.rule_name = matched.first().cloned();
+        self.rules_matched = matched;
         self
     }
-    
+
+    /// Key grouping repeated hits of one rule on one host, as `rule@hostname`;
+    /// `None` unless the event triggered a rule
+    pub fn correlation_key(&self) -> Option<String> {
+        self.rule_name
+            .as_ref()
+            .filter(|_| self.rule_triggered)
+            .map(|rule| format!("{}@{}", rule, self.hostname))
+    }
+
     /// Serialize to JSON string
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
+    pub fn to_json(&self) -> Result<String, GuardianError> {
+        Ok(serde_json::to_string(self)?)
     }
-    
+
     /// Deserialize from JSON string
-    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+    pub fn from_json(json: &str) -> Result<Self, GuardianError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Deserialize an event written with any schema version, upgrading older layouts
+    pub fn from_json_any_version(json: &str) -> Result<Self, GuardianError> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        schema::upgrade(&mut value);
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Serialize to CBOR, the compact binary form used on negotiated sockets
+    pub fn to_cbor(&self) -> Result<Vec<u8>, GuardianError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Deserialize from CBOR. Only agents that write the current schema
+    /// speak CBOR, so there is no older layout to upgrade.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, GuardianError> {
+        Ok(ciborium::from_reader(bytes)?)
     }
 }
 
@@ -138,6 +505,10 @@ mod tests {
                 path: "/etc/passwd".to_string(),
                 operation: FileOperation::Modify,
                 hash: Some("abc123".to_string()),
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
             },
             "localhost".to_string(),
         );
@@ -149,11 +520,225 @@ mod tests {
         assert_eq!(event.hostname, deserialized.hostname);
     }
 
+    #[test]
+    fn test_log_event_cbor_round_trip() {
+        let event = LogEvent::new(
+            Severity::Medium,
+            EventType::AuthEvent {
+                user: "alice".to_string(),
+                source_ip: Some("10.0.0.5".to_string()),
+                method: "password".to_string(),
+                outcome: AuthOutcome::Failure,
+                tty: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_tag("auth_log")
+        .with_rule("SSH Brute Force");
+
+        let cbor = event.to_cbor().unwrap();
+        assert!(cbor.len() < event.to_json().unwrap().len());
+        let decoded = LogEvent::from_cbor(&cbor).unwrap();
+        assert_eq!(decoded.to_json().unwrap(), event.to_json().unwrap());
+
+        // JSON text is not mistaken for an event
+        assert!(LogEvent::from_cbor(event.to_json().unwrap().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_correlation_key_requires_rule() {
+        let event = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "kernel".to_string(),
+                level: "info".to_string(),
+                message: "ok".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        assert_eq!(event.correlation_key(), None);
+        assert_eq!(
+            event
+                .with_rule("SSH Brute Force")
+                .correlation_key()
+                .as_deref(),
+            Some("SSH Brute Force@web-1")
+        );
+    }
+
+    #[test]
+    fn test_with_rules_keeps_every_match() {
+        let event = LogEvent::new(
+            Severity::Critical,
+            EventType::SystemLog {
+                source: "kernel".to_string(),
+                level: "error".to_string(),
+                message: "panic".to_string(),
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("threat_intel_ip_match")
+        .with_rules([
+            "canary_touched".to_string(),
+            "high_severity_alert".to_string(),
+        ]);
+        assert!(event.rule_triggered);
+        assert_eq!(event.rule_name.as_deref(), Some("canary_touched"));
+        assert_eq!(
+            event.rules_matched,
+            [
+                "canary_touched",
+                "high_severity_alert",
+                "threat_intel_ip_match"
+            ]
+        );
+
+        let json = event.to_json().unwrap();
+        assert_eq!(LogEvent::from_json(&json).unwrap().rules_matched.len(), 3);
+        let untouched = LogEvent::from_json(&json.replace("\"rules_matched\"", "\"ignored\""));
+        assert!(untouched.unwrap().rules_matched.is_empty());
+    }
+
+    #[test]
+    fn test_event_type_kind_matches_tag() {
+        let event_type = EventType::SystemLog {
+            source: "kernel".to_string(),
+            level: "error".to_string(),
+            message: "oops".to_string(),
+        };
+
+        let json = serde_json::to_value(&event_type).unwrap();
+        assert_eq!(json["type"], event_type.kind());
+    }
+
+    #[test]
+    fn test_auth_event_round_trip() {
+        let event = LogEvent::new(
+            Severity::Low,
+            EventType::AuthEvent {
+                user: "root".to_string(),
+                source_ip: Some("203.0.113.7".to_string()),
+                method: "password".to_string(),
+                outcome: AuthOutcome::Failure,
+                tty: None,
+            },
+            "bastion".to_string(),
+        );
+
+        let json = event.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], "auth_event");
+        assert_eq!(value["outcome"], "failure");
+
+        let deserialized = LogEvent::from_json(&json).unwrap();
+        assert_eq!(deserialized.event_type, event.event_type);
+        assert_eq!(deserialized.event_type.kind(), "auth_event");
+    }
+
+    #[test]
+    fn test_package_change_round_trip() {
+        let event_type = EventType::PackageChange {
+            name: "netcat-openbsd".to_string(),
+            version: Some("1.219-1".to_string()),
+            action: PackageAction::Install,
+            manager: "dpkg".to_string(),
+        };
+
+        let json = serde_json::to_value(&event_type).unwrap();
+        assert_eq!(json["type"], event_type.kind());
+        assert_eq!(json["action"], "install");
+        assert_eq!(
+            serde_json::from_value::<EventType>(json).unwrap(),
+            event_type
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_round_trip() {
+        let event_type = EventType::Heartbeat {
+            agent_version: "0.1.0".to_string(),
+            uptime_secs: 3600,
+            interval_secs: 60,
+            forward: Some(ForwardStats {
+                pending: 12,
+                dropped: 0,
+            }),
+        };
+
+        let json = serde_json::to_value(&event_type).unwrap();
+        assert_eq!(json["type"], event_type.kind());
+        assert_eq!(json["forward"]["pending"], 12);
+        assert_eq!(
+            serde_json::from_value::<EventType>(json).unwrap(),
+            event_type
+        );
+
+        // Agents that do not forward omit the queue
+        let json = serde_json::json!({
+            "type": "heartbeat",
+            "agent_version": "0.1.0",
+            "uptime_secs": 5,
+            "interval_secs": 60
+        });
+        assert!(matches!(
+            serde_json::from_value::<EventType>(json).unwrap(),
+            EventType::Heartbeat { forward: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_alert_references_raw_events() {
+        let related = [Uuid::new_v4(), Uuid::new_v4()];
+        let alert = LogEvent::new(
+            Severity::High,
+            EventType::Alert {
+                title: "SSH brute force followed by login".to_string(),
+                description: "12 failed logins then a success for root".to_string(),
+                related_event_ids: related.to_vec(),
+                technique: Some("T1110".to_string()),
+            },
+            "bastion".to_string(),
+        );
+
+        let json = alert.to_json().unwrap();
+        assert!(json.contains(r#""type":"alert""#));
+        let parsed = LogEvent::from_json(&json).unwrap();
+        assert_eq!(parsed.event_type, alert.event_type);
+    }
+
+    #[test]
+    fn test_scan_summary_round_trip() {
+        let summary = LogEvent::new(
+            Severity::High,
+            EventType::ScanSummary {
+                scan_id: Uuid::new_v4(),
+                path: "/srv/uploads".to_string(),
+                requested_by: "alice".to_string(),
+                outcome: ScanOutcome::Completed,
+                files_scanned: 1200,
+                files_matched: 1,
+                dirs_skipped: 2,
+                duration_ms: 5400,
+                error: None,
+            },
+            "web-1".to_string(),
+        );
+
+        let json = summary.to_json().unwrap();
+        assert!(json.contains(r#""type":"scan_summary""#));
+        assert!(json.contains(r#""outcome":"completed""#));
+        assert!(!json.contains("error"));
+        let parsed = LogEvent::from_json(&json).unwrap();
+        assert_eq!(parsed.event_type, summary.event_type);
+    }
+
     #[test]
     fn test_severity_ordering() {
         assert!(Severity::Critical > Severity::High);
         assert!(Severity::High > Severity::Medium);
         assert!(Severity::Medium > Severity::Low);
         assert!(Severity::Low > Severity::Info);
+        assert_eq!(Severity::Medium.raised(), Severity::High);
+        assert_eq!(Severity::Critical.raised(), Severity::Critical);
     }
 }
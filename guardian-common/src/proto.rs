@@ -0,0 +1,543 @@
+//! Protobuf form of events (`proto/event.proto`), for services that read
+//! Guardian's stream in other languages. Every field converts both ways;
+//! turning a message back into an event fails on values the model cannot
+//! hold, such as an unset enum or a malformed id.
+
+use crate as model;
+use crate::GuardianError;
+use chrono::{DateTime, SecondsFormat, Utc};
+use prost::Message;
+use uuid::Uuid;
+
+include!(concat!(env!("OUT_DIR"), "/guardian.event.v1.rs"));
+
+fn invalid(field: &str, value: impl std::fmt::Display) -> GuardianError {
+    GuardianError::InvalidEvent(format!("{} {} is not valid", field, value))
+}
+
+fn uuid(field: &str, value: &str) -> Result<Uuid, GuardianError> {
+    Uuid::parse_str(value).map_err(|_| invalid(field, value))
+}
+
+/// Both directions of enums named the same in the model and the schema; the
+/// schema's `Unspecified` has no counterpart
+macro_rules! enums {
+    ($($name:ident, $parse:ident { $($variant:ident),* })*) => {$(
+        impl From<model::$name> for $name {
+            fn from(value: model::$name) -> Self {
+                match value {
+                    $(model::$name::$variant => $name::$variant,)*
+                }
+            }
+        }
+
+        fn $parse(value: i32) -> Result<model::$name, GuardianError> {
+            match $name::try_from(value) {
+                $(Ok($name::$variant) => Ok(model::$name::$variant),)*
+                _ => Err(invalid(stringify!($parse), value)),
+            }
+        }
+    )*};
+}
+
+enums! {
+    Severity, severity { Info, Low, Medium, High, Critical }
+    FileOperation, file_operation { Create, Modify, Delete, Rename, Chmod, Access, Scan }
+    AuthOutcome, auth_outcome { Success, Failure }
+    PackageAction, package_action { Install, Upgrade, Downgrade, Remove }
+    ScanOutcome, scan_outcome { Completed, Failed }
+    ExecutableFormat, executable_format { Elf, Pe }
+}
+
+impl From<&model::EventUser> for EventUser {
+    fn from(user: &model::EventUser) -> Self {
+        Self {
+            uid: user.uid,
+            username: user.username.clone(),
+        }
+    }
+}
+
+impl From<EventUser> for model::EventUser {
+    fn from(user: EventUser) -> Self {
+        Self {
+            uid: user.uid,
+            username: user.username,
+        }
+    }
+}
+
+impl From<&model::Traffic> for Traffic {
+    fn from(traffic: &model::Traffic) -> Self {
+        Self {
+            bytes_sent: traffic.bytes_sent,
+            bytes_received: traffic.bytes_received,
+            packets: traffic.packets,
+            sni: traffic.sni.clone(),
+            http_host: traffic.http_host.clone(),
+        }
+    }
+}
+
+impl From<Traffic> for model::Traffic {
+    fn from(traffic: Traffic) -> Self {
+        Self {
+            bytes_sent: traffic.bytes_sent,
+            bytes_received: traffic.bytes_received,
+            packets: traffic.packets,
+            sni: traffic.sni,
+            http_host: traffic.http_host,
+        }
+    }
+}
+
+impl From<&model::ExecutableInfo> for ExecutableInfo {
+    fn from(info: &model::ExecutableInfo) -> Self {
+        Self {
+            format: ExecutableFormat::from(info.format) as i32,
+            arch: info.arch.clone(),
+            library: info.library,
+            libraries: info.libraries.clone(),
+            sections: info.sections.clone(),
+            signed: info.signed,
+            entropy: info.entropy,
+        }
+    }
+}
+
+impl TryFrom<ExecutableInfo> for model::ExecutableInfo {
+    type Error = GuardianError;
+
+    fn try_from(info: ExecutableInfo) -> Result<Self, GuardianError> {
+        Ok(Self {
+            format: executable_format(info.format)?,
+            arch: info.arch,
+            library: info.library,
+            libraries: info.libraries,
+            sections: info.sections,
+            signed: info.signed,
+            entropy: info.entropy,
+        })
+    }
+}
+
+impl From<model::ForwardStats> for ForwardStats {
+    fn from(stats: model::ForwardStats) -> Self {
+        Self {
+            pending: stats.pending,
+            dropped: stats.dropped,
+        }
+    }
+}
+
+impl From<ForwardStats> for model::ForwardStats {
+    fn from(stats: ForwardStats) -> Self {
+        Self {
+            pending: stats.pending,
+            dropped: stats.dropped,
+        }
+    }
+}
+
+impl From<&model::ProcessAncestor> for ProcessAncestor {
+    fn from(process: &model::ProcessAncestor) -> Self {
+        Self {
+            pid: process.pid,
+            name: process.name.clone(),
+        }
+    }
+}
+
+impl From<ProcessAncestor> for model::ProcessAncestor {
+    fn from(process: ProcessAncestor) -> Self {
+        Self {
+            pid: process.pid,
+            name: process.name,
+        }
+    }
+}
+
+impl From<&model::EventType> for log_event::EventType {
+    fn from(event_type: &model::EventType) -> Self {
+        use log_event::EventType as Proto;
+
+        match event_type.clone() {
+            model::EventType::FileIntegrity {
+                path,
+                operation,
+                hash,
+                user,
+                executable,
+                process,
+                diff,
+            } => Proto::FileIntegrity(FileIntegrity {
+                path,
+                operation: FileOperation::from(operation) as i32,
+                hash,
+                user: user.as_ref().map(Into::into),
+                executable: executable.as_ref().map(Into::into),
+                process: process.as_ref().map(Into::into),
+                diff,
+            }),
+            model::EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                remote_host,
+                protocol,
+                state,
+                traffic,
+                process,
+            } => Proto::NetworkSocket(NetworkSocket {
+                local_addr,
+                remote_addr,
+                remote_host,
+                protocol,
+                state,
+                traffic: traffic.as_ref().map(Into::into),
+                process: process.as_ref().map(Into::into),
+            }),
+            model::EventType::SystemLog {
+                source,
+                level,
+                message,
+            } => Proto::SystemLog(SystemLog {
+                source,
+                level,
+                message,
+            }),
+            model::EventType::ProcessMonitor {
+                pid,
+                name,
+                cpu_usage,
+                memory_usage,
+                ancestry,
+                user,
+            } => Proto::ProcessMonitor(ProcessMonitor {
+                pid,
+                name,
+                cpu_usage,
+                memory_usage,
+                ancestry: ancestry.iter().map(Into::into).collect(),
+                user: user.as_ref().map(Into::into),
+            }),
+            model::EventType::AuthEvent {
+                user,
+                source_ip,
+                method,
+                outcome,
+                tty,
+            } => Proto::AuthEvent(AuthEvent {
+                user,
+                source_ip,
+                method,
+                outcome: AuthOutcome::from(outcome) as i32,
+                tty,
+            }),
+            model::EventType::PackageChange {
+                name,
+                version,
+                action,
+                manager,
+            } => Proto::PackageChange(PackageChange {
+                name,
+                version,
+                action: PackageAction::from(action) as i32,
+                manager,
+            }),
+            model::EventType::Heartbeat {
+                agent_version,
+                uptime_secs,
+                interval_secs,
+                forward,
+            } => Proto::Heartbeat(Heartbeat {
+                agent_version,
+                uptime_secs,
+                interval_secs,
+                forward: forward.map(Into::into),
+            }),
+            model::EventType::Alert {
+                title,
+                description,
+                related_event_ids,
+                technique,
+            } => Proto::Alert(Alert {
+                title,
+                description,
+                related_event_ids: related_event_ids.iter().map(Uuid::to_string).collect(),
+                technique,
+            }),
+            model::EventType::ScanProgress {
+                scan_id,
+                path,
+                files_scanned,
+                files_total,
+                current_path,
+                percent,
+            } => Proto::ScanProgress(ScanProgress {
+                scan_id: scan_id.to_string(),
+                path,
+                files_scanned,
+                files_total,
+                current_path,
+                percent,
+            }),
+            model::EventType::ScanSummary {
+                scan_id,
+                path,
+                requested_by,
+                outcome,
+                files_scanned,
+                files_matched,
+                dirs_skipped,
+                duration_ms,
+                error,
+            } => Proto::ScanSummary(ScanSummary {
+                scan_id: scan_id.to_string(),
+                path,
+                requested_by,
+                outcome: ScanOutcome::from(outcome) as i32,
+                files_scanned,
+                files_matched,
+                dirs_skipped,
+                duration_ms,
+                error,
+            }),
+        }
+    }
+}
+
+impl TryFrom<log_event::EventType> for model::EventType {
+    type Error = GuardianError;
+
+    fn try_from(event_type: log_event::EventType) -> Result<Self, GuardianError> {
+        use log_event::EventType as Proto;
+
+        Ok(match event_type {
+            Proto::FileIntegrity(e) => model::EventType::FileIntegrity {
+                path: e.path,
+                operation: file_operation(e.operation)?,
+                hash: e.hash,
+                user: e.user.map(Into::into),
+                executable: e.executable.map(TryInto::try_into).transpose()?,
+                process: e.process.map(Into::into),
+                diff: e.diff,
+            },
+            Proto::NetworkSocket(e) => model::EventType::NetworkSocket {
+                local_addr: e.local_addr,
+                remote_addr: e.remote_addr,
+                remote_host: e.remote_host,
+                protocol: e.protocol,
+                state: e.state,
+                traffic: e.traffic.map(Into::into),
+                process: e.process.map(Into::into),
+            },
+            Proto::SystemLog(e) => model::EventType::SystemLog {
+                source: e.source,
+                level: e.level,
+                message: e.message,
+            },
+            Proto::ProcessMonitor(e) => model::EventType::ProcessMonitor {
+                pid: e.pid,
+                name: e.name,
+                cpu_usage: e.cpu_usage,
+                memory_usage: e.memory_usage,
+                ancestry: e.ancestry.into_iter().map(Into::into).collect(),
+                user: e.user.map(Into::into),
+            },
+            Proto::AuthEvent(e) => model::EventType::AuthEvent {
+                user: e.user,
+                source_ip: e.source_ip,
+                method: e.method,
+                outcome: auth_outcome(e.outcome)?,
+                tty: e.tty,
+            },
+            Proto::PackageChange(e) => model::EventType::PackageChange {
+                name: e.name,
+                version: e.version,
+                action: package_action(e.action)?,
+                manager: e.manager,
+            },
+            Proto::Heartbeat(e) => model::EventType::Heartbeat {
+                agent_version: e.agent_version,
+                uptime_secs: e.uptime_secs,
+                interval_secs: e.interval_secs,
+                forward: e.forward.map(Into::into),
+            },
+            Proto::Alert(e) => model::EventType::Alert {
+                title: e.title,
+                description: e.description,
+                related_event_ids: e
+                    .related_event_ids
+                    .iter()
+                    .map(|id| uuid("related_event_ids", id))
+                    .collect::<Result<_, _>>()?,
+                technique: e.technique,
+            },
+            Proto::ScanProgress(e) => model::EventType::ScanProgress {
+                scan_id: uuid("scan_id", &e.scan_id)?,
+                path: e.path,
+                files_scanned: e.files_scanned,
+                files_total: e.files_total,
+                current_path: e.current_path,
+                percent: e.percent,
+            },
+            Proto::ScanSummary(e) => model::EventType::ScanSummary {
+                scan_id: uuid("scan_id", &e.scan_id)?,
+                path: e.path,
+                requested_by: e.requested_by,
+                outcome: scan_outcome(e.outcome)?,
+                files_scanned: e.files_scanned,
+                files_matched: e.files_matched,
+                dirs_skipped: e.dirs_skipped,
+                duration_ms: e.duration_ms,
+                error: e.error,
+            },
+        })
+    }
+}
+
+impl From<&model::LogEvent> for LogEvent {
+    fn from(event: &model::LogEvent) -> Self {
+        Self {
+            schema_version: event.schema_version,
+            id: event.id.to_string(),
+            timestamp: event.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            severity: Severity::from(event.severity) as i32,
+            hostname: event.hostname.clone(),
+            tags: event.tags.clone(),
+            rule_triggered: event.rule_triggered,
+            rule_name: event.rule_name.clone(),
+            rules_matched: event.rules_matched.clone(),
+            signature: event.signature.clone(),
+            event_type: Some((&event.event_type).into()),
+        }
+    }
+}
+
+impl TryFrom<LogEvent> for model::LogEvent {
+    type Error = GuardianError;
+
+    fn try_from(event: LogEvent) -> Result<Self, GuardianError> {
+        let timestamp = DateTime::parse_from_rfc3339(&event.timestamp)
+            .map_err(|_| invalid("timestamp", &event.timestamp))?;
+        let event_type = event
+            .event_type
+            .ok_or_else(|| GuardianError::InvalidEvent("event has no event_type".to_string()))?;
+        Ok(Self {
+            schema_version: event.schema_version,
+            id: uuid("id", &event.id)?,
+            timestamp: timestamp.with_timezone(&Utc),
+            severity: severity(event.severity)?,
+            event_type: event_type.try_into()?,
+            hostname: event.hostname,
+            tags: event.tags,
+            rule_triggered: event.rule_triggered,
+            rule_name: event.rule_name,
+            rules_matched: event.rules_matched,
+            signature: event.signature,
+        })
+    }
+}
+
+impl model::LogEvent {
+    /// Serialize to the protobuf `guardian.event.v1.LogEvent` message
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        LogEvent::from(self).encode_to_vec()
+    }
+
+    /// Deserialize from a protobuf `guardian.event.v1.LogEvent` message
+    pub fn from_protobuf(bytes: &[u8]) -> Result<Self, GuardianError> {
+        LogEvent::decode(bytes)?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_round_trip_through_protobuf() {
+        let events = [
+            model::LogEvent::new(
+                model::Severity::High,
+                model::EventType::FileIntegrity {
+                    path: "/usr/bin/sshd".to_string(),
+                    operation: model::FileOperation::Modify,
+                    hash: Some("abc123".to_string()),
+                    user: Some(model::EventUser {
+                        uid: 0,
+                        username: Some("root".to_string()),
+                    }),
+                    executable: Some(model::ExecutableInfo {
+                        format: model::ExecutableFormat::Elf,
+                        arch: "x86_64".to_string(),
+                        library: false,
+                        libraries: vec!["libc.so.6".to_string()],
+                        sections: vec![".text".to_string()],
+                        signed: None,
+                        entropy: 6.5,
+                    }),
+                    process: None,
+                    diff: None,
+                },
+                "web-1".to_string(),
+            )
+            .with_tag("fim")
+            .with_rule("Binary Tampering"),
+            model::LogEvent::new(
+                model::Severity::High,
+                model::EventType::Alert {
+                    title: "SSH brute force".to_string(),
+                    description: "20 failures".to_string(),
+                    related_event_ids: vec![Uuid::new_v4(), Uuid::new_v4()],
+                    technique: Some("T1110".to_string()),
+                },
+                "web-1".to_string(),
+            ),
+            model::LogEvent::new(
+                model::Severity::Info,
+                model::EventType::ScanProgress {
+                    scan_id: Uuid::new_v4(),
+                    path: "/srv".to_string(),
+                    files_scanned: 10,
+                    files_total: Some(40),
+                    current_path: None,
+                    percent: Some(25.0),
+                },
+                "web-1".to_string(),
+            ),
+        ];
+        for event in events {
+            let decoded = model::LogEvent::from_protobuf(&event.to_protobuf()).unwrap();
+            assert_eq!(decoded.to_json().unwrap(), event.to_json().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_messages_are_rejected() {
+        let event = model::LogEvent::new(
+            model::Severity::Low,
+            model::EventType::SystemLog {
+                source: "kernel".to_string(),
+                level: "warning".to_string(),
+                message: "ok".to_string(),
+            },
+            "web-1".to_string(),
+        );
+
+        let mut message = LogEvent::from(&event);
+        message.severity = Severity::Unspecified as i32;
+        let error = model::LogEvent::try_from(message).unwrap_err();
+        assert_eq!(error.to_string(), "severity 0 is not valid");
+
+        let mut message = LogEvent::from(&event);
+        message.event_type = None;
+        assert!(model::LogEvent::try_from(message).is_err());
+
+        let mut message = LogEvent::from(&event);
+        message.id = "not-a-uuid".to_string();
+        assert!(model::LogEvent::try_from(message).is_err());
+
+        assert!(model::LogEvent::from_protobuf(&[0xff, 0xff]).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{GuardianError, Severity};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Pre-aggregated event counts for one agent over one period
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatsRollup {
+    /// Hostname of the agent that produced the rollup
+    pub hostname: String,
+
+    /// Start of the aggregated period
+    pub period_start: DateTime<Utc>,
+
+    /// Length of the period in seconds
+    pub period_secs: u32,
+
+    /// Event counts by type and severity
+    pub counts: Vec<RollupCount>,
+
+    /// Most frequently touched file paths
+    #[serde(default)]
+    pub top_paths: Vec<PathCount>,
+
+    /// HMAC-SHA256 of the rest in hex, when the agent signs what it emits
+    /// (see [`crate::EventSigner`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Number of events of one type and severity
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RollupCount {
+    pub event_type: String,
+    pub severity: Severity,
+    pub count: u64,
+}
+
+/// Number of file events seen for one path
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PathCount {
+    pub path: String,
+    pub count: u64,
+}
+
+impl StatsRollup {
+    /// Total events covered by this rollup
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|c| c.count).sum()
+    }
+
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String, GuardianError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserialize from JSON string
+    pub fn from_json(json: &str) -> Result<Self, GuardianError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup_round_trip() {
+        let rollup = StatsRollup {
+            hostname: "web-1".to_string(),
+            period_start: Utc::now(),
+            period_secs: 3600,
+            counts: vec![
+                RollupCount {
+                    event_type: "file_integrity".to_string(),
+                    severity: Severity::Low,
+                    count: 40,
+                },
+                RollupCount {
+                    event_type: "process_monitor".to_string(),
+                    severity: Severity::Info,
+                    count: 3600,
+                },
+            ],
+            top_paths: vec![PathCount {
+                path: "/var/www/index.php".to_string(),
+                count: 12,
+            }],
+            signature: None,
+        };
+
+        let json = rollup.to_json().unwrap();
+        assert_eq!(StatsRollup::from_json(&json).unwrap(), rollup);
+        assert_eq!(rollup.total(), 3640);
+
+        // A rollup line must never be mistaken for an event
+        assert!(crate::LogEvent::from_json(&json).is_err());
+    }
+}
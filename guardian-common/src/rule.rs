@@ -0,0 +1,935 @@
+//! Detection rules written as data. A rule names an event kind, a lowest
+//! severity, tags, conditions on fields of the event data and a schedule;
+//! an event matches when every part that is set holds. Its [`RuleActions`]
+//! say what then happens to the event. Conditions fields cannot express go
+//! in a CEL expression (with the `cel` feature) or a Rhai `script` (with the
+//! `rhai` feature), run by a [`CompiledRule`]. Candidate rules are tried
+//! against stored events with [`RuleTest`] before they go live.
+
+use crate::control::MAX_ISOLATION_SECS;
+use crate::{GuardianError, LogEvent, Severity};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
+
+/// A rule and the conditions an event must meet to trigger it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleDefinition {
+    /// Rule name, as set on the events it triggers
+    pub name: String,
+
+    /// Event kind as returned by [`EventType::kind`](crate::EventType::kind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+
+    /// Lowest severity matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_severity: Option<Severity>,
+
+    /// Tags the event must all carry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Conditions on the event data, all of which must hold
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldCondition>,
+
+    /// CEL expression that must evaluate to `true`, e.g.
+    /// `event.path.endsWith(".sh")`; see [`cel`](crate::cel)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cel: Option<String>,
+
+    /// Rhai expression that must evaluate to `true`, e.g.
+    /// `data.path.len() > 40`; see [`script`](crate::script)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+
+    /// Local times the rule applies at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<RuleSchedule>,
+
+    /// What happens to a matching event besides being named after the rule
+    #[serde(skip_serializing_if = "RuleActions::is_empty")]
+    pub actions: RuleActions,
+}
+
+/// Outcome of a rule match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleActions {
+    /// Severity the event is raised or lowered to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set_severity: Option<Severity>,
+
+    /// Tags added to the event
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub add_tags: Vec<String>,
+
+    /// Drop the event instead of sending it on
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub suppress: bool,
+
+    /// Response carried out on the host when the rule matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respond: Option<RuleResponse>,
+}
+
+impl RuleActions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Response a rule can trigger, e.g. `{"action": "kill_process"}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RuleResponse {
+    /// Kill the process a `process_monitor` event is about
+    KillProcess,
+    /// Contain the host as the console's `isolate_host` command does
+    IsolateHost {
+        /// Peers that stay reachable, as `ip`, `ip:port` or `ip/prefix`
+        #[serde(default)]
+        allow: Vec<String>,
+        /// Seconds until the policy is lifted automatically
+        duration_secs: u64,
+    },
+}
+
+/// When a rule applies, in `timezone` or else the local time of the machine
+/// evaluating it, e.g. `{"from": "22:00", "to": "06:00"}` or
+/// `{"days": ["sat", "sun"]}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSchedule {
+    /// Start of the daily window, inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<NaiveTime>,
+
+    /// End of the daily window, exclusive; earlier than `from` when the
+    /// window spans midnight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveTime>,
+
+    /// Days of the week the rule applies on. The part of a window after
+    /// midnight belongs to the day it started on.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+
+    /// IANA time zone the schedule is read in, e.g. `Europe/Berlin`, so the
+    /// rule means the same on every machine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub timezone: Option<Tz>,
+}
+
+impl RuleSchedule {
+    /// Whether `at` falls in the schedule in its time zone, or local time
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        match self.timezone {
+            Some(timezone) => self.contains_local(at.with_timezone(&timezone).naive_local()),
+            None => self.contains_local(at.with_timezone(&Local).naive_local()),
+        }
+    }
+
+    fn contains_local(&self, at: NaiveDateTime) -> bool {
+        let time = at.time();
+        let mut day = at.weekday();
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            let inside = if from <= to {
+                from <= time && time < to
+            } else {
+                from <= time || time < to
+            };
+            if !inside {
+                return false;
+            }
+            if from > to && time < to {
+                day = day.pred();
+            }
+        }
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.from.is_some() != self.to.is_some() {
+            return Err("schedule needs both from and to".to_string());
+        }
+        if self.from.is_some() && self.from == self.to {
+            return Err("schedule window is empty".to_string());
+        }
+        if self.from.is_none() && self.days.is_empty() {
+            return Err("schedule sets no window or days".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A test on one field of the event data, e.g.
+/// `{"field": "ancestry.0.name", "one_of": ["nginx", "httpd"]}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FieldCondition {
+    /// Dotted path into the event data as serialized, e.g. `path` or
+    /// `user.username`; numbers index into lists
+    pub field: String,
+
+    #[serde(flatten)]
+    pub test: FieldTest,
+}
+
+/// How a field's value is compared; string tests are case-sensitive and
+/// never match values that are not strings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FieldTest {
+    Equals(Value),
+    OneOf(Vec<Value>),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    /// Whether the field is present and not null
+    Exists(bool),
+    /// Regular expression found anywhere in the value unless anchored
+    Matches(String),
+}
+
+/// Value at a dotted path into the event data, unless missing or null
+fn lookup<'a>(data: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(data, |value, key| match value {
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => value.get(key),
+        })
+        .filter(|value| !value.is_null())
+}
+
+impl FieldCondition {
+    /// `pattern` tells whether a string value matches a `matches` test,
+    /// whose regex the caller has compiled
+    fn matches(&self, data: &Value, pattern: impl FnOnce(&str) -> bool) -> bool {
+        let value = lookup(data, &self.field);
+        let text = value.and_then(Value::as_str);
+        match &self.test {
+            FieldTest::Equals(expected) => value == Some(expected),
+            FieldTest::OneOf(expected) => value.is_some_and(|value| expected.contains(value)),
+            FieldTest::Contains(part) => text.is_some_and(|text| text.contains(part.as_str())),
+            FieldTest::StartsWith(prefix) => {
+                text.is_some_and(|text| text.starts_with(prefix.as_str()))
+            }
+            FieldTest::EndsWith(suffix) => text.is_some_and(|text| text.ends_with(suffix.as_str())),
+            FieldTest::Exists(exists) => value.is_some() == *exists,
+            FieldTest::Matches(_) => text.is_some_and(pattern),
+        }
+    }
+}
+
+impl RuleDefinition {
+    /// Reject rules without a name or without any condition, which would
+    /// match every event, invalid patterns, responses on rules that only
+    /// look at type, severity or tags, process kills on events without a
+    /// process, and isolations without an allowed peer or beyond
+    /// `MAX_ISOLATION_SECS`
+    pub fn validate(&self) -> Result<(), GuardianError> {
+        self.compile_patterns().map(drop)
+    }
+
+    /// Validate the rule, returning the regex of each field condition that
+    /// has one, by position
+    fn compile_patterns(&self) -> Result<Vec<Option<Regex>>, GuardianError> {
+        if self.name.trim().is_empty() {
+            return Err(GuardianError::config("Rule name is required"));
+        }
+        if self.event_type.is_none()
+            && self.min_severity.is_none()
+            && self.tags.is_empty()
+            && self.fields.is_empty()
+            && self.cel.is_none()
+            && self.script.is_none()
+        {
+            return Err(GuardianError::config(format!(
+                "Rule {} has no conditions",
+                self.name
+            )));
+        }
+        if let Some(schedule) = &self.schedule {
+            schedule
+                .validate()
+                .map_err(|e| GuardianError::config(format!("Rule {} {}", self.name, e)))?;
+        }
+        let patterns = self
+            .fields
+            .iter()
+            .map(|condition| match &condition.test {
+                FieldTest::Matches(pattern) => Regex::new(pattern).map(Some).map_err(|e| {
+                    GuardianError::config(format!(
+                        "Rule {} has an invalid pattern for {}: {}",
+                        self.name, condition.field, e
+                    ))
+                }),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.cel.is_some() && !cfg!(feature = "cel") {
+            return Err(GuardianError::config(format!(
+                "Rule {} has a CEL expression but this build cannot run them",
+                self.name
+            )));
+        }
+        if self.script.is_some() && !cfg!(feature = "rhai") {
+            return Err(GuardianError::config(format!(
+                "Rule {} has a script but this build cannot run scripts",
+                self.name
+            )));
+        }
+        if self.actions.respond.is_some()
+            && self.fields.is_empty()
+            && self.cel.is_none()
+            && self.script.is_none()
+        {
+            return Err(GuardianError::config(format!(
+                "Rule {} responds on the host and needs a field, CEL or script condition",
+                self.name
+            )));
+        }
+        if self.actions.respond == Some(RuleResponse::KillProcess)
+            && self
+                .event_type
+                .as_ref()
+                .is_none_or(|kind| kind != "process_monitor")
+        {
+            return Err(GuardianError::config(format!(
+                "Rule {} kills processes but does not match process_monitor events",
+                self.name
+            )));
+        }
+        if let Some(RuleResponse::IsolateHost {
+            allow,
+            duration_secs,
+        }) = &self.actions.respond
+        {
+            if allow.is_empty() {
+                return Err(GuardianError::config(format!(
+                    "Rule {} isolates the host and needs at least one allowed peer",
+                    self.name
+                )));
+            }
+            if !(1..=MAX_ISOLATION_SECS).contains(duration_secs) {
+                return Err(GuardianError::config(format!(
+                    "Rule {} isolates the host for {}s; the duration must be between 1 and {} seconds",
+                    self.name, duration_secs, MAX_ISOLATION_SECS
+                )));
+            }
+        }
+        Ok(patterns)
+    }
+
+    /// Whether `event` meets every condition of the rule but its CEL
+    /// expression and script, which only a [`CompiledRule`] runs. Patterns
+    /// are compiled on every call; a [`CompiledRule`] compiles them once.
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        let data = self.needs_data().then(|| event_data(event));
+        self.matches_data(event, data.as_ref(), |i, text| match &self.fields[i].test {
+            FieldTest::Matches(pattern) => Regex::new(pattern).is_ok_and(|re| re.is_match(text)),
+            _ => false,
+        })
+    }
+
+    fn needs_data(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    /// Match against the serialized event `data`, present when the rule has
+    /// field conditions; `pattern(i, text)` decides the `matches` test of
+    /// field condition `i`
+    fn matches_data(
+        &self,
+        event: &LogEvent,
+        data: Option<&Value>,
+        pattern: impl Fn(usize, &str) -> bool,
+    ) -> bool {
+        if self
+            .event_type
+            .as_ref()
+            .is_some_and(|kind| kind != event.event_type.kind())
+            || self.min_severity.is_some_and(|min| event.severity < min)
+            || !self.tags.iter().all(|tag| event.tags.contains(tag))
+            || self
+                .schedule
+                .as_ref()
+                .is_some_and(|schedule| !schedule.contains(event.timestamp))
+        {
+            return false;
+        }
+        let Some(data) = data else {
+            return self.fields.is_empty();
+        };
+        self.fields
+            .iter()
+            .enumerate()
+            .all(|(i, condition)| condition.matches(data, |text| pattern(i, text)))
+    }
+}
+
+fn event_data(event: &LogEvent) -> Value {
+    serde_json::to_value(&event.event_type).unwrap_or_default()
+}
+
+/// A valid rule ready to evaluate, with its patterns, CEL expression and
+/// script compiled
+#[derive(Debug)]
+pub struct CompiledRule {
+    pub definition: RuleDefinition,
+    /// Regex of each field condition that has one, by position
+    patterns: Vec<Option<Regex>>,
+    #[cfg(feature = "cel")]
+    cel: Option<crate::cel::CelCondition>,
+    #[cfg(feature = "rhai")]
+    script: Option<crate::script::RuleScript>,
+}
+
+impl CompiledRule {
+    /// Validate `definition` and compile its patterns, CEL expression and
+    /// script
+    pub fn new(definition: RuleDefinition) -> Result<Self, GuardianError> {
+        let patterns = definition.compile_patterns()?;
+        #[cfg(feature = "cel")]
+        let cel = definition
+            .cel
+            .as_deref()
+            .map(crate::cel::CelCondition::compile)
+            .transpose()
+            .map_err(|e| {
+                GuardianError::config(format!(
+                    "Rule {} has an invalid CEL expression: {}",
+                    definition.name, e
+                ))
+            })?;
+        #[cfg(feature = "rhai")]
+        let script = definition
+            .script
+            .as_deref()
+            .map(crate::script::RuleScript::compile)
+            .transpose()
+            .map_err(|e| {
+                GuardianError::config(format!(
+                    "Rule {} has an invalid script: {}",
+                    definition.name, e
+                ))
+            })?;
+        Ok(Self {
+            definition,
+            patterns,
+            #[cfg(feature = "cel")]
+            cel,
+            #[cfg(feature = "rhai")]
+            script,
+        })
+    }
+
+    /// Whether `event` meets every condition of the rule, CEL expression and
+    /// script included
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        let views = EventViews::new(event, self.definition.needs_data());
+        self.matches_views(&views, |i, text| {
+            self.patterns[i]
+                .as_ref()
+                .is_some_and(|re| re.is_match(text))
+        })
+    }
+
+    fn matches_views(&self, views: &EventViews, pattern: impl Fn(usize, &str) -> bool) -> bool {
+        let event = views.event;
+        if !self
+            .definition
+            .matches_data(event, views.data.as_ref(), pattern)
+        {
+            return false;
+        }
+        #[cfg(feature = "cel")]
+        if self.cel.as_ref().is_some_and(|cel| {
+            !cel.matches(views.cel.get_or_init(|| crate::cel::CelEvent::new(event)))
+        }) {
+            return false;
+        }
+        #[cfg(feature = "rhai")]
+        if let Some(script) = &self.script {
+            return script.matches(event);
+        }
+        true
+    }
+}
+
+/// The forms of one event the rules evaluated against it share, each built
+/// at most once
+struct EventViews<'a> {
+    event: &'a LogEvent,
+    /// The serialized event, when some rule has field conditions
+    data: Option<Value>,
+    /// The flattened event, built for the first rule with a CEL expression
+    #[cfg(feature = "cel")]
+    cel: std::cell::OnceCell<crate::cel::CelEvent>,
+}
+
+impl<'a> EventViews<'a> {
+    fn new(event: &'a LogEvent, needs_data: bool) -> Self {
+        Self {
+            event,
+            data: needs_data.then(|| event_data(event)),
+            #[cfg(feature = "cel")]
+            cel: std::cell::OnceCell::new(),
+        }
+    }
+}
+
+/// Rules evaluated together. The `matches` patterns of every rule are
+/// compiled into one [`RegexSet`] per field, so each field is scanned once
+/// per event however many rules test it, and the event data is serialized,
+/// and flattened for CEL, once for all of them. The sets are built on the
+/// first evaluation, once every rule has been added.
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+    patterns: OnceLock<BTreeMap<String, FieldPatterns>>,
+}
+
+/// Patterns tested against one field
+#[derive(Debug)]
+struct FieldPatterns {
+    /// Missing when the patterns are too large to join, in which case each
+    /// is tested on its own
+    set: Option<RegexSet>,
+    /// Rule and field condition each pattern belongs to
+    owners: Vec<(usize, usize)>,
+}
+
+impl RuleSet {
+    /// Add `rule`, returning its position
+    pub fn push(&mut self, rule: CompiledRule) -> usize {
+        self.patterns = OnceLock::new();
+        self.rules.push(rule);
+        self.rules.len() - 1
+    }
+
+    pub fn rules(&self) -> &[CompiledRule] {
+        &self.rules
+    }
+
+    fn patterns(&self) -> &BTreeMap<String, FieldPatterns> {
+        self.patterns.get_or_init(|| {
+            let mut fields: BTreeMap<String, FieldPatterns> = BTreeMap::new();
+            for (position, rule) in self.rules.iter().enumerate() {
+                for (i, condition) in rule.definition.fields.iter().enumerate() {
+                    if matches!(condition.test, FieldTest::Matches(_)) {
+                        fields
+                            .entry(condition.field.clone())
+                            .or_insert(FieldPatterns {
+                                set: None,
+                                owners: Vec::new(),
+                            })
+                            .owners
+                            .push((position, i));
+                    }
+                }
+            }
+            for patterns in fields.values_mut() {
+                let sources = patterns.owners.iter().filter_map(|&(rule, i)| {
+                    match &self.rules[rule].definition.fields[i].test {
+                        FieldTest::Matches(pattern) => Some(pattern),
+                        _ => None,
+                    }
+                });
+                patterns.set = RegexSet::new(sources).ok();
+            }
+            fields
+        })
+    }
+
+    /// Whether `event` matches each rule, by position
+    pub fn matching(&self, event: &LogEvent) -> Vec<bool> {
+        let views = EventViews::new(
+            event,
+            self.rules.iter().any(|rule| rule.definition.needs_data()),
+        );
+        let mut hits = HashSet::new();
+        if let Some(data) = &views.data {
+            for (field, patterns) in self.patterns() {
+                let Some(text) = lookup(data, field).and_then(Value::as_str) else {
+                    continue;
+                };
+                match &patterns.set {
+                    Some(set) => hits.extend(set.matches(text).iter().map(|i| patterns.owners[i])),
+                    None => hits.extend(patterns.owners.iter().copied().filter(|&(rule, i)| {
+                        self.rules[rule].patterns[i]
+                            .as_ref()
+                            .is_some_and(|re| re.is_match(text))
+                    })),
+                }
+            }
+        }
+        self.rules
+            .iter()
+            .enumerate()
+            .map(|(position, rule)| {
+                rule.matches_views(&views, |i, _| hits.contains(&(position, i)))
+            })
+            .collect()
+    }
+}
+
+/// Outcome of evaluating a candidate rule against a run of stored events
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleTest {
+    /// Events the rule was evaluated against
+    pub evaluated: u64,
+    pub matched: u64,
+    /// Matches that already triggered another rule
+    pub already_triggered: u64,
+    pub matched_by_host: BTreeMap<String, u64>,
+    /// The first matches in the order events were evaluated
+    pub samples: Vec<LogEvent>,
+    #[serde(skip)]
+    max_samples: usize,
+}
+
+impl RuleTest {
+    /// An empty result keeping up to `max_samples` matching events
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            max_samples,
+            ..Self::default()
+        }
+    }
+
+    /// Evaluate `rule` against one more event
+    pub fn observe(&mut self, rule: &CompiledRule, event: &LogEvent) {
+        self.evaluated += 1;
+        if !rule.matches(event) {
+            return;
+        }
+        self.matched += 1;
+        if event.rule_triggered {
+            self.already_triggered += 1;
+        }
+        *self
+            .matched_by_host
+            .entry(event.hostname.clone())
+            .or_default() += 1;
+        if self.samples.len() < self.max_samples {
+            self.samples.push(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, ProcessAncestor};
+
+    fn shell(parent: &str, hostname: &str) -> LogEvent {
+        LogEvent::new(
+            Severity::Medium,
+            EventType::ProcessMonitor {
+                pid: 4242,
+                name: "bash".to_string(),
+                cpu_usage: 0.0,
+                memory_usage: 0,
+                ancestry: vec![ProcessAncestor {
+                    pid: 1,
+                    name: parent.to_string(),
+                }],
+                user: None,
+            },
+            hostname.to_string(),
+        )
+        .with_tag("process")
+    }
+
+    #[test]
+    fn test_rule_definition_matches() {
+        let rule: RuleDefinition = serde_json::from_value(serde_json::json!({
+            "name": "web_shell",
+            "event_type": "process_monitor",
+            "min_severity": "MEDIUM",
+            "tags": ["process"],
+            "fields": [
+                {"field": "name", "one_of": ["bash", "sh"]},
+                {"field": "ancestry.0.name", "starts_with": "nginx"},
+                {"field": "user", "exists": false}
+            ]
+        }))
+        .unwrap();
+        assert!(rule.validate().is_ok());
+        assert!(rule.matches(&shell("nginx", "web-1")));
+        assert!(!rule.matches(&shell("sshd", "web-1")));
+
+        let mut low = shell("nginx", "web-1");
+        low.severity = Severity::Low;
+        assert!(!rule.matches(&low));
+
+        assert!(RuleDefinition {
+            name: "everything".to_string(),
+            ..RuleDefinition::default()
+        }
+        .validate()
+        .is_err());
+        assert!(
+            serde_json::from_str::<RuleDefinition>(r#"{"name":"x","sevrity":"HIGH"}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_rule_actions() {
+        let rule: RuleDefinition = serde_json::from_value(serde_json::json!({
+            "name": "miner",
+            "event_type": "process_monitor",
+            "fields": [{"field": "name", "equals": "xmrig"}],
+            "actions": {
+                "set_severity": "CRITICAL",
+                "add_tags": ["cryptominer"],
+                "respond": {"action": "kill_process"}
+            }
+        }))
+        .unwrap();
+        assert!(rule.validate().is_ok());
+        assert_eq!(rule.actions.set_severity, Some(Severity::Critical));
+        assert!(!rule.actions.suppress);
+        assert_eq!(rule.actions.respond, Some(RuleResponse::KillProcess));
+
+        // Empty actions are left out when the rule is written back
+        let plain = RuleDefinition {
+            actions: RuleActions::default(),
+            ..rule.clone()
+        };
+        assert!(!serde_json::to_string(&plain).unwrap().contains("actions"));
+
+        let any_event = RuleDefinition {
+            event_type: None,
+            ..rule.clone()
+        };
+        assert!(any_event.validate().is_err());
+        // Type alone is too broad to respond on
+        let any_process = RuleDefinition {
+            fields: Vec::new(),
+            ..rule
+        };
+        assert!(any_process.validate().is_err());
+        assert!(serde_json::from_str::<RuleActions>(
+            r#"{"respond": {"action": "isolate_host", "duration_secs": 600}}"#
+        )
+        .is_ok());
+        assert!(
+            serde_json::from_str::<RuleActions>(r#"{"respond": {"action": "reboot"}}"#).is_err()
+        );
+    }
+
+    fn isolating(allow: &[&str], duration_secs: u64) -> RuleDefinition {
+        serde_json::from_value(serde_json::json!({
+            "name": "beacon",
+            "event_type": "network_socket",
+            "fields": [{"field": "remote_addr", "starts_with": "203.0.113."}],
+            "actions": {
+                "respond": {"action": "isolate_host", "allow": allow, "duration_secs": duration_secs}
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_isolating_rule_needs_an_allowed_peer() {
+        assert!(isolating(&["10.0.0.5:8443"], 600).validate().is_ok());
+        let err = isolating(&[], 600).validate().unwrap_err();
+        assert!(err.to_string().contains("allowed peer"));
+    }
+
+    #[test]
+    fn test_isolating_rule_duration_is_bounded() {
+        assert!(isolating(&["10.0.0.5:8443"], MAX_ISOLATION_SECS)
+            .validate()
+            .is_ok());
+        assert!(isolating(&["10.0.0.5:8443"], 0).validate().is_err());
+        assert!(isolating(&["10.0.0.5:8443"], MAX_ISOLATION_SECS + 1)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_scripted_rule() {
+        let rule = RuleDefinition {
+            name: "deep_shell".to_string(),
+            event_type: Some("process_monitor".to_string()),
+            script: Some("data.ancestry.len() > 0 && data.name.len() <= 4".to_string()),
+            ..RuleDefinition::default()
+        };
+        if cfg!(feature = "rhai") {
+            let compiled = CompiledRule::new(rule.clone()).unwrap();
+            assert!(compiled.matches(&shell("nginx", "web-1")));
+            let mut no_parent = shell("nginx", "web-1");
+            if let EventType::ProcessMonitor { ancestry, .. } = &mut no_parent.event_type {
+                ancestry.clear();
+            }
+            assert!(!compiled.matches(&no_parent));
+            assert!(CompiledRule::new(RuleDefinition {
+                script: Some("data.name ==".to_string()),
+                ..rule
+            })
+            .is_err());
+        } else {
+            assert!(CompiledRule::new(rule).is_err());
+        }
+    }
+
+    #[test]
+    fn test_cel_rule() {
+        let rule = RuleDefinition {
+            name: "web_shell".to_string(),
+            tags: vec!["process".to_string()],
+            cel: Some(r#"event["ancestry.0.name"] in ["nginx", "httpd"]"#.to_string()),
+            ..RuleDefinition::default()
+        };
+        if cfg!(feature = "cel") {
+            let compiled = CompiledRule::new(rule).unwrap();
+            assert!(compiled.matches(&shell("httpd", "web-1")));
+            assert!(!compiled.matches(&shell("sshd", "web-1")));
+        } else {
+            assert!(CompiledRule::new(rule).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rule_set_patterns() {
+        let rule = |name: &str, field: &str, pattern: &str| {
+            CompiledRule::new(RuleDefinition {
+                name: name.to_string(),
+                fields: vec![FieldCondition {
+                    field: field.to_string(),
+                    test: FieldTest::Matches(pattern.to_string()),
+                }],
+                ..RuleDefinition::default()
+            })
+        };
+        let mut set = RuleSet::default();
+        set.push(rule("web_parent", "ancestry.0.name", "^(nginx|httpd)$").unwrap());
+        set.push(rule("short_shell", "name", r"^\w{2,4}$").unwrap());
+        set.push(rule("any_parent", "ancestry.0.name", "x").unwrap());
+        assert_eq!(set.matching(&shell("nginx", "web-1")), [true, true, true]);
+        assert_eq!(set.matching(&shell("sshd", "web-1")), [false, true, false]);
+
+        // A rule added later rebuilds the sets on the next evaluation
+        assert_eq!(
+            set.push(rule("ssh_parent", "ancestry.0.name", "^ssh").unwrap()),
+            3
+        );
+        assert_eq!(
+            set.matching(&shell("sshd", "web-1")),
+            [false, true, false, true]
+        );
+
+        // A compiled rule agrees with the set and with the bare definition
+        let single = &set.rules()[0];
+        assert!(single.matches(&shell("httpd", "web-1")));
+        assert!(single.definition.matches(&shell("httpd", "web-1")));
+        assert!(!single.matches(&shell("nginx-debug", "web-1")));
+
+        assert!(rule("broken", "name", "(unclosed").is_err());
+    }
+
+    #[test]
+    fn test_rule_schedule() {
+        let at = |day: u32, time: &str| {
+            chrono::NaiveDate::from_ymd_opt(2026, 3, day)
+                .unwrap()
+                .and_time(time.parse().unwrap())
+        };
+        let night: RuleSchedule =
+            serde_json::from_str(r#"{"from": "22:00", "to": "06:00"}"#).unwrap();
+        // 2026-03-04 is a Wednesday
+        assert!(night.contains_local(at(4, "03:10")));
+        assert!(night.contains_local(at(4, "22:00")));
+        assert!(!night.contains_local(at(4, "06:00")));
+        assert!(!night.contains_local(at(4, "12:30")));
+
+        let weekend_days: RuleSchedule =
+            serde_json::from_str(r#"{"from": "09:00", "to": "17:00", "days": ["sat", "sun"]}"#)
+                .unwrap();
+        assert!(weekend_days.contains_local(at(7, "10:00")));
+        assert!(!weekend_days.contains_local(at(7, "18:00")));
+        assert!(!weekend_days.contains_local(at(4, "10:00")));
+
+        // After midnight counts as the night of the day before
+        let friday_night: RuleSchedule =
+            serde_json::from_str(r#"{"from": "22:00", "to": "06:00", "days": ["fri"]}"#).unwrap();
+        assert!(friday_night.contains_local(at(6, "23:00")));
+        assert!(friday_night.contains_local(at(7, "03:00")));
+        assert!(!friday_night.contains_local(at(6, "03:00")));
+        assert!(!friday_night.contains_local(at(7, "23:00")));
+
+        // A time zone fixes the window whatever the machine's own
+        let berlin: RuleSchedule = serde_json::from_str(
+            r#"{"from": "09:00", "to": "17:00", "timezone": "Europe/Berlin"}"#,
+        )
+        .unwrap();
+        let utc = |time: &str| at(4, time).and_utc();
+        assert!(berlin.contains(utc("08:30")));
+        assert!(!berlin.contains(utc("16:30")));
+        assert!(serde_json::from_str::<RuleSchedule>(
+            r#"{"days": ["sat"], "timezone": "Mars/Olympus"}"#
+        )
+        .is_err());
+
+        let rule = |schedule: &str| RuleDefinition {
+            name: "off_hours_login".to_string(),
+            event_type: Some("auth_event".to_string()),
+            schedule: Some(serde_json::from_str(schedule).unwrap()),
+            ..RuleDefinition::default()
+        };
+        assert!(rule(r#"{"days": ["sat"]}"#).validate().is_ok());
+        assert!(rule(r#"{"from": "22:00"}"#).validate().is_err());
+        assert!(rule(r#"{"from": "22:00", "to": "22:00"}"#)
+            .validate()
+            .is_err());
+        assert!(rule("{}").validate().is_err());
+    }
+
+    #[test]
+    fn test_rule_test_counts_and_samples() {
+        let rule = CompiledRule::new(RuleDefinition {
+            name: "nginx_child".to_string(),
+            fields: vec![FieldCondition {
+                field: "ancestry.0.name".to_string(),
+                test: FieldTest::Equals("nginx".into()),
+            }],
+            ..RuleDefinition::default()
+        })
+        .unwrap();
+        let mut test = RuleTest::new(2);
+        for event in [
+            shell("nginx", "web-1"),
+            shell("sshd", "web-1"),
+            shell("nginx", "web-2").with_rule("web_server_shell"),
+            shell("nginx", "web-1"),
+        ] {
+            test.observe(&rule, &event);
+        }
+        assert_eq!(test.evaluated, 4);
+        assert_eq!(test.matched, 3);
+        assert_eq!(test.already_triggered, 1);
+        assert_eq!(
+            test.matched_by_host,
+            BTreeMap::from([("web-1".to_string(), 2), ("web-2".to_string(), 1)])
+        );
+        assert_eq!(test.samples.len(), 2);
+        assert_eq!(test.samples[1].hostname, "web-2");
+    }
+}
@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How often each rule of one agent was evaluated and matched, for tuning
+/// noisy rules
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleStatsReport {
+    /// Hostname of the agent whose rules were counted
+    pub hostname: String,
+
+    /// Start of the counted period
+    pub since: DateTime<Utc>,
+
+    /// End of the counted period
+    pub until: DateTime<Utc>,
+
+    /// Counts of every loaded rule, in evaluation order
+    pub rules: Vec<RuleStats>,
+}
+
+/// Counts for one rule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleStats {
+    pub rule: String,
+    /// Events the rule was evaluated against
+    pub evaluated: u64,
+    pub matched: u64,
+}
+
+impl RuleStats {
+    /// Share of evaluated events the rule matched, from 0 to 1
+    pub fn match_rate(&self) -> f64 {
+        if self.evaluated == 0 {
+            0.0
+        } else {
+            self.matched as f64 / self.evaluated as f64
+        }
+    }
+}
+
+impl RuleStatsReport {
+    /// Up to `limit` rules that matched, most matches first
+    pub fn noisiest(&self, limit: usize) -> Vec<&RuleStats> {
+        let mut rules: Vec<&RuleStats> = self.rules.iter().filter(|r| r.matched > 0).collect();
+        rules.sort_by_key(|r| std::cmp::Reverse(r.matched));
+        rules.truncate(limit);
+        rules
+    }
+
+    /// Counts accumulated after `earlier`, an older report of the same
+    /// counters; rules it lacks are counted from the start
+    pub fn since_report(&self, earlier: &RuleStatsReport) -> RuleStatsReport {
+        let rules = self
+            .rules
+            .iter()
+            .map(|stats| {
+                let (evaluated, matched) = earlier
+                    .rules
+                    .iter()
+                    .find(|r| r.rule == stats.rule)
+                    .map_or((0, 0), |r| (r.evaluated, r.matched));
+                RuleStats {
+                    rule: stats.rule.clone(),
+                    evaluated: stats.evaluated.saturating_sub(evaluated),
+                    matched: stats.matched.saturating_sub(matched),
+                }
+            })
+            .collect();
+        RuleStatsReport {
+            hostname: self.hostname.clone(),
+            since: earlier.until,
+            until: self.until,
+            rules,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(rule: &str, evaluated: u64, matched: u64) -> RuleStats {
+        RuleStats {
+            rule: rule.to_string(),
+            evaluated,
+            matched,
+        }
+    }
+
+    #[test]
+    fn test_noisiest_since_earlier_report() {
+        let start = Utc::now();
+        let earlier = RuleStatsReport {
+            hostname: "web-1".to_string(),
+            since: start,
+            until: start + chrono::Duration::hours(24),
+            rules: vec![stats("a", 100, 90), stats("b", 100, 5)],
+        };
+        let later = RuleStatsReport {
+            until: start + chrono::Duration::hours(48),
+            rules: vec![stats("a", 300, 95), stats("b", 300, 60), stats("c", 50, 0)],
+            ..earlier.clone()
+        };
+        assert_eq!(later.noisiest(1)[0].rule, "a");
+
+        let day = later.since_report(&earlier);
+        assert_eq!(day.since, earlier.until);
+        assert_eq!(day.rules[0], stats("a", 200, 5));
+        let noisiest = day.noisiest(5);
+        assert_eq!(noisiest.len(), 2);
+        assert_eq!(noisiest[0].rule, "b");
+        assert_eq!(noisiest[0].match_rate(), 0.275);
+    }
+}
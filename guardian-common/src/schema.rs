@@ -0,0 +1,111 @@
+use serde_json::{Map, Value};
+
+/// Layout version written into every new `LogEvent`
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Version assumed for events serialized before `schema_version` existed
+pub(crate) fn legacy_schema_version() -> u32 {
+    1
+}
+
+/// Upgrade steps; `UPGRADES[i]` turns a version `i + 1` event into version `i + 2`
+const UPGRADES: &[fn(&mut Map<String, Value>)] = &[v1_to_v2];
+
+/// Version 1 events name only the most severe rule hit in `rule_name`; version 2
+/// lists every hit in `rules_matched`, which rule searches and alert triage read
+fn v1_to_v2(event: &mut Map<String, Value>) {
+    let has_matches = event
+        .get("rules_matched")
+        .and_then(Value::as_array)
+        .is_some_and(|rules| !rules.is_empty());
+    if has_matches {
+        return;
+    }
+    if let Some(rule) = event.get("rule_name").filter(|rule| rule.is_string()) {
+        let rules_matched = Value::Array(vec![rule.clone()]);
+        event.insert("rules_matched".to_string(), rules_matched);
+    }
+}
+
+/// Bring a serialized event up to `SCHEMA_VERSION`.
+///
+/// Events from newer writers are left as they are: unknown fields are ignored
+/// when deserializing, so a newer agent's events still load on an older console.
+pub fn upgrade(value: &mut Value) {
+    let Some(event) = value.as_object_mut() else {
+        return;
+    };
+    let version = event
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map_or(legacy_schema_version(), |v| v as u32);
+    if version >= SCHEMA_VERSION {
+        return;
+    }
+
+    for step in UPGRADES.iter().skip(version.saturating_sub(1) as usize) {
+        step(event);
+    }
+    event.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, LogEvent};
+
+    #[test]
+    fn test_upgrades_legacy_event() {
+        // An event as written before versioning
+        let legacy = r#"{
+            "id": "6f1c2a4e-8a7b-4c55-9d3e-0f3a1b2c4d5e",
+            "timestamp": "2025-11-02T08:15:00Z",
+            "severity": "HIGH",
+            "type": "file_integrity",
+            "path": "/etc/passwd",
+            "operation": "modify",
+            "hash": null,
+            "hostname": "web-1",
+            "tags": ["file_monitor"],
+            "rule_triggered": true,
+            "rule_name": "critical_file_modification"
+        }"#;
+
+        let strict = LogEvent::from_json(legacy).unwrap();
+        assert_eq!(strict.schema_version, 1);
+
+        assert!(strict.rules_matched.is_empty());
+
+        let event = LogEvent::from_json_any_version(legacy).unwrap();
+        assert_eq!(event.schema_version, SCHEMA_VERSION);
+        assert_eq!(event.rules_matched, vec!["critical_file_modification"]);
+        assert!(matches!(
+            event.event_type,
+            EventType::FileIntegrity { user: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_newer_event_loads_as_is() {
+        let mut value: Value = serde_json::from_str(
+            &LogEvent::new(
+                crate::Severity::Info,
+                EventType::SystemLog {
+                    source: "kernel".to_string(),
+                    level: "info".to_string(),
+                    message: "ok".to_string(),
+                },
+                "db-1".to_string(),
+            )
+            .to_json()
+            .unwrap(),
+        )
+        .unwrap();
+        value["schema_version"] = Value::from(SCHEMA_VERSION + 1);
+        value["added_later"] = Value::from("ignored");
+
+        let event = LogEvent::from_json_any_version(&value.to_string()).unwrap();
+        assert_eq!(event.schema_version, SCHEMA_VERSION + 1);
+        assert_eq!(event.hostname, "db-1");
+    }
+}
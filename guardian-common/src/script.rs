@@ -0,0 +1,157 @@
+//! Rhai conditions for rules that field tests cannot express. A script sees
+//! the event as `event`, its data as `data` and a `state` map it keeps
+//! between events, and evaluates to whether the event matches, e.g.
+//! `data.path.ends_with(".sh") && data.path.len() > 40`.
+//!
+//! Scripts run sandboxed: no files, modules, `eval` or output, and bounded
+//! operations, nesting and sizes, so a bad script fails to match instead of
+//! stalling the pipeline.
+
+use crate::{GuardianError, LogEvent};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::sync::Mutex;
+
+/// Operations one evaluation may run before it is cut off
+const MAX_OPERATIONS: u64 = 10_000;
+
+/// Longest string a script may build, in bytes
+const MAX_STRING_SIZE: usize = 64 * 1024;
+
+/// Most entries in one array or map, including `state`
+const MAX_COLLECTION_SIZE: usize = 1024;
+
+/// A compiled script condition and the state it keeps
+pub struct RuleScript {
+    engine: Engine,
+    ast: AST,
+    state: Mutex<Map>,
+}
+
+impl std::fmt::Debug for RuleScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuleScript").finish_non_exhaustive()
+    }
+}
+
+impl RuleScript {
+    /// Compile `source`, failing on syntax errors
+    pub fn compile(source: &str) -> Result<Self, GuardianError> {
+        let engine = sandboxed_engine();
+        let ast = engine
+            .compile(source)
+            .map_err(|e| GuardianError::config(e.to_string()))?;
+        Ok(Self {
+            engine,
+            ast,
+            state: Mutex::new(Map::new()),
+        })
+    }
+
+    /// Whether the script returns `true` for `event`; errors, limits hit and
+    /// results that are not booleans count as no match
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        let (Ok(event_value), Ok(data)) = (
+            rhai::serde::to_dynamic(event),
+            rhai::serde::to_dynamic(&event.event_type),
+        ) else {
+            return false;
+        };
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut scope = Scope::new();
+        scope.push_constant("event", event_value);
+        scope.push_constant("data", data);
+        scope.push("state", std::mem::take(&mut *state));
+
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        // Keep whatever the script left in `state`, even when it failed
+        if let Some(kept) = scope.get_value::<Map>("state") {
+            *state = kept;
+        }
+        result.is_ok_and(|value| value.as_bool() == Ok(true))
+    }
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_modules(0)
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(16)
+        .set_max_expr_depths(32, 32)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {});
+    engine.disable_symbol("eval");
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, FileOperation, Severity};
+
+    fn file_event(path: &str, hostname: &str) -> LogEvent {
+        LogEvent::new(
+            Severity::Medium,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            hostname.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_script_matches_event() {
+        let script = RuleScript::compile(
+            r#"data.path.starts_with("/tmp/") && data.path.split("/").len() > 3
+                && event.severity == "MEDIUM""#,
+        )
+        .unwrap();
+        assert!(script.matches(&file_event("/tmp/a/b.sh", "web-1")));
+        assert!(!script.matches(&file_event("/tmp/b.sh", "web-1")));
+        assert!(!script.matches(&file_event("/etc/a/b.sh", "web-1")));
+
+        assert!(RuleScript::compile("data.path ==").is_err());
+    }
+
+    #[test]
+    fn test_script_keeps_state() {
+        // Match the third change seen on each host
+        let script = RuleScript::compile(
+            r#"let seen = state[event.hostname] ?? 0;
+               state[event.hostname] = seen + 1;
+               seen + 1 == 3"#,
+        )
+        .unwrap();
+        let results: Vec<bool> = ["a", "a", "b", "a", "b", "b"]
+            .iter()
+            .map(|host| script.matches(&file_event("/etc/hosts", host)))
+            .collect();
+        assert_eq!(results, [false, false, false, true, false, true]);
+    }
+
+    #[test]
+    fn test_script_sandbox() {
+        let event = file_event("/etc/hosts", "web-1");
+        // Runaway loops are cut off and count as no match
+        assert!(!RuleScript::compile("loop {} true").unwrap().matches(&event));
+        // Results other than `true` do not match
+        assert!(!RuleScript::compile("1").unwrap().matches(&event));
+        assert!(!RuleScript::compile(r#"import "x" as x; true"#)
+            .unwrap()
+            .matches(&event));
+        assert!(RuleScript::compile(r#"eval("true")"#).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{EventType, LogEvent, Severity};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Criteria for searching stored events; every set field must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SearchFilter {
+    /// Free text matched case-insensitively against event data, hostname and tags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+
+    /// Earliest event time, inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Latest event time, exclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+
+    /// Event kind as returned by [`EventType::kind`], e.g. `file_integrity`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+
+    /// A tag the event must carry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// Name of the rule the event triggered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_name: Option<String>,
+
+    /// Username or numeric uid owning the file or process, or the account of
+    /// an authentication attempt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl SearchFilter {
+    /// Free-text query, ignoring an empty one
+    pub fn text(&self) -> Option<&str> {
+        self.query.as_deref().filter(|q| !q.is_empty())
+    }
+
+    /// Whether `event` meets every criterion, as the console's stored search would
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        if let Some(text) = self.text() {
+            let text = text.to_lowercase();
+            let data = serde_json::to_string(&event.event_type).unwrap_or_default();
+            if !data.to_lowercase().contains(&text)
+                && !event.hostname.to_lowercase().contains(&text)
+                && !event.tags.iter().any(|t| t.to_lowercase().contains(&text))
+            {
+                return false;
+            }
+        }
+        if self.severity.is_some_and(|s| s != event.severity)
+            || self.from.is_some_and(|from| event.timestamp < from)
+            || self.to.is_some_and(|to| event.timestamp >= to)
+            || self
+                .event_type
+                .as_ref()
+                .is_some_and(|kind| kind != event.event_type.kind())
+            || self
+                .hostname
+                .as_ref()
+                .is_some_and(|hostname| *hostname != event.hostname)
+            || self
+                .tag
+                .as_ref()
+                .is_some_and(|tag| !event.tags.contains(tag))
+            || self
+                .rule_name
+                .as_ref()
+                .is_some_and(|rule| event.rule_name.as_ref() != Some(rule))
+        {
+            return false;
+        }
+        match &self.user {
+            Some(user) => event_user_matches(&event.event_type, user),
+            None => true,
+        }
+    }
+}
+
+fn event_user_matches(event_type: &EventType, user: &str) -> bool {
+    let owner = match event_type {
+        EventType::FileIntegrity { user, .. } | EventType::ProcessMonitor { user, .. } => {
+            user.as_ref()
+        }
+        EventType::AuthEvent { user: account, .. } => return account == user,
+        _ => None,
+    };
+    owner.is_some_and(|owner| {
+        owner.username.as_deref() == Some(user) || owner.uid.to_string() == user
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventUser, FileOperation};
+
+    #[test]
+    fn test_filter_matches_every_criterion() {
+        let event = LogEvent::new(
+            Severity::High,
+            EventType::FileIntegrity {
+                path: "/etc/Passwd".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: Some(EventUser {
+                    uid: 0,
+                    username: Some("root".to_string()),
+                }),
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_tag("fim")
+        .with_rule("Critical File Modified");
+
+        let filter = SearchFilter {
+            query: Some("passwd".to_string()),
+            severity: Some(Severity::High),
+            from: Some(event.timestamp),
+            to: Some(event.timestamp + chrono::Duration::seconds(1)),
+            event_type: Some("file_integrity".to_string()),
+            hostname: Some("web-1".to_string()),
+            tag: Some("fim".to_string()),
+            rule_name: Some("Critical File Modified".to_string()),
+            user: Some("0".to_string()),
+        };
+        assert!(filter.matches(&event));
+        assert!(SearchFilter::default().matches(&event));
+
+        let misses = [
+            SearchFilter {
+                to: Some(event.timestamp),
+                ..filter.clone()
+            },
+            SearchFilter {
+                event_type: Some("auth_event".to_string()),
+                ..filter.clone()
+            },
+            SearchFilter {
+                tag: Some("fi".to_string()),
+                ..filter.clone()
+            },
+            SearchFilter {
+                user: Some("www-data".to_string()),
+                ..filter.clone()
+            },
+        ];
+        assert!(misses.iter().all(|f| !f.matches(&event)));
+
+        // Unset fields stay out of the wire format
+        let json = serde_json::to_string(&SearchFilter {
+            hostname: Some("web-1".to_string()),
+            ..SearchFilter::default()
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"hostname":"web-1"}"#);
+        let parsed: SearchFilter = serde_json::from_str(r#"{"severity":"HIGH"}"#).unwrap();
+        assert_eq!(parsed.severity, Some(Severity::High));
+    }
+}
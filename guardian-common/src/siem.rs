@@ -0,0 +1,421 @@
+//! ArcSight Common Event Format and QRadar Log Event Extended Format
+//! renderings of events, for SIEMs that parse those without custom rules.
+
+use crate::{EventType, LogEvent, Severity};
+use serde::Serialize;
+use std::net::SocketAddr;
+
+const VENDOR: &str = "Guardian";
+const PRODUCT: &str = "Guardian";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Event attributes both formats carry, each under its own key
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    SourceAddress,
+    SourcePort,
+    DestinationAddress,
+    DestinationPort,
+    DestinationHost,
+    Protocol,
+    User,
+    FilePath,
+    FileHash,
+    Action,
+    Process,
+    ProcessId,
+    Outcome,
+    Message,
+}
+
+impl Field {
+    fn cef(self) -> &'static str {
+        match self {
+            Field::SourceAddress => "src",
+            Field::SourcePort => "spt",
+            Field::DestinationAddress => "dst",
+            Field::DestinationPort => "dpt",
+            Field::DestinationHost => "dhost",
+            Field::Protocol => "proto",
+            Field::User => "suser",
+            Field::FilePath => "filePath",
+            Field::FileHash => "fileHash",
+            Field::Action => "act",
+            Field::Process => "sproc",
+            Field::ProcessId => "spid",
+            Field::Outcome => "outcome",
+            Field::Message => "msg",
+        }
+    }
+
+    fn leef(self) -> &'static str {
+        match self {
+            Field::SourceAddress => "src",
+            Field::SourcePort => "srcPort",
+            Field::DestinationAddress => "dst",
+            Field::DestinationPort => "dstPort",
+            Field::DestinationHost => "dstHostName",
+            Field::Protocol => "proto",
+            Field::User => "usrName",
+            Field::FilePath => "filePath",
+            Field::FileHash => "fileHash",
+            Field::Action => "action",
+            Field::Process => "process",
+            Field::ProcessId => "pid",
+            Field::Outcome => "outcome",
+            Field::Message => "msg",
+        }
+    }
+}
+
+/// Lowercase name of a serialized unit variant, e.g. `modify`
+pub(crate) fn variant_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Address and optional port of an `ip:port` string
+fn split_addr(addr: &str) -> (String, Option<u16>) {
+    match addr.parse::<SocketAddr>() {
+        Ok(socket) => (socket.ip().to_string(), Some(socket.port())),
+        Err(_) => (addr.to_string(), None),
+    }
+}
+
+/// Severity on the 0-10 scale both formats use
+pub(crate) fn severity_score(severity: Severity) -> u8 {
+    match severity {
+        Severity::Info => 1,
+        Severity::Low => 3,
+        Severity::Medium => 5,
+        Severity::High => 8,
+        Severity::Critical => 10,
+    }
+}
+
+/// `\` and `|` are escaped in CEF headers
+fn cef_header(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// `\` and `=` are escaped in CEF extension values, and line breaks encoded
+fn cef_value(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// LEEF has no escaping; tabs and line breaks inside values become spaces
+fn leef_value(text: &str) -> String {
+    text.replace(['\t', '\r', '\n'], " ")
+}
+
+impl LogEvent {
+    /// Type-specific attributes, in a fixed order
+    fn siem_fields(&self) -> Vec<(Field, String)> {
+        let mut fields = Vec::new();
+        let user = |user: &Option<crate::EventUser>| {
+            user.as_ref()
+                .map(|u| u.username.clone().unwrap_or_else(|| u.uid.to_string()))
+        };
+        match &self.event_type {
+            EventType::FileIntegrity {
+                path,
+                operation,
+                hash,
+                user: owner,
+                ..
+            } => {
+                fields.push((Field::FilePath, path.clone()));
+                fields.push((Field::Action, variant_name(operation)));
+                if let Some(hash) = hash {
+                    fields.push((Field::FileHash, hash.clone()));
+                }
+                if let Some(owner) = user(owner) {
+                    fields.push((Field::User, owner));
+                }
+            }
+            EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                remote_host,
+                protocol,
+                state,
+                process,
+                ..
+            } => {
+                let (src, spt) = split_addr(local_addr);
+                fields.push((Field::SourceAddress, src));
+                if let Some(port) = spt {
+                    fields.push((Field::SourcePort, port.to_string()));
+                }
+                if let Some(remote) = remote_addr {
+                    let (dst, dpt) = split_addr(remote);
+                    fields.push((Field::DestinationAddress, dst));
+                    if let Some(port) = dpt {
+                        fields.push((Field::DestinationPort, port.to_string()));
+                    }
+                }
+                if let Some(host) = remote_host {
+                    fields.push((Field::DestinationHost, host.clone()));
+                }
+                fields.push((Field::Protocol, protocol.clone()));
+                fields.push((Field::Action, state.clone()));
+                if let Some(process) = process {
+                    fields.push((Field::Process, process.name.clone()));
+                    fields.push((Field::ProcessId, process.pid.to_string()));
+                }
+            }
+            EventType::SystemLog {
+                source, message, ..
+            } => {
+                fields.push((Field::Process, source.clone()));
+                fields.push((Field::Message, message.clone()));
+            }
+            EventType::ProcessMonitor {
+                pid,
+                name,
+                user: owner,
+                ..
+            } => {
+                fields.push((Field::Process, name.clone()));
+                fields.push((Field::ProcessId, pid.to_string()));
+                if let Some(owner) = user(owner) {
+                    fields.push((Field::User, owner));
+                }
+            }
+            EventType::AuthEvent {
+                user,
+                source_ip,
+                method,
+                outcome,
+                ..
+            } => {
+                fields.push((Field::User, user.clone()));
+                if let Some(ip) = source_ip {
+                    fields.push((Field::SourceAddress, ip.clone()));
+                }
+                fields.push((Field::Action, method.clone()));
+                fields.push((Field::Outcome, variant_name(outcome)));
+            }
+            EventType::PackageChange {
+                name,
+                version,
+                action,
+                manager,
+            } => {
+                fields.push((Field::Action, variant_name(action)));
+                fields.push((Field::Process, manager.clone()));
+                let package = match version {
+                    Some(version) => format!("{} {}", name, version),
+                    None => name.clone(),
+                };
+                fields.push((Field::Message, package));
+            }
+            EventType::Heartbeat {
+                agent_version,
+                uptime_secs,
+                ..
+            } => {
+                fields.push((
+                    Field::Message,
+                    format!("agent {} up {}s", agent_version, uptime_secs),
+                ));
+            }
+            EventType::Alert {
+                title, description, ..
+            } => {
+                fields.push((Field::Message, format!("{}: {}", title, description)));
+            }
+            EventType::ScanProgress {
+                path,
+                files_scanned,
+                files_total,
+                ..
+            } => {
+                fields.push((Field::FilePath, path.clone()));
+                let scanned = match files_total {
+                    Some(total) => format!("{} of {} files scanned", files_scanned, total),
+                    None => format!("{} files scanned", files_scanned),
+                };
+                fields.push((Field::Message, scanned));
+            }
+            EventType::ScanSummary {
+                path,
+                requested_by,
+                outcome,
+                files_scanned,
+                files_matched,
+                ..
+            } => {
+                fields.push((Field::FilePath, path.clone()));
+                fields.push((Field::User, requested_by.clone()));
+                fields.push((Field::Outcome, variant_name(outcome)));
+                fields.push((
+                    Field::Message,
+                    format!("{} files scanned, {} matched", files_scanned, files_matched),
+                ));
+            }
+        }
+        fields
+    }
+
+    /// Rule name, or the event kind in words
+    pub(crate) fn siem_name(&self) -> String {
+        self.rule_name
+            .clone()
+            .unwrap_or_else(|| self.event_type.kind().replace('_', " "))
+    }
+
+    /// Render as one CEF:0 line, without a syslog header
+    pub fn to_cef(&self) -> String {
+        let mut extension = vec![
+            format!("rt={}", self.timestamp.timestamp_millis()),
+            format!("dvchost={}", cef_value(&self.hostname)),
+            format!("externalId={}", self.id),
+        ];
+        extension.extend(
+            self.siem_fields()
+                .into_iter()
+                .map(|(field, value)| format!("{}={}", field.cef(), cef_value(&value))),
+        );
+        if !self.tags.is_empty() {
+            extension.push("cs1Label=tags".to_string());
+            extension.push(format!("cs1={}", cef_value(&self.tags.join(","))));
+        }
+
+        format!(
+            "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+            VENDOR,
+            PRODUCT,
+            VERSION,
+            self.event_type.kind(),
+            cef_header(&self.siem_name()),
+            severity_score(self.severity),
+            extension.join(" ")
+        )
+    }
+
+    /// Render as one tab-delimited LEEF:1.0 line, without a syslog header
+    pub fn to_leef(&self) -> String {
+        let mut attributes = vec![
+            (
+                "devTime",
+                self.timestamp.format("%Y-%m-%dT%H:%M:%S%.3f%z").to_string(),
+            ),
+            ("devTimeFormat", "yyyy-MM-dd'T'HH:mm:ss.SSSZ".to_string()),
+            ("sev", severity_score(self.severity).to_string()),
+            ("cat", self.event_type.kind().to_string()),
+            ("hostname", self.hostname.clone()),
+            ("eventId", self.id.to_string()),
+        ];
+        if let Some(rule) = &self.rule_name {
+            attributes.push(("rule", rule.clone()));
+        }
+        attributes.extend(
+            self.siem_fields()
+                .into_iter()
+                .map(|(field, value)| (field.leef(), value)),
+        );
+        if !self.tags.is_empty() {
+            attributes.push(("tags", self.tags.join(",")));
+        }
+
+        let attributes: Vec<String> = attributes
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, leef_value(&value)))
+            .collect();
+        format!(
+            "LEEF:1.0|{}|{}|{}|{}|{}",
+            VENDOR,
+            PRODUCT,
+            VERSION,
+            self.event_type.kind(),
+            attributes.join("\t")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthOutcome, FileOperation};
+
+    #[test]
+    fn test_cef_and_leef_rendering() {
+        let event = LogEvent::new(
+            Severity::High,
+            EventType::FileIntegrity {
+                path: "C:\\temp\\a=b.txt".to_string(),
+                operation: FileOperation::Modify,
+                hash: Some("abc123".to_string()),
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("Pipe | Rule")
+        .with_tag("fim");
+
+        let cef = event.to_cef();
+        let prefix = format!(
+            "CEF:0|Guardian|Guardian|{}|file_integrity|Pipe \\| Rule|8|rt=",
+            VERSION
+        );
+        assert!(cef.starts_with(&prefix), "{}", cef);
+        assert!(cef.contains(" filePath=C:\\\\temp\\\\a\\=b.txt act=modify fileHash=abc123"));
+        assert!(cef.ends_with(" cs1Label=tags cs1=fim"));
+
+        let leef = event.to_leef();
+        assert!(leef.starts_with(&format!(
+            "LEEF:1.0|Guardian|Guardian|{}|file_integrity|devTime=",
+            VERSION
+        )));
+        assert!(leef.contains("\tsev=8\tcat=file_integrity\thostname=web-1\t"));
+        assert!(leef.contains("\trule=Pipe | Rule\t"));
+        assert!(leef.contains("\tfilePath=C:\\temp\\a=b.txt\taction=modify\t"));
+
+        let login = LogEvent::new(
+            Severity::Low,
+            EventType::AuthEvent {
+                user: "root".to_string(),
+                source_ip: Some("203.0.113.5".to_string()),
+                method: "password".to_string(),
+                outcome: AuthOutcome::Failure,
+                tty: None,
+            },
+            "web-1".to_string(),
+        );
+        let cef = login.to_cef();
+        assert!(cef.contains("|auth_event|auth event|3|"));
+        assert!(cef.ends_with("suser=root src=203.0.113.5 act=password outcome=failure"));
+        assert!(login
+            .to_leef()
+            .ends_with("usrName=root\tsrc=203.0.113.5\taction=password\toutcome=failure"));
+    }
+
+    #[test]
+    fn test_network_addresses_split_into_ports() {
+        let event = LogEvent::new(
+            Severity::Medium,
+            EventType::NetworkSocket {
+                local_addr: "[::1]:22".to_string(),
+                remote_addr: Some("198.51.100.7:51514".to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "web-1".to_string(),
+        );
+        assert!(event
+            .to_cef()
+            .contains("src=::1 spt=22 dst=198.51.100.7 dpt=51514 proto=tcp act=established"));
+    }
+}
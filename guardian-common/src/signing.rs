@@ -0,0 +1,243 @@
+//! HMAC-SHA256 signatures on events. An agent holding the key signs every
+//! event it emits, and its rollups and hello; consumers holding the same key
+//! drop any whose signature is missing or wrong, so a frame written into the
+//! socket or pipe by anything else is caught before it is stored.
+
+use crate::dbkey;
+use crate::{AgentHello, GuardianError, LogEvent, StatsRollup};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Shared secret for event signatures
+pub const KEY_ENV: &str = "GUARDIAN_EVENT_KEY";
+
+/// Shell command printing the secret, e.g. an OS keychain lookup
+pub const KEY_COMMAND_ENV: &str = "GUARDIAN_EVENT_KEY_COMMAND";
+
+/// Shortest key accepted, in bytes
+pub const MIN_KEY_LEN: usize = 16;
+
+/// What an agent signs: its events, rollups and hello
+pub trait Signed: Serialize + Clone {
+    /// How errors name it, e.g. "Event <id>"
+    fn describe(&self) -> String;
+
+    fn signature(&self) -> Option<&str>;
+
+    fn set_signature(&mut self, signature: Option<String>);
+}
+
+impl Signed for LogEvent {
+    fn describe(&self) -> String {
+        format!("Event {}", self.id)
+    }
+
+    fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    fn set_signature(&mut self, signature: Option<String>) {
+        self.signature = signature;
+    }
+}
+
+impl Signed for StatsRollup {
+    fn describe(&self) -> String {
+        format!("Rollup from {}", self.hostname)
+    }
+
+    fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    fn set_signature(&mut self, signature: Option<String>) {
+        self.signature = signature;
+    }
+}
+
+impl Signed for AgentHello {
+    fn describe(&self) -> String {
+        format!("Hello from {}", self.hostname)
+    }
+
+    fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    fn set_signature(&mut self, signature: Option<String>) {
+        self.signature = signature;
+    }
+}
+
+/// Signs events and checks their signatures with one key
+#[derive(Clone)]
+pub struct EventSigner {
+    mac: Hmac<Sha256>,
+}
+
+impl std::fmt::Debug for EventSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSigner").finish_non_exhaustive()
+    }
+}
+
+impl EventSigner {
+    pub fn new(key: &[u8]) -> Result<Self, GuardianError> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(GuardianError::config(format!(
+                "Event signing key is {} bytes, at least {} are needed",
+                key.len(),
+                MIN_KEY_LEN
+            )));
+        }
+        let mac = Hmac::new_from_slice(key).expect("HMAC takes keys of any length");
+        Ok(Self { mac })
+    }
+
+    /// Signer keyed from `GUARDIAN_EVENT_KEY`, else from running
+    /// `GUARDIAN_EVENT_KEY_COMMAND`; `None` when neither is set
+    pub fn from_env() -> Result<Option<Self>, GuardianError> {
+        Self::resolve(
+            std::env::var(KEY_ENV).ok(),
+            std::env::var(KEY_COMMAND_ENV).ok(),
+            KEY_COMMAND_ENV,
+        )
+    }
+
+    /// Signer keyed from `key`, else from the output of the shell `command`,
+    /// which errors name as `source`; `None` when neither is set
+    pub fn resolve(
+        key: Option<String>,
+        command: Option<String>,
+        source: &str,
+    ) -> Result<Option<Self>, GuardianError> {
+        dbkey::resolve(key, command, source)
+            .map_err(GuardianError::config)?
+            .map(|key| Self::new(key.as_bytes()))
+            .transpose()
+    }
+
+    /// MAC over the frame as JSON without its signature
+    fn mac<T: Signed>(&self, frame: &T) -> Result<Hmac<Sha256>, GuardianError> {
+        let mut unsigned = frame.clone();
+        unsigned.set_signature(None);
+        let mut mac = self.mac.clone();
+        mac.update(&serde_json::to_vec(&unsigned)?);
+        Ok(mac)
+    }
+
+    /// Set the frame's `signature`, replacing any it had
+    pub fn sign<T: Signed>(&self, frame: &mut T) -> Result<(), GuardianError> {
+        let tag = self.mac(frame)?.finalize().into_bytes();
+        frame.set_signature(Some(tag.iter().map(|b| format!("{:02x}", b)).collect()));
+        Ok(())
+    }
+
+    /// Whether the frame carries this key's signature over its contents
+    pub fn verify<T: Signed>(&self, frame: &T) -> Result<(), GuardianError> {
+        let Some(signature) = frame.signature() else {
+            return Err(GuardianError::InvalidEvent(format!(
+                "{} is not signed",
+                frame.describe()
+            )));
+        };
+        let tag = decode_hex(signature).unwrap_or_default();
+        self.mac(frame)?.verify_slice(&tag).map_err(|_| {
+            GuardianError::InvalidEvent(format!("{} has a bad signature", frame.describe()))
+        })
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventType, Severity};
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = EventSigner::new(b"0123456789abcdef").unwrap();
+        let mut event = LogEvent::new(
+            Severity::Low,
+            EventType::SystemLog {
+                source: "sshd".to_string(),
+                level: "warning".to_string(),
+                message: "Failed password for root".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        assert!(signer.verify(&event).is_err());
+
+        signer.sign(&mut event).unwrap();
+        assert_eq!(event.signature.as_ref().map(String::len), Some(64));
+        // The signature survives the trip through JSON and CBOR
+        let json = LogEvent::from_json(&event.to_json().unwrap()).unwrap();
+        signer.verify(&json).unwrap();
+        signer
+            .verify(&LogEvent::from_cbor(&event.to_cbor().unwrap()).unwrap())
+            .unwrap();
+
+        let mut tampered = event.clone();
+        tampered.severity = Severity::Info;
+        let error = signer.verify(&tampered).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Event {} has a bad signature", event.id)
+        );
+
+        let other = EventSigner::new(b"fedcba9876543210").unwrap();
+        assert!(other.verify(&event).is_err());
+        assert!(EventSigner::new(b"short").is_err());
+        assert!(EventSigner::resolve(None, None, KEY_COMMAND_ENV)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_sign_rollups_and_hellos() {
+        let signer = EventSigner::new(b"0123456789abcdef").unwrap();
+        let mut rollup = StatsRollup {
+            hostname: "web-1".to_string(),
+            period_start: chrono::Utc::now(),
+            period_secs: 3600,
+            counts: Vec::new(),
+            top_paths: Vec::new(),
+            signature: None,
+        };
+        assert_eq!(
+            signer.verify(&rollup).unwrap_err().to_string(),
+            "Rollup from web-1 is not signed"
+        );
+        signer.sign(&mut rollup).unwrap();
+        signer
+            .verify(&StatsRollup::from_json(&rollup.to_json().unwrap()).unwrap())
+            .unwrap();
+        rollup.period_secs = 60;
+        assert!(signer.verify(&rollup).is_err());
+
+        let mut hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: crate::SCHEMA_VERSION,
+            capabilities: Default::default(),
+            signature: None,
+        };
+        assert!(signer.verify(&hello).is_err());
+        signer.sign(&mut hello).unwrap();
+        signer
+            .verify(&AgentHello::from_json(&hello.to_json().unwrap()).unwrap())
+            .unwrap();
+        hello.capabilities.commands.push("isolate_host".to_string());
+        assert!(signer.verify(&hello).is_err());
+    }
+}
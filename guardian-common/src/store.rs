@@ -0,0 +1,594 @@
+//! Writing events into Sentinel's database. Sentinel, the bridge and the
+//! tools that fill a database all store events through here, so they agree
+//! on the row layout and on what else an event adds.
+//!
+//! An event's payload can be stored zstd-compressed in `event_payload`,
+//! with a dictionary trained on the database's own events; `event_data` is
+//! then left empty. Queries read `event_json(event_data, event_payload)`
+//! instead of `event_data`, a SQL function [`register_functions`] adds to
+//! each connection, so filters, search and reports see every row whole
+//! whichever way it was stored.
+
+use crate::error::GuardianError;
+use crate::{LogEvent, SearchFilter, StatsRollup};
+use chrono::Utc;
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::{SqliteArguments, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, Sqlite, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::ffi::c_int;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+/// Set to compress the payloads of the events Sentinel and the bridge store
+pub const COMPRESS_ENV: &str = "GUARDIAN_COMPRESS_PAYLOADS";
+
+/// zstd level payloads are compressed at
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Largest dictionary trained; event payloads are small, so a small one
+/// captures their shared keys and values
+const DICTIONARY_BYTES: usize = 16 * 1024;
+
+/// Newest events a dictionary is trained on
+const TRAINING_SAMPLES: i64 = 2000;
+
+/// Fewest events worth training a dictionary on, and how many events are
+/// compressed between attempts while there is no dictionary yet
+const MIN_TRAINING_SAMPLES: u64 = 200;
+
+/// Events compressed with a dictionary before it is trained again on the
+/// newest events, so it follows what the agents send
+const RETRAIN_INTERVAL: u64 = 100_000;
+
+/// Whether `GUARDIAN_COMPRESS_PAYLOADS` asks for compression
+pub fn compression_enabled() -> bool {
+    std::env::var(COMPRESS_ENV).is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes" | "on"))
+}
+
+/// An event encoded as the columns of its `events` row
+pub struct EventRow<'a> {
+    event: &'a LogEvent,
+    severity: String,
+    event_data: String,
+    event_payload: Option<Vec<u8>>,
+    tags: String,
+    rules_matched: Option<String>,
+}
+
+impl<'a> EventRow<'a> {
+    pub fn new(event: &'a LogEvent) -> serde_json::Result<Self> {
+        let severity = serde_json::to_value(event.severity)?;
+        Ok(Self {
+            event,
+            severity: severity.as_str().unwrap_or_default().to_string(),
+            event_data: serde_json::to_string(&event.event_type)?,
+            event_payload: None,
+            tags: serde_json::to_string(&event.tags)?,
+            rules_matched: (!event.rules_matched.is_empty())
+                .then(|| serde_json::to_string(&event.rules_matched))
+                .transpose()?,
+        })
+    }
+
+    /// Store the payload compressed by `encoder` and nothing in `event_data`
+    pub fn compress(mut self, encoder: &PayloadEncoder) -> io::Result<Self> {
+        self.event_payload = Some(encoder.compress(self.event_data.as_bytes())?);
+        self.event_data = String::new();
+        Ok(self)
+    }
+
+    /// Store the event with its tags, queue it as an alert when it triggered
+    /// a rule and add it to the open incident collecting its rule and host.
+    ///
+    /// Forwarders resend events whose acknowledgement was lost, so an event
+    /// already stored is skipped; returns whether this one was new. Run it in
+    /// a transaction so an event is never stored without the rest.
+    pub async fn insert(&self, conn: &mut SqliteConnection) -> Result<bool, sqlx::Error> {
+        let event = self.event;
+        let inserted = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO events (id, timestamp, severity, event_type, event_data, event_payload, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(event.id.to_string())
+        .bind(event.timestamp.to_rfc3339())
+        .bind(&self.severity)
+        .bind(event.event_type.kind())
+        .bind(&self.event_data)
+        .bind(&self.event_payload)
+        .bind(&event.hostname)
+        .bind(&self.tags)
+        .bind(event.rule_triggered as i32)
+        .bind(&event.rule_name)
+        .bind(&self.rules_matched)
+        .bind(event.schema_version)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected()
+            > 0;
+        if !inserted {
+            return Ok(false);
+        }
+
+        // Exact tag filters and facet counts read the tags table
+        for tag in &event.tags {
+            sqlx::query("INSERT OR IGNORE INTO event_tags (event_id, tag) VALUES (?, ?)")
+                .bind(event.id.to_string())
+                .bind(tag)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        // Rule hits are queued as alerts; other events stay raw
+        if event.rule_triggered {
+            sqlx::query(
+                "INSERT OR IGNORE INTO alerts (event_id, status, updated_at) VALUES (?, 'open', ?)",
+            )
+            .bind(event.id.to_string())
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        if let Some(key) = event.correlation_key() {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO incident_events (incident_id, event_id, added_by, added_at)
+                SELECT id, ?, 'correlation', ? FROM incidents
+                WHERE status = 'open' AND correlation_key = ?
+                "#,
+            )
+            .bind(event.id.to_string())
+            .bind(Utc::now().to_rfc3339())
+            .bind(key)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Store an agent rollup, replacing any earlier copy of the same period.
+/// Run it in a transaction so a period is never left half replaced.
+pub async fn insert_rollup(
+    conn: &mut SqliteConnection,
+    rollup: &StatsRollup,
+) -> Result<(), GuardianError> {
+    let period_start = rollup.period_start.to_rfc3339();
+
+    for table in ["event_rollups", "rollup_paths"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE hostname = ? AND period_start = ?",
+            table
+        ))
+        .bind(&rollup.hostname)
+        .bind(&period_start)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for count in &rollup.counts {
+        sqlx::query(
+            r#"
+            INSERT INTO event_rollups (hostname, period_start, event_type, severity, count)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&rollup.hostname)
+        .bind(&period_start)
+        .bind(&count.event_type)
+        .bind(serde_json::to_string(&count.severity)?.trim_matches('"'))
+        .bind(count.count as i64)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for path in &rollup.top_paths {
+        sqlx::query(
+            "INSERT INTO rollup_paths (hostname, period_start, path, count) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&rollup.hostname)
+        .bind(&period_start)
+        .bind(&path.path)
+        .bind(path.count as i64)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Rebuild a `LogEvent` from an `events` row selected with `event_data`
+/// read through `event_json`, upgrading it from the schema version it was
+/// written with
+pub fn event_from_row(row: &SqliteRow) -> Result<LogEvent, GuardianError> {
+    let mut event = match serde_json::from_str(&row.try_get::<String, _>("event_data")?)? {
+        serde_json::Value::Object(map) => map,
+        _ => {
+            return Err(GuardianError::InvalidEvent(
+                "event_data is not an object".to_string(),
+            ))
+        }
+    };
+    event.insert("id".into(), row.try_get::<String, _>("id")?.into());
+    event.insert(
+        "timestamp".into(),
+        row.try_get::<String, _>("timestamp")?.into(),
+    );
+    event.insert(
+        "severity".into(),
+        row.try_get::<String, _>("severity")?.into(),
+    );
+    event.insert(
+        "hostname".into(),
+        row.try_get::<String, _>("hostname")?.into(),
+    );
+    event.insert(
+        "tags".into(),
+        serde_json::from_str(&row.try_get::<String, _>("tags")?)?,
+    );
+    event.insert(
+        "rule_triggered".into(),
+        (row.try_get::<i32, _>("rule_triggered")? != 0).into(),
+    );
+    event.insert(
+        "rule_name".into(),
+        row.try_get::<Option<String>, _>("rule_name")?.into(),
+    );
+    if let Some(rules) = row.try_get::<Option<String>, _>("rules_matched")? {
+        event.insert("rules_matched".into(), serde_json::from_str(&rules)?);
+    }
+    event.insert(
+        "schema_version".into(),
+        row.try_get::<i64, _>("schema_version")?.into(),
+    );
+
+    LogEvent::from_json_any_version(&serde_json::Value::Object(event).to_string())
+}
+
+pub type SqliteQuery<'q> = sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>;
+
+/// Append the conditions for `filter` to a query over `events`. Sentinel's
+/// search and `guardian-cli` both filter here, so they find the same events.
+pub fn push_filter(sql: &mut String, filter: &SearchFilter) {
+    if filter.text().is_some() {
+        sql.push_str(
+            " AND (event_json(event_data, event_payload) LIKE ? OR hostname LIKE ? OR tags LIKE ?)",
+        );
+    }
+    if filter.severity.is_some() {
+        sql.push_str(" AND severity = ?");
+    }
+    if filter.from.is_some() {
+        sql.push_str(" AND timestamp >= ?");
+    }
+    if filter.to.is_some() {
+        sql.push_str(" AND timestamp < ?");
+    }
+    if filter.event_type.is_some() {
+        sql.push_str(" AND event_type = ?");
+    }
+    if filter.hostname.is_some() {
+        sql.push_str(" AND hostname = ?");
+    }
+    if filter.tag.is_some() {
+        sql.push_str(" AND id IN (SELECT event_id FROM event_tags WHERE tag = ?)");
+    }
+    if filter.rule_name.is_some() {
+        sql.push_str(" AND rule_name = ?");
+    }
+    if filter.user.is_some() {
+        sql.push_str(
+            " AND (json_extract(event_json(event_data, event_payload), '$.user.username') = ? \
+             OR CAST(json_extract(event_json(event_data, event_payload), '$.user.uid') AS TEXT) = ? \
+             OR (json_extract(event_json(event_data, event_payload), '$.type') = 'auth_event' \
+                 AND json_extract(event_json(event_data, event_payload), '$.user') = ?))",
+        );
+    }
+}
+
+/// Bind the values of the conditions `push_filter` added, in the same order
+pub fn bind_filter<'q>(mut query: SqliteQuery<'q>, filter: &SearchFilter) -> SqliteQuery<'q> {
+    if let Some(text) = filter.text() {
+        let pattern = format!("%{}%", text);
+        query = query
+            .bind(pattern.clone())
+            .bind(pattern.clone())
+            .bind(pattern);
+    }
+    if let Some(severity) = filter.severity {
+        query = query.bind(
+            serde_json::to_string(&severity)
+                .unwrap_or_default()
+                .trim_matches('"')
+                .to_string(),
+        );
+    }
+    if let Some(from) = filter.from {
+        query = query.bind(from.to_rfc3339());
+    }
+    if let Some(to) = filter.to {
+        query = query.bind(to.to_rfc3339());
+    }
+    for value in [
+        &filter.event_type,
+        &filter.hostname,
+        &filter.tag,
+        &filter.rule_name,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        query = query.bind(value.clone());
+    }
+    if let Some(user) = &filter.user {
+        query = query
+            .bind(user.clone())
+            .bind(user.clone())
+            .bind(user.clone());
+    }
+    query
+}
+
+/// Compresses event payloads, with a dictionary once the database has one
+#[derive(Default)]
+pub struct PayloadEncoder {
+    state: Mutex<EncoderState>,
+}
+
+#[derive(Default)]
+struct EncoderState {
+    /// Whether the newest stored dictionary has been looked for
+    loaded: bool,
+    dictionary: Option<Arc<EncoderDictionary<'static>>>,
+    /// Events compressed since the dictionary was loaded or trained, or
+    /// since the last attempt to train one
+    compressed: u64,
+}
+
+impl PayloadEncoder {
+    /// Encoder that picks up the newest stored dictionary on first use
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether payloads are compressed with a trained dictionary
+    pub fn has_dictionary(&self) -> bool {
+        self.state().dictionary.is_some()
+    }
+
+    /// Load the newest stored dictionary on first use, and train a new one
+    /// on the newest events when due: every `MIN_TRAINING_SAMPLES` events
+    /// while there is none, every `RETRAIN_INTERVAL` once there is. Call it
+    /// before storing each event; payloads are still compressed, without a
+    /// dictionary if need be, when it fails.
+    pub async fn maintain(&self, pool: &SqlitePool) -> Result<(), GuardianError> {
+        let (loaded, due) = {
+            let mut state = self.state();
+            state.compressed += 1;
+            let interval = match state.dictionary {
+                Some(_) => RETRAIN_INTERVAL,
+                None => MIN_TRAINING_SAMPLES,
+            };
+            (state.loaded, state.compressed > interval)
+        };
+
+        if !loaded {
+            let newest: Option<Vec<u8>> = sqlx::query_scalar(
+                "SELECT dictionary FROM payload_dictionaries ORDER BY created_at DESC LIMIT 1",
+            )
+            .fetch_optional(pool)
+            .await?;
+            let mut state = self.state();
+            state.loaded = true;
+            if let Some(dictionary) = newest {
+                state.dictionary = Some(Arc::new(EncoderDictionary::copy(
+                    &dictionary,
+                    COMPRESSION_LEVEL,
+                )));
+                return Ok(());
+            }
+        } else if !due {
+            return Ok(());
+        }
+
+        // Reset first, so a failed attempt waits for the next interval too
+        self.state().compressed = 0;
+        if let Some(dictionary) = train_dictionary(pool).await? {
+            self.state().dictionary = Some(Arc::new(EncoderDictionary::copy(
+                &dictionary,
+                COMPRESSION_LEVEL,
+            )));
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, EncoderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self.state().dictionary.clone() {
+            Some(dictionary) => {
+                zstd::bulk::Compressor::with_prepared_dictionary(&dictionary)?.compress(data)
+            }
+            None => zstd::bulk::compress(data, COMPRESSION_LEVEL),
+        }
+    }
+}
+
+/// Train a dictionary on the payloads of the newest events and store it;
+/// `None` when there are too few events yet
+async fn train_dictionary(pool: &SqlitePool) -> Result<Option<Vec<u8>>, GuardianError> {
+    let samples: Vec<String> = sqlx::query_scalar(
+        "SELECT event_json(event_data, event_payload) FROM events ORDER BY timestamp DESC LIMIT ?",
+    )
+    .bind(TRAINING_SAMPLES)
+    .fetch_all(pool)
+    .await?;
+    if (samples.len() as u64) < MIN_TRAINING_SAMPLES {
+        return Ok(None);
+    }
+
+    let dictionary =
+        zstd::dict::from_samples(&samples, DICTIONARY_BYTES).map_err(GuardianError::storage)?;
+    let id = dictionary_id(zstd::zstd_safe::get_dict_id_from_dict(&dictionary))
+        .ok_or_else(|| GuardianError::storage("Trained payload dictionary has no ID"))?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO payload_dictionaries (id, dictionary, created_at) VALUES (?, ?, ?)",
+    )
+    .bind(id)
+    .bind(&dictionary)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(Some(dictionary))
+}
+
+/// Decoder dictionaries by zstd dictionary ID. Trained IDs are random, so
+/// dictionaries of different databases do not collide.
+fn decoders() -> &'static RwLock<HashMap<i64, Arc<DecoderDictionary<'static>>>> {
+    static DECODERS: OnceLock<RwLock<HashMap<i64, Arc<DecoderDictionary<'static>>>>> =
+        OnceLock::new();
+    DECODERS.get_or_init(Default::default)
+}
+
+fn dictionary_id(id: Option<std::num::NonZeroU32>) -> Option<i64> {
+    id.map(|id| i64::from(id.get()))
+}
+
+/// Decompress a payload; `dictionary` reads the stored dictionary of an ID
+/// the first time a payload needs it
+fn decompress(
+    payload: &[u8],
+    dictionary: impl FnOnce(i64) -> Result<Option<Vec<u8>>, String>,
+) -> Result<String, String> {
+    let mut json = String::new();
+    match dictionary_id(zstd::zstd_safe::get_dict_id_from_frame(payload)) {
+        Some(id) => {
+            let cached = decoders().read().unwrap().get(&id).cloned();
+            let decoder = match cached {
+                Some(decoder) => decoder,
+                None => {
+                    let stored = dictionary(id)?
+                        .ok_or_else(|| format!("Payload dictionary {} is missing", id))?;
+                    let decoder = Arc::new(DecoderDictionary::copy(&stored));
+                    decoders().write().unwrap().insert(id, Arc::clone(&decoder));
+                    decoder
+                }
+            };
+            zstd::stream::read::Decoder::with_prepared_dictionary(payload, &decoder)
+                .and_then(|mut decoder| decoder.read_to_string(&mut json))
+        }
+        None => zstd::stream::read::Decoder::new(payload)
+            .and_then(|mut decoder| decoder.read_to_string(&mut json)),
+    }
+    .map_err(|e| format!("Failed to decompress an event payload: {}", e))?;
+    Ok(json)
+}
+
+/// Pool options that run [`register_functions`] on every new connection
+pub fn pool_options() -> SqlitePoolOptions {
+    SqlitePoolOptions::new().after_connect(|conn, _| Box::pin(register_functions(conn)))
+}
+
+/// Add `event_json(event_data, event_payload)` to a connection: the JSON of
+/// an event's type and data, decompressed if its row was stored compressed.
+/// Run it on every new connection of a pool that reads or writes events.
+pub async fn register_functions(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let mut handle = conn.lock_handle().await?;
+    // SAFETY: the handle is a live connection this task holds the lock of,
+    // and the name is NUL-terminated
+    let code = unsafe {
+        ffi::sqlite3_create_function_v2(
+            handle.as_raw_handle().as_ptr(),
+            c"event_json".as_ptr(),
+            2,
+            ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+            std::ptr::null_mut(),
+            Some(event_json),
+            None,
+            None,
+            None,
+        )
+    };
+    if code != ffi::SQLITE_OK {
+        return Err(sqlx::Error::Protocol(format!(
+            "Failed to register event_json (SQLite error {})",
+            code
+        )));
+    }
+    Ok(())
+}
+
+/// `event_json(event_data, event_payload)`: `event_data` as it is unless
+/// the payload is set
+unsafe extern "C" fn event_json(
+    ctx: *mut ffi::sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    let payload = *argv.add(1);
+    if ffi::sqlite3_value_type(payload) != ffi::SQLITE_BLOB {
+        ffi::sqlite3_result_value(ctx, *argv);
+        return;
+    }
+
+    let len = ffi::sqlite3_value_bytes(payload) as usize;
+    let payload = match ffi::sqlite3_value_blob(payload) {
+        data if data.is_null() => &[][..],
+        data => std::slice::from_raw_parts(data.cast::<u8>(), len),
+    };
+    let db = ffi::sqlite3_context_db_handle(ctx);
+    match decompress(payload, |id| read_dictionary(db, id)) {
+        Ok(json) => ffi::sqlite3_result_text(
+            ctx,
+            json.as_ptr().cast(),
+            json.len() as c_int,
+            ffi::SQLITE_TRANSIENT(),
+        ),
+        Err(message) => {
+            ffi::sqlite3_result_error(ctx, message.as_ptr().cast(), message.len() as c_int)
+        }
+    }
+}
+
+/// The stored dictionary with `id`, read over the connection the function
+/// is running on
+unsafe fn read_dictionary(db: *mut ffi::sqlite3, id: i64) -> Result<Option<Vec<u8>>, String> {
+    let sql = c"SELECT dictionary FROM payload_dictionaries WHERE id = ?";
+    let mut stmt = std::ptr::null_mut();
+    if ffi::sqlite3_prepare_v2(db, sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut())
+        != ffi::SQLITE_OK
+    {
+        return Err(error_message(db));
+    }
+    ffi::sqlite3_bind_int64(stmt, 1, id);
+    let result = match ffi::sqlite3_step(stmt) {
+        ffi::SQLITE_ROW => {
+            let len = ffi::sqlite3_column_bytes(stmt, 0) as usize;
+            let data = ffi::sqlite3_column_blob(stmt, 0);
+            Ok(Some(if data.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(data.cast::<u8>(), len).to_vec()
+            }))
+        }
+        ffi::SQLITE_DONE => Ok(None),
+        _ => Err(error_message(db)),
+    };
+    ffi::sqlite3_finalize(stmt);
+    result
+}
+
+unsafe fn error_message(db: *mut ffi::sqlite3) -> String {
+    let message = std::ffi::CStr::from_ptr(ffi::sqlite3_errmsg(db));
+    format!(
+        "Failed to read a payload dictionary: {}",
+        message.to_string_lossy()
+    )
+}
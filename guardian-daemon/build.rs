@@ -0,0 +1,13 @@
+fn main() {
+    // The gRPC API is optional; protox compiles the schema without needing protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/guardian.proto");
+        let descriptors = protox::compile(["proto/guardian.proto"], ["proto"])
+            .expect("Failed to parse proto/guardian.proto");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("Failed to generate gRPC code");
+    }
+}
@@ -0,0 +1,340 @@
+//! Scanning inside zip, tar and gzip files. Entries are extracted one at a
+//! time into a private sandbox directory, run through the YARA rules and
+//! deleted, descending into archives inside archives up to `max_depth`.
+//! Extracted files get generated names, never the entry's own, so a crafted
+//! `../` entry cannot land outside the sandbox, and the size and count
+//! limits stop decompression bombs.
+
+use crate::config::ArchiveConfig;
+use crate::scanner::YaraScanner;
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Separates the names of nested entries, e.g. `site.zip!js/app.js`
+pub const ENTRY_SEPARATOR: char = '!';
+
+/// An entry that matched, named by its path through every enclosing archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveMatch {
+    pub entry: String,
+    pub rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Zip,
+    Tar,
+    Gzip,
+}
+
+/// Format from the leading bytes, whatever the file is called
+fn sniff(path: &Path) -> io::Result<Option<Format>> {
+    let mut header = Vec::with_capacity(512);
+    File::open(path)?.take(512).read_to_end(&mut header)?;
+    Ok(if header.starts_with(b"PK\x03\x04") {
+        Some(Format::Zip)
+    } else if header.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::Gzip)
+    } else if header.get(257..262) == Some(b"ustar") {
+        Some(Format::Tar)
+    } else {
+        None
+    })
+}
+
+/// Directory readable by the daemon only, removed with everything in it
+struct Sandbox {
+    dir: PathBuf,
+    next: u64,
+}
+
+impl Sandbox {
+    fn create(parent: &Path) -> io::Result<Self> {
+        fs::create_dir_all(parent)?;
+        let dir = parent.join(format!("scan-{}", Uuid::new_v4()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        Ok(Self { dir, next: 0 })
+    }
+
+    fn file(&mut self) -> PathBuf {
+        self.next += 1;
+        self.dir.join(self.next.to_string())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!("Failed to remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// One archive being walked, with the limits left
+struct Walk<'a> {
+    scanner: &'a YaraScanner,
+    config: &'a ArchiveConfig,
+    sandbox: Sandbox,
+    extracted_bytes: u64,
+    entries: usize,
+    /// A limit was hit; the rest of the archive is left unscanned
+    stopped: bool,
+    matches: Vec<ArchiveMatch>,
+}
+
+impl Walk<'_> {
+    /// Scan the entries of the archive at `path`, at nesting `depth`
+    fn archive(&mut self, path: &Path, prefix: &str, depth: u32) -> io::Result<()> {
+        match sniff(path)? {
+            None => {}
+            Some(Format::Zip) => {
+                let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+                for index in 0..zip.len() {
+                    if self.stopped {
+                        break;
+                    }
+                    let entry = match zip.by_index(index) {
+                        Ok(entry) => entry,
+                        Err(e) => {
+                            debug!("Skipping zip entry {} of {}: {}", index, prefix, e);
+                            continue;
+                        }
+                    };
+                    if entry.is_file() {
+                        let name = join(prefix, entry.name());
+                        self.entry(name, entry, depth)?;
+                    }
+                }
+            }
+            Some(Format::Tar) => {
+                let mut tar = tar::Archive::new(File::open(path)?);
+                for entry in tar.entries()? {
+                    if self.stopped {
+                        break;
+                    }
+                    let entry = entry?;
+                    if entry.header().entry_type().is_file() {
+                        let name = join(prefix, &entry.path()?.to_string_lossy());
+                        self.entry(name, entry, depth)?;
+                    }
+                }
+            }
+            Some(Format::Gzip) => {
+                // A single compressed file, often a tar
+                let archive_name = match prefix.rsplit(ENTRY_SEPARATOR).next() {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                };
+                let name = join(prefix, &gunzipped_name(&archive_name));
+                self.entry(name, GzDecoder::new(File::open(path)?), depth)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Extract one entry, scan it and whatever it contains, then delete it
+    fn entry(&mut self, name: String, reader: impl Read, depth: u32) -> io::Result<()> {
+        self.entries += 1;
+        if self.entries > self.config.max_entries {
+            return self.stop("entries");
+        }
+
+        let path = self.sandbox.file();
+        let mut out = File::create(&path)?;
+        let written = io::copy(&mut reader.take(self.config.max_entry_bytes + 1), &mut out);
+        drop(out);
+        let written = match written {
+            Ok(written) => written,
+            Err(e) => {
+                debug!("Skipping {}: {}", name, e);
+                return fs::remove_file(&path);
+            }
+        };
+        self.extracted_bytes += written;
+        if written > self.config.max_entry_bytes {
+            debug!("Skipping {}: larger than max_entry_bytes", name);
+            fs::remove_file(&path)?;
+        } else {
+            let rules = self.scanner.scan_file(&path.to_string_lossy());
+            if !rules.is_empty() {
+                self.matches.push(ArchiveMatch {
+                    entry: name.clone(),
+                    rules,
+                });
+            }
+            if depth < self.config.max_depth {
+                if let Err(e) = self.archive(&path, &name, depth + 1) {
+                    debug!("Skipping nested archive {}: {}", name, e);
+                }
+            }
+            fs::remove_file(&path)?;
+        }
+
+        if self.extracted_bytes > self.config.max_total_bytes {
+            return self.stop("bytes");
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self, limit: &str) -> io::Result<()> {
+        if !self.stopped {
+            warn!("Archive scan stopped early: too many extracted {}", limit);
+            self.stopped = true;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the file inside a gzip archive called `name`
+fn gunzipped_name(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix(".tgz") {
+        format!("{}.tar", stem)
+    } else {
+        match name.strip_suffix(".gz") {
+            Some(stem) if !stem.is_empty() => stem.to_string(),
+            _ => "data".to_string(),
+        }
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}{}{}", prefix, ENTRY_SEPARATOR, name)
+    }
+}
+
+/// Entries of the archive at `path` that match the YARA rules; empty when
+/// it is not an archive
+pub fn scan(scanner: &YaraScanner, path: &Path, config: &ArchiveConfig) -> Vec<ArchiveMatch> {
+    match sniff(path) {
+        Ok(Some(_)) => {}
+        _ => return Vec::new(),
+    }
+    let sandbox = match Sandbox::create(&config.sandbox_dir) {
+        Ok(sandbox) => sandbox,
+        Err(e) => {
+            warn!(
+                "Cannot scan inside {}: sandbox {}: {}",
+                path.display(),
+                config.sandbox_dir.display(),
+                e
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut walk = Walk {
+        scanner,
+        config,
+        sandbox,
+        extracted_bytes: 0,
+        entries: 0,
+        stopped: false,
+        matches: Vec::new(),
+    };
+    if let Err(e) = walk.archive(path, "", 1) {
+        warn!("Failed to read archive {}: {}", path.display(), e);
+    }
+    walk.matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const EICAR: &[u8] = br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+    fn tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Default::default()));
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_entry_type(tar::EntryType::Regular);
+            // Written raw so names the builder would refuse, like `..`, survive
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            tar.append(&header, *data).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_nested_archives() {
+        let dir = std::env::temp_dir().join(format!("guardian-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = ArchiveConfig {
+            enabled: true,
+            max_entry_bytes: 4096,
+            sandbox_dir: dir.join("sandbox"),
+            ..ArchiveConfig::default()
+        };
+        let scanner = YaraScanner::new().unwrap();
+
+        // EICAR two levels down, once under a traversal name, and once in
+        // an entry too big to extract
+        let inner = tar_gz(&[("docs/readme.txt", b"hello"), ("../../evil.com", EICAR)]);
+        let mut big = EICAR.to_vec();
+        big.resize(8192, b' ');
+        let outer = zip(&[
+            ("backup.tar.gz", &inner),
+            ("eicar.com", EICAR),
+            ("big.com", &big),
+        ]);
+        let path = dir.join("upload.bin");
+        fs::write(&path, outer).unwrap();
+
+        let matches = scan(&scanner, &path, &config);
+        let entries: Vec<_> = matches.iter().map(|m| m.entry.as_str()).collect();
+        // The uncompressed tar matches as a whole as well as its entry
+        assert_eq!(
+            entries,
+            [
+                "backup.tar.gz!backup.tar",
+                "backup.tar.gz!backup.tar!../../evil.com",
+                "eicar.com"
+            ]
+        );
+        assert_eq!(matches[0].rules, ["eicar_test_file"]);
+        // Nothing escaped or was left behind
+        assert!(!dir.join("evil.com").exists());
+        assert_eq!(fs::read_dir(&config.sandbox_dir).unwrap().count(), 0);
+
+        // One level only reaches the zip's own entries
+        let shallow = ArchiveConfig {
+            max_depth: 1,
+            ..config.clone()
+        };
+        let matches = scan(&scanner, &path, &shallow);
+        assert_eq!(matches.len(), 1);
+
+        // Plain files are not archives
+        fs::write(&path, EICAR).unwrap();
+        assert!(scan(&scanner, &path, &config).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
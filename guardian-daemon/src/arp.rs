@@ -0,0 +1,162 @@
+//! ARP spoofing detection. The host's ARP table is polled and each IPv4
+//! neighbour's MAC address remembered; when a known address starts
+//! resolving to a different MAC, something on the segment may be answering
+//! for it, typically the default gateway so traffic passes through the
+//! attacker.
+
+use crate::config::ArpConfig;
+use crate::platform;
+use crate::queue::EventSender;
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Tag on ARP binding change alerts
+pub const ARP_TAG: &str = "arp_spoofing";
+
+/// Rule name on ARP binding change alerts
+pub const ARP_RULE: &str = "arp_binding_changed";
+
+/// MAC address last seen for each neighbour
+#[derive(Default)]
+pub struct ArpWatch {
+    bindings: HashMap<IpAddr, String>,
+    primed: bool,
+}
+
+impl ArpWatch {
+    /// Compare a snapshot of the ARP table against the known bindings,
+    /// returning an alert for each address whose MAC changed. The first
+    /// snapshot only records the bindings.
+    pub fn observe(
+        &mut self,
+        table: &[(IpAddr, String)],
+        gateways: &[IpAddr],
+        gateway_only: bool,
+        hostname: &str,
+    ) -> Vec<LogEvent> {
+        let mut alerts = Vec::new();
+        for (ip, mac) in table {
+            let gateway = gateways.contains(ip);
+            let Some(old) = self.bindings.insert(*ip, mac.clone()) else {
+                continue;
+            };
+            if !self.primed || old == *mac || (gateway_only && !gateway) {
+                continue;
+            }
+
+            // A spoofer usually keeps its own address as well
+            let mut shared: Vec<String> = table
+                .iter()
+                .filter(|(other, other_mac)| other != ip && other_mac == mac)
+                .map(|(other, _)| other.to_string())
+                .collect();
+            shared.sort();
+            let mut description = format!("{} moved from {} to {}", ip, old, mac);
+            if !shared.is_empty() {
+                description.push_str(&format!("; {} also answers for {}", mac, shared.join(", ")));
+            }
+
+            warn!("ARP binding change: {}", description);
+            let title = if gateway {
+                format!("Default gateway {} changed MAC address", ip)
+            } else {
+                format!("ARP binding changed for {}", ip)
+            };
+            let mut alert = LogEvent::new(
+                Severity::High,
+                EventType::Alert {
+                    title,
+                    description,
+                    related_event_ids: Vec::new(),
+                    technique: Some("T1557.002".to_string()),
+                },
+                hostname.to_string(),
+            )
+            .with_tag(ARP_TAG)
+            .with_rule(ARP_RULE);
+            if gateway {
+                alert = alert.with_tag("gateway");
+            }
+            alerts.push(alert);
+        }
+        self.primed = true;
+        alerts
+    }
+}
+
+/// Poll the ARP table every `poll_secs` until the event channel closes
+pub fn run(config: ArpConfig, tx: EventSender, hostname: String) {
+    let mut watch = ArpWatch::default();
+    let interval = Duration::from_secs(config.poll_secs.max(1));
+    info!("Watching the ARP table every {}s", interval.as_secs());
+    loop {
+        let table = match platform::arp_table() {
+            Ok(table) => table,
+            Err(e) => {
+                warn!("ARP table unavailable, not watching it: {}", e);
+                return;
+            }
+        };
+        let gateways = platform::default_gateways();
+        for alert in watch.observe(&table, &gateways, config.gateway_only, &hostname) {
+            if tx.blocking_send(alert).is_err() {
+                return;
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str, mac: &str) -> (IpAddr, String) {
+        (ip.parse().unwrap(), mac.to_string())
+    }
+
+    #[test]
+    fn test_binding_changes() {
+        let gateway: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut watch = ArpWatch::default();
+        let table = [
+            entry("10.0.0.1", "00:11:22:33:44:55"),
+            entry("10.0.0.23", "66:77:88:99:aa:bb"),
+        ];
+        assert!(watch.observe(&table, &[gateway], false, "web-1").is_empty());
+        assert!(watch.observe(&table, &[gateway], false, "web-1").is_empty());
+
+        // The host at .23 now answers for the gateway as well
+        let spoofed = [
+            entry("10.0.0.1", "66:77:88:99:aa:bb"),
+            entry("10.0.0.23", "66:77:88:99:aa:bb"),
+        ];
+        let alerts = watch.observe(&spoofed, &[gateway], false, "web-1");
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, Severity::High);
+        assert!(alerts[0].tags.contains(&"gateway".to_string()));
+        match &alerts[0].event_type {
+            EventType::Alert {
+                title, description, ..
+            } => {
+                assert_eq!(title, "Default gateway 10.0.0.1 changed MAC address");
+                assert_eq!(
+                    description,
+                    "10.0.0.1 moved from 00:11:22:33:44:55 to 66:77:88:99:aa:bb; \
+                     66:77:88:99:aa:bb also answers for 10.0.0.23"
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Only the gateway is reported when limited to it
+        let moved = [
+            entry("10.0.0.1", "66:77:88:99:aa:bb"),
+            entry("10.0.0.23", "de:ad:be:ef:00:01"),
+        ];
+        assert!(watch.observe(&moved, &[gateway], true, "web-1").is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config::AuthLogConfig;
+use crate::queue::EventSender;
+use crate::tail;
+use guardian_common::{AuthOutcome, EventType, GuardianError, LogEvent};
+
+/// Programs whose log lines carry authentication activity
+const PROGRAMS: &[&str] = &["sshd", "sudo", "su"];
+
+/// Split a syslog line into program name and message
+fn split_syslog(line: &str) -> Option<(&str, &str)> {
+    PROGRAMS.iter().find_map(|program| {
+        [format!(" {}[", program), format!(" {}:", program)]
+            .iter()
+            .find_map(|marker| line.find(marker.as_str()))
+            .and_then(|start| {
+                let rest = &line[start + 1..];
+                let (_, message) = rest.split_once(": ")?;
+                Some((*program, message.trim()))
+            })
+    })
+}
+
+fn auth(
+    user: &str,
+    source_ip: Option<&str>,
+    method: &str,
+    outcome: AuthOutcome,
+    tty: Option<&str>,
+) -> EventType {
+    EventType::AuthEvent {
+        user: user.to_string(),
+        source_ip: source_ip.map(str::to_string),
+        method: method.to_string(),
+        outcome,
+        tty: tty.map(str::to_string),
+    }
+}
+
+/// `Accepted publickey for alice from 203.0.113.5 port 52000 ssh2`
+/// `Failed password for invalid user bob from 198.51.100.2 port 4711 ssh2`
+fn parse_sshd(message: &str) -> Option<EventType> {
+    let (outcome, rest) = if let Some(rest) = message.strip_prefix("Accepted ") {
+        (AuthOutcome::Success, rest)
+    } else if let Some(rest) = message.strip_prefix("Failed ") {
+        (AuthOutcome::Failure, rest)
+    } else {
+        return None;
+    };
+
+    let (method, rest) = rest.split_once(" for ")?;
+    let rest = rest.strip_prefix("invalid user ").unwrap_or(rest);
+    let (user, rest) = rest.rsplit_once(" from ")?;
+    let source_ip = rest.split_whitespace().next();
+    Some(auth(user, source_ip, method, outcome, None))
+}
+
+/// `alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/bin/ls`
+/// `alice : 3 incorrect password attempts ; TTY=pts/0 ; ...`
+fn parse_sudo(message: &str) -> Option<EventType> {
+    let (user, rest) = message.split_once(" : ")?;
+    let fields: Vec<&str> = rest.split(" ; ").map(str::trim).collect();
+    let tty = fields.iter().find_map(|f| f.strip_prefix("TTY="));
+    let outcome = if fields.first()?.starts_with("TTY=") {
+        AuthOutcome::Success
+    } else if rest.contains("incorrect password") || rest.contains("NOT in sudoers") {
+        AuthOutcome::Failure
+    } else {
+        return None;
+    };
+    Some(auth(user.trim(), None, "sudo", outcome, tty))
+}
+
+/// `(to root) alice on pts/1` / `FAILED SU (to root) alice on pts/1`
+fn parse_su(message: &str) -> Option<EventType> {
+    let (outcome, rest) = match message.strip_prefix("FAILED SU ") {
+        Some(rest) => (AuthOutcome::Failure, rest),
+        None => (AuthOutcome::Success, message),
+    };
+    let rest = rest.strip_prefix("(to ")?;
+    let (_, rest) = rest.split_once(") ")?;
+    let (user, tty) = rest.split_once(" on ")?;
+    Some(auth(user, None, "su", outcome, Some(tty)))
+}
+
+/// Parse one auth log line into an event type, if it records an authentication attempt
+pub fn parse_line(line: &str) -> Option<EventType> {
+    let (program, message) = split_syslog(line)?;
+    match program {
+        "sshd" => parse_sshd(message),
+        "sudo" => parse_sudo(message),
+        "su" => parse_su(message),
+        _ => None,
+    }
+}
+
+/// Wrap a parsed attempt in an event; failures are LOW, successes INFO
+pub fn to_event(event_type: EventType, hostname: &str) -> Result<LogEvent, GuardianError> {
+    LogEvent::builder(event_type, hostname)
+        .tag("auth_log")
+        .build()
+}
+
+/// Tail the configured auth logs and emit an event per authentication attempt
+pub async fn run(config: AuthLogConfig, tx: EventSender, hostname: String) {
+    if !config.enabled {
+        return;
+    }
+
+    tail::follow(&config.paths, config.poll_ms, tx, |line| {
+        parse_line(line).and_then(|event_type| to_event(event_type, &hostname).ok())
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_auth_lines() {
+        assert_eq!(
+            parse_line("Oct 17 03:31:10 bastion sshd[1234]: Accepted publickey for alice from 203.0.113.5 port 52000 ssh2: ED25519 SHA256:abc"),
+            Some(auth("alice", Some("203.0.113.5"), "publickey", AuthOutcome::Success, None))
+        );
+        assert_eq!(
+            parse_line("2026-10-17T03:31:10.123+00:00 bastion sshd[99]: Failed password for invalid user admin from 198.51.100.2 port 4711 ssh2"),
+            Some(auth("admin", Some("198.51.100.2"), "password", AuthOutcome::Failure, None))
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:32:00 bastion sudo:    alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/systemctl restart nginx"),
+            Some(auth("alice", None, "sudo", AuthOutcome::Success, Some("pts/0")))
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:32:30 bastion sudo:      bob : 3 incorrect password attempts ; TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/bin/sh"),
+            Some(auth("bob", None, "sudo", AuthOutcome::Failure, Some("pts/1")))
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:33:00 bastion su[2001]: FAILED SU (to root) bob on pts/1"),
+            Some(auth("bob", None, "su", AuthOutcome::Failure, Some("pts/1")))
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:33:05 bastion sshd[1234]: pam_unix(sshd:session): session opened for user alice"),
+            None
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:33:05 bastion cron[5]: (root) CMD (run-parts)"),
+            None
+        );
+    }
+}
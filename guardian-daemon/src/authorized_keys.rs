@@ -0,0 +1,306 @@
+//! SSH authorized keys monitoring. Appending a key to an account's
+//! `authorized_keys` is the quickest way to keep access to a machine, and
+//! the file rarely changes otherwise. Every account's key files are read
+//! every `poll_secs`, each key fingerprinted the way `ssh-keygen -l` does,
+//! and the keys compared with the last read; keys added or removed are
+//! listed in one alert per file.
+
+use crate::config::AuthorizedKeysConfig;
+use crate::platform;
+use crate::queue::EventSender;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use guardian_common::{EventType, LogEvent, Severity};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Tag on authorized keys alerts
+pub const KEYS_TAG: &str = "authorized_keys";
+
+/// Rule name on keys added to an authorized keys file
+pub const KEYS_RULE: &str = "ssh_key_added";
+
+/// Key files read under each home directory
+const KEY_FILES: &[&str] = &[".ssh/authorized_keys", ".ssh/authorized_keys2"];
+
+/// One public key line of an authorized keys file
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizedKey {
+    /// e.g. `ssh-ed25519`
+    pub key_type: String,
+    /// `SHA256:` and the unpadded base64 digest of the key blob
+    pub fingerprint: String,
+    pub comment: Option<String>,
+    /// Restrictions before the key, e.g. `command="..."` or `from="..."`
+    pub options: Option<String>,
+}
+
+/// Key files by path, with the account they belong to and their keys
+pub type Snapshot = BTreeMap<PathBuf, (String, Vec<AuthorizedKey>)>;
+
+/// Whether `token` names a public key algorithm
+fn is_key_type(token: &str) -> bool {
+    ["ssh-", "ecdsa-sha2-", "sk-ssh-", "sk-ecdsa-sha2-"]
+        .iter()
+        .any(|prefix| token.starts_with(prefix))
+}
+
+/// Split on whitespace outside double quotes, as option values may hold
+/// spaces
+fn tokens(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if let Some(from) = start.take() {
+                    tokens.push(&line[from..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(from) = start {
+        tokens.push(&line[from..]);
+    }
+    tokens
+}
+
+/// Parse one line, skipping comments and lines sshd would not accept
+fn parse_key(line: &str) -> Option<AuthorizedKey> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let tokens = tokens(line);
+    let at = tokens.iter().position(|token| is_key_type(token))?;
+    let blob = STANDARD.decode(tokens.get(at + 1)?).ok()?;
+    let join = |tokens: &[&str]| (!tokens.is_empty()).then(|| tokens.join(" "));
+    Some(AuthorizedKey {
+        key_type: tokens[at].to_string(),
+        fingerprint: format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(&blob))),
+        comment: join(&tokens[at + 2..]),
+        options: join(&tokens[..at]),
+    })
+}
+
+/// Every key in the contents of an authorized keys file
+pub fn parse_keys(contents: &str) -> Vec<AuthorizedKey> {
+    contents.lines().filter_map(parse_key).collect()
+}
+
+/// Read the key files of every account with a home directory, plus `paths`
+/// attributed to whoever owns them
+pub fn read(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    let mut read_file = |path: PathBuf, user: String| {
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            snapshot.insert(path, (user, parse_keys(&contents)));
+        }
+    };
+    for (user, home) in platform::user_homes() {
+        for file in KEY_FILES {
+            read_file(home.join(file), user.clone());
+        }
+    }
+    for path in paths {
+        let owner = crate::users::file_owner(path)
+            .and_then(|owner| owner.username)
+            .unwrap_or_default();
+        read_file(path.clone(), owner);
+    }
+    snapshot
+}
+
+fn describe(sign: char, key: &AuthorizedKey) -> String {
+    let mut line = format!("{} {} {}", sign, key.key_type, key.fingerprint);
+    if let Some(comment) = &key.comment {
+        line.push_str(&format!(" {}", comment));
+    }
+    if let Some(options) = &key.options {
+        line.push_str(&format!(" [{}]", options));
+    }
+    line
+}
+
+/// Alert for one file whose keys differ between reads: high severity when
+/// keys were added, low when they were only removed
+fn alert(
+    path: &Path,
+    user: &str,
+    old: &[AuthorizedKey],
+    new: &[AuthorizedKey],
+    hostname: &str,
+) -> Option<LogEvent> {
+    let added: Vec<&AuthorizedKey> = new.iter().filter(|key| !old.contains(key)).collect();
+    let removed: Vec<&AuthorizedKey> = old.iter().filter(|key| !new.contains(key)).collect();
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+
+    let account = if user.is_empty() { "unknown" } else { user };
+    let (severity, title) = if added.is_empty() {
+        (Severity::Low, format!("SSH key removed for {}", account))
+    } else {
+        (Severity::High, format!("SSH key added for {}", account))
+    };
+    let mut description = format!(
+        "{}: {} key(s) added, {} removed",
+        path.display(),
+        added.len(),
+        removed.len()
+    );
+    for key in &added {
+        description.push('\n');
+        description.push_str(&describe('+', key));
+    }
+    for key in &removed {
+        description.push('\n');
+        description.push_str(&describe('-', key));
+    }
+
+    let mut event = LogEvent::new(
+        severity,
+        EventType::Alert {
+            title,
+            description,
+            related_event_ids: Vec::new(),
+            technique: Some("T1098.004".to_string()),
+        },
+        hostname.to_string(),
+    )
+    .with_tag(KEYS_TAG);
+    if !user.is_empty() {
+        event = event.with_tag(format!("user:{}", user));
+    }
+    for key in &added {
+        event = event.with_tag(format!("ssh_key:{}", key.fingerprint));
+    }
+    Some(event)
+}
+
+/// Alerts for every key file that differs between two snapshots; a file
+/// that appears or disappears counts as having had no keys
+pub fn compare(old: &Snapshot, new: &Snapshot, hostname: &str) -> Vec<LogEvent> {
+    let mut paths: Vec<&PathBuf> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (user, _) = new.get(path).or_else(|| old.get(path))?;
+            alert(path, user, keys(old, path), keys(new, path), hostname)
+        })
+        .collect()
+}
+
+fn keys<'a>(snapshot: &'a Snapshot, path: &Path) -> &'a [AuthorizedKey] {
+    snapshot
+        .get(path)
+        .map_or(&[][..], |(_, keys)| keys.as_slice())
+}
+
+/// Read the key files every `poll_secs` until the event channel closes.
+/// The first read only records the keys.
+pub fn run(config: AuthorizedKeysConfig, tx: EventSender, hostname: String) {
+    let interval = std::time::Duration::from_secs(config.poll_secs.max(1));
+    let mut known = read(&config.paths);
+    info!(
+        "Watching {} authorized keys file(s) every {}s",
+        known.len(),
+        interval.as_secs()
+    );
+    loop {
+        std::thread::sleep(interval);
+        let current = read(&config.paths);
+        for event in compare(&known, &current, &hostname) {
+            if let EventType::Alert { title, .. } = &event.event_type {
+                warn!("{}", title);
+            }
+            if tx.blocking_send(event).is_err() {
+                return;
+            }
+        }
+        known = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGV7D1WUR8Pd7GIf6DOwqrwKD+GcBwfaZQOXZ3UvAi1m";
+
+    #[test]
+    fn test_parse_keys() {
+        let contents = format!(
+            "# deploy keys\n\
+             {} alice@laptop\n\
+             from=\"10.0.0.0/8\",command=\"/usr/bin/backup --run now\" {} backup\n\
+             ssh-rsa not-base64!\n\
+             \n",
+            ED25519, ED25519
+        );
+        let keys = parse_keys(&contents);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].key_type, "ssh-ed25519");
+        assert_eq!(
+            keys[0].fingerprint,
+            "SHA256:voV1T/LWg6eZR04VRGK+w4fTciOD6oIR6PfYlBilOD0"
+        );
+        assert_eq!(keys[0].comment.as_deref(), Some("alice@laptop"));
+        assert_eq!(keys[0].options, None);
+        assert_eq!(keys[1].fingerprint, keys[0].fingerprint);
+        assert_eq!(
+            keys[1].options.as_deref(),
+            Some("from=\"10.0.0.0/8\",command=\"/usr/bin/backup --run now\"")
+        );
+    }
+
+    #[test]
+    fn test_added_and_removed_keys() {
+        let path = PathBuf::from("/home/bob/.ssh/authorized_keys");
+        let laptop = parse_keys(&format!("{} bob@laptop", ED25519));
+        let old = Snapshot::from([(path.clone(), ("bob".to_string(), laptop.clone()))]);
+        assert!(compare(&old, &old, "web-1").is_empty());
+
+        let mut keys = laptop.clone();
+        keys.extend(parse_keys(&format!("{} root@kali", ED25519)));
+        let new = Snapshot::from([(path.clone(), ("bob".to_string(), keys))]);
+        let events = compare(&old, &new, "web-1");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].severity, Severity::High);
+        assert!(events[0].tags.contains(&"user:bob".to_string()));
+        assert!(events[0]
+            .tags
+            .contains(&format!("ssh_key:{}", laptop[0].fingerprint)));
+        match &events[0].event_type {
+            EventType::Alert {
+                title, description, ..
+            } => {
+                assert_eq!(title, "SSH key added for bob");
+                assert_eq!(
+                    description,
+                    &format!(
+                        "/home/bob/.ssh/authorized_keys: 1 key(s) added, 0 removed\n\
+                         + ssh-ed25519 {} root@kali",
+                        laptop[0].fingerprint
+                    )
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Deleting the file removes its keys
+        let events = compare(&old, &Snapshot::new(), "web-1");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].severity, Severity::Low);
+    }
+}
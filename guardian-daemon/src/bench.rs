@@ -0,0 +1,171 @@
+//! `bench`: events per second through each pipeline stage, measured on
+//! generated events and printed as one JSON object so runs before and after
+//! a change can be compared. The database stage writes a scratch SQLite
+//! file with the console's schema and settings, in transactions of `batch`.
+
+use crate::demo::Generator;
+use crate::rules::RuleEngine;
+use crate::threatintel::ThreatIntel;
+use anyhow::{Context, Result};
+use guardian_common::store::EventRow;
+use guardian_common::LogEvent;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const DEFAULT_EVENTS: usize = 100_000;
+pub const DEFAULT_BATCH: usize = 500;
+
+/// Fixed so every run measures the same mix of events
+const SEED: u64 = 0x6775_6172_6469_616e;
+
+/// Throughput of one stage
+#[derive(Debug, Serialize)]
+pub struct Stage {
+    pub name: &'static str,
+    pub seconds: f64,
+    pub events_per_sec: f64,
+}
+
+/// Time `f` over `events` events
+fn measure(name: &'static str, events: usize, f: impl FnOnce()) -> Stage {
+    let started = Instant::now();
+    f();
+    stage(name, events, started)
+}
+
+fn stage(name: &'static str, events: usize, started: Instant) -> Stage {
+    let seconds = started.elapsed().as_secs_f64();
+    Stage {
+        name,
+        seconds,
+        events_per_sec: events as f64 / seconds.max(f64::EPSILON),
+    }
+}
+
+/// Scratch database with the console's schema, removed when dropped
+struct ScratchDb {
+    path: PathBuf,
+    pool: SqlitePool,
+}
+
+impl ScratchDb {
+    async fn create(path: PathBuf) -> Result<Self> {
+        // Same journal and sync settings the console and bridge use
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        sqlx::migrate!("../guardian-sentinel/src-tauri/migrations")
+            .run(&pool)
+            .await?;
+        Ok(Self { path, pool })
+    }
+}
+
+impl Drop for ScratchDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Store `events` as the bridge does, committing every `batch` events
+async fn insert_batched(pool: &SqlitePool, events: &[LogEvent], batch: usize) -> Result<()> {
+    for chunk in events.chunks(batch.max(1)) {
+        let mut tx = pool.begin().await?;
+        for event in chunk {
+            EventRow::new(event)?.insert(&mut tx).await?;
+        }
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+/// Run every stage over `count` events
+pub async fn run(count: usize, batch: usize, db_dir: &Path) -> Result<Vec<Stage>> {
+    let mut generator = Generator::new(SEED);
+    let mut events = Vec::with_capacity(count);
+    let generate = measure("generate", count, || {
+        events.extend((0..count).map(|_| generator.next_event()));
+    });
+
+    let mut lines = Vec::with_capacity(count);
+    let serialize = measure("serialize", count, || {
+        lines.extend(events.iter().filter_map(|event| event.to_json().ok()));
+    });
+    let parse = measure("parse", count, || {
+        for line in &lines {
+            let _ = std::hint::black_box(LogEvent::from_json_any_version(line));
+        }
+    });
+
+    let rules = RuleEngine::new();
+    let threat_intel = ThreatIntel::default();
+    let evaluate = measure("rules", count, || {
+        for event in &mut events {
+            threat_intel.enrich(event);
+            let matched = rules.evaluate(event);
+            if let Some(rule) = matched.first() {
+                event.rule_triggered = true;
+                event.rule_name = Some(rule.clone());
+                event.rules_matched = matched;
+            }
+        }
+    });
+
+    let db =
+        ScratchDb::create(db_dir.join(format!("guardian-bench-{}.db", std::process::id()))).await?;
+    let started = Instant::now();
+    insert_batched(&db.pool, &events, batch).await?;
+    let insert = stage("db_insert", count, started);
+
+    Ok(vec![generate, serialize, parse, evaluate, insert])
+}
+
+/// Print the stages of a run as JSON
+pub async fn run_command(count: usize, batch: usize) -> Result<()> {
+    let stages = run(count, batch, &std::env::temp_dir()).await?;
+    let result = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "events": count,
+        "batch": batch,
+        "stages": stages,
+    });
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stages() {
+        let dir = std::env::temp_dir().join(format!("guardian-bench-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let stages = run(200, 64, &dir).await.unwrap();
+        let names: Vec<_> = stages.iter().map(|stage| stage.name).collect();
+        assert_eq!(
+            names,
+            ["generate", "serialize", "parse", "rules", "db_insert"]
+        );
+        assert!(stages.iter().all(|stage| stage.events_per_sec > 0.0));
+        // The scratch database is gone once the run ends
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::DaemonConfig;
+use crate::platform;
+use guardian_common::ipc::Encoding;
+use guardian_common::{AgentHello, Capabilities, SCHEMA_VERSION};
+
+fn names(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+/// Describe what this agent runs with `config`, for the hello line sent on startup
+pub fn hello(
+    config: &DaemonConfig,
+    hostname: &str,
+    yara: bool,
+    snapshots: bool,
+    nft: bool,
+) -> AgentHello {
+    let mut collectors = names(&["file_monitor", "system_monitor"]);
+    let mut event_types = names(&["file_integrity", "process_monitor", "system_log"]);
+    if yara {
+        collectors.push("yara".to_string());
+    }
+    if config.auth_log.enabled && platform::LOG_FILE_COLLECTORS {
+        collectors.push("auth_log".to_string());
+        event_types.push("auth_event".to_string());
+    }
+    if config.packages.enabled && platform::LOG_FILE_COLLECTORS {
+        collectors.push("packages".to_string());
+        event_types.push("package_change".to_string());
+    }
+    if config.syslog.enabled {
+        collectors.push("syslog".to_string());
+    }
+    if config.fluent.enabled {
+        collectors.push("fluent".to_string());
+    }
+    if config.arp.enabled {
+        collectors.push("arp".to_string());
+    }
+    if cfg!(target_os = "linux") && config.hidden_processes.enabled {
+        collectors.push("hidden_processes".to_string());
+    }
+    if cfg!(unix) && config.suid.enabled {
+        collectors.push("suid".to_string());
+    }
+    if config.listening_ports.enabled {
+        collectors.push("listening_ports".to_string());
+    }
+    if config.resolver.enabled {
+        collectors.push("resolver".to_string());
+    }
+    if config.authorized_keys.enabled {
+        collectors.push("authorized_keys".to_string());
+    }
+    if config.netflow.enabled {
+        collectors.push("netflow".to_string());
+    }
+    if config.honeypot.enabled {
+        collectors.push("honeypot".to_string());
+    }
+    if cfg!(feature = "pcap") && config.capture.enabled {
+        collectors.push("pcap".to_string());
+    }
+    if collectors.iter().any(|c| {
+        matches!(
+            c.as_str(),
+            "listening_ports" | "netflow" | "honeypot" | "pcap"
+        )
+    }) {
+        event_types.push("network_socket".to_string());
+    }
+    if config.heartbeat.enabled {
+        event_types.push("heartbeat".to_string());
+    }
+
+    let mut sinks = names(&["socket"]);
+    if config.ipc.stdout {
+        sinks.push("stdout".to_string());
+    }
+    if config.forward.enabled {
+        sinks.push("forward".to_string());
+    }
+    if !config.siem.is_empty() {
+        sinks.push("siem".to_string());
+    }
+    if config.elasticsearch.enabled {
+        sinks.push("elasticsearch".to_string());
+    }
+    if config.splunk.enabled {
+        sinks.push("splunk".to_string());
+    }
+    if config.loki.enabled {
+        sinks.push("loki".to_string());
+    }
+    if config.gelf.enabled {
+        sinks.push("gelf".to_string());
+    }
+    if cfg!(feature = "grpc") && config.grpc.enabled {
+        sinks.push("grpc".to_string());
+    }
+
+    let mut commands = names(&["set_sampling", "report_rule_stats", "reload_config"]);
+    if yara {
+        commands.push("scan_path".to_string());
+    }
+    if snapshots {
+        commands.push("restore_file".to_string());
+    }
+    if nft {
+        commands.extend(names(&["isolate_host", "release_host"]));
+    }
+
+    AgentHello {
+        hostname: hostname.to_string(),
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: SCHEMA_VERSION,
+        capabilities: Capabilities {
+            collectors,
+            sinks,
+            event_types,
+            commands,
+            encodings: Encoding::supported(),
+        },
+        signature: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hello_follows_config() {
+        let mut config = DaemonConfig::default();
+        config.packages.enabled = false;
+
+        let minimal = hello(&config, "web-1", false, false, false);
+        let caps = &minimal.capabilities;
+        assert!(caps.emits("auth_event"));
+        assert!(!caps.emits("package_change"));
+        assert!(!caps.collectors.contains(&"yara".to_string()));
+        assert!(caps.supports_command("set_sampling"));
+        assert!(caps.supports_command("report_rule_stats"));
+        assert!(caps.supports_command("reload_config"));
+        assert!(!caps.supports_command("isolate_host"));
+        assert!(!caps.supports_command("scan_path"));
+        assert!(!caps.supports_command("restore_file"));
+        assert_eq!(caps.encodings, vec!["cbor", "json"]);
+
+        let full = hello(&config, "web-1", true, true, true);
+        assert!(full.capabilities.supports_command("isolate_host"));
+        assert!(full.capabilities.supports_command("scan_path"));
+        assert!(full.capabilities.supports_command("restore_file"));
+        assert!(full.capabilities.collectors.contains(&"yara".to_string()));
+    }
+}
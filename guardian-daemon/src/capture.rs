@@ -0,0 +1,75 @@
+//! Packet capture collector, built with the `pcap` feature. Where polling
+//! the socket table is too coarse, libpcap sees every connection however
+//! short-lived, with its byte counts and the server names in TLS
+//! ClientHellos and HTTP requests. Needs libpcap and `CAP_NET_RAW`.
+
+use crate::config::CaptureConfig;
+use crate::queue::EventSender;
+use crate::traffic::{self, Flows};
+use anyhow::{anyhow, Context, Result};
+use pcap::{Capture, Device};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Enough of each packet for its headers and the start of a ClientHello
+const SNAPLEN: i32 = 2048;
+
+/// Longest wait for packets before checking whether a flush is due
+const READ_TIMEOUT_MS: i32 = 1000;
+
+/// The configured interface, or libpcap's default
+fn device(config: &CaptureConfig) -> Result<Device> {
+    match &config.interface {
+        Some(name) => Device::list()
+            .context("Failed to list capture interfaces")?
+            .into_iter()
+            .find(|device| device.name == *name)
+            .ok_or_else(|| anyhow!("No capture interface named {}", name)),
+        None => Device::lookup()
+            .context("Failed to find a capture interface")?
+            .ok_or_else(|| anyhow!("No capture interface available")),
+    }
+}
+
+/// Capture until the event channel closes, reporting each connection's
+/// traffic every `flush_secs`
+pub fn run(config: CaptureConfig, tx: EventSender, hostname: String) -> Result<()> {
+    let device = device(&config)?;
+    let name = device.name.clone();
+    let mut flows = Flows::new(device.addresses.iter().map(|address| address.addr));
+    let mut capture = Capture::from_device(device)?
+        .promisc(false)
+        .snaplen(SNAPLEN)
+        .timeout(READ_TIMEOUT_MS)
+        .immediate_mode(true)
+        .open()
+        .with_context(|| format!("Failed to capture on {}", name))?;
+    capture
+        .filter(&config.filter, true)
+        .with_context(|| format!("Invalid capture filter {:?}", config.filter))?;
+    let linktype = capture.get_datalink().0;
+    info!("Capturing traffic on {} ({})", name, config.filter);
+
+    let interval = Duration::from_secs(config.flush_secs.max(1));
+    let mut last_flush = Instant::now();
+    loop {
+        match capture.next_packet() {
+            Ok(packet) => {
+                if let Some(parsed) = traffic::parse(linktype, packet.data) {
+                    flows.add(&parsed, u64::from(packet.header.len));
+                }
+            }
+            Err(pcap::Error::TimeoutExpired) => {}
+            Err(e) => return Err(e).with_context(|| format!("Capture on {} failed", name)),
+        }
+
+        if last_flush.elapsed() >= interval {
+            last_flush = Instant::now();
+            for event in flows.flush(&hostname) {
+                if tx.blocking_send(event).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
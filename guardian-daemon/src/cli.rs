@@ -0,0 +1,652 @@
+//! Command line: `run` (the default) starts the agent; the other commands do
+//! one job and exit, non-zero when it fails.
+
+use crate::config::{DaemonConfig, GelfTransport, SiemTransport};
+use crate::file_monitor::WatchRegistry;
+use crate::rules::RuleEngine;
+use crate::scanner::YaraScanner;
+use crate::{
+    bench, demo, enroll, import, platform, policy, provision, replay, rule_pack, yara_sources,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use guardian_common::{CompiledRule, LogEvent};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long `check-config --strict` waits for each connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "guardian-daemon",
+    version,
+    about = "Guardian host monitoring agent"
+)]
+pub struct Cli {
+    /// Run on generated events from imaginary hosts instead of this machine
+    #[arg(long)]
+    pub demo: bool,
+
+    /// Generated events per second with `--demo`
+    #[arg(long, requires = "demo", default_value_t = demo::DEFAULT_RATE)]
+    pub demo_rate: u32,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the agent (the default)
+    Run,
+    /// Validate the config, watch paths, watchlists and rules
+    CheckConfig {
+        /// Also check that the sinks, collector and feeds accept connections
+        #[arg(long)]
+        strict: bool,
+        /// Print a JSON Schema of the config file for editors instead
+        #[arg(long, conflicts_with = "strict")]
+        schema: bool,
+    },
+    /// Evaluate the rules against events in an NDJSON file without emitting them
+    TestRules {
+        /// One JSON event per line, as the daemon writes them
+        events: PathBuf,
+    },
+    /// Alert on stored events (NDJSON, `-` for stdin) the current rules and
+    /// threat-intel lists flag but were not flagged when recorded
+    Replay { events: PathBuf },
+    /// Scan a file or directory with the YARA rules and print matches as JSON lines
+    Scan { path: PathBuf },
+    /// Measure events per second through serialization, rules and database
+    /// inserts, printed as JSON
+    Bench {
+        #[arg(long, default_value_t = bench::DEFAULT_EVENTS)]
+        events: usize,
+        /// Events per database transaction
+        #[arg(long, default_value_t = bench::DEFAULT_BATCH)]
+        batch: usize,
+    },
+    /// Print the effective config and watchlists as a provisioning file
+    ExportConfig,
+    /// Write the config and watchlists from a provisioning file
+    ApplyConfig { file: PathBuf },
+    /// Print the configured rules and YARA files as a rule pack
+    ExportRules {
+        /// Name recorded in the pack's manifest
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+        /// Sign the pack with a key written by `generate-rule-key`
+        #[arg(long)]
+        sign: Option<PathBuf>,
+    },
+    /// Write a new Ed25519 key for signing rule packs and print its public key
+    GenerateRuleKey { file: PathBuf },
+    /// Sign a YARA file served as a remote source, writing `<file>.sig`
+    SignYara {
+        file: PathBuf,
+        /// Key written by `generate-rule-key`
+        #[arg(long)]
+        key: PathBuf,
+    },
+    /// Install the rules and YARA files of a rule pack, showing conflicts
+    ImportRules {
+        pack: PathBuf,
+        /// Show what would change without writing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Overwrite configured rules and YARA files of the same name
+        #[arg(long)]
+        replace: bool,
+        /// Accept rules that kill processes or isolate the host
+        #[arg(long)]
+        allow_responses: bool,
+    },
+    /// Replace the config with a YAML policy, showing the changes
+    Apply {
+        policy: PathBuf,
+        /// Show the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print login and package history as events
+    Import {
+        #[arg(long, default_value_t = import::DEFAULT_DAYS)]
+        days: i64,
+    },
+    /// Trade a console token for a client certificate for `[forward]`
+    Enroll {
+        #[arg(long)]
+        token: String,
+        /// Directory for the key and certificate; defaults to the config's
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Install the daemon as a system service and start it
+    InstallService {
+        /// Only register the service
+        #[arg(long)]
+        no_start: bool,
+    },
+    /// Stop and remove the system service
+    UninstallService,
+    /// Manage the system service
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ServiceAction {
+    /// Same as `install-service`
+    Install {
+        #[arg(long)]
+        no_start: bool,
+    },
+    /// Same as `uninstall-service`
+    Uninstall,
+    /// Entry point for the Windows Service Control Manager
+    Run,
+}
+
+/// Run a command other than `run`
+pub fn execute(command: Command) -> Result<()> {
+    match command {
+        Command::Run | Command::Bench { .. } => bail!("{:?} runs on the async runtime", command),
+        Command::CheckConfig { schema: true, .. } => print_schema(),
+        Command::CheckConfig { strict, .. } => check_config(strict),
+        Command::TestRules { events } => test_rules(&events),
+        Command::Replay { events } => replay::run_command(&events),
+        Command::Scan { path } => scan(&path),
+        Command::ExportConfig => provision::export_command(),
+        Command::ApplyConfig { file } => provision::apply_command(&file),
+        Command::ExportRules {
+            name,
+            description,
+            sign,
+        } => rule_pack::export_command(&name, description, sign.as_deref()),
+        Command::GenerateRuleKey { file } => rule_pack::generate_key_command(&file),
+        Command::SignYara { file, key } => rule_pack::sign_file_command(&file, &key),
+        Command::ImportRules {
+            pack,
+            dry_run,
+            replace,
+            allow_responses,
+        } => rule_pack::import_command(&pack, dry_run, replace, allow_responses),
+        Command::Apply { policy, dry_run } => policy::apply_command(&policy, dry_run),
+        Command::Import { days } => import::run_command(days),
+        Command::Enroll { token, out } => enroll::run_command(&token, out),
+        Command::InstallService { no_start }
+        | Command::Service {
+            action: ServiceAction::Install { no_start },
+        } => platform::install_service(!no_start),
+        Command::UninstallService
+        | Command::Service {
+            action: ServiceAction::Uninstall,
+        } => platform::uninstall_service(),
+        Command::Service {
+            action: ServiceAction::Run,
+        } => platform::run_service(),
+    }
+}
+
+/// Problems that would stop the agent or make it miss events
+fn config_problems(config: &DaemonConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = WatchRegistry::new(config.watch.clone()) {
+        problems.push(format!("{:#}", e));
+    }
+    for watch in &config.watch {
+        if let Some(problem) = watch_path_problem(&watch.path) {
+            problems.push(format!("Watch path {}: {}", watch.path.display(), problem));
+        }
+    }
+    if config.forward.enabled {
+        let forward = &config.forward;
+        for file in [&forward.ca_file, &forward.cert_file, &forward.key_file]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(e) = std::fs::metadata(file) {
+                problems.push(format!("Forward TLS file {}: {}", file.display(), e));
+            }
+        }
+    }
+    let lists = config
+        .threat_intel
+        .hash_lists
+        .iter()
+        .chain(&config.threat_intel.ip_lists);
+    for list in lists {
+        if let Err(e) = std::fs::metadata(list) {
+            problems.push(format!("Watchlist {}: {}", list.display(), e));
+        }
+    }
+    if let Err(e) = YaraScanner::load(&config.yara) {
+        problems.push(format!("YARA rules: {:#}", e));
+    }
+    for source in &config.yara.sources {
+        if let Err(e) = yara_sources::validate(source) {
+            problems.push(e);
+        }
+    }
+    for rule in &config.rules {
+        if let Err(e) = CompiledRule::new(rule.clone()) {
+            problems.push(e.to_string());
+        }
+    }
+    if let Err(e) = config.signing.signer() {
+        problems.push(format!("Event signing key: {}", e));
+    }
+    for key in &config.rule_packs.trusted_keys {
+        if !STANDARD.decode(key.trim()).is_ok_and(|k| k.len() == 32) {
+            problems.push(format!(
+                "Trusted rule pack key {} is not a base64 Ed25519 key",
+                key
+            ));
+        }
+    }
+    problems
+}
+
+/// Why the daemon could not watch `path`. Missing directories are created
+/// at startup, so those only need an existing directory above them.
+fn watch_path_problem(path: &Path) -> Option<String> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    if !existing.is_dir() {
+        Some(format!("{} is not a directory", existing.display()))
+    } else {
+        None
+    }
+}
+
+/// A host the agent connects to, and whether over TCP rather than as
+/// datagrams, which can only be checked by resolving the name
+#[derive(Debug, PartialEq)]
+struct Endpoint {
+    name: String,
+    address: String,
+    tcp: bool,
+}
+
+/// `host:port` of an HTTP(S) URL, with the scheme's port when none is given
+fn url_address(url: &str) -> Result<String> {
+    let url = reqwest::Url::parse(url).with_context(|| format!("{} is not a URL", url))?;
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().context("URL has no port")?;
+    Ok(format!("{}:{}", host, port))
+}
+
+/// Everything enabled in the config that sends events or pulls content
+/// over the network
+fn endpoints(config: &DaemonConfig) -> Result<Vec<Endpoint>> {
+    let mut endpoints = Vec::new();
+    let mut push = |name: String, address: String, tcp: bool| {
+        endpoints.push(Endpoint { name, address, tcp });
+    };
+    if config.forward.enabled {
+        push(
+            "Collector".to_string(),
+            config.forward.address.clone(),
+            true,
+        );
+    }
+    if config.elasticsearch.enabled {
+        push(
+            "Elasticsearch".to_string(),
+            url_address(&config.elasticsearch.url)?,
+            true,
+        );
+    }
+    if config.splunk.enabled {
+        push(
+            "Splunk HEC".to_string(),
+            url_address(&config.splunk.url)?,
+            true,
+        );
+    }
+    if config.loki.enabled {
+        push("Loki".to_string(), url_address(&config.loki.url)?, true);
+    }
+    if config.gelf.enabled {
+        let tcp = config.gelf.transport == GelfTransport::Tcp;
+        push("GELF".to_string(), config.gelf.address.clone(), tcp);
+    }
+    for siem in &config.siem {
+        if let Some(address) = &siem.address {
+            let tcp = siem.transport == SiemTransport::Tcp;
+            push(format!("SIEM {:?}", siem.format), address.clone(), tcp);
+        }
+    }
+    if let Some(misp) = &config.threat_intel.misp {
+        push("MISP".to_string(), url_address(&misp.url)?, true);
+    }
+    for taxii in &config.threat_intel.taxii {
+        push(
+            format!("TAXII {}", taxii.name),
+            url_address(&taxii.api_root)?,
+            true,
+        );
+    }
+    for source in &config.yara.sources {
+        // Only HTTP(S) repositories; other git transports are left to git
+        let url = source.url.as_ref().or(source.git.as_ref());
+        if let Some(url) = url.filter(|url| url.starts_with("http")) {
+            push(
+                format!("YARA source {}", source.name),
+                url_address(url)?,
+                true,
+            );
+        }
+    }
+    Ok(endpoints)
+}
+
+/// Resolve the endpoint and, for TCP, connect to one of its addresses
+fn check_endpoint(endpoint: &Endpoint) -> Result<()> {
+    let addrs: Vec<_> = endpoint
+        .address
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", endpoint.address))?
+        .collect();
+    if !endpoint.tcp {
+        return Ok(());
+    }
+    let mut error = anyhow!("{} has no addresses", endpoint.address);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => error = anyhow!("Failed to connect to {}: {}", addr, e),
+        }
+    }
+    Err(error)
+}
+
+/// Endpoints that cannot be reached from here
+fn unreachable_endpoints(config: &DaemonConfig) -> Vec<String> {
+    let endpoints = match endpoints(config) {
+        Ok(endpoints) => endpoints,
+        Err(e) => return vec![format!("{:#}", e)],
+    };
+    endpoints
+        .iter()
+        .filter_map(|endpoint| {
+            check_endpoint(endpoint)
+                .err()
+                .map(|e| format!("{} at {}: {:#}", endpoint.name, endpoint.address, e))
+        })
+        .collect()
+}
+
+/// JSON Schema of the config file; the doc comments become descriptions
+fn print_schema() -> Result<()> {
+    let schema = schemars::schema_for!(DaemonConfig);
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+fn check_config(strict: bool) -> Result<()> {
+    let path = DaemonConfig::path();
+    let config = DaemonConfig::load().with_context(|| format!("{} is invalid", path.display()))?;
+
+    let mut problems = config_problems(&config);
+    if strict {
+        problems.extend(unreachable_endpoints(&config));
+    }
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    if !problems.is_empty() {
+        bail!("{} problem(s) found", problems.len());
+    }
+
+    let rules = RuleEngine::with_definitions(&config.rules);
+    println!(
+        "Config OK: {} watch path(s), {} rule(s) ({})",
+        config.watch.len(),
+        rules.rule_names().len(),
+        rules.rule_names().join(", ")
+    );
+    Ok(())
+}
+
+/// Result line for one event in `test-rules`; `rule` is the most severe match
+fn rule_result(line: usize, event: &LogEvent, rules: &[String]) -> serde_json::Value {
+    json!({
+        "line": line,
+        "id": event.id,
+        "event_type": event.event_type.kind(),
+        "severity": event.severity,
+        "rule": rules.first(),
+        "rules": rules,
+    })
+}
+
+fn test_rules(events: &Path) -> Result<()> {
+    let file = std::fs::File::open(events)
+        .with_context(|| format!("Failed to read {}", events.display()))?;
+    let rules = RuleEngine::with_definitions(&DaemonConfig::load()?.rules);
+
+    let mut matches: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut total, mut matched, mut invalid) = (0, 0, 0);
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = match LogEvent::from_json_any_version(&line) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("line {}: {}", index + 1, e);
+                invalid += 1;
+                continue;
+            }
+        };
+        total += 1;
+        let hits = rules.evaluate(&event);
+        if !hits.is_empty() {
+            matched += 1;
+        }
+        for rule in &hits {
+            *matches.entry(rule.clone()).or_default() += 1;
+        }
+        println!("{}", rule_result(index + 1, &event, &hits));
+    }
+
+    eprintln!("{} event(s), {} matched", total, matched);
+    for (rule, count) in &matches {
+        eprintln!("  {}: {}", rule, count);
+    }
+    if invalid > 0 {
+        bail!("{} line(s) could not be parsed", invalid);
+    }
+    Ok(())
+}
+
+/// Regular files under `path`, not following symlinks
+fn files_under(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_file() {
+        files.push(path.to_path_buf());
+    } else if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if let Err(e) = files_under(&entry.path(), files) {
+                eprintln!("{}: {}", entry.path().display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scan(path: &Path) -> Result<()> {
+    let scanner = YaraScanner::load(&DaemonConfig::load()?.yara)?;
+    let mut files = Vec::new();
+    files_under(path, &mut files).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut matched = 0;
+    for file in &files {
+        let rules = scanner.scan_file(&file.to_string_lossy());
+        if rules.is_empty() {
+            continue;
+        }
+        matched += 1;
+        println!("{}", json!({ "path": file, "rules": rules }));
+    }
+    eprintln!("Scanned {} file(s), {} matched", files.len(), matched);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatchConfig;
+    use guardian_common::{EventType, FileOperation, Severity};
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::try_parse_from(["guardian-daemon"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["guardian-daemon", "apply", "p.yaml", "--dry-run"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Apply { dry_run: true, .. })
+        ));
+        let cli = Cli::try_parse_from(["guardian-daemon", "import-rules", "p.toml", "--replace"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::ImportRules {
+                dry_run: false,
+                replace: true,
+                ..
+            })
+        ));
+        assert!(Cli::try_parse_from(["guardian-daemon", "export-rules"]).is_err());
+        let cli = Cli::try_parse_from(["guardian-daemon", "check-config", "--schema"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CheckConfig {
+                schema: true,
+                strict: false
+            })
+        ));
+        assert!(
+            Cli::try_parse_from(["guardian-daemon", "check-config", "--schema", "--strict"])
+                .is_err()
+        );
+        let cli = Cli::try_parse_from(["guardian-daemon", "import"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Import { days: 30 })));
+        assert!(Cli::try_parse_from(["guardian-daemon", "enroll"]).is_err());
+        let cli = Cli::try_parse_from(["guardian-daemon", "replay", "-"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(Command::Replay { events }) if events == Path::new("-"))
+        );
+        assert!(Cli::try_parse_from(["guardian-daemon", "bogus"]).is_err());
+
+        let cli = Cli::try_parse_from(["guardian-daemon", "--demo", "--demo-rate", "500"]).unwrap();
+        assert!(cli.demo && cli.demo_rate == 500 && cli.command.is_none());
+        assert!(Cli::try_parse_from(["guardian-daemon", "--demo-rate", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_rule_results() {
+        let event = LogEvent::new(
+            Severity::Medium,
+            EventType::FileIntegrity {
+                path: "/etc/shadow".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        );
+        let rules = RuleEngine::new();
+        let hits = rules.evaluate(&event);
+        let result = rule_result(3, &event, &hits);
+        assert_eq!(result["line"], 3);
+        assert_eq!(result["event_type"], "file_integrity");
+        assert_eq!(result["rule"], "critical_file_modification");
+        assert_eq!(result["rules"][0], "critical_file_modification");
+
+        let mut config = DaemonConfig::default();
+        config
+            .threat_intel
+            .ip_lists
+            .push("/nonexistent/ips.txt".into());
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("/nonexistent/ips.txt"));
+
+        config.rules.push(guardian_common::RuleDefinition {
+            name: "everything".to_string(),
+            ..Default::default()
+        });
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[1].contains("everything"));
+    }
+
+    #[test]
+    fn test_strict_endpoints() {
+        let mut config = DaemonConfig::default();
+        assert!(endpoints(&config).unwrap().is_empty());
+
+        config.splunk.enabled = true;
+        config.splunk.url = "https://splunk.example.com/services/collector".to_string();
+        config.gelf.enabled = true;
+        config.gelf.address = "graylog.example.com:12201".to_string();
+        let found = endpoints(&config).unwrap();
+        assert_eq!(found[0].address, "splunk.example.com:443");
+        assert_eq!(
+            found[1],
+            Endpoint {
+                name: "GELF".to_string(),
+                address: "graylog.example.com:12201".to_string(),
+                tcp: false,
+            }
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut endpoint = Endpoint {
+            name: "Collector".to_string(),
+            address: listener.local_addr().unwrap().to_string(),
+            tcp: true,
+        };
+        assert!(check_endpoint(&endpoint).is_ok());
+        drop(listener);
+        assert!(check_endpoint(&endpoint).is_err());
+        // Datagram endpoints only need to resolve
+        endpoint.tcp = false;
+        assert!(check_endpoint(&endpoint).is_ok());
+    }
+
+    #[test]
+    fn test_watch_paths_must_be_directories() {
+        let mut config = DaemonConfig::default();
+        config.watch.push(WatchConfig::new("/nonexistent/www"));
+        assert!(config_problems(&config).is_empty());
+
+        let file = std::env::temp_dir().join(format!("guardian-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "").unwrap();
+        config.watch.push(WatchConfig::new(file.join("uploads")));
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("is not a directory"));
+        std::fs::remove_file(&file).unwrap();
+    }
+}
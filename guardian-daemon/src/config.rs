@@ -0,0 +1,1740 @@
+use crate::platform::{self, DEFAULT_CONFIG_PATH, DEFAULT_SOCKET, DEFAULT_WATCH_PATH};
+use guardian_common::error::Result;
+use guardian_common::signing::EventSigner;
+use guardian_common::{GuardianError, RuleDefinition, Severity};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Daemon configuration loaded from TOML
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Directories monitored for file integrity events
+    pub watch: Vec<WatchConfig>,
+
+    /// Detection rules written as data, evaluated with the built-in ones
+    pub rules: Vec<RuleDefinition>,
+
+    /// Daily summary of the rules that matched most
+    pub rule_stats: RuleStatsConfig,
+
+    /// Hourly stats rollups shipped to the console
+    pub rollup: RollupConfig,
+
+    /// Local hash and IP blocklists
+    pub threat_intel: ThreatIntelConfig,
+
+    /// Reverse-DNS enrichment of remote addresses
+    pub rdns: RdnsConfig,
+
+    /// Authentication log collection
+    pub auth_log: AuthLogConfig,
+
+    /// Package manager log collection
+    pub packages: PackageLogConfig,
+
+    /// Syslog receiver for network devices and other hosts
+    pub syslog: SyslogConfig,
+
+    /// Fluentd forward protocol input for fluent-bit and Fluentd agents
+    pub fluent: FluentConfig,
+
+    /// NetFlow and IPFIX records from routers
+    pub netflow: NetflowConfig,
+
+    /// Decoy listeners that report every connection
+    pub honeypot: HoneypotConfig,
+
+    /// Spotting remote addresses probing many local ports
+    pub port_scan: PortScanConfig,
+
+    /// Alerting when a neighbour's MAC address changes
+    pub arp: ArpConfig,
+
+    /// Looking for processes hidden from the `/proc` listing
+    pub hidden_processes: HiddenProcessConfig,
+
+    /// Inventory of setuid and setgid binaries
+    pub suid: SuidConfig,
+
+    /// Reporting sockets that start or stop listening
+    pub listening_ports: ListeningPortConfig,
+
+    /// Watching the hosts file and resolver config for tampering
+    pub resolver: ResolverConfig,
+
+    /// Reporting SSH keys added to or removed from authorized keys files
+    pub authorized_keys: AuthorizedKeysConfig,
+
+    /// Packet capture for per-connection traffic; needs the `pcap` feature
+    pub capture: CaptureConfig,
+
+    /// How events reach the console
+    pub ipc: IpcConfig,
+
+    /// HMAC signatures on emitted events
+    pub signing: SigningConfig,
+
+    /// Optional gRPC API
+    pub grpc: GrpcConfig,
+
+    /// Shipping events to a remote collector
+    pub forward: ForwardConfig,
+
+    /// Periodic heartbeat events
+    pub heartbeat: HeartbeatConfig,
+
+    /// Scanning inside archives the file monitor sees change
+    pub archives: ArchiveConfig,
+
+    /// YARA rules compiled alongside the built-in ones
+    pub yara: YaraConfig,
+
+    /// Signature checks on imported rule packs
+    pub rule_packs: RulePackConfig,
+
+    /// Flagging executables that look packed or encrypted
+    pub entropy: EntropyConfig,
+
+    /// Diffs of what changed in selected config files
+    pub diff: DiffConfig,
+
+    /// Earlier versions of the diffed files, kept so they can be restored
+    pub snapshots: SnapshotConfig,
+
+    /// Spotting files being encrypted in bulk
+    pub ransomware: RansomwareConfig,
+
+    /// Decoy files that nothing legitimate should touch
+    pub canaries: CanaryConfig,
+
+    /// Queue between the collectors and the pipeline
+    pub queue: QueueConfig,
+
+    /// CEF or LEEF copies of events for SIEMs
+    pub siem: Vec<SiemConfig>,
+
+    /// ECS documents bulk-indexed into Elasticsearch or OpenSearch
+    pub elasticsearch: ElasticConfig,
+
+    /// Events batched to a Splunk HTTP Event Collector
+    pub splunk: SplunkConfig,
+
+    /// Log lines pushed to Grafana Loki
+    pub loki: LokiConfig,
+
+    /// GELF messages for Graylog
+    pub gelf: GelfConfig,
+}
+
+/// A single monitored directory and its settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    pub path: PathBuf,
+
+    /// Watch subdirectories as well
+    #[serde(default = "default_true")]
+    pub recursive: bool,
+
+    /// Glob patterns, relative to `path`, whose events are ignored
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Minimum severity for events under this path
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+
+    /// Run the YARA scanner when files here are created or modified
+    #[serde(default = "default_true")]
+    pub scan_on_change: bool,
+}
+
+/// Settings for the noisy-rule summary
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleStatsConfig {
+    pub enabled: bool,
+
+    /// Hours between summaries
+    pub interval_hours: u64,
+
+    /// Number of rules named in each summary
+    pub top: usize,
+}
+
+impl Default for RuleStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: 24,
+            top: 5,
+        }
+    }
+}
+
+/// Settings for pre-aggregated stats rollups
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RollupConfig {
+    pub enabled: bool,
+
+    /// Ship rollups alongside raw events, or instead of raw Info events
+    pub mode: RollupMode,
+
+    /// Number of most frequent file paths included per rollup
+    pub top_paths: usize,
+}
+
+impl Default for RollupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: RollupMode::Alongside,
+            top_paths: 10,
+        }
+    }
+}
+
+/// How rollups relate to the raw event stream
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupMode {
+    /// Every event is still emitted individually
+    Alongside,
+    /// Info events that triggered no rule are only counted in rollups
+    Instead,
+}
+
+/// Reverse-DNS lookup and cache settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RdnsConfig {
+    pub enabled: bool,
+
+    /// Seconds a resolved name is cached
+    pub ttl_secs: u64,
+
+    /// Seconds a failed lookup is cached
+    pub negative_ttl_secs: u64,
+
+    /// Maximum number of cached addresses
+    pub max_entries: usize,
+
+    /// Lookups running at once; further misses go unresolved rather than queueing
+    pub max_concurrent: usize,
+
+    /// How long an event waits for an uncached lookup before moving on
+    pub timeout_ms: u64,
+}
+
+impl Default for RdnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 3600,
+            negative_ttl_secs: 300,
+            max_entries: 10_000,
+            max_concurrent: 8,
+            timeout_ms: 250,
+        }
+    }
+}
+
+/// Receiver turning RFC 3164 and RFC 5424 syslog messages into events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyslogConfig {
+    pub enabled: bool,
+
+    /// Address to listen on; port 514 needs root or CAP_NET_BIND_SERVICE
+    pub listen: String,
+
+    /// Accept datagrams
+    pub udp: bool,
+
+    /// Accept TCP connections, newline-delimited or octet-counted
+    pub tcp: bool,
+
+    /// Longest message accepted; longer TCP frames close the connection
+    pub max_message: usize,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "0.0.0.0:514".to_string(),
+            udp: true,
+            tcp: true,
+            max_message: 8192,
+        }
+    }
+}
+
+/// Collector turning NetFlow v5, v9 and IPFIX records into events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetflowConfig {
+    pub enabled: bool,
+
+    /// UDP address exporters send to
+    pub listen: String,
+
+    /// Addresses flows are taken from; required to listen beyond loopback
+    pub exporters: Vec<IpAddr>,
+}
+
+impl Default for NetflowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:2055".to_string(),
+            exporters: Vec::new(),
+        }
+    }
+}
+
+/// Decoy TCP listeners on ports nothing here serves
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct HoneypotConfig {
+    pub enabled: bool,
+
+    /// Address the decoys listen on
+    pub address: IpAddr,
+
+    /// Ports to listen on; those below 1024 need root or CAP_NET_BIND_SERVICE
+    pub ports: Vec<u16>,
+
+    /// Seconds before another connection from the same peer to the same
+    /// port is reported again
+    pub repeat_secs: u64,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: IpAddr::from([0, 0, 0, 0]),
+            ports: vec![23, 3389],
+            repeat_secs: 60,
+        }
+    }
+}
+
+/// Alerting on a remote address that connects to many local ports
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortScanConfig {
+    pub enabled: bool,
+
+    /// Seconds of connections considered together
+    pub window_secs: u64,
+
+    /// Distinct local ports one address must reach within the window
+    pub min_ports: usize,
+
+    /// First port of the range the host uses for outgoing connections,
+    /// which are not counted
+    pub ephemeral_start: u16,
+}
+
+impl Default for PortScanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 60,
+            min_ports: 10,
+            ephemeral_start: 32768,
+        }
+    }
+}
+
+/// Polling the ARP table for addresses that change MAC address
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArpConfig {
+    pub enabled: bool,
+
+    /// Seconds between reads of the ARP table
+    pub poll_secs: u64,
+
+    /// Only alert on the default gateway, for networks where addresses
+    /// change hands often
+    pub gateway_only: bool,
+}
+
+impl Default for ArpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_secs: 10,
+            gateway_only: false,
+        }
+    }
+}
+
+/// Periodic check for processes a rootkit hides; Linux only
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct HiddenProcessConfig {
+    pub enabled: bool,
+
+    /// Seconds between checks, each of which probes every possible PID
+    pub interval_secs: u64,
+}
+
+impl Default for HiddenProcessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 300,
+        }
+    }
+}
+
+/// Periodic inventory of setuid and setgid binaries; Unix only
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SuidConfig {
+    pub enabled: bool,
+
+    /// Seconds between scans
+    pub interval_secs: u64,
+
+    /// Directories searched, each without crossing into other filesystems
+    pub roots: Vec<PathBuf>,
+
+    /// Where the binaries found are recorded between scans and restarts
+    pub inventory: PathBuf,
+}
+
+impl Default for SuidConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 3600,
+            roots: platform::SUID_ROOTS.iter().map(PathBuf::from).collect(),
+            inventory: PathBuf::from(platform::DEFAULT_SUID_INVENTORY),
+        }
+    }
+}
+
+/// Inventory of listening sockets, diffed on every poll
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListeningPortConfig {
+    pub enabled: bool,
+
+    /// Seconds between reads of the socket tables
+    pub poll_secs: u64,
+
+    /// Include bound UDP sockets, which also covers some clients waiting
+    /// for replies
+    pub udp: bool,
+}
+
+impl Default for ListeningPortConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_secs: 30,
+            udp: true,
+        }
+    }
+}
+
+/// Hosts file and DNS resolver config, diffed on every poll
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResolverConfig {
+    pub enabled: bool,
+
+    /// Seconds between reads of the files
+    pub poll_secs: u64,
+
+    /// Files watched; a directory covers the files directly inside it
+    pub paths: Vec<PathBuf>,
+
+    /// Changed lines quoted in an alert before the rest are only counted
+    pub max_diff_lines: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_secs: 10,
+            paths: platform::RESOLVER_PATHS.iter().map(PathBuf::from).collect(),
+            max_diff_lines: 40,
+        }
+    }
+}
+
+/// Every account's SSH authorized keys, fingerprinted and diffed on every
+/// poll
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthorizedKeysConfig {
+    pub enabled: bool,
+
+    /// Seconds between reads of the key files
+    pub poll_secs: u64,
+
+    /// Key files read besides `.ssh/authorized_keys` and
+    /// `.ssh/authorized_keys2` under each home directory
+    pub paths: Vec<PathBuf>,
+}
+
+impl Default for AuthorizedKeysConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_secs: 30,
+            paths: platform::AUTHORIZED_KEYS_FILES
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        }
+    }
+}
+
+/// Per-connection traffic from captured packets; collected only by builds
+/// with the `pcap` feature
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub enabled: bool,
+
+    /// Interface to capture on; defaults to libpcap's first usable device
+    pub interface: Option<String>,
+
+    /// BPF filter applied in the kernel
+    pub filter: String,
+
+    /// Seconds between traffic reports for each connection
+    pub flush_secs: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interface: None,
+            filter: "tcp or udp".to_string(),
+            flush_secs: 10,
+        }
+    }
+}
+
+/// Fluentd forward protocol input
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct FluentConfig {
+    pub enabled: bool,
+
+    /// Address to listen on
+    pub listen: String,
+
+    /// Largest message accepted, after decompression
+    pub max_message: usize,
+
+    /// Key senders must prove with Fluent's handshake; required to listen
+    /// beyond loopback or to take `guardian` routes
+    pub shared_key: Option<String>,
+
+    /// How records are turned into events, by tag; the first match wins and
+    /// unmatched records become `SystemLog` events
+    pub routes: Vec<FluentRoute>,
+}
+
+impl Default for FluentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:24224".to_string(),
+            max_message: 8 * 1024 * 1024,
+            shared_key: None,
+            routes: Vec::new(),
+        }
+    }
+}
+
+/// Event type for records with matching tags
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FluentRoute {
+    /// Tag pattern; `*` matches one dot-separated part and `**` any number
+    pub tag: String,
+
+    pub event_type: FluentEventType,
+
+    /// Record field holding the log line; `log`, then `message`, when unset
+    #[serde(default)]
+    pub message_key: Option<String>,
+
+    /// Severity of `system_log` events
+    #[serde(default = "default_fluent_severity")]
+    pub severity: Severity,
+}
+
+/// How a record becomes an event
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FluentEventType {
+    /// The log line as a `SystemLog` event
+    SystemLog,
+    /// sshd, sudo and su lines parsed like the auth log; others are dropped
+    AuthEvent,
+    /// dpkg, dnf/yum and pacman lines parsed like the package logs; others are dropped
+    PackageChange,
+    /// The record is a Guardian event, e.g. another daemon's stdout; only
+    /// with a `shared_key`
+    Guardian,
+}
+
+fn default_fluent_severity() -> Severity {
+    Severity::Info
+}
+
+/// Syslog files followed for SSH, sudo and su authentication attempts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthLogConfig {
+    pub enabled: bool,
+
+    /// Files to follow; missing ones are skipped (Debian uses auth.log, RHEL secure).
+    /// Not collected on Windows.
+    pub paths: Vec<PathBuf>,
+
+    /// Milliseconds between checks for new lines
+    pub poll_ms: u64,
+}
+
+impl Default for AuthLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            paths: platform::AUTH_LOGS.iter().map(PathBuf::from).collect(),
+            poll_ms: 1000,
+        }
+    }
+}
+
+/// Local socket the console connects to for events and control commands
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpcConfig {
+    /// Unix socket path, or named pipe name on Windows
+    pub socket: PathBuf,
+
+    /// Also write events as JSON lines on stdout, for `guardian-bridge` and other pipes
+    pub stdout: bool,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from(DEFAULT_SOCKET),
+            stdout: true,
+        }
+    }
+}
+
+/// Key for signing every emitted event, so consumers holding the same key can
+/// drop events injected into the socket or pipe. Events are unsigned when no
+/// key is set here or in `GUARDIAN_EVENT_KEY`/`GUARDIAN_EVENT_KEY_COMMAND`,
+/// which take precedence.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    /// Shared secret, at least 16 bytes
+    pub key: Option<String>,
+
+    /// Shell command printing the secret, e.g. an OS keychain lookup; used
+    /// when `key` is unset
+    pub key_command: Option<String>,
+}
+
+impl SigningConfig {
+    /// Signer for emitted events, or `None` to leave them unsigned
+    pub fn signer(&self) -> Result<Option<EventSigner>> {
+        if let Some(signer) = EventSigner::from_env()? {
+            return Ok(Some(signer));
+        }
+        EventSigner::resolve(
+            self.key.clone(),
+            self.key_command.clone(),
+            "signing.key_command",
+        )
+    }
+}
+
+/// gRPC API for third-party consumers; served only by builds with the `grpc` feature
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+
+    /// Address to serve on
+    pub listen: String,
+
+    /// SHA-256 of the bearer token clients send, in hex; the API is not
+    /// served without one
+    pub token_sha256: Option<String>,
+
+    /// Directories `TriggerScan` may scan in; scans are refused when empty
+    pub scan_roots: Vec<PathBuf>,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:50051".to_string(),
+            token_sha256: None,
+            scan_roots: Vec::new(),
+        }
+    }
+}
+
+/// Remote collector that receives a copy of every emitted event over TLS
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwardConfig {
+    pub enabled: bool,
+
+    /// Collector `host:port`
+    pub address: String,
+
+    /// Name the collector's certificate must carry; defaults to the host in `address`
+    pub server_name: Option<String>,
+
+    /// PEM file of CA certificates to trust instead of the public roots
+    pub ca_file: Option<PathBuf>,
+
+    /// PEM client certificate and key from `guardian-daemon enroll`, for
+    /// collectors that require mutual TLS
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+
+    /// Frames held while the collector is unreachable or has not acknowledged
+    /// them; the oldest are dropped beyond this
+    pub buffer: usize,
+}
+
+impl Default for ForwardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::new(),
+            server_name: None,
+            ca_file: None,
+            cert_file: None,
+            key_file: None,
+            buffer: 100_000,
+        }
+    }
+}
+
+/// Elasticsearch or OpenSearch cluster receiving events as ECS documents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElasticConfig {
+    pub enabled: bool,
+
+    /// Cluster URL, e.g. `https://elastic.example.com:9200`
+    pub url: String,
+
+    /// Target index; `%Y`, `%m` and `%d` expand from the event timestamp
+    pub index: String,
+
+    /// Basic auth credentials
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Encoded API key, sent instead of basic auth when set
+    pub api_key: Option<String>,
+
+    /// Verify the cluster certificate
+    pub verify_tls: bool,
+
+    /// Install an index template mapping the ECS fields before indexing
+    pub install_template: bool,
+
+    /// Most documents per bulk request
+    pub batch_size: usize,
+
+    /// Milliseconds a partial batch waits for more documents
+    pub flush_ms: u64,
+
+    /// Events below this severity are not indexed
+    pub min_severity: Severity,
+
+    /// Documents held while the cluster is unreachable; the oldest are dropped beyond this
+    pub buffer: usize,
+}
+
+impl Default for ElasticConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://localhost:9200".to_string(),
+            index: "guardian-%Y.%m.%d".to_string(),
+            username: None,
+            password: None,
+            api_key: None,
+            verify_tls: true,
+            install_template: true,
+            batch_size: 500,
+            flush_ms: 5000,
+            min_severity: Severity::Info,
+            buffer: 100_000,
+        }
+    }
+}
+
+/// Splunk HTTP Event Collector receiving events as JSON
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplunkConfig {
+    pub enabled: bool,
+
+    /// HEC base URL, e.g. `https://splunk.example.com:8088`
+    pub url: String,
+
+    /// HEC token
+    pub token: String,
+
+    /// Index for events; the token's default index when unset
+    pub index: Option<String>,
+
+    /// Index per severity, overriding `index`, e.g. `CRITICAL = "security_urgent"`
+    pub severity_indexes: BTreeMap<Severity, String>,
+
+    /// Value of the HEC `source` field
+    pub source: String,
+
+    /// Sourcetype per event type, e.g. `auth_event = "linux_secure"`;
+    /// others are sent as `guardian:<event type>`
+    pub sourcetypes: BTreeMap<String, String>,
+
+    /// Events below this severity are not sent
+    pub min_severity: Severity,
+
+    /// Gzip request bodies
+    pub gzip: bool,
+
+    /// Verify the HEC certificate
+    pub verify_tls: bool,
+
+    /// Most events per request
+    pub batch_size: usize,
+
+    /// Milliseconds a partial batch waits for more events
+    pub flush_ms: u64,
+
+    /// Events held while HEC is unreachable; the oldest are dropped beyond this
+    pub buffer: usize,
+}
+
+impl Default for SplunkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            token: String::new(),
+            index: None,
+            severity_indexes: BTreeMap::new(),
+            source: "guardian".to_string(),
+            sourcetypes: BTreeMap::new(),
+            min_severity: Severity::Info,
+            gzip: true,
+            verify_tls: true,
+            batch_size: 100,
+            flush_ms: 2000,
+            buffer: 100_000,
+        }
+    }
+}
+
+/// Grafana Loki receiving events as JSON log lines
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LokiConfig {
+    pub enabled: bool,
+
+    /// Loki base URL, e.g. `http://loki.example.com:3100`
+    pub url: String,
+
+    /// Sent as `X-Scope-OrgID` to multi-tenant Loki
+    pub tenant_id: Option<String>,
+
+    /// Basic auth credentials, e.g. for Grafana Cloud
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Static labels added to every stream, besides `host`, `severity` and `event_type`
+    pub labels: BTreeMap<String, String>,
+
+    /// Events below this severity are not pushed
+    pub min_severity: Severity,
+
+    /// Verify the Loki certificate
+    pub verify_tls: bool,
+
+    /// Most events per push
+    pub batch_size: usize,
+
+    /// Milliseconds a partial batch waits for more events
+    pub flush_ms: u64,
+
+    /// Most events pushed per second, to stay under Loki ingestion limits; 0 for no limit
+    pub max_events_per_sec: u32,
+
+    /// Events held while Loki is unreachable or throttled; the oldest are dropped beyond this
+    pub buffer: usize,
+}
+
+impl Default for LokiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://localhost:3100".to_string(),
+            tenant_id: None,
+            username: None,
+            password: None,
+            labels: BTreeMap::from([("job".to_string(), "guardian".to_string())]),
+            min_severity: Severity::Info,
+            verify_tls: true,
+            batch_size: 500,
+            flush_ms: 1000,
+            max_events_per_sec: 1000,
+            buffer: 100_000,
+        }
+    }
+}
+
+/// Graylog input receiving events as GELF messages
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct GelfConfig {
+    pub enabled: bool,
+
+    pub transport: GelfTransport,
+
+    /// GELF input `host:port`
+    pub address: String,
+
+    /// Gzip UDP messages; TCP messages are never compressed
+    pub compress: bool,
+
+    /// Largest UDP datagram; longer messages are split into GELF chunks
+    pub chunk_size: usize,
+
+    /// Events below this severity are not sent
+    pub min_severity: Severity,
+
+    /// Messages held while a TCP input is unreachable; the oldest are dropped beyond this
+    pub buffer: usize,
+}
+
+/// How GELF messages reach Graylog
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GelfTransport {
+    /// Datagrams, chunked when large
+    Udp,
+    /// Null-byte delimited messages over TCP
+    Tcp,
+}
+
+impl Default for GelfConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            transport: GelfTransport::Udp,
+            address: "localhost:12201".to_string(),
+            compress: true,
+            chunk_size: 1420,
+            min_severity: Severity::Info,
+            buffer: 10_000,
+        }
+    }
+}
+
+/// A SIEM receiving events as CEF or LEEF lines
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SiemConfig {
+    pub format: SiemFormat,
+
+    pub transport: SiemTransport,
+
+    /// Receiver `host:port` for the udp and tcp transports
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// File the file transport appends to, for collectors that tail it
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Events below this severity are not sent
+    #[serde(default = "default_siem_severity")]
+    pub min_severity: Severity,
+
+    /// Lines held while the receiver is unreachable; the oldest are dropped beyond this
+    #[serde(default = "default_siem_buffer")]
+    pub buffer: usize,
+}
+
+/// Line format a SIEM parses natively
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SiemFormat {
+    /// ArcSight Common Event Format
+    Cef,
+    /// QRadar Log Event Extended Format
+    Leef,
+}
+
+/// How lines reach a SIEM
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SiemTransport {
+    /// Syslog datagrams
+    Udp,
+    /// Newline-framed syslog over TCP
+    Tcp,
+    /// Plain lines appended to a file
+    File,
+}
+
+fn default_siem_severity() -> Severity {
+    Severity::Info
+}
+
+fn default_siem_buffer() -> usize {
+    10_000
+}
+
+/// Heartbeats let consoles notice an agent that stopped reporting
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+
+    /// Seconds between heartbeats
+    pub interval_secs: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 60,
+        }
+    }
+}
+
+/// Scanning the entries of zip, tar and gzip files that are created or
+/// modified under watches with `scan_on_change`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub enabled: bool,
+
+    /// Levels of archives inside archives to open; 1 scans only the
+    /// archive's own entries
+    pub max_depth: u32,
+
+    /// Entries larger than this once extracted are skipped
+    pub max_entry_bytes: u64,
+
+    /// Extraction stops once this much has been extracted from one archive
+    pub max_total_bytes: u64,
+
+    /// Extraction stops after this many entries of one archive
+    pub max_entries: usize,
+
+    /// Directory a private sandbox is made in for each archive
+    pub sandbox_dir: PathBuf,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_depth: 3,
+            max_entry_bytes: 64 * 1024 * 1024,
+            max_total_bytes: 256 * 1024 * 1024,
+            max_entries: 10_000,
+            sandbox_dir: PathBuf::from(platform::DEFAULT_ARCHIVE_SANDBOX),
+        }
+    }
+}
+
+/// Extra YARA rules for the scanner
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct YaraConfig {
+    /// Directory whose `.yar` and `.yara` files are compiled with the
+    /// built-in rules; `import-rules` installs a rule pack's YARA files here
+    pub rules_dir: Option<PathBuf>,
+
+    /// Remote rules fetched and recompiled while the daemon runs
+    pub sources: Vec<YaraSourceConfig>,
+
+    /// Where the last good version of each remote source is kept, so a
+    /// restart compiles it without fetching
+    pub cache_dir: PathBuf,
+}
+
+impl Default for YaraConfig {
+    fn default() -> Self {
+        Self {
+            rules_dir: None,
+            sources: Vec::new(),
+            cache_dir: PathBuf::from(platform::DEFAULT_YARA_CACHE_DIR),
+        }
+    }
+}
+
+/// A YARA file served over HTTPS, or a git repository of them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct YaraSourceConfig {
+    /// Names the source in logs, events and the cache
+    pub name: String,
+
+    /// HTTPS URL of one YARA file; polled with its ETag
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Git repository whose `.yar` and `.yara` files are compiled
+    #[serde(default)]
+    pub git: Option<String>,
+
+    /// Branch or tag of `git`; the remote's default branch when unset
+    #[serde(default)]
+    pub branch: Option<String>,
+
+    /// Directory within the repository holding the rules
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Seconds between checks for a new version
+    #[serde(default = "default_feed_pull_secs")]
+    pub pull_secs: u64,
+
+    /// Verify the server certificate
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+/// Which rule packs `import-rules` accepts
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulePackConfig {
+    /// Defaults to `require` once `trusted_keys` lists a key, and to `warn`
+    /// before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<SignaturePolicy>,
+
+    /// Base64 Ed25519 public keys whose signatures are trusted, as printed
+    /// by `generate-rule-key`
+    pub trusted_keys: Vec<String>,
+}
+
+impl RulePackConfig {
+    /// The policy set, or the default for the trusted keys
+    pub fn policy(&self) -> SignaturePolicy {
+        self.signatures.unwrap_or(if self.trusted_keys.is_empty() {
+            SignaturePolicy::Warn
+        } else {
+            SignaturePolicy::Require
+        })
+    }
+}
+
+/// What happens to packs that are unsigned or signed by an untrusted key.
+/// Packs whose signature does not match their contents are refused unless
+/// checks are off.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    /// Signatures are not checked
+    Off,
+    /// The pack is imported with a warning
+    Warn,
+    /// The pack is refused
+    Require,
+}
+
+/// Flagging created or modified executables whose contents look packed or
+/// encrypted, which string-based YARA rules rarely match
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntropyConfig {
+    pub enabled: bool,
+
+    /// Entropy in bits per byte, 0 to 8, above which an executable is
+    /// tagged `high_entropy` and its severity raised a level
+    pub threshold: f32,
+}
+
+impl Default for EntropyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 7.2,
+        }
+    }
+}
+
+/// Keeping the contents of selected text config files, so the file
+/// monitor's events for them carry a unified diff of what changed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffConfig {
+    pub enabled: bool,
+
+    /// Files whose contents are kept; a directory covers the files directly
+    /// inside it. Only changes under a `[[watch]]` entry are seen.
+    pub paths: Vec<PathBuf>,
+
+    /// Larger files are not kept or diffed
+    pub max_bytes: u64,
+
+    /// Diff lines attached before the rest are only counted
+    pub max_lines: usize,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            paths: platform::DIFF_PATHS.iter().map(PathBuf::from).collect(),
+            max_bytes: 256 * 1024,
+            max_lines: 200,
+        }
+    }
+}
+
+/// Shadow copies of the files `[diff]` keeps, so the console can restore
+/// an earlier version after tampering
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+
+    /// Where versions and their index are stored
+    pub dir: PathBuf,
+
+    /// Versions kept for each file; older ones are deleted
+    pub keep: usize,
+
+    /// Let the console write a stored version back; the service unit then
+    /// makes the `[diff]` files' directories writable
+    pub restore: bool,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: PathBuf::from(platform::DEFAULT_SNAPSHOT_DIR),
+            keep: 10,
+            restore: false,
+        }
+    }
+}
+
+/// Decoy files planted where an intruder would look; touching one raises
+/// a critical alert
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct CanaryConfig {
+    pub enabled: bool,
+
+    /// Directories canaries are planted in
+    pub dirs: Vec<PathBuf>,
+
+    /// Canaries kept in each directory
+    pub per_dir: usize,
+
+    /// Where the planted files are recorded, so they are reused after a
+    /// restart and files the daemon did not plant are never mistaken for them
+    pub manifest: PathBuf,
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dirs: Vec::new(),
+            per_dir: 2,
+            manifest: PathBuf::from(platform::DEFAULT_CANARY_MANIFEST),
+        }
+    }
+}
+
+/// Bursts of files renamed or rewritten with ransomware-style extensions
+/// while the originals disappear
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct RansomwareConfig {
+    pub enabled: bool,
+
+    /// Seconds of file activity considered together
+    pub window_secs: u64,
+
+    /// Files one user must give suspicious names, and files that must be
+    /// deleted or renamed away, within the window
+    pub min_files: usize,
+
+    /// Extensions, without the dot, that encrypted files are given
+    pub extensions: Vec<String>,
+
+    /// Also treat random-looking extensions appended to an existing one,
+    /// e.g. `report.docx.x7k2q9`, as suspicious
+    pub random_extensions: bool,
+
+    /// What to do once an alert is raised
+    pub action: RansomwareAction,
+
+    /// Peers that stay reachable when `action` isolates the host; isolating
+    /// is refused at startup while this is empty
+    pub isolate_allow: Vec<String>,
+
+    /// Seconds the host stays isolated
+    pub isolate_secs: u64,
+}
+
+impl Default for RansomwareConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 60,
+            min_files: 20,
+            extensions: [
+                "locked",
+                "lock",
+                "enc",
+                "encrypted",
+                "crypt",
+                "crypted",
+                "crypto",
+                "cry",
+                "locky",
+                "wncry",
+                "wnry",
+                "cerber",
+                "zepto",
+                "ryk",
+                "ransom",
+            ]
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+            random_extensions: true,
+            action: RansomwareAction::None,
+            isolate_allow: Vec::new(),
+            isolate_secs: 3600,
+        }
+    }
+}
+
+/// Response to suspected ransomware
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RansomwareAction {
+    /// Alert only
+    None,
+    /// Kill the process suspected of the writes
+    Kill,
+    /// Cut the host off the network, as the console's isolate action does
+    Isolate,
+}
+
+/// Queue between the collectors and the pipeline, and what happens when
+/// events arrive faster than they are processed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    /// Events held in memory
+    pub capacity: usize,
+
+    /// What to do when the queue is full
+    pub policy: BackpressurePolicy,
+
+    /// Overflow file for the `spill` policy
+    pub spill_path: PathBuf,
+
+    /// Size past which spilled events are dropped instead
+    pub spill_max_bytes: u64,
+
+    /// Seconds between self-monitoring events counting drops, spills and waits
+    pub report_secs: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            policy: BackpressurePolicy::Block,
+            spill_path: PathBuf::from(platform::DEFAULT_SPILL_PATH),
+            spill_max_bytes: 256 * 1024 * 1024,
+            report_secs: 60,
+        }
+    }
+}
+
+/// Handling of events that arrive while the queue is full
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    /// Collectors wait for room; nothing is lost but sources may fall behind
+    Block,
+    /// Discard the least severe event, queued or incoming
+    DropLowest,
+    /// Write the overflow to `spill_path` and read it back in order
+    Spill,
+}
+
+/// Package manager logs followed for installs, upgrades and removals
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageLogConfig {
+    pub enabled: bool,
+
+    /// Files to follow; missing ones are skipped
+    pub paths: Vec<PathBuf>,
+
+    /// Milliseconds between checks for new lines
+    pub poll_ms: u64,
+}
+
+impl Default for PackageLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            paths: platform::PACKAGE_LOGS.iter().map(PathBuf::from).collect(),
+            poll_ms: 1000,
+        }
+    }
+}
+
+/// Local threat-intel list files
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThreatIntelConfig {
+    /// Files with one file hash per line
+    pub hash_lists: Vec<PathBuf>,
+
+    /// Files with one IP address or CIDR network per line
+    pub ip_lists: Vec<PathBuf>,
+
+    /// Seconds between list reloads; 0 disables reloading
+    pub reload_secs: u64,
+
+    /// Optional MISP instance to pull indicators from
+    pub misp: Option<MispConfig>,
+
+    /// TAXII 2.1 collections to pull STIX indicators from
+    pub taxii: Vec<TaxiiConfig>,
+}
+
+/// Connection settings for a MISP instance
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MispConfig {
+    /// Base URL, e.g. `https://misp.example.org`
+    pub url: String,
+
+    /// Automation key sent in the `Authorization` header
+    pub api_key: String,
+
+    /// Seconds between pulls
+    #[serde(default = "default_feed_pull_secs")]
+    pub pull_secs: u64,
+
+    /// Only pull attributes from events published within this window (e.g. `30d`)
+    #[serde(default)]
+    pub last: Option<String>,
+
+    /// Verify the server certificate
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+impl Default for ThreatIntelConfig {
+    fn default() -> Self {
+        Self {
+            hash_lists: Vec::new(),
+            ip_lists: Vec::new(),
+            reload_secs: 300,
+            misp: None,
+            taxii: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A TAXII 2.1 collection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TaxiiConfig {
+    /// Feed name used to tag matching events
+    pub name: String,
+
+    /// API root URL, e.g. `https://taxii.example.org/api1`
+    pub api_root: String,
+
+    /// Collection ID within the API root
+    pub collection: String,
+
+    /// HTTP basic auth credentials
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Seconds between pulls
+    #[serde(default = "default_feed_pull_secs")]
+    pub pull_secs: u64,
+
+    /// Verify the server certificate
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+fn default_feed_pull_secs() -> u64 {
+    3600
+}
+
+fn default_severity() -> Severity {
+    Severity::Low
+}
+
+impl WatchConfig {
+    /// Watch entry with default settings
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            recursive: true,
+            exclude: Vec::new(),
+            severity: default_severity(),
+            scan_on_change: true,
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Where the config is read from: `GUARDIAN_CONFIG`, or the default path
+    pub fn path() -> PathBuf {
+        std::env::var_os("GUARDIAN_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+    }
+
+    /// Load the config from `GUARDIAN_CONFIG`, or the default path if it exists
+    pub fn load() -> Result<Self> {
+        let mut config = match std::env::var("GUARDIAN_CONFIG") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => Self::default(),
+        };
+
+        // Single-path setups configured through the environment keep working
+        if config.watch.is_empty() {
+            let path = std::env::var("GUARDIAN_WATCH_PATH")
+                .unwrap_or_else(|_| DEFAULT_WATCH_PATH.to_string());
+            config.watch.push(WatchConfig::new(path));
+        }
+
+        // A console that spawns the daemon chooses where it listens
+        if let Some(socket) = std::env::var_os("GUARDIAN_SOCKET") {
+            config.ipc.socket = PathBuf::from(socket);
+        }
+
+        Ok(config)
+    }
+
+    /// Parse a config file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        info!("Loading config from {}", path.display());
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            GuardianError::config_context(format!("Failed to read config {}", path.display()), e)
+        })?;
+        Self::from_toml(&contents).map_err(|e| {
+            GuardianError::config_context(format!("Failed to parse config {}", path.display()), e)
+        })
+    }
+
+    /// Parse config from a TOML string
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        toml::from_str(contents).map_err(GuardianError::config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watch_entries() {
+        let config = DaemonConfig::from_toml(
+            r#"
+            [[watch]]
+            path = "/etc"
+            severity = "HIGH"
+            exclude = ["*.swp", "**/cache/**"]
+
+            [[watch]]
+            path = "/home/app/uploads"
+            recursive = false
+            scan_on_change = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.watch.len(), 2);
+        assert_eq!(config.watch[0].severity, Severity::High);
+        assert!(config.watch[0].recursive);
+        assert_eq!(config.watch[0].exclude.len(), 2);
+        assert!(!config.watch[1].recursive);
+        assert!(!config.watch[1].scan_on_change);
+        assert_eq!(config.watch[1].severity, Severity::Low);
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let config = DaemonConfig::from_toml(
+            r#"
+            [[rules]]
+            name = "backup_reads_shadow"
+            event_type = "file_integrity"
+            tags = ["backup"]
+            fields = [{ field = "path", equals = "/etc/shadow" }]
+            actions = { suppress = true }
+
+            [[rules]]
+            name = "miner"
+            event_type = "process_monitor"
+            fields = [{ field = "name", one_of = ["xmrig", "minerd"] }]
+
+            [rules.actions]
+            set_severity = "CRITICAL"
+            add_tags = ["cryptominer"]
+            respond = { action = "kill_process" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.len(), 2);
+        assert!(config.rules[0].actions.suppress);
+        assert_eq!(
+            config.rules[1].actions.set_severity,
+            Some(Severity::Critical)
+        );
+        assert_eq!(
+            config.rules[1].actions.respond,
+            Some(guardian_common::RuleResponse::KillProcess)
+        );
+    }
+
+    #[test]
+    fn test_rollup_defaults() {
+        let config = DaemonConfig::from_toml("[rollup]\nmode = \"instead\"\n").unwrap();
+        assert!(config.rollup.enabled);
+        assert_eq!(config.rollup.mode, RollupMode::Instead);
+        assert_eq!(config.rollup.top_paths, 10);
+    }
+
+    #[test]
+    fn test_ransomware_action() {
+        let config = DaemonConfig::from_toml(
+            "[ransomware]\naction = \"isolate\"\nisolate_allow = [\"10.0.0.5:8443\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.ransomware.action, RansomwareAction::Isolate);
+        assert_eq!(config.ransomware.min_files, 20);
+        assert!(config.ransomware.extensions.contains(&"locked".to_string()));
+    }
+
+    #[test]
+    fn test_misp_defaults() {
+        let config = DaemonConfig::from_toml(
+            "[threat_intel.misp]\nurl = \"https://misp.local\"\napi_key = \"k\"\n",
+        )
+        .unwrap();
+        let misp = config.threat_intel.misp.unwrap();
+        assert_eq!(misp.pull_secs, 3600);
+        assert!(misp.verify_tls);
+        assert!(misp.last.is_none());
+    }
+
+    #[test]
+    fn test_siem_entries() {
+        let config = DaemonConfig::from_toml(
+            r#"
+            [[siem]]
+            format = "cef"
+            transport = "udp"
+            address = "siem.local:514"
+            min_severity = "MEDIUM"
+
+            [[siem]]
+            format = "leef"
+            transport = "file"
+            path = "/var/log/guardian/leef.log"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.siem.len(), 2);
+        assert_eq!(config.siem[0].format, SiemFormat::Cef);
+        assert_eq!(config.siem[0].min_severity, Severity::Medium);
+        assert_eq!(config.siem[1].transport, SiemTransport::File);
+        assert_eq!(config.siem[1].min_severity, Severity::Info);
+        assert_eq!(config.siem[1].buffer, 10_000);
+    }
+
+    #[test]
+    fn test_splunk_severity_indexes() {
+        let config = DaemonConfig::from_toml(
+            "[splunk]\nenabled = true\n[splunk.severity_indexes]\nCRITICAL = \"urgent\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.splunk.severity_indexes.get(&Severity::Critical),
+            Some(&"urgent".to_string())
+        );
+        assert!(config.splunk.gzip);
+    }
+
+    #[test]
+    fn test_signing_key() {
+        assert!(DaemonConfig::default().signing.signer().unwrap().is_none());
+        let config = DaemonConfig::from_toml("[signing]\nkey = \"0123456789abcdef\"\n").unwrap();
+        assert!(config.signing.signer().unwrap().is_some());
+        let config = DaemonConfig::from_toml("[signing]\nkey = \"short\"\n").unwrap();
+        assert!(config.signing.signer().is_err());
+    }
+
+    #[test]
+    fn test_unknown_fields_rejected() {
+        assert!(DaemonConfig::from_toml("[[watch]]\npath = \"/etc\"\nrecurse = true\n").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::response::HostIsolation;
+use crate::sampling::Sampler;
+use crate::scan::OnDemandScanner;
+use crate::snapshots::FileRestore;
+use guardian_common::ControlCommand;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, Notify};
+use tracing::{error, info, warn};
+
+/// Components the control channel can act on
+pub struct ControlHandlers {
+    pub isolation: Arc<HostIsolation>,
+    pub sampler: Arc<Sampler>,
+    /// Absent when the YARA scanner failed to initialize
+    pub scans: Option<Arc<OnDemandScanner>>,
+    /// Absent when snapshots are disabled or the store failed to open
+    pub restores: Option<FileRestore>,
+    /// Wakes the event loop, which owns the rule engine, to send its counts
+    pub rule_stats: Arc<Notify>,
+    /// Asks the event loop, which owns the sinks and rules, to reload the
+    /// config on behalf of an operator
+    pub reload: mpsc::Sender<String>,
+}
+
+/// Apply control commands from every source (socket consoles and stdin) in order
+pub async fn run_control_channel(
+    handlers: ControlHandlers,
+    mut commands: mpsc::Receiver<ControlCommand>,
+) {
+    while let Some(command) = commands.recv().await {
+        handle_command(command, &handlers).await;
+    }
+}
+
+/// Read control commands from stdin, one JSON object per line, for consoles
+/// that drive the daemon through pipes
+pub async fn read_stdin(commands: mpsc::Sender<ControlCommand>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                info!("Control channel closed");
+                break;
+            }
+            Err(e) => {
+                error!("Control channel read error: {}", e);
+                break;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match ControlCommand::from_json(&line) {
+            Ok(command) => {
+                if commands.send(command).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => warn!("Ignoring invalid control command: {}", e),
+        }
+    }
+}
+
+/// Dispatch a single control command
+async fn handle_command(command: ControlCommand, handlers: &ControlHandlers) {
+    match command {
+        ControlCommand::IsolateHost {
+            allow,
+            duration_secs,
+            requested_by,
+        } => {
+            let duration = Duration::from_secs(duration_secs);
+            if let Err(e) = handlers
+                .isolation
+                .isolate(&allow, duration, &requested_by)
+                .await
+            {
+                error!("Host isolation failed: {}", e);
+            }
+        }
+        ControlCommand::ReleaseHost { requested_by } => {
+            match handlers.isolation.release(&requested_by).await {
+                Ok(true) => {}
+                Ok(false) => info!("Release requested but host is not isolated"),
+                Err(e) => error!("Failed to release host isolation: {}", e),
+            }
+        }
+        ControlCommand::SetSampling { rate } => {
+            info!("Console set event sampling rate to 1/{}", rate.max(1));
+            handlers.sampler.set_rate(rate);
+        }
+        ControlCommand::ScanPath {
+            scan_id,
+            path,
+            requested_by,
+        } => match &handlers.scans {
+            Some(scans) => scans.start(scan_id, path, requested_by),
+            None => warn!("Scan of {} requested but YARA is unavailable", path),
+        },
+        ControlCommand::RestoreFile {
+            path,
+            hash,
+            requested_by,
+        } => match &handlers.restores {
+            Some(restores) => restores.restore(&path, &hash, &requested_by).await,
+            None => warn!("Restore of {} requested but restores are disabled", path),
+        },
+        ControlCommand::ReportRuleStats => handlers.rule_stats.notify_one(),
+        ControlCommand::ReloadConfig { requested_by } => {
+            info!("Config reload requested by {}", requested_by);
+            if handlers.reload.send(requested_by).await.is_err() {
+                warn!("Config reload requested but the event loop has stopped");
+            }
+        }
+    }
+}
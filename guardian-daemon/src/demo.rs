@@ -0,0 +1,364 @@
+//! `--demo`: plausible fake events from a handful of imaginary hosts in
+//! place of the real collectors, for trying the console, rules and sinks or
+//! load-testing them without touching a real system. Every event is tagged
+//! `demo` so it can be told apart and purged.
+
+use crate::platform;
+use crate::queue::EventSender;
+use guardian_common::{
+    AuthOutcome, EventType, EventUser, FileOperation, LogEvent, PackageAction, ProcessAncestor,
+    Severity,
+};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Tag on every generated event
+pub const DEMO_TAG: &str = "demo";
+
+/// Events per second when `--demo-rate` is not given
+pub const DEFAULT_RATE: u32 = 10;
+
+/// Events are sent in a burst every tick, so high rates don't need a timer
+/// per event
+const TICK: Duration = Duration::from_millis(10);
+
+const HOSTS: &[&str] = &["demo-web-1", "demo-web-2", "demo-db-1", "demo-bastion"];
+const USERS: &[(u32, &str)] = &[
+    (0, "root"),
+    (1000, "alice"),
+    (1001, "bob"),
+    (33, "www-data"),
+];
+const FILES: &[&str] = &[
+    "/var/www/html/index.php",
+    "/var/www/html/uploads/avatar.png",
+    "/etc/nginx/nginx.conf",
+    "/home/alice/.bashrc",
+    "/srv/app/config.yaml",
+    "/tmp/build.log",
+    "/etc/passwd",
+    "/etc/shadow",
+];
+const PROCESSES: &[&str] = &[
+    "nginx", "postgres", "sshd", "cron", "python3", "node", "bash",
+];
+const PACKAGES: &[(&str, &str)] = &[
+    ("openssl", "3.0.13-1"),
+    ("nginx", "1.24.0-2"),
+    ("curl", "8.5.0-2"),
+    ("netcat-openbsd", "1.226-1"),
+];
+const SOURCE_IPS: &[&str] = &["10.0.4.21", "10.0.4.35", "198.51.100.23", "203.0.113.77"];
+const REMOTES: &[&str] = &[
+    "140.82.121.4:443",
+    "151.101.1.69:443",
+    "10.0.4.10:5432",
+    "192.0.2.15:4444",
+    "198.51.100.9:31337",
+];
+const LOG_LINES: &[(&str, &str, &str)] = &[
+    ("kernel", "info", "eth0: link up, 1000Mbps, full-duplex"),
+    ("systemd", "info", "Started Daily apt download activities."),
+    ("cron", "info", "(root) CMD (run-parts /etc/cron.hourly)"),
+    (
+        "kernel",
+        "warning",
+        "Out of memory: Killed process 4121 (node)",
+    ),
+    ("postgres", "err", "FATAL: password authentication failed"),
+];
+
+/// Small xorshift generator; demo data doesn't need more and the daemon
+/// has no other use for randomness
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Zero is xorshift's one fixed point
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform value in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    /// True with probability `percent` in 100
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// Generates events across every event type, mostly routine with a few
+/// that trip the built-in rules
+pub struct Generator {
+    rng: Rng,
+    started: Instant,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            started: Instant::now(),
+        }
+    }
+
+    fn user(&mut self) -> EventUser {
+        let (uid, name) = *self.rng.pick(USERS);
+        EventUser {
+            uid,
+            username: Some(name.to_string()),
+        }
+    }
+
+    fn file_event(&mut self) -> (Severity, EventType) {
+        let path = *self.rng.pick(FILES);
+        let operation = match self.rng.below(10) {
+            0 => FileOperation::Create,
+            1 => FileOperation::Delete,
+            2 => FileOperation::Chmod,
+            _ => FileOperation::Modify,
+        };
+        let hash = (operation != FileOperation::Delete)
+            .then(|| format!("{:016x}{:016x}", self.rng.next(), self.rng.next()));
+        let severity = if platform::is_sensitive_path(path) {
+            Severity::High
+        } else if path.ends_with(".conf") {
+            Severity::Medium
+        } else {
+            Severity::Low
+        };
+        let user = Some(self.user());
+        (
+            severity,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation,
+                hash,
+                user,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+        )
+    }
+
+    fn network_event(&mut self) -> (Severity, EventType) {
+        let port = 40000 + self.rng.below(20000);
+        (
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: format!("10.0.4.{}:{}", 10 + self.rng.below(40), port),
+                remote_addr: Some(self.rng.pick(REMOTES).to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "ESTABLISHED".to_string(),
+                traffic: None,
+                process: None,
+            },
+        )
+    }
+
+    fn process_event(&mut self) -> (Severity, EventType) {
+        let name = *self.rng.pick(PROCESSES);
+        // Now and then a shell under the web server
+        let ancestry = if name == "bash" && self.rng.chance(30) {
+            vec![ProcessAncestor {
+                pid: 900 + self.rng.below(100) as u32,
+                name: "nginx".to_string(),
+            }]
+        } else {
+            Vec::new()
+        };
+        let cpu_usage = if self.rng.chance(3) {
+            91.0 + self.rng.below(9) as f32
+        } else {
+            self.rng.below(400) as f32 / 10.0
+        };
+        let user = Some(self.user());
+        (
+            Severity::Info,
+            EventType::ProcessMonitor {
+                pid: 1000 + self.rng.below(60000) as u32,
+                name: name.to_string(),
+                cpu_usage,
+                memory_usage: (16 + self.rng.below(2048)) * 1024 * 1024,
+                ancestry,
+                user,
+            },
+        )
+    }
+
+    fn auth_event(&mut self) -> (Severity, EventType) {
+        let (_, user) = *self.rng.pick(USERS);
+        let outcome = if self.rng.chance(70) {
+            AuthOutcome::Success
+        } else {
+            AuthOutcome::Failure
+        };
+        let severity = match outcome {
+            AuthOutcome::Failure => Severity::Low,
+            AuthOutcome::Success => Severity::Info,
+        };
+        (
+            severity,
+            EventType::AuthEvent {
+                user: user.to_string(),
+                source_ip: Some(self.rng.pick(SOURCE_IPS).to_string()),
+                method: self.rng.pick(&["password", "publickey"]).to_string(),
+                outcome,
+                tty: Some("ssh".to_string()),
+            },
+        )
+    }
+
+    fn package_event(&mut self) -> (Severity, EventType) {
+        let (name, version) = *self.rng.pick(PACKAGES);
+        let action = match self.rng.below(4) {
+            0 => PackageAction::Install,
+            1 => PackageAction::Remove,
+            _ => PackageAction::Upgrade,
+        };
+        (
+            Severity::Low,
+            EventType::PackageChange {
+                name: name.to_string(),
+                version: Some(version.to_string()),
+                action,
+                manager: "dpkg".to_string(),
+            },
+        )
+    }
+
+    fn log_event(&mut self) -> (Severity, EventType) {
+        let (source, level, message) = *self.rng.pick(LOG_LINES);
+        let severity = match level {
+            "err" => Severity::Medium,
+            "warning" => Severity::Low,
+            _ => Severity::Info,
+        };
+        (
+            severity,
+            EventType::SystemLog {
+                source: source.to_string(),
+                level: level.to_string(),
+                message: message.to_string(),
+            },
+        )
+    }
+
+    fn heartbeat_event(&mut self) -> (Severity, EventType) {
+        (
+            Severity::Info,
+            EventType::Heartbeat {
+                agent_version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_secs: self.started.elapsed().as_secs(),
+                interval_secs: 60,
+                forward: None,
+            },
+        )
+    }
+
+    fn alert_event(&mut self) -> (Severity, EventType) {
+        let related = (0..3).map(|_| Uuid::new_v4()).collect();
+        (
+            Severity::High,
+            EventType::Alert {
+                title: "SSH brute force followed by login".to_string(),
+                description: format!(
+                    "{} failed logins then a success from {}",
+                    5 + self.rng.below(20),
+                    self.rng.pick(SOURCE_IPS)
+                ),
+                related_event_ids: related,
+                technique: Some("T1110".to_string()),
+            },
+        )
+    }
+
+    /// The next event, weighted towards the types real hosts produce most
+    pub fn next_event(&mut self) -> LogEvent {
+        let (severity, event_type) = match self.rng.below(100) {
+            0..=24 => self.file_event(),
+            25..=44 => self.network_event(),
+            45..=64 => self.process_event(),
+            65..=79 => self.log_event(),
+            80..=91 => self.auth_event(),
+            92..=96 => self.package_event(),
+            97..=98 => self.heartbeat_event(),
+            _ => self.alert_event(),
+        };
+        let hostname = self.rng.pick(HOSTS).to_string();
+        LogEvent::new(severity, event_type, hostname).with_tag(DEMO_TAG)
+    }
+}
+
+/// Send about `rate` generated events a second until the channel closes
+pub async fn run(tx: EventSender, rate: u32) {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(1, |elapsed| elapsed.as_nanos() as u64);
+    let mut generator = Generator::new(seed);
+    let mut interval = tokio::time::interval(TICK);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    // Carry fractions over so low rates still average out
+    let mut due = 0.0;
+    loop {
+        interval.tick().await;
+        due += f64::from(rate) * TICK.as_secs_f64();
+        while due >= 1.0 {
+            due -= 1.0;
+            if tx.send(generator.next_event()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleEngine;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generator() {
+        let mut generator = Generator::new(42);
+        let rules = RuleEngine::new();
+        let mut kinds = HashSet::new();
+        let mut rule_hits = HashSet::new();
+        for _ in 0..5000 {
+            let event = generator.next_event();
+            assert!(event.tags.contains(&DEMO_TAG.to_string()));
+            assert!(HOSTS.contains(&event.hostname.as_str()));
+            // Generated events must survive the trip through JSON
+            LogEvent::from_json(&event.to_json().unwrap()).unwrap();
+            kinds.insert(event.event_type.kind());
+            rule_hits.extend(rules.evaluate(&event));
+        }
+
+        assert_eq!(kinds.len(), 8);
+        for rule in [
+            "critical_file_modification",
+            "suspicious_network",
+            "web_server_spawned_shell",
+            "remote_root_login",
+            "high_cpu_usage",
+        ] {
+            assert!(rule_hits.contains(rule), "{} never triggered", rule);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Content diffs for selected config files. A hash says that `sudoers` or
+//! `sshd_config` changed but not how, so the contents of the configured
+//! files are kept in memory and each change the file monitor reports
+//! carries a unified diff against the previous contents. With a snapshot
+//! store, every version read is also stored so it can be restored.
+
+use crate::config::DiffConfig;
+use crate::snapshots::SnapshotStore;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Last known contents of the configured files
+pub struct ContentHistory {
+    config: DiffConfig,
+    contents: HashMap<PathBuf, String>,
+    store: Option<Arc<Mutex<SnapshotStore>>>,
+}
+
+impl ContentHistory {
+    /// Read the contents of every configured file that exists now, storing
+    /// them in `store` when there is one
+    pub fn new(config: DiffConfig, store: Option<Arc<Mutex<SnapshotStore>>>) -> Self {
+        let mut history = Self {
+            config,
+            contents: HashMap::new(),
+            store,
+        };
+        if !history.config.enabled {
+            return history;
+        }
+        for path in history.config.paths.clone() {
+            let files = match std::fs::read_dir(&path) {
+                Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+                Err(_) => vec![path],
+            };
+            for file in files {
+                if let Some(text) = history.read(&file) {
+                    history.snapshot(&file, &text);
+                    history.contents.insert(file, text);
+                }
+            }
+        }
+        info!(
+            "Keeping the contents of {} file(s) for diffs",
+            history.contents.len()
+        );
+        history
+    }
+
+    /// Whether `path` is a configured file or directly inside a configured
+    /// directory
+    pub fn covers(&self, path: &Path) -> bool {
+        self.config
+            .paths
+            .iter()
+            .any(|kept| path == kept || path.parent() == Some(kept.as_path()))
+    }
+
+    /// Text of `path`, unless it is too large or not UTF-8
+    fn read(&self, path: &Path) -> Option<String> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.is_file() || metadata.len() > self.config.max_bytes {
+            return None;
+        }
+        String::from_utf8(std::fs::read(path).ok()?).ok()
+    }
+
+    fn snapshot(&self, path: &Path, text: &str) {
+        if let Some(store) = &self.store {
+            let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = store.record(path, text.as_bytes()) {
+                warn!("Failed to snapshot {}: {:#}", path.display(), e);
+            }
+        }
+    }
+
+    /// Hashes of the stored versions of `path`, oldest first
+    pub fn snapshots(&self, path: &Path) -> Vec<String> {
+        let Some(store) = &self.store else {
+            return Vec::new();
+        };
+        let store = store.lock().unwrap_or_else(|e| e.into_inner());
+        store
+            .versions(path)
+            .iter()
+            .map(|version| version.hash.clone())
+            .collect()
+    }
+
+    /// Record the current contents of `path` and return a unified diff
+    /// against the previous ones, if both are known and differ. Deleted
+    /// files keep their last contents, so a file replaced by delete and
+    /// create is still diffed.
+    pub fn update(&mut self, path: &Path) -> Option<String> {
+        if !self.config.enabled || !self.covers(path) {
+            return None;
+        }
+        let text = self.read(path)?;
+        self.snapshot(path, &text);
+        let old = self.contents.insert(path.to_path_buf(), text.clone())?;
+        (old != text).then(|| unified_diff(path, &old, &text, self.config.max_lines))
+    }
+}
+
+/// Unified diff of `old` and `new` with three lines of context, cut off
+/// after `max_lines` lines
+pub fn unified_diff(path: &Path, old: &str, new: &str, max_lines: usize) -> String {
+    let name = path.display().to_string();
+    let diff = TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&name, &name)
+        .to_string();
+    let total = diff.lines().count();
+    if total <= max_lines {
+        return diff;
+    }
+    let mut cut: String = diff
+        .lines()
+        .take(max_lines)
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    cut.push_str(&format!("... {} more line(s)\n", total - max_lines));
+    cut
+}
+
+/// Lines of `old` that `new` drops and lines `new` adds, each in order,
+/// line for line as in a unified diff
+pub fn changed_lines(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let old: Vec<&str> = old.iter().map(String::as_str).collect();
+    let new: Vec<&str> = new.iter().map(String::as_str).collect();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for change in TextDiff::from_slices(&old, &new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Delete => removed.push(change.value().to_string()),
+            ChangeTag::Insert => added.push(change.value().to_string()),
+            ChangeTag::Equal => {}
+        }
+    }
+    (removed, added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_on_change() {
+        let dir = std::env::temp_dir().join(format!("guardian-diff-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sudoers = dir.join("sudoers");
+        std::fs::write(&sudoers, "root ALL=(ALL) ALL\n%sudo ALL=(ALL) ALL\n").unwrap();
+        let other = dir.join("other");
+
+        let mut history = ContentHistory::new(
+            DiffConfig {
+                paths: vec![sudoers.clone()],
+                ..DiffConfig::default()
+            },
+            None,
+        );
+        assert!(history.covers(&sudoers));
+        assert!(!history.covers(&other));
+        assert_eq!(history.update(&sudoers), None);
+
+        std::fs::write(
+            &sudoers,
+            "root ALL=(ALL) ALL\n%sudo ALL=(ALL) ALL\nbob ALL=(ALL) NOPASSWD: ALL\n",
+        )
+        .unwrap();
+        let diff = history.update(&sudoers).unwrap();
+        let name = sudoers.display();
+        assert_eq!(
+            diff,
+            format!(
+                "--- {}\n+++ {}\n@@ -1,2 +1,3 @@\n root ALL=(ALL) ALL\n %sudo ALL=(ALL) ALL\n\
+                 +bob ALL=(ALL) NOPASSWD: ALL\n",
+                name, name
+            )
+        );
+
+        // Replaced by delete and create, diffed against the deleted contents
+        std::fs::remove_file(&sudoers).unwrap();
+        assert_eq!(history.update(&sudoers), None);
+        std::fs::write(&sudoers, "root ALL=(ALL) ALL\n").unwrap();
+        assert!(history.update(&sudoers).unwrap().contains("-bob ALL"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_long_diff_is_cut() {
+        let old: String = (0..50).map(|i| format!("line {}\n", i)).collect();
+        let diff = unified_diff(Path::new("/etc/group"), &old, "", 10);
+        assert_eq!(diff.lines().count(), 11);
+        assert!(diff.ends_with("... 43 more line(s)\n"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Bulk-indexes events into Elasticsearch or OpenSearch as Elastic Common
+//! Schema documents, so Kibana and OpenSearch Dashboards show them under the
+//! field names analysts already query. Batches are retried with backoff and
+//! documents keep their event id, so a resent batch does not duplicate them.
+
+use crate::config::ElasticConfig;
+use crate::outbox::Outbox;
+use anyhow::{bail, Context, Result};
+use guardian_common::LogEvent;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// First and longest wait between failed bulk requests
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Name of the index template Guardian installs
+const TEMPLATE_NAME: &str = "guardian";
+
+/// One document waiting to be indexed
+struct Pending {
+    index: String,
+    id: String,
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    #[serde(default)]
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkItem {
+    status: u16,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+pub struct ElasticSink {
+    http: reqwest::Client,
+    config: ElasticConfig,
+    outbox: Outbox<Pending>,
+}
+
+impl ElasticSink {
+    /// Check the settings and start indexing in the background
+    pub fn start(config: ElasticConfig) -> Result<Arc<Self>> {
+        if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+            bail!("Elasticsearch url must start with http:// or https://");
+        }
+        if config.index.is_empty() {
+            bail!("Elasticsearch index must not be empty");
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(!config.verify_tls)
+            .build()?;
+
+        let sink = Arc::new(Self {
+            http,
+            outbox: Outbox::new("Elasticsearch", config.buffer),
+            config,
+        });
+        let task = sink.clone();
+        tokio::spawn(async move { task.outbox.deliver(task.clone().run()).await });
+        Ok(sink)
+    }
+
+    /// Stop delivering, when a config reload replaces the sink
+    pub fn stop(&self) {
+        self.outbox.stop();
+    }
+
+    /// Queue an event if it meets the minimum severity
+    pub fn send(&self, event: &LogEvent) {
+        if event.severity < self.config.min_severity {
+            return;
+        }
+        let doc = Pending {
+            index: index_name(&self.config.index, event),
+            id: event.id.to_string(),
+            body: event.to_ecs().to_string(),
+        };
+
+        self.outbox.push(doc);
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut template_ready = !self.config.install_template;
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let batch = self
+                .outbox
+                .next_batch(
+                    self.config.batch_size,
+                    Duration::from_millis(self.config.flush_ms),
+                )
+                .await;
+            if !template_ready {
+                match self.install_template().await {
+                    Ok(()) => {
+                        info!("Installed Elasticsearch index template {}", TEMPLATE_NAME);
+                        template_ready = true;
+                    }
+                    Err(e) => warn!("Failed to install Elasticsearch index template: {}", e),
+                }
+            }
+
+            let count = batch.len();
+            let retry = match self.bulk(&batch).await {
+                Ok(retry) => retry,
+                Err(e) => {
+                    warn!("Elasticsearch bulk request failed: {}", e);
+                    (0..count).collect()
+                }
+            };
+            if retry.is_empty() {
+                backoff = MIN_BACKOFF;
+                continue;
+            }
+
+            let retry_docs = batch
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| retry.contains(i))
+                .map(|(_, doc)| doc)
+                .collect();
+            self.outbox.requeue(retry_docs);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), path);
+        let request = self.http.request(method, url);
+        if let Some(key) = &self.config.api_key {
+            request.header("Authorization", format!("ApiKey {}", key))
+        } else if let Some(username) = &self.config.username {
+            request.basic_auth(username, self.config.password.as_ref())
+        } else {
+            request
+        }
+    }
+
+    async fn install_template(&self) -> Result<()> {
+        let response = self
+            .request(
+                reqwest::Method::PUT,
+                &format!("_index_template/{}", TEMPLATE_NAME),
+            )
+            .json(&index_template(&self.config.index))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("{}: {}", status, response.text().await.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Send one bulk request; returns the positions of documents to retry
+    async fn bulk(&self, batch: &[Pending]) -> Result<Vec<usize>> {
+        let response = self
+            .request(reqwest::Method::POST, "_bulk")
+            .header("Content-Type", "application/x-ndjson")
+            .body(bulk_body(batch))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("{}: {}", status, response.text().await.unwrap_or_default());
+        }
+        let response: BulkResponse = response.json().await.context("Unexpected bulk response")?;
+        Ok(retryable_items(&response))
+    }
+}
+
+/// Index for an event, with `%Y`, `%m` and `%d` taken from its timestamp
+fn index_name(pattern: &str, event: &LogEvent) -> String {
+    pattern
+        .replace("%Y", &event.timestamp.format("%Y").to_string())
+        .replace("%m", &event.timestamp.format("%m").to_string())
+        .replace("%d", &event.timestamp.format("%d").to_string())
+        .to_lowercase()
+}
+
+/// NDJSON bulk request indexing each document under its event id
+fn bulk_body(batch: &[Pending]) -> String {
+    let mut body = String::new();
+    for doc in batch {
+        let action = json!({ "index": { "_index": doc.index, "_id": doc.id } });
+        body.push_str(&action.to_string());
+        body.push('\n');
+        body.push_str(&doc.body);
+        body.push('\n');
+    }
+    body
+}
+
+/// Positions of items the cluster asked to have resent; other failures,
+/// such as mapping conflicts, would fail again and are only logged
+fn retryable_items(response: &BulkResponse) -> Vec<usize> {
+    if !response.errors {
+        return Vec::new();
+    }
+    let mut retry = Vec::new();
+    for (i, item) in response.items.iter().enumerate() {
+        let Some(result) = item.values().next() else {
+            continue;
+        };
+        match result.status {
+            200..=299 => {}
+            429 | 500..=599 => retry.push(i),
+            status => warn!(
+                "Elasticsearch rejected a document ({}): {}",
+                status,
+                result.error.clone().unwrap_or_default()
+            ),
+        }
+    }
+    retry
+}
+
+/// Composable template mapping the ECS fields whose type cannot be inferred
+fn index_template(index: &str) -> Value {
+    let pattern = match index.find('%') {
+        Some(pos) => format!("{}*", &index[..pos]),
+        None => index.to_string(),
+    };
+    json!({
+        "index_patterns": [pattern.to_lowercase()],
+        "priority": 200,
+        "template": {
+            "mappings": {
+                "dynamic_templates": [{
+                    "strings_as_keyword": {
+                        "match_mapping_type": "string",
+                        "mapping": { "type": "keyword", "ignore_above": 1024 }
+                    }
+                }],
+                "properties": {
+                    "@timestamp": { "type": "date" },
+                    "message": { "type": "text" },
+                    "event": { "properties": {
+                        "severity": { "type": "long" },
+                        "reason": { "type": "text" }
+                    }},
+                    "source": { "properties": {
+                        "ip": { "type": "ip" },
+                        "port": { "type": "long" }
+                    }},
+                    "destination": { "properties": {
+                        "ip": { "type": "ip" },
+                        "port": { "type": "long" }
+                    }},
+                    "process": { "properties": {
+                        "pid": { "type": "long" },
+                        "cpu": { "properties": { "pct": { "type": "scaled_float", "scaling_factor": 1000 } } },
+                        "memory": { "properties": { "bytes": { "type": "long" } } }
+                    }},
+                    "host": { "properties": { "uptime": { "type": "long" } } }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use guardian_common::{EventType, Severity};
+
+    #[test]
+    fn test_bulk_requests() {
+        let mut event = LogEvent::new(
+            Severity::Low,
+            EventType::SystemLog {
+                source: "sshd".to_string(),
+                level: "info".to_string(),
+                message: "session opened".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        event.timestamp = chrono::Utc.with_ymd_and_hms(2024, 3, 7, 12, 0, 0).unwrap();
+        assert_eq!(
+            index_name("Guardian-%Y.%m.%d", &event),
+            "guardian-2024.03.07"
+        );
+        assert_eq!(
+            index_template("guardian-%Y.%m")["index_patterns"],
+            json!(["guardian-*"])
+        );
+
+        let batch = vec![Pending {
+            index: "guardian-2024.03.07".to_string(),
+            id: event.id.to_string(),
+            body: event.to_ecs().to_string(),
+        }];
+        let body = bulk_body(&batch);
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let action: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(action["index"]["_id"], event.id.to_string());
+        let doc: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(doc["process"]["name"], "sshd");
+
+        let response: BulkResponse = serde_json::from_value(json!({
+            "took": 3,
+            "errors": true,
+            "items": [
+                { "index": { "status": 201 } },
+                { "index": { "status": 429, "error": { "type": "es_rejected_execution_exception" } } },
+                { "index": { "status": 400, "error": { "type": "mapper_parsing_exception" } } },
+                { "index": { "status": 503 } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(retryable_items(&response), vec![1, 3]);
+    }
+}
@@ -0,0 +1,204 @@
+//! `guardian-daemon enroll`: trade a one-time token from the console for a
+//! client certificate, so the collector in `[forward]` accepts this agent
+//! over mutual TLS. The private key is generated here and never leaves the host.
+
+use crate::config::{DaemonConfig, ForwardConfig};
+use crate::forward;
+use anyhow::{bail, Context, Result};
+use guardian_common::ipc::{read_frame, write_frame, Frame};
+use rcgen::{CertificateParams, DnType, KeyPair};
+use std::path::{Path, PathBuf};
+use tokio::net::TcpStream;
+
+/// Enroll with `token`, writing the key and certificate to `out`, or next to
+/// the config file
+pub fn run_command(token: &str, out: Option<PathBuf>) -> Result<()> {
+    let out = match out {
+        Some(dir) => dir,
+        None => DaemonConfig::path()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+
+    let config = DaemonConfig::load()?;
+    if config.forward.address.is_empty() {
+        bail!("Set the collector address in [forward] before enrolling");
+    }
+    let hostname = crate::platform::hostname();
+
+    let key = KeyPair::generate()?;
+    let mut params = CertificateParams::new(Vec::<String>::new())?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, hostname.clone());
+    let csr = params.serialize_request(&key)?.pem()?;
+
+    // Provisioning commands run inside the daemon's runtime
+    let certificate = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(enroll(&config.forward, token, &hostname, &csr))
+    })?;
+
+    std::fs::create_dir_all(&out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let cert_file = out.join("agent.pem");
+    let key_file = out.join("agent-key.pem");
+    write_private(&key_file, key.serialize_pem())?;
+    std::fs::write(&cert_file, certificate)
+        .with_context(|| format!("Failed to write {}", cert_file.display()))?;
+
+    println!("Enrolled {} with {}", hostname, config.forward.address);
+    println!("Add to [forward] in {}:", DaemonConfig::path().display());
+    println!("cert_file = {:?}", cert_file.display().to_string());
+    println!("key_file = {:?}", key_file.display().to_string());
+    Ok(())
+}
+
+/// Send the signing request and return the issued PEM certificate
+async fn enroll(config: &ForwardConfig, token: &str, hostname: &str, csr: &str) -> Result<String> {
+    // Enrollment happens before there is a certificate to present
+    let config = ForwardConfig {
+        cert_file: None,
+        key_file: None,
+        ..config.clone()
+    };
+    let (connector, server_name) = forward::tls_target(&config)?;
+    let tcp = TcpStream::connect(&config.address)
+        .await
+        .with_context(|| format!("Failed to connect to {}", config.address))?;
+    let mut stream = connector.connect(server_name, tcp).await?;
+
+    let request = Frame::Enroll {
+        token: token.to_string(),
+        hostname: hostname.to_string(),
+        csr: csr.to_string(),
+    };
+    write_frame(&mut stream, &request).await?;
+    match read_frame(&mut stream).await? {
+        Some(Frame::Enrolled { certificate }) => Ok(certificate),
+        Some(Frame::Reject { reason }) => bail!("Collector refused enrollment: {}", reason),
+        _ => bail!("Collector did not answer the enrollment request"),
+    }
+}
+
+/// Create `path` readable by the owner only, failing if anything is there
+/// already, a symlink included
+pub(crate) fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(nix::libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+/// Write a file readable by the owner only
+pub(crate) fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        file.write_all(contents.as_ref())?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::TlsAcceptor;
+
+    #[tokio::test]
+    async fn test_enroll_exchanges_token_for_certificate() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file =
+            std::env::temp_dir().join(format!("guardian-ca-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&ca_file, cert.cert.pem()).unwrap();
+        let server_config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(cert.cert.der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der())),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ForwardConfig {
+            enabled: true,
+            address: listener.local_addr().unwrap().to_string(),
+            server_name: Some("localhost".to_string()),
+            ca_file: Some(ca_file.clone()),
+            ..ForwardConfig::default()
+        };
+
+        // A collector that accepts one token and refuses anything else
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut stream = acceptor.accept(tcp).await.unwrap();
+                let reply = match read_frame(&mut stream).await.unwrap() {
+                    Some(Frame::Enroll { token, csr, .. }) if token == "good" => {
+                        assert!(csr.contains("CERTIFICATE REQUEST"));
+                        Frame::Enrolled {
+                            certificate: "issued".to_string(),
+                        }
+                    }
+                    _ => Frame::Reject {
+                        reason: "unknown token".to_string(),
+                    },
+                };
+                write_frame(&mut stream, &reply).await.unwrap();
+            }
+        });
+
+        let key = KeyPair::generate().unwrap();
+        let csr = CertificateParams::new(vec!["web-1".to_string()])
+            .unwrap()
+            .serialize_request(&key)
+            .unwrap()
+            .pem()
+            .unwrap();
+        assert_eq!(
+            enroll(&config, "good", "web-1", &csr).await.unwrap(),
+            "issued"
+        );
+        let refused = enroll(&config, "bad", "web-1", &csr).await.unwrap_err();
+        assert!(refused.to_string().contains("unknown token"));
+
+        let _ = std::fs::remove_file(&ca_file);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_private_refuses_symlinks() {
+        let dir = std::env::temp_dir().join(format!("guardian-private-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("daemon.toml");
+        std::fs::write(&target, b"[forward]\n").unwrap();
+        let link = dir.join(".daemon.toml.guardian-tmp");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert!(create_private(&link).is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"[forward]\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
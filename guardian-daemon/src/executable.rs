@@ -0,0 +1,133 @@
+//! ELF and PE header analysis for executables the file monitor sees
+//! written, so rules can look at what a binary links against, whether it
+//! is signed and whether it looks packed rather than only at its path.
+
+use guardian_common::{ExecutableFormat, ExecutableInfo};
+use std::io::Read;
+use std::path::Path;
+
+/// Files larger than this are not parsed
+const MAX_EXECUTABLE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Whether `header` starts like an ELF or PE file
+fn looks_executable(header: &[u8]) -> bool {
+    header.starts_with(b"\x7fELF") || header.starts_with(b"MZ")
+}
+
+/// Header details of the file at `path`, or `None` when it is not an ELF
+/// or PE executable, is too large or cannot be parsed
+pub fn analyze(path: &Path) -> Option<ExecutableInfo> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_EXECUTABLE_SIZE {
+        return None;
+    }
+
+    // Most written files are not executables; check before reading them whole
+    let mut header = [0u8; 4];
+    file.read_exact(&mut header).ok()?;
+    if !looks_executable(&header) {
+        return None;
+    }
+    let mut bytes = header.to_vec();
+    file.read_to_end(&mut bytes).ok()?;
+    parse(&bytes)
+}
+
+/// Shannon entropy of `bytes` in bits per byte
+pub fn entropy(bytes: &[u8]) -> f32 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0u64; 256];
+    for byte in bytes {
+        counts[*byte as usize] += 1;
+    }
+    let len = bytes.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    bits as f32
+}
+
+/// Parse an ELF or PE file that passed `looks_executable`
+fn parse(bytes: &[u8]) -> Option<ExecutableInfo> {
+    let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    if bytes.starts_with(b"\x7fELF") {
+        let elf = goblin::elf::Elf::parse(bytes).ok()?;
+        Some(ExecutableInfo {
+            format: ExecutableFormat::Elf,
+            arch: goblin::elf::header::machine_to_str(elf.header.e_machine).to_lowercase(),
+            library: elf.is_lib,
+            libraries: strings(&elf.libraries),
+            sections: elf
+                .section_headers
+                .iter()
+                .filter_map(|section| elf.shdr_strtab.get_at(section.sh_name))
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            // ELF has no standard embedded signature
+            signed: None,
+            entropy: entropy(bytes),
+        })
+    } else {
+        let pe = goblin::pe::PE::parse(bytes).ok()?;
+        Some(ExecutableInfo {
+            format: ExecutableFormat::Pe,
+            arch: goblin::pe::header::machine_to_str(pe.header.coff_header.machine).to_lowercase(),
+            library: pe.is_lib,
+            libraries: strings(&pe.libraries),
+            sections: pe
+                .sections
+                .iter()
+                .filter_map(|section| section.name().ok())
+                .map(str::to_string)
+                .collect(),
+            // An Authenticode certificate is present; it is not verified
+            signed: Some(!pe.certificates.is_empty()),
+            entropy: entropy(bytes),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        // The test binary itself is a native executable, though too large
+        // for `analyze`
+        if cfg!(target_os = "linux") {
+            let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+            let info = parse(&exe).unwrap();
+            assert_eq!(info.format, ExecutableFormat::Elf);
+            assert_eq!(info.arch, std::env::consts::ARCH);
+            assert!(info.sections.iter().any(|name| name == ".text"));
+            assert!(info.libraries.iter().any(|lib| lib.starts_with("libc.so")));
+            assert_eq!(info.signed, None);
+            assert!(info.entropy > 0.0 && info.entropy < 8.0);
+        }
+
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[7; 1000]), 0.0);
+        let every_byte: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&every_byte), 8.0);
+
+        let dir = std::env::temp_dir().join(format!("guardian-exe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("run.sh");
+        std::fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+        assert_eq!(analyze(&script), None);
+        // Starts like a PE but is not one
+        std::fs::write(&script, "MZ and then nothing").unwrap();
+        assert_eq!(analyze(&script), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+use crate::archive;
+use crate::canary;
+use crate::config::{ArchiveConfig, DaemonConfig, DiffConfig, EntropyConfig, WatchConfig};
+use crate::diff::ContentHistory;
+use crate::executable;
+use crate::platform;
+use crate::queue::EventSender;
+use crate::scanner::YaraScanner;
+use crate::snapshots::{snapshot_tag, SnapshotStore, SNAPSHOT_TAG};
+use anyhow::{anyhow, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use guardian_common::{EventType, FileOperation, LogEvent, Severity};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+/// Files larger than this are not hashed
+const MAX_HASH_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A configured watch path with its compiled exclusion patterns
+pub struct WatchEntry {
+    pub config: WatchConfig,
+    exclude: GlobSet,
+}
+
+impl WatchEntry {
+    pub fn new(config: WatchConfig) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &config.exclude {
+            builder.add(
+                Glob::new(pattern)
+                    .with_context(|| format!("Invalid exclude pattern: {}", pattern))?,
+            );
+        }
+
+        Ok(Self {
+            exclude: builder.build()?,
+            config,
+        })
+    }
+
+    /// Whether `path` (inside this entry) matches an exclusion pattern
+    fn is_excluded(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.config.path).unwrap_or(path);
+        self.exclude.is_match(relative)
+    }
+
+    /// Whether this entry's watch covers `path`
+    fn covers(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.config.path) {
+            Ok(relative) => self.config.recursive || relative.components().count() <= 1,
+            Err(_) => false,
+        }
+    }
+}
+
+/// Registry of all watch entries, resolving events to the entry that owns them
+pub struct WatchRegistry {
+    entries: Vec<WatchEntry>,
+}
+
+impl WatchRegistry {
+    pub fn new(configs: Vec<WatchConfig>) -> Result<Self> {
+        let entries = configs
+            .into_iter()
+            .map(WatchEntry::new)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Register every entry with the watcher, creating missing directories
+    fn watch_all(&self, watcher: &mut impl Watcher) -> Result<()> {
+        for entry in &self.entries {
+            let path = &entry.config.path;
+            info!(
+                "Watching path: {} (recursive: {}, scan: {})",
+                path.display(),
+                entry.config.recursive,
+                entry.config.scan_on_change
+            );
+
+            std::fs::create_dir_all(path)?;
+
+            let mode = if entry.config.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            watcher
+                .watch(path, mode)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// The most specific entry covering `path`, if it is not excluded there
+    pub fn resolve(&self, path: &Path) -> Option<&WatchEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.covers(path))
+            .max_by_key(|entry| entry.config.path.components().count())
+            .filter(|entry| !entry.is_excluded(path))
+    }
+}
+
+/// What the file monitor watches and how it inspects changed files,
+/// replaced as a whole on a config reload
+pub struct MonitorSettings {
+    pub registry: WatchRegistry,
+    pub archives: ArchiveConfig,
+    pub entropy: EntropyConfig,
+    pub diff: DiffConfig,
+}
+
+impl MonitorSettings {
+    pub fn new(config: &DaemonConfig) -> Result<Self> {
+        Ok(Self {
+            registry: WatchRegistry::new(config.watch.clone())?,
+            archives: config.archives.clone(),
+            entropy: config.entropy.clone(),
+            diff: config.diff.clone(),
+        })
+    }
+}
+
+enum Input {
+    Fs(notify::Result<Event>),
+    Reload(MonitorSettings, oneshot::Sender<Result<()>>),
+}
+
+/// Handle to the file monitor thread
+pub struct FileMonitor {
+    input: std::sync::mpsc::Sender<Input>,
+}
+
+impl FileMonitor {
+    /// Start file system monitoring for all configured paths
+    pub fn start(
+        tx: EventSender,
+        hostname: String,
+        settings: MonitorSettings,
+        scanner: Option<Arc<YaraScanner>>,
+        snapshots: Option<Arc<Mutex<SnapshotStore>>>,
+    ) -> Self {
+        let (input, inputs) = std::sync::mpsc::channel();
+        let monitor_input = input.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = run(
+                tx,
+                hostname,
+                settings,
+                scanner,
+                snapshots,
+                monitor_input,
+                inputs,
+            ) {
+                error!("File monitor error: {}", e);
+            }
+        });
+        Self { input }
+    }
+
+    /// Watch the paths in `settings` instead. The old watches stay in place
+    /// until every new path is watched, and do if one cannot be.
+    pub async fn reload(&self, settings: MonitorSettings) -> Result<()> {
+        let (done, result) = oneshot::channel();
+        self.input
+            .send(Input::Reload(settings, done))
+            .map_err(|_| anyhow!("File monitor is not running"))?;
+        result.await.context("File monitor stopped")?
+    }
+}
+
+/// A watcher reporting to `input` with every entry of `registry` registered
+fn watch(
+    registry: &WatchRegistry,
+    input: std::sync::mpsc::Sender<Input>,
+) -> Result<notify::RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let _ = input.send(Input::Fs(res));
+    })?;
+    registry.watch_all(&mut watcher)?;
+    Ok(watcher)
+}
+
+fn run(
+    tx: EventSender,
+    hostname: String,
+    mut settings: MonitorSettings,
+    scanner: Option<Arc<YaraScanner>>,
+    snapshots: Option<Arc<Mutex<SnapshotStore>>>,
+    input: std::sync::mpsc::Sender<Input>,
+    inputs: std::sync::mpsc::Receiver<Input>,
+) -> Result<()> {
+    let mut history = ContentHistory::new(settings.diff.clone(), snapshots.clone());
+
+    // Create watcher; it is only held to keep the watches in place
+    let mut _watcher = watch(&settings.registry, input.clone())?;
+
+    // Process file system events
+    for input_event in &inputs {
+        match input_event {
+            Input::Fs(Ok(event)) => {
+                if let Some(log_event) = process_fs_event(
+                    event,
+                    &hostname,
+                    &settings.registry,
+                    scanner.as_deref(),
+                    &settings.archives,
+                    &settings.entropy,
+                    &mut history,
+                ) {
+                    if tx.blocking_send(log_event).is_err() {
+                        error!("Failed to send event - channel closed");
+                        break;
+                    }
+                }
+            }
+            Input::Fs(Err(e)) => warn!("Watch error: {:?}", e),
+            Input::Reload(new, done) => {
+                let result = watch(&new.registry, input.clone()).map(|new_watcher| {
+                    // Dropping the old watcher removes its watches
+                    _watcher = new_watcher;
+                    if new.diff != settings.diff {
+                        history = ContentHistory::new(new.diff.clone(), snapshots.clone());
+                    }
+                    settings = new;
+                });
+                let _ = done.send(result);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert notify events to LogEvents
+fn process_fs_event(
+    event: Event,
+    hostname: &str,
+    registry: &WatchRegistry,
+    scanner: Option<&YaraScanner>,
+    archives: &ArchiveConfig,
+    entropy: &EntropyConfig,
+    history: &mut ContentHistory,
+) -> Option<LogEvent> {
+    let operation = match event.kind {
+        EventKind::Create(_) => FileOperation::Create,
+        // Each side of a rename also arrives on its own, as a modification
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => FileOperation::Rename,
+        EventKind::Modify(_) => FileOperation::Modify,
+        EventKind::Remove(_) => FileOperation::Delete,
+        _ => return None,
+    };
+
+    // Renames are reported under the new name
+    let file_path = match operation {
+        FileOperation::Rename => event.paths.last()?,
+        _ => event.paths.first()?,
+    };
+    // Canaries are reported by their own watch, and opening one to hash
+    // or scan it would look like an intruder reading it
+    if canary::is_canary(file_path) {
+        return None;
+    }
+    let entry = registry.resolve(file_path)?;
+    let path = file_path.to_string_lossy().to_string();
+
+    // Default severity, raised to the watch entry's base severity
+    let mut severity = if platform::is_sensitive_path(&path) {
+        Severity::High
+    } else if path.ends_with(".conf") || path.ends_with(".cfg") {
+        Severity::Medium
+    } else {
+        Severity::Low
+    }
+    .max(entry.config.severity);
+
+    let mut rules_matched = Vec::new();
+    let mut matched_rule_name = None;
+    let mut archive_matches = Vec::new();
+
+    // Scan file if scanning is enabled for this path and event is Create/Modify
+    if let Some(s) = scanner.filter(|_| entry.config.scan_on_change) {
+        if matches!(operation, FileOperation::Create | FileOperation::Modify) {
+            // Only scan regular files
+            if file_path.is_file() {
+                let matches = s.scan_file(&path);
+                if !matches.is_empty() {
+                    severity = Severity::Critical;
+                    matched_rule_name = Some(matches[0].clone()); // Use first match as main rule
+                    rules_matched = matches;
+                }
+
+                if archives.enabled {
+                    archive_matches = archive::scan(s, file_path, archives);
+                    for found in &archive_matches {
+                        severity = Severity::Critical;
+                        if matched_rule_name.is_none() {
+                            matched_rule_name = Some(found.rules[0].clone());
+                        }
+                        for rule in &found.rules {
+                            if !rules_matched.contains(rule) {
+                                rules_matched.push(rule.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let (hash, executable, diff) = match operation {
+        FileOperation::Create | FileOperation::Modify | FileOperation::Rename => (
+            hash_file(file_path),
+            executable::analyze(file_path),
+            history.update(file_path),
+        ),
+        _ => (None, None, None),
+    };
+
+    // Packed or encrypted code that no string rule matches
+    let high_entropy = executable
+        .as_ref()
+        .is_some_and(|exe| entropy.enabled && exe.entropy > entropy.threshold);
+    if high_entropy {
+        severity = severity.raised();
+    }
+
+    let user = crate::users::file_owner(file_path);
+    // Every version the console could restore, when this one is stored
+    let snapshots = history.snapshots(file_path);
+    let snapshots = match &hash {
+        Some(hash) if snapshots.contains(hash) => snapshots,
+        _ => Vec::new(),
+    };
+
+    let mut log_event = LogEvent::new(
+        severity,
+        EventType::FileIntegrity {
+            path,
+            operation,
+            hash,
+            user,
+            executable,
+            process: None,
+            diff,
+        },
+        hostname.to_string(),
+    )
+    .with_tag("file_monitor");
+    if high_entropy {
+        log_event = log_event.with_tag("high_entropy");
+    }
+    if !snapshots.is_empty() {
+        log_event = log_event.with_tag(SNAPSHOT_TAG);
+    }
+    for version in snapshots {
+        log_event = log_event.with_tag(snapshot_tag(&version));
+    }
+
+    // Add tags for YARA matches
+    for rule in rules_matched {
+        log_event = log_event.with_tag(format!("yara:{}", rule));
+    }
+
+    // Name the archive and each matching entry inside it
+    if !archive_matches.is_empty() {
+        log_event = log_event.with_tag(format!("archive:{}", file_path.display()));
+    }
+    for found in archive_matches {
+        log_event = log_event.with_tag(format!("archive_entry:{}", found.entry));
+    }
+
+    if let Some(rule) = matched_rule_name {
+        log_event = log_event.with_rule(rule);
+    }
+
+    Some(log_event)
+}
+
+/// SHA-256 of a regular file, skipping files over `MAX_HASH_SIZE`
+pub fn hash_file(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_HASH_SIZE {
+        return None;
+    }
+
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) => {
+                warn!("Failed to hash {}: {}", path.display(), e);
+                return None;
+            }
+        }
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_resolves_most_specific_entry() {
+        let mut uploads = WatchConfig::new("/srv/app/uploads");
+        uploads.exclude = vec!["*.tmp".to_string()];
+        uploads.severity = Severity::Medium;
+
+        let mut logs = WatchConfig::new("/srv/logs");
+        logs.recursive = false;
+
+        let registry =
+            WatchRegistry::new(vec![WatchConfig::new("/srv/app"), uploads, logs]).unwrap();
+
+        let entry = registry
+            .resolve(Path::new("/srv/app/uploads/a.php"))
+            .unwrap();
+        assert_eq!(entry.config.severity, Severity::Medium);
+
+        let entry = registry.resolve(Path::new("/srv/app/index.php")).unwrap();
+        assert_eq!(entry.config.path, Path::new("/srv/app"));
+
+        // Excluded by the uploads entry even though /srv/app also covers it
+        assert!(registry
+            .resolve(Path::new("/srv/app/uploads/x/part.tmp"))
+            .is_none());
+
+        // Non-recursive entries only cover direct children
+        assert!(registry.resolve(Path::new("/srv/logs/app.log")).is_some());
+        assert!(registry
+            .resolve(Path::new("/srv/logs/old/app.log"))
+            .is_none());
+        assert!(registry.resolve(Path::new("/var/tmp/x")).is_none());
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let path = std::env::temp_dir().join(format!("hash-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "abc").unwrap();
+        let hash = hash_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            hash.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert!(hash_file(&std::env::temp_dir()).is_none());
+    }
+
+    #[test]
+    fn test_invalid_exclude_pattern() {
+        let mut config = WatchConfig::new("/srv");
+        config.exclude = vec!["[".to_string()];
+        assert!(WatchRegistry::new(vec![config]).is_err());
+    }
+}
@@ -0,0 +1,994 @@
+//! Fluentd forward protocol input, so fluent-bit and Fluentd agents on other
+//! machines can ship logs into the pipeline. Message, Forward and
+//! PackedForward modes are accepted, gzip-compressed or not, and chunks are
+//! acknowledged once their events are queued. Routes map tags to event types.
+//! With a shared key, senders must pass Fluent's handshake before sending.
+
+use crate::config::{FluentConfig, FluentEventType, FluentRoute};
+use crate::queue::EventSender;
+use crate::{auth_log, packages};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use flate2::read::MultiGzDecoder;
+use guardian_common::{EventType, LogEvent};
+use ring::rand::{SecureRandom, SystemRandom};
+use rmp::Marker;
+use serde_json::{Map, Value as Json};
+use sha2::{Digest, Sha512};
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Deepest nesting of arrays and maps accepted
+const MAX_DEPTH: usize = 32;
+
+/// Extension type of Fluent's nanosecond `EventTime`
+const EVENT_TIME_EXT: i8 = 0;
+
+/// A decoded MessagePack value
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Ext(i8, Vec<u8>),
+}
+
+#[derive(Debug)]
+enum DecodeError {
+    /// More bytes are needed
+    Incomplete,
+    Invalid(String),
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).ok_or(DecodeError::Incomplete)?;
+        let bytes = self.buf.get(self.pos..end).ok_or(DecodeError::Incomplete)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, len: usize) -> Result<u64, DecodeError> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |n, b| (n << 8) | u64::from(*b)))
+    }
+
+    fn int(&mut self, len: usize) -> Result<i64, DecodeError> {
+        let bits = len as u32 * 8;
+        let n = self.uint(len)?;
+        // Sign-extend from `bits` wide
+        Ok(((n << (64 - bits)) as i64) >> (64 - bits))
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, DecodeError> {
+        let bytes = self.take(len)?;
+        Ok(Value::Str(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value, DecodeError> {
+        let mut items = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            items.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, DecodeError> {
+        let mut entries = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            let key = self.value(depth + 1)?;
+            entries.push((key, self.value(depth + 1)?));
+        }
+        Ok(Value::Map(entries))
+    }
+
+    fn ext(&mut self, len: usize) -> Result<Value, DecodeError> {
+        let kind = self.int(1)? as i8;
+        Ok(Value::Ext(kind, self.take(len)?.to_vec()))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::Invalid("nesting too deep".to_string()));
+        }
+        let marker = Marker::from_u8(self.take(1)?[0]);
+        Ok(match marker {
+            Marker::FixPos(n) => Value::Int(i64::from(n)),
+            Marker::FixNeg(n) => Value::Int(i64::from(n)),
+            Marker::Null => Value::Nil,
+            Marker::True => Value::Bool(true),
+            Marker::False => Value::Bool(false),
+            Marker::U8 => Value::UInt(self.uint(1)?),
+            Marker::U16 => Value::UInt(self.uint(2)?),
+            Marker::U32 => Value::UInt(self.uint(4)?),
+            Marker::U64 => Value::UInt(self.uint(8)?),
+            Marker::I8 => Value::Int(self.int(1)?),
+            Marker::I16 => Value::Int(self.int(2)?),
+            Marker::I32 => Value::Int(self.int(4)?),
+            Marker::I64 => Value::Int(self.int(8)?),
+            Marker::F32 => Value::Float(f64::from(f32::from_bits(self.uint(4)? as u32))),
+            Marker::F64 => Value::Float(f64::from_bits(self.uint(8)?)),
+            Marker::FixStr(len) => self.string(len.into())?,
+            Marker::Str8 => {
+                let len = self.uint(1)? as usize;
+                self.string(len)?
+            }
+            Marker::Str16 => {
+                let len = self.uint(2)? as usize;
+                self.string(len)?
+            }
+            Marker::Str32 => {
+                let len = self.uint(4)? as usize;
+                self.string(len)?
+            }
+            Marker::Bin8 => {
+                let len = self.uint(1)? as usize;
+                Value::Bin(self.take(len)?.to_vec())
+            }
+            Marker::Bin16 => {
+                let len = self.uint(2)? as usize;
+                Value::Bin(self.take(len)?.to_vec())
+            }
+            Marker::Bin32 => {
+                let len = self.uint(4)? as usize;
+                Value::Bin(self.take(len)?.to_vec())
+            }
+            Marker::FixArray(len) => self.array(len.into(), depth)?,
+            Marker::Array16 => {
+                let len = self.uint(2)? as usize;
+                self.array(len, depth)?
+            }
+            Marker::Array32 => {
+                let len = self.uint(4)? as usize;
+                self.array(len, depth)?
+            }
+            Marker::FixMap(len) => self.map(len.into(), depth)?,
+            Marker::Map16 => {
+                let len = self.uint(2)? as usize;
+                self.map(len, depth)?
+            }
+            Marker::Map32 => {
+                let len = self.uint(4)? as usize;
+                self.map(len, depth)?
+            }
+            Marker::FixExt1 => self.ext(1)?,
+            Marker::FixExt2 => self.ext(2)?,
+            Marker::FixExt4 => self.ext(4)?,
+            Marker::FixExt8 => self.ext(8)?,
+            Marker::FixExt16 => self.ext(16)?,
+            Marker::Ext8 => {
+                let len = self.uint(1)? as usize;
+                self.ext(len)?
+            }
+            Marker::Ext16 => {
+                let len = self.uint(2)? as usize;
+                self.ext(len)?
+            }
+            Marker::Ext32 => {
+                let len = self.uint(4)? as usize;
+                self.ext(len)?
+            }
+            Marker::Reserved => return Err(DecodeError::Invalid("reserved marker".to_string())),
+        })
+    }
+}
+
+/// Decode one value from the start of `buf`, with the bytes it took
+fn decode(buf: &[u8]) -> Result<(Value, usize), DecodeError> {
+    let mut decoder = Decoder { buf, pos: 0 };
+    let value = decoder.value(0)?;
+    Ok((value, decoder.pos))
+}
+
+/// Finds where a value ends in a growing buffer without decoding it, picking
+/// up where the last scan stopped, so a message arriving in many reads is
+/// only walked once
+#[derive(Debug, Default)]
+struct Framer {
+    /// Bytes already scanned
+    pos: usize,
+    /// Values still expected by each open array or map
+    open: Vec<u64>,
+}
+
+impl Framer {
+    /// Length of the first value in `buf` once all of it has arrived. `buf`
+    /// must only grow between calls until a length is returned.
+    fn scan(&mut self, buf: &[u8]) -> Result<Option<usize>, DecodeError> {
+        while let Some(&byte) = buf.get(self.pos) {
+            // Bytes of the length field, then either `fixed` plus length
+            // payload bytes or, with `per` set, `fixed` plus length times
+            // `per` nested values
+            let (len_bytes, fixed, per) = match Marker::from_u8(byte) {
+                Marker::FixPos(_)
+                | Marker::FixNeg(_)
+                | Marker::Null
+                | Marker::True
+                | Marker::False => (0, 0, 0),
+                Marker::U8 | Marker::I8 => (0, 1, 0),
+                Marker::U16 | Marker::I16 => (0, 2, 0),
+                Marker::U32 | Marker::I32 | Marker::F32 => (0, 4, 0),
+                Marker::U64 | Marker::I64 | Marker::F64 => (0, 8, 0),
+                Marker::FixStr(len) => (0, u64::from(len), 0),
+                Marker::Str8 | Marker::Bin8 => (1, 0, 0),
+                Marker::Str16 | Marker::Bin16 => (2, 0, 0),
+                Marker::Str32 | Marker::Bin32 => (4, 0, 0),
+                Marker::FixExt1 => (0, 2, 0),
+                Marker::FixExt2 => (0, 3, 0),
+                Marker::FixExt4 => (0, 5, 0),
+                Marker::FixExt8 => (0, 9, 0),
+                Marker::FixExt16 => (0, 17, 0),
+                Marker::Ext8 => (1, 1, 0),
+                Marker::Ext16 => (2, 1, 0),
+                Marker::Ext32 => (4, 1, 0),
+                Marker::FixArray(len) => (0, u64::from(len), 1),
+                Marker::FixMap(len) => (0, 2 * u64::from(len), 2),
+                Marker::Array16 => (2, 0, 1),
+                Marker::Array32 => (4, 0, 1),
+                Marker::Map16 => (2, 0, 2),
+                Marker::Map32 => (4, 0, 2),
+                Marker::Reserved => {
+                    return Err(DecodeError::Invalid("reserved marker".to_string()))
+                }
+            };
+            let header = 1 + len_bytes;
+            let Some(field) = buf.get(self.pos + 1..self.pos + header) else {
+                return Ok(None);
+            };
+            let length = field.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b));
+
+            if per == 0 {
+                let end = (self.pos + header) as u64 + fixed + length;
+                if (buf.len() as u64) < end {
+                    return Ok(None);
+                }
+                self.pos = end as usize;
+            } else {
+                self.pos += header;
+                let nested = fixed + length * per;
+                if nested > 0 {
+                    if self.open.len() >= MAX_DEPTH {
+                        return Err(DecodeError::Invalid("nesting too deep".to_string()));
+                    }
+                    self.open.push(nested);
+                    continue;
+                }
+            }
+
+            // A value ended; it may complete the containers holding it
+            loop {
+                match self.open.last_mut() {
+                    None => {
+                        let end = self.pos;
+                        *self = Self::default();
+                        return Ok(Some(end));
+                    }
+                    Some(left) => {
+                        *left -= 1;
+                        if *left > 0 {
+                            break;
+                        }
+                        self.open.pop();
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Value {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Str(s) => Some(s.as_bytes()),
+            Value::Bin(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Event time: integer or float seconds, or an `EventTime` extension
+    fn as_time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Value::Int(secs) => Utc.timestamp_opt(*secs, 0).single(),
+            Value::UInt(secs) => Utc.timestamp_opt(i64::try_from(*secs).ok()?, 0).single(),
+            Value::Float(secs) => {
+                let nanos = (secs.fract() * 1e9) as u32;
+                Utc.timestamp_opt(secs.trunc() as i64, nanos).single()
+            }
+            Value::Ext(EVENT_TIME_EXT, bytes) if bytes.len() == 8 => {
+                let secs = u32::from_be_bytes(bytes[..4].try_into().ok()?);
+                let nanos = u32::from_be_bytes(bytes[4..].try_into().ok()?);
+                Utc.timestamp_opt(i64::from(secs), nanos).single()
+            }
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        match self {
+            Value::Nil | Value::Ext(..) => Json::Null,
+            Value::Bool(b) => Json::from(*b),
+            Value::Int(n) => Json::from(*n),
+            Value::UInt(n) => Json::from(*n),
+            Value::Float(n) => Json::from(*n),
+            Value::Str(s) => Json::from(s.clone()),
+            Value::Bin(bytes) => Json::from(String::from_utf8_lossy(bytes).into_owned()),
+            Value::Array(items) => Json::Array(items.iter().map(Value::to_json).collect()),
+            Value::Map(entries) => {
+                let mut map = Map::new();
+                for (key, value) in entries {
+                    let key = match key {
+                        Value::Str(s) => s.clone(),
+                        other => other.to_json().to_string(),
+                    };
+                    map.insert(key, value.to_json());
+                }
+                Json::Object(map)
+            }
+        }
+    }
+}
+
+/// Records of one forward-protocol message
+#[derive(Debug)]
+struct Batch {
+    tag: String,
+    entries: Vec<(Option<DateTime<Utc>>, Json)>,
+    /// Chunk id to acknowledge
+    chunk: Option<String>,
+}
+
+/// Unpack Message (`[tag, time, record]`), Forward (`[tag, [[time, record]...]]`)
+/// and PackedForward (`[tag, bin]`) modes
+fn unpack(message: Value, max_message: usize) -> Result<Batch> {
+    let Value::Array(mut parts) = message else {
+        bail!("message is not an array");
+    };
+    if parts.len() < 2 {
+        bail!("message has {} parts", parts.len());
+    }
+    let tag = parts[0]
+        .as_str()
+        .ok_or_else(|| anyhow!("tag is not a string"))?
+        .to_string();
+
+    let message_mode = parts[1].as_time().is_some();
+    let option = parts.get(if message_mode { 3 } else { 2 }).cloned();
+    let chunk = option
+        .as_ref()
+        .and_then(|o| o.get("chunk"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    let entries = if message_mode {
+        let record = parts
+            .get(2)
+            .ok_or_else(|| anyhow!("message has no record"))?;
+        vec![(parts[1].as_time(), record.to_json())]
+    } else {
+        let payload = parts.swap_remove(1);
+        let entries = match payload {
+            Value::Array(entries) => entries,
+            Value::Bin(bytes) => unpack_entries(&bytes, option.as_ref(), max_message)?,
+            Value::Str(s) => unpack_entries(s.as_bytes(), option.as_ref(), max_message)?,
+            _ => bail!("unexpected entries type"),
+        };
+        entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Value::Array(pair) if pair.len() >= 2 => {
+                    Some((pair[0].as_time(), pair[1].to_json()))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+    Ok(Batch {
+        tag,
+        entries,
+        chunk,
+    })
+}
+
+/// Concatenated `[time, record]` entries of PackedForward mode
+fn unpack_entries(bytes: &[u8], option: Option<&Value>, max_message: usize) -> Result<Vec<Value>> {
+    let compressed = option
+        .and_then(|o| o.get("compressed"))
+        .and_then(Value::as_str);
+    let inflated;
+    let mut bytes = match compressed {
+        None | Some("text") => bytes,
+        Some("gzip") => {
+            let mut out = Vec::new();
+            MultiGzDecoder::new(bytes)
+                .take(max_message as u64 + 1)
+                .read_to_end(&mut out)?;
+            if out.len() > max_message {
+                bail!("decompressed entries exceed {} bytes", max_message);
+            }
+            inflated = out;
+            &inflated[..]
+        }
+        Some(other) => bail!("unsupported compression {}", other),
+    };
+
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+        let (entry, used) = decode(bytes).map_err(|e| anyhow!("bad packed entry: {:?}", e))?;
+        entries.push(entry);
+        bytes = &bytes[used..];
+    }
+    Ok(entries)
+}
+
+/// Fluent tag pattern match: `*` is one dot-separated part, `**` any number
+fn tag_matches(pattern: &str, tag: &str) -> bool {
+    fn matches(pattern: &[&str], tag: &[&str]) -> bool {
+        match (pattern.first(), tag.first()) {
+            (None, None) => true,
+            (Some(&"**"), _) => {
+                matches(&pattern[1..], tag) || (!tag.is_empty() && matches(pattern, &tag[1..]))
+            }
+            (Some(p), Some(t)) => (*p == "*" || p == t) && matches(&pattern[1..], &tag[1..]),
+            _ => false,
+        }
+    }
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let tag: Vec<&str> = tag.split('.').collect();
+    matches(&pattern, &tag)
+}
+
+fn text_field(record: &Json, key: &str) -> Option<String> {
+    match record.get(key)? {
+        Json::String(s) => Some(s.clone()),
+        Json::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Turn one record into an event following the first route matching `tag`;
+/// `None` when the route's parser does not recognize the line. `start`
+/// refuses `guardian` routes unless senders authenticate.
+fn to_event(
+    routes: &[FluentRoute],
+    tag: &str,
+    time: Option<DateTime<Utc>>,
+    record: Json,
+    peer: IpAddr,
+) -> Option<LogEvent> {
+    let route = routes.iter().find(|route| tag_matches(&route.tag, tag));
+    let message = match route.and_then(|r| r.message_key.as_deref()) {
+        Some(key) => text_field(&record, key),
+        None => text_field(&record, "log").or_else(|| text_field(&record, "message")),
+    };
+    let hostname = text_field(&record, "hostname")
+        .or_else(|| text_field(&record, "host"))
+        .unwrap_or_else(|| peer.to_string());
+
+    let mut event = match route.map(|r| r.event_type) {
+        Some(FluentEventType::Guardian) => serde_json::from_value::<LogEvent>(record).ok()?,
+        Some(FluentEventType::AuthEvent) => {
+            auth_log::to_event(auth_log::parse_line(message.as_deref()?)?, &hostname).ok()?
+        }
+        Some(FluentEventType::PackageChange) => {
+            packages::to_event(packages::parse_line(message.as_deref()?)?, &hostname).ok()?
+        }
+        Some(FluentEventType::SystemLog) | None => {
+            let source = text_field(&record, "ident")
+                .or_else(|| text_field(&record, "source"))
+                .unwrap_or_else(|| tag.to_string());
+            let level = text_field(&record, "level")
+                .or_else(|| text_field(&record, "pri"))
+                .unwrap_or_else(|| "info".to_string());
+            let severity = route.map_or(guardian_common::Severity::Info, |r| r.severity);
+            LogEvent::new(
+                severity,
+                EventType::SystemLog {
+                    source,
+                    level,
+                    message: message.unwrap_or_else(|| record.to_string()),
+                },
+                hostname,
+            )
+        }
+    };
+    if let Some(time) = time {
+        event.timestamp = time;
+    }
+    Some(event.with_tag("fluent"))
+}
+
+/// Hex SHA-512 over the handshake fields, proving knowledge of the shared key
+fn key_digest(salt: &[u8], hostname: &[u8], nonce: &[u8], shared_key: &str) -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(salt);
+    hasher.update(hostname);
+    hasher.update(nonce);
+    hasher.update(shared_key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// `["HELO", {nonce, auth, keepalive}]`, opening the handshake; user
+/// authentication is not requested
+fn helo(nonce: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = rmp::encode::write_array_len(&mut out, 2);
+    let _ = rmp::encode::write_str(&mut out, "HELO");
+    let _ = rmp::encode::write_map_len(&mut out, 3);
+    let _ = rmp::encode::write_str(&mut out, "nonce");
+    let _ = rmp::encode::write_bin(&mut out, nonce);
+    let _ = rmp::encode::write_str(&mut out, "auth");
+    let _ = rmp::encode::write_str(&mut out, "");
+    let _ = rmp::encode::write_str(&mut out, "keepalive");
+    let _ = rmp::encode::write_bool(&mut out, true);
+    out
+}
+
+/// Salt the sender chose, when its `["PING", hostname, salt, digest, ...]`
+/// proves it holds `shared_key`
+fn check_ping(ping: &Value, nonce: &[u8], shared_key: &str) -> Result<Vec<u8>> {
+    let Value::Array(parts) = ping else {
+        bail!("PING is not an array");
+    };
+    if parts.first().and_then(Value::as_str) != Some("PING") || parts.len() < 4 {
+        bail!("expected PING");
+    }
+    let hostname = parts[1]
+        .as_bytes()
+        .ok_or_else(|| anyhow!("bad PING hostname"))?;
+    let salt = parts[2]
+        .as_bytes()
+        .ok_or_else(|| anyhow!("bad PING salt"))?;
+    if parts[3].as_str() != Some(key_digest(salt, hostname, nonce, shared_key).as_str()) {
+        bail!("shared key mismatch");
+    }
+    Ok(salt.to_vec())
+}
+
+/// `["PONG", ok, reason, hostname, digest]`, ending the handshake
+fn pong(ok: bool, reason: &str, hostname: &str, digest: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = rmp::encode::write_array_len(&mut out, 5);
+    let _ = rmp::encode::write_str(&mut out, "PONG");
+    let _ = rmp::encode::write_bool(&mut out, ok);
+    let _ = rmp::encode::write_str(&mut out, reason);
+    let _ = rmp::encode::write_str(&mut out, hostname);
+    let _ = rmp::encode::write_str(&mut out, digest);
+    out
+}
+
+/// `{"ack": chunk}` response for at-least-once senders
+fn ack(chunk: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    // Writes to a Vec cannot fail
+    let _ = rmp::encode::write_map_len(&mut out, 1);
+    let _ = rmp::encode::write_str(&mut out, "ack");
+    let _ = rmp::encode::write_str(&mut out, chunk);
+    out
+}
+
+/// Messages read off one connection
+struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    /// Start of the bytes not yet returned as messages
+    start: usize,
+    framer: Framer,
+    read: Vec<u8>,
+    max_message: usize,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, max_message: usize) -> Self {
+        Self {
+            stream,
+            buf: Vec::new(),
+            start: 0,
+            framer: Framer::default(),
+            read: vec![0u8; 64 * 1024],
+            max_message,
+        }
+    }
+
+    /// Next message, `None` once the sender closes
+    async fn next(&mut self) -> Result<Option<Value>> {
+        loop {
+            let pending = &self.buf[self.start..];
+            match self.framer.scan(pending) {
+                Ok(Some(len)) => {
+                    let message = match decode(&pending[..len]) {
+                        Ok((message, _)) => message,
+                        Err(DecodeError::Invalid(e)) => bail!("invalid MessagePack: {}", e),
+                        Err(DecodeError::Incomplete) => bail!("truncated MessagePack"),
+                    };
+                    self.start += len;
+                    return Ok(Some(message));
+                }
+                Ok(None) => {}
+                Err(DecodeError::Invalid(e)) => bail!("invalid MessagePack: {}", e),
+                Err(DecodeError::Incomplete) => bail!("truncated MessagePack"),
+            }
+
+            if pending.len() > self.max_message {
+                bail!("message exceeds {} bytes", self.max_message);
+            }
+            self.buf.drain(..self.start);
+            self.start = 0;
+            let n = self.stream.read(&mut self.read).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&self.read[..n]);
+        }
+    }
+
+    async fn handshake(&mut self, shared_key: &str) -> Result<()> {
+        let mut nonce = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("cannot generate a nonce"))?;
+        self.stream.write_all(&helo(&nonce)).await?;
+        let ping = self
+            .next()
+            .await?
+            .ok_or_else(|| anyhow!("closed before PING"))?;
+        match check_ping(&ping, &nonce, shared_key) {
+            Ok(salt) => {
+                let hostname = crate::platform::hostname();
+                let digest = key_digest(&salt, hostname.as_bytes(), &nonce, shared_key);
+                self.stream
+                    .write_all(&pong(true, "", &hostname, &digest))
+                    .await?;
+                Ok(())
+            }
+            Err(e) => {
+                self.stream
+                    .write_all(&pong(false, "authentication failed", "", ""))
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    config: Arc<FluentConfig>,
+    tx: EventSender,
+) -> Result<()> {
+    let mut connection = Connection::new(stream, config.max_message);
+    if let Some(shared_key) = &config.shared_key {
+        connection.handshake(shared_key).await?;
+    }
+    while let Some(message) = connection.next().await? {
+        let batch = unpack(message, config.max_message)?;
+        for (time, record) in batch.entries {
+            if let Some(event) = to_event(&config.routes, &batch.tag, time, record, peer.ip()) {
+                if tx.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(chunk) = batch.chunk {
+            connection.stream.write_all(&ack(&chunk)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Listen for forward protocol connections and feed their records into `tx`
+pub async fn start(config: &FluentConfig, tx: EventSender) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let listen: SocketAddr = config.listen.parse()?;
+    if config.shared_key.is_none() {
+        if !listen.ip().is_loopback() {
+            bail!(
+                "Fluent input on {} needs a shared_key to listen beyond loopback",
+                listen
+            );
+        }
+        // Any local process could otherwise pass off events as Guardian's
+        if let Some(route) = config
+            .routes
+            .iter()
+            .find(|route| route.event_type == FluentEventType::Guardian)
+        {
+            bail!(
+                "Fluent route {} takes Guardian events and needs a shared_key",
+                route.tag
+            );
+        }
+    }
+    let listener = TcpListener::bind(listen).await?;
+    info!("Receiving Fluent forward protocol on {}", config.listen);
+
+    let config = Arc::new(config.clone());
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Fluent accept failed: {}", e);
+                    continue;
+                }
+            };
+            let (config, tx) = (config.clone(), tx.clone());
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(stream, peer, config, tx).await {
+                    debug!("Closing Fluent connection from {}: {}", peer, e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use guardian_common::Severity;
+    use std::io::Write;
+
+    fn write_record(out: &mut Vec<u8>, log: &str) {
+        rmp::encode::write_map_len(out, 2).unwrap();
+        rmp::encode::write_str(out, "log").unwrap();
+        rmp::encode::write_str(out, log).unwrap();
+        rmp::encode::write_str(out, "hostname").unwrap();
+        rmp::encode::write_str(out, "bastion").unwrap();
+    }
+
+    fn write_event_time(out: &mut Vec<u8>, secs: u32, nanos: u32) {
+        rmp::encode::write_ext_meta(out, 8, EVENT_TIME_EXT).unwrap();
+        out.extend_from_slice(&secs.to_be_bytes());
+        out.extend_from_slice(&nanos.to_be_bytes());
+    }
+
+    #[test]
+    fn test_forward_modes() {
+        // Message mode with an EventTime and a chunk to acknowledge
+        let mut message = Vec::new();
+        rmp::encode::write_array_len(&mut message, 4).unwrap();
+        rmp::encode::write_str(&mut message, "app.web").unwrap();
+        write_event_time(&mut message, 1_700_000_000, 500_000_000);
+        write_record(&mut message, "GET /");
+        rmp::encode::write_map_len(&mut message, 1).unwrap();
+        rmp::encode::write_str(&mut message, "chunk").unwrap();
+        rmp::encode::write_str(&mut message, "abc").unwrap();
+
+        // A partial message waits for more bytes
+        assert!(matches!(
+            decode(&message[..message.len() - 2]),
+            Err(DecodeError::Incomplete)
+        ));
+        let (value, used) = decode(&message).unwrap();
+        assert_eq!(used, message.len());
+        let batch = unpack(value, 1024).unwrap();
+        assert_eq!(batch.tag, "app.web");
+        assert_eq!(batch.chunk.as_deref(), Some("abc"));
+        assert_eq!(
+            batch.entries[0].0.unwrap().to_rfc3339(),
+            "2023-11-14T22:13:20.500+00:00"
+        );
+        assert_eq!(batch.entries[0].1["log"], "GET /");
+
+        // Gzipped PackedForward mode with integer times
+        let mut packed = Vec::new();
+        for line in ["one", "two"] {
+            rmp::encode::write_array_len(&mut packed, 2).unwrap();
+            rmp::encode::write_uint(&mut packed, 1_700_000_000).unwrap();
+            write_record(&mut packed, line);
+        }
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&packed).unwrap();
+        let mut message = Vec::new();
+        rmp::encode::write_array_len(&mut message, 3).unwrap();
+        rmp::encode::write_str(&mut message, "auth").unwrap();
+        rmp::encode::write_bin(&mut message, &gz.finish().unwrap()).unwrap();
+        rmp::encode::write_map_len(&mut message, 1).unwrap();
+        rmp::encode::write_str(&mut message, "compressed").unwrap();
+        rmp::encode::write_str(&mut message, "gzip").unwrap();
+        let batch = unpack(decode(&message).unwrap().0, 1024).unwrap();
+        assert_eq!(batch.entries.len(), 2);
+        assert_eq!(batch.entries[1].1["log"], "two");
+        assert!(unpack(decode(&message).unwrap().0, 16).is_err());
+
+        assert_eq!(
+            decode(&ack("abc")).unwrap().0.get("ack").unwrap().as_str(),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn test_framer() {
+        let mut message = Vec::new();
+        rmp::encode::write_array_len(&mut message, 3).unwrap();
+        rmp::encode::write_str(&mut message, "app").unwrap();
+        rmp::encode::write_array_len(&mut message, 2).unwrap();
+        for line in ["one", "two"] {
+            rmp::encode::write_array_len(&mut message, 2).unwrap();
+            write_event_time(&mut message, 1_700_000_000, 0);
+            write_record(&mut message, line);
+        }
+        rmp::encode::write_map_len(&mut message, 0).unwrap();
+        let len = message.len();
+        message.extend_from_slice(&message.clone());
+
+        // Bytes arriving one at a time are only scanned once
+        let mut framer = Framer::default();
+        for end in 1..len {
+            assert_eq!(framer.scan(&message[..end]).unwrap(), None);
+        }
+        assert_eq!(framer.scan(&message[..len + 3]).unwrap(), Some(len));
+        assert_eq!(framer.scan(&message[len..]).unwrap(), Some(len));
+        assert_eq!(decode(&message[..len]).unwrap().1, len);
+
+        let mut deep = vec![0x91; MAX_DEPTH + 1];
+        deep.push(0xc0);
+        assert!(Framer::default().scan(&deep).is_err());
+        assert!(Framer::default().scan(&[0xc1]).is_err());
+    }
+
+    #[test]
+    fn test_handshake() {
+        let nonce = [7u8; 16];
+        let ping = |key: &str| {
+            Value::Array(vec![
+                Value::Str("PING".to_string()),
+                Value::Str("web-1".to_string()),
+                Value::Str("salt".to_string()),
+                Value::Str(key_digest(b"salt", b"web-1", &nonce, key)),
+                Value::Str(String::new()),
+                Value::Str(String::new()),
+            ])
+        };
+        assert_eq!(
+            check_ping(&ping("secret"), &nonce, "secret").unwrap(),
+            b"salt"
+        );
+        assert!(check_ping(&ping("guess"), &nonce, "secret").is_err());
+        assert!(check_ping(&Value::Str("PING".to_string()), &nonce, "secret").is_err());
+
+        let (helo, _) = decode(&helo(&nonce)).unwrap();
+        let Value::Array(parts) = helo else {
+            panic!("HELO is not an array");
+        };
+        assert_eq!(parts[0].as_str(), Some("HELO"));
+        assert_eq!(
+            parts[1].get("nonce").and_then(Value::as_bytes),
+            Some(&nonce[..])
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        assert!(tag_matches("auth.*", "auth.sshd"));
+        assert!(!tag_matches("auth.*", "auth.sshd.extra"));
+        assert!(tag_matches("kube.**", "kube"));
+        assert!(tag_matches("kube.**", "kube.a.b"));
+        assert!(tag_matches("**.pkg", "host1.pkg"));
+
+        let routes = vec![
+            FluentRoute {
+                tag: "auth.**".to_string(),
+                event_type: FluentEventType::AuthEvent,
+                message_key: None,
+                severity: Severity::Info,
+            },
+            FluentRoute {
+                tag: "app.*".to_string(),
+                event_type: FluentEventType::SystemLog,
+                message_key: Some("msg".to_string()),
+                severity: Severity::Low,
+            },
+        ];
+        let peer: IpAddr = "192.0.2.7".parse().unwrap();
+
+        let auth = to_event(
+            &routes,
+            "auth.secure",
+            None,
+            serde_json::json!({
+                "log": "Oct 17 03:31:10 bastion sshd[99]: Failed password for root from 198.51.100.2 port 4711 ssh2",
+                "hostname": "bastion",
+            }),
+            peer,
+        )
+        .unwrap();
+        assert_eq!(auth.hostname, "bastion");
+        assert_eq!(auth.severity, Severity::Low);
+        assert!(matches!(auth.event_type, EventType::AuthEvent { .. }));
+        assert!(auth.tags.contains(&"fluent".to_string()));
+        assert!(to_event(
+            &routes,
+            "auth.secure",
+            None,
+            serde_json::json!({ "log": "not an auth line" }),
+            peer
+        )
+        .is_none());
+
+        let app = to_event(
+            &routes,
+            "app.api",
+            None,
+            serde_json::json!({ "msg": "started", "level": "warn" }),
+            peer,
+        )
+        .unwrap();
+        assert_eq!(app.hostname, "192.0.2.7");
+        assert_eq!(app.severity, Severity::Low);
+        assert_eq!(
+            app.event_type,
+            EventType::SystemLog {
+                source: "app.api".to_string(),
+                level: "warn".to_string(),
+                message: "started".to_string(),
+            }
+        );
+
+        let forwarded = LogEvent::new(
+            Severity::High,
+            EventType::SystemLog {
+                source: "x".to_string(),
+                level: "info".to_string(),
+                message: "y".to_string(),
+            },
+            "web-9".to_string(),
+        );
+        let guardian = vec![FluentRoute {
+            tag: "guardian".to_string(),
+            event_type: FluentEventType::Guardian,
+            message_key: None,
+            severity: Severity::Info,
+        }];
+        let event = to_event(
+            &guardian,
+            "guardian",
+            None,
+            serde_json::to_value(&forwarded).unwrap(),
+            peer,
+        )
+        .unwrap();
+        assert_eq!(event.id, forwarded.id);
+        assert_eq!(event.hostname, "web-9");
+    }
+}
@@ -0,0 +1,407 @@
+//! Ships a copy of every emitted frame to a remote collector over TLS, so one
+//! daemon per server can report to a central console. Frames stay buffered
+//! until the collector acknowledges them and are resent after a reconnect.
+
+use crate::config::ForwardConfig;
+use crate::outbox::StopSignal;
+use anyhow::{anyhow, bail, Context, Result};
+use guardian_common::ipc::{read_frame, write_frame, Frame, PROTOCOL_VERSION};
+use guardian_common::{AgentHello, ControlCommand, ForwardStats};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Notify};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+
+/// First and longest wait between connection attempts
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Time allowed for the TCP connection and TLS handshake
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Pending {
+    /// Unacknowledged frames in sequence order
+    frames: VecDeque<(u64, Arc<Frame>)>,
+    next_seq: u64,
+    dropped: u64,
+}
+
+pub struct Forwarder {
+    limit: usize,
+    pending: Mutex<Pending>,
+    wake: Notify,
+    stop: StopSignal,
+    /// Where sampling changes asked for by the collector go
+    control: Option<mpsc::Sender<ControlCommand>>,
+}
+
+impl Forwarder {
+    /// Check the TLS settings and start connecting to the collector
+    pub fn start(
+        config: ForwardConfig,
+        hello: AgentHello,
+        control: Option<mpsc::Sender<ControlCommand>>,
+    ) -> Result<Arc<Self>> {
+        let (connector, server_name) = tls_target(&config)?;
+
+        let forwarder = Arc::new(Self {
+            limit: config.buffer.max(1),
+            pending: Mutex::new(Pending::default()),
+            wake: Notify::new(),
+            stop: StopSignal::default(),
+            control,
+        });
+        let task = forwarder.clone();
+        tokio::spawn(async move {
+            let run = task
+                .clone()
+                .run(config.address, connector, server_name, hello);
+            task.stop.run(run).await
+        });
+        Ok(forwarder)
+    }
+
+    /// Disconnect, when a config reload turns forwarding off; frames not
+    /// yet acknowledged are dropped
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Disconnect, when a config reload replaces the forwarder, handing the
+    /// frames not yet acknowledged to `next` so none are lost. Frames the
+    /// collector stored without acknowledging yet are sent again.
+    pub fn hand_over(&self, next: &Forwarder) {
+        self.stop();
+        let frames = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut pending.frames)
+        };
+        for (_, frame) in frames {
+            next.queue(frame);
+        }
+    }
+
+    /// Queue a frame for the collector
+    pub fn send(&self, frame: Frame) {
+        self.queue(Arc::new(frame));
+    }
+
+    fn queue(&self, frame: Arc<Frame>) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.frames.len() >= self.limit {
+            pending.frames.pop_front();
+            pending.dropped += 1;
+            if pending.dropped.is_power_of_two() {
+                warn!(
+                    "Forward buffer full, {} frames dropped so far",
+                    pending.dropped
+                );
+            }
+        }
+        let seq = pending.next_seq;
+        pending.next_seq += 1;
+        pending.frames.push_back((seq, frame));
+        drop(pending);
+        self.wake.notify_one();
+    }
+
+    /// Queue state for heartbeats
+    pub fn stats(&self) -> ForwardStats {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        ForwardStats {
+            pending: pending.frames.len() as u64,
+            dropped: pending.dropped,
+        }
+    }
+
+    /// Forget frames the collector has stored
+    fn ack(&self, seq: u64) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        while pending.frames.front().is_some_and(|(s, _)| *s <= seq) {
+            pending.frames.pop_front();
+        }
+    }
+
+    /// Buffered frames not yet written on the current connection
+    fn unsent(&self, last_sent: Option<u64>) -> Vec<(u64, Arc<Frame>)> {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending
+            .frames
+            .iter()
+            .filter(|(seq, _)| last_sent.is_none_or(|last| *seq > last))
+            .cloned()
+            .collect()
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        address: String,
+        connector: TlsConnector,
+        server_name: ServerName<'static>,
+        hello: AgentHello,
+    ) {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let connect = async {
+                let tcp = TcpStream::connect(&address).await?;
+                connector.connect(server_name.clone(), tcp).await
+            };
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+                Ok(Ok(stream)) => {
+                    info!("Forwarding events to {}", address);
+                    backoff = MIN_BACKOFF;
+                    if let Err(e) = self.session(stream, &hello).await {
+                        warn!("Collector connection lost: {:#}", e);
+                    }
+                }
+                Ok(Err(e)) => warn!("Collector {} not reachable: {}", address, e),
+                Err(_) => warn!("Connecting to collector {} timed out", address),
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Introduce the agent, then send frames and apply acknowledgements until
+    /// the connection fails
+    async fn session<S: AsyncRead + AsyncWrite>(
+        &self,
+        stream: S,
+        hello: &AgentHello,
+    ) -> Result<()> {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+
+        let client_hello = Frame::ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            client: "forwarder".to_string(),
+            encodings: Vec::new(),
+        };
+        write_frame(&mut writer, &client_hello).await?;
+        write_frame(&mut writer, &Frame::Hello(hello.clone())).await?;
+
+        tokio::select! {
+            result = self.receive_acks(&mut reader) => result,
+            result = self.send_pending(&mut writer) => result,
+        }
+    }
+
+    async fn receive_acks<R: AsyncRead + Unpin>(&self, reader: &mut R) -> Result<()> {
+        loop {
+            match read_frame(reader).await? {
+                Some(Frame::Ack { seq }) => self.ack(seq),
+                // A collector over its quota for this agent asks it to sample;
+                // it is not trusted with any other command
+                Some(Frame::Control(command @ ControlCommand::SetSampling { .. })) => {
+                    if let Some(control) = &self.control {
+                        let _ = control.send(command).await;
+                    }
+                }
+                Some(Frame::Reject { reason }) => {
+                    bail!("Collector rejected the connection: {}", reason)
+                }
+                Some(_) => warn!("Ignoring unexpected frame from collector"),
+                None => bail!("Collector closed the connection"),
+            }
+        }
+    }
+
+    /// Write everything still unacknowledged, then new frames as they are queued
+    async fn send_pending<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<()> {
+        let mut last_sent = None;
+        loop {
+            let batch = self.unsent(last_sent);
+            if batch.is_empty() {
+                self.wake.notified().await;
+                continue;
+            }
+            for (seq, frame) in batch {
+                let forwarded = Frame::Forwarded {
+                    seq,
+                    frame: Box::new(frame.as_ref().clone()),
+                };
+                write_frame(writer, &forwarded).await?;
+                last_sent = Some(seq);
+            }
+        }
+    }
+}
+
+/// TLS settings and expected certificate name for the configured collector
+pub fn tls_target(config: &ForwardConfig) -> Result<(TlsConnector, ServerName<'static>)> {
+    let (host, _) = config
+        .address
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Forward address {:?} is not host:port", config.address))?;
+    let name = config
+        .server_name
+        .clone()
+        .unwrap_or_else(|| host.trim_matches(['[', ']']).to_string());
+    let server_name = ServerName::try_from(name.clone())
+        .with_context(|| format!("Invalid collector name {}", name))?;
+    let connector = TlsConnector::from(Arc::new(client_config(config)?));
+    Ok((connector, server_name))
+}
+
+/// Trust the configured CA file, or the public web roots when none is set,
+/// and present the enrolled client certificate when there is one
+fn client_config(config: &ForwardConfig) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &config.ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA file {}", path.display()))?;
+            for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots.add(cert?)?;
+            }
+            if roots.is_empty() {
+                bail!("No certificates found in {}", path.display());
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let certs = std::fs::read(cert_file)
+                .with_context(|| format!("Failed to read {}", cert_file.display()))?;
+            let certs =
+                rustls_pemfile::certs(&mut certs.as_slice()).collect::<Result<Vec<_>, _>>()?;
+            let key = std::fs::read(key_file)
+                .with_context(|| format!("Failed to read {}", key_file.display()))?;
+            let key = rustls_pemfile::private_key(&mut key.as_slice())?
+                .with_context(|| format!("No private key in {}", key_file.display()))?;
+            Ok(builder.with_client_auth_cert(certs, key)?)
+        }
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => bail!("Forwarding needs both cert_file and key_file, or neither"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::{Capabilities, EventType, LogEvent, Severity};
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::TlsAcceptor;
+
+    fn event(message: &str) -> Frame {
+        Frame::Event(LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: message.to_string(),
+            },
+            "web-1".to_string(),
+        ))
+    }
+
+    /// Accept one connection, check the introduction and return the stream
+    async fn accept(
+        listener: &TcpListener,
+        acceptor: &TlsAcceptor,
+    ) -> tokio_rustls::server::TlsStream<TcpStream> {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(tcp).await.unwrap();
+        assert!(matches!(
+            read_frame(&mut stream).await.unwrap(),
+            Some(Frame::ClientHello { .. })
+        ));
+        assert!(matches!(
+            read_frame(&mut stream).await.unwrap(),
+            Some(Frame::Hello(_))
+        ));
+        stream
+    }
+
+    async fn next_seq(stream: &mut tokio_rustls::server::TlsStream<TcpStream>) -> u64 {
+        match read_frame(stream).await.unwrap() {
+            Some(Frame::Forwarded { seq, frame }) => {
+                assert!(matches!(*frame, Frame::Event(_)));
+                seq
+            }
+            other => panic!("expected a forwarded frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resends_unacknowledged_frames_after_reconnect() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file =
+            std::env::temp_dir().join(format!("guardian-ca-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&ca_file, cert.cert.pem()).unwrap();
+
+        let server_config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(cert.cert.der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der())),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let config = ForwardConfig {
+            enabled: true,
+            address: listener.local_addr().unwrap().to_string(),
+            server_name: Some("localhost".to_string()),
+            ca_file: Some(ca_file.clone()),
+            ..ForwardConfig::default()
+        };
+        let hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: guardian_common::SCHEMA_VERSION,
+            capabilities: Capabilities::default(),
+            signature: None,
+        };
+        let (control_tx, mut control_rx) = mpsc::channel(4);
+        let forwarder = Forwarder::start(config.clone(), hello.clone(), Some(control_tx)).unwrap();
+
+        // Buffered before the collector is reached
+        forwarder.send(event("first"));
+        forwarder.send(event("second"));
+
+        let mut stream = accept(&listener, &acceptor).await;
+        assert_eq!(next_seq(&mut stream).await, 0);
+        assert_eq!(next_seq(&mut stream).await, 1);
+        write_frame(&mut stream, &Frame::Ack { seq: 0 })
+            .await
+            .unwrap();
+        // Let the ack arrive before the collector goes away
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(stream);
+
+        // Only the unacknowledged frame is sent again, followed by new ones
+        let mut stream = accept(&listener, &acceptor).await;
+        assert_eq!(next_seq(&mut stream).await, 1);
+        forwarder.send(event("third"));
+        assert_eq!(next_seq(&mut stream).await, 2);
+        assert_eq!(forwarder.stats().pending, 2);
+
+        // Sampling asked for by the collector reaches the control channel
+        let sampling = ControlCommand::SetSampling { rate: 4 };
+        write_frame(&mut stream, &Frame::Control(sampling.clone()))
+            .await
+            .unwrap();
+        assert_eq!(control_rx.recv().await, Some(sampling));
+
+        // A reload hands what is still unacknowledged to the new forwarder
+        let next = Forwarder::start(config, hello, None).unwrap();
+        forwarder.hand_over(&next);
+        assert_eq!(forwarder.stats().pending, 0);
+        assert_eq!(next.stats().pending, 2);
+
+        let _ = std::fs::remove_file(&ca_file);
+    }
+}
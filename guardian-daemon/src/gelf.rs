@@ -0,0 +1,253 @@
+//! Sends events to a Graylog GELF input. Over UDP, messages are gzipped and
+//! split into GELF chunks when they exceed one datagram; over TCP they are
+//! null-byte delimited and resent after a reconnect.
+
+use crate::config::{GelfConfig, GelfTransport};
+use crate::outbox::Outbox;
+use anyhow::{bail, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use guardian_common::LogEvent;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{info, warn};
+
+/// First and longest wait between connection attempts
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Time allowed for a TCP connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Marks a datagram as one chunk of a larger message
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+
+/// Magic, message id, sequence number and count
+const CHUNK_HEADER_LEN: usize = 12;
+
+/// Most chunks Graylog reassembles into one message
+const MAX_CHUNKS: usize = 128;
+
+/// Chunk size bounds; Graylog accepts datagrams up to 8192 bytes
+const MIN_CHUNK_SIZE: usize = 512;
+const MAX_CHUNK_SIZE: usize = 8192;
+
+pub struct GelfSink {
+    config: GelfConfig,
+    outbox: Outbox<String>,
+}
+
+impl GelfSink {
+    /// Check the settings and start sending in the background
+    pub fn start(config: GelfConfig) -> Result<Arc<Self>> {
+        if config.address.is_empty() {
+            bail!("GELF output needs an address");
+        }
+        let sink = Arc::new(Self {
+            outbox: Outbox::new("GELF", config.buffer),
+            config,
+        });
+        let task = sink.clone();
+        tokio::spawn(async move { task.outbox.deliver(task.clone().run()).await });
+        Ok(sink)
+    }
+
+    /// Stop delivering, when a config reload replaces the sink
+    pub fn stop(&self) {
+        self.outbox.stop();
+    }
+
+    /// Queue an event if it meets the minimum severity
+    pub fn send(&self, event: &LogEvent) {
+        if event.severity < self.config.min_severity {
+            return;
+        }
+        self.outbox.push(event.to_gelf().to_string());
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let result = match self.config.transport {
+                GelfTransport::Udp => self.deliver_udp().await,
+                GelfTransport::Tcp => self.deliver_tcp().await,
+            };
+            if let Err(e) = result {
+                warn!("GELF {:?} output failed: {}", self.config.transport, e);
+            }
+            // An input that worked for a while is retried promptly
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn deliver_udp(&self) -> Result<()> {
+        let address = &self.config.address;
+        let bind = if address.starts_with('[') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(address).await?;
+        info!("Sending GELF messages to {} over UDP", address);
+
+        let chunk_size = self.config.chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+        loop {
+            let message = self.outbox.next().await;
+            let payload = if self.config.compress {
+                gzip(message.as_bytes())?
+            } else {
+                message.into_bytes()
+            };
+            let Some(datagrams) = chunk(&payload, chunk_size, message_id()) else {
+                warn!(
+                    "Dropping {} byte GELF message, more than {} chunks",
+                    payload.len(),
+                    MAX_CHUNKS
+                );
+                continue;
+            };
+            // A lost datagram is not retried; Graylog discards partial messages
+            for datagram in datagrams {
+                if let Err(e) = socket.send(&datagram).await {
+                    warn!("Failed to send GELF datagram to {}: {}", address, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn deliver_tcp(&self) -> Result<()> {
+        let address = &self.config.address;
+        let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out connecting to {}", address))??;
+        info!("Sending GELF messages to {} over TCP", address);
+
+        loop {
+            let message = self.outbox.next().await;
+            let mut frame = message.clone().into_bytes();
+            frame.push(0);
+            if let Err(e) = stream.write_all(&frame).await {
+                self.outbox.requeue(vec![message]);
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Random id shared by the chunks of one message
+fn message_id() -> [u8; 8] {
+    let bytes = uuid::Uuid::new_v4().into_bytes();
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&bytes[..8]);
+    id
+}
+
+/// Datagrams carrying `payload`: itself when it fits, else GELF chunks of at
+/// most `size` bytes each; `None` when it needs more than Graylog accepts
+fn chunk(payload: &[u8], size: usize, id: [u8; 8]) -> Option<Vec<Vec<u8>>> {
+    if payload.len() <= size {
+        return Some(vec![payload.to_vec()]);
+    }
+    let pieces: Vec<&[u8]> = payload.chunks(size - CHUNK_HEADER_LEN).collect();
+    if pieces.len() > MAX_CHUNKS {
+        return None;
+    }
+    let count = pieces.len() as u8;
+    Some(
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(seq, piece)| {
+                let mut datagram = Vec::with_capacity(CHUNK_HEADER_LEN + piece.len());
+                datagram.extend_from_slice(&CHUNK_MAGIC);
+                datagram.extend_from_slice(&id);
+                datagram.push(seq as u8);
+                datagram.push(count);
+                datagram.extend_from_slice(piece);
+                datagram
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use guardian_common::{EventType, Severity};
+    use std::io::Read;
+
+    #[test]
+    fn test_chunking() {
+        let id = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(chunk(b"short", 512, id).unwrap(), vec![b"short".to_vec()]);
+
+        let payload: Vec<u8> = (0..1200).map(|i| i as u8).collect();
+        let datagrams = chunk(&payload, 512, id).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        for (seq, datagram) in datagrams.iter().enumerate() {
+            assert!(datagram.len() <= 512);
+            assert_eq!(&datagram[..2], &CHUNK_MAGIC);
+            assert_eq!(&datagram[2..10], &id);
+            assert_eq!(datagram[10], seq as u8);
+            assert_eq!(datagram[11], 3);
+        }
+        let joined: Vec<u8> = datagrams
+            .iter()
+            .flat_map(|d| d[CHUNK_HEADER_LEN..].to_vec())
+            .collect();
+        assert_eq!(joined, payload);
+
+        assert!(chunk(&vec![0u8; 600 * 129], 600, id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_udp_output() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink = GelfSink::start(GelfConfig {
+            enabled: true,
+            address: receiver.local_addr().unwrap().to_string(),
+            ..GelfConfig::default()
+        })
+        .unwrap();
+        sink.send(&LogEvent::new(
+            Severity::Medium,
+            EventType::SystemLog {
+                source: "sshd".to_string(),
+                level: "warning".to_string(),
+                message: "too many failures".to_string(),
+            },
+            "web-1".to_string(),
+        ));
+
+        let mut buf = [0u8; 8192];
+        let len = tokio::time::timeout(Duration::from_secs(5), receiver.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut json = String::new();
+        GzDecoder::new(&buf[..len])
+            .read_to_string(&mut json)
+            .unwrap();
+        let message: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(message["short_message"], "too many failures");
+        assert_eq!(message["_source"], "sshd");
+        assert_eq!(message["level"], 4);
+    }
+}
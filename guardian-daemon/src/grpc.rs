@@ -0,0 +1,440 @@
+//! Optional gRPC API (`proto/guardian.proto`) for third-party consumers that
+//! want events and agent control without going through the console
+
+use crate::config::{GrpcConfig, ThreatIntelConfig};
+use crate::scanner::YaraScanner;
+use crate::threatintel::ThreatIntel;
+use guardian_common::{AgentHello, LogEvent, Severity, SCHEMA_VERSION};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+use tracing::{error, info, warn};
+
+pub mod proto {
+    tonic::include_proto!("guardian.v1");
+}
+
+use proto::guardian_server::{Guardian, GuardianServer};
+
+/// Events buffered per subscriber before it starts missing them
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+/// State shared by the RPC handlers and the daemon's event loop
+pub struct GrpcApi {
+    config: GrpcConfig,
+    hello: AgentHello,
+    started: Instant,
+    events: broadcast::Sender<Arc<LogEvent>>,
+    emitted: AtomicU64,
+    threat_intel: ThreatIntel,
+    threat_config: ThreatIntelConfig,
+    scanner: Option<Arc<YaraScanner>>,
+    /// Requests a config reload from the event loop, as SIGHUP does
+    reload: mpsc::Sender<String>,
+}
+
+impl GrpcApi {
+    pub fn new(
+        config: GrpcConfig,
+        hello: AgentHello,
+        threat_intel: ThreatIntel,
+        threat_config: ThreatIntelConfig,
+        scanner: Option<Arc<YaraScanner>>,
+        reload: mpsc::Sender<String>,
+    ) -> Arc<Self> {
+        let (events, _) = broadcast::channel(SUBSCRIBER_QUEUE);
+        Arc::new(Self {
+            config,
+            hello,
+            started: Instant::now(),
+            events,
+            emitted: AtomicU64::new(0),
+            threat_intel,
+            threat_config,
+            scanner,
+            reload,
+        })
+    }
+
+    /// Hand an emitted event to every subscriber
+    pub fn publish(&self, event: &LogEvent) {
+        self.emitted.fetch_add(1, Ordering::Relaxed);
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send(Arc::new(event.clone()));
+        }
+    }
+
+    /// Serve the API until the process exits; every call needs the bearer token
+    // tonic's interceptors return a bare `Status`
+    #[allow(clippy::result_large_err)]
+    pub async fn serve(self: Arc<Self>) {
+        let Some(token_sha256) = self.config.token_sha256.as_deref().map(str::to_lowercase) else {
+            error!("Not serving the gRPC API: grpc.token_sha256 is not set");
+            return;
+        };
+        let addr = match self.config.listen.parse() {
+            Ok(addr) => addr,
+            Err(e) => {
+                error!("Invalid gRPC listen address {}: {}", self.config.listen, e);
+                return;
+            }
+        };
+        info!("Serving gRPC API on {}", addr);
+        let service = InterceptedService::new(GuardianServer::from_arc(self), move |request| {
+            authorize(request, &token_sha256)
+        });
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(addr)
+            .await
+        {
+            error!("gRPC server failed: {}", e);
+        }
+    }
+}
+
+/// Let a request through when its `authorization` metadata carries the
+/// bearer token whose hash is `token_sha256`
+#[allow(clippy::result_large_err)] // an interceptor's signature
+fn authorize(request: Request<()>, token_sha256: &str) -> Result<Request<()>, Status> {
+    let token = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if format!("{:x}", Sha256::digest(token.as_bytes())) == token_sha256 => {
+            Ok(request)
+        }
+        _ => Err(Status::unauthenticated("Missing or invalid bearer token")),
+    }
+}
+
+/// `path` resolved, when it lies in one of `roots`
+fn confine(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| path.starts_with(root))
+        .then_some(path)
+}
+
+/// Subscriber-side filter from a `SubscribeRequest`
+struct Filter {
+    min_severity: Severity,
+    event_types: Vec<String>,
+}
+
+impl Filter {
+    /// `None` when the severity is not one the daemon knows
+    fn parse(request: &proto::SubscribeRequest) -> Option<Self> {
+        let min_severity = if request.min_severity.is_empty() {
+            Severity::Info
+        } else {
+            serde_json::from_value(request.min_severity.to_uppercase().into()).ok()?
+        };
+        Some(Self {
+            min_severity,
+            event_types: request.event_types.clone(),
+        })
+    }
+
+    fn accepts(&self, event: &LogEvent) -> bool {
+        event.severity >= self.min_severity
+            && (self.event_types.is_empty()
+                || self
+                    .event_types
+                    .iter()
+                    .any(|t| t == event.event_type.kind()))
+    }
+}
+
+fn to_proto(event: &LogEvent) -> proto::Event {
+    let severity = serde_json::to_value(event.severity)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default();
+    proto::Event {
+        id: event.id.to_string(),
+        timestamp: event.timestamp.to_rfc3339(),
+        severity,
+        r#type: event.event_type.kind().to_string(),
+        hostname: event.hostname.clone(),
+        tags: event.tags.clone(),
+        rule_name: event.rule_name.clone().unwrap_or_default(),
+        json: event.to_json().unwrap_or_default(),
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Guardian for GrpcApi {
+    type SubscribeStream = EventStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let request = request.into_inner();
+        let filter = Filter::parse(&request).ok_or_else(|| {
+            Status::invalid_argument(format!("Unknown severity {}", request.min_severity))
+        })?;
+        let stream =
+            BroadcastStream::new(self.events.subscribe()).filter_map(move |event| match event {
+                Ok(event) if filter.accepts(&event) => Some(Ok(to_proto(&event))),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    warn!(
+                        "gRPC subscriber is not keeping up, {} events missed",
+                        missed
+                    );
+                    None
+                }
+            });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, Status> {
+        let capabilities = &self.hello.capabilities;
+        Ok(Response::new(proto::Status {
+            hostname: self.hello.hostname.clone(),
+            agent_version: self.hello.agent_version.clone(),
+            schema_version: SCHEMA_VERSION,
+            uptime_secs: self.started.elapsed().as_secs(),
+            events_emitted: self.emitted.load(Ordering::Relaxed),
+            subscribers: self.events.receiver_count() as u32,
+            collectors: capabilities.collectors.clone(),
+            event_types: capabilities.event_types.clone(),
+            commands: capabilities.commands.clone(),
+        }))
+    }
+
+    async fn reload_rules(
+        &self,
+        _request: Request<proto::ReloadRulesRequest>,
+    ) -> Result<Response<proto::ReloadRulesResponse>, Status> {
+        // Rules are applied by the config reload, which reports the outcome
+        // as a `config_reload` event
+        self.reload
+            .send("gRPC client".to_string())
+            .await
+            .map_err(|_| Status::unavailable("The daemon is shutting down"))?;
+        let indicators = self
+            .threat_intel
+            .reload(&self.threat_config)
+            .map_err(|e| Status::failed_precondition(format!("{:#}", e)))?;
+        Ok(Response::new(proto::ReloadRulesResponse {
+            indicators: indicators as u64,
+        }))
+    }
+
+    async fn trigger_scan(
+        &self,
+        request: Request<proto::TriggerScanRequest>,
+    ) -> Result<Response<proto::TriggerScanResponse>, Status> {
+        let Some(scanner) = self.scanner.clone() else {
+            return Err(Status::unavailable("YARA scanner is not available"));
+        };
+        let request = request.into_inner();
+        let path = confine(Path::new(&request.path), &self.config.scan_roots).ok_or_else(|| {
+            Status::permission_denied(format!("{} is not under grpc.scan_roots", request.path))
+        })?;
+        let files = crate::scan::targets(&path, request.recursive)
+            .map(|targets| targets.files)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    Status::not_found(format!("{}: {}", request.path, e))
+                }
+                _ => Status::invalid_argument(format!("{}: {}", request.path, e)),
+            })?;
+        info!(
+            "Scanning {} files under {} for a gRPC client",
+            files.len(),
+            path.display()
+        );
+
+        let response = tokio::task::spawn_blocking(move || {
+            let mut response = proto::TriggerScanResponse::default();
+            for file in files {
+                let rules = scanner.scan_file(&file.to_string_lossy());
+                response.files_scanned += 1;
+                if !rules.is_empty() {
+                    response.matches.push(proto::ScanMatch {
+                        path: file.to_string_lossy().to_string(),
+                        rules,
+                    });
+                }
+            }
+            response
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::{Capabilities, EventType, FileOperation};
+
+    fn event(severity: Severity) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::FileIntegrity {
+                path: "/etc/passwd".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_status_reload_and_scan() {
+        let dir = std::env::temp_dir().join(format!("guardian-grpc-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let list = dir.join("hashes.txt");
+        std::fs::write(&list, format!("{}\n", "a".repeat(64))).unwrap();
+        std::fs::write(
+            dir.join("nested/eicar.txt"),
+            r"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*",
+        )
+        .unwrap();
+
+        let hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: SCHEMA_VERSION,
+            capabilities: Capabilities::default(),
+            signature: None,
+        };
+        let threat_config = ThreatIntelConfig {
+            hash_lists: vec![list],
+            ..ThreatIntelConfig::default()
+        };
+        let config = GrpcConfig {
+            scan_roots: vec![dir.clone()],
+            ..GrpcConfig::default()
+        };
+        let scanner = YaraScanner::new().ok().map(Arc::new);
+        let (reload_tx, mut reload_rx) = mpsc::channel(1);
+        let api = GrpcApi::new(
+            config,
+            hello,
+            ThreatIntel::default(),
+            threat_config,
+            scanner,
+            reload_tx,
+        );
+
+        let request = proto::SubscribeRequest {
+            min_severity: "high".to_string(),
+            event_types: vec!["file_integrity".to_string()],
+        };
+        let mut stream = api
+            .subscribe(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        api.publish(&event(Severity::Low));
+        let wanted = event(Severity::Critical);
+        api.publish(&wanted);
+        let received = stream.next().await.unwrap().unwrap();
+        assert_eq!(received.id, wanted.id.to_string());
+        assert_eq!(received.severity, "CRITICAL");
+        assert_eq!(received.r#type, "file_integrity");
+
+        let status = api
+            .get_status(Request::new(proto::GetStatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.events_emitted, 2);
+        assert_eq!(status.subscribers, 1);
+
+        let bad = proto::SubscribeRequest {
+            min_severity: "loud".to_string(),
+            event_types: Vec::new(),
+        };
+        assert!(api.subscribe(Request::new(bad)).await.is_err());
+
+        let reloaded = api
+            .reload_rules(Request::new(proto::ReloadRulesRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(reloaded.indicators, 1);
+        assert_eq!(reload_rx.recv().await.unwrap(), "gRPC client");
+
+        let outside = proto::TriggerScanRequest {
+            path: dir.join("../..").to_string_lossy().to_string(),
+            recursive: false,
+        };
+        let denied = api.trigger_scan(Request::new(outside)).await.unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+
+        let shallow = proto::TriggerScanRequest {
+            path: dir.to_string_lossy().to_string(),
+            recursive: false,
+        };
+        let scan = api
+            .trigger_scan(Request::new(shallow))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(scan.files_scanned, 1);
+        assert!(scan.matches.is_empty());
+
+        let deep = proto::TriggerScanRequest {
+            path: dir.to_string_lossy().to_string(),
+            recursive: true,
+        };
+        let scan = api
+            .trigger_scan(Request::new(deep))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(scan.files_scanned, 2);
+        assert_eq!(scan.matches[0].rules, vec!["eicar_test_file".to_string()]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_requests_need_the_bearer_token() {
+        let token_sha256 = format!("{:x}", Sha256::digest(b"s3cret"));
+        let request = |value: Option<&str>| {
+            let mut request = Request::new(());
+            if let Some(value) = value {
+                request
+                    .metadata_mut()
+                    .insert("authorization", value.parse().unwrap());
+            }
+            request
+        };
+
+        assert!(authorize(request(Some("Bearer s3cret")), &token_sha256).is_ok());
+        assert!(authorize(request(Some("Bearer wrong")), &token_sha256).is_err());
+        assert!(authorize(request(Some("s3cret")), &token_sha256).is_err());
+        assert!(authorize(request(None), &token_sha256).is_err());
+    }
+}
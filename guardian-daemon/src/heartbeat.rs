@@ -0,0 +1,73 @@
+//! Periodic heartbeat events carrying the agent version, uptime and
+//! forwarding queue, so consoles can tell a quiet agent from a dead one.
+
+use crate::config::HeartbeatConfig;
+use guardian_common::{EventType, ForwardStats, LogEvent, Severity};
+use std::time::{Duration, Instant};
+
+pub struct Heartbeat {
+    started: Instant,
+    interval: Duration,
+    hostname: String,
+}
+
+impl Heartbeat {
+    /// `None` when heartbeats are disabled
+    pub fn new(config: &HeartbeatConfig, hostname: String) -> Option<Self> {
+        (config.enabled && config.interval_secs > 0).then(|| Self {
+            started: Instant::now(),
+            interval: Duration::from_secs(config.interval_secs),
+            hostname,
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn event(&self, forward: Option<ForwardStats>) -> LogEvent {
+        LogEvent::new(
+            Severity::Info,
+            EventType::Heartbeat {
+                agent_version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_secs: self.started.elapsed().as_secs(),
+                interval_secs: self.interval.as_secs(),
+                forward,
+            },
+            self.hostname.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_reports_interval_and_queue() {
+        let disabled = HeartbeatConfig {
+            enabled: false,
+            ..HeartbeatConfig::default()
+        };
+        assert!(Heartbeat::new(&disabled, "web-1".to_string()).is_none());
+
+        let heartbeat = Heartbeat::new(&HeartbeatConfig::default(), "web-1".to_string()).unwrap();
+        let stats = ForwardStats {
+            pending: 3,
+            dropped: 1,
+        };
+        let event = heartbeat.event(Some(stats));
+        assert_eq!(event.hostname, "web-1");
+        match event.event_type {
+            EventType::Heartbeat {
+                interval_secs,
+                forward,
+                ..
+            } => {
+                assert_eq!(interval_secs, 60);
+                assert_eq!(forward, Some(stats));
+            }
+            other => panic!("expected a heartbeat, got {:?}", other),
+        }
+    }
+}
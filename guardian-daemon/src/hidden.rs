@@ -0,0 +1,246 @@
+//! Hidden process check. Rootkits commonly hide a process by filtering it
+//! out of the `/proc` directory listing, while the process itself still
+//! answers syscalls that take its PID. Every PID is probed with
+//! `kill(pid, 0)`, a chunk at a time with pauses in between so a sweep
+//! does not hog a CPU, and sysinfo's process table is read as well; a
+//! process either of them finds that the listing lacks is reported as a
+//! critical alert. Linux only, as it relies on procfs.
+
+use crate::config::HiddenProcessConfig;
+use crate::queue::EventSender;
+use guardian_common::{EventType, LogEvent, Severity};
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use sysinfo::System;
+use tracing::{info, warn};
+
+/// Tag on hidden process alerts
+pub const HIDDEN_TAG: &str = "hidden_process";
+
+/// Rule name on hidden process alerts
+pub const HIDDEN_RULE: &str = "hidden_process";
+
+/// PIDs probed between pauses
+const PROBE_CHUNK: u32 = 4096;
+
+/// Pause after each chunk of probes
+const PROBE_PAUSE: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// PIDs one of `sources` sees that the `/proc` listing does not, with the
+/// names of the sources that see them
+fn unlisted(
+    listed: &BTreeSet<u32>,
+    sources: &[(&'static str, BTreeSet<u32>)],
+) -> BTreeMap<u32, Vec<&'static str>> {
+    let mut unlisted: BTreeMap<u32, Vec<&'static str>> = BTreeMap::new();
+    for (source, pids) in sources {
+        for pid in pids.difference(listed) {
+            unlisted.entry(*pid).or_default().push(source);
+        }
+    }
+    unlisted
+}
+
+/// What `/proc/<pid>` still gives away about a process hidden from the
+/// listing; looking a PID up by name gets past a filtered listing
+#[derive(Debug, Default, PartialEq)]
+struct Details {
+    name: Option<String>,
+    exe: Option<String>,
+}
+
+/// Critical alert for a process seen by `sources` but not listed in `/proc`
+fn alert(pid: u32, sources: &[&str], details: &Details, hostname: &str) -> LogEvent {
+    let name = details.name.as_deref().unwrap_or("unknown");
+    let mut description = format!(
+        "PID {} ({}) is visible to {} but missing from the /proc listing",
+        pid,
+        name,
+        sources.join(" and ")
+    );
+    if let Some(exe) = &details.exe {
+        description.push_str(&format!("; executable {}", exe));
+    }
+    LogEvent::new(
+        Severity::Critical,
+        EventType::Alert {
+            title: format!("Hidden process {} ({})", pid, name),
+            description,
+            related_event_ids: Vec::new(),
+            technique: Some("T1014".to_string()),
+        },
+        hostname.to_string(),
+    )
+    .with_tag(HIDDEN_TAG)
+    .with_rule(HIDDEN_RULE)
+}
+
+/// Highest PID the kernel hands out
+fn pid_max() -> u32 {
+    std::fs::read_to_string("/proc/sys/kernel/pid_max")
+        .ok()
+        .and_then(|max| max.trim().parse().ok())
+        .unwrap_or(32768)
+}
+
+/// PIDs in the `/proc` directory listing
+fn listing() -> BTreeSet<u32> {
+    std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a process or thread with this ID exists; `EPERM` means it
+/// does but belongs to someone else
+fn exists(pid: u32) -> bool {
+    let Ok(raw) = i32::try_from(pid) else {
+        return false;
+    };
+    matches!(kill(Pid::from_raw(raw), None), Ok(()) | Err(Errno::EPERM))
+}
+
+/// Whether `pid` is a thread of another process, which `/proc` never
+/// lists at the top level
+fn is_thread(pid: u32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/status", pid)).is_ok_and(|status| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("Tgid:"))
+            .and_then(|tgid| tgid.trim().parse::<u32>().ok())
+            .is_some_and(|tgid| tgid != pid)
+    })
+}
+
+/// Name and executable of `pid`, read through its `/proc` entry
+fn details(pid: u32) -> Details {
+    Details {
+        name: std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .ok()
+            .map(|comm| comm.trim_end().to_string()),
+        exe: std::fs::read_link(format!("/proc/{}/exe", pid))
+            .ok()
+            .map(|exe| exe.display().to_string()),
+    }
+}
+
+/// PIDs up to `max` that answer `kill(pid, 0)`
+fn signalled(max: u32) -> BTreeSet<u32> {
+    let mut found = BTreeSet::new();
+    let mut start = 1;
+    while start <= max {
+        let end = start.saturating_add(PROBE_CHUNK - 1).min(max);
+        found.extend((start..=end).filter(|pid| exists(*pid)));
+        if end == max {
+            break;
+        }
+        start = end + 1;
+        std::thread::sleep(PROBE_PAUSE);
+    }
+    found
+}
+
+/// Processes that answer to a PID but are not listed, checked twice so
+/// processes starting or exiting during the scan are left out
+fn hidden() -> BTreeMap<u32, (Vec<&'static str>, Details)> {
+    let listed = listing();
+    let mut sys = System::new();
+    sys.refresh_processes();
+    let sysinfo = sys.processes().keys().map(|pid| pid.as_u32()).collect();
+    let signalled = signalled(pid_max());
+
+    let candidates = unlisted(&listed, &[("kill(0)", signalled), ("sysinfo", sysinfo)]);
+    if candidates.is_empty() {
+        return BTreeMap::new();
+    }
+    let relisted = listing();
+    candidates
+        .into_iter()
+        .filter(|(pid, _)| !relisted.contains(pid) && exists(*pid) && !is_thread(*pid))
+        .map(|(pid, sources)| (pid, (sources, details(pid))))
+        .collect()
+}
+
+/// Check for hidden processes every `interval_secs` until the event channel
+/// closes, reporting each one once while it stays hidden
+pub fn run(config: HiddenProcessConfig, tx: EventSender, hostname: String) {
+    let interval = std::time::Duration::from_secs(config.interval_secs.max(1));
+    info!(
+        "Checking for hidden processes every {}s",
+        interval.as_secs()
+    );
+    let mut reported = HashSet::new();
+    loop {
+        let hidden = hidden();
+        reported.retain(|pid| hidden.contains_key(pid));
+        for (pid, (sources, details)) in hidden {
+            if !reported.insert(pid) {
+                continue;
+            }
+            warn!("Process {} is hidden from the /proc listing", pid);
+            if tx
+                .blocking_send(alert(pid, &sources, &details, &hostname))
+                .is_err()
+            {
+                return;
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlisted_pids() {
+        let listed = BTreeSet::from([1, 2, 300]);
+        let hidden = unlisted(
+            &listed,
+            &[
+                ("kill(0)", BTreeSet::from([1, 2, 300, 4242])),
+                ("sysinfo", BTreeSet::from([1, 300, 4242, 5000])),
+            ],
+        );
+        assert_eq!(
+            hidden,
+            BTreeMap::from([(4242, vec!["kill(0)", "sysinfo"]), (5000, vec!["sysinfo"])])
+        );
+
+        let details = Details {
+            name: Some("kworkerd".to_string()),
+            exe: Some("/dev/shm/.x".to_string()),
+        };
+        let event = alert(4242, &hidden[&4242], &details, "web-1");
+        assert_eq!(event.severity, Severity::Critical);
+        match &event.event_type {
+            EventType::Alert {
+                title, description, ..
+            } => {
+                assert_eq!(title, "Hidden process 4242 (kworkerd)");
+                assert_eq!(
+                    description,
+                    "PID 4242 (kworkerd) is visible to kill(0) and sysinfo but missing \
+                     from the /proc listing; executable /dev/shm/.x"
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_signalled_in_chunks() {
+        // Probing runs across more than one chunk
+        let own = std::process::id();
+        let found = signalled(own.max(PROBE_CHUNK + 1));
+        assert!(found.contains(&own));
+        assert!(found.iter().all(|pid| *pid <= own.max(PROBE_CHUNK + 1)));
+    }
+}
@@ -0,0 +1,155 @@
+//! Decoy listeners on ports no service here uses, such as telnet or RDP.
+//! Nothing legitimate connects to them, so every connection attempt is
+//! reported as a critical `network_socket` event with the peer's address
+//! and closed without a byte being sent or read.
+
+use crate::config::HoneypotConfig;
+use crate::queue::EventSender;
+use anyhow::{Context, Result};
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+/// Tag on honeypot events
+pub const HONEYPOT_TAG: &str = "honeypot";
+
+/// Rule name on honeypot events
+pub const HONEYPOT_RULE: &str = "honeypot_connection";
+
+/// Repeat connections from a peer to a port are reported once per interval
+struct Recent {
+    interval: Duration,
+    seen: Mutex<HashMap<(IpAddr, u16), Instant>>,
+}
+
+impl Recent {
+    /// Whether a connection from `peer` to `port` should be reported now
+    fn report(&self, peer: IpAddr, port: u16, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        seen.retain(|_, last| now.duration_since(*last) < self.interval);
+        match seen.get(&(peer, port)) {
+            Some(_) => false,
+            None => {
+                seen.insert((peer, port), now);
+                true
+            }
+        }
+    }
+}
+
+/// Event for a connection from `peer` to the decoy at `local`
+pub fn event(local: SocketAddr, peer: SocketAddr, hostname: &str) -> LogEvent {
+    LogEvent::new(
+        Severity::Critical,
+        EventType::NetworkSocket {
+            local_addr: local.to_string(),
+            remote_addr: Some(peer.to_string()),
+            remote_host: None,
+            protocol: "tcp".to_string(),
+            state: "honeypot".to_string(),
+            traffic: None,
+            process: None,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(HONEYPOT_TAG)
+    .with_tag(format!("honeypot_port:{}", local.port()))
+    .with_rule(HONEYPOT_RULE)
+}
+
+async fn serve(listener: TcpListener, recent: Arc<Recent>, tx: EventSender, hostname: String) {
+    let Ok(local) = listener.local_addr() else {
+        return;
+    };
+    loop {
+        match listener.accept().await {
+            // Dropping the stream closes the connection straight away
+            Ok((_stream, peer)) => {
+                if !recent.report(peer.ip(), local.port(), Instant::now()) {
+                    continue;
+                }
+                warn!("Honeypot connection to port {} from {}", local.port(), peer);
+                if tx.send(event(local, peer, &hostname)).await.is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!("Honeypot accept failed on {}: {}", local, e),
+        }
+    }
+}
+
+/// Bind every configured decoy port and report connections to them
+pub async fn start(config: &HoneypotConfig, tx: EventSender, hostname: String) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let recent = Arc::new(Recent {
+        interval: Duration::from_secs(config.repeat_secs),
+        seen: Mutex::new(HashMap::new()),
+    });
+    for port in &config.ports {
+        let addr = SocketAddr::new(config.address, *port);
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind honeypot port {}", addr))?;
+        info!("Honeypot listening on tcp/{}", addr);
+        tokio::spawn(serve(
+            listener,
+            recent.clone(),
+            tx.clone(),
+            hostname.clone(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueueConfig;
+
+    #[tokio::test]
+    async fn test_connection_is_reported() {
+        // Find a free port for the decoy
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = HoneypotConfig {
+            enabled: true,
+            address: "127.0.0.1".parse().unwrap(),
+            ports: vec![port],
+            repeat_secs: 60,
+        };
+        let (tx, mut rx) = crate::queue::channel(&QueueConfig::default());
+        start(&config, tx, "web-1".to_string()).await.unwrap();
+
+        // A second attempt from the same peer within the interval is not reported
+        for _ in 0..2 {
+            tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .unwrap();
+        }
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.severity, Severity::Critical);
+        assert_eq!(event.rule_name.as_deref(), Some(HONEYPOT_RULE));
+        match &event.event_type {
+            EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                ..
+            } => {
+                assert_eq!(*local_addr, format!("127.0.0.1:{}", port));
+                assert!(remote_addr.as_ref().unwrap().starts_with("127.0.0.1:"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        let next = tokio::time::timeout(Duration::from_millis(200), rx.recv()).await;
+        assert!(next.is_err(), "received {:?}", next);
+    }
+}
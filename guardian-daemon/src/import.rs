@@ -0,0 +1,416 @@
+use crate::config::DaemonConfig;
+use crate::rules::RuleEngine;
+use crate::{auth_log, packages};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use guardian_common::{AuthOutcome, EventType, LogEvent};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Tag marking events reconstructed from history rather than observed live
+pub const IMPORTED_TAG: &str = "imported";
+
+pub const DEFAULT_DAYS: i64 = 30;
+
+/// Successful logins, and failed ones (readable by root only)
+const WTMP: &str = "/var/log/wtmp";
+const BTMP: &str = "/var/log/btmp";
+
+/// Size of a glibc `struct utmp` record on Linux
+const UTMP_RECORD: usize = 384;
+const LOGIN_PROCESS: i16 = 6;
+const USER_PROCESS: i16 = 7;
+
+/// Rotated copies with these extensions are skipped
+const COMPRESSED: &[&str] = &["gz", "xz", "bz2", "zst"];
+
+/// Year-less syslog time (`Oct 17 03:31:10`), placed in the most recent year not after `now`
+fn syslog_time(month: &str, day: &str, time: &str, now: DateTime<Utc>) -> Option<NaiveDateTime> {
+    let year = now.with_timezone(&Local).year();
+    let parse = |year: i32| {
+        NaiveDateTime::parse_from_str(
+            &format!("{} {} {} {}", year, month, day, time),
+            "%Y %b %d %H:%M:%S",
+        )
+        .ok()
+    };
+    let candidate = parse(year)?;
+    if candidate
+        > (now + Duration::days(1))
+            .with_timezone(&Local)
+            .naive_local()
+    {
+        parse(year - 1)
+    } else {
+        Some(candidate)
+    }
+}
+
+/// Timestamp at the start of a log line, in any format the supported logs use
+fn line_timestamp(line: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut tokens = line
+        .split_whitespace()
+        .map(|t| t.trim_matches(|c| c == '[' || c == ']'));
+    let first = tokens.next()?;
+
+    // rsyslog, dnf and pacman write an offset
+    if let Ok(ts) = DateTime::parse_from_rfc3339(first)
+        .or_else(|_| DateTime::parse_from_str(first, "%Y-%m-%dT%H:%M:%S%z"))
+    {
+        return Some(ts.with_timezone(&Utc));
+    }
+
+    // dpkg and older pacman write local time, classic syslog omits the year
+    let second = tokens.next()?;
+    let date_time = format!("{} {}", first, second);
+    let naive = NaiveDateTime::parse_from_str(&date_time, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&date_time, "%Y-%m-%d %H:%M"))
+        .ok()
+        .or_else(|| syslog_time(first, second, tokens.next()?, now))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|ts| ts.with_timezone(&Utc))
+}
+
+/// `path` and its uncompressed rotations (`auth.log.1`, `secure-20261010`)
+fn with_rotations(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            let file = p.file_name().unwrap_or_default().to_string_lossy();
+            let rotated = file
+                .strip_prefix(name.as_ref())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '-']));
+            let compressed = p
+                .extension()
+                .is_some_and(|ext| COMPRESSED.iter().any(|c| ext == *c));
+            rotated && !compressed && p.is_file()
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Events for every line of `paths` (and their rotations) written since `since`
+fn import_logs(
+    paths: &[PathBuf],
+    to_event: impl Fn(&str) -> Option<LogEvent>,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Vec<LogEvent> {
+    let mut events = Vec::new();
+    for file in paths.iter().flat_map(|p| with_rotations(p)) {
+        let Ok(bytes) = std::fs::read(&file) else {
+            continue;
+        };
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            let Some(timestamp) = line_timestamp(line, now).filter(|ts| *ts >= since) else {
+                continue;
+            };
+            if let Some(mut event) = to_event(line) {
+                event.timestamp = timestamp;
+                events.push(event);
+            }
+        }
+    }
+    events
+}
+
+fn utmp_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// One utmp record as (time, user, tty, remote host)
+fn parse_utmp(record: &[u8], types: &[i16]) -> Option<(DateTime<Utc>, String, String, String)> {
+    let ut_type = i16::from_ne_bytes(record.get(0..2)?.try_into().ok()?);
+    if !types.contains(&ut_type) {
+        return None;
+    }
+    let seconds = i32::from_ne_bytes(record.get(340..344)?.try_into().ok()?);
+    let timestamp = DateTime::from_timestamp(seconds.into(), 0)?;
+    let user = utmp_str(record.get(44..76)?);
+    if user.is_empty() {
+        return None;
+    }
+    Some((
+        timestamp,
+        user,
+        utmp_str(record.get(8..40)?),
+        utmp_str(record.get(76..332)?),
+    ))
+}
+
+/// Logins recorded in a wtmp/btmp file. Remote logins are skipped when
+/// `skip_remote` is set, since sshd already logged them to the auth log.
+fn import_utmp(
+    path: &Path,
+    outcome: AuthOutcome,
+    skip_remote: bool,
+    hostname: &str,
+    since: DateTime<Utc>,
+) -> Vec<LogEvent> {
+    let types: &[i16] = match outcome {
+        AuthOutcome::Success => &[USER_PROCESS],
+        AuthOutcome::Failure => &[LOGIN_PROCESS, USER_PROCESS],
+    };
+
+    let source = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut events = Vec::new();
+    for file in with_rotations(path) {
+        let Ok(bytes) = std::fs::read(&file) else {
+            continue;
+        };
+        for record in bytes.chunks_exact(UTMP_RECORD) {
+            let Some((timestamp, user, tty, host)) = parse_utmp(record, types) else {
+                continue;
+            };
+            if timestamp < since || (skip_remote && !host.is_empty()) {
+                continue;
+            }
+            let event_type = EventType::AuthEvent {
+                user,
+                source_ip: (!host.is_empty()).then_some(host),
+                method: "login".to_string(),
+                outcome,
+                tty: (!tty.is_empty()).then_some(tty),
+            };
+            let Ok(event) = auth_log::to_event(event_type, hostname) else {
+                continue;
+            };
+            let mut event = event.with_tag(source.as_ref());
+            event.timestamp = timestamp;
+            events.push(event);
+        }
+    }
+    events
+}
+
+/// sshd, sudo and su entries from the systemd journal, for hosts without an auth log file
+fn import_journal(days: i64, hostname: &str) -> Vec<LogEvent> {
+    let output = Command::new("journalctl")
+        .arg(format!("--since=-{}d", days))
+        .args(["--output=json", "--no-pager"])
+        .args([
+            "SYSLOG_IDENTIFIER=sshd",
+            "SYSLOG_IDENTIFIER=sudo",
+            "SYSLOG_IDENTIFIER=su",
+        ])
+        .output();
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    let mut events = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        let field = |name: &str| entry.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+        // Rebuild the syslog line the auth log parser expects
+        let syslog = format!(
+            "{} {}[{}]: {}",
+            field("_HOSTNAME"),
+            field("SYSLOG_IDENTIFIER"),
+            field("_PID"),
+            field("MESSAGE")
+        );
+        let micros = field("__REALTIME_TIMESTAMP").parse::<i64>().ok();
+        let (Some(event_type), Some(timestamp)) = (
+            auth_log::parse_line(&syslog),
+            micros.and_then(DateTime::from_timestamp_micros),
+        ) else {
+            continue;
+        };
+        let Ok(event) = auth_log::to_event(event_type, hostname) else {
+            continue;
+        };
+        let mut event = event.with_tag("journald");
+        event.timestamp = timestamp;
+        events.push(event);
+    }
+    events
+}
+
+/// Rebuild the last `days` of login and package history, oldest first
+pub fn import(config: &DaemonConfig, hostname: &str, days: i64) -> Vec<LogEvent> {
+    let now = Utc::now();
+    let since = now - Duration::days(days);
+
+    let mut events = import_logs(
+        &config.auth_log.paths,
+        |line| auth_log::parse_line(line).and_then(|e| auth_log::to_event(e, hostname).ok()),
+        since,
+        now,
+    );
+    let has_auth_log = config.auth_log.paths.iter().any(|p| p.exists());
+    if !has_auth_log {
+        events.extend(import_journal(days, hostname));
+    }
+    events.extend(import_utmp(
+        Path::new(WTMP),
+        AuthOutcome::Success,
+        has_auth_log,
+        hostname,
+        since,
+    ));
+    events.extend(import_utmp(
+        Path::new(BTMP),
+        AuthOutcome::Failure,
+        has_auth_log,
+        hostname,
+        since,
+    ));
+    events.extend(import_logs(
+        &config.packages.paths,
+        |line| packages::parse_line(line).and_then(|e| packages::to_event(e, hostname).ok()),
+        since,
+        now,
+    ));
+
+    let rules = RuleEngine::with_definitions(&config.rules);
+    events.sort_by_key(|e| e.timestamp);
+    events
+        .into_iter()
+        .filter_map(|event| {
+            // Responses are for live events; history only gets the rest
+            let verdict = rules.apply(event.with_tag(IMPORTED_TAG));
+            (!verdict.suppressed).then_some(verdict.event)
+        })
+        .collect()
+}
+
+/// `import [--days N]`: print historical events as JSON lines on stdout
+/// Print events for the last `days` of history as JSON lines
+pub fn run_command(days: i64) -> Result<()> {
+    let config = DaemonConfig::load()?;
+    let hostname = hostname::get()
+        .unwrap_or_else(|_| "unknown".into())
+        .to_string_lossy()
+        .to_string();
+
+    let events = import(&config, &hostname, days);
+    for event in &events {
+        println!("{}", event.to_json()?);
+    }
+    eprintln!(
+        "Imported {} events from the last {} days",
+        events.len(),
+        days
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_timestamps() {
+        let now = "2026-10-17T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let local = |s: &str| {
+            let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+            Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+
+        assert_eq!(
+            line_timestamp("2026-10-17T03:31:10.123+00:00 bastion sshd[1]: x", now),
+            Some("2026-10-17T03:31:10.123Z".parse().unwrap())
+        );
+        assert_eq!(
+            line_timestamp("[2026-10-17T03:31:10+0200] [ALPM] installed x (1)", now),
+            Some("2026-10-17T01:31:10Z".parse().unwrap())
+        );
+        assert_eq!(
+            line_timestamp("2026-10-17 03:31:10 install nginx:amd64 <none> 1", now),
+            Some(local("2026-10-17 03:31:10"))
+        );
+        assert_eq!(
+            line_timestamp("Oct 16 03:31:10 bastion sshd[1]: x", now),
+            Some(local("2026-10-16 03:31:10"))
+        );
+        // A December line read in October is from last year
+        assert_eq!(
+            line_timestamp("Dec 30 23:00:00 bastion sshd[1]: x", now),
+            Some(local("2025-12-30 23:00:00"))
+        );
+        assert_eq!(line_timestamp("not a log line", now), None);
+    }
+
+    #[test]
+    fn test_import_logs_within_window() {
+        let dir = std::env::temp_dir().join(format!("guardian-import-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dpkg = dir.join("dpkg.log");
+        std::fs::write(
+            &dpkg,
+            "2026-10-16 09:00:00 install nmap:amd64 <none> 7.94-1\n\
+             2026-10-16 09:00:01 status installed nmap:amd64 7.94-1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("dpkg.log.1"),
+            "2026-09-01 09:00:00 install old:amd64 <none> 1.0\n\
+             2026-10-10 09:00:00 install curl:amd64 <none> 8.5.0-2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("dpkg.log.2.gz"),
+            "2026-10-11 09:00:00 install gz:amd64 <none> 1\n",
+        )
+        .unwrap();
+
+        let now = "2026-10-17T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let events = import_logs(
+            &[dpkg],
+            |line| packages::parse_line(line).and_then(|e| packages::to_event(e, "web-1").ok()),
+            now - Duration::days(30),
+            now,
+        );
+        let names: Vec<_> = events
+            .iter()
+            .map(|e| match &e.event_type {
+                EventType::PackageChange { name, .. } => name.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(names, vec!["nmap", "curl"]);
+        assert!(events[0].timestamp > events[1].timestamp);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_utmp_record() {
+        let mut record = vec![0u8; UTMP_RECORD];
+        record[0..2].copy_from_slice(&USER_PROCESS.to_ne_bytes());
+        record[8..13].copy_from_slice(b"pts/3");
+        record[44..49].copy_from_slice(b"alice");
+        record[76..87].copy_from_slice(b"203.0.113.5");
+        record[340..344].copy_from_slice(&1_792_200_000i32.to_ne_bytes());
+
+        let (timestamp, user, tty, host) = parse_utmp(&record, &[USER_PROCESS]).unwrap();
+        assert_eq!(timestamp.timestamp(), 1_792_200_000);
+        assert_eq!(
+            (user.as_str(), tty.as_str(), host.as_str()),
+            ("alice", "pts/3", "203.0.113.5")
+        );
+        assert!(parse_utmp(&record, &[LOGIN_PROCESS]).is_none());
+    }
+}
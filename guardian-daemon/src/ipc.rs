@@ -0,0 +1,337 @@
+use guardian_common::ipc::{
+    read_frame, read_frame_as, write_frame, write_frame_as, Encoding, Frame, PROTOCOL_VERSION,
+};
+use guardian_common::{AgentHello, ControlCommand};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// Frames kept while no console is connected; the oldest are dropped beyond this
+const BACKLOG_LIMIT: usize = 10_000;
+
+/// Frames queued per connected console before it counts as stalled
+const CLIENT_QUEUE: usize = 1024;
+
+/// Time a new connection gets to send its `ClientHello`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Shared {
+    clients: Vec<mpsc::Sender<Arc<Frame>>>,
+    backlog: VecDeque<Arc<Frame>>,
+    dropped: u64,
+}
+
+/// Serves events to consoles over a local socket and receives their control commands.
+/// Frames produced while no console is connected are held and replayed to the next one.
+pub struct IpcServer {
+    hello: AgentHello,
+    control: mpsc::Sender<ControlCommand>,
+    shared: Mutex<Shared>,
+}
+
+impl IpcServer {
+    pub fn new(hello: AgentHello, control: mpsc::Sender<ControlCommand>) -> Arc<Self> {
+        Arc::new(Self {
+            hello,
+            control,
+            shared: Mutex::new(Shared::default()),
+        })
+    }
+
+    /// Deliver a frame to every connected console, or hold it until one connects
+    pub fn send(&self, frame: Frame) {
+        let frame = Arc::new(frame);
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+
+        shared
+            .clients
+            .retain(|client| match client.try_send(frame.clone()) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    warn!("Console is not keeping up, dropping a frame");
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            });
+
+        if shared.clients.is_empty() {
+            if shared.backlog.len() == BACKLOG_LIMIT {
+                shared.backlog.pop_front();
+                shared.dropped += 1;
+            }
+            shared.backlog.push_back(frame);
+        }
+    }
+
+    /// Register a console, returning its queue and the frames it missed
+    fn attach(&self) -> (mpsc::Receiver<Arc<Frame>>, Vec<Arc<Frame>>) {
+        let (tx, rx) = mpsc::channel(CLIENT_QUEUE);
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        if shared.dropped > 0 {
+            warn!(
+                "{} frames were dropped while no console was connected",
+                shared.dropped
+            );
+            shared.dropped = 0;
+        }
+        shared.clients.push(tx);
+        (rx, shared.backlog.drain(..).collect())
+    }
+
+    /// Handshake, then stream frames out and control commands in until the console leaves.
+    /// The handshake is JSON; later frames use the encoding agreed in it.
+    async fn serve<S>(self: Arc<Self>, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+
+        let first = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_frame(&mut reader)).await;
+        let (client, encodings) = match first {
+            Ok(Ok(Some(Frame::ClientHello {
+                protocol_version,
+                client,
+                encodings,
+            }))) if protocol_version == PROTOCOL_VERSION => (client, encodings),
+            Ok(Ok(Some(Frame::ClientHello {
+                protocol_version, ..
+            }))) => {
+                let reason = format!(
+                    "Protocol version {} is not supported (daemon speaks {})",
+                    protocol_version, PROTOCOL_VERSION
+                );
+                warn!("Rejected console: {}", reason);
+                let _ = write_frame(&mut writer, &Frame::Reject { reason }).await;
+                return;
+            }
+            Ok(Ok(_)) => {
+                warn!("Console connection did not start with a handshake");
+                return;
+            }
+            Ok(Err(e)) => {
+                warn!("Console handshake failed: {}", e);
+                return;
+            }
+            Err(_) => {
+                warn!("Console handshake timed out");
+                return;
+            }
+        };
+        if let Err(e) = write_frame(&mut writer, &Frame::Hello(self.hello.clone())).await {
+            warn!("Failed to greet console {}: {}", client, e);
+            return;
+        }
+        let encoding = Encoding::negotiate(&encodings, &self.hello.capabilities.encodings);
+        info!("Console {} connected ({})", client, encoding.name());
+
+        let (mut queue, backlog) = self.attach();
+        let control = self.control.clone();
+        let mut inbound = tokio::spawn(async move {
+            loop {
+                match read_frame_as(&mut reader, encoding).await {
+                    Ok(Some(Frame::Control(command))) => {
+                        if control.send(command).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Some(_)) => warn!("Ignoring unexpected frame from console"),
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("Console read error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let outbound = async {
+            for frame in backlog {
+                write_frame_as(&mut writer, &frame, encoding).await?;
+            }
+            while let Some(frame) = queue.recv().await {
+                write_frame_as(&mut writer, &frame, encoding).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        tokio::select! {
+            result = outbound => if let Err(e) = result {
+                warn!("Console {} write error: {}", client, e);
+            },
+            _ = &mut inbound => {}
+        }
+        inbound.abort();
+        info!("Console {} disconnected", client);
+    }
+
+    /// Accept consoles on `path` until the process exits
+    #[cfg(unix)]
+    pub async fn listen(self: Arc<Self>, path: PathBuf) {
+        // Anyone connected can isolate the host
+        let listener = match guardian_common::ipc::bind_private(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {}: {}", path.display(), e);
+                return;
+            }
+        };
+        info!("Listening for consoles on {}", path.display());
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(self.clone().serve(stream));
+                }
+                Err(e) => {
+                    error!("Failed to accept console connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Accept consoles on the named pipe `path` until the process exits
+    #[cfg(windows)]
+    pub async fn listen(self: Arc<Self>, path: PathBuf) {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut server = match ServerOptions::new().first_pipe_instance(true).create(&path) {
+            Ok(server) => server,
+            Err(e) => {
+                error!("Failed to create pipe {}: {}", path.display(), e);
+                return;
+            }
+        };
+        info!("Listening for consoles on {}", path.display());
+
+        loop {
+            if let Err(e) = server.connect().await {
+                error!("Failed to accept console connection: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            let next = match ServerOptions::new().create(&path) {
+                Ok(next) => next,
+                Err(e) => {
+                    error!("Failed to create pipe {}: {}", path.display(), e);
+                    return;
+                }
+            };
+            tokio::spawn(self.clone().serve(std::mem::replace(&mut server, next)));
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use guardian_common::{Capabilities, EventType, LogEvent, Severity};
+    use std::path::Path;
+    use tokio::net::UnixStream;
+
+    fn event(message: &str) -> Frame {
+        Frame::Event(LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: message.to_string(),
+            },
+            "web-1".to_string(),
+        ))
+    }
+
+    fn message(frame: Frame) -> String {
+        match frame {
+            Frame::Event(LogEvent {
+                event_type: EventType::SystemLog { message, .. },
+                ..
+            }) => message,
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    async fn connect(path: &Path, protocol_version: u32, encodings: &[Encoding]) -> UnixStream {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        let hello = Frame::ClientHello {
+            protocol_version,
+            client: "test".to_string(),
+            encodings: encodings.iter().map(|e| e.name().to_string()).collect(),
+        };
+        write_frame(&mut stream, &hello).await.unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_handshake_backlog_and_control() {
+        let path = std::env::temp_dir().join(format!("guardian-{}.sock", uuid::Uuid::new_v4()));
+        let hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: guardian_common::SCHEMA_VERSION,
+            capabilities: Capabilities {
+                encodings: Encoding::supported(),
+                ..Capabilities::default()
+            },
+            signature: None,
+        };
+        let (control_tx, mut control_rx) = mpsc::channel(4);
+        let server = IpcServer::new(hello, control_tx);
+        tokio::spawn(server.clone().listen(path.clone()));
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Produced before any console connects
+        server.send(event("early"));
+
+        let mut stale = connect(&path, PROTOCOL_VERSION + 1, &[]).await;
+        assert!(matches!(
+            read_frame(&mut stale).await.unwrap(),
+            Some(Frame::Reject { .. })
+        ));
+
+        let mut console = connect(&path, PROTOCOL_VERSION, &[]).await;
+        assert!(matches!(
+            read_frame(&mut console).await.unwrap(),
+            Some(Frame::Hello(_))
+        ));
+        assert_eq!(
+            message(read_frame(&mut console).await.unwrap().unwrap()),
+            "early"
+        );
+
+        server.send(event("live"));
+        assert_eq!(
+            message(read_frame(&mut console).await.unwrap().unwrap()),
+            "live"
+        );
+
+        let command = ControlCommand::SetSampling { rate: 3 };
+        write_frame(&mut console, &Frame::Control(command.clone()))
+            .await
+            .unwrap();
+        assert_eq!(control_rx.recv().await, Some(command));
+
+        // A console offering CBOR gets the hello as JSON and CBOR after it
+        let mut binary = connect(&path, PROTOCOL_VERSION, &[Encoding::Cbor]).await;
+        assert!(matches!(
+            read_frame(&mut binary).await.unwrap(),
+            Some(Frame::Hello(_))
+        ));
+        server.send(event("binary"));
+        assert_eq!(
+            message(read_frame(&mut console).await.unwrap().unwrap()),
+            "binary"
+        );
+        let frame = read_frame_as(&mut binary, Encoding::Cbor).await.unwrap();
+        assert_eq!(message(frame.unwrap()), "binary");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
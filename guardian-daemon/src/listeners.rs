@@ -0,0 +1,204 @@
+//! Listening port inventory. The sockets waiting for connections are read
+//! every `poll_secs` and compared with the previous read, so a service that
+//! starts listening, such as a bind shell or an unexpected server, shows up
+//! as one `network_socket` event with the process behind it. Client
+//! connections are never part of the inventory.
+
+use crate::config::ListeningPortConfig;
+use crate::platform::{self, Listener};
+use crate::queue::EventSender;
+use guardian_common::{EventType, LogEvent, ProcessAncestor, Severity};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use sysinfo::{Pid, System};
+use tracing::{info, warn};
+
+/// Tag on listening port inventory events
+pub const LISTENER_TAG: &str = "listening_port";
+
+/// Tag on listeners bound to every interface
+pub const ALL_INTERFACES_TAG: &str = "all_interfaces";
+
+/// Listeners by protocol and address, with their owning process
+#[derive(Default)]
+pub struct ListenerInventory {
+    known: Option<BTreeMap<(&'static str, SocketAddr), Option<u32>>>,
+}
+
+impl ListenerInventory {
+    /// Compare the sockets listening now with the last read, returning an
+    /// event for each one that opened or closed. The first read only
+    /// records the inventory.
+    pub fn observe(
+        &mut self,
+        listeners: Vec<Listener>,
+        mut process_name: impl FnMut(u32) -> Option<String>,
+        hostname: &str,
+    ) -> Vec<LogEvent> {
+        let current: BTreeMap<_, _> = listeners
+            .into_iter()
+            .map(|listener| ((listener.protocol, listener.addr), listener.pid))
+            .collect();
+        let Some(known) = self.known.replace(current.clone()) else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        for ((protocol, addr), pid) in &current {
+            if known.contains_key(&(*protocol, *addr)) {
+                continue;
+            }
+            let process = pid.and_then(|pid| {
+                Some(ProcessAncestor {
+                    pid,
+                    name: process_name(pid)?,
+                })
+            });
+            let all_interfaces = addr.ip().is_unspecified();
+            let severity = if all_interfaces {
+                Severity::Medium
+            } else {
+                Severity::Low
+            };
+            info!(
+                "New {} listener on {}{}",
+                protocol,
+                addr,
+                process
+                    .as_ref()
+                    .map(|p| format!(" ({} {})", p.name, p.pid))
+                    .unwrap_or_default()
+            );
+            let mut event = event(severity, protocol, addr, "listen", process, hostname);
+            if all_interfaces {
+                event = event.with_tag(ALL_INTERFACES_TAG);
+            }
+            events.push(event);
+        }
+        for (protocol, addr) in known.keys() {
+            if !current.contains_key(&(*protocol, *addr)) {
+                events.push(event(
+                    Severity::Info,
+                    protocol,
+                    addr,
+                    "closed",
+                    None,
+                    hostname,
+                ));
+            }
+        }
+        events
+    }
+}
+
+fn event(
+    severity: Severity,
+    protocol: &str,
+    addr: &SocketAddr,
+    state: &str,
+    process: Option<ProcessAncestor>,
+    hostname: &str,
+) -> LogEvent {
+    LogEvent::new(
+        severity,
+        EventType::NetworkSocket {
+            local_addr: addr.to_string(),
+            remote_addr: None,
+            remote_host: None,
+            protocol: protocol.to_string(),
+            state: state.to_string(),
+            traffic: None,
+            process,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(LISTENER_TAG)
+}
+
+/// Read the listening sockets every `poll_secs` until the event channel
+/// closes
+pub fn run(config: ListeningPortConfig, tx: EventSender, hostname: String) {
+    let interval = std::time::Duration::from_secs(config.poll_secs.max(1));
+    let mut inventory = ListenerInventory::default();
+    let mut sys = System::new();
+    loop {
+        let listeners = match platform::listening_sockets(config.udp) {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                warn!("Listening sockets unavailable, not tracking them: {}", e);
+                return;
+            }
+        };
+        let process_name = |pid: u32| {
+            let pid = Pid::from_u32(pid);
+            sys.refresh_process(pid);
+            sys.process(pid).map(|process| process.name().to_string())
+        };
+        for event in inventory.observe(listeners, process_name, &hostname) {
+            if tx.blocking_send(event).is_err() {
+                return;
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(protocol: &'static str, addr: &str, pid: u32) -> Listener {
+        Listener {
+            protocol,
+            addr: addr.parse().unwrap(),
+            pid: Some(pid),
+        }
+    }
+
+    #[test]
+    fn test_new_and_closed_listeners() {
+        let names = |pid: u32| (pid == 4242).then(|| "nc".to_string());
+        let mut inventory = ListenerInventory::default();
+        let sshd = listener("tcp", "0.0.0.0:22", 800);
+        assert!(inventory
+            .observe(vec![sshd.clone()], names, "web-1")
+            .is_empty());
+
+        let events = inventory.observe(
+            vec![
+                sshd.clone(),
+                listener("tcp", "0.0.0.0:4444", 4242),
+                listener("udp", "127.0.0.1:5353", 900),
+            ],
+            names,
+            "web-1",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].severity, Severity::Medium);
+        assert!(events[0].tags.contains(&ALL_INTERFACES_TAG.to_string()));
+        match &events[0].event_type {
+            EventType::NetworkSocket {
+                local_addr,
+                state,
+                process,
+                ..
+            } => {
+                assert_eq!(local_addr, "0.0.0.0:4444");
+                assert_eq!(state, "listen");
+                assert_eq!(
+                    process,
+                    &Some(ProcessAncestor {
+                        pid: 4242,
+                        name: "nc".to_string()
+                    })
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(events[1].severity, Severity::Low);
+
+        let events = inventory.observe(vec![sshd], names, "web-1");
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.severity == Severity::Info));
+    }
+}
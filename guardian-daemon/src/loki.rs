@@ -0,0 +1,233 @@
+//! Pushes events to Grafana Loki as JSON log lines. Streams are labelled by
+//! host, severity and event type, which stay few enough for Loki's index;
+//! everything else is in the line for LogQL's `json` parser. Pushes are
+//! paced to a configured rate and retried with backoff when Loki throttles.
+
+use crate::config::LokiConfig;
+use crate::outbox::Outbox;
+use anyhow::{bail, Result};
+use guardian_common::LogEvent;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// First and longest wait between failed pushes
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Path of the push API under the Loki base URL
+const PUSH_PATH: &str = "loki/api/v1/push";
+
+/// One log line waiting to be pushed
+struct Entry {
+    labels: BTreeMap<String, String>,
+    timestamp_ns: i64,
+    line: String,
+}
+
+pub struct LokiSink {
+    http: reqwest::Client,
+    config: LokiConfig,
+    outbox: Outbox<Entry>,
+}
+
+impl LokiSink {
+    /// Check the settings and start pushing in the background
+    pub fn start(config: LokiConfig) -> Result<Arc<Self>> {
+        if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+            bail!("Loki url must start with http:// or https://");
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(!config.verify_tls)
+            .build()?;
+
+        let sink = Arc::new(Self {
+            http,
+            outbox: Outbox::new("Loki", config.buffer),
+            config,
+        });
+        let task = sink.clone();
+        tokio::spawn(async move { task.outbox.deliver(task.clone().run()).await });
+        Ok(sink)
+    }
+
+    /// Stop delivering, when a config reload replaces the sink
+    pub fn stop(&self) {
+        self.outbox.stop();
+    }
+
+    /// Queue an event if it meets the minimum severity
+    pub fn send(&self, event: &LogEvent) {
+        if event.severity < self.config.min_severity {
+            return;
+        }
+        let line = match event.to_json() {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize event for Loki: {}", e);
+                return;
+            }
+        };
+        self.outbox.push(Entry {
+            labels: stream_labels(&self.config, event),
+            timestamp_ns: event
+                .timestamp
+                .timestamp_nanos_opt()
+                .unwrap_or_else(|| event.timestamp.timestamp_millis() * 1_000_000),
+            line,
+        });
+    }
+
+    async fn run(self: Arc<Self>) {
+        let flush = Duration::from_millis(self.config.flush_ms);
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let batch = self.outbox.next_batch(self.config.batch_size, flush).await;
+            let started = Instant::now();
+            match self.push(&batch).await {
+                Ok(()) => backoff = MIN_BACKOFF,
+                Err(e) => {
+                    warn!("Loki push failed, retrying: {}", e);
+                    self.outbox.requeue(batch);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            }
+
+            // Spread pushes so the rate stays under the limit
+            let pause = pace(batch.len(), self.config.max_events_per_sec);
+            if let Some(remaining) = pause.checked_sub(started.elapsed()) {
+                tokio::time::sleep(remaining).await;
+            }
+        }
+    }
+
+    async fn push(&self, batch: &[Entry]) -> Result<()> {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), PUSH_PATH);
+        let mut request = self.http.post(url).json(&push_body(batch));
+        if let Some(tenant) = &self.config.tenant_id {
+            request = request.header("X-Scope-OrgID", tenant);
+        }
+        if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = response.text().await.unwrap_or_default();
+        // Loki answers 400 for lines it will never accept, such as ones too
+        // old for the stream; resending them would block the queue
+        if status.is_client_error() && status.as_u16() != 429 {
+            warn!(
+                "Loki rejected {} lines ({}): {}",
+                batch.len(),
+                status,
+                error
+            );
+            return Ok(());
+        }
+        bail!("{}: {}", status, error)
+    }
+}
+
+/// Labels identifying an event's stream
+fn stream_labels(config: &LokiConfig, event: &LogEvent) -> BTreeMap<String, String> {
+    let mut labels = config.labels.clone();
+    labels.insert("host".to_string(), event.hostname.clone());
+    labels.insert(
+        "severity".to_string(),
+        format!("{:?}", event.severity).to_lowercase(),
+    );
+    labels.insert(
+        "event_type".to_string(),
+        event.event_type.kind().to_string(),
+    );
+    labels
+}
+
+/// Push request grouping lines by stream, oldest first within each
+fn push_body(batch: &[Entry]) -> Value {
+    let mut streams: BTreeMap<&BTreeMap<String, String>, Vec<&Entry>> = BTreeMap::new();
+    for entry in batch {
+        streams.entry(&entry.labels).or_default().push(entry);
+    }
+
+    let streams: Vec<Value> = streams
+        .into_iter()
+        .map(|(labels, mut entries)| {
+            entries.sort_by_key(|entry| entry.timestamp_ns);
+            let values: Vec<Value> = entries
+                .iter()
+                .map(|entry| json!([entry.timestamp_ns.to_string(), entry.line]))
+                .collect();
+            json!({ "stream": labels, "values": values })
+        })
+        .collect();
+    json!({ "streams": streams })
+}
+
+/// Shortest time a push of `count` events may take at `per_sec`
+fn pace(count: usize, per_sec: u32) -> Duration {
+    if per_sec == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(count as f64 / per_sec as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::{EventType, Severity};
+
+    fn syslog(severity: Severity, host: &str) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "sshd".to_string(),
+                level: "info".to_string(),
+                message: "session opened".to_string(),
+            },
+            host.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_push_body_groups_streams() {
+        let config = LokiConfig::default();
+        let entry = |event: &LogEvent, timestamp_ns: i64| Entry {
+            labels: stream_labels(&config, event),
+            timestamp_ns,
+            line: event.to_json().unwrap(),
+        };
+        let batch = vec![
+            entry(&syslog(Severity::High, "web-1"), 30),
+            entry(&syslog(Severity::Info, "web-1"), 20),
+            entry(&syslog(Severity::High, "web-1"), 10),
+        ];
+
+        let body = push_body(&batch);
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        let high = streams
+            .iter()
+            .find(|s| s["stream"]["severity"] == "high")
+            .unwrap();
+        assert_eq!(high["stream"]["host"], "web-1");
+        assert_eq!(high["stream"]["event_type"], "system_log");
+        assert_eq!(high["stream"]["job"], "guardian");
+        assert_eq!(high["values"][0][0], "10");
+        assert_eq!(high["values"][1][0], "30");
+        let line: Value = serde_json::from_str(high["values"][0][1].as_str().unwrap()).unwrap();
+        assert_eq!(line["message"], "session opened");
+
+        assert_eq!(pace(500, 1000), Duration::from_millis(500));
+        assert_eq!(pace(500, 0), Duration::ZERO);
+    }
+}
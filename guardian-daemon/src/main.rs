@@ -1,22 +1,117 @@
-use anyhow::Result;
-use guardian_common::{EventType, FileOperation, LogEvent, Severity};
-use notify::{Event, EventKind, RecursiveMode, Watcher};
-use std::path::Path;
+use anyhow::{bail, Result};
+use guardian_common::ipc::Frame;
+use guardian_common::{ControlCommand, EventType, LogEvent, Severity};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
 use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
+mod archive;
+mod arp;
+mod auth_log;
+mod authorized_keys;
+mod bench;
+mod canary;
+mod capabilities;
+#[cfg(feature = "pcap")]
+mod capture;
+mod cli;
+mod config;
+mod control;
+mod demo;
+mod diff;
+mod elastic;
+mod enroll;
+mod executable;
+mod file_monitor;
+mod fluent;
+mod forward;
+mod gelf;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod heartbeat;
+#[cfg(target_os = "linux")]
+mod hidden;
+mod honeypot;
+mod import;
+mod ipc;
+mod listeners;
+mod loki;
+mod misp;
+mod netflow;
+mod outbox;
+mod packages;
+mod platform;
+mod policy;
+mod portscan;
+mod process;
+mod provision;
+mod queue;
+mod ransomware;
+mod rdns;
+mod reload;
+mod replay;
+mod resolver;
+mod response;
+mod rollup;
+mod rule_pack;
+mod rule_stats;
 mod rules;
+mod sampling;
+mod scan;
 mod scanner;
-
-use rules::RuleEngine;
+mod siem;
+mod sinks;
+mod snapshots;
+mod splunk;
+#[cfg(unix)]
+mod suid;
+mod syslog;
+mod tail;
+mod taxii;
+mod threatintel;
+#[cfg(any(test, feature = "pcap"))]
+mod traffic;
+mod users;
+mod yara_sources;
+
+use clap::Parser;
+use cli::{Cli, Command};
+use config::DaemonConfig;
+use file_monitor::{FileMonitor, MonitorSettings};
+use heartbeat::Heartbeat;
+use ipc::IpcServer;
+use portscan::PortScanDetector;
+use process::ProcessTracker;
+use ransomware::{RansomwareDetector, RansomwareResponse};
+use rdns::ReverseDns;
+use response::{HostIsolation, RuleResponder};
+use rollup::RollupAggregator;
+use rule_stats::NoisyRules;
+use rules::{RuleEngine, Verdict};
+use sampling::Sampler;
 use scanner::YaraScanner;
-use sysinfo::System;
+use sinks::Sinks;
 use std::time::Duration;
+use sysinfo::System;
+use threatintel::ThreatIntel;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Other commands run instead of the agent
+    let cli = Cli::parse();
+    let demo = cli.demo.then_some(cli.demo_rate);
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(demo).await,
+        _ if demo.is_some() => bail!("--demo only applies to `run`"),
+        Command::Bench { events, batch } => bench::run_command(events, batch).await,
+        command => cli::execute(command),
+    }
+}
+
+/// Run the agent until its event channel closes, with `demo` events a
+/// second from the generator in place of the collectors when set
+async fn run(demo: Option<u32>) -> Result<()> {
     // Initialize tracing for internal logging (stderr)
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -27,174 +122,474 @@ async fn main() -> Result<()> {
 
     info!("Guardian Daemon starting...");
 
+    let mut config = DaemonConfig::load()?;
+    let monitor_settings = MonitorSettings::new(&config)?;
+    if demo.is_none() {
+        policy::record_running(&config);
+    }
+
     // Get hostname
-    let hostname = hostname::get()
-        .unwrap_or_else(|_| "unknown".into())
-        .to_string_lossy()
-        .to_string();
+    let hostname = platform::hostname();
+
+    // Queue for events, with the configured policy for when it fills up
+    let (tx, mut rx) = queue::channel(&config.queue);
 
-    // Create channel for events
-    let (tx, mut rx) = mpsc::channel::<LogEvent>(1000);
+    // Initialize rule engine with the built-in rules and those in the config
+    let mut rule_engine = RuleEngine::with_definitions(&config.rules);
 
-    // Initialize rule engine
-    let rule_engine = RuleEngine::new();
+    // Load local threat-intel lists and start any configured feeds
+    let threat_intel = ThreatIntel::start(config.threat_intel.clone())?;
+    let rdns = ReverseDns::new(config.rdns.clone());
 
     // Initialize YARA scanner
-    let scanner = match YaraScanner::new() {
+    let scanner = match YaraScanner::load(&config.yara) {
         Ok(s) => Some(Arc::new(s)),
         Err(e) => {
-            error!("Failed to initialize YARA scanner: {}", e);
+            error!(
+                "Failed to initialize YARA scanner: {:#}",
+                anyhow::Error::from(e)
+            );
             None
         }
     };
+    // Keep remote YARA sources up to date in the running scanner
+    if let (Some(scanner), None) = (&scanner, demo) {
+        yara_sources::start(
+            config.yara.clone(),
+            config.rule_packs.clone(),
+            scanner.clone(),
+            tx.clone(),
+            hostname.clone(),
+        );
+    }
 
-    // Spawn file monitor task
-    let monitor_tx = tx.clone();
-    let monitor_hostname = hostname.clone();
-    let monitor_scanner = scanner.clone();
-    
-    tokio::task::spawn_blocking(move || {
-        if let Err(e) = start_file_monitor(monitor_tx, monitor_hostname, monitor_scanner) {
-            error!("File monitor error: {}", e);
+    // Versions of the diffed files, for the console to restore
+    let snapshots = if demo.is_none() && config.diff.enabled && config.snapshots.enabled {
+        match snapshots::SnapshotStore::open(&config.snapshots) {
+            Ok(store) => Some(Arc::new(std::sync::Mutex::new(store))),
+            Err(e) => {
+                error!("Failed to open snapshot store: {:#}", e);
+                None
+            }
         }
-    });
+    } else {
+        None
+    };
 
-    // Spawn system monitor task
-    let sys_tx = tx.clone();
-    let sys_hostname = hostname.clone();
-    tokio::task::spawn_blocking(move || {
-        monitor_system(sys_tx, sys_hostname);
-    });
+    let monitor = if let Some(rate) = demo {
+        // Generated events only; nothing on this host is watched
+        info!("Demo mode: generating about {} events per second", rate);
+        tokio::spawn(demo::run(tx.clone(), rate));
+        None
+    } else {
+        // Plant canaries before the file monitor starts, so planting them
+        // is not reported
+        if config.canaries.enabled {
+            let canaries = canary::Canaries::plant(&config.canaries)?;
+            info!("Watching {} canary files", canaries.len());
+            let canary_tx = tx.clone();
+            let canary_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = canary::run(canaries, canary_tx, canary_hostname) {
+                    error!("Canary watch error: {}", e);
+                }
+            });
+        }
 
-    info!("Guardian Daemon initialized. Monitoring events...");
+        // Spawn file monitor task
+        let monitor = FileMonitor::start(
+            tx.clone(),
+            hostname.clone(),
+            monitor_settings,
+            scanner.clone(),
+            snapshots.clone(),
+        );
+
+        // Spawn system monitor task
+        let sys_tx = tx.clone();
+        let sys_hostname = hostname.clone();
+        tokio::task::spawn_blocking(move || {
+            monitor_system(sys_tx, sys_hostname);
+        });
+
+        // Watch the ARP table for spoofed neighbours
+        if config.arp.enabled {
+            let arp_config = config.arp.clone();
+            let arp_tx = tx.clone();
+            let arp_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || arp::run(arp_config, arp_tx, arp_hostname));
+        }
 
-    // Main event loop - process events and output to stdout
-    while let Some(mut event) = rx.recv().await {
-        // Apply rule engine
-        if let Some(rule_name) = rule_engine.evaluate(&event) {
-            event = event.with_rule(rule_name);
+        // Look for processes hidden from the /proc listing
+        #[cfg(target_os = "linux")]
+        if config.hidden_processes.enabled {
+            let hidden_config = config.hidden_processes.clone();
+            let hidden_tx = tx.clone();
+            let hidden_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                hidden::run(hidden_config, hidden_tx, hidden_hostname)
+            });
         }
 
-        // Output JSON to stdout for Tauri to consume
-        match event.to_json() {
-            Ok(json) => println!("{}", json),
-            Err(e) => warn!("Failed to serialize event: {}", e),
+        // Keep an inventory of setuid and setgid binaries
+        #[cfg(unix)]
+        if config.suid.enabled {
+            let suid_config = config.suid.clone();
+            let suid_tx = tx.clone();
+            let suid_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = suid::run(suid_config, suid_tx, suid_hostname) {
+                    error!("Setuid inventory error: {}", e);
+                }
+            });
         }
-    }
 
-    Ok(())
-}
+        // Report services that start listening
+        if config.listening_ports.enabled {
+            let listen_config = config.listening_ports.clone();
+            let listen_tx = tx.clone();
+            let listen_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                listeners::run(listen_config, listen_tx, listen_hostname)
+            });
+        }
 
-/// Start file system monitoring
-fn start_file_monitor(
-    tx: mpsc::Sender<LogEvent>, 
-    hostname: String,
-    scanner: Option<Arc<YaraScanner>>
-) -> Result<()> {
-    let (notify_tx, notify_rx) = std::sync::mpsc::channel();
-
-    // Create watcher
-    let mut watcher = notify::recommended_watcher(notify_tx)?;
-
-    // Watch a specific path (configurable in production)
-    let watch_path = std::env::var("GUARDIAN_WATCH_PATH").unwrap_or_else(|_| "/tmp/guardian-test".to_string());
-    
-    info!("Watching path: {}", watch_path);
-    
-    // Create the directory if it doesn't exist
-    std::fs::create_dir_all(&watch_path)?;
-    
-    watcher.watch(Path::new(&watch_path), RecursiveMode::Recursive)?;
-
-    // Process file system events
-    for res in notify_rx {
-        match res {
-            Ok(event) => {
-                if let Some(log_event) = process_fs_event(event, &hostname, scanner.as_deref()) {
-                    if tx.blocking_send(log_event).is_err() {
-                        error!("Failed to send event - channel closed");
-                        break;
-                    }
+        // Watch the hosts file and resolver config for tampering
+        if config.resolver.enabled {
+            let resolver_config = config.resolver.clone();
+            let resolver_tx = tx.clone();
+            let resolver_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                resolver::run(resolver_config, resolver_tx, resolver_hostname)
+            });
+        }
+
+        // Report SSH keys added to authorized keys files
+        if config.authorized_keys.enabled {
+            let keys_config = config.authorized_keys.clone();
+            let keys_tx = tx.clone();
+            let keys_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                authorized_keys::run(keys_config, keys_tx, keys_hostname)
+            });
+        }
+
+        // Spawn auth and package manager log collectors where those logs exist
+        if platform::LOG_FILE_COLLECTORS {
+            tokio::spawn(auth_log::run(
+                config.auth_log.clone(),
+                tx.clone(),
+                hostname.clone(),
+            ));
+            tokio::spawn(packages::run(
+                config.packages.clone(),
+                tx.clone(),
+                hostname.clone(),
+            ));
+        }
+
+        // Receive syslog from other devices
+        syslog::start(&config.syslog, tx.clone()).await?;
+
+        // Receive logs from fluent-bit and Fluentd agents
+        fluent::start(&config.fluent, tx.clone()).await?;
+
+        // Receive flow records from routers
+        netflow::start(&config.netflow, tx.clone(), hostname.clone()).await?;
+
+        // Decoy ports that report anything connecting
+        honeypot::start(&config.honeypot, tx.clone(), hostname.clone()).await?;
+
+        // Per-connection traffic from captured packets
+        #[cfg(feature = "pcap")]
+        if config.capture.enabled {
+            let capture_config = config.capture.clone();
+            let capture_tx = tx.clone();
+            let capture_hostname = hostname.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = capture::run(capture_config, capture_tx, capture_hostname) {
+                    error!("Packet capture error: {}", e);
                 }
-            }
-            Err(e) => warn!("Watch error: {:?}", e),
+            });
+        }
+        #[cfg(not(feature = "pcap"))]
+        if config.capture.enabled {
+            warn!("Packet capture is enabled in the config but this build lacks the pcap feature");
         }
+
+        Some(monitor)
+    };
+
+    // Spawn control channel for console-initiated response actions, fed by
+    // socket consoles and stdin
+    let sampler = Arc::new(Sampler::new());
+    let rule_stats_requested = Arc::new(Notify::new());
+    let isolation = HostIsolation::new(tx.clone(), hostname.clone());
+    if response::nft_available() {
+        // An isolation table outlives a crash; expire it on schedule
+        tokio::spawn({
+            let isolation = isolation.clone();
+            async move { isolation.recover().await }
+        });
     }
+    let (reload_tx, mut reload_rx) = mpsc::channel::<String>(4);
+    reload::on_sighup(reload_tx.clone())?;
+    let (control_tx, control_rx) = mpsc::channel::<ControlCommand>(16);
+    tokio::spawn(control::read_stdin(control_tx.clone()));
+    tokio::spawn(control::run_control_channel(
+        control::ControlHandlers {
+            isolation: isolation.clone(),
+            sampler: sampler.clone(),
+            scans: scanner
+                .clone()
+                .map(|scanner| scan::OnDemandScanner::new(scanner, tx.clone(), hostname.clone())),
+            restores: snapshots
+                .clone()
+                .filter(|_| config.snapshots.restore)
+                .map(|store| snapshots::FileRestore::new(store, tx.clone(), hostname.clone())),
+            rule_stats: rule_stats_requested.clone(),
+            reload: reload_tx.clone(),
+        },
+        control_rx,
+    ));
 
-    Ok(())
-}
+    // Sign what is emitted so consumers can drop anything injected on the way to them
+    let signer = config.signing.signer()?;
+    if signer.is_some() {
+        info!("Signing emitted events");
+    }
 
-/// Convert notify events to LogEvents
-fn process_fs_event(
-    event: Event, 
-    hostname: &str,
-    scanner: Option<&YaraScanner>
-) -> Option<LogEvent> {
-    let operation = match event.kind {
-        EventKind::Create(_) => FileOperation::Create,
-        EventKind::Modify(_) => FileOperation::Modify,
-        EventKind::Remove(_) => FileOperation::Delete,
-        _ => return None,
-    };
+    // Announce capabilities so the console only offers supported actions
+    let mut hello = capabilities::hello(
+        &config,
+        &hostname,
+        scanner.is_some(),
+        snapshots.is_some() && config.snapshots.restore,
+        response::nft_available(),
+    );
+    if let Some(signer) = &signer {
+        if let Err(e) = signer.sign(&mut hello) {
+            warn!("Failed to sign hello: {}", e);
+        }
+    }
+    if config.ipc.stdout {
+        match hello.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => warn!("Failed to serialize hello: {}", e),
+        }
+    }
+
+    // Serve third-party consumers over gRPC when built in and enabled
+    #[cfg(feature = "grpc")]
+    let grpc = config.grpc.enabled.then(|| {
+        let api = grpc::GrpcApi::new(
+            config.grpc.clone(),
+            hello.clone(),
+            threat_intel.clone(),
+            config.threat_intel.clone(),
+            scanner.clone(),
+            reload_tx.clone(),
+        );
+        tokio::spawn(api.clone().serve());
+        api
+    });
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.enabled {
+        warn!("gRPC API is enabled in the config but this build lacks the grpc feature");
+    }
 
-    let path = event.paths.first()?.to_string_lossy().to_string();
+    // Copies for the collector, SIEMs and log stores, replaced on reload
+    let mut sinks = Sinks::start(&config, &hello, control_tx.clone())?;
 
-    // Default severity
-    let mut severity = if path.contains("/etc") || path.contains("passwd") || path.contains("shadow") {
-        Severity::High
-    } else if path.ends_with(".conf") || path.ends_with(".cfg") {
-        Severity::Medium
-    } else {
-        Severity::Low
+    // Serve consoles on the local socket
+    let ipc = IpcServer::new(hello.clone(), control_tx);
+    tokio::spawn(ipc.clone().listen(config.ipc.socket.clone()));
+
+    info!("Guardian Daemon initialized. Monitoring events...");
+
+    // Watch file activity for bulk encryption
+    let mut ransomware = RansomwareDetector::new(config.ransomware.clone());
+    let ransomware_response = RansomwareResponse::new(
+        config.ransomware.clone(),
+        tx.clone(),
+        hostname.clone(),
+        isolation.clone(),
+    )?;
+
+    // Responses asked for by rule actions
+    let rule_responder = RuleResponder::new(isolation, tx.clone(), hostname.clone());
+
+    let mut port_scans = PortScanDetector::new(config.port_scan.clone());
+
+    let mut rollups = RollupAggregator::new(config.rollup.clone(), hostname.clone());
+    let mut rollup_tick = tokio::time::interval(Duration::from_secs(60));
+    let heartbeat = Heartbeat::new(&config.heartbeat, hostname.clone());
+    let mut heartbeat_tick = tokio::time::interval(
+        heartbeat
+            .as_ref()
+            .map_or(Duration::from_secs(60), Heartbeat::interval),
+    );
+    let mut queue_tick =
+        tokio::time::interval(Duration::from_secs(config.queue.report_secs.max(1)));
+    let mut noisy_rules = NoisyRules::new(&config.rule_stats);
+    let noisy_interval = noisy_rules
+        .as_ref()
+        .map_or(Duration::from_secs(86400), NoisyRules::interval);
+    let mut noisy_tick =
+        tokio::time::interval_at(tokio::time::Instant::now() + noisy_interval, noisy_interval);
+
+    // Output JSON lines for pipes, and frames for socket consoles
+    let stdout = config.ipc.stdout;
+    let emit = |sinks: &Sinks, mut event: LogEvent| {
+        if let Some(signer) = &signer {
+            if let Err(e) = signer.sign(&mut event) {
+                warn!("Failed to sign event: {}", e);
+            }
+        }
+        if stdout {
+            match event.to_json() {
+                Ok(json) => println!("{}", json),
+                Err(e) => warn!("Failed to serialize event: {}", e),
+            }
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &grpc {
+            grpc.publish(&event);
+        }
+        sinks.send(&event);
+        ipc.send(Frame::Event(event));
     };
 
-    let mut rules_matched = Vec::new();
-    let mut matched_rule_name = None;
-
-    // Scan file if scanner is available and event is Create/Modify
-    if let Some(s) = scanner {
-        if matches!(operation, FileOperation::Create | FileOperation::Modify) {
-            // Only scan regular files
-            if Path::new(&path).is_file() {
-                let matches = s.scan_file(&path);
-                if !matches.is_empty() {
-                    severity = Severity::Critical;
-                    matched_rule_name = Some(matches[0].clone()); // Use first match as main rule
-                    rules_matched = matches;
+    // Main event loop - process events and hand them to the console
+    loop {
+        let mut event = tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = rollup_tick.tick() => {
+                if let Some(mut rollup) = rollups.flush_if_due(chrono::Utc::now()) {
+                    if let Some(signer) = &signer {
+                        if let Err(e) = signer.sign(&mut rollup) {
+                            warn!("Failed to sign rollup: {}", e);
+                        }
+                    }
+                    if stdout {
+                        match rollup.to_json() {
+                            Ok(json) => println!("{}", json),
+                            Err(e) => warn!("Failed to serialize rollup: {}", e),
+                        }
+                    }
+                    sinks.forward(Frame::Rollup(rollup.clone()));
+                    ipc.send(Frame::Rollup(rollup));
                 }
+                continue;
             }
+            // Heartbeats skip rules, rollups and sampling
+            _ = heartbeat_tick.tick(), if heartbeat.is_some() => {
+                if let Some(heartbeat) = &heartbeat {
+                    emit(&sinks, heartbeat.event(sinks.forwarder().map(|f| f.stats())));
+                }
+                continue;
+            }
+            // Queue reports are about the daemon itself, so they skip them too
+            _ = queue_tick.tick() => {
+                if let Some(report) = rx.report(&hostname) {
+                    emit(&sinks, report);
+                }
+                continue;
+            }
+            // So are rule hit counts, sent with each summary and on request
+            _ = noisy_tick.tick(), if noisy_rules.is_some() => {
+                let report = rule_engine.stats(&hostname);
+                if let Some(summary) = noisy_rules.as_mut().and_then(|n| n.summary(&report)) {
+                    emit(&sinks, summary);
+                }
+                sinks.forward(Frame::RuleStats(report.clone()));
+                ipc.send(Frame::RuleStats(report));
+                continue;
+            }
+            _ = rule_stats_requested.notified() => {
+                ipc.send(Frame::RuleStats(rule_engine.stats(&hostname)));
+                continue;
+            }
+            // Reloads swap the sinks and rules this loop owns between events
+            Some(requested_by) = reload_rx.recv() => {
+                let running = reload::Running {
+                    config: &mut config,
+                    sinks: &mut sinks,
+                    rules: &mut rule_engine,
+                    monitor: monitor.as_ref(),
+                    hello: &hello,
+                };
+                let event = reload::apply(running, &requested_by, &hostname).await;
+                policy::record_running(&config);
+                emit(&sinks, event);
+                continue;
+            }
+        };
+
+        // Resolve remote hostnames, escalate events matching threat-intel
+        // indicators, then apply rule engine
+        rdns.enrich(&mut event).await;
+        threat_intel.enrich(&mut event);
+        let Verdict {
+            event,
+            suppressed,
+            responses,
+        } = rule_engine.apply(event);
+        for (rule, response) in responses {
+            rule_responder.respond(&rule, response, &event);
         }
-    }
+        if let Some(burst) = ransomware.observe(&event) {
+            warn!(
+                "Ransomware suspected: {} files given suspicious names",
+                burst.written.len()
+            );
+            ransomware_response.respond(burst);
+        }
+        let port_scan = port_scans.observe(&event);
 
-    let mut log_event = LogEvent::new(
-        severity,
-        EventType::FileIntegrity {
-            path: path.clone(),
-            operation,
-            hash: None, // TODO: Compute file hash for Create/Modify
-        },
-        hostname.to_string(),
-    )
-    .with_tag("file_monitor");
+        // Suppressed events still feed the detectors above, but go no further
+        if suppressed {
+            if let Some(alert) = port_scan {
+                emit(&sinks, alert);
+            }
+            continue;
+        }
 
-    // Add tags for YARA matches
-    for rule in rules_matched {
-        log_event = log_event.with_tag(format!("yara:{}", rule));
-    }
+        // Count every event in the hourly rollup, then apply console-requested
+        // sampling; in `instead` mode routine Info events stop at the rollup
+        let emit_raw = rollups.record(&event);
+        if !emit_raw || !sampler.keep(&event) {
+            continue;
+        }
 
-    if let Some(rule) = matched_rule_name {
-        log_event = log_event.with_rule(rule);
+        emit(&sinks, event);
+        if let Some(alert) = port_scan {
+            emit(&sinks, alert);
+        }
     }
 
-    Some(log_event)
+    Ok(())
 }
 
-fn monitor_system(tx: mpsc::Sender<LogEvent>, hostname: String) {
+fn monitor_system(tx: queue::EventSender, hostname: String) {
     let mut sys = System::new_all();
-    
+    let mut tracker = ProcessTracker::default();
+
     loop {
         sys.refresh_all();
-        
+
+        // Report newly started processes with their parent chain
+        for started in tracker.new_processes(&sys) {
+            if let Some(event) = process::process_start_event(&sys, started, &hostname) {
+                if tx.blocking_send(event).is_err() {
+                    return;
+                }
+            }
+        }
+
         let pid = std::process::id();
         let cpu_usage = sys.global_cpu_info().cpu_usage();
         let memory_usage = sys.used_memory();
@@ -206,9 +601,12 @@ fn monitor_system(tx: mpsc::Sender<LogEvent>, hostname: String) {
                 name: "system".to_string(), // aggregated system stats
                 cpu_usage,
                 memory_usage,
+                ancestry: Vec::new(),
+                user: None,
             },
             hostname.clone(),
-        ).with_tag("system_monitor");
+        )
+        .with_tag("system_monitor");
 
         // Use blocking send for the standalone thread
         if tx.blocking_send(event).is_err() {
@@ -0,0 +1,205 @@
+use crate::config::MispConfig;
+use crate::threatintel::{Indicator, Indicators, ThreatIntel};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Source name under which MISP indicators are stored
+const SOURCE: &str = "misp";
+
+/// Attribute types pulled from MISP
+const ATTRIBUTE_TYPES: &[&str] = &[
+    "md5",
+    "sha1",
+    "sha256",
+    "sha512",
+    "filename|md5",
+    "filename|sha1",
+    "filename|sha256",
+    "ip-src",
+    "ip-dst",
+    "ip-src|port",
+    "ip-dst|port",
+    "domain",
+    "hostname",
+    "domain|ip",
+];
+
+#[derive(Debug, Deserialize)]
+struct RestSearchResponse {
+    response: AttributeList,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttributeList {
+    #[serde(rename = "Attribute", default)]
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Attribute {
+    #[serde(rename = "type")]
+    kind: String,
+    value: String,
+    event_id: String,
+}
+
+/// Client for the MISP `attributes/restSearch` API
+pub struct MispClient {
+    http: reqwest::Client,
+    config: MispConfig,
+}
+
+impl MispClient {
+    pub fn new(config: MispConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(!config.verify_tls)
+            .build()?;
+        Ok(Self { http, config })
+    }
+
+    /// Pull all IDS-flagged attributes of the supported types
+    pub async fn pull(&self) -> Result<Indicators> {
+        let mut body = json!({
+            "returnFormat": "json",
+            "type": ATTRIBUTE_TYPES,
+            "to_ids": true,
+            "deleted": false,
+        });
+        if let Some(last) = &self.config.last {
+            body["last"] = json!(last);
+        }
+
+        let url = format!(
+            "{}/attributes/restSearch",
+            self.config.url.trim_end_matches('/')
+        );
+        let response = self
+            .http
+            .post(&url)
+            .header("Authorization", &self.config.api_key)
+            .header("Accept", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .with_context(|| format!("MISP request to {} failed", url))?
+            .error_for_status()?;
+
+        parse_attributes(&response.text().await?)
+    }
+}
+
+/// Convert a restSearch response into indicators tagged with their MISP event
+fn parse_attributes(body: &str) -> Result<Indicators> {
+    let response: RestSearchResponse =
+        serde_json::from_str(body).context("Unexpected MISP response")?;
+    let mut indicators = Indicators::default();
+
+    for attribute in response.response.attributes {
+        let indicator = Indicator {
+            source: SOURCE.to_string(),
+            reference: Some(format!("misp_event:{}", attribute.event_id)),
+        };
+        // Composite types carry two values separated by `|`
+        let (first, second) = match attribute.value.split_once('|') {
+            Some((a, b)) => (a, Some(b)),
+            None => (attribute.value.as_str(), None),
+        };
+
+        match attribute.kind.as_str() {
+            "md5" | "sha1" | "sha256" | "sha512" => indicators.add_hash(first, indicator),
+            "filename|md5" | "filename|sha1" | "filename|sha256" => {
+                if let Some(hash) = second {
+                    indicators.add_hash(hash, indicator);
+                }
+            }
+            "ip-src" | "ip-dst" | "ip-src|port" | "ip-dst|port" => {
+                if let Err(e) = indicators.add_network(first, indicator) {
+                    debug!("Skipping MISP attribute {}: {}", attribute.value, e);
+                }
+            }
+            "domain" | "hostname" => indicators.add_domain(first, indicator),
+            "domain|ip" => {
+                indicators.add_domain(first, indicator.clone());
+                if let Some(ip) = second {
+                    if let Err(e) = indicators.add_network(ip, indicator) {
+                        debug!("Skipping MISP attribute {}: {}", attribute.value, e);
+                    }
+                }
+            }
+            other => debug!("Ignoring MISP attribute type {}", other),
+        }
+    }
+
+    Ok(indicators)
+}
+
+/// Periodically pull MISP attributes into the threat-intel store
+pub async fn run_feed(intel: ThreatIntel, config: MispConfig) {
+    let pull_secs = config.pull_secs.max(60);
+    let client = match MispClient::new(config) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("MISP feed disabled: {}", e);
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(pull_secs));
+    loop {
+        interval.tick().await;
+        match client.pull().await {
+            Ok(indicators) => {
+                info!("Pulled {} indicators from MISP", indicators.len());
+                intel.replace(SOURCE, indicators);
+            }
+            Err(e) => warn!("MISP pull failed, keeping previous indicators: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attributes() {
+        let body = r#"{"response": {"Attribute": [
+            {"id": "1", "event_id": "42", "type": "sha256", "value": "ABCDEF01", "to_ids": true},
+            {"id": "2", "event_id": "42", "type": "ip-dst|port", "value": "203.0.113.7|4444", "to_ids": true},
+            {"id": "3", "event_id": "7", "type": "domain|ip", "value": "evil.example|198.51.100.1", "to_ids": true},
+            {"id": "4", "event_id": "7", "type": "url", "value": "http://evil.example/x", "to_ids": true}
+        ]}}"#;
+
+        let indicators = parse_attributes(body).unwrap();
+        assert_eq!(indicators.len(), 4);
+        assert_eq!(
+            indicators
+                .match_hash("abcdef01")
+                .unwrap()
+                .reference
+                .as_deref(),
+            Some("misp_event:42")
+        );
+        assert!(indicators
+            .match_addr("203.0.113.7".parse().unwrap())
+            .is_some());
+        assert_eq!(
+            indicators
+                .match_domain("www.evil.example")
+                .unwrap()
+                .reference
+                .as_deref(),
+            Some("misp_event:7")
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_response() {
+        let indicators = parse_attributes(r#"{"response": {}}"#).unwrap();
+        assert_eq!(indicators.len(), 0);
+    }
+}
@@ -0,0 +1,586 @@
+//! NetFlow v5, NetFlow v9 and IPFIX collector, so traffic between other
+//! hosts, which this one never sees, reaches the same rules and storage as
+//! local connections. Each flow record becomes a `network_socket` event
+//! tagged with the exporter's address, seen from the flow's destination:
+//! its source is the remote end, as a scanner is to the hosts it probes.
+
+use crate::config::NetflowConfig;
+use crate::queue::EventSender;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, Severity, Traffic};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+/// Tag on events from flow records
+pub const NETFLOW_TAG: &str = "netflow";
+
+/// Largest datagram an exporter sends
+const MAX_DATAGRAM: usize = 65535;
+
+/// Templates kept across all exporters; new ones past it are dropped
+const MAX_TEMPLATES: usize = 4096;
+
+/// Length of a v5 record
+const V5_RECORD: usize = 48;
+
+/// Template length marking an IPFIX variable-length field
+const VARIABLE_LENGTH: u16 = 65535;
+
+/// Information elements read from v9 and IPFIX records
+const IN_BYTES: u16 = 1;
+const IN_PKTS: u16 = 2;
+const PROTOCOL: u16 = 4;
+const TCP_FLAGS: u16 = 6;
+const L4_SRC_PORT: u16 = 7;
+const IPV4_SRC_ADDR: u16 = 8;
+const L4_DST_PORT: u16 = 11;
+const IPV4_DST_ADDR: u16 = 12;
+const LAST_SWITCHED: u16 = 21;
+const IPV6_SRC_ADDR: u16 = 27;
+const IPV6_DST_ADDR: u16 = 28;
+const OCTET_TOTAL_COUNT: u16 = 85;
+const PACKET_TOTAL_COUNT: u16 = 86;
+const FLOW_END_SECONDS: u16 = 151;
+const FLOW_END_MILLISECONDS: u16 = 153;
+
+/// One unidirectional flow
+#[derive(Debug, PartialEq)]
+struct Flow {
+    src: SocketAddr,
+    dst: SocketAddr,
+    protocol: u8,
+    bytes: u64,
+    packets: u64,
+    tcp_flags: u8,
+    end: DateTime<Utc>,
+}
+
+/// A template field; enterprise-specific ones have no `id`
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    id: Option<u16>,
+    len: u16,
+}
+
+/// Export time of a packet, and the exporter's uptime for v9 timestamps
+#[derive(Clone, Copy)]
+struct Header {
+    export: DateTime<Utc>,
+    uptime_ms: Option<u32>,
+}
+
+/// Exporter address, observation domain and template ID
+type TemplateKey = (IpAddr, u32, u16);
+
+/// Unsigned big-endian integer of up to eight bytes
+fn uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .fold(0, |value, b| (value << 8) | u64::from(*b))
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16> {
+    let bytes = data.get(at..at + 2).context("truncated packet")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32> {
+    let bytes = data.get(at..at + 4).context("truncated packet")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn seconds(secs: u32) -> DateTime<Utc> {
+    DateTime::from_timestamp(i64::from(secs), 0).unwrap_or_default()
+}
+
+/// Time `uptime_ms` into the exporter's uptime, from the export header
+fn switched(header: Header, uptime_ms: u32) -> DateTime<Utc> {
+    match header.uptime_ms {
+        Some(now) => header.export - Duration::milliseconds(i64::from(now.wrapping_sub(uptime_ms))),
+        None => header.export,
+    }
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "icmp".to_string(),
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        58 => "ipv6-icmp".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Event for a flow reported by `exporter`
+fn to_event(flow: Flow, exporter: IpAddr, hostname: &str) -> LogEvent {
+    let state = if flow.tcp_flags & 0x05 != 0 {
+        "closed"
+    } else {
+        "flow"
+    };
+    let mut event = LogEvent::new(
+        Severity::Info,
+        EventType::NetworkSocket {
+            local_addr: flow.dst.to_string(),
+            remote_addr: Some(flow.src.to_string()),
+            remote_host: None,
+            protocol: protocol_name(flow.protocol),
+            state: state.to_string(),
+            traffic: Some(Traffic {
+                bytes_received: flow.bytes,
+                packets: flow.packets,
+                ..Traffic::default()
+            }),
+            process: None,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(NETFLOW_TAG)
+    .with_tag(format!("exporter:{}", exporter));
+    event.timestamp = flow.end;
+    event
+}
+
+/// Flow record decoder, holding the v9 and IPFIX templates each exporter
+/// has sent
+#[derive(Default)]
+struct Decoder {
+    templates: HashMap<TemplateKey, Vec<Field>>,
+}
+
+impl Decoder {
+    /// Flows in one export packet from `exporter`
+    fn decode(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<Flow>> {
+        match u16_at(data, 0)? {
+            5 => v5(data),
+            9 => {
+                let header = Header {
+                    export: seconds(u32_at(data, 8)?),
+                    uptime_ms: Some(u32_at(data, 4)?),
+                };
+                let domain = u32_at(data, 16)?;
+                self.sets(
+                    exporter,
+                    domain,
+                    header,
+                    data.get(20..).unwrap_or_default(),
+                    0,
+                    1,
+                )
+            }
+            10 => {
+                let length = usize::from(u16_at(data, 2)?).min(data.len());
+                let header = Header {
+                    export: seconds(u32_at(data, 4)?),
+                    uptime_ms: None,
+                };
+                let domain = u32_at(data, 12)?;
+                self.sets(
+                    exporter,
+                    domain,
+                    header,
+                    data.get(16..length).unwrap_or_default(),
+                    2,
+                    3,
+                )
+            }
+            version => bail!("unsupported flow export version {}", version),
+        }
+    }
+
+    /// v9 flowsets or IPFIX sets, with the set IDs used for templates and
+    /// options templates
+    fn sets(
+        &mut self,
+        exporter: IpAddr,
+        domain: u32,
+        header: Header,
+        mut data: &[u8],
+        template_set: u16,
+        options_set: u16,
+    ) -> Result<Vec<Flow>> {
+        let ipfix = template_set == 2;
+        let mut flows = Vec::new();
+        while data.len() >= 4 {
+            let id = u16_at(data, 0)?;
+            let length = usize::from(u16_at(data, 2)?);
+            if length < 4 || length > data.len() {
+                bail!("set of {} bytes overruns the packet", length);
+            }
+            let body = &data[4..length];
+            data = &data[length..];
+
+            if id == template_set {
+                self.templates_in(exporter, domain, body, ipfix)?;
+            } else if id == options_set {
+                // Options data describes the exporter, not flows
+            } else if id >= 256 {
+                match self.templates.get(&(exporter, domain, id)) {
+                    Some(fields) => flows.extend(records(fields, body, header, ipfix)),
+                    None => debug!("No template {} yet from {}", id, exporter),
+                }
+            }
+        }
+        Ok(flows)
+    }
+
+    fn templates_in(
+        &mut self,
+        exporter: IpAddr,
+        domain: u32,
+        mut body: &[u8],
+        ipfix: bool,
+    ) -> Result<()> {
+        while body.len() >= 4 {
+            let id = u16_at(body, 0)?;
+            let count = u16_at(body, 2)?;
+            let mut at = 4;
+            let mut fields = Vec::with_capacity(usize::from(count));
+            for _ in 0..count {
+                let element = u16_at(body, at)?;
+                let len = u16_at(body, at + 2)?;
+                at += 4;
+                // IPFIX enterprise elements carry a private enterprise number
+                let enterprise = ipfix && element & 0x8000 != 0;
+                if enterprise {
+                    at += 4;
+                }
+                fields.push(Field {
+                    id: (!enterprise).then_some(element),
+                    len,
+                });
+            }
+            let key = (exporter, domain, id);
+            if fields.is_empty() {
+                // A withdrawal
+                self.templates.remove(&key);
+            } else if self.templates.len() < MAX_TEMPLATES || self.templates.contains_key(&key) {
+                self.templates.insert(key, fields);
+            } else {
+                debug!(
+                    "Dropping template {} from {}: too many templates",
+                    id, exporter
+                );
+            }
+            body = body.get(at..).unwrap_or_default();
+        }
+        Ok(())
+    }
+}
+
+/// Records of a data set laid out by `fields`, up to the set's padding
+fn records(fields: &[Field], mut body: &[u8], header: Header, ipfix: bool) -> Vec<Flow> {
+    let min_len: usize = fields
+        .iter()
+        .map(|f| match f.len {
+            VARIABLE_LENGTH if ipfix => 1,
+            len => usize::from(len),
+        })
+        .sum();
+    let mut flows = Vec::new();
+    while min_len > 0 && body.len() >= min_len {
+        let mut values = HashMap::new();
+        let mut at = 0;
+        for field in fields {
+            let mut len = usize::from(field.len);
+            if ipfix && field.len == VARIABLE_LENGTH {
+                let Some(&short) = body.get(at) else {
+                    return flows;
+                };
+                at += 1;
+                len = usize::from(short);
+                if short == 255 {
+                    let Ok(long) = u16_at(body, at) else {
+                        return flows;
+                    };
+                    at += 2;
+                    len = usize::from(long);
+                }
+            }
+            let Some(value) = body.get(at..at + len) else {
+                return flows;
+            };
+            if let Some(id) = field.id {
+                values.insert(id, value);
+            }
+            at += len;
+        }
+        body = &body[at..];
+        if let Some(flow) = flow(&values, header) {
+            flows.push(flow);
+        }
+    }
+    flows
+}
+
+/// Flow from the values of one v9 or IPFIX record
+fn flow(values: &HashMap<u16, &[u8]>, header: Header) -> Option<Flow> {
+    let address = |v4: u16, v6: u16| -> Option<IpAddr> {
+        if let Some(bytes) = values.get(&v4) {
+            let octets: [u8; 4] = (*bytes).try_into().ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        } else {
+            let octets: [u8; 16] = (*values.get(&v6)?).try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+    };
+    let number = |id: u16| values.get(&id).map(|bytes| uint(bytes));
+    let end = if let Some(ms) = number(FLOW_END_MILLISECONDS) {
+        DateTime::from_timestamp_millis(ms as i64)?
+    } else if let Some(secs) = number(FLOW_END_SECONDS) {
+        seconds(secs as u32)
+    } else if let Some(uptime) = number(LAST_SWITCHED) {
+        switched(header, uptime as u32)
+    } else {
+        header.export
+    };
+    Some(Flow {
+        src: SocketAddr::new(
+            address(IPV4_SRC_ADDR, IPV6_SRC_ADDR)?,
+            number(L4_SRC_PORT).unwrap_or(0) as u16,
+        ),
+        dst: SocketAddr::new(
+            address(IPV4_DST_ADDR, IPV6_DST_ADDR)?,
+            number(L4_DST_PORT).unwrap_or(0) as u16,
+        ),
+        protocol: number(PROTOCOL).unwrap_or(0) as u8,
+        bytes: number(IN_BYTES)
+            .or_else(|| number(OCTET_TOTAL_COUNT))
+            .unwrap_or(0),
+        packets: number(IN_PKTS)
+            .or_else(|| number(PACKET_TOTAL_COUNT))
+            .unwrap_or(0),
+        tcp_flags: number(TCP_FLAGS).unwrap_or(0) as u8,
+        end,
+    })
+}
+
+/// NetFlow v5, whose records have a fixed layout
+fn v5(data: &[u8]) -> Result<Vec<Flow>> {
+    let count = usize::from(u16_at(data, 2)?);
+    let header = Header {
+        export: seconds(u32_at(data, 8)?) + Duration::nanoseconds(i64::from(u32_at(data, 12)?)),
+        uptime_ms: Some(u32_at(data, 4)?),
+    };
+    let records = data
+        .get(24..24 + count * V5_RECORD)
+        .with_context(|| format!("{} bytes is too short for {} v5 records", data.len(), count))?;
+    records
+        .chunks_exact(V5_RECORD)
+        .map(|record| {
+            let address =
+                |at| -> Result<IpAddr> { Ok(IpAddr::V4(Ipv4Addr::from(u32_at(record, at)?))) };
+            Ok(Flow {
+                src: SocketAddr::new(address(0)?, u16_at(record, 32)?),
+                dst: SocketAddr::new(address(4)?, u16_at(record, 34)?),
+                protocol: record[38],
+                bytes: u64::from(u32_at(record, 20)?),
+                packets: u64::from(u32_at(record, 16)?),
+                tcp_flags: record[37],
+                end: switched(header, u32_at(record, 28)?),
+            })
+        })
+        .collect()
+}
+
+async fn serve(socket: UdpSocket, exporters: Vec<IpAddr>, tx: EventSender, hostname: String) {
+    let mut decoder = Decoder::default();
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Flow export receive failed: {}", e);
+                continue;
+            }
+        };
+        if !exporters.is_empty() && !exporters.contains(&peer.ip()) {
+            debug!(
+                "Dropping flow export from {}: not an allowed exporter",
+                peer
+            );
+            continue;
+        }
+        let flows = match decoder.decode(peer.ip(), &buf[..len]) {
+            Ok(flows) => flows,
+            Err(e) => {
+                debug!("Dropping flow export from {}: {}", peer, e);
+                continue;
+            }
+        };
+        for flow in flows {
+            if tx.send(to_event(flow, peer.ip(), &hostname)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Bind the flow collector and feed received flows into `tx`
+pub async fn start(config: &NetflowConfig, tx: EventSender, hostname: String) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let listen: SocketAddr = config.listen.parse()?;
+    if config.exporters.is_empty() && !listen.ip().is_loopback() {
+        bail!(
+            "Flow collector on {} needs exporters to listen beyond loopback",
+            listen
+        );
+    }
+    let socket = UdpSocket::bind(listen)
+        .await
+        .with_context(|| format!("Failed to bind flow collector on {}", listen))?;
+    info!("Receiving NetFlow and IPFIX on udp/{}", listen);
+    tokio::spawn(serve(socket, config.exporters.clone(), tx, hostname));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> IpAddr {
+        "192.0.2.1".parse().unwrap()
+    }
+
+    #[test]
+    fn test_v5_records() {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&[0, 5, 0, 1]);
+        packet.extend_from_slice(&10_000u32.to_be_bytes()); // uptime
+        packet.extend_from_slice(&1_800_000_000u32.to_be_bytes());
+        packet.extend_from_slice(&[0; 12]);
+        let mut record = vec![0; V5_RECORD];
+        record[0..4].copy_from_slice(&[10, 1, 2, 3]);
+        record[4..8].copy_from_slice(&[198, 51, 100, 7]);
+        record[16..20].copy_from_slice(&12u32.to_be_bytes());
+        record[20..24].copy_from_slice(&4096u32.to_be_bytes());
+        record[28..32].copy_from_slice(&8_000u32.to_be_bytes()); // last switched
+        record[32..34].copy_from_slice(&51515u16.to_be_bytes());
+        record[34..36].copy_from_slice(&443u16.to_be_bytes());
+        record[37] = 0x01;
+        record[38] = 6;
+        packet.extend_from_slice(&record);
+
+        let flows = Decoder::default().decode(exporter(), &packet).unwrap();
+        assert_eq!(
+            flows,
+            vec![Flow {
+                src: "10.1.2.3:51515".parse().unwrap(),
+                dst: "198.51.100.7:443".parse().unwrap(),
+                protocol: 6,
+                bytes: 4096,
+                packets: 12,
+                tcp_flags: 0x01,
+                end: seconds(1_800_000_000) - Duration::seconds(2),
+            }]
+        );
+
+        let event = to_event(flows.into_iter().next().unwrap(), exporter(), "collector-1");
+        assert_eq!(event.hostname, "collector-1");
+        assert!(event.tags.contains(&"exporter:192.0.2.1".to_string()));
+        match &event.event_type {
+            EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                protocol,
+                state,
+                traffic: Some(traffic),
+                ..
+            } => {
+                // Seen from the destination, as port scan detection expects
+                assert_eq!(local_addr, "198.51.100.7:443");
+                assert_eq!(remote_addr.as_deref(), Some("10.1.2.3:51515"));
+                assert_eq!(protocol, "tcp");
+                assert_eq!(state, "closed");
+                assert_eq!(traffic.bytes_received, 4096);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        assert!(Decoder::default()
+            .decode(exporter(), &packet[..60])
+            .is_err());
+    }
+
+    #[test]
+    fn test_templates() {
+        // An IPFIX template with an enterprise field and a variable-length
+        // field, then a data set using it
+        let mut template = vec![0, 2, 0, 0, 1, 0, 0, 7];
+        for (id, len) in [
+            (IPV4_SRC_ADDR, 4u16),
+            (IPV4_DST_ADDR, 4),
+            (L4_SRC_PORT, 2),
+            (L4_DST_PORT, 2),
+            (PROTOCOL, 1),
+            (OCTET_TOTAL_COUNT, 8),
+        ] {
+            template.extend_from_slice(&id.to_be_bytes());
+            template.extend_from_slice(&len.to_be_bytes());
+        }
+        template.extend_from_slice(&[0x80, 1, 0xff, 0xff, 0, 0, 0x9e, 0x2b]);
+        let len = template.len() as u16;
+        template[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let mut data = vec![1, 0, 0, 0];
+        data.extend_from_slice(&[10, 0, 0, 9, 10, 0, 0, 1]);
+        data.extend_from_slice(&50000u16.to_be_bytes());
+        data.extend_from_slice(&22u16.to_be_bytes());
+        data.push(6);
+        data.extend_from_slice(&1234u64.to_be_bytes());
+        data.extend_from_slice(&[3, b'a', b'b', b'c']);
+        data.extend_from_slice(&[0, 0]); // padding
+        let len = data.len() as u16;
+        data[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let ipfix = |sets: &[&[u8]]| {
+            let mut packet = vec![0, 10, 0, 0];
+            packet.extend_from_slice(&1_800_000_000u32.to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 42]);
+            for set in sets {
+                packet.extend_from_slice(set);
+            }
+            let len = packet.len() as u16;
+            packet[2..4].copy_from_slice(&len.to_be_bytes());
+            packet
+        };
+
+        // Data before its template is skipped, and templates are per exporter
+        let mut decoder = Decoder::default();
+        assert!(decoder
+            .decode(exporter(), &ipfix(&[&data]))
+            .unwrap()
+            .is_empty());
+        assert!(decoder
+            .decode(exporter(), &ipfix(&[&template]))
+            .unwrap()
+            .is_empty());
+        let other = "192.0.2.2".parse().unwrap();
+        assert!(decoder.decode(other, &ipfix(&[&data])).unwrap().is_empty());
+
+        let flows = decoder.decode(exporter(), &ipfix(&[&data])).unwrap();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].src, "10.0.0.9:50000".parse().unwrap());
+        assert_eq!(flows[0].dst, "10.0.0.1:22".parse().unwrap());
+        assert_eq!(flows[0].bytes, 1234);
+        assert_eq!(flows[0].end, seconds(1_800_000_000));
+
+        // Past the cap, templates from a new source are not kept
+        let mut decoder = Decoder::default();
+        for n in 0..MAX_TEMPLATES {
+            decoder.templates.insert((other, n as u32, 256), Vec::new());
+        }
+        decoder.decode(exporter(), &ipfix(&[&template])).unwrap();
+        assert_eq!(decoder.templates.len(), MAX_TEMPLATES);
+        assert!(decoder
+            .decode(exporter(), &ipfix(&[&data]))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Bounded queue between the event loop and an output sink's delivery task.
+//! When the sink falls behind the oldest items are dropped, and items that
+//! failed to deliver go back to the front to keep their order.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::time::Instant;
+use tracing::warn;
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    dropped: u64,
+}
+
+pub struct Outbox<T> {
+    /// Sink name used in warnings
+    name: &'static str,
+    limit: usize,
+    queue: Mutex<Queue<T>>,
+    wake: Notify,
+    stop: StopSignal,
+}
+
+/// Ends a sink's delivery task when the sink is replaced on a config reload
+pub struct StopSignal(watch::Sender<bool>);
+
+impl Default for StopSignal {
+    fn default() -> Self {
+        Self(watch::Sender::new(false))
+    }
+}
+
+impl StopSignal {
+    pub fn stop(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Drive `task` until it finishes or [`StopSignal::stop`] is called
+    pub async fn run(&self, task: impl Future<Output = ()>) {
+        let mut stopped = self.0.subscribe();
+        tokio::select! {
+            _ = task => {}
+            _ = stopped.wait_for(|stopped| *stopped) => {}
+        }
+    }
+}
+
+impl<T> Outbox<T> {
+    pub fn new(name: &'static str, limit: usize) -> Self {
+        Self {
+            name,
+            limit: limit.max(1),
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                dropped: 0,
+            }),
+            wake: Notify::new(),
+            stop: StopSignal::default(),
+        }
+    }
+
+    /// Drive the sink's delivery task until [`Outbox::stop`]
+    pub async fn deliver(&self, task: impl Future<Output = ()>) {
+        self.stop.run(task).await
+    }
+
+    /// End the delivery task; items still queued are dropped
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queue an item, dropping the oldest when full
+    pub fn push(&self, item: T) {
+        let mut queue = self.lock();
+        if queue.items.len() >= self.limit {
+            queue.items.pop_front();
+            queue.dropped += 1;
+            if queue.dropped.is_power_of_two() {
+                warn!(
+                    "{} buffer full, {} items dropped so far",
+                    self.name, queue.dropped
+                );
+            }
+        }
+        queue.items.push_back(item);
+        drop(queue);
+        self.wake.notify_one();
+    }
+
+    /// Wait for the next item
+    pub async fn next(&self) -> T {
+        loop {
+            let notified = self.wake.notified();
+            if let Some(item) = self.lock().items.pop_front() {
+                return item;
+            }
+            notified.await;
+        }
+    }
+
+    /// Wait until `size` items are queued, or `flush` passes with at least one
+    pub async fn next_batch(&self, size: usize, flush: Duration) -> Vec<T> {
+        let size = size.max(1);
+        let mut deadline = Instant::now() + flush;
+        loop {
+            let notified = self.wake.notified();
+            {
+                let mut queue = self.lock();
+                let len = queue.items.len();
+                if len >= size || (len > 0 && Instant::now() >= deadline) {
+                    return queue.items.drain(..len.min(size)).collect();
+                }
+            }
+            if Instant::now() >= deadline {
+                deadline = Instant::now() + flush;
+            }
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep_until(deadline) => {}
+            }
+        }
+    }
+
+    /// Put back items to be delivered again, ahead of newer ones
+    pub fn requeue(&self, items: Vec<T>) {
+        let mut queue = self.lock();
+        for item in items.into_iter().rev() {
+            queue.items.push_front(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drops_oldest_and_requeues_in_order() {
+        let outbox = Outbox::new("test", 3);
+        for i in 0..5 {
+            outbox.push(i);
+        }
+        assert_eq!(outbox.next().await, 2);
+
+        let batch = outbox.next_batch(10, Duration::from_millis(20)).await;
+        assert_eq!(batch, vec![3, 4]);
+        outbox.push(5);
+        outbox.requeue(batch);
+        assert_eq!(
+            outbox.next_batch(2, Duration::from_secs(60)).await,
+            vec![3, 4]
+        );
+        assert_eq!(outbox.next().await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_delivery() {
+        let outbox = std::sync::Arc::new(Outbox::<u32>::new("test", 3));
+        let delivery = outbox.clone();
+        let task = tokio::spawn(async move {
+            delivery
+                .deliver(async {
+                    loop {
+                        delivery.next().await;
+                    }
+                })
+                .await
+        });
+        outbox.push(1);
+        outbox.stop();
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
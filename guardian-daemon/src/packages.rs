@@ -0,0 +1,177 @@
+use crate::config::PackageLogConfig;
+use crate::queue::EventSender;
+use crate::tail;
+use guardian_common::{EventType, GuardianError, LogEvent, PackageAction};
+
+/// Architecture suffixes of RPM package names
+const RPM_ARCHES: &[&str] = &[
+    "x86_64", "noarch", "i686", "aarch64", "ppc64le", "s390x", "armv7hl",
+];
+
+fn change(name: &str, version: Option<&str>, action: PackageAction, manager: &str) -> EventType {
+    EventType::PackageChange {
+        name: name.to_string(),
+        version: version.map(str::to_string),
+        action,
+        manager: manager.to_string(),
+    }
+}
+
+/// `2026-10-17 03:31:10 upgrade openssl:amd64 3.0.11-1 3.0.13-1`
+fn parse_dpkg(line: &str) -> Option<EventType> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [_, _, action, package, old, new] = fields[..] else {
+        return None;
+    };
+    let name = package.split(':').next()?;
+    let (action, version) = match action {
+        "install" => (PackageAction::Install, new),
+        "upgrade" => (PackageAction::Upgrade, new),
+        // `purge` follows `remove` for the same package, so only `remove` is reported
+        "remove" => (PackageAction::Remove, old),
+        _ => return None,
+    };
+    let version = (version != "<none>").then_some(version);
+    Some(change(name, version, action, "dpkg"))
+}
+
+/// Split `nginx-1:1.24.0-1.el9.x86_64` into name and `[epoch:]version-release`
+fn split_nevra(nevra: &str) -> (&str, Option<&str>) {
+    let without_arch = match nevra.rsplit_once('.') {
+        Some((rest, arch)) if RPM_ARCHES.contains(&arch) => rest,
+        _ => nevra,
+    };
+    let split = without_arch
+        .rsplit_once('-')
+        .and_then(|(rest, _)| rest.rsplit_once('-'));
+    match split {
+        Some((name, _)) => (name, Some(&without_arch[name.len() + 1..])),
+        None => (nevra, None),
+    }
+}
+
+/// dnf.rpm.log `... SUBDEBUG Installed: nginx-1:1.24.0-1.el9.x86_64`, or yum.log `... Erased: nginx`
+fn parse_rpm(line: &str) -> Option<EventType> {
+    const ACTIONS: &[(&str, PackageAction)] = &[
+        (" Installed: ", PackageAction::Install),
+        (" Upgrade: ", PackageAction::Upgrade),
+        (" Updated: ", PackageAction::Upgrade),
+        (" Downgrade: ", PackageAction::Downgrade),
+        (" Erase: ", PackageAction::Remove),
+        (" Erased: ", PackageAction::Remove),
+    ];
+
+    ACTIONS.iter().find_map(|(marker, action)| {
+        let (_, package) = line.split_once(marker)?;
+        let (name, version) = split_nevra(package.trim());
+        Some(change(name, version, *action, "rpm"))
+    })
+}
+
+/// `[2026-10-17T03:31:10+0000] [ALPM] upgraded openssl (3.1.0-1 -> 3.1.1-1)`
+fn parse_pacman(line: &str) -> Option<EventType> {
+    let (_, rest) = line.split_once("[ALPM] ")?;
+    let (action, rest) = rest.split_once(' ')?;
+    let action = match action {
+        "installed" => PackageAction::Install,
+        "upgraded" => PackageAction::Upgrade,
+        "downgraded" => PackageAction::Downgrade,
+        "removed" => PackageAction::Remove,
+        _ => return None,
+    };
+    let (name, versions) = rest.split_once(" (")?;
+    let versions = versions.trim_end().strip_suffix(')')?;
+    let version = versions.rsplit(" -> ").next();
+    Some(change(name, version, action, "pacman"))
+}
+
+/// Parse a line from any supported package manager log
+pub fn parse_line(line: &str) -> Option<EventType> {
+    parse_pacman(line)
+        .or_else(|| parse_rpm(line))
+        .or_else(|| parse_dpkg(line))
+}
+
+/// Wrap a parsed package change in an event
+pub fn to_event(event_type: EventType, hostname: &str) -> Result<LogEvent, GuardianError> {
+    LogEvent::builder(event_type, hostname)
+        .tag("package_manager")
+        .build()
+}
+
+/// Follow package manager logs and emit an event per package change
+pub async fn run(config: PackageLogConfig, tx: EventSender, hostname: String) {
+    if !config.enabled {
+        return;
+    }
+
+    tail::follow(&config.paths, config.poll_ms, tx, |line| {
+        parse_line(line).and_then(|event_type| to_event(event_type, &hostname).ok())
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_logs() {
+        assert_eq!(
+            parse_line("2026-10-17 03:31:10 install netcat-openbsd:amd64 <none> 1.219-1"),
+            Some(change(
+                "netcat-openbsd",
+                Some("1.219-1"),
+                PackageAction::Install,
+                "dpkg"
+            ))
+        );
+        assert_eq!(
+            parse_line("2026-10-17 03:31:12 remove nginx:amd64 1.24.0-1 <none>"),
+            Some(change(
+                "nginx",
+                Some("1.24.0-1"),
+                PackageAction::Remove,
+                "dpkg"
+            ))
+        );
+        assert_eq!(
+            parse_line("2026-10-17 03:31:11 status installed nginx:amd64 1.24.0-1"),
+            None
+        );
+        assert_eq!(
+            parse_line(
+                "2026-10-17T03:31:10+0000 SUBDEBUG Upgrade: openssl-libs-1:3.0.7-25.el9.x86_64"
+            ),
+            Some(change(
+                "openssl-libs",
+                Some("1:3.0.7-25.el9"),
+                PackageAction::Upgrade,
+                "rpm"
+            ))
+        );
+        assert_eq!(
+            parse_line(
+                "2026-10-17T03:31:10+0000 SUBDEBUG Upgraded: openssl-libs-1:3.0.7-24.el9.x86_64"
+            ),
+            None
+        );
+        assert_eq!(
+            parse_line("Oct 17 03:31:10 Erased: nmap"),
+            Some(change("nmap", None, PackageAction::Remove, "rpm"))
+        );
+        assert_eq!(
+            parse_line("[2026-10-17T03:31:10+0000] [ALPM] upgraded openssl (3.1.0-1 -> 3.1.1-1)"),
+            Some(change(
+                "openssl",
+                Some("3.1.1-1"),
+                PackageAction::Upgrade,
+                "pacman"
+            ))
+        );
+        assert_eq!(
+            parse_line("[2026-10-17T03:31:10+0000] [ALPM] running 'systemd-daemon-reload.hook'..."),
+            None
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! What differs between Linux and Windows: default paths, which collectors
+//! exist, how sensitive files are recognized and how the daemon runs as a
+//! system service. The rest of the daemon goes through this module instead
+//! of carrying its own `cfg` attributes.
+
+#[cfg(unix)]
+mod systemd;
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use unix::*;
+#[cfg(windows)]
+pub use windows::*;
+
+/// A socket waiting for connections or datagrams
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listener {
+    /// `tcp` or `udp`
+    pub protocol: &'static str,
+    pub addr: std::net::SocketAddr,
+    /// Owning process, when it could be found
+    pub pid: Option<u32>,
+}
+
+/// This machine's name as events report it: the DNS hostname, or the
+/// platform's hostname variable when the lookup fails
+pub fn hostname() -> String {
+    hostname::get()
+        .ok()
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var(HOSTNAME_ENV).ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_unix_paths() {
+        assert!(!hostname().is_empty());
+        assert!(is_sensitive_path("/etc/ssh/sshd_config"));
+        assert!(is_sensitive_path("/home/app/.htpasswd"));
+        assert!(!is_sensitive_path("/srv/www/index.php"));
+        assert!(is_critical_file("/etc/sudoers"));
+        assert!(!is_critical_file("/etc/hosts"));
+        assert!(run_service().is_err());
+    }
+
+    #[test]
+    fn test_arp_and_route_tables() {
+        let arp =
+            "IP address       HW type     Flags       HW address            Mask     Device\n\
+                   192.168.1.1      0x1         0x2         AA:BB:CC:00:11:22     *        eth0\n\
+                   192.168.1.77     0x1         0x0         00:00:00:00:00:00     *        eth0\n";
+        assert_eq!(
+            unix::parse_arp_table(arp),
+            vec![(
+                "192.168.1.1".parse().unwrap(),
+                "aa:bb:cc:00:11:22".to_string()
+            )]
+        );
+
+        let gateway = u32::from_ne_bytes([192, 168, 1, 1]);
+        let routes = format!(
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\t00000000\t{:08X}\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+             eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n",
+            gateway
+        );
+        assert_eq!(
+            unix::parse_default_gateways(&routes),
+            vec!["192.168.1.1".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_passwd_homes() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      daemon:x:1:1:daemon:/:/usr/sbin/nologin\n\
+                      # local accounts\n\
+                      bob:x:1000:1000:Bob,,,:/home/bob:/bin/zsh\n\
+                      broken:x:1001\n";
+        assert_eq!(
+            unix::parse_passwd_homes(passwd),
+            vec![
+                ("root".to_string(), "/root".into()),
+                ("bob".to_string(), "/home/bob".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_socket_tables() {
+        let loopback = u32::from_ne_bytes([127, 0, 0, 1]);
+        let tcp = format!(
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+             0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1001 1\n\
+             1: {:08X}:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1002 1\n\
+             2: {:08X}:D431 {:08X}:0016 01 00000000:00000000 00:00000000 00000000  1000        0 1003 1\n",
+            loopback, loopback, loopback
+        );
+        assert_eq!(
+            unix::parse_socket_table(&tcp, true),
+            vec![
+                ("0.0.0.0:22".parse().unwrap(), 1001),
+                ("127.0.0.1:8080".parse().unwrap(), 1002)
+            ]
+        );
+
+        let udp6 = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+                    0: 00000000000000000000000000000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 2001 2\n";
+        assert_eq!(
+            unix::parse_socket_table(udp6, false),
+            vec![("[::]:53".parse().unwrap(), 2001)]
+        );
+    }
+}
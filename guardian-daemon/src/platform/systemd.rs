@@ -0,0 +1,272 @@
+//! `guardian-daemon install-service` on Linux: a hardened systemd unit for
+//! this binary and config. The filesystem is read-only to the daemon apart
+//! from its socket directory, `/var/log/guardian`, `/var/lib/guardian`, SIEM
+//! output files, the queue's overflow file and the files snapshots restore, and it keeps only the capabilities monitoring and host isolation need.
+
+use crate::config::{BackpressurePolicy, DaemonConfig, RansomwareAction, SiemTransport};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const UNIT_NAME: &str = "guardian-daemon.service";
+const UNIT_PATH: &str = "/etc/systemd/system/guardian-daemon.service";
+
+/// Directory systemd creates for the default console socket
+const RUNTIME_DIR: &str = "/run/guardian";
+
+/// Directory systemd creates for the default overflow file
+const STATE_DIR: &str = "/var/lib/guardian";
+
+/// Reading any file and process, binding syslog's port and running `nft`
+const CAPABILITIES: &str = "CAP_DAC_READ_SEARCH CAP_SYS_PTRACE CAP_NET_BIND_SERVICE CAP_NET_ADMIN";
+
+/// `CAPABILITIES`, plus killing processes of other users when ransomware
+/// alerts do that and raw sockets for packet capture
+fn capabilities(config: &DaemonConfig) -> String {
+    let mut capabilities = CAPABILITIES.to_string();
+    if config.ransomware.enabled && config.ransomware.action == RansomwareAction::Kill {
+        capabilities.push_str(" CAP_KILL");
+    }
+    if config.capture.enabled {
+        capabilities.push_str(" CAP_NET_RAW");
+    }
+    capabilities
+}
+
+/// Quote a unit setting value containing whitespace
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Directories outside the unit's own that `config` writes to
+fn writable_paths(config: &DaemonConfig) -> Vec<PathBuf> {
+    let files = config
+        .siem
+        .iter()
+        .filter(|siem| siem.transport == SiemTransport::File)
+        .filter_map(|siem| siem.path.as_deref());
+    let spill = (config.queue.policy == BackpressurePolicy::Spill)
+        .then_some(config.queue.spill_path.as_path());
+    let mut dirs: Vec<PathBuf> = std::iter::once(config.ipc.socket.as_path())
+        .chain(files)
+        .chain(spill)
+        .filter_map(Path::parent)
+        .filter(|dir| {
+            !dir.starts_with(RUNTIME_DIR)
+                && !dir.starts_with("/var/log/guardian")
+                && !dir.starts_with(STATE_DIR)
+        })
+        .map(Path::to_path_buf)
+        .collect();
+    if config.archives.enabled && !config.archives.sandbox_dir.starts_with(STATE_DIR) {
+        dirs.push(config.archives.sandbox_dir.clone());
+    }
+    if config.canaries.enabled {
+        // Canaries are planted, even under the read-only /home
+        dirs.extend(config.canaries.dirs.iter().cloned());
+        dirs.extend(
+            config
+                .canaries
+                .manifest
+                .parent()
+                .filter(|dir| !dir.starts_with(STATE_DIR))
+                .map(Path::to_path_buf),
+        );
+    }
+    if config.suid.enabled {
+        dirs.extend(
+            config
+                .suid
+                .inventory
+                .parent()
+                .filter(|dir| !dir.starts_with(STATE_DIR))
+                .map(Path::to_path_buf),
+        );
+    }
+    if config.diff.enabled && config.snapshots.enabled {
+        dirs.extend(
+            Some(config.snapshots.dir.as_path())
+                .filter(|dir| !dir.starts_with(STATE_DIR))
+                .map(Path::to_path_buf),
+        );
+        // Restores replace a protected file by renaming a new copy over it
+        if config.snapshots.restore {
+            dirs.extend(config.diff.paths.iter().map(|path| {
+                if path.is_dir() {
+                    path.clone()
+                } else {
+                    path.parent().unwrap_or(path).to_path_buf()
+                }
+            }));
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Unit running `exe` with the config at `config_path`
+pub fn unit(exe: &Path, config_path: &Path, config: &DaemonConfig) -> String {
+    let mut unit = format!(
+        "[Unit]
+Description=Guardian security monitoring daemon
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=simple
+ExecStart={exe}
+# Applies watch paths, rules and sinks from the edited config
+ExecReload=/bin/kill -HUP $MAINPID
+Environment={environment}
+Restart=on-failure
+RestartSec=5s
+# Consoles read events from the socket; keep them out of the journal
+StandardOutput=null
+RuntimeDirectory=guardian
+RuntimeDirectoryMode=0750
+LogsDirectory=guardian
+StateDirectory=guardian
+# Holds spilled events and the running config
+StateDirectoryMode=0700
+
+NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=read-only
+PrivateDevices=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectKernelLogs=yes
+ProtectControlGroups=yes
+ProtectClock=yes
+ProtectHostname=yes
+RestrictSUIDSGID=yes
+RestrictRealtime=yes
+RestrictNamespaces=yes
+LockPersonality=yes
+SystemCallArchitectures=native
+RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK
+CapabilityBoundingSet={capabilities}
+",
+        exe = quote(&exe.to_string_lossy()),
+        environment = quote(&format!("GUARDIAN_CONFIG={}", config_path.display())),
+        capabilities = capabilities(config),
+    );
+    for dir in writable_paths(config) {
+        // `-` lets the unit start before the directory exists
+        unit.push_str(&format!(
+            "ReadWritePaths={}\n",
+            quote(&format!("-{}", dir.display()))
+        ));
+    }
+    unit.push_str("\n[Install]\nWantedBy=multi-user.target\n");
+    unit
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .args(args)
+        .status()
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        bail!("systemctl {} failed: {}", args.join(" "), status);
+    }
+    Ok(())
+}
+
+/// Write the unit, then enable and start it when `start` is set
+pub fn install(start: bool) -> Result<()> {
+    let config_path = DaemonConfig::path();
+    let config_path = std::fs::canonicalize(&config_path).unwrap_or(config_path);
+    let config = DaemonConfig::load()?;
+    let exe = std::env::current_exe().context("Failed to locate the daemon binary")?;
+
+    std::fs::write(UNIT_PATH, unit(&exe, &config_path, &config))
+        .with_context(|| format!("Failed to write {}", UNIT_PATH))?;
+    println!("Wrote {}", UNIT_PATH);
+    systemctl(&["daemon-reload"])?;
+
+    if start {
+        systemctl(&["enable", "--now", UNIT_NAME])?;
+        println!("Enabled and started {}", UNIT_NAME);
+    } else {
+        println!("Start it with `systemctl enable --now {}`", UNIT_NAME);
+    }
+    Ok(())
+}
+
+/// Stop and disable the unit, then remove it
+pub fn uninstall() -> Result<()> {
+    if !Path::new(UNIT_PATH).exists() {
+        bail!("{} is not installed", UNIT_PATH);
+    }
+    systemctl(&["disable", "--now", UNIT_NAME])?;
+    std::fs::remove_file(UNIT_PATH).with_context(|| format!("Failed to remove {}", UNIT_PATH))?;
+    systemctl(&["daemon-reload"])?;
+    println!("Removed {}", UNIT_PATH);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SiemConfig, SiemFormat};
+
+    #[test]
+    fn test_unit() {
+        let mut config = DaemonConfig::default();
+        config.ipc.socket = PathBuf::from("/run/guardian/daemon.sock");
+        config.siem.push(SiemConfig {
+            format: SiemFormat::Cef,
+            transport: SiemTransport::File,
+            address: None,
+            path: Some(PathBuf::from("/srv/siem/cef.log")),
+            min_severity: guardian_common::Severity::Info,
+            buffer: 100,
+        });
+        config.queue.policy = BackpressurePolicy::Spill;
+        config.archives.enabled = true;
+        config.archives.sandbox_dir = PathBuf::from("/srv/sandbox");
+        config.ransomware.action = RansomwareAction::Kill;
+        config.canaries.enabled = true;
+        config.canaries.dirs = vec![PathBuf::from("/home/shared")];
+
+        let unit = unit(
+            Path::new("/usr/local/bin/guardian-daemon"),
+            Path::new("/etc/guardian/my config.toml"),
+            &config,
+        );
+        assert!(unit.contains("ExecStart=/usr/local/bin/guardian-daemon\n"));
+        assert!(unit.contains("Environment=\"GUARDIAN_CONFIG=/etc/guardian/my config.toml\"\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("ExecReload=/bin/kill -HUP $MAINPID\n"));
+        assert!(unit.contains("NoNewPrivileges=yes\n"));
+        assert!(unit.contains("ProtectSystem=strict\n"));
+        assert!(unit.contains("ReadWritePaths=-/srv/siem\n"));
+        assert!(unit.contains("ReadWritePaths=-/srv/sandbox\n"));
+        assert!(unit.contains("ReadWritePaths=-/home/shared\n"));
+        // Protected files stay read-only unless restores are enabled
+        assert!(!unit.contains("ReadWritePaths=-/etc\n"));
+        assert!(!unit.contains("ReadWritePaths=-/run/guardian"));
+        // The default overflow file lives in the unit's state directory
+        assert!(unit.contains("StateDirectory=guardian\n"));
+        assert!(unit.contains("StateDirectoryMode=0700\n"));
+        assert!(!unit.contains("ReadWritePaths=-/var/lib/guardian"));
+        assert!(unit.contains(" CAP_NET_ADMIN CAP_KILL\n"));
+        assert!(unit.ends_with("WantedBy=multi-user.target\n"));
+
+        // Restores rename a new copy into the protected file's directory
+        config.snapshots.restore = true;
+        config.diff.paths = vec![PathBuf::from("/etc/ssh/sshd_config")];
+        let restoring = super::unit(
+            Path::new("/usr/local/bin/guardian-daemon"),
+            Path::new("/etc/guardian/my config.toml"),
+            &config,
+        );
+        assert!(restoring.contains("ReadWritePaths=-/etc/ssh\n"));
+    }
+}
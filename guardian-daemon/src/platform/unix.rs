@@ -0,0 +1,314 @@
+//! Linux and other Unix defaults
+
+use super::Listener;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+/// Config file used when `GUARDIAN_CONFIG` is not set
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/guardian/daemon.toml";
+
+/// Watch path used when neither the config nor `GUARDIAN_WATCH_PATH` name one
+pub const DEFAULT_WATCH_PATH: &str = "/tmp/guardian-test";
+
+/// Console socket used when neither the config nor `GUARDIAN_SOCKET` name one
+pub const DEFAULT_SOCKET: &str = "/run/guardian/daemon.sock";
+
+/// Copy of the config the running daemon loaded, which `apply` diffs against
+pub const RUNNING_CONFIG_PATH: &str = "/var/lib/guardian/running.toml";
+
+/// Overflow file for the `spill` queue policy
+pub const DEFAULT_SPILL_PATH: &str = "/var/lib/guardian/queue.ndjson";
+
+/// Where archive entries are extracted to be scanned
+pub const DEFAULT_ARCHIVE_SANDBOX: &str = "/var/lib/guardian/archive-sandbox";
+
+/// List of the canary files the daemon planted
+pub const DEFAULT_CANARY_MANIFEST: &str = "/var/lib/guardian/canaries.json";
+
+/// Inventory of the setuid and setgid binaries last seen
+pub const DEFAULT_SUID_INVENTORY: &str = "/var/lib/guardian/suid.json";
+
+/// Stored versions of the files kept for diffs
+pub const DEFAULT_SNAPSHOT_DIR: &str = "/var/lib/guardian/snapshots";
+
+/// Last good copies of the remote YARA sources
+pub const DEFAULT_YARA_CACHE_DIR: &str = "/var/lib/guardian/yara-sources";
+
+/// Directories searched for setuid and setgid binaries by default; the
+/// large, busy trees under /home, /tmp and /var are left to opt into
+pub const SUID_ROOTS: &[&str] = &["/usr", "/bin", "/sbin", "/opt", "/etc", "/root", "/srv"];
+
+/// Name resolution files watched for tampering; directories cover the
+/// files directly inside them
+pub const RESOLVER_PATHS: &[&str] = &[
+    "/etc/hosts",
+    "/etc/resolv.conf",
+    "/etc/nsswitch.conf",
+    "/etc/systemd/resolved.conf",
+    "/etc/systemd/resolved.conf.d",
+];
+
+/// Config files whose contents are kept so changes to them carry a diff;
+/// directories cover the files directly inside them. `/etc/shadow` is left
+/// out, as its diff would hold password hashes, and the hosts file, as the
+/// resolver check already alerts on it with the lines changed.
+pub const DIFF_PATHS: &[&str] = &[
+    "/etc/sudoers",
+    "/etc/sudoers.d",
+    "/etc/ssh/sshd_config",
+    "/etc/ssh/sshd_config.d",
+    "/etc/passwd",
+    "/etc/group",
+    "/etc/crontab",
+];
+
+/// Authentication logs followed by default (Debian uses auth.log, RHEL secure)
+pub const AUTH_LOGS: &[&str] = &["/var/log/auth.log", "/var/log/secure"];
+
+/// Package manager logs followed by default
+pub const PACKAGE_LOGS: &[&str] = &[
+    "/var/log/dpkg.log",
+    "/var/log/dnf.rpm.log",
+    "/var/log/yum.log",
+    "/var/log/pacman.log",
+];
+
+/// Whether the auth and package log collectors run here
+pub const LOG_FILE_COLLECTORS: bool = true;
+
+/// Environment variable holding the hostname
+pub(super) const HOSTNAME_ENV: &str = "HOSTNAME";
+
+/// Files holding accounts, credentials and privilege grants
+const CRITICAL_FILES: &[&str] = &["/etc/passwd", "/etc/shadow", "/etc/sudoers"];
+
+/// Whether a change to `path` deserves high severity by default
+pub fn is_sensitive_path(path: &str) -> bool {
+    path.contains("/etc") || path.contains("passwd") || path.contains("shadow")
+}
+
+/// Whether modifying or deleting `path` trips the critical file rule
+pub fn is_critical_file(path: &str) -> bool {
+    CRITICAL_FILES.iter().any(|file| path.contains(file))
+}
+
+/// Directories whose contents run at boot or login
+const STARTUP_DIRS: &[&str] = &[
+    "/etc/init.d/",
+    "/etc/systemd/system/",
+    "/etc/xdg/autostart/",
+    "/.config/autostart/",
+];
+
+/// Whether a file at `path` is started automatically at boot or login
+pub fn is_startup_path(path: &str) -> bool {
+    STARTUP_DIRS.iter().any(|dir| path.contains(dir))
+}
+
+/// Resolved IPv4 neighbours and their MAC addresses, from the kernel's ARP
+/// table
+pub fn arp_table() -> Result<Vec<(IpAddr, String)>> {
+    let table = std::fs::read_to_string("/proc/net/arp").context("Failed to read /proc/net/arp")?;
+    Ok(parse_arp_table(&table))
+}
+
+/// Rows of `/proc/net/arp` whose address is resolved
+pub(super) fn parse_arp_table(table: &str) -> Vec<(IpAddr, String)> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields.first()?.parse().ok()?;
+            let flags = u32::from_str_radix(fields.get(2)?.trim_start_matches("0x"), 16).ok()?;
+            let mac = fields.get(3)?.to_ascii_lowercase();
+            // ATF_COM: the entry is complete
+            (flags & 0x2 != 0 && mac != "00:00:00:00:00:00").then_some((ip, mac))
+        })
+        .collect()
+}
+
+/// IPv4 default gateways, from the kernel's routing table
+pub fn default_gateways() -> Vec<IpAddr> {
+    std::fs::read_to_string("/proc/net/route")
+        .map(|routes| parse_default_gateways(&routes))
+        .unwrap_or_default()
+}
+
+/// Gateways of the default routes in `/proc/net/route`, whose addresses are
+/// hex in host byte order
+pub(super) fn parse_default_gateways(routes: &str) -> Vec<IpAddr> {
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(1) != Some(&"00000000") || fields.get(7) != Some(&"00000000") {
+                return None;
+            }
+            let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+            (gateway != 0).then(|| IpAddr::V4(Ipv4Addr::from(gateway.to_ne_bytes())))
+        })
+        .collect()
+}
+
+/// Authorized keys files read for every account besides those under its
+/// home directory
+pub const AUTHORIZED_KEYS_FILES: &[&str] = &[];
+
+/// Accounts and their home directories, from `/etc/passwd`
+pub fn user_homes() -> Vec<(String, PathBuf)> {
+    std::fs::read_to_string("/etc/passwd")
+        .map(|passwd| parse_passwd_homes(&passwd))
+        .unwrap_or_default()
+}
+
+/// Name and home directory of each `/etc/passwd` entry with an absolute
+/// home other than `/`
+pub(super) fn parse_passwd_homes(passwd: &str) -> Vec<(String, PathBuf)> {
+    passwd
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let home = fields.get(5)?;
+            (home.starts_with('/') && *home != "/")
+                .then(|| (fields[0].to_string(), PathBuf::from(home)))
+        })
+        .collect()
+}
+
+/// Listening TCP sockets and bound, unconnected UDP sockets, from the
+/// kernel's socket tables, with the processes holding them
+pub fn listening_sockets(udp: bool) -> Result<Vec<Listener>> {
+    let tables: &[(&str, &'static str)] = if udp {
+        &[
+            ("tcp", "tcp"),
+            ("tcp6", "tcp"),
+            ("udp", "udp"),
+            ("udp6", "udp"),
+        ]
+    } else {
+        &[("tcp", "tcp"), ("tcp6", "tcp")]
+    };
+    let owners = socket_owners();
+    let mut listeners = Vec::new();
+    for (table, protocol) in tables {
+        let path = format!("/proc/net/{}", table);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            // The IPv6 tables are missing when IPv6 is disabled
+            Err(_) if table.ends_with('6') => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path)),
+        };
+        listeners.extend(
+            parse_socket_table(&contents, *protocol == "tcp")
+                .into_iter()
+                .map(|(addr, inode)| Listener {
+                    protocol,
+                    addr,
+                    pid: owners.get(&inode).copied(),
+                }),
+        );
+    }
+    Ok(listeners)
+}
+
+/// Address in a `/proc/net` socket table: the IP as hex words in host byte
+/// order, then the port in hex
+fn parse_socket_addr(field: &str) -> Option<SocketAddr> {
+    let (ip, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let word = |at: usize| -> Option<[u8; 4]> {
+        Some(
+            u32::from_str_radix(ip.get(at..at + 8)?, 16)
+                .ok()?
+                .to_ne_bytes(),
+        )
+    };
+    let ip = match ip.len() {
+        8 => IpAddr::V4(Ipv4Addr::from(word(0)?)),
+        32 => {
+            let mut octets = [0u8; 16];
+            for (i, chunk) in octets.chunks_mut(4).enumerate() {
+                chunk.copy_from_slice(&word(i * 8)?);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Local addresses and inodes of the listening rows of a `/proc/net` TCP or
+/// UDP table: `LISTEN` for TCP, unconnected for UDP
+pub(super) fn parse_socket_table(table: &str, tcp: bool) -> Vec<(SocketAddr, u64)> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local = parse_socket_addr(fields.get(1)?)?;
+            let remote = parse_socket_addr(fields.get(2)?)?;
+            let listening = if tcp {
+                *fields.get(3)? == "0A"
+            } else {
+                *fields.get(3)? == "07" && remote.port() == 0
+            };
+            let inode = fields.get(9)?.parse().ok()?;
+            (listening && inode != 0).then_some((local, inode))
+        })
+        .collect()
+}
+
+/// Process holding each socket inode, from the descriptors under `/proc`
+fn socket_owners() -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return owners;
+    };
+    for process in processes.flatten() {
+        let Some(pid) = process
+            .file_name()
+            .to_str()
+            .and_then(|pid| pid.parse().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|inode| inode.strip_suffix(']'))
+                .and_then(|inode| inode.parse().ok());
+            if let Some(inode) = inode {
+                owners.entry(inode).or_insert(pid);
+            }
+        }
+    }
+    owners
+}
+
+/// Install the daemon as a systemd unit, started right away when `start` is set
+pub fn install_service(start: bool) -> Result<()> {
+    super::systemd::install(start)
+}
+
+/// Remove the systemd unit
+pub fn uninstall_service() -> Result<()> {
+    super::systemd::uninstall()
+}
+
+/// `service run` is for the Windows Service Control Manager; systemd runs
+/// the binary directly
+pub fn run_service() -> Result<()> {
+    bail!("`service run` is only used on Windows; systemd runs `guardian-daemon` directly")
+}
@@ -0,0 +1,315 @@
+//! Windows defaults and Service Control Manager integration. The auth and
+//! package log collectors follow Unix text logs, so they do not run here.
+
+use super::Listener;
+use anyhow::{anyhow, bail, Context, Result};
+use std::ffi::OsString;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::error;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+/// Config file used when `GUARDIAN_CONFIG` is not set
+pub const DEFAULT_CONFIG_PATH: &str = r"C:\ProgramData\Guardian\daemon.toml";
+
+/// Watch path used when neither the config nor `GUARDIAN_WATCH_PATH` name one
+pub const DEFAULT_WATCH_PATH: &str = r"C:\Windows\System32\drivers\etc";
+
+/// Console pipe used when neither the config nor `GUARDIAN_SOCKET` name one
+pub const DEFAULT_SOCKET: &str = r"\\.\pipe\guardian";
+
+/// Copy of the config the running daemon loaded, which `apply` diffs against
+pub const RUNNING_CONFIG_PATH: &str = r"C:\ProgramData\Guardian\running.toml";
+
+/// Overflow file for the `spill` queue policy
+pub const DEFAULT_SPILL_PATH: &str = r"C:\ProgramData\Guardian\queue.ndjson";
+
+/// Where archive entries are extracted to be scanned
+pub const DEFAULT_ARCHIVE_SANDBOX: &str = r"C:\ProgramData\Guardian\archive-sandbox";
+
+/// List of the canary files the daemon planted
+pub const DEFAULT_CANARY_MANIFEST: &str = r"C:\ProgramData\Guardian\canaries.json";
+
+/// Inventory of the setuid and setgid binaries last seen; unused, as
+/// Windows has no such binaries
+pub const DEFAULT_SUID_INVENTORY: &str = r"C:\ProgramData\Guardian\suid.json";
+
+/// Stored versions of the files kept for diffs
+pub const DEFAULT_SNAPSHOT_DIR: &str = r"C:\ProgramData\Guardian\snapshots";
+
+/// Last good copies of the remote YARA sources
+pub const DEFAULT_YARA_CACHE_DIR: &str = r"C:\ProgramData\Guardian\yara-sources";
+
+/// Directories searched for setuid and setgid binaries by default
+pub const SUID_ROOTS: &[&str] = &[];
+
+/// Name resolution files watched for tampering
+pub const RESOLVER_PATHS: &[&str] = &[r"C:\Windows\System32\drivers\etc\hosts"];
+
+/// Config files whose contents are kept so changes to them carry a diff;
+/// the hosts file is left to the resolver check
+pub const DIFF_PATHS: &[&str] = &[];
+
+/// Authentication logs followed by default
+pub const AUTH_LOGS: &[&str] = &[];
+
+/// Package manager logs followed by default
+pub const PACKAGE_LOGS: &[&str] = &[];
+
+/// Whether the auth and package log collectors run here
+pub const LOG_FILE_COLLECTORS: bool = false;
+
+/// Environment variable holding the hostname
+pub(super) const HOSTNAME_ENV: &str = "COMPUTERNAME";
+
+/// Name the service is registered under
+const SERVICE_NAME: &str = "GuardianDaemon";
+const SERVICE_DISPLAY_NAME: &str = "Guardian Daemon";
+
+/// Registry hives holding accounts, credentials and system policy
+const CRITICAL_FILES: &[&str] = &[
+    r"\system32\config\sam",
+    r"\system32\config\security",
+    r"\system32\config\system",
+];
+
+/// Directories whose changes deserve high severity by default
+const SENSITIVE_DIRS: &[&str] = &[
+    r"\system32\drivers\etc",
+    r"\system32\config",
+    r"\system32\tasks",
+    r"\start menu\programs\startup",
+];
+
+/// Directories whose contents run at login
+const STARTUP_DIRS: &[&str] = &[r"\start menu\programs\startup\"];
+
+/// Runtime the service's agent runs on, set by `service run`
+static RUNTIME: OnceLock<tokio::runtime::Handle> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Whether a change to `path` deserves high severity by default
+pub fn is_sensitive_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    SENSITIVE_DIRS.iter().any(|dir| path.contains(dir))
+}
+
+/// Whether a file at `path` is started automatically at login
+pub fn is_startup_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    STARTUP_DIRS.iter().any(|dir| path.contains(dir))
+}
+
+/// Whether modifying or deleting `path` trips the critical file rule
+pub fn is_critical_file(path: &str) -> bool {
+    let path = path.to_lowercase();
+    CRITICAL_FILES.iter().any(|file| path.ends_with(file))
+}
+
+/// Output of a command from System32, which must succeed
+fn command_output(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!("{} exited with {}", program, output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Dynamic IPv4 neighbours and their MAC addresses, from `arp -a`
+pub fn arp_table() -> Result<Vec<(IpAddr, String)>> {
+    let table = command_output("arp", &["-a"])?;
+    Ok(table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [ip, mac, "dynamic"] = fields.as_slice() else {
+                return None;
+            };
+            Some((ip.parse().ok()?, mac.replace('-', ":").to_ascii_lowercase()))
+        })
+        .collect())
+}
+
+/// IPv4 default gateways, from `route print`
+pub fn default_gateways() -> Vec<IpAddr> {
+    let Ok(routes) = command_output("route", &["print", "-4", "0.0.0.0"]) else {
+        return Vec::new();
+    };
+    routes
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["0.0.0.0", "0.0.0.0", gateway, ..] => gateway.parse().ok(),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Authorized keys files read for every account besides those under its
+/// home directory; OpenSSH reads this one for administrators instead
+pub const AUTHORIZED_KEYS_FILES: &[&str] = &[r"C:\ProgramData\ssh\administrators_authorized_keys"];
+
+/// Profiles under `C:\Users`, named after the account that owns them
+pub fn user_homes() -> Vec<(String, PathBuf)> {
+    std::fs::read_dir(r"C:\Users")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Listening TCP sockets and bound UDP sockets, from `netstat -ano`
+pub fn listening_sockets(udp: bool) -> Result<Vec<Listener>> {
+    let table = command_output("netstat", &["-ano"])?;
+    Ok(table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (protocol, local, pid) = match fields.as_slice() {
+                ["TCP", local, _, "LISTENING", pid] => ("tcp", local, pid),
+                ["UDP", local, "*:*", pid] if udp => ("udp", local, pid),
+                _ => return None,
+            };
+            Some(Listener {
+                protocol,
+                addr: local.parse::<SocketAddr>().ok()?,
+                pid: pid.parse().ok().filter(|pid| *pid != 0),
+            })
+        })
+        .collect())
+}
+
+/// `service run`, as started by the Service Control Manager, which calls
+/// `service_main` on its own thread; commands run inside the runtime
+pub fn run_service() -> Result<()> {
+    RUNTIME.get_or_init(tokio::runtime::Handle::current);
+    tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))?;
+    Ok(())
+}
+
+/// Register the daemon to start at boot as LocalSystem, started right away
+/// when `start` is set
+pub fn install_service(start: bool) -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![OsString::from("service"), OsString::from("run")],
+        dependencies: Vec::new(),
+        account_name: None,
+        account_password: None,
+    };
+    let service =
+        manager.create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
+    service.set_description("Guardian file integrity and host monitoring agent")?;
+
+    if start {
+        service.start::<OsString>(&[])?;
+        println!("Installed and started the {} service", SERVICE_NAME);
+    } else {
+        println!(
+            "Installed the {} service; start it with `sc.exe start {}`",
+            SERVICE_NAME, SERVICE_NAME
+        );
+    }
+    Ok(())
+}
+
+/// Stop the service if it runs, then remove it
+pub fn uninstall_service() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+
+    println!("Removed the {} service", SERVICE_NAME);
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_agent() {
+        error!("Guardian service failed: {}", e);
+    }
+}
+
+fn status(state: ServiceState, exit_code: ServiceExitCode) -> ServiceStatus {
+    let controls_accepted = if state == ServiceState::Running {
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+    } else {
+        ServiceControlAccept::empty()
+    };
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+/// Run the agent until the Service Control Manager asks it to stop
+fn run_agent() -> Result<()> {
+    let stop = Arc::new(Notify::new());
+    let handler_stop = stop.clone();
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                handler_stop.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+    status_handle.set_service_status(status(ServiceState::Running, ServiceExitCode::Win32(0)))?;
+
+    let runtime = RUNTIME
+        .get()
+        .ok_or_else(|| anyhow!("service started without `service run`"))?;
+    let result = runtime.block_on(async {
+        tokio::select! {
+            result = crate::run(None) => result,
+            _ = stop.notified() => Ok(()),
+        }
+    });
+
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    status_handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+    result
+}
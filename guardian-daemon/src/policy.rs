@@ -0,0 +1,309 @@
+use crate::config::DaemonConfig;
+use crate::enroll::write_private;
+use crate::platform;
+use crate::provision::{write_if_changed, ApplyOutcome};
+use anyhow::{Context, Result};
+use guardian_common::GuardianError;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use tracing::warn;
+
+/// Fields identifying an entry in a list of tables, so entries are matched
+/// by identity rather than position
+const ENTRY_KEYS: &[&str] = &["path", "name"];
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One difference between the current config and a policy
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Change {
+    /// Dotted location, with list entries addressed by their path or name (`watch[/etc]`)
+    pub path: String,
+    pub kind: ChangeKind,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<Value>| v.as_ref().map(Value::to_string).unwrap_or_default();
+        match self.kind {
+            ChangeKind::Added => write!(f, "+ {} = {}", self.path, show(&self.after)),
+            ChangeKind::Removed => write!(f, "- {}", self.path),
+            ChangeKind::Changed => write!(
+                f,
+                "~ {}: {} -> {}",
+                self.path,
+                show(&self.before),
+                show(&self.after)
+            ),
+        }
+    }
+}
+
+fn child(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Identity of a list entry, if every entry in the list has one
+fn entry_key(value: &Value) -> Option<String> {
+    ENTRY_KEYS
+        .iter()
+        .find_map(|k| value.get(k))
+        .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string))
+}
+
+fn keyed(items: &[Value]) -> Option<Vec<(String, &Value)>> {
+    items
+        .iter()
+        .map(|item| entry_key(item).map(|key| (key, item)))
+        .collect()
+}
+
+fn diff_values(path: &str, before: &Value, after: &Value, changes: &mut Vec<Change>) {
+    match (before, after) {
+        (Value::Object(b), Value::Object(a)) => {
+            let keys: BTreeSet<&String> = b.keys().chain(a.keys()).collect();
+            for key in keys {
+                let null = Value::Null;
+                diff_values(
+                    &child(path, key),
+                    b.get(key).unwrap_or(&null),
+                    a.get(key).unwrap_or(&null),
+                    changes,
+                );
+            }
+        }
+        (Value::Array(b), Value::Array(a)) => diff_lists(path, b, a, changes),
+        _ if before == after => {}
+        (Value::Null, _) => changes.push(Change {
+            path: path.to_string(),
+            kind: ChangeKind::Added,
+            before: None,
+            after: Some(after.clone()),
+        }),
+        (_, Value::Null) => changes.push(Change {
+            path: path.to_string(),
+            kind: ChangeKind::Removed,
+            before: Some(before.clone()),
+            after: None,
+        }),
+        _ => changes.push(Change {
+            path: path.to_string(),
+            kind: ChangeKind::Changed,
+            before: Some(before.clone()),
+            after: Some(after.clone()),
+        }),
+    }
+}
+
+fn diff_lists(path: &str, before: &[Value], after: &[Value], changes: &mut Vec<Change>) {
+    // Lists of tables with an identity are diffed entry by entry
+    if let (Some(b), Some(a)) = (keyed(before), keyed(after)) {
+        for (key, old) in &b {
+            let entry = format!("{}[{}]", path, key);
+            match a.iter().find(|(k, _)| k == key) {
+                Some((_, new)) => diff_values(&entry, old, new, changes),
+                None => diff_values(&entry, old, &Value::Null, changes),
+            }
+        }
+        for (key, new) in a.iter().filter(|(k, _)| !b.iter().any(|(bk, _)| bk == k)) {
+            diff_values(&format!("{}[{}]", path, key), &Value::Null, new, changes);
+        }
+        return;
+    }
+
+    // Otherwise treat the list as a set of values
+    for old in before.iter().filter(|v| !after.contains(v)) {
+        diff_values(&format!("{}[]", path), old, &Value::Null, changes);
+    }
+    for new in after.iter().filter(|v| !before.contains(v)) {
+        diff_values(&format!("{}[]", path), &Value::Null, new, changes);
+    }
+}
+
+/// Structured differences between two configs
+pub fn diff(current: &DaemonConfig, policy: &DaemonConfig) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    diff_values(
+        "",
+        &serde_json::to_value(current)?,
+        &serde_json::to_value(policy)?,
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+/// Parse a policy file; `.toml` files are read as TOML, anything else as YAML
+pub fn load_policy(path: &Path) -> Result<DaemonConfig> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read policy {}", path.display()))?;
+    let policy = if path.extension().is_some_and(|e| e == "toml") {
+        DaemonConfig::from_toml(&contents)
+    } else {
+        serde_yaml::from_str(&contents).map_err(GuardianError::config)
+    };
+    policy.with_context(|| format!("Invalid policy {}", path.display()))
+}
+
+/// Record the settings the daemon is running with, at startup and after
+/// each reload, so `apply` diffs against them rather than a config file that
+/// may have been edited since
+pub fn record_running(config: &DaemonConfig) {
+    let path = Path::new(platform::RUNNING_CONFIG_PATH);
+    if let Err(e) = write_running(path, config) {
+        warn!(
+            "Failed to record the running config in {}: {:#}",
+            path.display(),
+            e
+        );
+    }
+}
+
+fn write_running(path: &Path, config: &DaemonConfig) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    // Outputs carry tokens and keys
+    write_private(path, toml::to_string_pretty(config)?)
+}
+
+/// The settings in effect: those the daemon last recorded, or the config
+/// file when no daemon has run yet
+fn running_config(recorded: &Path, config_path: &Path) -> Result<(DaemonConfig, String)> {
+    if recorded.exists() {
+        let config = DaemonConfig::from_file(recorded)?;
+        return Ok((config, "the running config".to_string()));
+    }
+    let config = if config_path.exists() {
+        DaemonConfig::from_file(config_path)?
+    } else {
+        DaemonConfig::default()
+    };
+    Ok((config, config_path.display().to_string()))
+}
+
+/// `apply <policy> [--dry-run]`: show the diff against the running config,
+/// then replace the config file
+pub fn apply_command(file: &Path, dry_run: bool) -> Result<()> {
+    let policy = load_policy(file)?;
+    let config_path = DaemonConfig::path();
+    let (current, source) = running_config(Path::new(platform::RUNNING_CONFIG_PATH), &config_path)?;
+
+    let changes = diff(&current, &policy)?;
+    if changes.is_empty() {
+        println!("No changes: {} matches the policy", source);
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    if dry_run {
+        println!("Dry run: {} change(s) not applied", changes.len());
+        return Ok(());
+    }
+
+    let outcome = write_if_changed(&config_path, &toml::to_string_pretty(&policy)?)?;
+    if outcome != ApplyOutcome::Unchanged {
+        println!(
+            "Applied {} change(s) to {}",
+            changes.len(),
+            config_path.display()
+        );
+    }
+    println!("Reload the daemon to put them into effect");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatchConfig;
+    use guardian_common::Severity;
+
+    #[test]
+    fn test_diff_matches_list_entries_by_identity() {
+        let mut current = DaemonConfig::default();
+        current.watch.push(WatchConfig::new("/etc"));
+        current.watch.push(WatchConfig::new("/tmp/uploads"));
+        current
+            .threat_intel
+            .ip_lists
+            .push("/etc/guardian/ips.txt".into());
+
+        let policy: DaemonConfig = serde_yaml::from_str(
+            r#"
+            watch:
+              - path: /srv/www
+              - path: /etc
+                severity: HIGH
+            rdns:
+              ttl_secs: 600
+            threat_intel:
+              ip_lists: [/etc/guardian/ips.txt, /etc/guardian/tor.txt]
+            "#,
+        )
+        .unwrap();
+
+        let changes = diff(&current, &policy).unwrap();
+        let summary: Vec<_> = changes.iter().map(|c| (c.kind, c.path.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeKind::Changed, "rdns.ttl_secs"),
+                (ChangeKind::Added, "threat_intel.ip_lists[]"),
+                (ChangeKind::Changed, "watch[/etc].severity"),
+                (ChangeKind::Removed, "watch[/tmp/uploads]"),
+                (ChangeKind::Added, "watch[/srv/www]"),
+            ]
+        );
+        assert_eq!(policy.watch[1].severity, Severity::High);
+        assert!(diff(&policy, &policy).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diffs_against_recorded_running_config() {
+        let dir = std::env::temp_dir().join(format!("guardian-policy-{}", uuid::Uuid::new_v4()));
+        let recorded = dir.join("running.toml");
+        let config_path = dir.join("daemon.toml");
+
+        let mut running = DaemonConfig::default();
+        running.watch.push(WatchConfig::new("/etc"));
+        write_running(&recorded, &running).unwrap();
+        // Edited on disk but not yet reloaded
+        let mut edited = running.clone();
+        edited.watch.push(WatchConfig::new("/srv/www"));
+        std::fs::write(&config_path, toml::to_string_pretty(&edited).unwrap()).unwrap();
+
+        let (current, source) = running_config(&recorded, &config_path).unwrap();
+        assert_eq!(source, "the running config");
+        let changes = diff(&current, &edited).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "watch[/srv/www]");
+
+        std::fs::remove_file(&recorded).unwrap();
+        let (current, _) = running_config(&recorded, &config_path).unwrap();
+        assert!(diff(&current, &edited).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_rejects_unknown_fields() {
+        assert!(serde_yaml::from_str::<DaemonConfig>("watchh: []").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Port scan detection over `network_socket` events: a remote address that
+//! reaches many distinct local ports within a short window is probing the
+//! host. Honeypot ports are the usual source, since every connection to
+//! one is reported. Local ports in the ephemeral range are left out, as
+//! those are the ones the host picks for its own outgoing connections.
+
+use crate::config::PortScanConfig;
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+
+/// Tag on port scan alerts
+pub const PORT_SCAN_TAG: &str = "port_scan";
+
+/// Rule name on port scan alerts
+pub const PORT_SCAN_RULE: &str = "port_scan_detected";
+
+/// A connection to one local port
+struct Probe {
+    time: DateTime<Utc>,
+    port: u16,
+    id: Uuid,
+}
+
+/// Distinct local ports each remote address reached within the window
+pub struct PortScanDetector {
+    config: PortScanConfig,
+    probes: HashMap<IpAddr, VecDeque<Probe>>,
+}
+
+impl PortScanDetector {
+    pub fn new(config: PortScanConfig) -> Self {
+        Self {
+            config,
+            probes: HashMap::new(),
+        }
+    }
+
+    /// Count a connection, returning an alert once its remote address has
+    /// reached `min_ports` distinct ports within the window
+    pub fn observe(&mut self, event: &LogEvent) -> Option<LogEvent> {
+        if !self.config.enabled {
+            return None;
+        }
+        let EventType::NetworkSocket {
+            local_addr,
+            remote_addr: Some(remote_addr),
+            ..
+        } = &event.event_type
+        else {
+            return None;
+        };
+        let port = local_addr.parse::<SocketAddr>().ok()?.port();
+        let remote = remote_ip(remote_addr)?;
+        if port >= self.config.ephemeral_start {
+            return None;
+        }
+
+        let cutoff = event.timestamp - Duration::seconds(self.config.window_secs as i64);
+        self.probes.retain(|_, probes| {
+            probes.retain(|probe| probe.time > cutoff);
+            !probes.is_empty()
+        });
+        let probes = self.probes.entry(remote).or_default();
+        probes.push_back(Probe {
+            time: event.timestamp,
+            port,
+            id: event.id,
+        });
+
+        let ports: BTreeSet<u16> = probes.iter().map(|probe| probe.port).collect();
+        if ports.len() < self.config.min_ports.max(1) {
+            return None;
+        }
+
+        // Start over, so one scan raises one alert until it probes more
+        let probes = self.probes.remove(&remote)?;
+        let first = probes.front()?.time;
+        let secs = (event.timestamp - first).num_milliseconds().max(1000) as f64 / 1000.0;
+        let rate = ports.len() as f64 * 60.0 / secs;
+        let list = ports
+            .iter()
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Some(
+            LogEvent::new(
+                Severity::High,
+                EventType::Alert {
+                    title: format!("Port scan from {}", remote),
+                    description: format!(
+                        "{} probed {} local ports in {:.0}s ({:.1} ports/min): {}",
+                        remote,
+                        ports.len(),
+                        secs,
+                        rate,
+                        list
+                    ),
+                    related_event_ids: probes.iter().map(|probe| probe.id).collect(),
+                    technique: Some("T1046".to_string()),
+                },
+                event.hostname.clone(),
+            )
+            .with_tag(PORT_SCAN_TAG)
+            .with_tag(format!("scan_source:{}", remote))
+            .with_rule(PORT_SCAN_RULE),
+        )
+    }
+}
+
+/// Address part of `ip:port`, `[ipv6]:port` or a bare address
+fn remote_ip(remote: &str) -> Option<IpAddr> {
+    remote
+        .parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| remote.parse())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(local: &str, remote: &str, seconds: i64) -> LogEvent {
+        let mut event = LogEvent::new(
+            Severity::Critical,
+            EventType::NetworkSocket {
+                local_addr: local.to_string(),
+                remote_addr: Some(remote.to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "honeypot".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "web-1".to_string(),
+        );
+        event.timestamp = DateTime::UNIX_EPOCH + Duration::seconds(seconds);
+        event
+    }
+
+    #[test]
+    fn test_distinct_ports_per_source() {
+        let mut detector = PortScanDetector::new(PortScanConfig {
+            min_ports: 3,
+            ..PortScanConfig::default()
+        });
+
+        // A probe that falls out of the window, the same port again, another
+        // source and an ephemeral port do not add up
+        for event in [
+            connection("0.0.0.0:23", "10.0.0.9:50000", 0),
+            connection("0.0.0.0:22", "10.0.0.9:50001", 100),
+            connection("0.0.0.0:22", "10.0.0.9:50002", 101),
+            connection("0.0.0.0:3389", "10.0.0.7:50003", 102),
+            connection("10.0.0.1:51515", "10.0.0.9:443", 103),
+        ] {
+            assert!(detector.observe(&event).is_none());
+        }
+
+        assert!(detector
+            .observe(&connection("0.0.0.0:3389", "10.0.0.9:50004", 130))
+            .is_none());
+        let alert = detector
+            .observe(&connection("0.0.0.0:445", "10.0.0.9:50005", 135))
+            .unwrap();
+        assert_eq!(alert.rule_name.as_deref(), Some(PORT_SCAN_RULE));
+        match &alert.event_type {
+            EventType::Alert {
+                description,
+                related_event_ids,
+                ..
+            } => {
+                assert_eq!(
+                    description,
+                    "10.0.0.9 probed 3 local ports in 35s (5.1 ports/min): 22, 445, 3389"
+                );
+                assert_eq!(related_event_ids.len(), 4);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
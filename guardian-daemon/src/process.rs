@@ -0,0 +1,116 @@
+use guardian_common::{EventType, LogEvent, ProcessAncestor, Severity};
+use std::collections::HashSet;
+use sysinfo::{Pid, System};
+
+/// Longest parent chain reported, guarding against pid reuse loops
+const MAX_ANCESTRY_DEPTH: usize = 32;
+
+/// Walk parent links from `parent`, using `lookup` to fetch `(name, parent)` for a pid
+fn walk(
+    mut parent: Option<u32>,
+    lookup: impl Fn(u32) -> Option<(String, Option<u32>)>,
+) -> Vec<ProcessAncestor> {
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+
+    while let Some(pid) = parent {
+        if chain.len() >= MAX_ANCESTRY_DEPTH || !seen.insert(pid) {
+            break;
+        }
+        let Some((name, next)) = lookup(pid) else {
+            break;
+        };
+        chain.push(ProcessAncestor { pid, name });
+        parent = next;
+    }
+
+    chain
+}
+
+/// Parent chain of `pid`, nearest parent first
+pub fn ancestry(sys: &System, pid: Pid) -> Vec<ProcessAncestor> {
+    let parent = sys.process(pid).and_then(|p| p.parent());
+    walk(parent.map(|p| p.as_u32()), |pid| {
+        sys.process(Pid::from_u32(pid))
+            .map(|p| (p.name().to_string(), p.parent().map(|pp| pp.as_u32())))
+    })
+}
+
+/// Detects processes started since the previous refresh
+#[derive(Default)]
+pub struct ProcessTracker {
+    known: HashSet<Pid>,
+    primed: bool,
+}
+
+impl ProcessTracker {
+    /// Pids that appeared since the last call; the first call only records a baseline
+    pub fn new_processes(&mut self, sys: &System) -> Vec<Pid> {
+        let current: HashSet<Pid> = sys.processes().keys().copied().collect();
+        let started = if self.primed {
+            current.difference(&self.known).copied().collect()
+        } else {
+            Vec::new()
+        };
+        self.known = current;
+        self.primed = true;
+        started
+    }
+}
+
+/// Build a process-start event with the parent chain attached
+pub fn process_start_event(sys: &System, pid: Pid, hostname: &str) -> Option<LogEvent> {
+    let process = sys.process(pid)?;
+    Some(
+        LogEvent::new(
+            Severity::Info,
+            EventType::ProcessMonitor {
+                pid: pid.as_u32(),
+                name: process.name().to_string(),
+                cpu_usage: process.cpu_usage(),
+                memory_usage: process.memory(),
+                ancestry: ancestry(sys, pid),
+                user: crate::users::process_owner(process),
+            },
+            hostname.to_string(),
+        )
+        .with_tag("process_start"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_walk_stops_at_root_and_cycles() {
+        let table: HashMap<u32, (&str, Option<u32>)> = HashMap::from([
+            (300, ("bash", Some(200))),
+            (200, ("nginx", Some(1))),
+            (1, ("systemd", None)),
+            // Reused pids forming a loop
+            (20, ("a", Some(21))),
+            (21, ("b", Some(20))),
+        ]);
+        let lookup = |pid: u32| table.get(&pid).map(|(n, p)| (n.to_string(), *p));
+
+        let chain = walk(Some(200), lookup);
+        let names: Vec<_> = chain.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["nginx", "systemd"]);
+
+        assert_eq!(walk(Some(20), lookup).len(), 2);
+        assert!(walk(Some(999), lookup).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ancestry_of_current_process() {
+        let sys = System::new_all();
+        let chain = ancestry(&sys, Pid::from_u32(std::process::id()));
+        assert_eq!(
+            chain.first().map(|a| a.pid),
+            Some(std::os::unix::process::parent_id())
+        );
+    }
+}
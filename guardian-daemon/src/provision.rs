@@ -0,0 +1,231 @@
+use crate::config::DaemonConfig;
+use crate::enroll::create_private;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Format version written into provisioning files
+const PROVISION_VERSION: u32 = 1;
+
+/// The complete effective setup of an agent in one file: its config plus the
+/// watchlist files the config points at. May contain feed credentials.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Provision {
+    pub version: u32,
+    pub config: DaemonConfig,
+    #[serde(default)]
+    pub files: Vec<BundledFile>,
+}
+
+/// A file referenced by the config, carried inline
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BundledFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// What applying did to one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyOutcome {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+impl Provision {
+    /// Capture `config` and the watchlists it references
+    pub fn export(config: &DaemonConfig) -> Result<Self> {
+        let lists = config
+            .threat_intel
+            .hash_lists
+            .iter()
+            .chain(&config.threat_intel.ip_lists);
+
+        let mut files = Vec::new();
+        for path in lists {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read watchlist {}", path.display()))?;
+            files.push(BundledFile {
+                path: path.clone(),
+                contents,
+            });
+        }
+
+        Ok(Self {
+            version: PROVISION_VERSION,
+            config: config.clone(),
+            files,
+        })
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let provision: Self = toml::from_str(contents)?;
+        if provision.version > PROVISION_VERSION {
+            bail!(
+                "Provisioning file version {} is newer than supported version {}",
+                provision.version,
+                PROVISION_VERSION
+            );
+        }
+        Ok(provision)
+    }
+
+    /// Write the config to `config_path` and every bundled file to its path.
+    /// Files already matching are left untouched, so applying twice is a no-op.
+    pub fn apply(&self, config_path: &Path) -> Result<Vec<(PathBuf, ApplyOutcome)>> {
+        let config = toml::to_string_pretty(&self.config)?;
+
+        let mut outcomes = Vec::new();
+        for file in &self.files {
+            let outcome = write_if_changed(&file.path, &file.contents)?;
+            outcomes.push((file.path.clone(), outcome));
+        }
+        // The config goes last so an agent never picks up a config whose lists are missing
+        outcomes.push((
+            config_path.to_path_buf(),
+            write_if_changed(config_path, &config)?,
+        ));
+        Ok(outcomes)
+    }
+}
+
+/// Replace `path` with `contents` via a temporary file and rename, unless it
+/// already matches. The files written hold keys and passwords, so the
+/// temporary file gets a random name, is created afresh and readable by the
+/// owner only, and only takes the mode of the file it replaces through its
+/// handle.
+pub(crate) fn write_if_changed(path: &Path, contents: &str) -> Result<ApplyOutcome> {
+    let outcome = match std::fs::read_to_string(path) {
+        Ok(existing) if existing == contents => return Ok(ApplyOutcome::Unchanged),
+        Ok(_) => ApplyOutcome::Updated,
+        Err(_) => ApplyOutcome::Created,
+    };
+
+    let dir = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+            parent
+        }
+        None => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let tmp = dir.join(format!(
+        ".{}.{}.guardian-tmp",
+        name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let written = (|| -> Result<()> {
+        let mut file = create_private(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        if let Ok(existing) = std::fs::metadata(path) {
+            file.set_permissions(existing.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(outcome)
+}
+
+/// `export-config`: print the effective setup as a provisioning file
+pub fn export_command() -> Result<()> {
+    let provision = Provision::export(&DaemonConfig::load()?)?;
+    print!("{}", provision.to_toml()?);
+    Ok(())
+}
+
+/// `apply-config <file>`: write a provisioning file's config and watchlists
+pub fn apply_command(file: &Path) -> Result<()> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let provision = Provision::from_toml(&contents)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+    for (path, outcome) in provision.apply(&DaemonConfig::path())? {
+        println!("{:?}: {}", outcome, path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatchConfig;
+
+    #[test]
+    fn test_export_apply_round_trip_is_idempotent() {
+        let dir = std::env::temp_dir().join(format!("guardian-provision-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("hashes.txt");
+        std::fs::write(&list, "e3b0c442 # empty\n").unwrap();
+
+        let mut config = DaemonConfig::default();
+        config.watch.push(WatchConfig::new("/srv/www"));
+        config.threat_intel.hash_lists.push(list.clone());
+
+        let exported = Provision::export(&config).unwrap().to_toml().unwrap();
+        std::fs::remove_file(&list).unwrap();
+
+        let provision = Provision::from_toml(&exported).unwrap();
+        assert_eq!(provision.config, config);
+
+        let config_path = dir.join("etc/daemon.toml");
+        let first = provision.apply(&config_path).unwrap();
+        assert!(first.iter().all(|(_, o)| *o == ApplyOutcome::Created));
+        assert_eq!(
+            std::fs::read_to_string(&list).unwrap(),
+            "e3b0c442 # empty\n"
+        );
+        assert_eq!(DaemonConfig::from_file(&config_path).unwrap(), config);
+
+        let second = provision.apply(&config_path).unwrap();
+        assert!(second.iter().all(|(_, o)| *o == ApplyOutcome::Unchanged));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_if_changed_keeps_files_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("guardian-provision-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("daemon.toml");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        write_if_changed(&path, "[signing]\nkey = \"a\"\n").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // A replaced file keeps its mode, and nothing is left beside it
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let outcome = write_if_changed(&path, "[signing]\nkey = \"b\"\n").unwrap();
+        assert_eq!(outcome, ApplyOutcome::Updated);
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let contents = format!("version = {}\n[config]\n", PROVISION_VERSION + 1);
+        assert!(Provision::from_toml(&contents).is_err());
+    }
+}
@@ -0,0 +1,798 @@
+//! Queue between the collectors and the event pipeline. When it is full the
+//! configured policy decides: wait for room, drop the least severe event, or
+//! write the overflow to a file that is read back once the queue drains.
+//! What the policy had to do is counted and reported as self-monitoring
+//! events, so overload never goes unnoticed.
+
+use crate::config::{BackpressurePolicy, QueueConfig};
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::runtime::Handle;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Tag on the queue's own reports
+pub const SELF_MONITORING_TAG: &str = "self_monitoring";
+
+/// The pipeline has stopped
+#[derive(Debug)]
+pub struct Closed;
+
+/// Counts of what the policy did since the daemon started
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueStats {
+    /// Events discarded, by severity
+    pub dropped: BTreeMap<Severity, u64>,
+    /// Events written to the overflow file
+    pub spilled: u64,
+    /// Times a collector had to wait for room
+    pub blocked: u64,
+    /// Events in the overflow file not yet read back
+    pub on_disk: u64,
+}
+
+/// Overflow file: events are appended as JSON lines and read back in order.
+/// How far reading got is recorded next to it, so a restart does not replay
+/// events already delivered, and the file is rewritten without the part
+/// already read once that part is as large as the rest.
+struct Spill {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    /// `<path>.offset`, holding the generation and read offset
+    offset_file: File,
+    /// Bumped by every rewrite and named in the `# <generation>` first line,
+    /// so a recorded offset is never applied to a newer file
+    generation: u64,
+    /// Length of the generation line
+    header: u64,
+    /// Bytes read so far, the generation line included
+    offset: u64,
+    /// Bytes in the file
+    len: u64,
+    /// Events written but not yet read
+    lines: u64,
+    max_bytes: u64,
+}
+
+/// Read part that is rewritten away even while the rest is still unread
+const COMPACT_MIN_BYTES: u64 = 1024 * 1024;
+
+impl Spill {
+    /// Open the overflow file, keeping events a previous run left unread in it
+    fn open(path: &Path, max_bytes: u64) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            create_private_dir(parent)?;
+        }
+        let writer = private_options().create(true).append(true).open(path)?;
+        // Files left by earlier versions may be readable by others
+        set_private(&writer)?;
+        let len = writer.metadata()?.len();
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut first = String::new();
+        reader.read_line(&mut first)?;
+        let (generation, header) = match first
+            .strip_prefix("# ")
+            .and_then(|generation| generation.trim_end().parse().ok())
+        {
+            Some(generation) => (generation, first.len() as u64),
+            None => (0, 0),
+        };
+
+        let offset_path = Self::offset_path(path);
+        let recorded = std::fs::read_to_string(&offset_path).ok().and_then(|text| {
+            let mut fields = text.split_whitespace().map(str::parse::<u64>);
+            Some((fields.next()?.ok()?, fields.next()?.ok()?))
+        });
+        let offset = match recorded {
+            Some((recorded, offset))
+                if recorded == generation && (header..=len).contains(&offset) =>
+            {
+                offset
+            }
+            _ => header,
+        };
+        let offset_file = private_options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&offset_path)?;
+        set_private(&offset_file)?;
+
+        reader.seek(SeekFrom::Start(offset))?;
+        let lines = (&mut reader).split(b'\n').count() as u64;
+        reader.seek(SeekFrom::Start(offset))?;
+        if lines > 0 {
+            info!("Replaying {} events left in {}", lines, path.display());
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            reader,
+            offset_file,
+            generation,
+            header,
+            offset,
+            len,
+            lines,
+            max_bytes,
+        })
+    }
+
+    fn offset_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".offset");
+        path.with_file_name(name)
+    }
+
+    /// Append an event; `false` when the unread events reach the size limit
+    fn push(&mut self, event: &LogEvent) -> std::io::Result<bool> {
+        let mut line = event.to_json().map_err(std::io::Error::other)?;
+        line.push('\n');
+        if self.len - self.offset + line.len() as u64 > self.max_bytes {
+            return Ok(false);
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.len += line.len() as u64;
+        self.lines += 1;
+        Ok(true)
+    }
+
+    /// Read back the oldest unread event, with the number of lines it took
+    /// to find it. `None` when no line is left.
+    fn pop(&mut self) -> (Option<LogEvent>, u64) {
+        let mut taken = 0;
+        while self.lines > 0 {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(n) if n > 0 => {
+                    self.offset += n as u64;
+                    self.lines -= 1;
+                    taken += 1;
+                }
+                read => {
+                    if let Err(e) = read {
+                        warn!(
+                            "Lost {} events: failed to read {}: {}",
+                            self.lines,
+                            self.path.display(),
+                            e
+                        );
+                    }
+                    taken += self.lines;
+                    self.lines = 0;
+                    self.offset = self.len;
+                    line.clear();
+                }
+            }
+            if let Err(e) = self.record() {
+                warn!(
+                    "Failed to record how far {} was read: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+            if line.is_empty() {
+                break;
+            }
+            match LogEvent::from_json_any_version(line.trim_end()) {
+                Ok(event) => return (Some(event), taken),
+                Err(e) => warn!(
+                    "Skipping unreadable event in {}: {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        (None, taken)
+    }
+
+    /// Save the read offset, then rewrite the file without the read part when
+    /// everything was read or the read part is as large as the rest
+    fn record(&mut self) -> std::io::Result<()> {
+        let read = self.offset - self.header;
+        let unread = self.len - self.offset;
+        if read > 0 && (unread == 0 || (read >= COMPACT_MIN_BYTES && read >= unread)) {
+            self.compact()?;
+        }
+        self.offset_file.seek(SeekFrom::Start(0))?;
+        // Fixed width, so each record overwrites the last one entirely
+        write!(
+            self.offset_file,
+            "{:020} {:020}",
+            self.generation, self.offset
+        )
+    }
+
+    /// Move the unread events into a new file, replacing this one
+    fn compact(&mut self) -> std::io::Result<()> {
+        let generation = self.generation + 1;
+        let header = format!("# {}\n", generation);
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp = self.path.with_file_name(temp_name);
+
+        let mut out = private_options()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&temp)?;
+        set_private(&out)?;
+        out.write_all(header.as_bytes())?;
+        let mut rest = File::open(&self.path)?;
+        rest.seek(SeekFrom::Start(self.offset))?;
+        std::io::copy(&mut rest, &mut out)?;
+        out.sync_all()?;
+        drop(out);
+        std::fs::rename(&temp, &self.path)?;
+
+        // A crash from here until the offset is recorded only means the new
+        // file is read from its start, which holds exactly the unread events
+        self.writer = OpenOptions::new().append(true).open(&self.path)?;
+        self.reader = BufReader::new(File::open(&self.path)?);
+        self.header = header.len() as u64;
+        self.reader.seek(SeekFrom::Start(self.header))?;
+        self.len = self.header + self.len - self.offset;
+        self.offset = self.header;
+        self.generation = generation;
+        Ok(())
+    }
+}
+
+/// Create missing directories readable by the owner only. One that exists
+/// is left as it is, since the overflow file may be put in a shared one.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Options to open a file readable by the owner only; spilled events carry
+/// whatever the collectors saw
+fn private_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+}
+
+fn set_private(file: &File) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = file;
+    Ok(())
+}
+
+struct State {
+    queue: VecDeque<LogEvent>,
+    stats: QueueStats,
+    receiver_closed: bool,
+}
+
+struct Shared {
+    capacity: usize,
+    policy: BackpressurePolicy,
+    state: Mutex<State>,
+    /// Only locked on blocking threads, since it does file I/O
+    spill: Option<Mutex<Spill>>,
+    /// Events on disk or on their way there, not yet read back
+    on_disk: AtomicU64,
+    /// Signalled when an event is queued or the last sender goes
+    readable: Notify,
+    /// Signalled when room frees up or the receiver goes
+    writable: Notify,
+    senders: AtomicUsize,
+    runtime: Handle,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_spill(spill: &Mutex<Spill>) -> MutexGuard<'_, Spill> {
+        spill.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether an event can go straight into memory
+    fn has_room(&self, state: &State) -> bool {
+        // Once events are on disk, later ones follow them there to keep the order
+        let spilling = self.on_disk.load(Ordering::SeqCst) > 0;
+        state.queue.len() < self.capacity && !spilling
+    }
+
+    /// Queue `event`, applying the policy if there is no room. Under `block`
+    /// the caller waits for room first. An event returned has its place on
+    /// disk reserved, and the caller writes it with `spill` once the state
+    /// is unlocked.
+    fn offer(&self, state: &mut State, event: LogEvent) -> Option<LogEvent> {
+        if self.has_room(state) {
+            state.queue.push_back(event);
+            return None;
+        }
+
+        match self.policy {
+            BackpressurePolicy::Block => state.queue.push_back(event),
+            BackpressurePolicy::DropLowest => {
+                let lowest = state
+                    .queue
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, queued)| queued.severity);
+                match lowest {
+                    Some((index, queued)) if queued.severity < event.severity => {
+                        let severity = queued.severity;
+                        state.queue.remove(index);
+                        state.queue.push_back(event);
+                        *state.stats.dropped.entry(severity).or_default() += 1;
+                    }
+                    _ => *state.stats.dropped.entry(event.severity).or_default() += 1,
+                }
+            }
+            BackpressurePolicy::Spill if self.spill.is_some() => {
+                self.on_disk.fetch_add(1, Ordering::SeqCst);
+                return Some(event);
+            }
+            BackpressurePolicy::Spill => {
+                *state.stats.dropped.entry(event.severity).or_default() += 1;
+            }
+        }
+        None
+    }
+
+    /// Write an event `offer` reserved room on disk for. The write and its
+    /// accounting finish even if the sender stops waiting for them.
+    async fn spill(self: &Arc<Self>, event: LogEvent) {
+        let shared = self.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            let written = match shared
+                .spill
+                .as_ref()
+                .map(|spill| Self::lock_spill(spill).push(&event))
+            {
+                Some(Ok(written)) => written,
+                Some(Err(e)) => {
+                    warn!("Failed to spill event to disk: {}", e);
+                    false
+                }
+                None => false,
+            };
+            let mut state = shared.lock();
+            if written {
+                state.stats.spilled += 1;
+            } else {
+                shared.on_disk.fetch_sub(1, Ordering::SeqCst);
+                *state.stats.dropped.entry(event.severity).or_default() += 1;
+            }
+            drop(state);
+            shared.readable.notify_one();
+        })
+        .await;
+        if let Err(e) = written {
+            warn!("Failed to spill event to disk: {}", e);
+        }
+    }
+}
+
+/// Collectors' end of the queue
+pub struct EventSender {
+    shared: Arc<Shared>,
+}
+
+/// Pipeline's end of the queue
+pub struct EventReceiver {
+    shared: Arc<Shared>,
+    /// Read of the overflow file still running when `recv` was cancelled
+    unspilling: Option<JoinHandle<(Option<LogEvent>, u64)>>,
+    /// Stats as of the last report
+    reported: QueueStats,
+}
+
+/// Create the queue, opening the overflow file for the `spill` policy. Must
+/// be called on the runtime, which `blocking_send` uses.
+pub fn channel(config: &QueueConfig) -> (EventSender, EventReceiver) {
+    let spill = match config.policy {
+        BackpressurePolicy::Spill => {
+            match Spill::open(&config.spill_path, config.spill_max_bytes) {
+                Ok(spill) => Some(spill),
+                Err(e) => {
+                    warn!(
+                        "Cannot spill to {}, dropping overflow instead: {}",
+                        config.spill_path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let shared = Arc::new(Shared {
+        capacity: config.capacity.max(1),
+        policy: config.policy,
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(config.capacity),
+            stats: QueueStats::default(),
+            receiver_closed: false,
+        }),
+        on_disk: AtomicU64::new(spill.as_ref().map_or(0, |spill| spill.lines)),
+        spill: spill.map(Mutex::new),
+        readable: Notify::new(),
+        writable: Notify::new(),
+        senders: AtomicUsize::new(1),
+        runtime: Handle::current(),
+    });
+    (
+        EventSender {
+            shared: shared.clone(),
+        },
+        EventReceiver {
+            shared,
+            unspilling: None,
+            reported: QueueStats::default(),
+        },
+    )
+}
+
+impl EventSender {
+    /// Queue an event, applying the policy when the queue is full
+    pub async fn send(&self, mut event: LogEvent) -> Result<(), Closed> {
+        let mut waited = false;
+        loop {
+            let room = self.shared.writable.notified();
+            tokio::pin!(room);
+            // Register before looking, so room freed meanwhile still wakes us
+            room.as_mut().enable();
+            let offered = {
+                let mut state = self.shared.lock();
+                if state.receiver_closed {
+                    return Err(Closed);
+                }
+                if self.shared.policy != BackpressurePolicy::Block || self.shared.has_room(&state) {
+                    Ok(self.shared.offer(&mut state, event))
+                } else {
+                    if !waited {
+                        state.stats.blocked += 1;
+                        waited = true;
+                    }
+                    Err(event)
+                }
+            };
+            match offered {
+                Ok(spilled) => {
+                    if let Some(event) = spilled {
+                        self.shared.spill(event).await;
+                    }
+                    self.shared.readable.notify_one();
+                    return Ok(());
+                }
+                Err(waiting) => event = waiting,
+            }
+            room.await;
+        }
+    }
+
+    /// `send` for threads outside the runtime
+    pub fn blocking_send(&self, event: LogEvent) -> Result<(), Closed> {
+        self.shared.runtime.block_on(self.send(event))
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.readable.notify_one();
+        }
+    }
+}
+
+impl EventReceiver {
+    /// Next event, oldest first; `None` once every sender is gone and the
+    /// queue is empty
+    pub async fn recv(&mut self) -> Option<LogEvent> {
+        let shared = self.shared.clone();
+        loop {
+            let queued = shared.readable.notified();
+            tokio::pin!(queued);
+            queued.as_mut().enable();
+            // Memory holds the older events while the file holds newer ones
+            let event = match self.unspilling {
+                Some(_) => None,
+                None => shared.lock().queue.pop_front(),
+            };
+            let event = match event {
+                Some(event) => Some(event),
+                None if self.unspilling.is_some() || shared.on_disk.load(Ordering::SeqCst) > 0 => {
+                    self.unspill().await
+                }
+                None => None,
+            };
+            if event.is_some() {
+                shared.writable.notify_waiters();
+                return event;
+            }
+            {
+                let state = shared.lock();
+                // Unreadable lines may have emptied the file meanwhile
+                if !state.queue.is_empty() {
+                    continue;
+                }
+                if shared.on_disk.load(Ordering::SeqCst) == 0
+                    && shared.senders.load(Ordering::SeqCst) == 0
+                {
+                    return None;
+                }
+            }
+            queued.await;
+        }
+    }
+
+    /// Oldest event on disk; `None` while those counted are still being
+    /// written. Cancel-safe: a read cut short is picked up by the next call.
+    async fn unspill(&mut self) -> Option<LogEvent> {
+        let shared = self.shared.clone();
+        let reading = self.unspilling.get_or_insert_with(|| {
+            tokio::task::spawn_blocking(move || {
+                shared
+                    .spill
+                    .as_ref()
+                    .map_or((None, 0), |spill| Shared::lock_spill(spill).pop())
+            })
+        });
+        let popped = reading.await;
+        self.unspilling = None;
+        match popped {
+            Ok((event, taken)) => {
+                self.shared.on_disk.fetch_sub(taken, Ordering::SeqCst);
+                event
+            }
+            Err(e) => {
+                warn!("Failed to read spilled events: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Counts since the daemon started
+    pub fn stats(&self) -> QueueStats {
+        let mut stats = self.shared.lock().stats.clone();
+        stats.on_disk = self.shared.on_disk.load(Ordering::SeqCst);
+        stats
+    }
+
+    /// Self-monitoring event describing drops, spills and waits since the
+    /// last report; `None` when there were none and nothing is on disk
+    pub fn report(&mut self, hostname: &str) -> Option<LogEvent> {
+        let stats = self.stats();
+        let dropped: BTreeMap<Severity, u64> = stats
+            .dropped
+            .iter()
+            .map(|(severity, count)| {
+                let before = self.reported.dropped.get(severity).copied().unwrap_or(0);
+                (*severity, count - before)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let spilled = stats.spilled - self.reported.spilled;
+        let blocked = stats.blocked - self.reported.blocked;
+        if dropped.is_empty() && spilled == 0 && blocked == 0 && stats.on_disk == 0 {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        if !dropped.is_empty() {
+            let by_severity: Vec<String> = dropped
+                .iter()
+                .map(|(severity, count)| format!("{:?} {}", severity, count))
+                .collect();
+            parts.push(format!(
+                "dropped {} ({})",
+                dropped.values().sum::<u64>(),
+                by_severity.join(", ")
+            ));
+        }
+        if spilled > 0 {
+            parts.push(format!("spilled {} to disk", spilled));
+        }
+        if blocked > 0 {
+            parts.push(format!("collectors waited {} times", blocked));
+        }
+        if stats.on_disk > 0 {
+            parts.push(format!("{} waiting on disk", stats.on_disk));
+        }
+        let severity = if dropped.is_empty() {
+            Severity::Low
+        } else {
+            Severity::Medium
+        };
+        self.reported = stats;
+
+        let event = LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "guardian-daemon".to_string(),
+                level: "warning".to_string(),
+                message: format!("Event queue full: {}", parts.join(", ")),
+            },
+            hostname.to_string(),
+        );
+        Some(event.with_tag(SELF_MONITORING_TAG))
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_closed = true;
+        self.shared.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(severity: Severity, message: &str) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: message.to_string(),
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    fn message(event: &LogEvent) -> &str {
+        match &event.event_type {
+            EventType::SystemLog { message, .. } => message,
+            _ => "",
+        }
+    }
+
+    fn config(policy: BackpressurePolicy, spill_path: PathBuf) -> QueueConfig {
+        QueueConfig {
+            capacity: 2,
+            policy,
+            spill_path,
+            ..QueueConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_lowest_and_block() {
+        let (tx, mut rx) = channel(&config(BackpressurePolicy::DropLowest, PathBuf::new()));
+        tx.send(event(Severity::Info, "a")).await.unwrap();
+        tx.send(event(Severity::High, "b")).await.unwrap();
+        // Evicts the queued Info event
+        tx.send(event(Severity::Medium, "c")).await.unwrap();
+        // Lower than anything queued, so dropped itself
+        tx.send(event(Severity::Low, "d")).await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            received.push(message(&rx.recv().await.unwrap()).to_string());
+        }
+        assert_eq!(received, ["b", "c"]);
+        let report = rx.report("web-1").unwrap();
+        assert!(message(&report).contains("dropped 2 (Info 1, Low 1)"));
+        assert!(report.tags.contains(&SELF_MONITORING_TAG.to_string()));
+        assert!(rx.report("web-1").is_none());
+
+        // Blocking: the third send waits until the receiver takes one
+        let (tx, mut rx) = channel(&config(BackpressurePolicy::Block, PathBuf::new()));
+        tx.send(event(Severity::Info, "a")).await.unwrap();
+        tx.send(event(Severity::Info, "b")).await.unwrap();
+        let sender = tokio::spawn(async move { tx.send(event(Severity::Info, "c")).await });
+        tokio::task::yield_now().await;
+        assert!(!sender.is_finished());
+        assert_eq!(message(&rx.recv().await.unwrap()), "a");
+        sender.await.unwrap().unwrap();
+        assert_eq!(message(&rx.recv().await.unwrap()), "b");
+        assert_eq!(message(&rx.recv().await.unwrap()), "c");
+        // Every sender is gone
+        assert!(rx.recv().await.is_none());
+        assert_eq!(rx.stats().blocked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_spill_keeps_order() {
+        let dir = std::env::temp_dir().join(format!("guardian-queue-{}", std::process::id()));
+        let path = dir.join("queue.ndjson");
+        let _ = std::fs::remove_file(&path);
+
+        let (tx, mut rx) = channel(&config(BackpressurePolicy::Spill, path.clone()));
+        for name in ["a", "b", "c", "d"] {
+            tx.send(event(Severity::Info, name)).await.unwrap();
+        }
+        assert_eq!(rx.stats().on_disk, 2);
+        assert!(message(&rx.report("web-1").unwrap()).contains("spilled 2 to disk"));
+
+        // Memory drains first, then the file, and later sends queue behind it
+        assert_eq!(message(&rx.recv().await.unwrap()), "a");
+        tx.send(event(Severity::Info, "e")).await.unwrap();
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(message(&rx.recv().await.unwrap()).to_string());
+        }
+        assert_eq!(received, ["b", "c", "d", "e"]);
+        // Once everything is read the file is rewritten down to its header
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# 1\n");
+
+        // Events a previous run left on disk come back first
+        drop((tx, rx));
+        let mut spill = Spill::open(&path, u64::MAX).unwrap();
+        spill.push(&event(Severity::Info, "left over")).unwrap();
+        let (tx, mut rx) = channel(&config(BackpressurePolicy::Spill, path.clone()));
+        tx.send(event(Severity::Info, "new")).await.unwrap();
+        assert_eq!(message(&rx.recv().await.unwrap()), "left over");
+        assert_eq!(message(&rx.recv().await.unwrap()), "new");
+
+        // A restart resumes after the last event read, and the size limit
+        // counts only unread events
+        drop((tx, rx));
+        let line = event(Severity::Info, "x").to_json().unwrap().len() as u64 + 1;
+        let mut spill = Spill::open(&path, 2 * line + 16).unwrap();
+        assert!(spill.push(&event(Severity::Info, "x")).unwrap());
+        assert!(spill.push(&event(Severity::Info, "y")).unwrap());
+        assert!(!spill.push(&event(Severity::Info, "z")).unwrap());
+        assert_eq!(message(&spill.pop().0.unwrap()), "x");
+        assert!(spill.push(&event(Severity::Info, "z")).unwrap());
+        drop(spill);
+        let mut spill = Spill::open(&path, u64::MAX).unwrap();
+        assert_eq!(spill.lines, 2);
+        assert_eq!(message(&spill.pop().0.unwrap()), "y");
+        assert_eq!(message(&spill.pop().0.unwrap()), "z");
+        assert!(spill.pop().0.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spill_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let root = std::env::temp_dir().join(format!("guardian-queue-{}", uuid::Uuid::new_v4()));
+        let dir = root.join("state");
+        let path = dir.join("queue.ndjson");
+        let mut spill = Spill::open(&path, u64::MAX).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&Spill::offset_path(&path)), 0o600);
+
+        // Reading everything back rewrites the file, which stays private
+        spill.push(&event(Severity::Info, "a")).unwrap();
+        assert_eq!(message(&spill.pop().0.unwrap()), "a");
+        assert_eq!(spill.generation, 1);
+        assert_eq!(mode(&path), 0o600);
+
+        // A file an earlier version left readable is tightened
+        drop(spill);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        Spill::open(&path, u64::MAX).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
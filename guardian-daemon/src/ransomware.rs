@@ -0,0 +1,489 @@
+//! Ransomware heuristic over the file monitor's events. Encrypting files in
+//! bulk shows up as a burst of files renamed or rewritten with a telltale
+//! extension (`.locked`, `.enc`, or random characters appended to the real
+//! one) while the originals are deleted or renamed away. The file monitor
+//! cannot see which process wrote a file, so writes are grouped by the
+//! user owning them and the process is picked afterwards: the one running
+//! as that user that is writing the most, and only killed once it is seen
+//! holding one of the files.
+
+use crate::config::{RansomwareAction, RansomwareConfig};
+use crate::queue::EventSender;
+use crate::response::HostIsolation;
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, EventUser, FileOperation, LogEvent, Severity};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use sysinfo::System;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Tag on ransomware alerts
+pub const RANSOMWARE_TAG: &str = "ransomware";
+
+/// Rule name on ransomware alerts
+pub const RANSOMWARE_RULE: &str = "ransomware_suspected";
+
+/// Files named in an alert's description
+const EXAMPLE_FILES: usize = 5;
+
+/// Time between the process snapshots used to measure writes
+const SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// A file event counted towards a burst
+struct Seen {
+    time: DateTime<Utc>,
+    id: Uuid,
+    path: String,
+    user: Option<EventUser>,
+}
+
+/// Activity that looks like files being encrypted in bulk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burst {
+    /// Owner of the suspicious files, where ownership applies
+    pub user: Option<EventUser>,
+    /// Files given suspicious names
+    pub written: Vec<String>,
+    /// Files deleted or renamed away
+    pub removed: usize,
+    pub event_ids: Vec<Uuid>,
+}
+
+/// Process suspected of the writes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suspect {
+    pub pid: u32,
+    pub name: String,
+    pub written_bytes: u64,
+    pub killed: bool,
+}
+
+/// Sliding window over file events
+pub struct RansomwareDetector {
+    config: RansomwareConfig,
+    /// Suspicious names written, by owner uid
+    written: HashMap<Option<u32>, VecDeque<Seen>>,
+    removed: VecDeque<Seen>,
+}
+
+impl RansomwareDetector {
+    pub fn new(config: RansomwareConfig) -> Self {
+        Self {
+            config,
+            written: HashMap::new(),
+            removed: VecDeque::new(),
+        }
+    }
+
+    /// Count a file event, returning the burst once one user's writes and
+    /// the removals both reach `min_files` within the window
+    pub fn observe(&mut self, event: &LogEvent) -> Option<Burst> {
+        if !self.config.enabled {
+            return None;
+        }
+        let EventType::FileIntegrity {
+            path,
+            operation,
+            user,
+            ..
+        } = &event.event_type
+        else {
+            return None;
+        };
+        self.expire(event.timestamp);
+
+        let seen = || Seen {
+            time: event.timestamp,
+            id: event.id,
+            path: path.clone(),
+            user: user.clone(),
+        };
+        // A rename always takes the original name away
+        if matches!(operation, FileOperation::Delete | FileOperation::Rename) {
+            self.removed.push_back(seen());
+        }
+        if matches!(
+            operation,
+            FileOperation::Create | FileOperation::Modify | FileOperation::Rename
+        ) && self.suspicious(path)
+        {
+            let writes = self
+                .written
+                .entry(user.as_ref().map(|u| u.uid))
+                .or_default();
+            // Rewrites of the same file count once
+            if !writes.iter().any(|w| w.path == *path) {
+                writes.push_back(seen());
+            }
+        }
+
+        // Deleted files have no owner left, so removals are not grouped
+        let min_files = self.config.min_files.max(1);
+        if self.removed.len() < min_files {
+            return None;
+        }
+        let owner = *self
+            .written
+            .iter()
+            .find(|(_, writes)| writes.len() >= min_files)?
+            .0;
+
+        // Start over, so one burst raises one alert
+        let written = self.written.remove(&owner)?;
+        let removed = std::mem::take(&mut self.removed);
+        Some(Burst {
+            user: written.front().and_then(|w| w.user.clone()),
+            event_ids: written.iter().chain(&removed).map(|seen| seen.id).collect(),
+            written: written.into_iter().map(|w| w.path).collect(),
+            removed: removed.len(),
+        })
+    }
+
+    /// Forget events older than the window
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::seconds(self.config.window_secs as i64);
+        self.removed.retain(|seen| seen.time > cutoff);
+        self.written.retain(|_, writes| {
+            writes.retain(|seen| seen.time > cutoff);
+            !writes.is_empty()
+        });
+    }
+
+    /// Whether `path` has a name encrypted files are given
+    fn suspicious(&self, path: &str) -> bool {
+        let Some(name) = Path::new(path).file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+        let mut parts = name.rsplit('.');
+        let (Some(ext), Some(rest)) = (parts.next(), parts.next()) else {
+            return false;
+        };
+        // Dotfiles have no extension
+        if rest.is_empty() && parts.next().is_none() {
+            return false;
+        }
+        if self
+            .config
+            .extensions
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(ext))
+        {
+            return true;
+        }
+        // Appended to the real extension, e.g. `report.docx.x7k2q9`
+        self.config.random_extensions
+            && !rest.is_empty()
+            && parts.next().is_some_and(|stem| !stem.is_empty())
+            && random_extension(ext)
+    }
+}
+
+/// Whether `ext` looks generated rather than like a file type
+fn random_extension(ext: &str) -> bool {
+    let digits = ext.chars().filter(|c| c.is_ascii_digit()).count();
+    let letters = ext.chars().filter(|c| c.is_ascii_alphabetic()).count();
+    (5..=16).contains(&ext.len()) && digits + letters == ext.len() && digits >= 2 && letters >= 2
+}
+
+/// Raises the alert for a burst and carries out the configured action
+pub struct RansomwareResponse {
+    config: RansomwareConfig,
+    tx: EventSender,
+    hostname: String,
+    isolation: Arc<HostIsolation>,
+}
+
+impl RansomwareResponse {
+    pub fn new(
+        config: RansomwareConfig,
+        tx: EventSender,
+        hostname: String,
+        isolation: Arc<HostIsolation>,
+    ) -> anyhow::Result<Arc<Self>> {
+        // Isolating with nothing allowed would also cut off the console
+        // needed to lift it
+        if config.action == RansomwareAction::Isolate && config.isolate_allow.is_empty() {
+            anyhow::bail!("ransomware.action = \"isolate\" needs ransomware.isolate_allow");
+        }
+        Ok(Arc::new(Self {
+            config,
+            tx,
+            hostname,
+            isolation,
+        }))
+    }
+
+    /// Look for the writing process and respond in the background
+    pub fn respond(self: &Arc<Self>, burst: Burst) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let uid = burst.user.as_ref().map(|user| user.uid);
+            let kill = this.config.action == RansomwareAction::Kill;
+            let written = burst.written.clone();
+            let suspect = tokio::task::spawn_blocking(move || find_suspect(uid, &written, kill))
+                .await
+                .unwrap_or_default();
+
+            let alert = alert(&burst, suspect.as_ref(), &this.hostname);
+            if this.tx.send(alert).await.is_err() {
+                warn!("Failed to emit ransomware alert - channel closed");
+            }
+
+            if this.config.action == RansomwareAction::Isolate {
+                let duration = std::time::Duration::from_secs(this.config.isolate_secs);
+                if let Err(e) = this
+                    .isolation
+                    .isolate(&this.config.isolate_allow, duration, "ransomware detector")
+                    .await
+                {
+                    error!("Host isolation after ransomware alert failed: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Process running as `uid` that wrote the most over a short sample,
+/// preferring one seen holding a file of the burst open. It is only
+/// killed, when `kill` is set, if it was seen holding one; a busy writer
+/// that merely runs as the same user is named in the alert and left be.
+/// Without an owner nothing can be attributed.
+fn find_suspect(uid: Option<u32>, written: &[String], kill: bool) -> Option<Suspect> {
+    let uid = uid?;
+    let mut sys = System::new();
+    sys.refresh_processes();
+    std::thread::sleep(SAMPLE_INTERVAL);
+    sys.refresh_processes();
+
+    let own_pid = std::process::id();
+    let (pid, process, holding) = sys
+        .processes()
+        .iter()
+        .filter(|(pid, _)| pid.as_u32() != own_pid)
+        .filter(|(_, process)| owned_by(process, uid))
+        .filter(|(_, process)| process.disk_usage().written_bytes > 0)
+        .map(|(pid, process)| (pid, process, holds_any(pid.as_u32(), written)))
+        .max_by_key(|(_, process, holding)| (*holding, process.disk_usage().written_bytes))?;
+
+    let killed = kill && holding && process.kill();
+    if killed {
+        info!(
+            "Killed {} ({}) suspected of encrypting files",
+            process.name(),
+            pid
+        );
+    } else if kill && holding {
+        warn!("Failed to kill {} ({})", process.name(), pid);
+    } else if kill {
+        warn!(
+            "Not killing {} ({}): it was not seen holding any of the renamed files",
+            process.name(),
+            pid
+        );
+    }
+    Some(Suspect {
+        pid: pid.as_u32(),
+        name: process.name().to_string(),
+        written_bytes: process.disk_usage().written_bytes,
+        killed,
+    })
+}
+
+fn owned_by(process: &sysinfo::Process, uid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        process.user_id().is_some_and(|owner| **owner == uid)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (process, uid);
+        false
+    }
+}
+
+/// Whether `pid` has any of `paths` open
+#[cfg(target_os = "linux")]
+fn holds_any(pid: u32, paths: &[String]) -> bool {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        std::fs::read_link(entry.path())
+            .is_ok_and(|target| paths.iter().any(|path| target == Path::new(path)))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holds_any(_pid: u32, _paths: &[String]) -> bool {
+    false
+}
+
+/// Critical alert describing `burst`
+pub fn alert(burst: &Burst, suspect: Option<&Suspect>, hostname: &str) -> LogEvent {
+    let user = match &burst.user {
+        Some(EventUser {
+            username: Some(name),
+            ..
+        }) => format!(" owned by {}", name),
+        Some(user) => format!(" owned by uid {}", user.uid),
+        None => String::new(),
+    };
+    let examples = burst
+        .written
+        .iter()
+        .take(EXAMPLE_FILES)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut description = format!(
+        "{} files{} given ransomware-style names and {} files deleted or renamed away, e.g. {}",
+        burst.written.len(),
+        user,
+        burst.removed,
+        examples
+    );
+    match suspect {
+        Some(suspect) => description.push_str(&format!(
+            ". Most active writer: {} (pid {}, {} bytes in {}ms){}",
+            suspect.name,
+            suspect.pid,
+            suspect.written_bytes,
+            SAMPLE_INTERVAL.as_millis(),
+            if suspect.killed { ", killed" } else { "" }
+        )),
+        None => description.push_str(". No writing process found"),
+    }
+
+    LogEvent::new(
+        Severity::Critical,
+        EventType::Alert {
+            title: "Ransomware suspected".to_string(),
+            description,
+            related_event_ids: burst.event_ids.clone(),
+            technique: Some("T1486".to_string()),
+        },
+        hostname.to_string(),
+    )
+    .with_tag(RANSOMWARE_TAG)
+    .with_rule(RANSOMWARE_RULE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_event(path: &str, operation: FileOperation, seconds: i64) -> LogEvent {
+        // A deleted file's owner can no longer be read
+        let user = (operation != FileOperation::Delete).then(|| EventUser {
+            uid: 1000,
+            username: Some("bob".to_string()),
+        });
+        let mut event = LogEvent::new(
+            Severity::Low,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation,
+                hash: None,
+                user,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        );
+        event.timestamp = DateTime::UNIX_EPOCH + Duration::seconds(seconds);
+        event
+    }
+
+    #[test]
+    fn test_burst_of_encrypted_files() {
+        let mut detector = RansomwareDetector::new(RansomwareConfig {
+            min_files: 3,
+            ..RansomwareConfig::default()
+        });
+
+        // Ordinary edits and a slow trickle never alert
+        assert!(detector
+            .observe(&file_event("/home/bob/notes.txt", FileOperation::Modify, 0))
+            .is_none());
+        for n in 0..3 {
+            let path = format!("/home/bob/old{}.locked", n);
+            assert!(detector
+                .observe(&file_event(&path, FileOperation::Create, n * 120))
+                .is_none());
+        }
+
+        // Encrypted copies written and the originals deleted
+        let mut burst = None;
+        for n in 0..3 {
+            let original = format!("/home/bob/doc{}.docx", n);
+            let encrypted = format!("{}.x7k2q9", original);
+            let seconds = 1000 + n;
+            assert!(detector
+                .observe(&file_event(&encrypted, FileOperation::Create, seconds))
+                .is_none());
+            burst = detector.observe(&file_event(&original, FileOperation::Delete, seconds));
+        }
+        let burst = burst.unwrap();
+        assert_eq!(burst.written.len(), 3);
+        assert_eq!(burst.removed, 3);
+        assert_eq!(burst.event_ids.len(), 6);
+        assert_eq!(burst.user.as_ref().map(|user| user.uid), Some(1000));
+
+        let alert = alert(&burst, None, "web-1");
+        match &alert.event_type {
+            EventType::Alert { description, .. } => {
+                assert!(description.starts_with("3 files owned by bob"))
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(alert.severity, Severity::Critical);
+        assert_eq!(alert.rule_name.as_deref(), Some(RANSOMWARE_RULE));
+        assert!(alert.tags.contains(&RANSOMWARE_TAG.to_string()));
+
+        // The window starts over after an alert
+        assert!(detector
+            .observe(&file_event("/home/bob/x.enc", FileOperation::Rename, 1003))
+            .is_none());
+    }
+
+    #[test]
+    fn test_suspect_needs_an_owner() {
+        assert!(find_suspect(None, &[], true).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_holds_any() {
+        let path = std::env::temp_dir().join(format!("guardian-held-{}", Uuid::new_v4()));
+        let file = std::fs::File::create(&path).unwrap();
+        let held = vec![path.to_string_lossy().to_string()];
+        assert!(holds_any(std::process::id(), &held));
+        assert!(!holds_any(
+            std::process::id(),
+            &["/nonexistent".to_string()]
+        ));
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_suspicious_names() {
+        let detector = RansomwareDetector::new(RansomwareConfig::default());
+        for path in ["/srv/a.pdf.locked", "/srv/a.ENC", "/srv/report.docx.a8f3k2"] {
+            assert!(detector.suspicious(path), "{}", path);
+        }
+        for path in [
+            "/srv/song.mp3",
+            "/srv/video.h264",
+            "/srv/x7k2q9",
+            "/srv/report.x7k2q9",
+            "/srv/backup.tar.gz",
+            "/srv/.locked",
+        ] {
+            assert!(!detector.suspicious(path), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::config::RdnsConfig;
+use crate::threatintel::parse_remote_ip;
+use guardian_common::{EventType, LogEvent};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    host: Option<String>,
+    expires: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<IpAddr, CacheEntry>,
+    /// Addresses with a lookup in flight, so a burst for one address triggers one lookup
+    pending: HashSet<IpAddr>,
+}
+
+impl Cache {
+    fn get(&self, ip: IpAddr, now: Instant) -> Option<Option<String>> {
+        self.entries
+            .get(&ip)
+            .filter(|e| e.expires > now)
+            .map(|e| e.host.clone())
+    }
+
+    fn insert(&mut self, ip: IpAddr, entry: CacheEntry, max_entries: usize, now: Instant) {
+        if self.entries.len() >= max_entries {
+            self.entries.retain(|_, e| e.expires > now);
+        }
+        if self.entries.len() >= max_entries {
+            // Still full of live entries: drop the one closest to expiry
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.expires)
+                .map(|(ip, _)| *ip)
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(ip, entry);
+    }
+}
+
+/// Cached, rate-bounded reverse-DNS enrichment for network events
+#[derive(Clone)]
+pub struct ReverseDns {
+    config: RdnsConfig,
+    cache: Arc<Mutex<Cache>>,
+    permits: Arc<Semaphore>,
+}
+
+impl ReverseDns {
+    pub fn new(config: RdnsConfig) -> Self {
+        let permits = Arc::new(Semaphore::new(config.max_concurrent.max(1)));
+        Self {
+            config,
+            cache: Arc::new(Mutex::new(Cache::default())),
+            permits,
+        }
+    }
+
+    /// Fill in `remote_host` for network events with a resolvable remote address
+    pub async fn enrich(&self, event: &mut LogEvent) {
+        if !self.config.enabled {
+            return;
+        }
+        let EventType::NetworkSocket {
+            remote_addr: Some(addr),
+            remote_host,
+            ..
+        } = &mut event.event_type
+        else {
+            return;
+        };
+        if remote_host.is_some() {
+            return;
+        }
+        let Some(ip) = parse_remote_ip(addr) else {
+            return;
+        };
+        if ip.is_loopback() || ip.is_unspecified() {
+            return;
+        }
+
+        *remote_host = self.resolve(ip).await;
+    }
+
+    /// Cached name for `ip`, waiting briefly for a fresh lookup on a miss
+    async fn resolve(&self, ip: IpAddr) -> Option<String> {
+        let now = Instant::now();
+        {
+            let mut cache = self.lock();
+            if let Some(host) = cache.get(ip, now) {
+                return host;
+            }
+            if !cache.pending.insert(ip) {
+                return None;
+            }
+        }
+
+        // Under a storm of new addresses, leave the rest unresolved instead of queueing
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            self.lock().pending.remove(&ip);
+            return None;
+        };
+
+        let this = self.clone();
+        let lookup = tokio::spawn(async move {
+            let host = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip))
+                .await
+                .ok()
+                .and_then(|r| r.ok())
+                // getnameinfo falls back to the numeric form when there is no PTR record
+                .filter(|host| host.parse::<IpAddr>().is_err());
+            drop(permit);
+            this.store(ip, host.clone());
+            host
+        });
+
+        // A lookup that outlives the timeout still lands in the cache for later events
+        match tokio::time::timeout(Duration::from_millis(self.config.timeout_ms), lookup).await {
+            Ok(Ok(host)) => host,
+            _ => {
+                debug!("Reverse DNS for {} still pending", ip);
+                None
+            }
+        }
+    }
+
+    fn store(&self, ip: IpAddr, host: Option<String>) {
+        let ttl = if host.is_some() {
+            self.config.ttl_secs
+        } else {
+            self.config.negative_ttl_secs
+        };
+        let now = Instant::now();
+        let mut cache = self.lock();
+        cache.pending.remove(&ip);
+        cache.insert(
+            ip,
+            CacheEntry {
+                host,
+                expires: now + Duration::from_secs(ttl),
+            },
+            self.config.max_entries,
+            now,
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::Severity;
+
+    fn network_event(remote: &str) -> LogEvent {
+        LogEvent::new(
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: "10.0.0.2:50000".to_string(),
+                remote_addr: Some(remote.to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "localhost".to_string(),
+        )
+    }
+
+    fn remote_host(event: &LogEvent) -> Option<&str> {
+        match &event.event_type {
+            EventType::NetworkSocket { remote_host, .. } => remote_host.as_deref(),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enrich_uses_cache() {
+        let rdns = ReverseDns::new(RdnsConfig::default());
+        rdns.store(
+            "203.0.113.9".parse().unwrap(),
+            Some("ec2-203-0-113-9.compute.amazonaws.com".to_string()),
+        );
+
+        let mut event = network_event("203.0.113.9:443");
+        rdns.enrich(&mut event).await;
+        assert_eq!(
+            remote_host(&event),
+            Some("ec2-203-0-113-9.compute.amazonaws.com")
+        );
+
+        // Loopback is never looked up
+        let mut event = network_event("127.0.0.1:8080");
+        rdns.enrich(&mut event).await;
+        assert_eq!(remote_host(&event), None);
+    }
+
+    #[test]
+    fn test_cache_expiry_and_bound() {
+        let now = Instant::now();
+        let mut cache = Cache::default();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let entry = |host: &str, secs: u64| CacheEntry {
+            host: Some(host.to_string()),
+            expires: now + Duration::from_secs(secs),
+        };
+
+        cache.insert(ip("192.0.2.1"), entry("a", 10), 2, now);
+        cache.insert(ip("192.0.2.2"), entry("b", 20), 2, now);
+        cache.insert(ip("192.0.2.3"), entry("c", 30), 2, now);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(ip("192.0.2.1"), now).is_none());
+        assert_eq!(cache.get(ip("192.0.2.3"), now), Some(Some("c".to_string())));
+        assert!(cache
+            .get(ip("192.0.2.2"), now + Duration::from_secs(21))
+            .is_none());
+    }
+}
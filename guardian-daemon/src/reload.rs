@@ -0,0 +1,330 @@
+//! Applies an edited config file to the running agent on SIGHUP or the
+//! `reload_config` control command. Watch paths, file inspection settings,
+//! rules and output sinks are replaced in place; other sections only take
+//! effect after a restart, and the reload event says which.
+
+use crate::config::DaemonConfig;
+use crate::file_monitor::{FileMonitor, MonitorSettings};
+use crate::rules::RuleEngine;
+use crate::sinks::Sinks;
+use anyhow::{Context, Result};
+use guardian_common::{AgentHello, EventType, LogEvent, Severity};
+use serde_json::Value;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Tag on events reporting a reload
+pub const CONFIG_RELOAD_TAG: &str = "config_reload";
+
+/// Sections of the config file that a reload applies
+const RELOADABLE: &[&str] = &[
+    "watch",
+    "archives",
+    "entropy",
+    "diff",
+    "rules",
+    "forward",
+    "siem",
+    "elasticsearch",
+    "splunk",
+    "loki",
+    "gelf",
+];
+
+/// Sections the file monitor reads
+const MONITOR: &[&str] = &["watch", "archives", "entropy", "diff"];
+
+/// What changed between the running config and the reloaded one
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDelta {
+    pub watch_added: Vec<PathBuf>,
+    pub watch_removed: Vec<PathBuf>,
+    /// Paths still watched, with other settings
+    pub watch_changed: Vec<PathBuf>,
+    /// Sections applied by the reload, by name
+    pub applied: Vec<String>,
+    /// Sections that changed but keep their old settings until a restart,
+    /// by name
+    pub restart: Vec<String>,
+}
+
+impl ConfigDelta {
+    pub fn between(old: &DaemonConfig, new: &DaemonConfig) -> Result<Self> {
+        let mut delta = Self::default();
+        for watch in &new.watch {
+            match old.watch.iter().find(|old| old.path == watch.path) {
+                None => delta.watch_added.push(watch.path.clone()),
+                Some(old) if old != watch => delta.watch_changed.push(watch.path.clone()),
+                Some(_) => {}
+            }
+        }
+        for watch in &old.watch {
+            if !new.watch.iter().any(|new| new.path == watch.path) {
+                delta.watch_removed.push(watch.path.clone());
+            }
+        }
+
+        // Compare section by section through the serialized form, so new
+        // sections are covered without listing them here
+        let old = serde_json::to_value(old)?;
+        let new = serde_json::to_value(new)?;
+        if let (Some(old), Some(new)) = (old.as_object(), new.as_object()) {
+            for (section, value) in new {
+                if old.get(section) == Some(value) {
+                    continue;
+                }
+                if RELOADABLE.contains(&section.as_str()) {
+                    delta.applied.push(section.clone());
+                } else {
+                    delta.restart.push(section.clone());
+                }
+            }
+        }
+        delta.applied.sort();
+        delta.restart.sort();
+        Ok(delta)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart.is_empty()
+    }
+
+    /// Whether the file monitor needs the new settings
+    pub fn monitor_changed(&self) -> bool {
+        self.applied.iter().any(|s| MONITOR.contains(&s.as_str()))
+    }
+
+    pub fn rules_changed(&self) -> bool {
+        self.applied.iter().any(|s| s == "rules")
+    }
+
+    /// Summary such as `watch +/srv/www; changed splunk`
+    pub fn describe(&self) -> String {
+        let mut changes = Vec::new();
+        let paths = |sign: &str, paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|path| format!("{}{}", sign, path.display()))
+                .collect::<Vec<_>>()
+        };
+        let watch: Vec<String> = [
+            paths("+", &self.watch_added),
+            paths("-", &self.watch_removed),
+            paths("~", &self.watch_changed),
+        ]
+        .concat();
+        if !watch.is_empty() {
+            changes.push(format!("watch {}", watch.join(" ")));
+        }
+        let other: Vec<&str> = self
+            .applied
+            .iter()
+            .map(String::as_str)
+            .filter(|s| *s != "watch")
+            .collect();
+        if !other.is_empty() {
+            changes.push(format!("changed {}", other.join(", ")));
+        }
+        if !self.restart.is_empty() {
+            changes.push(format!("restart needed for {}", self.restart.join(", ")));
+        }
+        if changes.is_empty() {
+            changes.push("no changes".to_string());
+        }
+        changes.join("; ")
+    }
+
+    /// Event describing the applied delta
+    pub fn event(&self, requested_by: &str, hostname: &str) -> LogEvent {
+        reload_event(
+            Severity::Info,
+            "info",
+            format!(
+                "Config reloaded (requested by {}): {}",
+                requested_by,
+                self.describe()
+            ),
+            hostname,
+        )
+    }
+}
+
+/// Parts of the running agent a reload replaces
+pub struct Running<'a> {
+    /// Settings in effect, updated to those applied
+    pub config: &'a mut DaemonConfig,
+    pub sinks: &'a mut Sinks,
+    pub rules: &'a mut RuleEngine,
+    /// Absent in demo mode
+    pub monitor: Option<&'a FileMonitor>,
+    /// Sent by new forwarders when they connect
+    pub hello: &'a AgentHello,
+}
+
+/// Load the config file again and apply what changed; the event describes
+/// the delta, or why nothing was applied
+pub async fn apply(running: Running<'_>, requested_by: &str, hostname: &str) -> LogEvent {
+    match try_apply(running).await {
+        Ok(delta) if delta.is_empty() => {
+            info!("Config reloaded: no changes");
+            delta.event(requested_by, hostname)
+        }
+        Ok(delta) => {
+            info!("Config reloaded: {}", delta.describe());
+            delta.event(requested_by, hostname)
+        }
+        Err(e) => {
+            warn!("Config reload failed, keeping the running config: {:#}", e);
+            failed_event(&e, requested_by, hostname)
+        }
+    }
+}
+
+async fn try_apply(running: Running<'_>) -> Result<ConfigDelta> {
+    let path = DaemonConfig::path();
+    let loaded = DaemonConfig::load().with_context(|| format!("{} is invalid", path.display()))?;
+    let delta = ConfigDelta::between(running.config, &loaded)?;
+    let new = keep_sections(running.config, loaded, &delta.restart)?;
+
+    // Start everything new before replacing anything, so a failure leaves
+    // the agent as it was
+    let monitor = match (running.monitor, delta.monitor_changed()) {
+        (Some(monitor), true) => Some((monitor, MonitorSettings::new(&new)?)),
+        _ => None,
+    };
+    let sinks = running.sinks.reload(running.config, &new, running.hello)?;
+    if let Some((monitor, settings)) = monitor {
+        if let Err(e) = monitor.reload(settings).await {
+            sinks.stop_unshared(running.sinks);
+            return Err(e);
+        }
+    }
+
+    running.sinks.stop_unshared(&sinks);
+    *running.sinks = sinks;
+    if delta.rules_changed() {
+        let mut rules = RuleEngine::with_definitions(&new.rules);
+        rules.keep_stats(running.rules);
+        *running.rules = rules;
+    }
+    *running.config = new;
+    Ok(delta)
+}
+
+/// `new` with `sections` as they are in `old`, for those a reload cannot
+/// apply; they are reported again until the agent restarts
+fn keep_sections(
+    old: &DaemonConfig,
+    new: DaemonConfig,
+    sections: &[String],
+) -> Result<DaemonConfig> {
+    if sections.is_empty() {
+        return Ok(new);
+    }
+    let old = serde_json::to_value(old)?;
+    let mut new = serde_json::to_value(new)?;
+    if let (Some(old), Some(new)) = (old.as_object(), new.as_object_mut()) {
+        for section in sections {
+            let value = old.get(section).cloned().unwrap_or(Value::Null);
+            new.insert(section.clone(), value);
+        }
+    }
+    Ok(serde_json::from_value(new)?)
+}
+
+/// Event reporting a reload that left the running config in place
+pub fn failed_event(error: &anyhow::Error, requested_by: &str, hostname: &str) -> LogEvent {
+    reload_event(
+        Severity::Medium,
+        "warning",
+        format!(
+            "Config reload (requested by {}) failed, keeping the running config: {:#}",
+            requested_by, error
+        ),
+        hostname,
+    )
+}
+
+fn reload_event(severity: Severity, level: &str, message: String, hostname: &str) -> LogEvent {
+    LogEvent::new(
+        severity,
+        EventType::SystemLog {
+            source: "guardian-daemon".to_string(),
+            level: level.to_string(),
+            message,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(CONFIG_RELOAD_TAG)
+}
+
+/// Ask for a reload on every SIGHUP, as `systemctl reload` sends
+#[cfg(unix)]
+pub fn on_sighup(requests: mpsc::Sender<String>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if requests.send("SIGHUP".to_string()).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Windows has no SIGHUP; reloads come from the control channel only
+#[cfg(not(unix))]
+pub fn on_sighup(_requests: mpsc::Sender<String>) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatchConfig;
+    use std::path::Path;
+
+    #[test]
+    fn test_delta_between_configs() {
+        let old = DaemonConfig {
+            watch: vec![WatchConfig::new("/etc"), WatchConfig::new("/tmp/old")],
+            ..DaemonConfig::default()
+        };
+        let mut new = old.clone();
+        assert!(ConfigDelta::between(&old, &new).unwrap().is_empty());
+
+        new.watch.retain(|w| w.path != Path::new("/tmp/old"));
+        new.watch[0].recursive = false;
+        new.watch.push(WatchConfig::new("/srv/www"));
+        new.splunk.enabled = true;
+        new.syslog.enabled = !old.syslog.enabled;
+        let delta = ConfigDelta::between(&old, &new).unwrap();
+        assert_eq!(delta.watch_added, vec![PathBuf::from("/srv/www")]);
+        assert_eq!(delta.watch_removed, vec![PathBuf::from("/tmp/old")]);
+        assert_eq!(delta.watch_changed, vec![PathBuf::from("/etc")]);
+        assert_eq!(delta.applied, vec!["splunk", "watch"]);
+        assert_eq!(delta.restart, vec!["syslog"]);
+        assert!(delta.monitor_changed());
+        assert!(!delta.rules_changed());
+
+        let event = delta.event("alice", "web-1");
+        assert!(event.tags.contains(&CONFIG_RELOAD_TAG.to_string()));
+        match &event.event_type {
+            EventType::SystemLog { message, .. } => assert_eq!(
+                message,
+                "Config reloaded (requested by alice): watch +/srv/www -/tmp/old ~/etc; \
+                 changed splunk; restart needed for syslog"
+            ),
+            other => panic!("expected a system log, got {:?}", other),
+        }
+
+        // Only the sections a reload applies change in the running config
+        let running = keep_sections(&old, new.clone(), &delta.restart).unwrap();
+        assert_eq!(running.syslog, old.syslog);
+        assert_eq!(running.splunk, new.splunk);
+        assert_eq!(running.watch, new.watch);
+    }
+}
@@ -0,0 +1,196 @@
+//! `replay`: run stored events through the current rules and threat-intel
+//! lists, and report the ones that would be flagged now but were not when
+//! they were recorded. Run it after adding rules or indicators to find
+//! activity that slipped through before.
+
+use crate::config::DaemonConfig;
+use crate::rules::RuleEngine;
+use crate::threatintel::ThreatIntel;
+use anyhow::{Context, Result};
+use guardian_common::{EventType, LogEvent};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Tag marking alerts raised by replaying history
+pub const RETRO_TAG: &str = "retro_detection";
+
+/// Current detection logic, applied to events after the fact
+pub struct Replay {
+    rules: RuleEngine,
+    threat_intel: ThreatIntel,
+}
+
+impl Replay {
+    pub fn new(rules: RuleEngine, threat_intel: ThreatIntel) -> Self {
+        Self {
+            rules,
+            threat_intel,
+        }
+    }
+
+    /// Alert for `event` if it was stored unflagged and would be flagged
+    /// now. Alerts are not replayed, so a replay never alerts on itself.
+    pub fn detect(&self, event: &LogEvent) -> Option<LogEvent> {
+        if event.rule_triggered || matches!(event.event_type, EventType::Alert { .. }) {
+            return None;
+        }
+
+        let mut candidate = event.clone();
+        self.threat_intel.enrich(&mut candidate);
+        // A threat-intel match heads the rules the event now triggers
+        let mut matched = std::mem::take(&mut candidate.rules_matched);
+        candidate.rule_name = None;
+        let verdict = self.rules.apply(candidate);
+        if verdict.suppressed {
+            return None;
+        }
+        let candidate = verdict.event;
+        matched.extend(candidate.rules_matched.iter().cloned());
+        let rule = matched.first()?.clone();
+
+        let mut alert = LogEvent::new(
+            candidate.severity,
+            EventType::Alert {
+                title: format!("Retro-detection: {}", rule),
+                description: format!(
+                    "A {} event from {} recorded at {} matches {}",
+                    event.event_type.kind(),
+                    event.hostname,
+                    event.timestamp.to_rfc3339(),
+                    matched.join(", ")
+                ),
+                related_event_ids: vec![event.id],
+                technique: None,
+            },
+            event.hostname.clone(),
+        );
+        // Keep the threat-intel tags the match added
+        alert.tags = candidate
+            .tags
+            .into_iter()
+            .filter(|tag| !event.tags.contains(tag))
+            .collect();
+        Some(alert.with_tag(RETRO_TAG).with_rules(matched))
+    }
+}
+
+/// Print an alert for each event in `events` (NDJSON, `-` for stdin) that
+/// the current rules and lists flag, with a per-rule summary on stderr
+pub fn run_command(events: &Path) -> Result<()> {
+    let config = DaemonConfig::load()?;
+    // Feeds are pulled while the agent runs; only the list files apply here
+    let threat_intel = ThreatIntel::default();
+    threat_intel.reload(&config.threat_intel)?;
+    let replay = Replay::new(RuleEngine::with_definitions(&config.rules), threat_intel);
+
+    let reader: Box<dyn BufRead> = if events == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(events)
+            .with_context(|| format!("Failed to read {}", events.display()))?;
+        Box::new(std::io::BufReader::new(file))
+    };
+
+    let mut detections: BTreeMap<String, usize> = BTreeMap::new();
+    let mut total = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = match LogEvent::from_json_any_version(&line) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("line {}: {}", index + 1, e);
+                continue;
+            }
+        };
+        total += 1;
+        if let Some(alert) = replay.detect(&event) {
+            let rule = alert.rule_name.clone().unwrap_or_default();
+            *detections.entry(rule).or_default() += 1;
+            println!("{}", alert.to_json()?);
+        }
+    }
+
+    eprintln!(
+        "Replayed {} event(s), {} retro-detection(s)",
+        total,
+        detections.values().sum::<usize>()
+    );
+    for (rule, count) in &detections {
+        eprintln!("  {}: {}", rule, count);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::threatintel::{Indicator, Indicators};
+    use guardian_common::{FileOperation, Severity};
+
+    fn file_event(path: &str, hash: Option<&str>) -> LogEvent {
+        LogEvent::new(
+            Severity::Low,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation: FileOperation::Modify,
+                hash: hash.map(str::to_string),
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_retro_detection() {
+        let threat_intel = ThreatIntel::default();
+        let mut indicators = Indicators::default();
+        indicators.add_hash(
+            "deadbeef",
+            Indicator {
+                source: "feed".to_string(),
+                reference: None,
+            },
+        );
+        threat_intel.replace("lists", indicators);
+        let replay = Replay::new(RuleEngine::new(), threat_intel);
+
+        // A hash listed after the event was stored
+        let dropped = file_event("/tmp/x", Some("deadbeef"));
+        let alert = replay.detect(&dropped).unwrap();
+        assert_eq!(alert.severity, Severity::Critical);
+        assert_eq!(alert.rule_name.as_deref(), Some("threat_intel_hash_match"));
+        assert_eq!(
+            alert.rules_matched,
+            ["threat_intel_hash_match", "high_severity_alert"]
+        );
+        assert_eq!(alert.tags, ["threat_intel", "threat_intel:feed", RETRO_TAG]);
+        assert!(matches!(
+            &alert.event_type,
+            EventType::Alert { related_event_ids, .. } if related_event_ids == &[dropped.id]
+        ));
+
+        // A rule match on an event that was stored unflagged
+        let shadow = file_event("/etc/shadow", None);
+        let alert = replay.detect(&shadow).unwrap();
+        assert_eq!(
+            alert.rule_name.as_deref(),
+            Some("critical_file_modification")
+        );
+        assert_eq!(alert.severity, Severity::Low);
+
+        // Already flagged when stored, nothing matching, or an alert itself
+        let flagged = file_event("/etc/shadow", None).with_rule("critical_file_modification");
+        assert!(replay.detect(&flagged).is_none());
+        assert!(replay
+            .detect(&file_event("/srv/index.html", None))
+            .is_none());
+        assert!(replay.detect(&alert).is_none());
+    }
+}
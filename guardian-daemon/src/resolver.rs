@@ -0,0 +1,269 @@
+//! Hosts file and resolver tampering. Pointing a name at an attacker's
+//! address in the hosts file, or swapping the nameservers in `resolv.conf`
+//! or systemd-resolved's config, quietly redirects the host's traffic and is
+//! a common step after a compromise. The files are read every `poll_secs`
+//! and compared line by line with the last read; a change raises an alert
+//! quoting the lines added and removed.
+
+use crate::config::ResolverConfig;
+use crate::diff;
+use crate::queue::EventSender;
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Tag on every hosts file and resolver config alert
+pub const RESOLVER_TAG: &str = "dns_tampering";
+
+/// Tag on changes to a hosts file
+pub const HOSTS_TAG: &str = "hosts_file";
+
+/// Tag on changes to resolver config
+pub const RESOLVER_CONFIG_TAG: &str = "resolver_config";
+
+/// Rule name on hosts file entries added
+pub const HOSTS_RULE: &str = "hosts_file_modified";
+
+/// Rule name on resolver config lines added
+pub const RESOLVER_RULE: &str = "resolver_config_modified";
+
+/// Significant lines of each watched file; `None` when it does not exist
+pub type Snapshot = BTreeMap<PathBuf, Option<Vec<String>>>;
+
+/// Lines that affect resolution, with comments dropped and whitespace
+/// collapsed so reformatting is not reported
+fn significant_lines(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Read every file in `paths`, taking the files directly inside any
+/// directory
+pub fn read(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in paths {
+        if path.is_dir() {
+            let Ok(entries) = std::fs::read_dir(path) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let file = entry.path();
+                if file.is_file() {
+                    let lines = read_file(&file);
+                    snapshot.insert(file, lines);
+                }
+            }
+        } else {
+            snapshot.insert(path.clone(), read_file(path));
+        }
+    }
+    snapshot
+}
+
+fn read_file(path: &Path) -> Option<Vec<String>> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(significant_lines(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Whether `path` is a hosts file rather than resolver config
+fn is_hosts_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("hosts"))
+}
+
+/// Alert for one file whose significant lines differ between reads; high
+/// severity when lines were added, as a hijack needs to add an entry or a
+/// nameserver
+fn alert(
+    path: &Path,
+    old: Option<&[String]>,
+    new: Option<&[String]>,
+    max_diff_lines: usize,
+    hostname: &str,
+) -> Option<LogEvent> {
+    let (removed, added) = diff::changed_lines(old.unwrap_or_default(), new.unwrap_or_default());
+    if removed.is_empty() && added.is_empty() && old.is_some() == new.is_some() {
+        return None;
+    }
+
+    let (what, tag) = if is_hosts_file(path) {
+        ("Hosts file", HOSTS_TAG)
+    } else {
+        ("Resolver config", RESOLVER_CONFIG_TAG)
+    };
+    let verb = match (old, new) {
+        (None, _) => "created",
+        (_, None) => "deleted",
+        _ => "changed",
+    };
+    let mut description = format!(
+        "{} {}: {} line(s) added, {} removed",
+        path.display(),
+        verb,
+        added.len(),
+        removed.len()
+    );
+    let changes: Vec<String> = added
+        .iter()
+        .map(|line| format!("+ {}", line))
+        .chain(removed.iter().map(|line| format!("- {}", line)))
+        .collect();
+    for change in changes.iter().take(max_diff_lines) {
+        description.push('\n');
+        description.push_str(change);
+    }
+    if changes.len() > max_diff_lines {
+        description.push_str(&format!(
+            "\n... and {} more",
+            changes.len() - max_diff_lines
+        ));
+    }
+
+    let severity = if added.is_empty() {
+        Severity::Medium
+    } else {
+        Severity::High
+    };
+    Some(
+        LogEvent::new(
+            severity,
+            EventType::Alert {
+                title: format!("{} {} {}", what, path.display(), verb),
+                description,
+                related_event_ids: Vec::new(),
+                technique: Some("T1565.001".to_string()),
+            },
+            hostname.to_string(),
+        )
+        .with_tag(RESOLVER_TAG)
+        .with_tag(tag),
+    )
+}
+
+/// Alerts for every file that differs between two snapshots
+pub fn compare(
+    old: &Snapshot,
+    new: &Snapshot,
+    max_diff_lines: usize,
+    hostname: &str,
+) -> Vec<LogEvent> {
+    let mut paths: Vec<&PathBuf> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old = old.get(path).and_then(|lines| lines.as_deref());
+            let new = new.get(path).and_then(|lines| lines.as_deref());
+            alert(path, old, new, max_diff_lines, hostname)
+        })
+        .collect()
+}
+
+/// Read the files every `poll_secs` until the event channel closes. The
+/// first read only records what is there.
+pub fn run(config: ResolverConfig, tx: EventSender, hostname: String) {
+    let interval = std::time::Duration::from_secs(config.poll_secs.max(1));
+    info!(
+        "Watching {} resolver path(s) for tampering every {}s",
+        config.paths.len(),
+        interval.as_secs()
+    );
+    let mut known = read(&config.paths);
+    loop {
+        std::thread::sleep(interval);
+        let current = read(&config.paths);
+        for event in compare(&known, &current, config.max_diff_lines, &hostname) {
+            if let EventType::Alert { title, .. } = &event.event_type {
+                warn!("{}", title);
+            }
+            if tx.blocking_send(event).is_err() {
+                return;
+            }
+        }
+        known = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(contents: &str) -> Option<Vec<String>> {
+        Some(significant_lines(contents))
+    }
+
+    #[test]
+    fn test_hosts_and_resolver_changes() {
+        let hosts = PathBuf::from("/etc/hosts");
+        let resolv = PathBuf::from("/etc/resolv.conf");
+        let dropin = PathBuf::from("/etc/systemd/resolved.conf.d/dns.conf");
+        let old = Snapshot::from([
+            (
+                hosts.clone(),
+                lines("127.0.0.1\tlocalhost\n::1 localhost\n"),
+            ),
+            (resolv.clone(), lines("# managed\nnameserver 10.0.0.1\n")),
+        ]);
+
+        // Reformatting and comments are not changes
+        let reformatted = Snapshot::from([
+            (
+                hosts.clone(),
+                lines("127.0.0.1 localhost # loopback\n::1   localhost\n"),
+            ),
+            (resolv.clone(), lines("nameserver 10.0.0.1\n")),
+        ]);
+        assert!(compare(&old, &reformatted, 10, "web-1").is_empty());
+
+        let new = Snapshot::from([
+            (
+                hosts.clone(),
+                lines("127.0.0.1 localhost\n::1 localhost\n203.0.113.9 bank.example.com\n"),
+            ),
+            (resolv.clone(), None),
+            (dropin.clone(), lines("[Resolve]\nDNS=203.0.113.53\n")),
+        ]);
+        let events = compare(&old, &new, 1, "web-1");
+        assert_eq!(events.len(), 3);
+
+        assert_eq!(events[0].severity, Severity::High);
+        assert!(events[0].tags.contains(&HOSTS_TAG.to_string()));
+        match &events[0].event_type {
+            EventType::Alert {
+                title, description, ..
+            } => {
+                assert_eq!(title, "Hosts file /etc/hosts changed");
+                assert_eq!(
+                    description,
+                    "/etc/hosts changed: 1 line(s) added, 0 removed\n+ 203.0.113.9 bank.example.com"
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Sorted by path: resolv.conf, deleted, then the new drop-in
+        assert_eq!(events[1].severity, Severity::Medium);
+        match &events[1].event_type {
+            EventType::Alert { title, .. } => {
+                assert_eq!(title, "Resolver config /etc/resolv.conf deleted")
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(events[2].severity, Severity::High);
+        assert!(events[2].tags.contains(&RESOLVER_CONFIG_TAG.to_string()));
+        match &events[2].event_type {
+            EventType::Alert { description, .. } => assert_eq!(
+                description,
+                "/etc/systemd/resolved.conf.d/dns.conf created: 2 line(s) added, 0 removed\n\
+                 + [Resolve]\n... and 1 more"
+            ),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
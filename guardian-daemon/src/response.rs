@@ -0,0 +1,628 @@
+use crate::queue::EventSender;
+use anyhow::{bail, Context, Result};
+use guardian_common::control::MAX_ISOLATION_SECS;
+use guardian_common::{EventType, LogEvent, RuleResponse, Severity};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, System};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{error, info, warn};
+
+/// nftables table that holds the isolation policy
+const ISOLATION_TABLE: &str = "guardian_isolation";
+
+/// Shortest time between two responses asked for by the same rule
+const RULE_RESPONSE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parent links followed when looking for the daemon's ancestors
+const MAX_ANCESTORS: usize = 64;
+
+/// A peer that stays reachable while the host is isolated
+#[derive(Debug, Clone, PartialEq)]
+struct AllowedPeer {
+    addr: IpAddr,
+    prefix: Option<u8>,
+    port: Option<u16>,
+}
+
+impl AllowedPeer {
+    /// Parse `ip`, `ip:port`, `[ipv6]:port` or `ip/prefix`
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+
+        if let Ok(socket) = spec.parse::<SocketAddr>() {
+            return Ok(Self {
+                addr: socket.ip(),
+                prefix: None,
+                port: Some(socket.port()),
+            });
+        }
+
+        let (addr, prefix) = match spec.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (spec, None),
+        };
+
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("Invalid allowed peer: {}", spec))?;
+
+        let prefix = match prefix {
+            Some(p) => {
+                let p: u8 = p
+                    .parse()
+                    .with_context(|| format!("Invalid prefix in allowed peer: {}", spec))?;
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                if p > max {
+                    bail!("Prefix /{} out of range in allowed peer: {}", p, spec);
+                }
+                Some(p)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            addr,
+            prefix,
+            port: None,
+        })
+    }
+
+    /// Render accept rules for one direction (`saddr`/`sport` or `daddr`/`dport`)
+    fn rules(&self, addr_dir: &str, port_dir: &str) -> Vec<String> {
+        let family = if self.addr.is_ipv4() { "ip" } else { "ip6" };
+        let target = match self.prefix {
+            Some(p) => format!("{}/{}", self.addr, p),
+            None => self.addr.to_string(),
+        };
+
+        match self.port {
+            Some(port) => ["tcp", "udp"]
+                .iter()
+                .map(|proto| {
+                    format!(
+                        "{} {} {} {} {} {} accept",
+                        family, addr_dir, target, proto, port_dir, port
+                    )
+                })
+                .collect(),
+            None => vec![format!("{} {} {} accept", family, addr_dir, target)],
+        }
+    }
+}
+
+/// Build the nftables script that drops everything but loopback and the
+/// allowed peers. The table's comment records when it expires (Unix
+/// seconds), so a restarted daemon can take it over.
+fn isolation_ruleset(peers: &[AllowedPeer], expires: i64) -> String {
+    let mut input = vec!["iif \"lo\" accept".to_string()];
+    let mut output = vec!["oif \"lo\" accept".to_string()];
+
+    for peer in peers {
+        input.extend(peer.rules("saddr", "sport"));
+        output.extend(peer.rules("daddr", "dport"));
+    }
+
+    let indent = |rules: Vec<String>| {
+        rules
+            .into_iter()
+            .map(|r| format!("        {}\n", r))
+            .collect::<String>()
+    };
+
+    // Declaring then deleting the table makes re-applying idempotent
+    format!(
+        "table inet {table}\ndelete table inet {table}\n\
+         table inet {table} {{\n\
+         \x20   comment \"expires {expires}\"\n\
+         \x20   chain input {{\n\
+         \x20       type filter hook input priority -100; policy drop;\n\
+         {input}\
+         \x20   }}\n\
+         \x20   chain output {{\n\
+         \x20       type filter hook output priority -100; policy drop;\n\
+         {output}\
+         \x20   }}\n\
+         }}\n",
+        table = ISOLATION_TABLE,
+        expires = expires,
+        input = indent(input),
+        output = indent(output),
+    )
+}
+
+/// Parse the peers of an isolation, refusing one that would cut the host
+/// off entirely or last longer than a console may ask for
+fn isolation_peers(allow: &[String], duration: Duration) -> Result<Vec<AllowedPeer>> {
+    if allow.is_empty() {
+        bail!("Isolation needs at least one allowed peer");
+    }
+    if duration.is_zero() || duration.as_secs() > MAX_ISOLATION_SECS {
+        bail!(
+            "Isolation duration must be between 1 and {} seconds",
+            MAX_ISOLATION_SECS
+        );
+    }
+    allow.iter().map(|spec| AllowedPeer::parse(spec)).collect()
+}
+
+/// Expiry recorded in a listing of the isolation table, if any
+fn isolation_expiry(listing: &str) -> Option<i64> {
+    let (_, rest) = listing.split_once("comment \"expires ")?;
+    rest.split('"').next()?.parse().ok()
+}
+
+/// Currently applied isolation policy
+struct ActiveIsolation {
+    generation: u64,
+}
+
+/// Applies and lifts host network isolation on behalf of the console
+pub struct HostIsolation {
+    tx: EventSender,
+    hostname: String,
+    active: Mutex<Option<ActiveIsolation>>,
+    generation: Mutex<u64>,
+}
+
+impl HostIsolation {
+    pub fn new(tx: EventSender, hostname: String) -> Arc<Self> {
+        Arc::new(Self {
+            tx,
+            hostname,
+            active: Mutex::new(None),
+            generation: Mutex::new(0),
+        })
+    }
+
+    /// Isolate the host, replacing any active policy, and schedule its expiry
+    pub async fn isolate(
+        self: &Arc<Self>,
+        allow: &[String],
+        duration: Duration,
+        requested_by: &str,
+    ) -> Result<()> {
+        let peers = isolation_peers(allow, duration)?;
+        let expires = chrono::Utc::now().timestamp() + duration.as_secs() as i64;
+
+        // Held until the new generation is recorded, so a pending expiry
+        // cannot lift the policy just applied
+        let mut active = self.active.lock().await;
+        if let Err(e) = run_nft(&isolation_ruleset(&peers, expires)).await {
+            drop(active);
+            self.emit(
+                Severity::High,
+                "error",
+                format!("Host isolation requested by {} failed: {}", requested_by, e),
+            )
+            .await;
+            return Err(e);
+        }
+        let generation = self.next_generation().await;
+        *active = Some(ActiveIsolation { generation });
+        drop(active);
+
+        info!(
+            "Host isolated (allow: {:?}, duration: {:?})",
+            allow, duration
+        );
+        self.emit(
+            Severity::High,
+            "warning",
+            format!(
+                "Host isolated by {} for {}s; allowed peers: {}",
+                requested_by,
+                duration.as_secs(),
+                allow.join(", ")
+            ),
+        )
+        .await;
+
+        self.schedule_expiry(generation, duration);
+        Ok(())
+    }
+
+    /// Take over an isolation policy left by an earlier run that did not
+    /// lift it, such as one that crashed: keep it until its recorded
+    /// expiry, or lift it now if that has passed or is unknown
+    pub async fn recover(self: &Arc<Self>) {
+        // Listing fails when there is no table
+        let Ok(listing) = nft_list(ISOLATION_TABLE).await else {
+            return;
+        };
+        let remaining = isolation_expiry(&listing)
+            .map(|expires| expires - chrono::Utc::now().timestamp())
+            .filter(|secs| *secs > 0);
+
+        let mut active = self.active.lock().await;
+        let Some(secs) = remaining else {
+            if let Err(e) = self.lift(active, "restart").await {
+                error!(
+                    "Failed to lift host isolation left by an earlier run: {}",
+                    e
+                );
+            }
+            return;
+        };
+        let generation = self.next_generation().await;
+        *active = Some(ActiveIsolation { generation });
+        drop(active);
+
+        info!("Host isolation from an earlier run stays for {}s", secs);
+        self.emit(
+            Severity::High,
+            "warning",
+            format!(
+                "Host isolation from an earlier run stays in place for {}s",
+                secs
+            ),
+        )
+        .await;
+        self.schedule_expiry(generation, Duration::from_secs(secs as u64));
+    }
+
+    /// Lift the isolation policy. Returns false if the host was not isolated.
+    pub async fn release(&self, requested_by: &str) -> Result<bool> {
+        let active = self.active.lock().await;
+        if active.is_none() {
+            return Ok(false);
+        }
+        self.lift(active, requested_by).await?;
+        Ok(true)
+    }
+
+    /// Release the policy if it is still the one that scheduled this expiry
+    async fn expire(&self, generation: u64) {
+        // Checked and lifted under one lock, so a policy applied in between
+        // is never the one removed
+        let active = self.active.lock().await;
+        if active.as_ref().map(|a| a.generation) != Some(generation) {
+            return;
+        }
+
+        if let Err(e) = self.lift(active, "expiry").await {
+            error!("Failed to lift expired host isolation: {}", e);
+        }
+    }
+
+    /// Remove the active policy while still holding its lock
+    async fn lift(
+        &self,
+        mut active: MutexGuard<'_, Option<ActiveIsolation>>,
+        requested_by: &str,
+    ) -> Result<()> {
+        run_nft(&format!("delete table inet {}\n", ISOLATION_TABLE)).await?;
+        *active = None;
+        drop(active);
+
+        info!("Host isolation released");
+        self.emit(
+            Severity::Medium,
+            "info",
+            format!("Host isolation released by {}", requested_by),
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn next_generation(&self) -> u64 {
+        let mut generation = self.generation.lock().await;
+        *generation += 1;
+        *generation
+    }
+
+    fn schedule_expiry(self: &Arc<Self>, generation: u64, duration: Duration) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            this.expire(generation).await;
+        });
+    }
+
+    /// Emit a response audit event into the main pipeline
+    async fn emit(&self, severity: Severity, level: &str, message: String) {
+        let event = LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "response".to_string(),
+                level: level.to_string(),
+                message,
+            },
+            self.hostname.clone(),
+        )
+        .with_tag("response")
+        .with_tag("host_isolation");
+
+        if self.tx.send(event).await.is_err() {
+            warn!("Failed to emit response event - channel closed");
+        }
+    }
+}
+
+/// Carries out the responses that rule actions ask for
+pub struct RuleResponder {
+    isolation: Arc<HostIsolation>,
+    tx: EventSender,
+    hostname: String,
+    /// When each rule last had a response carried out
+    last: std::sync::Mutex<HashMap<String, Instant>>,
+}
+
+impl RuleResponder {
+    pub fn new(isolation: Arc<HostIsolation>, tx: EventSender, hostname: String) -> Self {
+        Self {
+            isolation,
+            tx,
+            hostname,
+            last: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `rule` may respond now, recording it if so
+    fn due(&self, rule: &str) -> bool {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if last
+            .get(rule)
+            .is_some_and(|at| now.duration_since(*at) < RULE_RESPONSE_INTERVAL)
+        {
+            return false;
+        }
+        last.insert(rule.to_string(), now);
+        true
+    }
+
+    /// Start `response`, asked for by `rule` on `event`, in the background.
+    /// A rule responds at most once per `RULE_RESPONSE_INTERVAL`.
+    pub fn respond(&self, rule: &str, response: RuleResponse, event: &LogEvent) {
+        let requested_by = format!("rule {}", rule);
+        if !self.due(rule) {
+            warn!(
+                "Skipping the response asked for by {}: it responded within the last {}s",
+                requested_by,
+                RULE_RESPONSE_INTERVAL.as_secs()
+            );
+            return;
+        }
+        match response {
+            RuleResponse::IsolateHost {
+                allow,
+                duration_secs,
+            } => {
+                let isolation = Arc::clone(&self.isolation);
+                tokio::spawn(async move {
+                    let duration = Duration::from_secs(duration_secs);
+                    if let Err(e) = isolation.isolate(&allow, duration, &requested_by).await {
+                        error!("Host isolation requested by {} failed: {}", requested_by, e);
+                    }
+                });
+            }
+            RuleResponse::KillProcess => {
+                let EventType::ProcessMonitor { pid, name, .. } = &event.event_type else {
+                    warn!(
+                        "{} asked for a process kill on an event without one",
+                        requested_by
+                    );
+                    return;
+                };
+                let (pid, name) = (*pid, name.clone());
+                // System stats are reported under the daemon's own pid
+                if pid == std::process::id() {
+                    return;
+                }
+                let tx = self.tx.clone();
+                let hostname = self.hostname.clone();
+                tokio::spawn(async move {
+                    let target = name.clone();
+                    let killed = tokio::task::spawn_blocking(move || kill_process(pid, &target))
+                        .await
+                        .unwrap_or_else(|e| Err(e.into()));
+                    let (level, message) = match killed {
+                        Ok(()) => {
+                            info!("Killed {} ({}) for {}", name, pid, requested_by);
+                            (
+                                "warning",
+                                format!("{} ({}) killed by {}", name, pid, requested_by),
+                            )
+                        }
+                        Err(e) => {
+                            warn!(
+                                "Failed to kill {} ({}) for {}: {}",
+                                name, pid, requested_by, e
+                            );
+                            (
+                                "error",
+                                format!(
+                                    "Kill of {} ({}) requested by {} failed: {}",
+                                    name, pid, requested_by, e
+                                ),
+                            )
+                        }
+                    };
+
+                    let event = LogEvent::new(
+                        Severity::High,
+                        EventType::SystemLog {
+                            source: "response".to_string(),
+                            level: level.to_string(),
+                            message,
+                        },
+                        hostname,
+                    )
+                    .with_tag("response")
+                    .with_tag("process_kill");
+                    if tx.send(event).await.is_err() {
+                        warn!("Failed to emit response event - channel closed");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Kill `pid` if it is still the process called `name`, not one that has
+/// since taken over its pid, and not one the host or the daemon needs
+fn kill_process(pid: u32, name: &str) -> Result<()> {
+    let mut sys = System::new();
+    sys.refresh_processes();
+    let pid = Pid::from_u32(pid);
+    if let Some(reason) = protected(&sys, pid) {
+        bail!("refusing to kill {}", reason);
+    }
+    let process = sys.process(pid).context("process has exited")?;
+    if process.name() != name {
+        bail!("pid now belongs to {}", process.name());
+    }
+    if !process.kill() {
+        bail!("kill failed");
+    }
+    Ok(())
+}
+
+/// Why `pid` must never be killed: it is init, a kernel thread, or the
+/// daemon or one of its ancestors
+fn protected(sys: &System, pid: Pid) -> Option<&'static str> {
+    if pid.as_u32() <= 1 {
+        return Some("init");
+    }
+    // Kernel threads are kthreadd and its children
+    let kthreadd = Pid::from_u32(2);
+    if cfg!(target_os = "linux")
+        && (pid == kthreadd || sys.process(pid).and_then(|p| p.parent()) == Some(kthreadd))
+    {
+        return Some("a kernel thread");
+    }
+    let mut ancestor = Some(Pid::from_u32(std::process::id()));
+    for _ in 0..MAX_ANCESTORS {
+        let Some(current) = ancestor else {
+            break;
+        };
+        if current == pid {
+            return Some("the daemon or one of its ancestors");
+        }
+        ancestor = sys.process(current).and_then(|p| p.parent());
+    }
+    None
+}
+
+/// Whether `nft` is on the PATH, so isolation can be applied on this host
+pub fn nft_available() -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join("nft").is_file()))
+        .unwrap_or(false)
+}
+
+/// `nft list table inet <table>`
+async fn nft_list(table: &str) -> Result<String> {
+    let output = Command::new("nft")
+        .args(["list", "table", "inet", table])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to run nft")?;
+    if !output.status.success() {
+        bail!(
+            "nft exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Feed a script to `nft -f -`
+async fn run_nft(script: &str) -> Result<()> {
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run nft")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!(
+            "nft exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_processes() {
+        let mut sys = System::new();
+        sys.refresh_processes();
+        let own = Pid::from_u32(std::process::id());
+        assert!(protected(&sys, Pid::from_u32(1)).is_some());
+        assert!(protected(&sys, own).is_some());
+        if let Some(parent) = sys.process(own).and_then(|p| p.parent()) {
+            assert!(protected(&sys, parent).is_some());
+        }
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        sys.refresh_processes();
+        assert_eq!(protected(&sys, Pid::from_u32(child.id())), None);
+        child.kill().unwrap();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn test_parse_allowed_peers() {
+        let peer = AllowedPeer::parse("10.0.0.5:8443").unwrap();
+        assert_eq!(peer.port, Some(8443));
+        assert_eq!(peer.prefix, None);
+
+        let peer = AllowedPeer::parse("10.0.0.0/24").unwrap();
+        assert_eq!(peer.prefix, Some(24));
+
+        assert!(AllowedPeer::parse("10.0.0.0/33").is_err());
+        assert!(AllowedPeer::parse("collector.local").is_err());
+    }
+
+    #[test]
+    fn test_isolation_peers_are_bounded() {
+        let allow = vec!["10.0.0.5:8443".to_string()];
+        let hour = Duration::from_secs(3600);
+        assert_eq!(isolation_peers(&allow, hour).unwrap().len(), 1);
+
+        assert!(isolation_peers(&[], hour).is_err());
+        assert!(isolation_peers(&allow, Duration::ZERO).is_err());
+        assert!(isolation_peers(&allow, Duration::from_secs(MAX_ISOLATION_SECS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_isolation_ruleset() {
+        let peers = vec![
+            AllowedPeer::parse("10.0.0.5:8443").unwrap(),
+            AllowedPeer::parse("fd00::/8").unwrap(),
+        ];
+        let script = isolation_ruleset(&peers, 1_700_000_000);
+
+        assert!(script.contains("policy drop;"));
+        assert!(script.contains("ip daddr 10.0.0.5 tcp dport 8443 accept"));
+        assert!(script.contains("ip saddr 10.0.0.5 tcp sport 8443 accept"));
+        assert!(script.contains("ip6 daddr fd00::/8 accept"));
+        assert!(script.starts_with("table inet guardian_isolation\ndelete table"));
+        assert_eq!(isolation_expiry(&script), Some(1_700_000_000));
+        assert_eq!(isolation_expiry("table inet guardian_isolation {\n}"), None);
+    }
+}
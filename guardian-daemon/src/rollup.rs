@@ -0,0 +1,183 @@
+use crate::config::{RollupConfig, RollupMode};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use guardian_common::rollup::{PathCount, RollupCount};
+use guardian_common::{EventType, LogEvent, Severity, StatsRollup};
+use std::collections::HashMap;
+
+/// Length of one rollup period
+const PERIOD_SECS: i64 = 3600;
+
+/// Accumulates hourly event counts and produces rollups when a period ends
+pub struct RollupAggregator {
+    config: RollupConfig,
+    hostname: String,
+    period_start: DateTime<Utc>,
+    counts: HashMap<(&'static str, Severity), u64>,
+    paths: HashMap<String, u64>,
+}
+
+impl RollupAggregator {
+    pub fn new(config: RollupConfig, hostname: String) -> Self {
+        Self {
+            config,
+            hostname,
+            period_start: period_start(Utc::now()),
+            counts: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Count an event. Returns whether the raw event should still be emitted.
+    pub fn record(&mut self, event: &LogEvent) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+
+        *self
+            .counts
+            .entry((event.event_type.kind(), event.severity))
+            .or_default() += 1;
+
+        if let EventType::FileIntegrity { path, .. } = &event.event_type {
+            *self.paths.entry(path.clone()).or_default() += 1;
+        }
+
+        self.config.mode == RollupMode::Alongside
+            || event.severity > Severity::Info
+            || event.rule_triggered
+    }
+
+    /// Close the current period if `now` is past it, returning its rollup
+    pub fn flush_if_due(&mut self, now: DateTime<Utc>) -> Option<StatsRollup> {
+        if !self.config.enabled || now < self.period_start + Duration::seconds(PERIOD_SECS) {
+            return None;
+        }
+
+        let mut counts: Vec<RollupCount> = self
+            .counts
+            .drain()
+            .map(|((event_type, severity), count)| RollupCount {
+                event_type: event_type.to_string(),
+                severity,
+                count,
+            })
+            .collect();
+        counts.sort_by(|a, b| {
+            (a.event_type.as_str(), a.severity).cmp(&(b.event_type.as_str(), b.severity))
+        });
+
+        let mut top_paths: Vec<PathCount> = self
+            .paths
+            .drain()
+            .map(|(path, count)| PathCount { path, count })
+            .collect();
+        top_paths.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+        top_paths.truncate(self.config.top_paths);
+
+        let rollup = StatsRollup {
+            hostname: self.hostname.clone(),
+            period_start: self.period_start,
+            period_secs: PERIOD_SECS as u32,
+            counts,
+            top_paths,
+            signature: None,
+        };
+        self.period_start = period_start(now);
+
+        Some(rollup).filter(|r| !r.counts.is_empty())
+    }
+}
+
+/// Start of the hour containing `time`
+fn period_start(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.duration_trunc(Duration::seconds(PERIOD_SECS))
+        .unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::FileOperation;
+
+    fn file_event(path: &str, severity: Severity) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::FileIntegrity {
+                path: path.to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    fn process_event() -> LogEvent {
+        LogEvent::new(
+            Severity::Info,
+            EventType::ProcessMonitor {
+                pid: 1,
+                name: "system".to_string(),
+                cpu_usage: 1.0,
+                memory_usage: 1024,
+                ancestry: Vec::new(),
+                user: None,
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_rollup_counts_and_top_paths() {
+        let config = RollupConfig {
+            top_paths: 1,
+            ..RollupConfig::default()
+        };
+        let mut aggregator = RollupAggregator::new(config, "web-1".to_string());
+        let start = aggregator.period_start;
+
+        for _ in 0..3 {
+            assert!(aggregator.record(&file_event("/srv/a", Severity::Low)));
+        }
+        aggregator.record(&file_event("/srv/b", Severity::Low));
+        aggregator.record(&process_event());
+
+        assert!(aggregator
+            .flush_if_due(start + Duration::minutes(30))
+            .is_none());
+
+        let rollup = aggregator.flush_if_due(start + Duration::hours(1)).unwrap();
+        assert_eq!(rollup.period_start, start);
+        assert_eq!(rollup.total(), 5);
+        assert_eq!(rollup.counts[0].event_type, "file_integrity");
+        assert_eq!(rollup.counts[0].count, 4);
+        assert_eq!(
+            rollup.top_paths,
+            vec![PathCount {
+                path: "/srv/a".to_string(),
+                count: 3
+            }]
+        );
+
+        // Next period starts empty
+        assert!(aggregator
+            .flush_if_due(start + Duration::hours(2))
+            .is_none());
+    }
+
+    #[test]
+    fn test_instead_mode_suppresses_raw_info_events() {
+        let config = RollupConfig {
+            mode: RollupMode::Instead,
+            ..RollupConfig::default()
+        };
+        let mut aggregator = RollupAggregator::new(config, "web-1".to_string());
+
+        assert!(!aggregator.record(&process_event()));
+        assert!(aggregator.record(&file_event("/etc/passwd", Severity::High)));
+        assert!(aggregator.record(&process_event().with_rule("high_cpu_usage")));
+    }
+}
@@ -0,0 +1,849 @@
+//! Rule packs: detection content in one TOML file, so it can be shared
+//! between deployments. A pack holds a manifest, rules written as data and
+//! the YARA files of `[yara] rules_dir`. Packs can be signed with an Ed25519
+//! key, and `[rule_packs]` decides whether unsigned or untrusted ones are
+//! imported.
+
+use crate::config::{DaemonConfig, RulePackConfig, SignaturePolicy, YaraConfig};
+use crate::enroll::write_private;
+use crate::platform;
+use crate::provision::{write_if_changed, ApplyOutcome};
+use crate::rules::RuleEngine;
+use crate::scanner::{self, YaraScanner};
+use crate::yara_sources;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use guardian_common::{CompiledRule, RuleDefinition, RuleResponse};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+/// Format version written into rule packs
+const RULE_PACK_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RulePack {
+    pub manifest: PackManifest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<PackSignature>,
+    #[serde(default)]
+    pub rules: Vec<RuleDefinition>,
+    #[serde(default)]
+    pub yara: Vec<YaraFile>,
+}
+
+/// What a pack is and where it came from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PackManifest {
+    pub version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Hostname of the agent the pack was exported from
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub source: String,
+    pub created: DateTime<Utc>,
+}
+
+/// Ed25519 signature over the rest of the pack, as compact JSON with the
+/// fields in declaration order, so reformatting the TOML keeps it valid.
+/// Remote YARA files are signed with the same table in a `.sig` file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PackSignature {
+    /// Base64 public key of the signer
+    pub key: String,
+    /// Base64 signature
+    pub value: String,
+}
+
+impl PackSignature {
+    pub fn sign(key: &Ed25519KeyPair, bytes: &[u8]) -> Self {
+        Self {
+            key: STANDARD.encode(key.public_key()),
+            value: STANDARD.encode(key.sign(bytes)),
+        }
+    }
+
+    /// Check the signature over `bytes` against base64 `trusted_keys`
+    pub fn check(&self, bytes: &[u8], trusted_keys: &[String]) -> SignatureCheck {
+        let (Ok(key), Ok(value)) = (STANDARD.decode(&self.key), STANDARD.decode(&self.value))
+        else {
+            return SignatureCheck::Invalid;
+        };
+        if UnparsedPublicKey::new(&ED25519, &key)
+            .verify(bytes, &value)
+            .is_err()
+        {
+            return SignatureCheck::Invalid;
+        }
+        let trusted = trusted_keys
+            .iter()
+            .any(|trusted| STANDARD.decode(trusted.trim()).is_ok_and(|t| t == key));
+        if trusted {
+            SignatureCheck::Trusted
+        } else {
+            SignatureCheck::Untrusted(self.key.clone())
+        }
+    }
+}
+
+/// Apply the signature policy to `what`, e.g. `rule pack web`: an error
+/// when it is refused, a warning when it is accepted despite its
+/// signature. A signature that does not match is refused whatever the
+/// policy, unless checks are off.
+pub fn enforce(
+    what: &str,
+    check: SignatureCheck,
+    config: &RulePackConfig,
+) -> Result<Option<String>> {
+    match (check, config.policy()) {
+        (_, SignaturePolicy::Off) | (SignatureCheck::Trusted, _) => Ok(None),
+        (check @ SignatureCheck::Invalid, _) | (check, SignaturePolicy::Require) => {
+            bail!("Refusing {}: {}", what, check)
+        }
+        (check, SignaturePolicy::Warn) => Ok(Some(format!("{} is {}", what, check))),
+    }
+}
+
+/// How a pack's signature checks out against the trusted keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureCheck {
+    Trusted,
+    Unsigned,
+    /// Valid, but by a key not in `trusted_keys`
+    Untrusted(String),
+    /// Does not match the pack's contents, which were changed after signing
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureCheck::Trusted => write!(f, "signed by a trusted key"),
+            SignatureCheck::Unsigned => write!(f, "not signed"),
+            SignatureCheck::Untrusted(key) => write!(f, "signed by untrusted key {}", key),
+            SignatureCheck::Invalid => write!(f, "signature does not match the contents"),
+        }
+    }
+}
+
+/// A YARA source file, carried inline
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct YaraFile {
+    /// File name within `rules_dir`, ending in `.yar` or `.yara`
+    pub name: String,
+    pub source: String,
+}
+
+/// A pack entry that clashes with what is installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// A configured rule of the same name differs
+    Rule(String),
+    /// An installed YARA file of the same name differs
+    YaraFile(String),
+    /// A built-in rule has the same name
+    BuiltinRule(String),
+    /// A YARA rule of the same name is declared by a file the pack does not
+    /// replace, or built in
+    YaraRule { rule: String, declared_in: String },
+}
+
+impl Conflict {
+    /// Whether `--replace` resolves the conflict by overwriting the
+    /// installed entry
+    pub fn replaceable(&self) -> bool {
+        matches!(self, Conflict::Rule(_) | Conflict::YaraFile(_))
+    }
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::Rule(name) => write!(f, "rule {} differs from the configured one", name),
+            Conflict::YaraFile(name) => {
+                write!(f, "YARA file {} differs from the installed one", name)
+            }
+            Conflict::BuiltinRule(name) => write!(f, "rule {} is built in", name),
+            Conflict::YaraRule { rule, declared_in } => {
+                write!(
+                    f,
+                    "YARA rule {} is already declared in {}",
+                    rule, declared_in
+                )
+            }
+        }
+    }
+}
+
+/// What importing a pack changes, worked out before anything is written
+#[derive(Debug)]
+pub struct ImportPlan {
+    /// Entries new to this agent, e.g. `rule ssh_from_tor`
+    pub added: Vec<String>,
+    /// Entries already installed as they are in the pack
+    pub unchanged: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    /// Rules to write that kill processes or isolate the host, e.g.
+    /// `rule kill_miner kills processes`
+    pub responding: Vec<String>,
+    /// Rules new to the config or changed by the pack
+    rules: Vec<RuleDefinition>,
+    /// YARA files to write
+    files: Vec<(PathBuf, String)>,
+    /// Every YARA source once the files are written, to compile first
+    sources: Vec<(String, String)>,
+}
+
+impl RulePack {
+    /// Capture the rules of `config` and the YARA files of its `rules_dir`
+    pub fn export(config: &DaemonConfig, name: &str, source: &str) -> Result<Self> {
+        let yara = match &config.yara.rules_dir {
+            Some(dir) => scanner::read_sources(dir)?
+                .into_iter()
+                .map(|(name, source)| YaraFile { name, source })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            manifest: PackManifest {
+                version: RULE_PACK_VERSION,
+                name: name.to_string(),
+                description: String::new(),
+                source: source.to_string(),
+                created: Utc::now(),
+            },
+            signature: None,
+            rules: config.rules.clone(),
+            yara,
+        })
+    }
+
+    /// What a signature covers: the pack without its signature
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        let unsigned = Self {
+            signature: None,
+            ..self.clone()
+        };
+        Ok(serde_json::to_vec(&unsigned)?)
+    }
+
+    pub fn sign(&mut self, key: &Ed25519KeyPair) -> Result<()> {
+        self.signature = Some(PackSignature::sign(key, &self.signed_bytes()?));
+        Ok(())
+    }
+
+    /// Check the signature, if any, against base64 `trusted_keys`
+    pub fn check_signature(&self, trusted_keys: &[String]) -> Result<SignatureCheck> {
+        Ok(match &self.signature {
+            Some(signature) => signature.check(&self.signed_bytes()?, trusted_keys),
+            None => SignatureCheck::Unsigned,
+        })
+    }
+
+    /// Apply the signature policy to the pack, as [`enforce`] does
+    pub fn verify(&self, config: &RulePackConfig) -> Result<Option<String>> {
+        if config.policy() == SignaturePolicy::Off {
+            return Ok(None);
+        }
+        let check = self.check_signature(&config.trusted_keys)?;
+        enforce(&format!("rule pack {}", self.manifest.name), check, config)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Parse and check a pack: a supported version, valid rules, no name
+    /// used twice and YARA file names that stay inside `rules_dir`
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let pack: Self = toml::from_str(contents)?;
+        if pack.manifest.version > RULE_PACK_VERSION {
+            bail!(
+                "Rule pack version {} is newer than supported version {}",
+                pack.manifest.version,
+                RULE_PACK_VERSION
+            );
+        }
+
+        let mut names = BTreeSet::new();
+        for rule in &pack.rules {
+            if !names.insert(rule.name.as_str()) {
+                bail!("Rule {} appears more than once", rule.name);
+            }
+            CompiledRule::new(rule.clone())?;
+        }
+        let mut files = BTreeSet::new();
+        for file in &pack.yara {
+            let path = Path::new(&file.name);
+            if path.file_name() != Some(path.as_os_str()) || !scanner::is_yara_file(path) {
+                bail!(
+                    "YARA file name {:?} must be a plain .yar or .yara file name",
+                    file.name
+                );
+            }
+            if !files.insert(file.name.as_str()) {
+                bail!("YARA file {} appears more than once", file.name);
+            }
+        }
+        Ok(pack)
+    }
+
+    /// Compare the pack with `config`, the YARA files it points at and the
+    /// built-in rules
+    pub fn plan(&self, config: &DaemonConfig) -> Result<ImportPlan> {
+        let engine = RuleEngine::new();
+        let builtin = engine.rule_names();
+        let mut plan = ImportPlan {
+            added: Vec::new(),
+            unchanged: Vec::new(),
+            conflicts: Vec::new(),
+            responding: Vec::new(),
+            rules: Vec::new(),
+            files: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        for rule in &self.rules {
+            if builtin.contains(&rule.name.as_str()) {
+                plan.conflicts
+                    .push(Conflict::BuiltinRule(rule.name.clone()));
+                continue;
+            }
+            match config.rules.iter().find(|r| r.name == rule.name) {
+                Some(existing) if existing == rule => {
+                    plan.unchanged.push(format!("rule {}", rule.name));
+                    continue;
+                }
+                Some(_) => plan.conflicts.push(Conflict::Rule(rule.name.clone())),
+                None => plan.added.push(format!("rule {}", rule.name)),
+            }
+            match rule.actions.respond {
+                Some(RuleResponse::KillProcess) => plan
+                    .responding
+                    .push(format!("rule {} kills processes", rule.name)),
+                Some(RuleResponse::IsolateHost { .. }) => plan
+                    .responding
+                    .push(format!("rule {} isolates the host", rule.name)),
+                None => {}
+            }
+            plan.rules.push(rule.clone());
+        }
+
+        if self.yara.is_empty() {
+            return Ok(plan);
+        }
+        let dir = config
+            .yara
+            .rules_dir
+            .as_ref()
+            .context("The pack has YARA files; set `[yara] rules_dir` to install them")?;
+        let installed = scanner::read_sources(dir)?;
+
+        // Where each YARA rule the pack leaves in place is declared
+        let mut declared: BTreeMap<String, String> = scanner::builtin_rules()
+            .into_iter()
+            .map(|rule| (rule, "the built-in rules".to_string()))
+            .collect();
+        let remote = yara_sources::load_cache(&config.yara);
+        let remote = yara_sources::all_sources(
+            &YaraConfig {
+                rules_dir: None,
+                ..config.yara.clone()
+            },
+            &remote,
+        )?;
+        let kept = installed
+            .iter()
+            .filter(|(name, _)| !self.yara.iter().any(|file| &file.name == name));
+        for (name, source) in kept.chain(&remote) {
+            plan.sources.push((name.clone(), source.clone()));
+            for rule in scanner::rule_names(source) {
+                declared.insert(rule, name.clone());
+            }
+        }
+
+        for file in &self.yara {
+            for rule in scanner::rule_names(&file.source) {
+                if let Some(declared_in) = declared.get(&rule) {
+                    plan.conflicts.push(Conflict::YaraRule {
+                        rule,
+                        declared_in: declared_in.clone(),
+                    });
+                }
+            }
+            match installed.iter().find(|(name, _)| name == &file.name) {
+                Some((_, source)) if source == &file.source => {
+                    plan.unchanged.push(format!("YARA file {}", file.name));
+                }
+                Some(_) => plan.conflicts.push(Conflict::YaraFile(file.name.clone())),
+                None => plan.added.push(format!("YARA file {}", file.name)),
+            }
+            plan.files.push((dir.join(&file.name), file.source.clone()));
+            plan.sources.push((file.name.clone(), file.source.clone()));
+        }
+        Ok(plan)
+    }
+}
+
+impl ImportPlan {
+    /// Write the YARA files and then the config. Fails without writing
+    /// anything on conflicts, except those `replace` allows overwriting,
+    /// on rules that respond on the host unless `allow_responses`, or when
+    /// the resulting YARA rules do not compile.
+    pub fn apply(
+        &self,
+        config_path: &Path,
+        replace: bool,
+        allow_responses: bool,
+    ) -> Result<Vec<(PathBuf, ApplyOutcome)>> {
+        let blocking: Vec<String> = self
+            .conflicts
+            .iter()
+            .filter(|c| !(replace && c.replaceable()))
+            .map(|c| c.to_string())
+            .collect();
+        if !blocking.is_empty() {
+            bail!("{} conflict(s): {}", blocking.len(), blocking.join("; "));
+        }
+        if !allow_responses && !self.responding.is_empty() {
+            bail!(
+                "{}; pass --allow-responses to import rules that act on the host",
+                self.responding.join("; ")
+            );
+        }
+        if !self.files.is_empty() {
+            YaraScanner::with_sources(&self.sources)
+                .context("The pack's YARA files do not compile with the installed ones")?;
+        }
+
+        let mut outcomes = Vec::new();
+        for (path, contents) in &self.files {
+            outcomes.push((path.clone(), write_if_changed(path, contents)?));
+        }
+        // Written only when the rules change, so an import of YARA files
+        // alone leaves the config file as it is
+        if !self.rules.is_empty() {
+            let config = self.edit_config(config_path)?;
+            outcomes.push((
+                config_path.to_path_buf(),
+                write_if_changed(config_path, &config)?,
+            ));
+        }
+        Ok(outcomes)
+    }
+
+    /// The config file with the pack's rules replacing the `[[rules]]`
+    /// tables of the same name or added after them. Everything else,
+    /// comments and layout included, stays as it is.
+    fn edit_config(&self, config_path: &Path) -> Result<String> {
+        let contents = match std::fs::read_to_string(config_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", config_path.display()))
+            }
+        };
+        let mut document: DocumentMut = contents
+            .parse()
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        // Rules written inline, e.g. the `rules = []` of a generated
+        // config, become tables
+        let mut tables = match document.remove("rules") {
+            None => ArrayOfTables::new(),
+            Some(item) if item.as_array().is_some_and(|a| a.is_empty()) => ArrayOfTables::new(),
+            Some(item) => item.into_array_of_tables().map_err(|_| {
+                anyhow!(
+                    "The rules in {} are not a list of tables",
+                    config_path.display()
+                )
+            })?,
+        };
+        for rule in &self.rules {
+            // Nested tables inline, so each rule stays one [[rules]] table
+            let mut table = Table::new();
+            for (key, item) in toml::to_string_pretty(rule)?.parse::<DocumentMut>()?.iter() {
+                if let Ok(value) = item.clone().into_value() {
+                    table.insert(key, Item::Value(value));
+                }
+            }
+            let existing = tables
+                .iter_mut()
+                .find(|t| t.get("name").and_then(Item::as_str) == Some(rule.name.as_str()));
+            match existing {
+                Some(existing) => *existing = table,
+                None => tables.push(table),
+            }
+        }
+        document.insert("rules", Item::ArrayOfTables(tables));
+        Ok(document.to_string())
+    }
+}
+
+/// `export-rules`: print the configured rules and YARA files as a pack
+pub fn export_command(name: &str, description: Option<String>, sign: Option<&Path>) -> Result<()> {
+    let mut pack = RulePack::export(&DaemonConfig::load()?, name, &platform::hostname())?;
+    pack.manifest.description = description.unwrap_or_default();
+    if let Some(key) = sign {
+        pack.sign(&load_signing_key(key)?)?;
+    }
+    print!("{}", pack.to_toml()?);
+    Ok(())
+}
+
+/// `generate-rule-key <file>`: write a new signing key, readable by the
+/// owner only, and print its public key for `trusted_keys`
+pub fn generate_key_command(file: &Path) -> Result<()> {
+    if file.exists() {
+        bail!("{} already exists", file.display());
+    }
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| anyhow!("Failed to generate a key"))?;
+    write_private(file, format!("{}\n", STANDARD.encode(pkcs8.as_ref())))?;
+
+    let key = load_signing_key(file)?;
+    println!("{}", STANDARD.encode(key.public_key()));
+    eprintln!(
+        "Wrote {}; add the public key above to `[rule_packs] trusted_keys` on the agents",
+        file.display()
+    );
+    Ok(())
+}
+
+/// `sign-yara <file> --key <key>`: write `<file>.sig` for serving the file
+/// as a remote YARA source
+pub fn sign_file_command(file: &Path, key: &Path) -> Result<()> {
+    let contents =
+        std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let signature = PackSignature::sign(&load_signing_key(key)?, &contents);
+    let mut path = file.as_os_str().to_owned();
+    path.push(".sig");
+    let path = PathBuf::from(path);
+    std::fs::write(&path, toml::to_string(&signature)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    eprintln!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// A signing key written by `generate-rule-key`: base64 PKCS#8
+fn load_signing_key(file: &Path) -> Result<Ed25519KeyPair> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let pkcs8 = STANDARD
+        .decode(contents.trim())
+        .with_context(|| format!("{} is not a base64 key", file.display()))?;
+    Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|e| anyhow!("{} is not an Ed25519 key: {}", file.display(), e))
+}
+
+/// `import-rules <pack>`: add a pack's rules to the config and its YARA
+/// files to `rules_dir`, showing what changes and what conflicts
+pub fn import_command(
+    file: &Path,
+    dry_run: bool,
+    replace: bool,
+    allow_responses: bool,
+) -> Result<()> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let pack = RulePack::from_toml(&contents)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+    let config_path = DaemonConfig::path();
+    let config = if config_path.exists() {
+        DaemonConfig::from_file(&config_path)?
+    } else {
+        DaemonConfig::default()
+    };
+    if let Some(warning) = pack.verify(&config.rule_packs)? {
+        eprintln!("warning: {}", warning);
+    }
+    let plan = pack.plan(&config)?;
+
+    println!(
+        "Rule pack {}: {} rule(s), {} YARA file(s)",
+        pack.manifest.name,
+        pack.rules.len(),
+        pack.yara.len()
+    );
+    for entry in &plan.added {
+        println!("+ {}", entry);
+    }
+    for entry in &plan.unchanged {
+        println!("= {}", entry);
+    }
+    for conflict in &plan.conflicts {
+        let marker = if replace && conflict.replaceable() {
+            "~"
+        } else {
+            "!"
+        };
+        println!("{} {}", marker, conflict);
+    }
+    for entry in &plan.responding {
+        println!("{} {}", if allow_responses { "~" } else { "!" }, entry);
+    }
+
+    if dry_run {
+        println!("Dry run: nothing written");
+        return Ok(());
+    }
+    for (path, outcome) in plan.apply(&config_path, replace, allow_responses)? {
+        println!("{:?}: {}", outcome, path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, tag: &str) -> RuleDefinition {
+        toml::from_str(&format!(
+            r#"name = "{}"
+            event_type = "file_integrity"
+            fields = [{{ field = "path", starts_with = "/srv/" }}]
+            actions = {{ add_tags = ["{}"] }}"#,
+            name, tag
+        ))
+        .unwrap()
+    }
+
+    fn yara(name: &str, rule: &str) -> YaraFile {
+        YaraFile {
+            name: name.to_string(),
+            source: format!("rule {} {{ condition: filesize == 0 }}\n", rule),
+        }
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let dir = std::env::temp_dir().join(format!("guardian-rule-pack-{}", uuid::Uuid::new_v4()));
+        let rules_dir = dir.join("yara");
+        std::fs::create_dir_all(&rules_dir).unwrap();
+        std::fs::write(rules_dir.join("empty.yar"), &yara("x", "empty_file").source).unwrap();
+        std::fs::write(rules_dir.join("notes.txt"), "not a rule").unwrap();
+
+        let mut source = DaemonConfig::default();
+        source.rules.push(rule("srv_change", "srv"));
+        source.yara.rules_dir = Some(rules_dir.clone());
+        let exported = RulePack::export(&source, "web", "web-1").unwrap();
+        assert_eq!(exported.yara, [yara("empty.yar", "empty_file")]);
+        let pack = RulePack::from_toml(&exported.to_toml().unwrap()).unwrap();
+        assert_eq!(pack, exported);
+
+        let mut target = DaemonConfig::default();
+        target.yara.rules_dir = Some(dir.join("target-yara"));
+        let config_path = dir.join("daemon.toml");
+        std::fs::write(&config_path, toml::to_string_pretty(&target).unwrap()).unwrap();
+        let plan = pack.plan(&target).unwrap();
+        assert_eq!(plan.added, ["rule srv_change", "YARA file empty.yar"]);
+        assert!(plan.conflicts.is_empty());
+        let outcomes = plan.apply(&config_path, false, false).unwrap();
+        assert_eq!(outcomes[0].1, ApplyOutcome::Created);
+        assert_eq!(outcomes[1].1, ApplyOutcome::Updated);
+
+        let installed = DaemonConfig::from_file(&config_path).unwrap();
+        assert_eq!(installed.rules, source.rules);
+        let plan = pack.plan(&installed).unwrap();
+        assert!(plan.added.is_empty() && plan.conflicts.is_empty());
+        assert!(plan
+            .apply(&config_path, false, false)
+            .unwrap()
+            .iter()
+            .all(|(_, o)| *o == ApplyOutcome::Unchanged));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let dir = std::env::temp_dir().join(format!("guardian-rule-pack-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.yar"), &yara("a.yar", "dropper").source).unwrap();
+        std::fs::write(dir.join("b.yar"), &yara("b.yar", "miner").source).unwrap();
+
+        let mut config = DaemonConfig::default();
+        config.rules.push(rule("srv_change", "srv"));
+        config.yara.rules_dir = Some(dir.clone());
+
+        let pack = RulePack {
+            manifest: RulePack::export(&DaemonConfig::default(), "p", "")
+                .unwrap()
+                .manifest,
+            signature: None,
+            rules: vec![
+                rule("srv_change", "web"),
+                rule("critical_file_modification", "x"),
+            ],
+            yara: vec![
+                // Replaces a.yar, so its dropper rule does not clash
+                yara("a.yar", "dropper"),
+                yara("c.yar", "miner"),
+                yara("d.yar", "eicar_test_file"),
+            ],
+        };
+        let plan = pack.plan(&config).unwrap();
+        assert_eq!(plan.added, ["YARA file c.yar", "YARA file d.yar"]);
+        assert_eq!(plan.unchanged, ["YARA file a.yar"]);
+        assert_eq!(
+            plan.conflicts,
+            [
+                Conflict::Rule("srv_change".to_string()),
+                Conflict::BuiltinRule("critical_file_modification".to_string()),
+                Conflict::YaraRule {
+                    rule: "miner".to_string(),
+                    declared_in: "b.yar".to_string()
+                },
+                Conflict::YaraRule {
+                    rule: "eicar_test_file".to_string(),
+                    declared_in: "the built-in rules".to_string()
+                },
+            ]
+        );
+        // Rule name clashes cannot be replaced, and nothing is written
+        let config_path = dir.join("daemon.toml");
+        assert!(plan.apply(&config_path, true, false).is_err());
+        assert!(!config_path.exists());
+
+        // A changed rule alone only needs --replace
+        let pack = RulePack {
+            rules: vec![rule("srv_change", "web")],
+            yara: Vec::new(),
+            ..pack
+        };
+        let plan = pack.plan(&config).unwrap();
+        assert!(plan.apply(&config_path, false, false).is_err());
+        plan.apply(&config_path, true, false).unwrap();
+        let installed = DaemonConfig::from_file(&config_path).unwrap();
+        assert_eq!(installed.rules, pack.rules);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_edits_only_rules() {
+        let dir = std::env::temp_dir().join(format!("guardian-rule-pack-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("daemon.toml");
+        std::fs::write(
+            &config_path,
+            "# Managed by hand\n[queue]\ncapacity = 5000 # busy host\n\n\
+             [[rules]]\nname = \"srv_change\" # old\nevent_type = \"file_integrity\"\n\n\
+             [[rules]]\nname = \"kept\"\ntags = [\"x\"]\n",
+        )
+        .unwrap();
+        let config = DaemonConfig::from_file(&config_path).unwrap();
+
+        let kill: RuleDefinition = toml::from_str(
+            r#"name = "kill_miner"
+            event_type = "process_monitor"
+            fields = [{ field = "name", equals = "xmrig" }]
+            actions = { respond = { action = "kill_process" } }"#,
+        )
+        .unwrap();
+        let mut pack = RulePack::export(&DaemonConfig::default(), "p", "").unwrap();
+        pack.rules = vec![rule("srv_change", "web"), kill.clone()];
+        let plan = pack.plan(&config).unwrap();
+        assert_eq!(plan.responding, ["rule kill_miner kills processes"]);
+        // Rules that act on the host need the operator's say-so
+        assert!(plan.apply(&config_path, true, false).is_err());
+        plan.apply(&config_path, true, true).unwrap();
+
+        let written = std::fs::read_to_string(&config_path).unwrap();
+        assert!(written.starts_with("# Managed by hand\n[queue]\ncapacity = 5000 # busy host\n"));
+        let installed = DaemonConfig::from_file(&config_path).unwrap();
+        let names: Vec<&str> = installed.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["srv_change", "kept", "kill_miner"]);
+        assert_eq!(installed.rules[0], pack.rules[0]);
+        assert_eq!(installed.queue.capacity, 5000);
+
+        // Rules already installed as they are need no opt-in again
+        assert!(pack.plan(&installed).unwrap().responding.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_bad_packs() {
+        let manifest = format!(
+            "[manifest]\nversion = {}\nname = \"p\"\ncreated = \"2026-01-01T00:00:00Z\"\n",
+            RULE_PACK_VERSION
+        );
+        assert!(RulePack::from_toml(&manifest).is_ok());
+
+        let newer = manifest.replace(
+            &format!("version = {}", RULE_PACK_VERSION),
+            &format!("version = {}", RULE_PACK_VERSION + 1),
+        );
+        assert!(RulePack::from_toml(&newer).is_err());
+
+        for name in ["../cron.yar", "/etc/a.yar", "a.txt"] {
+            let escape = format!("{}[[yara]]\nname = \"{}\"\nsource = \"\"\n", manifest, name);
+            assert!(RulePack::from_toml(&escape).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_signed_packs() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public = STANDARD.encode(key.public_key());
+
+        let mut config = DaemonConfig::default();
+        config.rules.push(rule("srv_change", "srv"));
+        let mut pack = RulePack::export(&config, "web", "web-1").unwrap();
+        let mut policy = RulePackConfig {
+            signatures: None,
+            trusted_keys: vec![public.clone()],
+        };
+        // Trusted keys make signatures required by default
+        assert!(pack.verify(&policy).is_err());
+
+        // The signature survives the trip through TOML
+        pack.sign(&key).unwrap();
+        let pack = RulePack::from_toml(&pack.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            pack.check_signature(&policy.trusted_keys).unwrap(),
+            SignatureCheck::Trusted
+        );
+        assert_eq!(pack.verify(&policy).unwrap(), None);
+
+        let mut tampered = pack.clone();
+        tampered.rules[0].actions.suppress = true;
+        assert_eq!(
+            tampered.check_signature(&policy.trusted_keys).unwrap(),
+            SignatureCheck::Invalid
+        );
+        assert!(tampered.verify(&policy).is_err());
+
+        policy.trusted_keys.clear();
+        assert_eq!(
+            pack.check_signature(&policy.trusted_keys).unwrap(),
+            SignatureCheck::Untrusted(public)
+        );
+        policy.signatures = Some(SignaturePolicy::Require);
+        assert!(pack.verify(&policy).is_err());
+
+        // Without trusted keys the default only warns, and says why
+        policy.signatures = None;
+        let warning = pack.verify(&policy).unwrap().unwrap();
+        assert!(warning.contains("untrusted key"));
+        // Warn still refuses a signature that does not match; Off does not
+        // check at all
+        policy.signatures = Some(SignaturePolicy::Warn);
+        assert!(tampered.verify(&policy).is_err());
+        policy.signatures = Some(SignaturePolicy::Off);
+        assert_eq!(tampered.verify(&policy).unwrap(), None);
+    }
+}
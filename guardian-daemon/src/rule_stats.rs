@@ -0,0 +1,121 @@
+//! Periodic summary of the rules that matched most, so noisy rules can be
+//! tuned before they bury the alerts that matter.
+
+use crate::config::RuleStatsConfig;
+use guardian_common::{EventType, LogEvent, RuleStatsReport, Severity};
+use std::time::Duration;
+
+/// Tag on noisy-rule summary events
+pub const RULE_STATS_TAG: &str = "rule_stats";
+
+pub struct NoisyRules {
+    interval: Duration,
+    top: usize,
+    /// Counts at the previous summary, so each covers only its own interval
+    last: Option<RuleStatsReport>,
+}
+
+impl NoisyRules {
+    /// `None` when summaries are disabled
+    pub fn new(config: &RuleStatsConfig) -> Option<Self> {
+        (config.enabled && config.interval_hours > 0).then(|| Self {
+            interval: Duration::from_secs(config.interval_hours * 3600),
+            top: config.top,
+            last: None,
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Summary of the rules that matched most since the previous summary,
+    /// given the engine's counts now; `None` when no rule matched
+    pub fn summary(&mut self, report: &RuleStatsReport) -> Option<LogEvent> {
+        let period = match &self.last {
+            Some(last) => report.since_report(last),
+            None => report.clone(),
+        };
+        self.last = Some(report.clone());
+
+        let noisiest = period.noisiest(self.top);
+        if noisiest.is_empty() {
+            return None;
+        }
+        let evaluated = period.rules.iter().map(|r| r.evaluated).max().unwrap_or(0);
+        let rules: Vec<String> = noisiest
+            .iter()
+            .map(|r| format!("{} {} ({:.1}%)", r.rule, r.matched, r.match_rate() * 100.0))
+            .collect();
+        let hours = (period.until - period.since).num_hours();
+        let event = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "guardian-daemon".to_string(),
+                level: "info".to_string(),
+                message: format!(
+                    "Noisiest rules over {} events in the last {}h: {}",
+                    evaluated,
+                    hours,
+                    rules.join(", ")
+                ),
+            },
+            period.hostname.clone(),
+        );
+        Some(event.with_tag(RULE_STATS_TAG))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use guardian_common::RuleStats;
+
+    fn report(counts: &[(&str, u64)], evaluated: u64) -> RuleStatsReport {
+        let now = Utc::now();
+        RuleStatsReport {
+            hostname: "web-1".to_string(),
+            since: now - chrono::Duration::hours(24),
+            until: now,
+            rules: counts
+                .iter()
+                .map(|(rule, matched)| RuleStats {
+                    rule: rule.to_string(),
+                    evaluated,
+                    matched: *matched,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_summary_covers_each_interval() {
+        let disabled = RuleStatsConfig {
+            enabled: false,
+            ..RuleStatsConfig::default()
+        };
+        assert!(NoisyRules::new(&disabled).is_none());
+
+        let mut noisy = NoisyRules::new(&RuleStatsConfig::default()).unwrap();
+        assert_eq!(noisy.interval(), Duration::from_secs(24 * 3600));
+
+        let first = noisy
+            .summary(&report(&[("high_cpu_usage", 40), ("canary", 0)], 1000))
+            .unwrap();
+        assert!(first.tags.contains(&RULE_STATS_TAG.to_string()));
+        match &first.event_type {
+            EventType::SystemLog { message, .. } => {
+                assert!(message.contains("over 1000 events"));
+                assert!(message.contains("high_cpu_usage 40 (4.0%)"));
+                assert!(!message.contains("canary"));
+            }
+            other => panic!("expected a system log, got {:?}", other),
+        }
+
+        // Nothing matched since the first summary
+        assert!(noisy
+            .summary(&report(&[("high_cpu_usage", 40), ("canary", 0)], 1500))
+            .is_none());
+    }
+}
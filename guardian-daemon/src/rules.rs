@@ -1,29 +1,189 @@
-use guardian_common::{EventType, FileOperation, LogEvent, Severity};
+use crate::arp;
+use crate::authorized_keys;
+use crate::canary;
+#[cfg(target_os = "linux")]
+use crate::hidden;
+use crate::honeypot;
+use crate::platform;
+use crate::resolver;
+#[cfg(unix)]
+use crate::suid;
+use chrono::{DateTime, Utc};
+use guardian_common::{
+    AuthOutcome, CompiledRule, EventType, ExecutableFormat, ExecutableInfo, FileOperation,
+    GuardianError, LogEvent, RuleActions, RuleDefinition, RuleResponse, RuleSet, RuleStats,
+    RuleStatsReport, Severity,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::warn;
+
+/// Interactive shells and interpreters commonly used for post-exploitation
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh", "csh", "tcsh", "fish"];
+
+/// Processes serving web traffic
+const WEB_SERVERS: &[&str] = &[
+    "nginx", "apache2", "httpd", "lighttpd", "caddy", "php-fpm", "tomcat", "java",
+];
 
 /// Simple rule engine for evaluating events
 pub struct RuleEngine {
     rules: Vec<Rule>,
+    /// Rules written as data, evaluated together
+    definitions: RuleSet,
+    /// When the hit counts started
+    started: DateTime<Utc>,
 }
 
 /// A rule that can be evaluated against a LogEvent
 struct Rule {
     name: String,
-    matcher: Box<dyn Fn(&LogEvent) -> bool + Send + Sync>,
+    /// How serious a match is; the most severe match names the event
+    severity: Severity,
+    matcher: Matcher,
+    actions: RuleActions,
+    evaluated: AtomicU64,
+    matched: AtomicU64,
+}
+
+/// How a rule decides whether an event matches
+enum Matcher {
+    Code(Box<dyn Fn(&LogEvent) -> bool + Send + Sync>),
+    /// Position in `definitions`
+    Definition(usize),
+}
+
+/// What the rules made of one event
+#[derive(Debug)]
+pub struct Verdict {
+    /// The event with its rules, severity and tags applied
+    pub event: LogEvent,
+    /// A matching rule asked for the event to be dropped
+    pub suppressed: bool,
+    /// Responses the matching rules asked for, with the rule that asked
+    pub responses: Vec<(String, RuleResponse)>,
 }
 
 impl RuleEngine {
     /// Create a new rule engine with default rules
     pub fn new() -> Self {
-        let mut engine = Self { rules: Vec::new() };
+        let mut engine = Self {
+            rules: Vec::new(),
+            definitions: RuleSet::default(),
+            started: Utc::now(),
+        };
         engine.load_default_rules();
         engine
     }
 
+    /// Default rules followed by `definitions` from the config; invalid
+    /// definitions are skipped with a warning
+    pub fn with_definitions(definitions: &[RuleDefinition]) -> Self {
+        let mut engine = Self::new();
+        for definition in definitions {
+            if let Err(e) = engine.add_definition(definition.clone()) {
+                warn!("Skipping rule: {}", e);
+            }
+        }
+        engine
+    }
+
+    /// Take over the hit counts of the rules of `previous` with the same
+    /// names, and when it started counting, so a reload keeps them
+    pub fn keep_stats(&mut self, previous: &RuleEngine) {
+        self.started = previous.started;
+        for rule in &self.rules {
+            if let Some(old) = previous.rules.iter().find(|old| old.name == rule.name) {
+                rule.evaluated
+                    .store(old.evaluated.load(Ordering::Relaxed), Ordering::Relaxed);
+                rule.matched
+                    .store(old.matched.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+        }
+    }
+
     /// Load default security rules
     fn load_default_rules(&mut self) {
-        // Rule 1: Critical file modifications
+        // Canary file touched; nothing legitimate opens one, so this
+        // heads every other match
+        self.add_rule(
+            canary::CANARY_RULE,
+            Severity::Critical,
+            Box::new(|event| event.tags.iter().any(|tag| tag == canary::CANARY_TAG)),
+        );
+
+        // Connection to a honeypot port
+        self.add_rule(
+            honeypot::HONEYPOT_RULE,
+            Severity::High,
+            Box::new(|event| event.tags.iter().any(|tag| tag == honeypot::HONEYPOT_TAG)),
+        );
+
+        // A neighbour's MAC address changed
+        self.add_rule(
+            arp::ARP_RULE,
+            Severity::High,
+            Box::new(|event| event.tags.iter().any(|tag| tag == arp::ARP_TAG)),
+        );
+
+        // Process hidden from the /proc listing
+        #[cfg(target_os = "linux")]
+        self.add_rule(
+            hidden::HIDDEN_RULE,
+            Severity::Critical,
+            Box::new(|event| event.tags.iter().any(|tag| tag == hidden::HIDDEN_TAG)),
+        );
+
+        // New or changed setuid or setgid binary
+        #[cfg(unix)]
+        self.add_rule(
+            suid::SUID_RULE,
+            Severity::High,
+            Box::new(|event| {
+                event.severity >= Severity::High
+                    && event.tags.iter().any(|tag| tag == suid::SUID_TAG)
+            }),
+        );
+
+        // Entry added to a hosts file
+        self.add_rule(
+            resolver::HOSTS_RULE,
+            Severity::High,
+            Box::new(|event| {
+                event.severity >= Severity::High
+                    && event.tags.iter().any(|tag| tag == resolver::HOSTS_TAG)
+            }),
+        );
+
+        // Nameserver or other resolver setting added
+        self.add_rule(
+            resolver::RESOLVER_RULE,
+            Severity::High,
+            Box::new(|event| {
+                event.severity >= Severity::High
+                    && event
+                        .tags
+                        .iter()
+                        .any(|tag| tag == resolver::RESOLVER_CONFIG_TAG)
+            }),
+        );
+
+        // SSH key added to an authorized keys file
+        self.add_rule(
+            authorized_keys::KEYS_RULE,
+            Severity::High,
+            Box::new(|event| {
+                event.severity >= Severity::High
+                    && event
+                        .tags
+                        .iter()
+                        .any(|tag| tag == authorized_keys::KEYS_TAG)
+            }),
+        );
+
+        // Critical file modifications
         self.add_rule(
             "critical_file_modification",
+            Severity::High,
             Box::new(|event| {
                 matches!(
                     &event.event_type,
@@ -31,27 +191,48 @@ impl RuleEngine {
                         path,
                         operation: FileOperation::Modify | FileOperation::Delete,
                         ..
-                    } if path.contains("/etc/passwd")
-                        || path.contains("/etc/shadow")
-                        || path.contains("/etc/sudoers")
+                    } if platform::is_critical_file(path)
                 )
             }),
         );
 
-        // Rule 2: High severity threshold
+        // Unsigned Windows executable dropped where it runs at login
+        self.add_rule(
+            "unsigned_pe_in_startup",
+            Severity::High,
+            Box::new(|event| {
+                matches!(
+                    &event.event_type,
+                    EventType::FileIntegrity {
+                        path,
+                        operation: FileOperation::Create | FileOperation::Modify,
+                        executable: Some(ExecutableInfo {
+                            format: ExecutableFormat::Pe,
+                            signed: Some(false),
+                            ..
+                        }),
+                        ..
+                    } if platform::is_startup_path(path)
+                )
+            }),
+        );
+
+        // High severity threshold
         self.add_rule(
             "high_severity_alert",
+            Severity::Medium,
             Box::new(|event| event.severity >= Severity::High),
         );
 
-        // Rule 3: Suspicious network activity
+        // Suspicious network activity
         self.add_rule(
             "suspicious_network",
+            Severity::High,
             Box::new(|event| {
                 matches!(
                     &event.event_type,
                     EventType::NetworkSocket { remote_addr, .. }
-                    if remote_addr.as_ref().map_or(false, |addr| {
+                    if remote_addr.as_ref().is_some_and(|addr| {
                         // Flag connections to non-standard ports
                         addr.contains(":4444") || addr.contains(":31337")
                     })
@@ -59,9 +240,43 @@ impl RuleEngine {
             }),
         );
 
-        // Rule 4: Excessive CPU usage
+        // Shell spawned (directly or indirectly) by a web server
+        self.add_rule(
+            "web_server_spawned_shell",
+            Severity::Critical,
+            Box::new(|event| {
+                matches!(
+                    &event.event_type,
+                    EventType::ProcessMonitor { name, ancestry, .. }
+                    if SHELLS.contains(&name.as_str())
+                        && ancestry.iter().any(|a| {
+                            WEB_SERVERS.iter().any(|web| a.name.starts_with(web))
+                        })
+                )
+            }),
+        );
+
+        // Successful remote login as root
+        self.add_rule(
+            "remote_root_login",
+            Severity::High,
+            Box::new(|event| {
+                matches!(
+                    &event.event_type,
+                    EventType::AuthEvent {
+                        user,
+                        source_ip: Some(_),
+                        outcome: AuthOutcome::Success,
+                        ..
+                    } if user == "root"
+                )
+            }),
+        );
+
+        // Excessive CPU usage
         self.add_rule(
             "high_cpu_usage",
+            Severity::Low,
             Box::new(|event| {
                 matches!(
                     &event.event_type,
@@ -76,23 +291,129 @@ impl RuleEngine {
     pub fn add_rule(
         &mut self,
         name: impl Into<String>,
+        severity: Severity,
         matcher: Box<dyn Fn(&LogEvent) -> bool + Send + Sync>,
     ) {
         self.rules.push(Rule {
             name: name.into(),
-            matcher,
+            severity,
+            matcher: Matcher::Code(matcher),
+            actions: RuleActions::default(),
+            evaluated: AtomicU64::new(0),
+            matched: AtomicU64::new(0),
+        });
+    }
+
+    /// Add a rule written as data. It ranks as the severity it sets, or
+    /// medium when it leaves severity alone.
+    pub fn add_definition(&mut self, definition: RuleDefinition) -> Result<(), GuardianError> {
+        let name = definition.name.clone();
+        let actions = definition.actions.clone();
+        let position = self.definitions.push(CompiledRule::new(definition)?);
+        self.rules.push(Rule {
+            name,
+            severity: actions.set_severity.unwrap_or(Severity::Medium),
+            matcher: Matcher::Definition(position),
+            actions,
+            evaluated: AtomicU64::new(0),
+            matched: AtomicU64::new(0),
         });
+        Ok(())
+    }
+
+    /// Names of the loaded rules, in evaluation order
+    pub fn rule_names(&self) -> Vec<&str> {
+        self.rules.iter().map(|rule| rule.name.as_str()).collect()
+    }
+
+    /// How often each rule was evaluated and matched since the engine was
+    /// created
+    pub fn stats(&self, hostname: &str) -> RuleStatsReport {
+        RuleStatsReport {
+            hostname: hostname.to_string(),
+            since: self.started,
+            until: Utc::now(),
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| RuleStats {
+                    rule: rule.name.clone(),
+                    evaluated: rule.evaluated.load(Ordering::Relaxed),
+                    matched: rule.matched.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+
+    /// Rules matching `event`, most severe first; rules of equal severity
+    /// keep their evaluation order
+    fn matching(&self, event: &LogEvent) -> Vec<&Rule> {
+        let definitions = self.definitions.matching(event);
+        let mut matched: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                let matched = match &rule.matcher {
+                    Matcher::Code(matcher) => matcher(event),
+                    Matcher::Definition(position) => definitions[*position],
+                };
+                rule.evaluated.fetch_add(1, Ordering::Relaxed);
+                if matched {
+                    rule.matched.fetch_add(1, Ordering::Relaxed);
+                }
+                matched
+            })
+            .collect();
+        matched.sort_by_key(|rule| std::cmp::Reverse(rule.severity));
+        matched
     }
 
     /// Evaluate an event against all rules
-    /// Returns the name of the first matching rule, if any
-    pub fn evaluate(&self, event: &LogEvent) -> Option<String> {
-        for rule in &self.rules {
-            if (rule.matcher)(event) {
-                return Some(rule.name.clone());
+    /// Returns the names of every matching rule, most severe first
+    pub fn evaluate(&self, event: &LogEvent) -> Vec<String> {
+        self.matching(event)
+            .into_iter()
+            .map(|rule| rule.name.clone())
+            .collect()
+    }
+
+    /// Evaluate an event and apply the actions of the rules it matches. The
+    /// most severe rule that sets a severity decides it, tags of every rule
+    /// are added, and any rule suppressing the event drops it along with
+    /// the responses of the others.
+    pub fn apply(&self, event: LogEvent) -> Verdict {
+        let matched = self.matching(&event);
+        let mut event = if matched.is_empty() {
+            event
+        } else {
+            event.with_rules(matched.iter().map(|rule| rule.name.clone()))
+        };
+        if let Some(severity) = matched.iter().find_map(|rule| rule.actions.set_severity) {
+            event.severity = severity;
+        }
+        for tag in matched.iter().flat_map(|rule| &rule.actions.add_tags) {
+            if !event.tags.contains(tag) {
+                event.tags.push(tag.clone());
             }
         }
-        None
+
+        let suppressed = matched.iter().any(|rule| rule.actions.suppress);
+        let responses = if suppressed {
+            Vec::new()
+        } else {
+            matched
+                .iter()
+                .filter_map(|rule| {
+                    let response = rule.actions.respond.clone()?;
+                    Some((rule.name.clone(), response))
+                })
+                .collect()
+        };
+        Verdict {
+            event,
+            suppressed,
+            responses,
+        }
     }
 }
 
@@ -117,12 +438,145 @@ mod tests {
                 path: "/etc/passwd".to_string(),
                 operation: FileOperation::Modify,
                 hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
             },
             "localhost".to_string(),
         );
 
         let result = engine.evaluate(&event);
-        assert_eq!(result, Some("critical_file_modification".to_string()));
+        assert_eq!(
+            result,
+            ["critical_file_modification", "high_severity_alert"]
+        );
+    }
+
+    #[test]
+    fn test_canary_rule_comes_first() {
+        let engine = RuleEngine::new();
+        let event = crate::canary::event(
+            std::path::Path::new("/etc/passwd"),
+            FileOperation::Modify,
+            None,
+            "localhost",
+        );
+        let matched = engine.evaluate(&event);
+        assert_eq!(matched[0], canary::CANARY_RULE);
+        assert!(matched.contains(&"critical_file_modification".to_string()));
+    }
+
+    #[test]
+    fn test_honeypot_rule() {
+        let engine = RuleEngine::new();
+        let event = crate::honeypot::event(
+            "0.0.0.0:4444".parse().unwrap(),
+            "10.0.0.9:51000".parse().unwrap(),
+            "localhost",
+        );
+        // Headed by the honeypot rule, not the generic suspicious port or
+        // severity rules
+        assert_eq!(engine.evaluate(&event)[0], honeypot::HONEYPOT_RULE);
+    }
+
+    #[test]
+    fn test_unsigned_pe_in_startup_rule() {
+        let engine = RuleEngine::new();
+        let startup = if cfg!(windows) {
+            r"C:\Users\bob\AppData\Roaming\Microsoft\Windows\Start Menu\Programs\Startup\update.exe"
+        } else {
+            "/home/bob/.config/autostart/update.exe"
+        };
+        let dropped = |path: &str, signed| {
+            LogEvent::new(
+                Severity::Medium,
+                EventType::FileIntegrity {
+                    path: path.to_string(),
+                    operation: FileOperation::Create,
+                    hash: None,
+                    user: None,
+                    executable: Some(ExecutableInfo {
+                        format: ExecutableFormat::Pe,
+                        arch: "x86_64".to_string(),
+                        library: false,
+                        libraries: vec!["KERNEL32.dll".to_string()],
+                        sections: vec![".text".to_string()],
+                        signed: Some(signed),
+                        entropy: 6.1,
+                    }),
+                    process: None,
+                    diff: None,
+                },
+                "localhost".to_string(),
+            )
+        };
+
+        assert_eq!(
+            engine.evaluate(&dropped(startup, false)),
+            ["unsigned_pe_in_startup"]
+        );
+        assert!(engine.evaluate(&dropped(startup, true)).is_empty());
+        assert!(engine
+            .evaluate(&dropped("/tmp/update.exe", false))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_web_server_spawned_shell_rule() {
+        let engine = RuleEngine::new();
+        let ancestor = |pid, name: &str| guardian_common::ProcessAncestor {
+            pid,
+            name: name.to_string(),
+        };
+
+        let mut event = LogEvent::new(
+            Severity::Info,
+            EventType::ProcessMonitor {
+                pid: 4242,
+                name: "bash".to_string(),
+                cpu_usage: 0.0,
+                memory_usage: 0,
+                ancestry: vec![ancestor(4200, "php-fpm8.2"), ancestor(1, "systemd")],
+                user: None,
+            },
+            "localhost".to_string(),
+        );
+        assert_eq!(engine.evaluate(&event), ["web_server_spawned_shell"]);
+
+        if let EventType::ProcessMonitor { ancestry, .. } = &mut event.event_type {
+            *ancestry = vec![ancestor(900, "sshd"), ancestor(1, "systemd")];
+        }
+        assert!(engine.evaluate(&event).is_empty());
+    }
+
+    #[test]
+    fn test_remote_root_login_rule() {
+        let engine = RuleEngine::new();
+        let login = |user: &str, source_ip: Option<&str>, outcome| {
+            LogEvent::new(
+                Severity::Info,
+                EventType::AuthEvent {
+                    user: user.to_string(),
+                    source_ip: source_ip.map(str::to_string),
+                    method: "publickey".to_string(),
+                    outcome,
+                    tty: None,
+                },
+                "localhost".to_string(),
+            )
+        };
+
+        assert_eq!(
+            engine.evaluate(&login("root", Some("203.0.113.5"), AuthOutcome::Success)),
+            ["remote_root_login"]
+        );
+        assert!(engine
+            .evaluate(&login("root", Some("203.0.113.5"), AuthOutcome::Failure))
+            .is_empty());
+        assert!(engine
+            .evaluate(&login("alice", Some("203.0.113.5"), AuthOutcome::Success))
+            .is_empty());
     }
 
     #[test]
@@ -140,6 +594,184 @@ mod tests {
         );
 
         let result = engine.evaluate(&event);
-        assert!(result.is_some());
+        assert_eq!(result, ["high_severity_alert"]);
+    }
+
+    #[test]
+    fn test_all_matches_most_severe_first() {
+        let engine = RuleEngine::new();
+        let event = LogEvent::new(
+            Severity::High,
+            EventType::ProcessMonitor {
+                pid: 4242,
+                name: "sh".to_string(),
+                cpu_usage: 97.0,
+                memory_usage: 0,
+                ancestry: vec![guardian_common::ProcessAncestor {
+                    pid: 4200,
+                    name: "nginx".to_string(),
+                }],
+                user: None,
+            },
+            "localhost".to_string(),
+        );
+
+        let matched = engine.evaluate(&event);
+        assert_eq!(
+            matched,
+            [
+                "web_server_spawned_shell",
+                "high_severity_alert",
+                "high_cpu_usage"
+            ]
+        );
+        let event = event.with_rules(matched);
+        assert_eq!(event.rule_name.as_deref(), Some("web_server_spawned_shell"));
+        assert_eq!(event.rules_matched.len(), 3);
+    }
+
+    #[test]
+    fn test_definition_actions_applied() {
+        let definitions: Vec<RuleDefinition> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "miner",
+                "event_type": "process_monitor",
+                "fields": [{"field": "name", "equals": "xmrig"}],
+                "actions": {
+                    "set_severity": "CRITICAL",
+                    "add_tags": ["cryptominer"],
+                    "respond": {"action": "kill_process"}
+                }
+            },
+            {
+                "name": "backup_touches_shadow",
+                "tags": ["backup"],
+                "fields": [{"field": "path", "equals": "/etc/shadow"}],
+                "actions": {"suppress": true}
+            },
+            {"name": "everything"}
+        ]))
+        .unwrap();
+        let engine = RuleEngine::with_definitions(&definitions);
+        assert!(engine.rule_names().contains(&"miner"));
+        assert!(!engine.rule_names().contains(&"everything"));
+
+        let miner = LogEvent::new(
+            Severity::Info,
+            EventType::ProcessMonitor {
+                pid: 4242,
+                name: "xmrig".to_string(),
+                cpu_usage: 12.0,
+                memory_usage: 0,
+                ancestry: Vec::new(),
+                user: None,
+            },
+            "localhost".to_string(),
+        );
+        let verdict = engine.apply(miner);
+        assert!(!verdict.suppressed);
+        assert_eq!(verdict.event.severity, Severity::Critical);
+        assert_eq!(verdict.event.rule_name.as_deref(), Some("miner"));
+        assert_eq!(verdict.event.tags, ["cryptominer"]);
+        assert_eq!(
+            verdict.responses,
+            [("miner".to_string(), RuleResponse::KillProcess)]
+        );
+
+        let backup = LogEvent::new(
+            Severity::Low,
+            EventType::FileIntegrity {
+                path: "/etc/shadow".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "localhost".to_string(),
+        )
+        .with_tag("backup");
+        let verdict = engine.apply(backup);
+        assert!(verdict.suppressed);
+        assert_eq!(
+            verdict.event.rules_matched,
+            ["critical_file_modification", "backup_touches_shadow"]
+        );
+        assert!(verdict.responses.is_empty());
+    }
+
+    #[test]
+    fn test_rule_stats_counted() {
+        let engine = RuleEngine::new();
+        let alert = |severity| {
+            LogEvent::new(
+                severity,
+                EventType::SystemLog {
+                    source: "kernel".to_string(),
+                    level: "info".to_string(),
+                    message: String::new(),
+                },
+                "localhost".to_string(),
+            )
+        };
+        engine.evaluate(&alert(Severity::High));
+        engine.evaluate(&alert(Severity::Low));
+        engine.evaluate(&alert(Severity::Critical));
+
+        let report = engine.stats("localhost");
+        assert_eq!(report.rules.len(), engine.rule_names().len());
+        assert!(report.rules.iter().all(|stats| stats.evaluated == 3));
+        let noisiest = report.noisiest(1);
+        assert_eq!(noisiest[0].rule, "high_severity_alert");
+        assert_eq!(noisiest[0].matched, 2);
+
+        // A reloaded engine goes on counting where this one stopped
+        let mut reloaded = RuleEngine::new();
+        reloaded.keep_stats(&engine);
+        let carried = reloaded.stats("localhost");
+        assert_eq!(carried.since, report.since);
+        assert_eq!(carried.noisiest(1)[0].matched, 2);
+    }
+
+    #[test]
+    fn test_definition_patterns() {
+        let definitions: Vec<RuleDefinition> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "script_in_tmp",
+                "event_type": "file_integrity",
+                "fields": [{"field": "path", "matches": "^/(tmp|var/tmp)/.+\\.sh$"}]
+            },
+            {
+                "name": "dotfile_in_tmp",
+                "fields": [{"field": "path", "matches": "^/tmp/\\."}]
+            },
+            {"name": "broken", "fields": [{"field": "path", "matches": "("}]}
+        ]))
+        .unwrap();
+        let engine = RuleEngine::with_definitions(&definitions);
+        assert!(!engine.rule_names().contains(&"broken"));
+
+        let file = |path: &str| {
+            LogEvent::new(
+                Severity::Info,
+                EventType::FileIntegrity {
+                    path: path.to_string(),
+                    operation: FileOperation::Create,
+                    hash: None,
+                    user: None,
+                    executable: None,
+                    process: None,
+                    diff: None,
+                },
+                "localhost".to_string(),
+            )
+        };
+        assert_eq!(engine.evaluate(&file("/var/tmp/x.sh")), ["script_in_tmp"]);
+        assert_eq!(
+            engine.evaluate(&file("/tmp/.x.sh")),
+            ["script_in_tmp", "dotfile_in_tmp"]
+        );
+        assert!(engine.evaluate(&file("/home/x.sh")).is_empty());
     }
 }
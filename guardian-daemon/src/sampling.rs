@@ -0,0 +1,77 @@
+use guardian_common::{LogEvent, Severity};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Drops a share of low-value events when the console asks the agent to back off
+#[derive(Debug)]
+pub struct Sampler {
+    rate: AtomicU32,
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Self {
+            rate: AtomicU32::new(1),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep one in `rate` Info/Low events; a rate of 0 or 1 keeps everything
+    pub fn set_rate(&self, rate: u32) {
+        self.rate.store(rate.max(1), Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    /// Whether an event survives sampling. Medium and above are never sampled.
+    pub fn keep(&self, event: &LogEvent) -> bool {
+        if event.severity >= Severity::Medium || event.rule_triggered {
+            return true;
+        }
+
+        let rate = self.rate() as u64;
+        rate <= 1
+            || self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(rate)
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::EventType;
+
+    fn event(severity: Severity) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: "sample".to_string(),
+            },
+            "localhost".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_sampling_keeps_one_in_rate() {
+        let sampler = Sampler::new();
+        sampler.set_rate(4);
+
+        let kept = (0..100)
+            .filter(|_| sampler.keep(&event(Severity::Info)))
+            .count();
+        assert_eq!(kept, 25);
+        assert!(sampler.keep(&event(Severity::High)));
+    }
+}
@@ -0,0 +1,379 @@
+//! On-demand scans the console requests over the control channel. Files
+//! under the path are run through the YARA rules on a blocking thread.
+//! Progress about once a second, every match and a closing summary are sent
+//! as events tagged `scan:<id>`, so the console can follow one scan among
+//! the rest and the database keeps a record of what each scan covered.
+
+use crate::canary;
+use crate::file_monitor;
+use crate::queue::{Closed, EventSender};
+use crate::scanner::YaraScanner;
+use guardian_common::{EventType, FileOperation, LogEvent, ScanOutcome, Severity};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Tag on every event an on-demand scan sends
+pub const SCAN_TAG: &str = "scan";
+
+/// Longest gap between progress events while a scan runs
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most files one scan covers; a larger tree has to be scanned in parts
+pub const MAX_SCAN_FILES: usize = 100_000;
+
+/// Tag identifying the events of one scan
+pub fn scan_tag(scan_id: &Uuid) -> String {
+    format!("{}:{}", SCAN_TAG, scan_id)
+}
+
+/// Files a scan will cover
+#[derive(Debug, Default)]
+pub struct Targets {
+    pub files: Vec<PathBuf>,
+    /// Directories that could not be read
+    pub dirs_skipped: u64,
+}
+
+/// Regular files at `path`, descending into directories when `recursive`;
+/// symlinked directories are not followed and canaries are left out. Fails
+/// past `MAX_SCAN_FILES`.
+pub fn targets(path: &Path, recursive: bool) -> std::io::Result<Targets> {
+    targets_up_to(path, recursive, MAX_SCAN_FILES)
+}
+
+fn targets_up_to(path: &Path, recursive: bool, max_files: usize) -> std::io::Result<Targets> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.is_file() {
+        let files = if canary::is_canary(path) {
+            Vec::new()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        return Ok(Targets {
+            files,
+            dirs_skipped: 0,
+        });
+    }
+
+    let mut targets = Targets::default();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Skipping {} during scan: {}", dir.display(), e);
+                targets.dirs_skipped += 1;
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_file() {
+                if canary::is_canary(&entry.path()) {
+                    continue;
+                }
+                if targets.files.len() == max_files {
+                    return Err(std::io::Error::other(format!(
+                        "{} holds more than {} files; scan a narrower path",
+                        path.display(),
+                        max_files
+                    )));
+                }
+                targets.files.push(entry.path());
+            } else if file_type.is_dir() && recursive {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Runs scans requested by the console
+pub struct OnDemandScanner {
+    scanner: Arc<YaraScanner>,
+    tx: EventSender,
+    hostname: String,
+}
+
+/// One scan in progress
+struct Scan<'a> {
+    id: Uuid,
+    path: &'a str,
+    requested_by: &'a str,
+    started: Instant,
+}
+
+impl OnDemandScanner {
+    pub fn new(scanner: Arc<YaraScanner>, tx: EventSender, hostname: String) -> Arc<Self> {
+        Arc::new(Self {
+            scanner,
+            tx,
+            hostname,
+        })
+    }
+
+    /// Scan everything under `path` in the background
+    pub fn start(self: &Arc<Self>, scan_id: Uuid, path: String, requested_by: String) {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let scan = Scan {
+                id: scan_id,
+                path: &path,
+                requested_by: &requested_by,
+                started: Instant::now(),
+            };
+            // A closed channel means the daemon is shutting down
+            let _ = this.run(&scan);
+        });
+    }
+
+    fn run(&self, scan: &Scan) -> Result<(), Closed> {
+        let targets = match targets(Path::new(scan.path), true) {
+            Ok(targets) => targets,
+            Err(e) => {
+                warn!("Scan of {} failed: {}", scan.path, e);
+                return self.send(self.summary(
+                    scan,
+                    ScanOutcome::Failed,
+                    &Targets::default(),
+                    0,
+                    Some(e.to_string()),
+                ));
+            }
+        };
+        info!(
+            "Scanning {} files under {} for {}",
+            targets.files.len(),
+            scan.path,
+            scan.requested_by
+        );
+        self.send(self.progress(scan, &targets, 0))?;
+
+        let mut matched = 0;
+        let mut reported = Instant::now();
+        for (scanned, file) in targets.files.iter().enumerate() {
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                reported = Instant::now();
+                self.send(self.progress(scan, &targets, scanned))?;
+            }
+            let rules = self.scanner.scan_file(&file.to_string_lossy());
+            if !rules.is_empty() {
+                matched += 1;
+                self.send(self.detection(scan, file, rules))?;
+            }
+        }
+
+        info!(
+            "Scan of {} finished: {} files, {} matched",
+            scan.path,
+            targets.files.len(),
+            matched
+        );
+        self.send(self.summary(scan, ScanOutcome::Completed, &targets, matched, None))
+    }
+
+    /// Progress after `scanned` of the target files
+    fn progress(&self, scan: &Scan, targets: &Targets, scanned: usize) -> LogEvent {
+        let total = targets.files.len();
+        let percent = if total == 0 {
+            100.0
+        } else {
+            scanned as f32 * 100.0 / total as f32
+        };
+        self.event(
+            scan,
+            Severity::Info,
+            EventType::ScanProgress {
+                scan_id: scan.id,
+                path: scan.path.to_string(),
+                files_scanned: scanned as u64,
+                files_total: Some(total as u64),
+                current_path: targets
+                    .files
+                    .get(scanned)
+                    .map(|file| file.to_string_lossy().to_string()),
+                percent: Some(percent),
+            },
+        )
+    }
+
+    fn summary(
+        &self,
+        scan: &Scan,
+        outcome: ScanOutcome,
+        targets: &Targets,
+        matched: u64,
+        error: Option<String>,
+    ) -> LogEvent {
+        let severity = match outcome {
+            ScanOutcome::Failed => Severity::Medium,
+            ScanOutcome::Completed if matched > 0 => Severity::High,
+            ScanOutcome::Completed => Severity::Info,
+        };
+        self.event(
+            scan,
+            severity,
+            EventType::ScanSummary {
+                scan_id: scan.id,
+                path: scan.path.to_string(),
+                requested_by: scan.requested_by.to_string(),
+                outcome,
+                files_scanned: targets.files.len() as u64,
+                files_matched: matched,
+                dirs_skipped: targets.dirs_skipped,
+                duration_ms: scan.started.elapsed().as_millis() as u64,
+                error,
+            },
+        )
+    }
+
+    /// Event for a file that matched `rules`, shaped like a file monitor hit
+    fn detection(&self, scan: &Scan, file: &Path, rules: Vec<String>) -> LogEvent {
+        let mut event = self.event(
+            scan,
+            Severity::Critical,
+            EventType::FileIntegrity {
+                path: file.to_string_lossy().to_string(),
+                operation: FileOperation::Scan,
+                hash: file_monitor::hash_file(file),
+                user: crate::users::file_owner(file),
+                executable: crate::executable::analyze(file),
+                process: None,
+                diff: None,
+            },
+        );
+        for rule in &rules {
+            event = event.with_tag(format!("yara:{}", rule));
+        }
+        event.with_rule(rules[0].clone())
+    }
+
+    fn event(&self, scan: &Scan, severity: Severity, event_type: EventType) -> LogEvent {
+        LogEvent::new(severity, event_type, self.hostname.clone())
+            .with_tag(SCAN_TAG)
+            .with_tag(scan_tag(&scan.id))
+    }
+
+    fn send(&self, event: LogEvent) -> Result<(), Closed> {
+        self.tx
+            .blocking_send(event)
+            .inspect_err(|_| warn!("Failed to emit scan event - channel closed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueueConfig;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_scan_reports_matches() {
+        let dir = std::env::temp_dir().join(format!("guardian-scan-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("clean.txt"), "nothing to see").unwrap();
+        std::fs::write(
+            dir.join("nested/eicar.com"),
+            r"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*",
+        )
+        .unwrap();
+
+        let (tx, mut rx) = crate::queue::channel(&QueueConfig::default());
+        let scanner = OnDemandScanner::new(
+            Arc::new(YaraScanner::new().unwrap()),
+            tx,
+            "web-1".to_string(),
+        );
+        let scan_id = Uuid::new_v4();
+        scanner.start(
+            scan_id,
+            dir.to_string_lossy().to_string(),
+            "alice".to_string(),
+        );
+
+        let mut events = Vec::new();
+        while events
+            .last()
+            .is_none_or(|event: &LogEvent| event.event_type.kind() != "scan_summary")
+        {
+            events.push(rx.recv().await.unwrap());
+        }
+        assert!(events
+            .iter()
+            .all(|event| event.tags.contains(&scan_tag(&scan_id))));
+
+        assert_eq!(events.len(), 3);
+        match &events[0].event_type {
+            EventType::ScanProgress {
+                files_scanned,
+                files_total,
+                percent,
+                ..
+            } => {
+                assert_eq!((*files_scanned, *files_total), (0, Some(2)));
+                assert_eq!(*percent, Some(0.0));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        match &events[1].event_type {
+            EventType::FileIntegrity {
+                path, operation, ..
+            } => {
+                assert!(path.ends_with("eicar.com"));
+                assert_eq!(*operation, FileOperation::Scan);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(events[1].rule_name.as_deref(), Some("eicar_test_file"));
+        match &events[2].event_type {
+            EventType::ScanSummary {
+                outcome,
+                files_scanned,
+                files_matched,
+                requested_by,
+                ..
+            } => {
+                assert_eq!(*outcome, ScanOutcome::Completed);
+                assert_eq!((*files_scanned, *files_matched), (2, 1));
+                assert_eq!(requested_by, "alice");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(events[2].severity, Severity::High);
+
+        // A missing path still ends with a summary
+        let missing = Uuid::new_v4();
+        scanner.start(
+            missing,
+            dir.join("gone").to_string_lossy().to_string(),
+            "alice".to_string(),
+        );
+        let event = rx.recv().await.unwrap();
+        match event.event_type {
+            EventType::ScanSummary { outcome, error, .. } => {
+                assert_eq!(outcome, ScanOutcome::Failed);
+                assert!(error.is_some());
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_targets_are_capped() {
+        let dir = std::env::temp_dir().join(format!("guardian-scan-cap-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a"), "a").unwrap();
+        std::fs::write(dir.join("nested/b"), "b").unwrap();
+
+        assert_eq!(targets_up_to(&dir, true, 2).unwrap().files.len(), 2);
+        assert_eq!(targets_up_to(&dir, false, 1).unwrap().files.len(), 1);
+        let error = targets_up_to(&dir, true, 1).unwrap_err();
+        assert!(error.to_string().contains("more than 1 files"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,111 @@
-use anyhow::{Context, Result};
+use crate::config::YaraConfig;
+use crate::yara_sources;
+use guardian_common::error::Result;
+use guardian_common::GuardianError;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use tracing::{error, info};
 use yara_x::{Compiler, Scanner};
 
+/// Names of the rules compiled into every scanner
+pub fn builtin_rules() -> Vec<String> {
+    BUILTIN_SOURCES
+        .iter()
+        .flat_map(|(_, source)| rule_names(source))
+        .collect()
+}
+
 pub struct YaraScanner {
-    rules: yara_x::Rules,
+    /// Swapped whole when remote sources update; scans in progress keep
+    /// the rules they started with
+    rules: RwLock<Arc<yara_x::Rules>>,
 }
 
 impl YaraScanner {
+    /// Scanner with only the built-in rules
+    #[cfg(test)]
     pub fn new() -> Result<Self> {
+        Self::with_sources(&[])
+    }
+
+    /// Scanner with the built-in rules, those under `rules_dir` and the last
+    /// good version of each remote source
+    pub fn load(config: &YaraConfig) -> Result<Self> {
+        let remote = yara_sources::load_cache(config);
+        let sources = yara_sources::all_sources(config, &remote).map_err(GuardianError::scanner)?;
+        Self::with_sources(&sources)
+    }
+
+    /// Scan with the rules of `other` from now on
+    pub fn replace(&self, other: YaraScanner) {
+        let rules = other.rules.into_inner().unwrap_or_else(|e| e.into_inner());
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = rules;
+    }
+
+    /// Scanner with the built-in rules and `sources`, given as (file name, source)
+    pub fn with_sources(sources: &[(String, String)]) -> Result<Self> {
         info!("Initializing YARA scanner with default rules...");
         let mut compiler = Compiler::new();
 
-        // Rule 1: EICAR Test File
-        compiler.add_source(
-            r#"
+        for (what, source) in BUILTIN_SOURCES {
+            compiler.add_source(*source).map_err(|e| {
+                GuardianError::scanner_context(format!("Failed to add {} rule", what), e)
+            })?;
+        }
+
+        for (file, source) in sources {
+            compiler.add_source(source.as_str()).map_err(|e| {
+                GuardianError::scanner_context(format!("Failed to add YARA rules from {}", file), e)
+            })?;
+        }
+
+        let rules = compiler.build(); // yara-x compiler.build() returns Rules directly, typically doesn't fail unless errors were emitted
+
+        // In yara-x 0.4+, build() might return Rules or Result<Rules, Error>
+        // Let's assume typical behavior or check if errors handles it.
+        // Actually, compiler.build() consumes compiler and returns Rules.
+        // Errors are collected in the compiler, but add_source returns &mut Compiler or Result?
+        // In yara-x, add_source returns &mut Compiler. It stores errors.
+        // Wait, I used ? on add_source. I need to verify API.
+
+        // Let's try to assume add_source returns result or we check errors.
+        // If API is different, the compiler will complain and I will fix it.
+        // Usually: wrapper pattern.
+
+        info!("YARA rules compiled successfully");
+        Ok(Self {
+            rules: RwLock::new(Arc::new(rules)),
+        })
+    }
+
+    /// Scan a file and return matching rule names
+    pub fn scan_file(&self, path: &str) -> Vec<String> {
+        let rules = self.rules.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut scanner = Scanner::new(&rules);
+        match scanner.scan_file(path) {
+            Ok(scan_results) => {
+                let mut results = Vec::new();
+                for rule in scan_results.matching_rules() {
+                    let rule_name = rule.identifier().to_string();
+                    info!("YARA Match: {} in file {}", rule_name, path);
+                    results.push(rule_name);
+                }
+                results
+            }
+            Err(e) => {
+                error!("Failed to scan file {}: {}", path, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Rules compiled into every scanner, each with what it is called in
+/// errors
+const BUILTIN_SOURCES: &[(&str, &str)] = &[
+    (
+        "EICAR",
+        r#"
             rule eicar_test_file {
                 meta:
                     description = "EICAR Test File"
@@ -24,11 +116,10 @@ impl YaraScanner {
                     $s1
             }
             "#,
-        ).context("Failed to add EICAR rule")?;
-
-        // Rule 2: Suspicious Shell Script
-        compiler.add_source(
-            r#"
+    ),
+    (
+        "shell script",
+        r#"
             rule suspicious_shell_script {
                 meta:
                     description = "Suspicious shell script indicators"
@@ -42,11 +133,10 @@ impl YaraScanner {
                     $s1 and ($s2 or $s3 or $s4)
             }
             "#,
-        ).context("Failed to add shell script rule")?;
-
-        // Rule 3: Potential Reverse Shell (Python)
-        compiler.add_source(
-            r#"
+    ),
+    (
+        "python",
+        r#"
             rule python_reverse_shell {
                 meta:
                     description = "Potential Python reverse shell"
@@ -60,43 +150,109 @@ impl YaraScanner {
                     all of them
             }
             "#,
-        ).context("Failed to add python rule")?;
+    ),
+];
 
-        let rules = compiler
-            .build(); // yara-x compiler.build() returns Rules directly, typically doesn't fail unless errors were emitted
+/// The `.yar` and `.yara` files directly in `dir` as (file name, source),
+/// sorted by name; a missing directory has none
+pub fn read_sources(dir: &Path) -> Result<Vec<(String, String)>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(read_error(dir, e)),
+    };
 
-        // In yara-x 0.4+, build() might return Rules or Result<Rules, Error>
-        // Let's assume typical behavior or check if errors handles it.
-        // Actually, compiler.build() consumes compiler and returns Rules. 
-        // Errors are collected in the compiler, but add_source returns &mut Compiler or Result?
-        // In yara-x, add_source returns &mut Compiler. It stores errors.
-        // Wait, I used ? on add_source. I need to verify API.
-        
-        // Let's try to assume add_source returns result or we check errors.
-        // If API is different, the compiler will complain and I will fix it.
-        // Usually: wrapper pattern.
-        
-        info!("YARA rules compiled successfully");
-        Ok(Self { rules })
+    let mut sources = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| read_error(dir, e))?.path();
+        let (Some(name), true) = (
+            path.file_name().and_then(|n| n.to_str()),
+            is_yara_file(&path),
+        ) else {
+            continue;
+        };
+        let source = std::fs::read_to_string(&path).map_err(|e| read_error(&path, e))?;
+        sources.push((name.to_string(), source));
     }
+    sources.sort();
+    Ok(sources)
+}
 
-    /// Scan a file and return matching rule names
-    pub fn scan_file(&self, path: &str) -> Vec<String> {
-        let mut scanner = Scanner::new(&self.rules);
-        match scanner.scan_file(path) {
-            Ok(scan_results) => {
-                let mut results = Vec::new();
-                for rule in scan_results.matching_rules() {
-                    let rule_name = rule.identifier().to_string();
-                    info!("YARA Match: {} in file {}", rule_name, path);
-                    results.push(rule_name);
-                }
-                results
-            }
-            Err(e) => {
-                error!("Failed to scan file {}: {}", path, e);
-                Vec::new()
-            }
+fn read_error(path: &Path, e: std::io::Error) -> GuardianError {
+    GuardianError::scanner_context(format!("Failed to read {}", path.display()), e)
+}
+
+/// Whether `path` has a YARA source extension
+pub fn is_yara_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yar" | "yara")
+    )
+}
+
+/// Identifiers of the rules a YARA source declares, found by the `rule`
+/// keyword at the start of a line, after any `private` or `global`
+pub fn rule_names(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.find(|w| !matches!(*w, "private" | "global")) else {
+            continue;
+        };
+        if keyword != "rule" {
+            continue;
+        }
+        let name: String = words
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        if !name.is_empty() {
+            names.push(name);
         }
     }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_names() {
+        let source = r#"
+            private rule helper { condition: true }
+            global private rule size_cap { condition: filesize < 10MB }
+            rule webshell : php {
+                strings: $s = "rule fake"
+                condition: helper and $s
+            }
+        "#;
+        assert_eq!(rule_names(source), ["helper", "size_cap", "webshell"]);
+        assert_eq!(
+            builtin_rules(),
+            [
+                "eicar_test_file",
+                "suspicious_shell_script",
+                "python_reverse_shell"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_with_sources() {
+        let sources = vec![(
+            "webshell.yar".to_string(),
+            "rule webshell { strings: $s = \"eval($_POST\" condition: $s }".to_string(),
+        )];
+        assert!(YaraScanner::with_sources(&sources).is_ok());
+
+        // A name already taken by a built-in rule does not compile
+        let clash = vec![(
+            "eicar.yar".to_string(),
+            "rule eicar_test_file { condition: true }".to_string(),
+        )];
+        assert!(YaraScanner::with_sources(&clash).is_err());
+    }
 }
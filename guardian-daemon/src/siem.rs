@@ -0,0 +1,261 @@
+//! Sends events to SIEMs as CEF or LEEF lines, over syslog or through a file
+//! their collectors tail, so ArcSight and QRadar parse them without custom
+//! rules. Lines queue while a receiver is down; the oldest are dropped first.
+
+use crate::config::{SiemConfig, SiemFormat, SiemTransport};
+use crate::outbox::Outbox;
+use anyhow::{bail, Result};
+use guardian_common::{LogEvent, Severity};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{info, warn};
+
+/// First and longest wait between connection attempts
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Time allowed for a TCP connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Syslog facility `local0`
+const FACILITY: u8 = 16;
+
+pub struct SiemSink {
+    config: SiemConfig,
+    outbox: Outbox<String>,
+}
+
+impl SiemSink {
+    /// Check the destination and start delivering
+    pub fn start(config: SiemConfig) -> Result<Arc<Self>> {
+        match config.transport {
+            SiemTransport::Udp | SiemTransport::Tcp if config.address.is_none() => {
+                bail!("SIEM {:?} transport needs an address", config.transport)
+            }
+            SiemTransport::File if config.path.is_none() => {
+                bail!("SIEM file transport needs a path")
+            }
+            _ => {}
+        }
+
+        let sink = Arc::new(Self {
+            outbox: Outbox::new("SIEM", config.buffer),
+            config,
+        });
+        let task = sink.clone();
+        tokio::spawn(async move { task.outbox.deliver(task.clone().run()).await });
+        Ok(sink)
+    }
+
+    /// Stop delivering, when a config reload replaces the sink
+    pub fn stop(&self) {
+        self.outbox.stop();
+    }
+
+    /// Queue an event if it meets the minimum severity
+    pub fn send(&self, event: &LogEvent) {
+        if event.severity < self.config.min_severity {
+            return;
+        }
+        let line = match self.config.format {
+            SiemFormat::Cef => event.to_cef(),
+            SiemFormat::Leef => event.to_leef(),
+        };
+        let line = match self.config.transport {
+            SiemTransport::File => line,
+            SiemTransport::Udp | SiemTransport::Tcp => {
+                format!("{}{}", syslog_header(event), line)
+            }
+        };
+        self.outbox.push(line);
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let result = match self.config.transport {
+                SiemTransport::Udp => self.deliver_udp().await,
+                SiemTransport::Tcp => self.deliver_tcp().await,
+                SiemTransport::File => self.deliver_file().await,
+            };
+            if let Err(e) = result {
+                warn!("SIEM {:?} output failed: {}", self.config.transport, e);
+            }
+            // A destination that worked for a while is retried promptly
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn deliver_udp(&self) -> Result<()> {
+        let address = self.config.address.as_deref().unwrap_or_default();
+        let bind = if address.starts_with('[') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(address).await?;
+        info!(
+            "Sending {:?} events to {} over UDP",
+            self.config.format, address
+        );
+
+        loop {
+            let line = self.outbox.next().await;
+            // A lost datagram is not retried, matching syslog over UDP
+            if let Err(e) = socket.send(line.as_bytes()).await {
+                warn!("Failed to send SIEM datagram to {}: {}", address, e);
+            }
+        }
+    }
+
+    async fn deliver_tcp(&self) -> Result<()> {
+        let address = self.config.address.as_deref().unwrap_or_default();
+        let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out connecting to {}", address))??;
+        info!(
+            "Sending {:?} events to {} over TCP",
+            self.config.format, address
+        );
+
+        loop {
+            let line = self.outbox.next().await;
+            if let Err(e) = stream.write_all(format!("{}\n", line).as_bytes()).await {
+                self.outbox.requeue(vec![line]);
+                return Err(e.into());
+            }
+        }
+    }
+
+    async fn deliver_file(&self) -> Result<()> {
+        let path = self.config.path.as_deref().unwrap_or(Path::new(""));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        info!(
+            "Writing {:?} events to {}",
+            self.config.format,
+            path.display()
+        );
+
+        loop {
+            let line = self.outbox.next().await;
+            let written = async {
+                file.write_all(format!("{}\n", line).as_bytes()).await?;
+                file.flush().await
+            }
+            .await;
+            if let Err(e) = written {
+                self.outbox.requeue(vec![line]);
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// RFC 3164 header, which ArcSight and QRadar syslog receivers expect
+fn syslog_header(event: &LogEvent) -> String {
+    let severity = match event.severity {
+        Severity::Critical => 2,
+        Severity::High => 3,
+        Severity::Medium => 4,
+        Severity::Low => 5,
+        Severity::Info => 6,
+    };
+    format!(
+        "<{}>{} {} ",
+        FACILITY * 8 + severity,
+        event.timestamp.format("%b %e %H:%M:%S"),
+        event.hostname
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::EventType;
+
+    fn login(severity: Severity) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "sshd".to_string(),
+                level: "info".to_string(),
+                message: "session opened".to_string(),
+            },
+            "web-1".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_udp_and_file_outputs() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp = SiemSink::start(SiemConfig {
+            format: SiemFormat::Cef,
+            transport: SiemTransport::Udp,
+            address: Some(receiver.local_addr().unwrap().to_string()),
+            path: None,
+            min_severity: Severity::Info,
+            buffer: 10,
+        })
+        .unwrap();
+        udp.send(&login(Severity::High));
+
+        let mut buf = [0u8; 2048];
+        let len = tokio::time::timeout(Duration::from_secs(5), receiver.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..len]).to_string();
+        assert!(datagram.starts_with("<131>"), "{}", datagram);
+        assert!(datagram.contains(" web-1 CEF:0|Guardian|Guardian|"));
+
+        let path = std::env::temp_dir().join(format!("guardian-siem-{}.log", std::process::id()));
+        let file = SiemSink::start(SiemConfig {
+            format: SiemFormat::Leef,
+            transport: SiemTransport::File,
+            address: None,
+            path: Some(path.clone()),
+            min_severity: Severity::Medium,
+            buffer: 10,
+        })
+        .unwrap();
+        file.send(&login(Severity::Info));
+        file.send(&login(Severity::Critical));
+
+        let mut contents = String::new();
+        for _ in 0..50 {
+            contents = std::fs::read_to_string(&path).unwrap_or_default();
+            if !contents.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.starts_with("LEEF:1.0|Guardian|Guardian|"));
+        assert!(contents.contains("\tsev=10\t"));
+
+        assert!(SiemSink::start(SiemConfig {
+            format: SiemFormat::Cef,
+            transport: SiemTransport::Tcp,
+            address: None,
+            path: None,
+            min_severity: Severity::Info,
+            buffer: 10,
+        })
+        .is_err());
+    }
+}
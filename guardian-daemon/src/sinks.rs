@@ -0,0 +1,218 @@
+//! The outputs that receive a copy of every emitted event besides the
+//! console: the collector, SIEMs, Elasticsearch, Splunk, Loki and Graylog.
+//! A config reload restarts only those whose settings changed, and only once
+//! every new one has started.
+
+use crate::config::DaemonConfig;
+use crate::elastic::ElasticSink;
+use crate::forward::Forwarder;
+use crate::gelf::GelfSink;
+use crate::loki::LokiSink;
+use crate::siem::SiemSink;
+use crate::splunk::SplunkSink;
+use anyhow::Result;
+use guardian_common::ipc::Frame;
+use guardian_common::{AgentHello, ControlCommand, LogEvent};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[derive(Default)]
+pub struct Sinks {
+    /// Ships a copy of everything to a remote collector
+    forwarder: Option<Arc<Forwarder>>,
+    /// CEF/LEEF copies for SIEMs
+    siem: Vec<Arc<SiemSink>>,
+    /// ECS documents for Elasticsearch
+    elastic: Option<Arc<ElasticSink>>,
+    /// HEC batches for Splunk
+    splunk: Option<Arc<SplunkSink>>,
+    /// Log lines for Grafana Loki
+    loki: Option<Arc<LokiSink>>,
+    /// GELF messages for Graylog
+    gelf: Option<Arc<GelfSink>>,
+    /// Control channel handed to the forwarder for the collector's commands
+    control: Option<mpsc::Sender<ControlCommand>>,
+}
+
+/// The running sink when its settings are unchanged, else a new one when
+/// enabled
+fn keep_or_start<C: PartialEq + Clone, S>(
+    running: &Option<Arc<S>>,
+    old: Option<&C>,
+    new: &C,
+    enabled: bool,
+    start: impl FnOnce(C) -> Result<Arc<S>>,
+) -> Result<Option<Arc<S>>> {
+    if old == Some(new) {
+        Ok(running.clone())
+    } else if enabled {
+        start(new.clone()).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+impl Sinks {
+    /// Start every sink enabled in `config`
+    pub fn start(
+        config: &DaemonConfig,
+        hello: &AgentHello,
+        control: mpsc::Sender<ControlCommand>,
+    ) -> Result<Self> {
+        let sinks = Self {
+            control: Some(control),
+            ..Self::default()
+        };
+        sinks.replace(None, config, hello)
+    }
+
+    /// Sinks for `new`, sharing the running ones whose settings are the same
+    /// in `old`. If one fails to start, those already started are stopped
+    /// and the running set is left as it was.
+    pub fn reload(
+        &self,
+        old: &DaemonConfig,
+        new: &DaemonConfig,
+        hello: &AgentHello,
+    ) -> Result<Self> {
+        self.replace(Some(old), new, hello)
+    }
+
+    fn replace(
+        &self,
+        old: Option<&DaemonConfig>,
+        new: &DaemonConfig,
+        hello: &AgentHello,
+    ) -> Result<Self> {
+        let mut next = Self {
+            control: self.control.clone(),
+            ..Self::default()
+        };
+        match next.start_changed(self, old, new, hello) {
+            Ok(()) => Ok(next),
+            Err(e) => {
+                next.stop_unshared(self);
+                Err(e)
+            }
+        }
+    }
+
+    fn start_changed(
+        &mut self,
+        running: &Sinks,
+        old: Option<&DaemonConfig>,
+        new: &DaemonConfig,
+        hello: &AgentHello,
+    ) -> Result<()> {
+        self.forwarder = keep_or_start(
+            &running.forwarder,
+            old.map(|old| &old.forward),
+            &new.forward,
+            new.forward.enabled,
+            |config| Forwarder::start(config, hello.clone(), self.control.clone()),
+        )?;
+        if old.map(|old| &old.siem) == Some(&new.siem) {
+            self.siem = running.siem.clone();
+        } else {
+            for config in &new.siem {
+                self.siem.push(SiemSink::start(config.clone())?);
+            }
+        }
+        self.elastic = keep_or_start(
+            &running.elastic,
+            old.map(|old| &old.elasticsearch),
+            &new.elasticsearch,
+            new.elasticsearch.enabled,
+            ElasticSink::start,
+        )?;
+        self.splunk = keep_or_start(
+            &running.splunk,
+            old.map(|old| &old.splunk),
+            &new.splunk,
+            new.splunk.enabled,
+            SplunkSink::start,
+        )?;
+        self.loki = keep_or_start(
+            &running.loki,
+            old.map(|old| &old.loki),
+            &new.loki,
+            new.loki.enabled,
+            LokiSink::start,
+        )?;
+        self.gelf = keep_or_start(
+            &running.gelf,
+            old.map(|old| &old.gelf),
+            &new.gelf,
+            new.gelf.enabled,
+            GelfSink::start,
+        )?;
+        Ok(())
+    }
+
+    /// Stop the sinks that `other` does not share, once it has replaced
+    /// these or failed to. A replaced forwarder hands its unacknowledged
+    /// frames to the one in `other`.
+    pub fn stop_unshared(&self, other: &Sinks) {
+        fn unshared<S>(sink: &Option<Arc<S>>, other: &Option<Arc<S>>) -> Option<Arc<S>> {
+            match (sink, other) {
+                (Some(sink), Some(other)) if Arc::ptr_eq(sink, other) => None,
+                _ => sink.clone(),
+            }
+        }
+        if let Some(forwarder) = unshared(&self.forwarder, &other.forwarder) {
+            match &other.forwarder {
+                Some(next) => forwarder.hand_over(next),
+                None => forwarder.stop(),
+            }
+        }
+        for sink in &self.siem {
+            if !other.siem.iter().any(|other| Arc::ptr_eq(sink, other)) {
+                sink.stop();
+            }
+        }
+        if let Some(elastic) = unshared(&self.elastic, &other.elastic) {
+            elastic.stop();
+        }
+        if let Some(splunk) = unshared(&self.splunk, &other.splunk) {
+            splunk.stop();
+        }
+        if let Some(loki) = unshared(&self.loki, &other.loki) {
+            loki.stop();
+        }
+        if let Some(gelf) = unshared(&self.gelf, &other.gelf) {
+            gelf.stop();
+        }
+    }
+
+    pub fn forwarder(&self) -> Option<&Arc<Forwarder>> {
+        self.forwarder.as_ref()
+    }
+
+    /// Frames other than events only go to the collector
+    pub fn forward(&self, frame: Frame) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.send(frame);
+        }
+    }
+
+    pub fn send(&self, event: &LogEvent) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.send(Frame::Event(event.clone()));
+        }
+        for sink in &self.siem {
+            sink.send(event);
+        }
+        if let Some(elastic) = &self.elastic {
+            elastic.send(event);
+        }
+        if let Some(splunk) = &self.splunk {
+            splunk.send(event);
+        }
+        if let Some(loki) = &self.loki {
+            loki.send(event);
+        }
+        if let Some(gelf) = &self.gelf {
+            gelf.send(event);
+        }
+    }
+}
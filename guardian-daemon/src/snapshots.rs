@@ -0,0 +1,331 @@
+//! Shadow copies of the files kept for diffs. Every version of those files
+//! the daemon reads is stored under `dir` by its SHA-256, the same hash the
+//! file monitor's event for that version carries, and an index lists the
+//! last `keep` versions of each file. File events for those files list the
+//! stored versions in their tags, and with `restore` set the console
+//! restores one of them over the control channel, so an edited `sudoers`
+//! can be put back without leaving the console.
+
+use crate::config::SnapshotConfig;
+use crate::enroll::{create_private, write_private};
+use crate::queue::EventSender;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+
+/// Tag on file events whose contents were stored as a version. Those
+/// events also name every stored version of the file with `snapshot_tag`.
+pub const SNAPSHOT_TAG: &str = "snapshot";
+
+/// Tag naming one stored version of a file by its hash
+pub fn snapshot_tag(hash: &str) -> String {
+    format!("{}:{}", SNAPSHOT_TAG, hash)
+}
+
+/// One stored version of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    /// SHA-256 of the contents
+    pub hash: String,
+    pub taken: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Versions of each file, oldest first
+type Index = BTreeMap<PathBuf, Vec<Version>>;
+
+/// Stored versions of the files kept for diffs
+pub struct SnapshotStore {
+    dir: PathBuf,
+    keep: usize,
+    index: Index,
+}
+
+impl SnapshotStore {
+    /// Open the store under `config.dir`, creating it if needed
+    pub fn open(config: &SnapshotConfig) -> Result<Self> {
+        let store = Self {
+            dir: config.dir.clone(),
+            keep: config.keep.max(1),
+            index: Index::new(),
+        };
+        std::fs::create_dir_all(store.dir.join("objects"))
+            .with_context(|| format!("Failed to create snapshot store {}", store.dir.display()))?;
+        let index = match std::fs::read(store.index_path()) {
+            Ok(bytes) => serde_json::from_slice(&bytes).with_context(|| {
+                format!("Invalid snapshot index {}", store.index_path().display())
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Index::new(),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to read snapshot index {}",
+                        store.index_path().display()
+                    )
+                })
+            }
+        };
+        Ok(Self { index, ..store })
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.json")
+    }
+
+    fn object(&self, hash: &str) -> PathBuf {
+        self.dir.join("objects").join(hash)
+    }
+
+    /// Store `contents` as the latest version of `path`, unless it already
+    /// is, and return its hash. Versions past `keep` are deleted.
+    pub fn record(&mut self, path: &Path, contents: &[u8]) -> Result<String> {
+        let hash = format!("{:x}", Sha256::digest(contents));
+        let versions = self.index.entry(path.to_path_buf()).or_default();
+        if versions.last().is_some_and(|version| version.hash == hash) {
+            return Ok(hash);
+        }
+
+        // A version restored or written back again moves to the end
+        versions.retain(|version| version.hash != hash);
+        versions.push(Version {
+            hash: hash.clone(),
+            taken: Utc::now(),
+            size: contents.len() as u64,
+        });
+        let dropped: Vec<Version> = versions
+            .drain(..versions.len().saturating_sub(self.keep))
+            .collect();
+
+        // Versions can hold secrets the original file's mode kept private
+        let object = self.object(&hash);
+        if !object.exists() {
+            write_private(&object, contents)
+                .with_context(|| format!("Failed to store snapshot {}", object.display()))?;
+        }
+        write_private(&self.index_path(), serde_json::to_vec_pretty(&self.index)?)?;
+
+        // Contents can be shared by several files
+        let referenced: HashSet<&str> = self
+            .index
+            .values()
+            .flatten()
+            .map(|version| version.hash.as_str())
+            .collect();
+        for version in dropped {
+            if !referenced.contains(version.hash.as_str()) {
+                let _ = std::fs::remove_file(self.object(&version.hash));
+            }
+        }
+        Ok(hash)
+    }
+
+    /// Stored versions of `path`, oldest first
+    pub fn versions(&self, path: &Path) -> &[Version] {
+        self.index.get(path).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// Stored copy of the version of `path` with `hash`
+    pub fn source(&self, path: &Path, hash: &str) -> Result<PathBuf> {
+        if !self
+            .versions(path)
+            .iter()
+            .any(|version| version.hash == hash)
+        {
+            bail!("No stored version {} of {}", hash, path.display());
+        }
+        Ok(self.object(hash))
+    }
+}
+
+/// Write the stored copy `object` of the version with `hash` back over
+/// `path`. The contents go to a new file beside it, taking the original's
+/// owner and mode, which is synced and renamed over the original, so the
+/// file is never seen half written. The new file has a random name and is
+/// only ever changed through its handle, so nothing planted in the
+/// directory can redirect the write.
+pub fn write_back(object: &Path, path: &Path, hash: &str) -> Result<()> {
+    let contents =
+        std::fs::read(object).with_context(|| format!("Failed to read snapshot {}", hash))?;
+    if format!("{:x}", Sha256::digest(&contents)) != hash {
+        bail!("Snapshot {} of {} is corrupt", hash, path.display());
+    }
+    let original = std::fs::metadata(path).ok();
+    let dir = path
+        .parent()
+        .with_context(|| format!("{} has no directory", path.display()))?;
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let temp = dir.join(format!(
+        ".{}.{}.guardian-restore",
+        name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let written = (|| -> Result<()> {
+        let mut file = create_private(&temp)?;
+        file.write_all(&contents)?;
+        if let Some(original) = &original {
+            // Owner first: changing it clears setuid and setgid bits
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                std::os::unix::fs::fchown(&file, Some(original.uid()), Some(original.gid()))?;
+            }
+            file.set_permissions(original.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp, path)?;
+        #[cfg(unix)]
+        std::fs::File::open(dir)?.sync_all()?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Restores versions on behalf of the console and reports the outcome
+pub struct FileRestore {
+    store: Arc<Mutex<SnapshotStore>>,
+    tx: EventSender,
+    hostname: String,
+}
+
+impl FileRestore {
+    pub fn new(store: Arc<Mutex<SnapshotStore>>, tx: EventSender, hostname: String) -> Self {
+        Self {
+            store,
+            tx,
+            hostname,
+        }
+    }
+
+    /// Restore `path` to the version with `hash`
+    pub async fn restore(&self, path: &str, hash: &str, requested_by: &str) {
+        // The index is only needed to find the copy; the file monitor
+        // keeps recording while it is written back
+        let source = self
+            .store
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .source(Path::new(path), hash);
+        let result = match source {
+            Ok(object) => {
+                let (target, hash) = (PathBuf::from(path), hash.to_string());
+                tokio::task::spawn_blocking(move || write_back(&object, &target, &hash))
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()))
+            }
+            Err(e) => Err(e),
+        };
+        let (severity, level, message) = match result {
+            Ok(()) => {
+                info!("Restored {} to version {}", path, hash);
+                (
+                    Severity::Medium,
+                    "warning",
+                    format!("{} restored to version {} by {}", path, hash, requested_by),
+                )
+            }
+            Err(e) => {
+                error!("File restore failed: {:#}", e);
+                (
+                    Severity::High,
+                    "error",
+                    format!(
+                        "Restore of {} requested by {} failed: {:#}",
+                        path, requested_by, e
+                    ),
+                )
+            }
+        };
+
+        let event = LogEvent::new(
+            severity,
+            EventType::SystemLog {
+                source: "response".to_string(),
+                level: level.to_string(),
+                message,
+            },
+            self.hostname.clone(),
+        )
+        .with_tag("response")
+        .with_tag("file_restore");
+        if self.tx.send(event).await.is_err() {
+            warn!("Failed to emit response event - channel closed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_restore() {
+        let dir = std::env::temp_dir().join(format!("guardian-snapshots-{}", uuid::Uuid::new_v4()));
+        let config = SnapshotConfig {
+            enabled: true,
+            dir: dir.join("store"),
+            keep: 2,
+            restore: true,
+        };
+        let sudoers = dir.join("sudoers");
+        let mut store = SnapshotStore::open(&config).unwrap();
+
+        let first = store.record(&sudoers, b"root ALL=(ALL) ALL\n").unwrap();
+        assert_eq!(
+            store.record(&sudoers, b"root ALL=(ALL) ALL\n").unwrap(),
+            first
+        );
+        let second = store.record(&sudoers, b"bob ALL=(ALL) ALL\n").unwrap();
+        let third = store.record(&sudoers, b"eve ALL=(ALL) ALL\n").unwrap();
+        let hashes: Vec<&str> = store
+            .versions(&sudoers)
+            .iter()
+            .map(|version| version.hash.as_str())
+            .collect();
+        assert_eq!(hashes, [second.as_str(), third.as_str()]);
+        assert!(!store.object(&first).exists());
+
+        // The index survives reopening
+        let store = SnapshotStore::open(&config).unwrap();
+        assert_eq!(store.versions(&sudoers).len(), 2);
+
+        // Written back whole, keeping the mode
+        std::fs::write(&sudoers, b"mallory ALL=(ALL) ALL\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&sudoers, std::fs::Permissions::from_mode(0o440)).unwrap();
+        }
+        let object = store.source(&sudoers, &second).unwrap();
+        write_back(&object, &sudoers, &second).unwrap();
+        assert_eq!(std::fs::read(&sudoers).unwrap(), b"bob ALL=(ALL) ALL\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&sudoers).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o440);
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(store.source(&sudoers, &first).is_err());
+        assert!(store.source(&dir.join("passwd"), &second).is_err());
+
+        // A damaged copy is refused
+        std::fs::write(&object, b"tampered").unwrap();
+        assert!(write_back(&object, &sudoers, &second).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
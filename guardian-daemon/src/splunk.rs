@@ -0,0 +1,240 @@
+//! Batches events to a Splunk HTTP Event Collector. Each event keeps its
+//! Guardian JSON as the HEC `event`, with a sourcetype per event type and an
+//! index chosen by severity, so Splunk searches can split them without
+//! custom props. Requests are gzipped and retried with backoff on 5xx.
+
+use crate::config::SplunkConfig;
+use crate::outbox::Outbox;
+use anyhow::{bail, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use guardian_common::LogEvent;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// First and longest wait between failed requests
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Path of the JSON event endpoint under the HEC base URL
+const EVENT_PATH: &str = "services/collector/event";
+
+pub struct SplunkSink {
+    http: reqwest::Client,
+    config: SplunkConfig,
+    outbox: Outbox<String>,
+}
+
+impl SplunkSink {
+    /// Check the settings and start sending in the background
+    pub fn start(config: SplunkConfig) -> Result<Arc<Self>> {
+        if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+            bail!("Splunk url must start with http:// or https://");
+        }
+        if config.token.is_empty() {
+            bail!("Splunk HEC token must not be empty");
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(!config.verify_tls)
+            .build()?;
+
+        let sink = Arc::new(Self {
+            http,
+            outbox: Outbox::new("Splunk", config.buffer),
+            config,
+        });
+        let task = sink.clone();
+        tokio::spawn(async move { task.outbox.deliver(task.clone().run()).await });
+        Ok(sink)
+    }
+
+    /// Stop delivering, when a config reload replaces the sink
+    pub fn stop(&self) {
+        self.outbox.stop();
+    }
+
+    /// Queue an event if it meets the minimum severity
+    pub fn send(&self, event: &LogEvent) {
+        if event.severity < self.config.min_severity {
+            return;
+        }
+        match hec_event(&self.config, event) {
+            Ok(payload) => self.outbox.push(payload.to_string()),
+            Err(e) => warn!("Failed to serialize event for Splunk: {}", e),
+        }
+    }
+
+    async fn run(self: Arc<Self>) {
+        let flush = Duration::from_millis(self.config.flush_ms);
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let batch = self.outbox.next_batch(self.config.batch_size, flush).await;
+            match self.post(&batch).await {
+                Ok(()) => backoff = MIN_BACKOFF,
+                Err(Delivery::Retry(e)) => {
+                    warn!("Splunk HEC request failed, retrying: {}", e);
+                    self.outbox.requeue(batch);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(Delivery::Rejected(e)) => {
+                    warn!("Splunk HEC rejected {} events: {}", batch.len(), e);
+                }
+            }
+        }
+    }
+
+    async fn post(&self, batch: &[String]) -> Result<(), Delivery> {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), EVENT_PATH);
+        let body =
+            encode(batch, self.config.gzip).map_err(|e| Delivery::Rejected(e.to_string()))?;
+        let mut request = self
+            .http
+            .post(url)
+            .header("Authorization", format!("Splunk {}", self.config.token))
+            .header("Content-Type", "application/json");
+        if self.config.gzip {
+            request = request.header("Content-Encoding", "gzip");
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| Delivery::Retry(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = format!("{}: {}", status, response.text().await.unwrap_or_default());
+        if retryable(status.as_u16()) {
+            Err(Delivery::Retry(error))
+        } else {
+            Err(Delivery::Rejected(error))
+        }
+    }
+}
+
+/// Why a batch was not accepted
+enum Delivery {
+    /// HEC was unreachable or overloaded; send again later
+    Retry(String),
+    /// HEC refused the request, e.g. a bad token; sending again would not help
+    Rejected(String),
+}
+
+/// HEC answers 503 when busy and 429 when throttling
+fn retryable(status: u16) -> bool {
+    status == 429 || (500..=599).contains(&status)
+}
+
+/// HEC envelope for one event
+fn hec_event(config: &SplunkConfig, event: &LogEvent) -> Result<Value> {
+    let kind = event.event_type.kind();
+    let sourcetype = config
+        .sourcetypes
+        .get(kind)
+        .cloned()
+        .unwrap_or_else(|| format!("guardian:{}", kind));
+
+    let mut payload = json!({
+        "time": event.timestamp.timestamp_millis() as f64 / 1000.0,
+        "host": event.hostname,
+        "source": config.source,
+        "sourcetype": sourcetype,
+        "event": serde_json::to_value(event)?,
+        "fields": { "severity": event.severity },
+    });
+    if let Some(index) = config
+        .severity_indexes
+        .get(&event.severity)
+        .or(config.index.as_ref())
+    {
+        payload["index"] = json!(index);
+    }
+    if let Some(rule) = &event.rule_name {
+        payload["fields"]["rule"] = json!(rule);
+    }
+    Ok(payload)
+}
+
+/// Request body: envelopes back to back, which HEC accepts as a batch
+fn encode(batch: &[String], gzip: bool) -> std::io::Result<Vec<u8>> {
+    let body = batch.join("\n");
+    if !gzip {
+        return Ok(body.into_bytes());
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body.as_bytes())?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use guardian_common::{AuthOutcome, EventType, Severity};
+    use std::io::Read;
+
+    #[test]
+    fn test_hec_batches() {
+        let mut config = SplunkConfig {
+            index: Some("guardian".to_string()),
+            ..SplunkConfig::default()
+        };
+        config
+            .severity_indexes
+            .insert(Severity::Critical, "security_urgent".to_string());
+        config
+            .sourcetypes
+            .insert("auth_event".to_string(), "linux_secure".to_string());
+
+        let login = LogEvent::new(
+            Severity::Critical,
+            EventType::AuthEvent {
+                user: "root".to_string(),
+                source_ip: None,
+                method: "password".to_string(),
+                outcome: AuthOutcome::Failure,
+                tty: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("Root login");
+        let payload = hec_event(&config, &login).unwrap();
+        assert_eq!(payload["host"], "web-1");
+        assert_eq!(payload["sourcetype"], "linux_secure");
+        assert_eq!(payload["index"], "security_urgent");
+        assert_eq!(payload["fields"]["severity"], "CRITICAL");
+        assert_eq!(payload["fields"]["rule"], "Root login");
+        assert_eq!(payload["event"]["id"], login.id.to_string());
+
+        let status = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "cron".to_string(),
+                level: "info".to_string(),
+                message: "job ran".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        let payload = hec_event(&config, &status).unwrap();
+        assert_eq!(payload["sourcetype"], "guardian:system_log");
+        assert_eq!(payload["index"], "guardian");
+
+        let batch = vec!["{\"event\":1}".to_string(), "{\"event\":2}".to_string()];
+        let mut body = String::new();
+        GzDecoder::new(&encode(&batch, true).unwrap()[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "{\"event\":1}\n{\"event\":2}");
+
+        assert!(retryable(503));
+        assert!(retryable(429));
+        assert!(!retryable(403));
+    }
+}
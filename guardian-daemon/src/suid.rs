@@ -0,0 +1,307 @@
+//! Setuid and setgid binary inventory. Dropping a setuid-root copy of a
+//! shell, or swapping the contents of an existing setuid binary, is a
+//! classic way to keep root. The configured roots are walked periodically
+//! and every setuid or setgid file recorded with its hash in an inventory
+//! file; a binary that appears or changes since the last scan is reported
+//! as a high severity `file_integrity` event.
+
+use crate::config::SuidConfig;
+use crate::file_monitor::hash_file;
+use crate::queue::EventSender;
+use crate::{executable, users};
+use anyhow::{Context, Result};
+use guardian_common::{EventType, FileOperation, LogEvent, Severity};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Tag on inventory change events
+pub const SUID_TAG: &str = "suid_inventory";
+
+/// Rule name on new or changed setuid and setgid binaries
+pub const SUID_RULE: &str = "suid_binary_changed";
+
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+
+/// What the inventory keeps about one binary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Setuid and setgid bits of the mode
+    pub bits: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub hash: Option<String>,
+}
+
+/// Setuid and setgid binaries by path
+pub type Inventory = BTreeMap<PathBuf, Entry>;
+
+/// Roots with symlinks resolved, leaving out any inside another
+fn distinct_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .collect();
+    roots.sort();
+    roots.dedup();
+    let all = roots.clone();
+    roots.retain(|root| {
+        !all.iter()
+            .any(|other| other != root && root.starts_with(other))
+    });
+    roots
+}
+
+/// Every setuid or setgid regular file under `roots`. Symlinks are not
+/// followed and each root's walk stays on the root's filesystem.
+pub fn scan(roots: &[PathBuf]) -> Inventory {
+    let mut inventory = Inventory::new();
+    for root in distinct_roots(roots) {
+        let Ok(device) = std::fs::metadata(&root).map(|m| m.dev()) else {
+            continue;
+        };
+        let mut pending = vec![root];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    if metadata.dev() == device {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                let bits = metadata.mode() & (S_ISUID | S_ISGID);
+                if !metadata.is_file() || bits == 0 {
+                    continue;
+                }
+                let hash = hash_file(&path);
+                inventory.insert(
+                    path,
+                    Entry {
+                        bits,
+                        uid: metadata.uid(),
+                        gid: metadata.gid(),
+                        hash,
+                    },
+                );
+            }
+        }
+    }
+    inventory
+}
+
+/// `setuid`, `setgid` or both
+fn kind(bits: u32) -> &'static str {
+    match (bits & S_ISUID != 0, bits & S_ISGID != 0) {
+        (true, true) => "setuid and setgid",
+        (true, false) => "setuid",
+        _ => "setgid",
+    }
+}
+
+fn event(path: &Path, entry: &Entry, operation: FileOperation, hostname: &str) -> LogEvent {
+    let severity = match operation {
+        FileOperation::Create | FileOperation::Modify => Severity::High,
+        _ => Severity::Low,
+    };
+    let mut event = LogEvent::new(
+        severity,
+        EventType::FileIntegrity {
+            path: path.display().to_string(),
+            operation,
+            hash: entry.hash.clone(),
+            user: Some(users::event_user(entry.uid)),
+            executable: executable::analyze(path),
+            process: None,
+            diff: None,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(SUID_TAG);
+    if entry.bits & S_ISUID != 0 {
+        event = event.with_tag("setuid");
+    }
+    if entry.bits & S_ISGID != 0 {
+        event = event.with_tag("setgid");
+    }
+    event
+}
+
+/// Events for the differences between two inventories: binaries that are
+/// new, or newly setuid or setgid, are `create`; changed contents or
+/// ownership are `modify`; ones gone or no longer privileged are a low
+/// severity `delete` or `chmod`
+pub fn compare(old: &Inventory, new: &Inventory, hostname: &str) -> Vec<LogEvent> {
+    let mut events = Vec::new();
+    for (path, entry) in new {
+        let operation = match old.get(path) {
+            None => FileOperation::Create,
+            Some(known) if entry.bits & !known.bits != 0 => FileOperation::Create,
+            Some(known)
+                if known.hash != entry.hash || known.uid != entry.uid || known.gid != entry.gid =>
+            {
+                FileOperation::Modify
+            }
+            Some(known) if known.bits != entry.bits => FileOperation::Chmod,
+            Some(_) => continue,
+        };
+        match operation {
+            FileOperation::Create => warn!("New {} binary {}", kind(entry.bits), path.display()),
+            FileOperation::Modify => {
+                warn!("Changed {} binary {}", kind(entry.bits), path.display())
+            }
+            _ => {}
+        }
+        events.push(event(path, entry, operation, hostname));
+    }
+    for (path, entry) in old {
+        if !new.contains_key(path) {
+            let operation = if path.exists() {
+                FileOperation::Chmod
+            } else {
+                FileOperation::Delete
+            };
+            events.push(event(path, entry, operation, hostname));
+        }
+    }
+    events
+}
+
+/// The recorded inventory; one that no longer parses is treated as
+/// missing, so the next scan records a fresh one
+fn load(path: &Path) -> Result<Option<Inventory>> {
+    match std::fs::read(path) {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(inventory) => Ok(Some(inventory)),
+            Err(e) => {
+                warn!(
+                    "Invalid setuid inventory {}, recording it again: {}",
+                    path.display(),
+                    e
+                );
+                Ok(None)
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to read setuid inventory {}", path.display()))
+        }
+    }
+}
+
+fn save(path: &Path, inventory: &Inventory) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(inventory)?)
+        .with_context(|| format!("Failed to write setuid inventory {}", path.display()))
+}
+
+/// Scan every `interval_secs` until the event channel closes. The first
+/// scan without an inventory file only records what is there.
+pub fn run(config: SuidConfig, tx: EventSender, hostname: String) -> Result<()> {
+    let interval = std::time::Duration::from_secs(config.interval_secs.max(1));
+    let mut known = load(&config.inventory)?;
+    loop {
+        let inventory = scan(&config.roots);
+        match &known {
+            Some(old) => {
+                for event in compare(old, &inventory, &hostname) {
+                    if tx.blocking_send(event).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            None => info!(
+                "Recorded {} setuid and setgid binaries in {}",
+                inventory.len(),
+                config.inventory.display()
+            ),
+        }
+        if known.as_ref() != Some(&inventory) {
+            save(&config.inventory, &inventory)?;
+        }
+        known = Some(inventory);
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn operations(events: &[LogEvent]) -> Vec<(String, FileOperation, Severity)> {
+        events
+            .iter()
+            .map(|event| match &event.event_type {
+                EventType::FileIntegrity {
+                    path, operation, ..
+                } => (path.clone(), operation.clone(), event.severity),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inventory_changes() {
+        let dir = std::env::temp_dir().join(format!("guardian-suid-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        let helper = dir.join("bin/helper");
+        let plain = dir.join("bin/plain");
+        std::fs::write(&helper, b"v1").unwrap();
+        std::fs::write(&plain, b"plain").unwrap();
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o4755)).unwrap();
+        std::fs::set_permissions(&plain, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The same directory through a second root is scanned once
+        let roots = vec![dir.clone(), dir.join("bin")];
+        let first = scan(&roots);
+        assert_eq!(first.keys().collect::<Vec<_>>(), vec![&helper]);
+        assert!(compare(&first, &scan(&roots), "web-1").is_empty());
+
+        // New contents, a new setgid binary and a dropped setuid bit
+        std::fs::write(&helper, b"v2").unwrap();
+        std::fs::set_permissions(&plain, std::fs::Permissions::from_mode(0o2755)).unwrap();
+        let second = scan(&roots);
+        let events = compare(&first, &second, "web-1");
+        let path = |p: &Path| p.display().to_string();
+        assert_eq!(
+            operations(&events),
+            vec![
+                (path(&helper), FileOperation::Modify, Severity::High),
+                (path(&plain), FileOperation::Create, Severity::High),
+            ]
+        );
+        assert!(events[1].tags.contains(&"setgid".to_string()));
+
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_file(&plain).unwrap();
+        let events = compare(&second, &scan(&roots), "web-1");
+        assert_eq!(
+            operations(&events),
+            vec![
+                (path(&helper), FileOperation::Chmod, Severity::Low),
+                (path(&plain), FileOperation::Delete, Severity::Low),
+            ]
+        );
+
+        // A damaged inventory is recorded again rather than stopping the scans
+        let inventory = dir.join("suid.json");
+        save(&inventory, &second).unwrap();
+        assert_eq!(load(&inventory).unwrap(), Some(second));
+        std::fs::write(&inventory, "{\"/usr/bin/su").unwrap();
+        assert_eq!(load(&inventory).unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
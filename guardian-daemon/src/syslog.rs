@@ -0,0 +1,403 @@
+//! Syslog receiver, so routers, switches and hosts without an agent can log
+//! to Guardian. RFC 3164 and RFC 5424 messages become `SystemLog` events
+//! under the sender's address and go through the same rules and sinks as
+//! local events.
+
+use crate::config::SyslogConfig;
+use crate::queue::EventSender;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::{debug, info, warn};
+
+/// Syslog severity names, indexed by severity number
+const LEVELS: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// PRI assumed when a message has none, `user.notice` per RFC 3164
+const DEFAULT_PRI: u8 = 13;
+
+/// Parts of a received message
+#[derive(Debug, PartialEq)]
+struct Message {
+    severity: u8,
+    timestamp: Option<DateTime<Utc>>,
+    hostname: Option<String>,
+    app: Option<String>,
+    text: String,
+}
+
+/// Split off the first whitespace-delimited token
+fn token(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+    Some(s.split_once(' ').unwrap_or((s, "")))
+}
+
+/// `<PRI>` prefix as facility * 8 + severity
+fn split_pri(raw: &str) -> (u8, &str) {
+    raw.strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .and_then(|(pri, rest)| Some((pri.parse::<u8>().ok().filter(|p| *p < 192)?, rest)))
+        .unwrap_or((DEFAULT_PRI, raw))
+}
+
+/// `-` stands for an absent RFC 5424 field
+fn nil(field: &str) -> Option<String> {
+    (field != "-").then(|| field.to_string())
+}
+
+/// Structured data, `-` or one or more `[id key="value"]` elements
+fn split_structured_data(s: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return Some(("", rest.strip_prefix(' ').unwrap_or(rest)));
+    }
+    let bytes = s.as_bytes();
+    let (mut in_quotes, mut escaped, mut depth) = (false, false, 0);
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' if in_quotes => escaped = true,
+            b'"' => in_quotes = !in_quotes,
+            b'[' if !in_quotes => depth += 1,
+            b']' if !in_quotes => {
+                depth -= 1;
+                if depth == 0 && bytes.get(i + 1) != Some(&b'[') {
+                    let rest = &s[i + 1..];
+                    return Some((&s[..=i], rest.strip_prefix(' ').unwrap_or(rest)));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `1 2026-10-17T03:31:10.123Z router1 sshd 812 - [meta x="1"] message`
+fn parse_rfc5424(severity: u8, rest: &str) -> Option<Message> {
+    let rest = rest.strip_prefix("1 ")?;
+    let (timestamp, rest) = token(rest)?;
+    let (hostname, rest) = token(rest)?;
+    let (app, rest) = token(rest)?;
+    let (_procid, rest) = token(rest)?;
+    let (_msgid, rest) = token(rest)?;
+    let (data, text) = split_structured_data(rest.trim_start())?;
+
+    let text = text.trim_start_matches('\u{feff}');
+    let text = match (data.is_empty(), text.is_empty()) {
+        (true, _) => text.to_string(),
+        (false, true) => data.to_string(),
+        (false, false) => format!("{} {}", text, data),
+    };
+    Some(Message {
+        severity,
+        timestamp: DateTime::parse_from_rfc3339(timestamp)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        hostname: nil(hostname),
+        app: nil(app),
+        text,
+    })
+}
+
+/// `Oct 17 03:31:10` in local time. The message has no year, so it is the
+/// current one unless that puts the time over a day ahead, e.g. a December
+/// message received in January.
+fn parse_bsd_timestamp(s: &str, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    [now.year(), now.year() - 1].into_iter().find_map(|year| {
+        let naive =
+            NaiveDateTime::parse_from_str(&format!("{} {}", year, s), "%Y %b %d %H:%M:%S").ok()?;
+        let local = Local.from_local_datetime(&naive).earliest()?;
+        (local <= now + chrono::Duration::days(1)).then(|| local.with_timezone(&Utc))
+    })
+}
+
+/// `Oct 17 03:31:10 router1 sshd[812]: message`; anything not in that shape
+/// is kept whole as the message
+fn parse_rfc3164(severity: u8, rest: &str, now: DateTime<Local>) -> Message {
+    let parsed = (|| {
+        let (month, r) = token(rest)?;
+        let (day, r) = token(r)?;
+        let (time, r) = token(r)?;
+        let timestamp = parse_bsd_timestamp(&format!("{} {} {}", month, day, time), now)?;
+        let (hostname, r) = token(r)?;
+
+        // TAG is alphanumeric and ends at `[`, `:` or a space
+        let tag_end = r
+            .find(|c: char| !(c.is_alphanumeric() || "-_./".contains(c)))
+            .unwrap_or(r.len());
+        let (app, text) = match r[tag_end..].strip_prefix('[') {
+            Some(after) => (&r[..tag_end], after.split_once(']')?.1),
+            None => (&r[..tag_end], &r[tag_end..]),
+        };
+        let text = text.strip_prefix(':').unwrap_or(text).trim_start();
+        Some(Message {
+            severity,
+            timestamp: Some(timestamp),
+            hostname: Some(hostname.to_string()),
+            app: (!app.is_empty()).then(|| app.to_string()),
+            text: text.to_string(),
+        })
+    })();
+
+    parsed.unwrap_or_else(|| Message {
+        severity,
+        timestamp: None,
+        hostname: None,
+        app: None,
+        text: rest.trim().to_string(),
+    })
+}
+
+fn parse_message(raw: &str, now: DateTime<Local>) -> Message {
+    let raw = raw.trim_end_matches(['\r', '\n', '\0']);
+    let (pri, rest) = split_pri(raw);
+    let severity = pri % 8;
+    parse_rfc5424(severity, rest).unwrap_or_else(|| parse_rfc3164(severity, rest, now))
+}
+
+/// Turn a received message into an event attributed to `peer`. Any sender
+/// can claim any HOSTNAME, so the claimed one is only kept as a tag.
+fn to_event(raw: &str, peer: IpAddr) -> LogEvent {
+    let message = parse_message(raw, Local::now());
+    let severity = match message.severity {
+        0..=2 => Severity::High,
+        3 => Severity::Medium,
+        4 => Severity::Low,
+        _ => Severity::Info,
+    };
+    let peer = peer.to_string();
+    let claimed = message.hostname.filter(|name| *name != peer);
+    let mut event = LogEvent::new(
+        severity,
+        EventType::SystemLog {
+            source: message.app.unwrap_or_else(|| "syslog".to_string()),
+            level: LEVELS[message.severity as usize].to_string(),
+            message: message.text,
+        },
+        peer,
+    )
+    .with_tag("syslog");
+    if let Some(claimed) = claimed {
+        event = event.with_tag(format!("syslog_host:{}", claimed));
+    }
+    if let Some(timestamp) = message.timestamp {
+        event.timestamp = timestamp;
+    }
+    event
+}
+
+/// Next message on a TCP stream: octet-counted (`LEN SP MSG`, RFC 6587) when
+/// it starts with digits followed by a space, else up to the next newline.
+/// `None` at end of stream.
+async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_message: usize,
+) -> Result<Option<String>> {
+    let buffered = reader.fill_buf().await?;
+    if buffered.is_empty() {
+        return Ok(None);
+    }
+    // A PRI-less line may start with digits too, such as a bare timestamp
+    let digits = buffered.iter().take_while(|b| b.is_ascii_digit()).count();
+    let octet_counted = digits > 0 && buffered.get(digits).is_none_or(|b| *b == b' ');
+
+    let mut frame = Vec::new();
+    if octet_counted {
+        let mut length = Vec::new();
+        // A length prefix longer than 20 digits is not a length
+        (&mut *reader)
+            .take(20)
+            .read_until(b' ', &mut length)
+            .await?;
+        let length: usize = std::str::from_utf8(&length)?.trim().parse()?;
+        if length > max_message {
+            bail!(
+                "{} byte frame exceeds the {} byte limit",
+                length,
+                max_message
+            );
+        }
+        frame.resize(length, 0);
+        reader.read_exact(&mut frame).await?;
+    } else {
+        let mut limited = reader.take(max_message as u64 + 1);
+        limited.read_until(b'\n', &mut frame).await?;
+        if frame.len() > max_message {
+            bail!("line exceeds the {} byte limit", max_message);
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&frame).into_owned()))
+}
+
+async fn serve_udp(socket: UdpSocket, max_message: usize, tx: EventSender) {
+    let mut buf = vec![0u8; max_message];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Syslog receive failed: {}", e);
+                continue;
+            }
+        };
+        let raw = String::from_utf8_lossy(&buf[..len]);
+        if tx.send(to_event(&raw, peer.ip())).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, max_message: usize, tx: EventSender) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Syslog accept failed: {}", e);
+                continue;
+            }
+        };
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stream);
+            loop {
+                match read_frame(&mut reader, max_message).await {
+                    Ok(Some(raw)) if raw.trim().is_empty() => {}
+                    Ok(Some(raw)) => {
+                        if tx.send(to_event(&raw, peer.ip())).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => return,
+                    Err(e) => {
+                        debug!("Closing syslog connection from {}: {}", peer, e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Bind the configured listeners and feed received messages into `tx`
+pub async fn start(config: &SyslogConfig, tx: EventSender) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let listen: SocketAddr = config.listen.parse()?;
+    if config.udp {
+        let socket = UdpSocket::bind(listen).await?;
+        info!("Receiving syslog on udp/{}", listen);
+        tokio::spawn(serve_udp(socket, config.max_message, tx.clone()));
+    }
+    if config.tcp {
+        let listener = TcpListener::bind(listen).await?;
+        info!("Receiving syslog on tcp/{}", listen);
+        tokio::spawn(serve_tcp(listener, config.max_message, tx));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_messages() {
+        let now = Local.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+
+        let bsd = parse_message(
+            "<34>Oct  7 03:31:10 router1 sshd[812]: Failed password for root",
+            now,
+        );
+        assert_eq!(bsd.severity, 2);
+        assert_eq!(bsd.hostname.as_deref(), Some("router1"));
+        assert_eq!(bsd.app.as_deref(), Some("sshd"));
+        assert_eq!(bsd.text, "Failed password for root");
+        let expected = Local.with_ymd_and_hms(2026, 10, 7, 3, 31, 10).unwrap();
+        assert_eq!(bsd.timestamp, Some(expected.with_timezone(&Utc)));
+
+        // Late December seen in January belongs to the previous year
+        let january = Local.with_ymd_and_hms(2027, 1, 1, 0, 5, 0).unwrap();
+        let old = parse_message("<13>Dec 31 23:59:59 sw1 kernel: link down", january);
+        assert_eq!(
+            old.timestamp.map(|t| t.with_timezone(&Local).year()),
+            Some(2026)
+        );
+
+        let ietf = parse_message(
+            "<165>1 2026-10-17T03:31:10.123Z fw1 filterlog 812 ID47 [origin ip=\"10.0.0.1\" note=\"a\\]b\"] \u{feff}blocked 203.0.113.5",
+            now,
+        );
+        assert_eq!(ietf.severity, 5);
+        assert_eq!(ietf.hostname.as_deref(), Some("fw1"));
+        assert_eq!(ietf.app.as_deref(), Some("filterlog"));
+        assert_eq!(
+            ietf.text,
+            "blocked 203.0.113.5 [origin ip=\"10.0.0.1\" note=\"a\\]b\"]"
+        );
+        assert_eq!(
+            ietf.timestamp.unwrap().to_rfc3339(),
+            "2026-10-17T03:31:10.123+00:00"
+        );
+
+        let nil = parse_message("<14>1 - - - - - -", now);
+        assert_eq!(nil.hostname, None);
+        assert_eq!(nil.text, "");
+
+        let bare = parse_message("link flap on port 7\n", now);
+        assert_eq!(bare.severity, 5);
+        assert_eq!(bare.text, "link flap on port 7");
+
+        let event = to_event("<11>printer jammed", "192.0.2.9".parse().unwrap());
+        assert_eq!(event.hostname, "192.0.2.9");
+        assert_eq!(event.tags, vec!["syslog"]);
+        assert_eq!(event.severity, Severity::Medium);
+        assert_eq!(
+            event.event_type,
+            EventType::SystemLog {
+                source: "syslog".to_string(),
+                level: "err".to_string(),
+                message: "printer jammed".to_string(),
+            }
+        );
+
+        // A claimed hostname never replaces the sender's address
+        let spoofed = to_event(
+            "<34>Oct  7 03:31:10 dc01 sshd[812]: Accepted password for root",
+            "192.0.2.9".parse().unwrap(),
+        );
+        assert_eq!(spoofed.hostname, "192.0.2.9");
+        assert!(spoofed.tags.contains(&"syslog_host:dc01".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_framing() {
+        let mut stream: &[u8] = b"12 <13>hello\nxy<14>second line\n2026-10-17 no pri\n<15>last";
+        assert_eq!(
+            read_frame(&mut stream, 100).await.unwrap().as_deref(),
+            Some("<13>hello\nxy")
+        );
+        assert_eq!(
+            read_frame(&mut stream, 100).await.unwrap().as_deref(),
+            Some("<14>second line\n")
+        );
+        assert_eq!(
+            read_frame(&mut stream, 100).await.unwrap().as_deref(),
+            Some("2026-10-17 no pri\n")
+        );
+        assert_eq!(
+            read_frame(&mut stream, 100).await.unwrap().as_deref(),
+            Some("<15>last")
+        );
+        assert_eq!(read_frame(&mut stream, 100).await.unwrap(), None);
+
+        let mut oversized: &[u8] = b"500 <13>...";
+        assert!(read_frame(&mut oversized, 100).await.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::queue::EventSender;
+use guardian_common::LogEvent;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Follows one log file from its current end, reopening it after rotation
+pub struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    #[cfg(unix)]
+    inode: u64,
+    partial: String,
+}
+
+impl LogTail {
+    pub fn open(path: PathBuf) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(&path)?;
+        Ok(Self {
+            offset: metadata.len(),
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&metadata),
+            path,
+            partial: String::new(),
+        })
+    }
+
+    /// Complete lines appended since the last call
+    pub fn read_lines(&mut self) -> std::io::Result<Vec<String>> {
+        let metadata = std::fs::metadata(&self.path)?;
+        #[cfg(unix)]
+        {
+            let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+            if inode != self.inode {
+                self.inode = inode;
+                self.offset = 0;
+            }
+        }
+        if metadata.len() < self.offset {
+            // Truncated in place
+            self.offset = 0;
+        }
+        if self.offset == 0 {
+            self.partial.clear();
+        }
+        if metadata.len() == self.offset {
+            return Ok(Vec::new());
+        }
+
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        self.offset += buf.len() as u64;
+
+        self.partial.push_str(&String::from_utf8_lossy(&buf));
+        let mut lines: Vec<String> = self.partial.split('\n').map(str::to_string).collect();
+        self.partial = lines.pop().unwrap_or_default();
+        Ok(lines)
+    }
+}
+
+/// Follow `paths` from their current end, sending the event `parse` builds from each new line.
+/// Missing files are skipped; returns when none exist or the receiver is gone.
+pub async fn follow(
+    paths: &[PathBuf],
+    poll_ms: u64,
+    tx: EventSender,
+    parse: impl Fn(&str) -> Option<LogEvent>,
+) {
+    let mut tails: Vec<LogTail> = paths
+        .iter()
+        .filter_map(|path| match LogTail::open(path.clone()) {
+            Ok(tail) => {
+                info!("Following {}", path.display());
+                Some(tail)
+            }
+            Err(e) => {
+                debug!("Log {} unavailable: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    if tails.is_empty() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_millis(poll_ms.max(100)));
+    loop {
+        interval.tick().await;
+        for tail in &mut tails {
+            let lines = match tail.read_lines() {
+                Ok(lines) => lines,
+                Err(e) => {
+                    warn!("Failed to read {}: {}", tail.path.display(), e);
+                    continue;
+                }
+            };
+
+            for event in lines.iter().filter_map(|line| parse(line)) {
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_follows_appends_and_truncation() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("guardian-auth-{}.log", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "old line\n").unwrap();
+        let mut tail = LogTail::open(path.clone()).unwrap();
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(file, "first\nsecond (partial").unwrap();
+        assert_eq!(tail.read_lines().unwrap(), vec!["first"]);
+        writeln!(file, ")").unwrap();
+        assert_eq!(tail.read_lines().unwrap(), vec!["second (partial)"]);
+
+        std::fs::write(&path, "after rotate\n").unwrap();
+        assert_eq!(tail.read_lines().unwrap(), vec!["after rotate"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+use crate::config::TaxiiConfig;
+use crate::threatintel::{Indicator, Indicators, ThreatIntel};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Media type for TAXII 2.1 requests and responses
+const TAXII_MEDIA_TYPE: &str = "application/taxii+json;version=2.1";
+
+/// Upper bound on pages fetched per pull, guarding against a server that never ends
+const MAX_PAGES: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    more: bool,
+    #[serde(default)]
+    next: Option<String>,
+    #[serde(default)]
+    objects: Vec<serde_json::Value>,
+}
+
+/// The parts of a STIX 2.1 indicator object we use
+#[derive(Debug, Deserialize)]
+struct StixIndicator {
+    id: String,
+    pattern: String,
+    #[serde(default)]
+    pattern_type: Option<String>,
+    #[serde(default)]
+    valid_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    revoked: bool,
+}
+
+/// An observable extracted from a STIX pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Observable {
+    Hash(String),
+    Address(String),
+    Domain(String),
+}
+
+/// Extract matchable observables from a STIX pattern.
+///
+/// Only patterns made of equality comparisons joined by `OR` are used; anything
+/// needing correlation (`AND`, `FOLLOWEDBY`, qualifiers) cannot be matched per
+/// event and yields nothing.
+fn parse_pattern(pattern: &str) -> Vec<Observable> {
+    const UNSUPPORTED: &[&str] = &[
+        " AND ",
+        " FOLLOWEDBY ",
+        " WITHIN ",
+        " REPEATS ",
+        " START ",
+        "!=",
+        " LIKE ",
+        " MATCHES ",
+    ];
+    if UNSUPPORTED.iter().any(|op| pattern.contains(op)) {
+        return Vec::new();
+    }
+
+    let mut observables = Vec::new();
+    for comparison in pattern
+        .split(" OR ")
+        .map(|c| c.trim().trim_start_matches('[').trim_end_matches(']'))
+    {
+        let Some((path, value)) = comparison.split_once('=') else {
+            return Vec::new();
+        };
+        let path = path.trim();
+        let value = value.trim().trim_matches('\'').replace("\\'", "'");
+
+        let observable = match path {
+            "ipv4-addr:value" | "ipv6-addr:value" => Observable::Address(value),
+            "domain-name:value" => Observable::Domain(value),
+            _ if path.starts_with("file:hashes.") => Observable::Hash(value),
+            _ => {
+                debug!("Ignoring STIX object path {}", path);
+                continue;
+            }
+        };
+        observables.push(observable);
+    }
+    observables
+}
+
+/// Convert STIX objects into indicators, skipping revoked and expired ones
+fn parse_objects(source: &str, objects: &[serde_json::Value], indicators: &mut Indicators) {
+    let now = Utc::now();
+
+    for object in objects {
+        if object.get("type").and_then(|t| t.as_str()) != Some("indicator") {
+            continue;
+        }
+        let stix: StixIndicator = match serde_json::from_value(object.clone()) {
+            Ok(stix) => stix,
+            Err(e) => {
+                debug!("Skipping malformed STIX indicator: {}", e);
+                continue;
+            }
+        };
+        if stix.revoked
+            || stix.valid_until.is_some_and(|until| until <= now)
+            || stix.pattern_type.as_deref().is_some_and(|t| t != "stix")
+        {
+            continue;
+        }
+
+        let indicator = Indicator {
+            source: source.to_string(),
+            reference: Some(format!("stix:{}", stix.id)),
+        };
+        for observable in parse_pattern(&stix.pattern) {
+            match observable {
+                Observable::Hash(hash) => indicators.add_hash(&hash, indicator.clone()),
+                Observable::Domain(domain) => indicators.add_domain(&domain, indicator.clone()),
+                Observable::Address(addr) => {
+                    if let Err(e) = indicators.add_network(&addr, indicator.clone()) {
+                        debug!("Skipping STIX address {}: {}", addr, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Client for one TAXII 2.1 collection
+pub struct TaxiiClient {
+    http: reqwest::Client,
+    config: TaxiiConfig,
+}
+
+impl TaxiiClient {
+    pub fn new(config: TaxiiConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .danger_accept_invalid_certs(!config.verify_tls)
+            .build()?;
+        Ok(Self { http, config })
+    }
+
+    /// Pull every indicator in the collection, following pagination
+    pub async fn pull(&self) -> Result<Indicators> {
+        let url = format!(
+            "{}/collections/{}/objects/",
+            self.config.api_root.trim_end_matches('/'),
+            self.config.collection
+        );
+        let mut indicators = Indicators::default();
+        let mut next: Option<String> = None;
+
+        for _ in 0..MAX_PAGES {
+            let mut request = self
+                .http
+                .get(&url)
+                .header("Accept", TAXII_MEDIA_TYPE)
+                .query(&[("match[type]", "indicator")]);
+            if let Some(next) = &next {
+                request = request.query(&[("next", next)]);
+            }
+            if let Some(username) = &self.config.username {
+                request = request.basic_auth(username, self.config.password.as_ref());
+            }
+
+            let envelope: Envelope = request
+                .send()
+                .await
+                .with_context(|| format!("TAXII request to {} failed", url))?
+                .error_for_status()?
+                .json()
+                .await
+                .context("Unexpected TAXII response")?;
+
+            parse_objects(&self.config.name, &envelope.objects, &mut indicators);
+
+            match envelope.next {
+                Some(token) if envelope.more => next = Some(token),
+                _ => return Ok(indicators),
+            }
+        }
+
+        warn!(
+            "TAXII collection {} exceeded {} pages, using a partial pull",
+            self.config.collection, MAX_PAGES
+        );
+        Ok(indicators)
+    }
+}
+
+/// Periodically pull a TAXII collection into the threat-intel store
+pub async fn run_feed(intel: ThreatIntel, config: TaxiiConfig) {
+    let source = format!("taxii:{}", config.name);
+    let pull_secs = config.pull_secs.max(60);
+    let client = match TaxiiClient::new(config) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("TAXII feed {} disabled: {}", source, e);
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(pull_secs));
+    loop {
+        interval.tick().await;
+        match client.pull().await {
+            Ok(indicators) => {
+                info!("Pulled {} indicators from {}", indicators.len(), source);
+                intel.replace(&source, indicators);
+            }
+            Err(e) => warn!(
+                "TAXII pull from {} failed, keeping previous indicators: {}",
+                source, e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(
+            parse_pattern("[file:hashes.'SHA-256' = 'ABCDEF01']"),
+            vec![Observable::Hash("ABCDEF01".to_string())]
+        );
+        assert_eq!(
+            parse_pattern(
+                "[ipv4-addr:value = '203.0.113.0/24' OR domain-name:value = 'evil.example']"
+            ),
+            vec![
+                Observable::Address("203.0.113.0/24".to_string()),
+                Observable::Domain("evil.example".to_string())
+            ]
+        );
+        // Correlated observations cannot be matched against single events
+        assert!(parse_pattern(
+            "[ipv4-addr:value = '203.0.113.7' AND network-traffic:dst_port = 4444]"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_parse_objects_skips_revoked_and_expired() {
+        let objects: Vec<serde_json::Value> = serde_json::from_str(
+            r#"[
+                {"type": "indicator", "id": "indicator--1", "pattern_type": "stix",
+                 "pattern": "[domain-name:value = 'evil.example']"},
+                {"type": "indicator", "id": "indicator--2", "pattern_type": "stix", "revoked": true,
+                 "pattern": "[domain-name:value = 'revoked.example']"},
+                {"type": "indicator", "id": "indicator--3", "pattern_type": "stix",
+                 "valid_until": "2000-01-01T00:00:00Z",
+                 "pattern": "[domain-name:value = 'expired.example']"},
+                {"type": "indicator", "id": "indicator--4", "pattern_type": "sigma",
+                 "pattern": "title: x"},
+                {"type": "malware", "id": "malware--1", "name": "x"}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut indicators = Indicators::default();
+        parse_objects("community", &objects, &mut indicators);
+
+        assert_eq!(indicators.len(), 1);
+        let hit = indicators.match_domain("evil.example").unwrap();
+        assert_eq!(hit.source, "community");
+        assert_eq!(hit.reference.as_deref(), Some("stix:indicator--1"));
+    }
+}
@@ -0,0 +1,462 @@
+use crate::config::ThreatIntelConfig;
+use crate::misp;
+use crate::taxii;
+use anyhow::{Context, Result};
+use guardian_common::{EventType, LogEvent, Severity};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Where a matching indicator came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Indicator {
+    /// Name of the list or feed that supplied the indicator
+    pub source: String,
+
+    /// Feed-specific reference added to matching events as a tag (e.g. `misp_event:42`)
+    pub reference: Option<String>,
+}
+
+/// Indexed indicators for constant-time lookups
+#[derive(Debug, Default)]
+pub struct Indicators {
+    hashes: HashMap<String, Indicator>,
+    addrs: HashMap<IpAddr, Indicator>,
+    domains: HashMap<String, Indicator>,
+    /// Networks keyed by prefix length, then by masked network address
+    networks: HashMap<u8, HashMap<IpAddr, Indicator>>,
+}
+
+impl Indicators {
+    /// Add a file hash (any digest, matched case-insensitively)
+    pub fn add_hash(&mut self, hash: &str, indicator: Indicator) {
+        self.hashes.insert(hash.to_ascii_lowercase(), indicator);
+    }
+
+    /// Add an IP address or CIDR network
+    pub fn add_network(&mut self, spec: &str, indicator: Indicator) -> Result<()> {
+        match spec.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr: IpAddr = addr.parse()?;
+                let prefix: u8 = prefix.parse()?;
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                anyhow::ensure!(prefix <= max, "prefix /{} out of range", prefix);
+
+                if prefix == max {
+                    self.addrs.insert(addr, indicator);
+                } else {
+                    self.networks
+                        .entry(prefix)
+                        .or_default()
+                        .insert(mask(addr, prefix), indicator);
+                }
+            }
+            None => {
+                self.addrs.insert(spec.parse()?, indicator);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a domain; subdomains match as well
+    pub fn add_domain(&mut self, domain: &str, indicator: Indicator) {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        if !domain.is_empty() {
+            self.domains.insert(domain, indicator);
+        }
+    }
+
+    pub fn match_hash(&self, hash: &str) -> Option<&Indicator> {
+        self.hashes.get(&hash.to_ascii_lowercase())
+    }
+
+    pub fn match_addr(&self, addr: IpAddr) -> Option<&Indicator> {
+        self.addrs.get(&addr).or_else(|| {
+            self.networks
+                .iter()
+                .filter(|(prefix, _)| addr.is_ipv6() || **prefix <= 32)
+                .find_map(|(prefix, nets)| nets.get(&mask(addr, *prefix)))
+        })
+    }
+
+    /// Match `host` or any parent domain of it
+    pub fn match_domain(&self, host: &str) -> Option<&Indicator> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let mut candidate = host.as_str();
+        loop {
+            if let Some(indicator) = self.domains.get(candidate) {
+                return Some(indicator);
+            }
+            candidate = candidate.split_once('.')?.1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+            + self.addrs.len()
+            + self.domains.len()
+            + self.networks.values().map(HashMap::len).sum::<usize>()
+    }
+
+    /// Load every configured list file
+    fn load(config: &ThreatIntelConfig) -> Result<Self> {
+        let mut indicators = Self::default();
+
+        for path in &config.hash_lists {
+            for (line_no, entry) in read_list(path)? {
+                if entry.chars().all(|c| c.is_ascii_hexdigit()) {
+                    indicators.add_hash(&entry, list_indicator(path));
+                } else {
+                    warn!(
+                        "{}:{}: not a hex digest: {}",
+                        path.display(),
+                        line_no,
+                        entry
+                    );
+                }
+            }
+        }
+
+        for path in &config.ip_lists {
+            for (line_no, entry) in read_list(path)? {
+                if let Err(e) = indicators.add_network(&entry, list_indicator(path)) {
+                    warn!(
+                        "{}:{}: invalid address {}: {}",
+                        path.display(),
+                        line_no,
+                        entry,
+                        e
+                    );
+                }
+            }
+        }
+
+        Ok(indicators)
+    }
+}
+
+/// Zero the host bits of `addr` beyond `prefix`
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let mask = u32::MAX
+                .checked_shl(32 - prefix.min(32) as u32)
+                .unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(bits & mask))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let mask = u128::MAX
+                .checked_shl(128 - prefix.min(128) as u32)
+                .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(bits & mask))
+        }
+    }
+}
+
+/// Indicator named after the list file it came from
+fn list_indicator(path: &Path) -> Indicator {
+    Indicator {
+        source: path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string()),
+        reference: None,
+    }
+}
+
+/// Read a list file: one entry per line, `#` comments, anything after the first token ignored
+fn read_list(path: &Path) -> Result<Vec<(usize, String)>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read threat-intel list {}", path.display()))?;
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let entry = line.split(|c: char| c.is_whitespace() || c == ',').next()?;
+            (!entry.is_empty()).then(|| (i + 1, entry.to_string()))
+        })
+        .collect())
+}
+
+/// Parse the IP out of `ip` or `ip:port` forms
+pub fn parse_remote_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<SocketAddr>()
+        .map(|s| s.ip())
+        .or_else(|_| addr.parse::<IpAddr>())
+        .ok()
+}
+
+/// Strip the port from a `host:port` remote address
+fn host_part(addr: &str) -> &str {
+    addr.rsplit_once(':').map_or(addr, |(host, _)| host)
+}
+
+/// Source name under which the local list files are stored
+const LIST_SOURCE: &str = "lists";
+
+/// Threat-intel store shared between the event pipeline and the reload/pull tasks
+///
+/// Each source (the local list files, each remote feed) is replaced as a
+/// whole when it refreshes, so a failed pull never disturbs the others.
+#[derive(Clone, Default)]
+pub struct ThreatIntel {
+    sources: Arc<RwLock<HashMap<String, Indicators>>>,
+}
+
+impl ThreatIntel {
+    /// Load the configured lists and start periodic reloading
+    pub fn start(config: ThreatIntelConfig) -> Result<Self> {
+        let intel = Self::default();
+        intel.reload(&config)?;
+
+        if let Some(misp_config) = config.misp.clone() {
+            tokio::spawn(misp::run_feed(intel.clone(), misp_config));
+        }
+        for taxii_config in config.taxii.clone() {
+            tokio::spawn(taxii::run_feed(intel.clone(), taxii_config));
+        }
+
+        if config.reload_secs > 0 && !(config.hash_lists.is_empty() && config.ip_lists.is_empty()) {
+            let reload = intel.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(config.reload_secs));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = reload.reload(&config) {
+                        warn!("Threat-intel reload failed, keeping previous lists: {}", e);
+                    }
+                }
+            });
+        }
+
+        Ok(intel)
+    }
+
+    /// Replace the indexed indicators with a fresh load of the list files,
+    /// returning how many were loaded
+    pub fn reload(&self, config: &ThreatIntelConfig) -> Result<usize> {
+        let indicators = Indicators::load(config)?;
+        let count = indicators.len();
+        info!("Loaded {} threat-intel indicators from lists", count);
+        self.replace(LIST_SOURCE, indicators);
+        Ok(count)
+    }
+
+    /// Swap in a fresh set of indicators for one source
+    pub fn replace(&self, source: &str, indicators: Indicators) {
+        self.sources
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(source.to_string(), indicators);
+    }
+
+    /// First indicator across all sources accepted by `matcher`
+    fn lookup(&self, matcher: impl Fn(&Indicators) -> Option<&Indicator>) -> Option<Indicator> {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        sources.values().find_map(matcher).cloned()
+    }
+
+    /// Tag and escalate an event whose file hash or remote address is listed
+    pub fn enrich(&self, event: &mut LogEvent) {
+        let (kind, indicator) = match &event.event_type {
+            EventType::FileIntegrity {
+                hash: Some(hash), ..
+            } => ("hash", self.lookup(|i| i.match_hash(hash))),
+            EventType::NetworkSocket {
+                remote_addr: Some(addr),
+                remote_host,
+                ..
+            } => match parse_remote_ip(addr) {
+                // Fall back to the reverse-DNS name so domain indicators apply too
+                Some(ip) => match self.lookup(|i| i.match_addr(ip)) {
+                    Some(indicator) => ("ip", Some(indicator)),
+                    None => (
+                        "domain",
+                        remote_host
+                            .as_deref()
+                            .and_then(|host| self.lookup(|i| i.match_domain(host))),
+                    ),
+                },
+                None => ("domain", self.lookup(|i| i.match_domain(host_part(addr)))),
+            },
+            _ => return,
+        };
+
+        if let Some(indicator) = indicator {
+            event.severity = Severity::Critical;
+            event.tags.push("threat_intel".to_string());
+            event
+                .tags
+                .push(format!("threat_intel:{}", indicator.source));
+            if let Some(reference) = indicator.reference {
+                event.tags.push(reference);
+            }
+            if !event.rule_triggered {
+                let rule = format!("threat_intel_{}_match", kind);
+                event.rule_triggered = true;
+                event.rule_name = Some(rule.clone());
+                event.rules_matched.push(rule);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::FileOperation;
+
+    fn indicator() -> Indicator {
+        Indicator {
+            source: "test".to_string(),
+            reference: None,
+        }
+    }
+
+    #[test]
+    fn test_network_matching() {
+        let mut indicators = Indicators::default();
+        indicators.add_network("203.0.113.7", indicator()).unwrap();
+        indicators
+            .add_network("198.51.100.0/24", indicator())
+            .unwrap();
+        indicators
+            .add_network("2001:db8::/32", indicator())
+            .unwrap();
+        assert!(indicators.add_network("10.0.0.0/40", indicator()).is_err());
+
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(indicators.match_addr(ip("203.0.113.7")).is_some());
+        assert!(indicators.match_addr(ip("198.51.100.200")).is_some());
+        assert!(indicators.match_addr(ip("198.51.101.1")).is_none());
+        assert!(indicators.match_addr(ip("2001:db8:1::1")).is_some());
+        assert!(indicators.match_addr(ip("2001:db9::1")).is_none());
+    }
+
+    #[test]
+    fn test_enrich_escalates_matching_events() {
+        let intel = ThreatIntel::default();
+        let mut indicators = Indicators::default();
+        indicators.add_hash("ABCDEF01", indicator());
+        indicators
+            .add_network("203.0.113.0/24", indicator())
+            .unwrap();
+        intel.replace("test", indicators);
+
+        let mut event = LogEvent::new(
+            Severity::Low,
+            EventType::FileIntegrity {
+                path: "/tmp/dropper".to_string(),
+                operation: FileOperation::Create,
+                hash: Some("abcdef01".to_string()),
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "localhost".to_string(),
+        );
+        intel.enrich(&mut event);
+        assert_eq!(event.severity, Severity::Critical);
+        assert_eq!(event.rule_name.as_deref(), Some("threat_intel_hash_match"));
+        assert!(event.tags.contains(&"threat_intel:test".to_string()));
+
+        let mut event = LogEvent::new(
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: "10.0.0.2:50000".to_string(),
+                remote_addr: Some("203.0.113.9:443".to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "localhost".to_string(),
+        );
+        intel.enrich(&mut event);
+        assert_eq!(event.rule_name.as_deref(), Some("threat_intel_ip_match"));
+    }
+
+    #[test]
+    fn test_domain_matching_and_reference_tag() {
+        let intel = ThreatIntel::default();
+        let mut indicators = Indicators::default();
+        indicators.add_domain(
+            "Evil.example.",
+            Indicator {
+                source: "misp".to_string(),
+                reference: Some("misp_event:42".to_string()),
+            },
+        );
+        assert!(indicators.match_domain("cdn.evil.example").is_some());
+        assert!(indicators.match_domain("notevil.example").is_none());
+        intel.replace("misp", indicators);
+
+        let mut event = LogEvent::new(
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: "10.0.0.2:50000".to_string(),
+                remote_addr: Some("c2.evil.example:443".to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "localhost".to_string(),
+        );
+        intel.enrich(&mut event);
+        assert_eq!(
+            event.rule_name.as_deref(),
+            Some("threat_intel_domain_match")
+        );
+
+        // A reverse-DNS name matches when the address itself is not listed
+        let mut event = LogEvent::new(
+            Severity::Info,
+            EventType::NetworkSocket {
+                local_addr: "10.0.0.2:50000".to_string(),
+                remote_addr: Some("198.51.100.20:443".to_string()),
+                remote_host: Some("www.evil.example".to_string()),
+                protocol: "tcp".to_string(),
+                state: "established".to_string(),
+                traffic: None,
+                process: None,
+            },
+            "localhost".to_string(),
+        );
+        intel.enrich(&mut event);
+        assert_eq!(event.severity, Severity::Critical);
+        assert!(event.tags.contains(&"misp_event:42".to_string()));
+    }
+
+    #[test]
+    fn test_read_list_skips_comments() {
+        let path = std::env::temp_dir().join(format!("ti-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "# header\n203.0.113.7  # c2\n\n198.51.100.0/24,botnet\n",
+        )
+        .unwrap();
+
+        let entries = read_list(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                (2, "203.0.113.7".to_string()),
+                (4, "198.51.100.0/24".to_string())
+            ]
+        );
+    }
+}
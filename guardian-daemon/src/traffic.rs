@@ -0,0 +1,562 @@
+//! Decoding captured packets into per-connection traffic: link, IP and
+//! transport headers, plus the server name from TLS ClientHellos and the
+//! `Host` header of plain HTTP requests. `Flows` adds packets up by
+//! connection and turns them into `network_socket` events on each flush,
+//! keeping at most `MAX_FLOWS` connections between flushes.
+
+use chrono::Utc;
+use guardian_common::{EventType, LogEvent, Severity, Traffic};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tracing::warn;
+
+/// Tag on events from captured traffic
+pub const CAPTURE_TAG: &str = "pcap";
+
+/// libpcap link-layer header types
+const LINKTYPE_NULL: i32 = 0;
+const LINKTYPE_ETHERNET: i32 = 1;
+const LINKTYPE_RAW: i32 = 101;
+const LINKTYPE_LOOP: i32 = 108;
+const LINKTYPE_LINUX_SLL: i32 = 113;
+const LINKTYPE_LINUX_SLL2: i32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+/// Connections tracked at once; the least recently active are dropped
+/// beyond it, a sixteenth at a time
+const MAX_FLOWS: usize = 65_536;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_RST: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    fn as_str(self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        }
+    }
+}
+
+/// Addresses and payload of one TCP or UDP packet
+#[derive(Debug)]
+pub struct Packet<'a> {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub transport: Transport,
+    /// TCP flags; zero for UDP
+    pub flags: u8,
+    pub payload: &'a [u8],
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// Decode a packet captured on a link of type `linktype`
+pub fn parse(linktype: i32, data: &[u8]) -> Option<Packet<'_>> {
+    match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut at = 12;
+            let mut ethertype = u16_at(data, at)?;
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                at += 4;
+                ethertype = u16_at(data, at)?;
+            }
+            network(ethertype, data.get(at + 2..)?)
+        }
+        LINKTYPE_LINUX_SLL => network(u16_at(data, 14)?, data.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => network(u16_at(data, 0)?, data.get(20..)?),
+        LINKTYPE_RAW => ip(data),
+        // BSD loopback carries the address family in host byte order
+        LINKTYPE_NULL | LINKTYPE_LOOP => ip(data.get(4..)?),
+        _ => None,
+    }
+}
+
+fn network(ethertype: u16, data: &[u8]) -> Option<Packet<'_>> {
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => ip(data),
+        _ => None,
+    }
+}
+
+/// IPv4 or IPv6, by the version in the first nibble
+fn ip(data: &[u8]) -> Option<Packet<'_>> {
+    match data.first()? >> 4 {
+        4 => ipv4(data),
+        6 => ipv6(data),
+        _ => None,
+    }
+}
+
+fn ipv4(data: &[u8]) -> Option<Packet<'_>> {
+    let header = usize::from(data.first()? & 0x0f) * 4;
+    // Later fragments carry no transport header
+    if u16_at(data, 6)? & 0x1fff != 0 || header < 20 {
+        return None;
+    }
+    // Ethernet pads short frames past the end of the IP packet
+    let total = usize::from(u16_at(data, 2)?).min(data.len());
+    let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+    let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+    transport(
+        *data.get(9)?,
+        IpAddr::V4(Ipv4Addr::from(src)),
+        IpAddr::V4(Ipv4Addr::from(dst)),
+        data.get(header..total)?,
+    )
+}
+
+fn ipv6(data: &[u8]) -> Option<Packet<'_>> {
+    let total = (40 + usize::from(u16_at(data, 4)?)).min(data.len());
+    let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+    let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+    let mut next = *data.get(6)?;
+    let mut at = 40;
+    // Hop-by-hop, routing and destination options headers
+    while matches!(next, 0 | 43 | 60) {
+        next = *data.get(at)?;
+        at += (usize::from(*data.get(at + 1)?) + 1) * 8;
+    }
+    transport(
+        next,
+        IpAddr::V6(Ipv6Addr::from(src)),
+        IpAddr::V6(Ipv6Addr::from(dst)),
+        data.get(at..total)?,
+    )
+}
+
+fn transport(protocol: u8, src: IpAddr, dst: IpAddr, data: &[u8]) -> Option<Packet<'_>> {
+    let (transport, flags, header) = match protocol {
+        6 => (
+            Transport::Tcp,
+            *data.get(13)?,
+            usize::from(data.get(12)? >> 4) * 4,
+        ),
+        17 => (Transport::Udp, 0, 8),
+        _ => return None,
+    };
+    Some(Packet {
+        src: SocketAddr::new(src, u16_at(data, 0)?),
+        dst: SocketAddr::new(dst, u16_at(data, 2)?),
+        transport,
+        flags,
+        payload: data.get(header..).unwrap_or_default(),
+    })
+}
+
+/// Server name from a TLS ClientHello that starts in `payload`
+pub fn sni(payload: &[u8]) -> Option<String> {
+    // Handshake record holding a ClientHello
+    if *payload.first()? != 0x16 || *payload.get(5)? != 0x01 {
+        return None;
+    }
+    // Record and handshake headers, client version and random
+    let mut at = 5 + 4 + 2 + 32;
+    at += 1 + usize::from(*payload.get(at)?);
+    at += 2 + usize::from(u16_at(payload, at)?);
+    at += 1 + usize::from(*payload.get(at)?);
+    let end = (at + 2 + usize::from(u16_at(payload, at)?)).min(payload.len());
+    at += 2;
+    while at + 4 <= end {
+        let kind = u16_at(payload, at)?;
+        let len = usize::from(u16_at(payload, at + 2)?);
+        at += 4;
+        if kind == 0 {
+            // Server name list; the first entry of type host_name
+            let list = payload.get(at + 2..at + len)?;
+            if *list.first()? != 0 {
+                return None;
+            }
+            let name_len = usize::from(u16_at(list, 1)?);
+            let name = std::str::from_utf8(list.get(3..3 + name_len)?).ok()?;
+            return Some(name.to_ascii_lowercase());
+        }
+        at += len;
+    }
+    None
+}
+
+/// `Host` header of an HTTP/1 request that starts in `payload`, without
+/// its port
+pub fn http_host(payload: &[u8]) -> Option<String> {
+    let head = payload
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(payload, |end| &payload[..end]);
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+    let request = lines.next()?;
+    let method = request.split(' ').next()?;
+    if method.is_empty()
+        || !method.bytes().all(|b| b.is_ascii_uppercase())
+        || !request.contains(" HTTP/1.")
+    {
+        return None;
+    }
+    let value = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("host").then(|| value.trim())
+    })?;
+    let host = match value.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            host
+        }
+        _ => value,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    local: SocketAddr,
+    remote: SocketAddr,
+    transport: Transport,
+}
+
+#[derive(Default)]
+struct Flow {
+    traffic: Traffic,
+    closed: bool,
+    /// Number of the last packet counted, for eviction
+    last_packet: u64,
+}
+
+/// Traffic of each connection since the last flush
+pub struct Flows {
+    local: HashSet<IpAddr>,
+    flows: HashMap<Key, Flow>,
+    packets: u64,
+    evicted: u64,
+}
+
+impl Flows {
+    /// Flows whose local end is one of `local`. Without any, as on a
+    /// device with no address, either end can be local: a connection's
+    /// first packet seen decides, its sender being taken as local.
+    pub fn new(local: impl IntoIterator<Item = IpAddr>) -> Self {
+        Self {
+            local: local.into_iter().collect(),
+            flows: HashMap::new(),
+            packets: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Count a packet that was `len` bytes on the wire
+    pub fn add(&mut self, packet: &Packet, len: u64) {
+        let outgoing = Key {
+            local: packet.src,
+            remote: packet.dst,
+            transport: packet.transport,
+        };
+        let incoming = Key {
+            local: packet.dst,
+            remote: packet.src,
+            transport: packet.transport,
+        };
+        let (key, sent) = if self.local.is_empty() {
+            if self.flows.contains_key(&incoming) {
+                (incoming, false)
+            } else {
+                (outgoing, true)
+            }
+        } else if self.local.contains(&packet.src.ip()) {
+            (outgoing, true)
+        } else if self.local.contains(&packet.dst.ip()) {
+            (incoming, false)
+        } else {
+            return;
+        };
+
+        if self.flows.len() >= MAX_FLOWS && !self.flows.contains_key(&key) {
+            self.evict();
+        }
+        self.packets += 1;
+        let flow = self.flows.entry(key).or_default();
+        flow.last_packet = self.packets;
+        let traffic = &mut flow.traffic;
+        traffic.packets += 1;
+        if sent {
+            traffic.bytes_sent += len;
+            if traffic.sni.is_none() {
+                traffic.sni = sni(packet.payload);
+            }
+            if traffic.http_host.is_none() {
+                traffic.http_host = http_host(packet.payload);
+            }
+        } else {
+            traffic.bytes_received += len;
+        }
+        if packet.flags & (TCP_FIN | TCP_RST) != 0 {
+            flow.closed = true;
+        }
+    }
+
+    /// Drop the least recently active sixteenth of the flows
+    fn evict(&mut self) {
+        let mut last: Vec<u64> = self.flows.values().map(|flow| flow.last_packet).collect();
+        let cutoff = *last.select_nth_unstable(MAX_FLOWS / 16).1;
+        let before = self.flows.len();
+        self.flows.retain(|_, flow| flow.last_packet > cutoff);
+        self.evicted += (before - self.flows.len()) as u64;
+    }
+
+    /// An event per connection with traffic since the last flush. Server
+    /// names are repeated on every report of their connection.
+    pub fn flush(&mut self, hostname: &str) -> Vec<LogEvent> {
+        if self.evicted > 0 {
+            warn!(
+                "Dropped {} connections past {} tracked at once; their traffic is not reported",
+                self.evicted, MAX_FLOWS
+            );
+            self.evicted = 0;
+        }
+        let timestamp = Utc::now();
+        let mut events = Vec::new();
+        self.flows.retain(|key, flow| {
+            if flow.traffic.packets == 0 {
+                return false;
+            }
+            let mut event = LogEvent::new(
+                Severity::Info,
+                EventType::NetworkSocket {
+                    local_addr: key.local.to_string(),
+                    remote_addr: Some(key.remote.to_string()),
+                    remote_host: None,
+                    protocol: key.transport.as_str().to_string(),
+                    state: if flow.closed { "closed" } else { "active" }.to_string(),
+                    traffic: Some(flow.traffic.clone()),
+                    process: None,
+                },
+                hostname.to_string(),
+            )
+            .with_tag(CAPTURE_TAG);
+            event.timestamp = timestamp;
+            events.push(event);
+
+            flow.traffic = Traffic {
+                sni: flow.traffic.sni.take(),
+                http_host: flow.traffic.http_host.take(),
+                ..Traffic::default()
+            };
+            !flow.closed
+        });
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ethernet, IPv4 and TCP headers around `payload`
+    fn tcp_frame(
+        src: [u8; 4],
+        sport: u16,
+        dst: [u8; 4],
+        dport: u16,
+        flags: u8,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(40 + payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&sport.to_be_bytes());
+        frame.extend_from_slice(&dport.to_be_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&[0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// A ClientHello with an SNI extension for `name`
+    fn client_hello(name: &str) -> Vec<u8> {
+        let mut server_name = vec![0];
+        server_name.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        server_name.extend_from_slice(name.as_bytes());
+        let mut extension = (server_name.len() as u16).to_be_bytes().to_vec();
+        extension.extend_from_slice(&server_name);
+
+        let mut extensions = vec![0xff, 0x01, 0, 1, 0];
+        extensions.extend_from_slice(&[0, 0]);
+        extensions.extend_from_slice(&(extension.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&extension);
+
+        let mut hello = vec![3, 3];
+        hello.extend_from_slice(&[7; 32]);
+        hello.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+        hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&extensions);
+
+        let mut handshake = vec![1, 0];
+        handshake.extend_from_slice(&(hello.len() as u16).to_be_bytes());
+        handshake.extend_from_slice(&hello);
+        let mut record = vec![0x16, 3, 1];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn test_server_names() {
+        assert_eq!(
+            sni(&client_hello("Example.COM")).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(sni(b"GET / HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(
+            http_host(b"GET /x HTTP/1.1\r\nUser-Agent: curl\r\nhost: Intranet.local:8080\r\n\r\n")
+                .as_deref(),
+            Some("intranet.local")
+        );
+        assert_eq!(http_host(b"SSH-2.0-OpenSSH_9.6\r\n"), None);
+    }
+
+    #[test]
+    fn test_flows_by_connection() {
+        let mut flows = Flows::new(["10.0.0.5".parse().unwrap()]);
+        let hello = tcp_frame(
+            [10, 0, 0, 5],
+            51000,
+            [93, 184, 216, 34],
+            443,
+            0x18,
+            &client_hello("example.com"),
+        );
+        let reply = tcp_frame(
+            [93, 184, 216, 34],
+            443,
+            [10, 0, 0, 5],
+            51000,
+            0x10,
+            &[0; 100],
+        );
+        let fin = tcp_frame(
+            [93, 184, 216, 34],
+            443,
+            [10, 0, 0, 5],
+            51000,
+            TCP_FIN | 0x10,
+            &[],
+        );
+        let other = tcp_frame([10, 0, 0, 7], 1234, [10, 0, 0, 8], 80, 0x10, &[]);
+        for frame in [&hello, &reply, &other] {
+            let packet = parse(LINKTYPE_ETHERNET, frame).unwrap();
+            flows.add(&packet, frame.len() as u64);
+        }
+
+        let events = flows.flush("web-1");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].tags, vec![CAPTURE_TAG.to_string()]);
+        match &events[0].event_type {
+            EventType::NetworkSocket {
+                local_addr,
+                remote_addr,
+                state,
+                traffic: Some(traffic),
+                ..
+            } => {
+                assert_eq!(local_addr, "10.0.0.5:51000");
+                assert_eq!(remote_addr.as_deref(), Some("93.184.216.34:443"));
+                assert_eq!(state, "active");
+                assert_eq!(traffic.bytes_sent, hello.len() as u64);
+                assert_eq!(traffic.bytes_received, reply.len() as u64);
+                assert_eq!(traffic.packets, 2);
+                assert_eq!(traffic.sni.as_deref(), Some("example.com"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // The server name carries over; a closed connection is reported once more
+        flows.add(&parse(LINKTYPE_ETHERNET, &fin).unwrap(), fin.len() as u64);
+        let events = flows.flush("web-1");
+        match &events[0].event_type {
+            EventType::NetworkSocket {
+                state,
+                traffic: Some(traffic),
+                ..
+            } => {
+                assert_eq!(state, "closed");
+                assert_eq!(traffic.packets, 1);
+                assert_eq!(traffic.sni.as_deref(), Some("example.com"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(flows.flush("web-1").is_empty());
+    }
+
+    fn udp(src: &str, dst: &str) -> Packet<'static> {
+        Packet {
+            src: src.parse().unwrap(),
+            dst: dst.parse().unwrap(),
+            transport: Transport::Udp,
+            flags: 0,
+            payload: &[],
+        }
+    }
+
+    #[test]
+    fn test_flows_without_addresses() {
+        // The first sender of a connection is taken as the local end
+        let mut flows = Flows::new([]);
+        flows.add(&udp("10.0.0.5:5353", "10.0.0.9:53"), 60);
+        flows.add(&udp("10.0.0.9:53", "10.0.0.5:5353"), 100);
+        let events = flows.flush("web-1");
+        assert_eq!(events.len(), 1);
+        match &events[0].event_type {
+            EventType::NetworkSocket {
+                local_addr,
+                traffic: Some(traffic),
+                ..
+            } => {
+                assert_eq!(local_addr, "10.0.0.5:5353");
+                assert_eq!(traffic.bytes_sent, 60);
+                assert_eq!(traffic.bytes_received, 100);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_flows_are_capped() {
+        let mut flows = Flows::new(["10.0.0.5".parse().unwrap()]);
+        let first = udp("10.0.0.5:40000", "10.1.0.1:53");
+        flows.add(&first, 60);
+        for n in 0..MAX_FLOWS {
+            let remote = SocketAddr::from(([10, 2, (n >> 8) as u8, n as u8], 53));
+            flows.add(
+                &Packet {
+                    dst: remote,
+                    ..udp("10.0.0.5:40001", "10.1.0.1:53")
+                },
+                60,
+            );
+        }
+        assert!(flows.flows.len() <= MAX_FLOWS);
+        assert!(flows.evicted > 0);
+
+        // The oldest connection went first
+        let events = flows.flush("web-1");
+        assert!(events.len() < MAX_FLOWS);
+        assert!(!events.iter().any(|event| matches!(
+            &event.event_type,
+            EventType::NetworkSocket { remote_addr, .. } if remote_addr.as_deref() == Some("10.1.0.1:53")
+        )));
+    }
+}
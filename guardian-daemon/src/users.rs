@@ -0,0 +1,75 @@
+use guardian_common::EventUser;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Usernames by uid; accounts rarely change while the daemon runs
+static NAMES: OnceLock<Mutex<HashMap<u32, Option<String>>>> = OnceLock::new();
+
+#[cfg(unix)]
+fn lookup_name(uid: u32) -> Option<String> {
+    nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid))
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+}
+
+#[cfg(not(unix))]
+fn lookup_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// User for `uid`, with the name resolved from the account database
+pub fn event_user(uid: u32) -> EventUser {
+    let names = NAMES.get_or_init(Default::default);
+    let mut names = names.lock().unwrap_or_else(|e| e.into_inner());
+    let username = names.entry(uid).or_insert_with(|| lookup_name(uid)).clone();
+    EventUser { uid, username }
+}
+
+/// Owner of the file at `path`, or `None` if it is gone or ownership does not apply
+pub fn file_owner(path: &Path) -> Option<EventUser> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::symlink_metadata(path).ok()?;
+        Some(event_user(metadata.uid()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// User a process runs as
+pub fn process_owner(process: &sysinfo::Process) -> Option<EventUser> {
+    #[cfg(unix)]
+    {
+        process.user_id().map(|uid| event_user(**uid))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = process;
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_and_file_owner() {
+        let root = event_user(0);
+        assert_eq!(root.username.as_deref(), Some("root"));
+
+        let file = std::env::temp_dir().join(format!("guardian-owner-{}", std::process::id()));
+        std::fs::write(&file, b"x").unwrap();
+        let owner = file_owner(&file).unwrap();
+        assert_eq!(owner.uid, nix::unistd::getuid().as_raw());
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(file_owner(&file).is_none());
+    }
+}
@@ -0,0 +1,607 @@
+//! Remote YARA sources: files served over HTTPS and git repositories,
+//! checked on a schedule and compiled into the running scanner. An update is
+//! only swapped in once the whole rule set compiles with it; otherwise the
+//! scanner keeps the last good version, which is also what a restart loads.
+//! Files are checked against `[rule_packs]` like rule packs, with their
+//! signatures in `.sig` files next to them.
+
+use crate::config::{RulePackConfig, SignaturePolicy, YaraConfig, YaraSourceConfig};
+use crate::provision::write_if_changed;
+use crate::queue::EventSender;
+use crate::rule_pack::{self, PackSignature, SignatureCheck};
+use crate::scanner::{self, YaraScanner};
+use anyhow::{bail, Context, Result};
+use guardian_common::{EventType, LogEvent, Severity};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Tag on events about remote YARA updates
+pub const YARA_UPDATE_TAG: &str = "yara_update";
+
+/// Largest file taken from an HTTPS source
+const MAX_SOURCE_BYTES: usize = 16 * 1024 * 1024;
+
+/// One version of a source's files
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SourceVersion {
+    /// ETag of the file, or commit of the repository, when known
+    pub version: Option<String>,
+    /// (file name, source) pairs
+    pub files: Vec<(String, String)>,
+    /// Signature of each signed file, by file name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signatures: BTreeMap<String, PackSignature>,
+}
+
+/// Problems with a source's settings
+pub fn validate(source: &YaraSourceConfig) -> Result<(), String> {
+    let name = Path::new(&source.name);
+    if source.name.is_empty() || name.file_name() != Some(name.as_os_str()) {
+        return Err(format!(
+            "YARA source name {:?} must be a plain file name",
+            source.name
+        ));
+    }
+    match (&source.url, &source.git) {
+        (Some(url), None) if url.starts_with("https://") => {}
+        (Some(url), None) => {
+            return Err(format!("YARA source {}: {} is not https", source.name, url));
+        }
+        (None, Some(_)) if source.branch.as_ref().is_some_and(|b| b.starts_with('-')) => {
+            return Err(format!(
+                "YARA source {}: branch must not start with -",
+                source.name
+            ));
+        }
+        (None, Some(_)) => {}
+        _ => {
+            return Err(format!(
+                "YARA source {} needs exactly one of url and git",
+                source.name
+            ))
+        }
+    }
+    let escapes = source
+        .path
+        .iter()
+        .flat_map(|p| p.components())
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(format!(
+            "YARA source {}: path must stay inside the repository",
+            source.name
+        ));
+    }
+    Ok(())
+}
+
+/// The last good version of each configured source, from `cache_dir`
+pub fn load_cache(config: &YaraConfig) -> BTreeMap<String, SourceVersion> {
+    let mut cache = BTreeMap::new();
+    for source in &config.sources {
+        let path = config.cache_dir.join(format!("{}.json", source.name));
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        match serde_json::from_str(&contents) {
+            Ok(version) => {
+                cache.insert(source.name.clone(), version);
+            }
+            Err(e) => warn!("Ignoring YARA cache {}: {}", path.display(), e),
+        }
+    }
+    cache
+}
+
+/// The files of `rules_dir` followed by those of the configured sources in
+/// `remote`, named `<source>/<file>`
+pub fn all_sources(
+    config: &YaraConfig,
+    remote: &BTreeMap<String, SourceVersion>,
+) -> Result<Vec<(String, String)>> {
+    let mut sources = match &config.rules_dir {
+        Some(dir) => scanner::read_sources(dir)?,
+        None => Vec::new(),
+    };
+    for source in &config.sources {
+        let Some(version) = remote.get(&source.name) else {
+            continue;
+        };
+        for (file, contents) in &version.files {
+            sources.push((format!("{}/{}", source.name, file), contents.clone()));
+        }
+    }
+    Ok(sources)
+}
+
+/// Compiles updates into the shared scanner
+pub struct YaraUpdater {
+    config: YaraConfig,
+    /// Signature policy and trusted keys
+    trust: RulePackConfig,
+    scanner: Arc<YaraScanner>,
+    /// Last good version of each source
+    current: Mutex<BTreeMap<String, SourceVersion>>,
+}
+
+impl YaraUpdater {
+    pub fn new(config: YaraConfig, trust: RulePackConfig, scanner: Arc<YaraScanner>) -> Self {
+        let current = Mutex::new(load_cache(&config));
+        Self {
+            config,
+            trust,
+            scanner,
+            current,
+        }
+    }
+
+    fn current(&self, name: &str) -> Option<SourceVersion> {
+        self.current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// Check the signatures of `update`, then compile it with the rest of
+    /// the rules, swap it into the scanner and cache it. When a file is
+    /// refused or the rules do not compile nothing changes and the error
+    /// says why.
+    pub fn apply(&self, name: &str, update: SourceVersion) -> Result<()> {
+        self.verify(name, &update)?;
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let mut candidate = current.clone();
+        candidate.insert(name.to_string(), update);
+
+        let compiled = YaraScanner::with_sources(&all_sources(&self.config, &candidate)?)?;
+        let cache = self.config.cache_dir.join(format!("{}.json", name));
+        write_if_changed(&cache, &serde_json::to_string(&candidate[name])?)?;
+        self.scanner.replace(compiled);
+        *current = candidate;
+        Ok(())
+    }
+
+    /// Apply the signature policy to each file of `update`
+    fn verify(&self, name: &str, update: &SourceVersion) -> Result<()> {
+        if self.trust.policy() == SignaturePolicy::Off {
+            return Ok(());
+        }
+        for (file, contents) in &update.files {
+            let check = match update.signatures.get(file) {
+                Some(signature) => signature.check(contents.as_bytes(), &self.trust.trusted_keys),
+                None => SignatureCheck::Unsigned,
+            };
+            let what = format!("YARA source {} file {}", name, file);
+            if let Some(warning) = rule_pack::enforce(&what, check, &self.trust)? {
+                warn!("{}", warning);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check every configured source on its schedule, reporting each applied or
+/// rejected update as an event
+pub fn start(
+    config: YaraConfig,
+    trust: RulePackConfig,
+    scanner: Arc<YaraScanner>,
+    tx: EventSender,
+    hostname: String,
+) {
+    let sources: Vec<YaraSourceConfig> = config
+        .sources
+        .iter()
+        .filter(|source| match validate(source) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Skipping {}", e);
+                false
+            }
+        })
+        .cloned()
+        .collect();
+    if sources.is_empty() {
+        return;
+    }
+
+    let updater = Arc::new(YaraUpdater::new(config, trust, scanner));
+    for source in sources {
+        tokio::spawn(run_source(
+            updater.clone(),
+            source,
+            tx.clone(),
+            hostname.clone(),
+        ));
+    }
+}
+
+async fn run_source(
+    updater: Arc<YaraUpdater>,
+    source: YaraSourceConfig,
+    tx: EventSender,
+    hostname: String,
+) {
+    let http = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .danger_accept_invalid_certs(!source.verify_tls)
+        .build()
+    {
+        Ok(http) => http,
+        Err(e) => {
+            warn!("YARA source {} disabled: {}", source.name, e);
+            return;
+        }
+    };
+
+    let mut seen = updater.current(&source.name).and_then(|v| v.version);
+    // Files of the last update that did not compile, so it is reported once
+    let mut rejected: Option<Vec<(String, String)>> = None;
+    let mut interval = tokio::time::interval(Duration::from_secs(source.pull_secs.max(60)));
+    loop {
+        interval.tick().await;
+        let signed = updater.trust.policy() != SignaturePolicy::Off;
+        let update = match fetch(&http, &updater.config, &source, seen.as_deref(), signed).await {
+            Ok(Some(update)) => update,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    "YARA source {} fetch failed, keeping the last good rules: {:#}",
+                    source.name, e
+                );
+                continue;
+            }
+        };
+        seen = update.version.clone();
+        let current = updater.current(&source.name).map(|v| v.files);
+        if current.as_ref() == Some(&update.files) || rejected.as_ref() == Some(&update.files) {
+            continue;
+        }
+
+        let label = match &update.version {
+            Some(version) => format!("{} at {}", source.name, version),
+            None => source.name.clone(),
+        };
+        let files = update.files.clone();
+        let (name, applier) = (source.name.clone(), updater.clone());
+        let result = tokio::task::spawn_blocking(move || applier.apply(&name, update))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        let event = match result {
+            Ok(()) => {
+                rejected = None;
+                info!("YARA source {} compiled and loaded", label);
+                update_event(
+                    Severity::Info,
+                    format!("YARA source {} loaded: {} file(s)", label, files.len()),
+                    &hostname,
+                )
+            }
+            Err(e) => {
+                rejected = Some(files);
+                warn!("YARA source {} rejected: {:#}", label, e);
+                update_event(
+                    Severity::Medium,
+                    format!(
+                        "YARA source {} rejected, keeping the last good rules: {:#}",
+                        label, e
+                    ),
+                    &hostname,
+                )
+            }
+        };
+        if tx.send(event).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn update_event(severity: Severity, message: String, hostname: &str) -> LogEvent {
+    let level = if severity == Severity::Info {
+        "info"
+    } else {
+        "warning"
+    };
+    LogEvent::new(
+        severity,
+        EventType::SystemLog {
+            source: "guardian-daemon".to_string(),
+            level: level.to_string(),
+            message,
+        },
+        hostname.to_string(),
+    )
+    .with_tag(YARA_UPDATE_TAG)
+}
+
+/// The source's files, unless they are still at version `seen`, with their
+/// signatures when `signed`
+async fn fetch(
+    http: &reqwest::Client,
+    config: &YaraConfig,
+    source: &YaraSourceConfig,
+    seen: Option<&str>,
+    signed: bool,
+) -> Result<Option<SourceVersion>> {
+    if let Some(url) = &source.url {
+        return fetch_url(http, source, url, seen, signed).await;
+    }
+    let checkout = config.cache_dir.join(format!("{}.git", source.name));
+    let source = source.clone();
+    let seen = seen.map(str::to_string);
+    tokio::task::spawn_blocking(move || fetch_git(&source, &checkout, seen.as_deref())).await?
+}
+
+async fn fetch_url(
+    http: &reqwest::Client,
+    source: &YaraSourceConfig,
+    url: &str,
+    seen: Option<&str>,
+    signed: bool,
+) -> Result<Option<SourceVersion>> {
+    let mut request = http.get(url);
+    if let Some(etag) = seen {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Request to {} failed", url))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let version = response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_string);
+    let contents = String::from_utf8(read_capped(response).await?)
+        .with_context(|| format!("{} is not UTF-8", url))?;
+    let file = format!("{}.yar", source.name);
+
+    // The signature sits next to the file; a missing one leaves it unsigned
+    let mut signatures = BTreeMap::new();
+    if signed {
+        let sig_url = format!("{}.sig", url);
+        let response = http
+            .get(&sig_url)
+            .send()
+            .await
+            .with_context(|| format!("Request to {} failed", sig_url))?;
+        if response.status() != StatusCode::NOT_FOUND {
+            let signature = read_capped(response.error_for_status()?).await?;
+            let signature = toml::from_str(&String::from_utf8_lossy(&signature))
+                .with_context(|| format!("{} is not a signature", sig_url))?;
+            signatures.insert(file.clone(), signature);
+        }
+    }
+    Ok(Some(SourceVersion {
+        version,
+        files: vec![(file, contents)],
+        signatures,
+    }))
+}
+
+/// The body of `response`, refusing bodies over [`MAX_SOURCE_BYTES`]
+async fn read_capped(mut response: reqwest::Response) -> Result<Vec<u8>> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_SOURCE_BYTES as u64)
+    {
+        bail!(
+            "{} is larger than {} bytes",
+            response.url(),
+            MAX_SOURCE_BYTES
+        );
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_SOURCE_BYTES {
+            bail!(
+                "{} is larger than {} bytes",
+                response.url(),
+                MAX_SOURCE_BYTES
+            );
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Clone or fast-forward the repository into `checkout` and read its rules,
+/// unless its commit is still `seen`
+fn fetch_git(
+    source: &YaraSourceConfig,
+    checkout: &Path,
+    seen: Option<&str>,
+) -> Result<Option<SourceVersion>> {
+    let Some(repository) = &source.git else {
+        bail!("YARA source {} has no repository", source.name);
+    };
+    let branch = source.branch.as_deref().unwrap_or("HEAD");
+    if checkout.join(".git").exists() {
+        git(
+            source,
+            Some(checkout),
+            &["fetch", "--depth", "1", "--", "origin", branch],
+        )?;
+        git(source, Some(checkout), &["reset", "--hard", "FETCH_HEAD"])?;
+    } else {
+        let checkout = checkout.to_string_lossy();
+        let mut args = vec!["clone", "--depth", "1"];
+        if let Some(branch) = &source.branch {
+            args.extend(["--branch", branch.as_str()]);
+        }
+        args.extend(["--", repository.as_str(), checkout.as_ref()]);
+        git(source, None, &args)?;
+    }
+
+    let commit = git(source, Some(checkout), &["rev-parse", "HEAD"])?;
+    if seen == Some(commit.as_str()) {
+        return Ok(None);
+    }
+    let dir = match &source.path {
+        Some(path) => checkout.join(path),
+        None => checkout.to_path_buf(),
+    };
+    let files = scanner::read_sources(&dir)?;
+    let mut signatures = BTreeMap::new();
+    for (file, _) in &files {
+        let path = dir.join(format!("{}.sig", file));
+        let Ok(signature) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let signature = toml::from_str(&signature)
+            .with_context(|| format!("{} is not a signature", path.display()))?;
+        signatures.insert(file.clone(), signature);
+    }
+    Ok(Some(SourceVersion {
+        version: Some(commit),
+        files,
+        signatures,
+    }))
+}
+
+/// Run git without prompting, returning its trimmed output
+fn git(source: &YaraSourceConfig, dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    command.env("GIT_TERMINAL_PROMPT", "0");
+    if !source.verify_tls {
+        command.args(["-c", "http.sslVerify=false"]);
+    }
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command.args(args).output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, url: Option<&str>, git: Option<&str>) -> YaraSourceConfig {
+        YaraSourceConfig {
+            name: name.to_string(),
+            url: url.map(str::to_string),
+            git: git.map(str::to_string),
+            branch: None,
+            path: None,
+            pull_secs: 3600,
+            verify_tls: true,
+        }
+    }
+
+    fn version(version: &str, rule: &str) -> SourceVersion {
+        SourceVersion {
+            version: Some(version.to_string()),
+            files: vec![("feed.yar".to_string(), rule.to_string())],
+            signatures: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_sources() {
+        let url = Some("https://rules.example.com/web.yar");
+        let repo = Some("https://git.example.com/rules.git");
+        assert!(validate(&source("web", url, None)).is_ok());
+        assert!(validate(&source("web", None, repo)).is_ok());
+        assert!(validate(&source("web", None, None)).is_err());
+        assert!(validate(&source("web", url, repo)).is_err());
+        assert!(validate(&source(
+            "web",
+            Some("http://rules.example.com/web.yar"),
+            None
+        ))
+        .is_err());
+        assert!(validate(&source("../web", url, None)).is_err());
+
+        let mut escaping = source("web", None, repo);
+        escaping.path = Some("../../etc".into());
+        assert!(validate(&escaping).is_err());
+        escaping.path = Some("rules/linux".into());
+        assert!(validate(&escaping).is_ok());
+        escaping.branch = Some("--upload-pack=touch".to_string());
+        assert!(validate(&escaping).is_err());
+    }
+
+    #[test]
+    fn test_update_signatures() {
+        use ring::rand::SystemRandom;
+        use ring::signature::Ed25519KeyPair;
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let trust = RulePackConfig {
+            signatures: None,
+            trusted_keys: vec![rule_pack::PackSignature::sign(&key, b"").key],
+        };
+        let updater = YaraUpdater::new(
+            YaraConfig::default(),
+            trust,
+            Arc::new(YaraScanner::new().unwrap()),
+        );
+
+        let mut update = version("\"v1\"", "rule feed_marker { condition: filesize == 0 }");
+        // Trusted keys make signatures required
+        assert!(updater.verify("feed", &update).is_err());
+        let signature = PackSignature::sign(&key, update.files[0].1.as_bytes());
+        update.signatures.insert("feed.yar".to_string(), signature);
+        updater.verify("feed", &update).unwrap();
+
+        update.files[0]
+            .1
+            .push_str("rule extra { condition: true }\n");
+        assert!(updater.verify("feed", &update).is_err());
+    }
+
+    #[test]
+    fn test_update_rolls_back_on_compile_error() {
+        let dir = std::env::temp_dir().join(format!("guardian-yara-{}", uuid::Uuid::new_v4()));
+        let config = YaraConfig {
+            sources: vec![source(
+                "feed",
+                Some("https://rules.example.com/feed.yar"),
+                None,
+            )],
+            cache_dir: dir.clone(),
+            ..YaraConfig::default()
+        };
+        let scanner = Arc::new(YaraScanner::new().unwrap());
+        let updater = YaraUpdater::new(config.clone(), RulePackConfig::default(), scanner);
+
+        let good = version("\"v1\"", "rule feed_marker { condition: filesize == 0 }");
+        updater.apply("feed", good.clone()).unwrap();
+        assert_eq!(updater.current("feed"), Some(good.clone()));
+
+        // A broken update, or one clashing with a built-in rule, is not taken
+        let broken = version("\"v2\"", "rule feed_marker { condition: ");
+        assert!(updater.apply("feed", broken).is_err());
+        let clash = version("\"v3\"", "rule eicar_test_file { condition: true }");
+        assert!(updater.apply("feed", clash).is_err());
+        assert_eq!(updater.current("feed"), Some(good.clone()));
+
+        // The last good version is what a restart loads
+        assert_eq!(load_cache(&config)["feed"], good);
+        let sources = all_sources(&config, &load_cache(&config)).unwrap();
+        assert_eq!(sources[0].0, "feed/feed.yar");
+        assert!(YaraScanner::load(&config).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
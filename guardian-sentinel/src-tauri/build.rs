@@ -1,3 +1,5 @@
 fn main() {
+    // `sqlx::migrate!` embeds the migrations at compile time
+    println!("cargo:rerun-if-changed=migrations");
     tauri_build::build()
 }
@@ -0,0 +1,102 @@
+-- Schema as created before migrations were tracked. Every statement is
+-- idempotent so databases from those releases adopt this migration as-is.
+
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY NOT NULL,
+    timestamp TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    event_data TEXT NOT NULL,
+    hostname TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    rule_triggered INTEGER NOT NULL DEFAULT 0,
+    rule_name TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_timestamp ON events(timestamp DESC);
+CREATE INDEX IF NOT EXISTS idx_severity ON events(severity);
+CREATE INDEX IF NOT EXISTS idx_rule_triggered ON events(rule_triggered);
+
+-- Hourly rollups shipped by agents instead of (or alongside) raw events
+CREATE TABLE IF NOT EXISTS event_rollups (
+    hostname TEXT NOT NULL,
+    period_start TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (hostname, period_start, event_type, severity)
+);
+
+CREATE TABLE IF NOT EXISTS rollup_paths (
+    hostname TEXT NOT NULL,
+    period_start TEXT NOT NULL,
+    path TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (hostname, period_start, path)
+);
+
+-- Per-principal alert acknowledgements
+CREATE TABLE IF NOT EXISTS alert_acks (
+    event_id TEXT NOT NULL,
+    principal TEXT NOT NULL,
+    acknowledged_at TEXT NOT NULL,
+    PRIMARY KEY (event_id, principal)
+);
+
+-- Alert resolutions; acknowledgement times come from alert_acks
+CREATE TABLE IF NOT EXISTS alert_resolutions (
+    event_id TEXT PRIMARY KEY NOT NULL,
+    resolved_by TEXT NOT NULL,
+    resolved_at TEXT NOT NULL
+);
+
+-- SLA breaches already reported, so each is raised once
+CREATE TABLE IF NOT EXISTS sla_breaches (
+    event_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    breached_at TEXT NOT NULL,
+    PRIMARY KEY (event_id, kind)
+);
+
+-- Last delivered digest per subscriber and channel
+CREATE TABLE IF NOT EXISTS digest_state (
+    subscriber TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    last_sent TEXT NOT NULL,
+    PRIMARY KEY (subscriber, channel)
+);
+
+-- Agents that have announced themselves, locally or to the collector
+CREATE TABLE IF NOT EXISTS hosts (
+    hostname TEXT PRIMARY KEY NOT NULL,
+    agent_version TEXT NOT NULL,
+    address TEXT NOT NULL,
+    capabilities TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+-- Latest heartbeat per agent, for offline detection
+CREATE TABLE IF NOT EXISTS heartbeats (
+    hostname TEXT PRIMARY KEY NOT NULL,
+    agent_version TEXT NOT NULL,
+    uptime_secs INTEGER NOT NULL,
+    forward_pending INTEGER,
+    forward_dropped INTEGER,
+    last_heartbeat TEXT NOT NULL,
+    expected_by TEXT NOT NULL,
+    offline_since TEXT
+);
+
+-- Agents allowed to forward to the collector, keyed by their certificate
+CREATE TABLE IF NOT EXISTS enrollments (
+    hostname TEXT PRIMARY KEY NOT NULL,
+    token_hash TEXT,
+    token_expires TEXT NOT NULL,
+    status TEXT NOT NULL,
+    fingerprint TEXT UNIQUE,
+    created_at TEXT NOT NULL,
+    enrolled_at TEXT,
+    revoked_at TEXT
+);
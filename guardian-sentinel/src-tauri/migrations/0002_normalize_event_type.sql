@@ -0,0 +1,7 @@
+-- `events.event_type` used to hold the whole serialized event type, duplicating
+-- `event_data`. It now holds only the type tag, e.g. `file_integrity`.
+UPDATE events
+SET event_type = json_extract(event_data, '$.type')
+WHERE event_type LIKE '{%' AND json_valid(event_data);
+
+CREATE INDEX IF NOT EXISTS idx_event_type ON events(event_type);
@@ -0,0 +1,14 @@
+-- One row per event tag, so exact tag filters and facet counts use an index
+-- instead of LIKE over the `events.tags` JSON array, which stays as written.
+CREATE TABLE IF NOT EXISTS event_tags (
+    event_id TEXT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (event_id, tag)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS idx_event_tags_tag ON event_tags(tag, event_id);
+
+INSERT OR IGNORE INTO event_tags (event_id, tag)
+SELECT events.id, tag.value
+FROM events, json_each(events.tags) AS tag
+WHERE json_valid(events.tags) AND tag.type = 'text';
@@ -0,0 +1,12 @@
+-- Named search presets shared by every analyst. `filter` is a JSON
+-- SearchFilter; `window_secs`, when set, makes the range relative to the
+-- time the search runs.
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    filter TEXT NOT NULL,
+    window_secs INTEGER,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,37 @@
+-- The alert queue: one row per rule-triggered event, tracking who is working
+-- on it. Acknowledgements and resolutions recorded before the queue existed
+-- carry over.
+CREATE TABLE IF NOT EXISTS alerts (
+    event_id TEXT PRIMARY KEY NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'open',
+    assignee TEXT,
+    close_reason TEXT,
+    acknowledged_by TEXT,
+    acknowledged_at TEXT,
+    closed_by TEXT,
+    closed_at TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_alerts_status ON alerts(status);
+CREATE INDEX IF NOT EXISTS idx_alerts_assignee ON alerts(assignee);
+
+INSERT OR IGNORE INTO alerts (
+    event_id, status, acknowledged_by, acknowledged_at, closed_by, closed_at, updated_at
+)
+SELECT e.id,
+       CASE
+           WHEN r.event_id IS NOT NULL THEN 'closed'
+           WHEN a.acknowledged_at IS NOT NULL THEN 'acknowledged'
+           ELSE 'open'
+       END,
+       a.principal, a.acknowledged_at, r.resolved_by, r.resolved_at,
+       COALESCE(r.resolved_at, a.acknowledged_at, e.timestamp)
+FROM events e
+LEFT JOIN alert_resolutions r ON r.event_id = e.id
+LEFT JOIN (
+    SELECT event_id, principal, MIN(acknowledged_at) AS acknowledged_at
+    FROM alert_acks
+    GROUP BY event_id
+) a ON a.event_id = e.id
+WHERE e.rule_triggered = 1;
@@ -0,0 +1,26 @@
+-- Incidents group related events for investigation. An open incident with a
+-- correlation key also collects new rule hits with the same key.
+CREATE TABLE IF NOT EXISTS incidents (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open',
+    correlation_key TEXT,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    closed_at TEXT
+);
+
+-- At most one open incident collects each correlation key
+CREATE UNIQUE INDEX IF NOT EXISTS idx_incidents_open_key
+    ON incidents(correlation_key) WHERE status = 'open';
+
+CREATE TABLE IF NOT EXISTS incident_events (
+    incident_id TEXT NOT NULL REFERENCES incidents(id) ON DELETE CASCADE,
+    event_id TEXT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    added_by TEXT NOT NULL,
+    added_at TEXT NOT NULL,
+    PRIMARY KEY (incident_id, event_id)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS idx_incident_events_event ON incident_events(event_id);
@@ -0,0 +1,3 @@
+-- Every rule an event triggered, as a JSON array, most severe first.
+-- `rule_name` keeps the most severe one; older rows leave this NULL.
+ALTER TABLE events ADD COLUMN rules_matched TEXT;
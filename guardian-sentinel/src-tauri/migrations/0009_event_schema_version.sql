@@ -0,0 +1,4 @@
+-- Layout version each event was written with, so stored rows are upgraded
+-- from their own version. Rows from before this column count as version 1;
+-- upgrading a version 2 event from version 1 leaves it unchanged.
+ALTER TABLE events ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;
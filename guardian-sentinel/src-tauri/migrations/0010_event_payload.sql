@@ -0,0 +1,11 @@
+-- Optional zstd compression of event payloads. A compressed row stores its
+-- payload in `event_payload` and leaves `event_data` empty; uncompressed rows
+-- leave `event_payload` NULL.
+CREATE TABLE IF NOT EXISTS payload_dictionaries (
+    -- zstd dictionary ID, also recorded in each frame compressed with it
+    id INTEGER PRIMARY KEY,
+    dictionary BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+ALTER TABLE events ADD COLUMN event_payload BLOB;
@@ -0,0 +1,206 @@
+//! Append-only log of state-changing operator actions, kept apart from the
+//! event stream so it survives event retention and cannot be edited.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+/// Most entries one query returns
+pub const MAX_AUDIT_ENTRIES: i64 = 1000;
+
+/// How an audited action ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    /// Permitted but failed, e.g. an unknown alert id
+    Failure,
+    /// Refused by access control
+    Denied,
+}
+
+impl AuditOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+            AuditOutcome::Denied => "denied",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(serde_json::from_value(serde_json::json!(s))?)
+    }
+}
+
+/// One recorded action
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: String,
+    pub actor: String,
+    /// Command name, e.g. `close_alert`
+    pub action: String,
+    /// What the action applied to, e.g. an event id or hostname
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+}
+
+/// Criteria for listing audit entries; every set field must match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    /// Earliest entry time, inclusive
+    pub from: Option<DateTime<Utc>>,
+    /// Latest entry time, exclusive
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Append an entry
+pub async fn record(
+    pool: &SqlitePool,
+    actor: &str,
+    action: &str,
+    target: Option<&str>,
+    outcome: AuditOutcome,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (timestamp, actor, action, target, outcome, error)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(actor)
+    .bind(action)
+    .bind(target)
+    .bind(outcome.as_str())
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Entries matching `filter`, newest first
+pub async fn get_entries(
+    pool: &SqlitePool,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<Vec<AuditEntry>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, timestamp, actor, action, target, outcome, error
+        FROM audit_log
+        WHERE (?1 IS NULL OR actor = ?1) AND (?2 IS NULL OR action = ?2)
+          AND (?3 IS NULL OR timestamp >= ?3) AND (?4 IS NULL OR timestamp < ?4)
+        ORDER BY id DESC
+        LIMIT ?5
+        "#,
+    )
+    .bind(&filter.actor)
+    .bind(&filter.action)
+    .bind(filter.from.map(|t| t.to_rfc3339()))
+    .bind(filter.to.map(|t| t.to_rfc3339()))
+    .bind(limit.clamp(1, MAX_AUDIT_ENTRIES))
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(AuditEntry {
+                id: row.get("id"),
+                timestamp: row.get("timestamp"),
+                actor: row.get("actor"),
+                action: row.get("action"),
+                target: row.get("target"),
+                outcome: AuditOutcome::parse(row.get("outcome"))?,
+                error: row.get("error"),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+
+    #[tokio::test]
+    async fn test_audit_log_is_append_only_and_filterable() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let pool = database::init_database(&path).await.unwrap();
+
+        record(
+            &pool,
+            "alice",
+            "close_alert",
+            Some("e1"),
+            AuditOutcome::Success,
+            None,
+        )
+        .await
+        .unwrap();
+        record(
+            &pool,
+            "bob",
+            "revoke_agent",
+            Some("web-1"),
+            AuditOutcome::Failure,
+            Some("web-1 is not enrolled"),
+        )
+        .await
+        .unwrap();
+        record(
+            &pool,
+            "bob",
+            "authorize",
+            Some("manage_agents"),
+            AuditOutcome::Denied,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let all = get_entries(&pool, &AuditFilter::default(), 100)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].outcome, AuditOutcome::Denied);
+
+        let bob = AuditFilter {
+            actor: Some("bob".to_string()),
+            action: Some("revoke_agent".to_string()),
+            ..AuditFilter::default()
+        };
+        let entries = get_entries(&pool, &bob, 100).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].error.as_deref(), Some("web-1 is not enrolled"));
+
+        let future = AuditFilter {
+            from: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..AuditFilter::default()
+        };
+        assert!(get_entries(&pool, &future, 100).await.unwrap().is_empty());
+
+        // Entries cannot be rewritten or removed
+        assert!(sqlx::query("UPDATE audit_log SET actor = 'mallory'")
+            .execute(&pool)
+            .await
+            .is_err());
+        assert!(sqlx::query("DELETE FROM audit_log")
+            .execute(&pool)
+            .await
+            .is_err());
+        assert_eq!(
+            get_entries(&pool, &AuditFilter::default(), 100)
+                .await
+                .unwrap(),
+            all
+        );
+    }
+}
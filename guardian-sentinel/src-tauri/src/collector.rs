@@ -0,0 +1,698 @@
+//! Collector mode: accept events forwarded by remote daemons over TLS and
+//! store them with the host that sent them, turning the console into a small
+//! fleet console. Agents enroll with a one-time token and then authenticate
+//! with the client certificate issued to them.
+
+use crate::enrollment::{self, AgentCa};
+use crate::AppState;
+use anyhow::{bail, Context, Result};
+use guardian_common::ipc::{read_frame, write_frame, Frame, PROTOCOL_VERSION};
+use guardian_common::{AgentHello, ControlCommand, EventSigner, LogEvent};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+/// Time a forwarder gets to introduce itself
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Listener settings, from `collector.toml` in the app data directory or the
+/// file named by `GUARDIAN_COLLECTOR_CONFIG`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollectorConfig {
+    /// Address to accept forwarders on, e.g. `0.0.0.0:7443`
+    pub listen: String,
+
+    /// PEM certificate chain presented to forwarders
+    pub cert_file: PathBuf,
+
+    /// PEM private key for `cert_file`
+    pub key_file: PathBuf,
+
+    /// CA that signs agent certificates; created on first start.
+    /// Defaults to `agent-ca.pem` next to `cert_file`.
+    #[serde(default)]
+    pub ca_cert_file: Option<PathBuf>,
+
+    /// Private key of the agent CA, defaults to `agent-ca-key.pem`
+    #[serde(default)]
+    pub ca_key_file: Option<PathBuf>,
+
+    /// Refuse forwarders without an enrolled client certificate
+    #[serde(default = "default_require_enrollment")]
+    pub require_enrollment: bool,
+}
+
+fn default_require_enrollment() -> bool {
+    true
+}
+
+impl CollectorConfig {
+    /// Load the collector config; `None` when collector mode is not configured
+    pub fn load(data_dir: Option<&Path>) -> Result<Option<Self>> {
+        let path = match std::env::var("GUARDIAN_COLLECTOR_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => match data_dir.map(|d| d.join("collector.toml")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+
+        info!("Loading collector config from {}", path.display());
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read collector config {}", path.display()))?;
+        let config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse collector config {}", path.display()))?;
+        Ok(Some(config))
+    }
+
+    fn agent_ca(&self) -> Result<AgentCa> {
+        let dir = self.cert_file.parent().unwrap_or(Path::new("."));
+        let cert = self
+            .ca_cert_file
+            .clone()
+            .unwrap_or_else(|| dir.join("agent-ca.pem"));
+        let key = self
+            .ca_key_file
+            .clone()
+            .unwrap_or_else(|| dir.join("agent-ca-key.pem"));
+        AgentCa::load_or_create(&cert, &key)
+    }
+
+    /// Server TLS that asks for, but does not demand, an agent certificate:
+    /// agents without one may still enroll
+    fn tls(&self, ca: &AgentCa) -> Result<ServerConfig> {
+        let certs = std::fs::read(&self.cert_file)
+            .with_context(|| format!("Failed to read {}", self.cert_file.display()))?;
+        let certs = rustls_pemfile::certs(&mut certs.as_slice()).collect::<Result<Vec<_>, _>>()?;
+        let key = std::fs::read(&self.key_file)
+            .with_context(|| format!("Failed to read {}", self.key_file.display()))?;
+        let key = rustls_pemfile::private_key(&mut key.as_slice())?
+            .with_context(|| format!("No private key in {}", self.key_file.display()))?;
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(ca.roots()?))
+            .allow_unauthenticated()
+            .build()?;
+        Ok(ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs, key)?)
+    }
+}
+
+/// What every forwarder connection needs
+struct Collector {
+    state: Arc<Mutex<AppState>>,
+    events: mpsc::Sender<LogEvent>,
+    ca: AgentCa,
+    require_enrollment: bool,
+    /// With a signing key set, what agents didn't sign is dropped
+    signer: Option<EventSigner>,
+}
+
+impl Collector {
+    /// Hostname of the enrolled agent holding the certificate `fingerprint`;
+    /// the error is the reason given to the agent
+    async fn enrolled_host(&self, fingerprint: &str) -> Result<String> {
+        match self
+            .state
+            .lock()
+            .await
+            .agent_enrollment(fingerprint)
+            .await?
+        {
+            Some((hostname, status)) if status == "enrolled" => Ok(hostname),
+            Some((hostname, _)) => bail!("Agent {} has been revoked", hostname),
+            None => bail!("Certificate is not enrolled"),
+        }
+    }
+}
+
+/// Accept forwarders until the process exits. Stored events are also passed
+/// to `events` for live display.
+pub async fn run(
+    config: CollectorConfig,
+    state: Arc<Mutex<AppState>>,
+    events: mpsc::Sender<LogEvent>,
+) -> Result<()> {
+    let ca = config.agent_ca()?;
+    let acceptor = TlsAcceptor::from(Arc::new(config.tls(&ca)?));
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", config.listen))?;
+    info!("Collecting forwarded events on {}", listener.local_addr()?);
+    if !config.require_enrollment {
+        warn!("Collector accepts forwarders without an enrolled certificate");
+    }
+    let signer = EventSigner::from_env().context("Failed to read the event signing key")?;
+    let collector = Arc::new(Collector {
+        state,
+        events,
+        ca,
+        require_enrollment: config.require_enrollment,
+        signer,
+    });
+
+    loop {
+        let (tcp, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept forwarder: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        // What the agent connected to, which isolation has to leave open
+        let local = match tcp.local_addr() {
+            Ok(local) => SocketAddr::new(local.ip().to_canonical(), local.port()),
+            Err(e) => {
+                warn!("Failed to read the local address of {}: {}", peer, e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let collector = collector.clone();
+        tokio::spawn(async move {
+            let result = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(Ok(stream)) => {
+                    let fingerprint = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .map(|cert| enrollment::fingerprint(cert));
+                    serve(stream, peer, local, fingerprint, &collector).await
+                }
+                Ok(Err(e)) => Err(e).context("TLS handshake failed"),
+                Err(_) => Err(anyhow::anyhow!("TLS handshake timed out")),
+            };
+            if let Err(e) = result {
+                warn!("Forwarder {} disconnected: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+/// Tell the peer why it is being dropped and end the connection
+async fn reject<S: AsyncWrite + Unpin>(stream: &mut S, reason: String) -> Result<()> {
+    let _ = write_frame(
+        stream,
+        &Frame::Reject {
+            reason: reason.clone(),
+        },
+    )
+    .await;
+    bail!(reason)
+}
+
+/// Read the rest of the forwarder's introduction: protocol check on the
+/// first frame, then its agent hello
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    first: Option<Frame>,
+) -> Result<AgentHello> {
+    match first {
+        Some(Frame::ClientHello {
+            protocol_version, ..
+        }) if protocol_version == PROTOCOL_VERSION => {}
+        Some(Frame::ClientHello {
+            protocol_version, ..
+        }) => {
+            let reason = format!(
+                "Protocol version {} is not supported (collector speaks {})",
+                protocol_version, PROTOCOL_VERSION
+            );
+            reject(stream, reason).await?;
+        }
+        _ => bail!("Connection did not start with a handshake"),
+    }
+    match read_frame(stream).await? {
+        Some(Frame::Hello(hello)) => Ok(hello),
+        _ => bail!("Forwarder did not announce its agent"),
+    }
+}
+
+/// Trade an enrollment token and signing request for an agent certificate
+async fn enroll<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    peer: SocketAddr,
+    collector: &Collector,
+    token: &str,
+    claimed: &str,
+    csr: &str,
+) -> Result<()> {
+    // Held throughout so a token cannot be redeemed twice concurrently
+    let state = collector.state.lock().await;
+    let Some(hostname) = state.enrollment_for_token(token).await? else {
+        drop(state);
+        return reject(stream, "Enrollment token is invalid or expired".to_string()).await;
+    };
+    let (certificate, fingerprint) = match collector.ca.issue(csr, &hostname) {
+        Ok(issued) => issued,
+        Err(e) => {
+            drop(state);
+            return reject(stream, format!("{:#}", e)).await;
+        }
+    };
+    if !state
+        .complete_enrollment(&hostname, token, &fingerprint)
+        .await?
+    {
+        drop(state);
+        return reject(stream, "Enrollment token was already used".to_string()).await;
+    }
+    drop(state);
+
+    info!(
+        "Enrolled agent {} from {} (announced as {})",
+        hostname, peer, claimed
+    );
+    write_frame(stream, &Frame::Enrolled { certificate }).await?;
+    Ok(())
+}
+
+/// Store forwarded frames and acknowledge each once it is safely stored.
+/// `local` is the collector address `peer` connected to.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    peer: SocketAddr,
+    local: SocketAddr,
+    fingerprint: Option<String>,
+    collector: &Collector,
+) -> Result<()> {
+    let first = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_frame(&mut stream))
+        .await
+        .context("Forwarder handshake timed out")??;
+    if let Some(Frame::Enroll {
+        token,
+        hostname,
+        csr,
+    }) = &first
+    {
+        return enroll(&mut stream, peer, collector, token, hostname, csr).await;
+    }
+
+    // An enrolled agent is known by its certificate, whatever it announces
+    let enrolled = match &fingerprint {
+        Some(fingerprint) => match collector.enrolled_host(fingerprint).await {
+            Ok(hostname) => Some(hostname),
+            Err(e) => return reject(&mut stream, e.to_string()).await,
+        },
+        None if collector.require_enrollment => {
+            let reason = "Agent is not enrolled with this collector".to_string();
+            return reject(&mut stream, reason).await;
+        }
+        None => None,
+    };
+
+    let hello = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, first))
+        .await
+        .context("Forwarder handshake timed out")??;
+    if let Some(Err(e)) = collector
+        .signer
+        .as_ref()
+        .map(|signer| signer.verify(&hello))
+    {
+        return reject(&mut stream, e.to_string()).await;
+    }
+    let hostname = enrolled.unwrap_or_else(|| hello.hostname.clone());
+    let hello = AgentHello {
+        hostname: hostname.clone(),
+        ..hello
+    };
+    let state = &collector.state;
+    state
+        .lock()
+        .await
+        .record_host(&hello, &peer.to_string())
+        .await?;
+    info!(
+        "Agent {} v{} forwarding from {}",
+        hostname, hello.agent_version, peer
+    );
+
+    // Commands for this agent, such as the sampling its quota calls for
+    let (control_tx, mut control_rx) = mpsc::channel(4);
+    state
+        .lock()
+        .await
+        .register_forwarder(&hostname, control_tx.clone(), local);
+    let result = relay(
+        &mut stream,
+        &hostname,
+        fingerprint.as_deref(),
+        collector,
+        &mut control_rx,
+    )
+    .await;
+    state
+        .lock()
+        .await
+        .unregister_forwarder(&hostname, &control_tx);
+    result?;
+
+    info!("Agent {} stopped forwarding", hostname);
+    Ok(())
+}
+
+/// Store and acknowledge frames until the agent disconnects, passing on
+/// commands queued for it after each acknowledgement
+async fn relay<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    hostname: &str,
+    fingerprint: Option<&str>,
+    collector: &Collector,
+    control_rx: &mut mpsc::Receiver<ControlCommand>,
+) -> Result<()> {
+    let state = &collector.state;
+    while let Some(frame) = read_frame(stream).await? {
+        // Revocation takes effect on the next frame, not the next connection
+        if let Some(fingerprint) = fingerprint {
+            if let Err(e) = collector.enrolled_host(fingerprint).await {
+                return reject(stream, e.to_string()).await;
+            }
+        }
+        let Frame::Forwarded { seq, frame } = frame else {
+            warn!("Ignoring unexpected frame from {}", hostname);
+            continue;
+        };
+
+        // Checked before attribution changes the host the agent signed
+        if let Some(signer) = &collector.signer {
+            let verified = match frame.as_ref() {
+                Frame::Event(event) => signer.verify(event),
+                Frame::Rollup(rollup) => signer.verify(rollup),
+                _ => Ok(()),
+            };
+            if let Err(e) = verified {
+                // Acknowledged all the same, or the agent would resend it forever
+                warn!("Dropping frame from {}: {}", hostname, e);
+                write_frame(stream, &Frame::Ack { seq }).await?;
+                continue;
+            }
+        }
+
+        // Frames are attributed to the host that connected, whatever they claim
+        match *frame {
+            Frame::Event(mut event) if event.event_type.kind() == "heartbeat" => {
+                event.hostname = hostname.to_string();
+                state.lock().await.record_heartbeat(&event).await?;
+            }
+            Frame::Event(mut event) => {
+                event.hostname = hostname.to_string();
+                let bytes = event.to_json().map(|json| json.len()).unwrap_or(0);
+                let mut state = state.lock().await;
+                if state.admit_event(&event, bytes).await {
+                    state.store_event(&event).await?;
+                    drop(state);
+                    let _ = collector.events.try_send(event);
+                }
+            }
+            Frame::Rollup(mut rollup) => {
+                rollup.hostname = hostname.to_string();
+                state.lock().await.store_rollup(&rollup).await?;
+            }
+            Frame::RuleStats(mut report) => {
+                report.hostname = hostname.to_string();
+                state.lock().await.set_rule_stats(report);
+            }
+            _ => warn!("Ignoring unexpected forwarded frame from {}", hostname),
+        }
+        write_frame(stream, &Frame::Ack { seq }).await?;
+        while let Ok(command) = control_rx.try_recv() {
+            write_frame(stream, &Frame::Control(command)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::QuotaConfig;
+    use guardian_common::{Capabilities, EventType, Severity};
+    use rcgen::KeyPair;
+    use tokio::net::TcpStream;
+    use tokio_rustls::client::TlsStream;
+    use tokio_rustls::rustls::pki_types::{
+        CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName,
+    };
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    fn forwarded(seq: u64, claimed_host: &str) -> (LogEvent, Frame) {
+        let event = LogEvent::new(
+            Severity::Low,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: "remote".to_string(),
+            },
+            claimed_host.to_string(),
+        );
+        let frame = Frame::Forwarded {
+            seq,
+            frame: Box::new(Frame::Event(event.clone())),
+        };
+        (event, frame)
+    }
+
+    async fn connect(port: u16, client: ClientConfig) -> TlsStream<TcpStream> {
+        let tcp = loop {
+            match TcpStream::connect(("127.0.0.1", port)).await {
+                Ok(tcp) => break tcp,
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        };
+        TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap()
+    }
+
+    /// Send the forwarder introduction, claiming to be `hostname`
+    async fn introduce(stream: &mut TlsStream<TcpStream>, hostname: &str) {
+        let hello = AgentHello {
+            hostname: hostname.to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: guardian_common::SCHEMA_VERSION,
+            capabilities: Capabilities::default(),
+            signature: None,
+        };
+        let client_hello = Frame::ClientHello {
+            protocol_version: PROTOCOL_VERSION,
+            client: "forwarder".to_string(),
+            encodings: Vec::new(),
+        };
+        write_frame(stream, &client_hello).await.unwrap();
+        write_frame(stream, &Frame::Hello(hello)).await.unwrap();
+    }
+
+    async fn expect_reject(stream: &mut TlsStream<TcpStream>) -> String {
+        match read_frame(stream).await.unwrap() {
+            Some(Frame::Reject { reason }) => reason,
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enrolled_agents_forward_with_host_attribution() {
+        let dir = std::env::temp_dir().join(format!("guardian-collector-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+
+        let mut state = AppState::new(dir.join("guardian.db"));
+        state.init_db().await.unwrap();
+        let state = Arc::new(Mutex::new(state));
+
+        // Bind a free port first so the test knows where to connect
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = CollectorConfig {
+            listen: format!("127.0.0.1:{}", port),
+            cert_file: dir.join("cert.pem"),
+            key_file: dir.join("key.pem"),
+            ca_cert_file: None,
+            ca_key_file: None,
+            require_enrollment: true,
+        };
+        let (events_tx, mut events_rx) = mpsc::channel(16);
+        tokio::spawn(run(config, state.clone(), events_tx));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let anonymous = ClientConfig::builder()
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+
+        // Without a certificate only enrollment is possible
+        let mut stream = connect(port, anonymous.clone()).await;
+        introduce(&mut stream, "web-7").await;
+        assert!(expect_reject(&mut stream).await.contains("not enrolled"));
+
+        let token = state
+            .lock()
+            .await
+            .create_enrollment_token("web-7", None)
+            .await
+            .unwrap();
+        let key = KeyPair::generate().unwrap();
+        let csr = rcgen::CertificateParams::new(Vec::<String>::new())
+            .unwrap()
+            .serialize_request(&key)
+            .unwrap()
+            .pem()
+            .unwrap();
+        let enroll = Frame::Enroll {
+            token,
+            hostname: "whatever".to_string(),
+            csr,
+        };
+        let mut stream = connect(port, anonymous.clone()).await;
+        write_frame(&mut stream, &enroll).await.unwrap();
+        let certificate = match read_frame(&mut stream).await.unwrap() {
+            Some(Frame::Enrolled { certificate }) => certificate,
+            other => panic!("expected a certificate, got {:?}", other),
+        };
+        // Tokens are single use
+        let mut stream = connect(port, anonymous).await;
+        write_frame(&mut stream, &enroll).await.unwrap();
+        assert!(expect_reject(&mut stream).await.contains("invalid"));
+
+        let chain = rustls_pemfile::certs(&mut certificate.as_bytes())
+            .collect::<Result<Vec<CertificateDer>, _>>()
+            .unwrap();
+        let agent = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(
+                chain,
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+            )
+            .unwrap();
+        let mut stream = connect(port, agent.clone()).await;
+        // The enrolled name wins over the announced one
+        introduce(&mut stream, "spoofed").await;
+
+        // The second event claims another host, and the first is resent
+        let (first, first_frame) = forwarded(0, "web-7");
+        let (_, second_frame) = forwarded(1, "db-1");
+        for frame in [&first_frame, &second_frame, &first_frame] {
+            write_frame(&mut stream, frame).await.unwrap();
+        }
+        for expected in [0, 1, 0] {
+            match read_frame(&mut stream).await.unwrap() {
+                Some(Frame::Ack { seq }) => assert_eq!(seq, expected),
+                other => panic!("expected an ack, got {:?}", other),
+            }
+        }
+        assert_eq!(events_rx.recv().await.unwrap().id, first.id);
+
+        {
+            let state = state.lock().await;
+            // The console's own audit trail is listed under its hostname
+            let hosts = state.get_hosts().await.unwrap();
+            let web = hosts.iter().find(|h| h.hostname == "web-7").unwrap();
+            assert_eq!(web.events, 2);
+            assert_eq!(web.agent_version.as_deref(), Some("0.1.0"));
+            assert!(!hosts
+                .iter()
+                .any(|h| h.hostname == "spoofed" || h.hostname == "db-1"));
+
+            let scoped = state
+                .get_recent_events(10, Some("web-7"), None)
+                .await
+                .unwrap();
+            assert_eq!(scoped.events.len(), 2);
+            assert!(state
+                .get_recent_events(10, Some("db-1"), None)
+                .await
+                .unwrap()
+                .events
+                .is_empty());
+            let stats = state.get_event_stats(Some("web-7")).await.unwrap();
+            assert_eq!(stats["total"], 2);
+
+            let enrollments = state.get_enrollments().await.unwrap();
+            assert_eq!(enrollments[0].status, "enrolled");
+            assert!(enrollments[0].fingerprint.is_some());
+        }
+
+        // Revocation cuts off the open connection and later ones
+        state.lock().await.revoke_agent("web-7").await.unwrap();
+        let (_, third_frame) = forwarded(2, "web-7");
+        write_frame(&mut stream, &third_frame).await.unwrap();
+        assert!(expect_reject(&mut stream).await.contains("revoked"));
+        let mut stream = connect(port, agent).await;
+        introduce(&mut stream, "web-7").await;
+        assert!(expect_reject(&mut stream).await.contains("revoked"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_agents_over_quota_are_told_to_sample() {
+        let dir = std::env::temp_dir().join(format!("guardian-collector-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+
+        let mut state = AppState::new(dir.join("guardian.db"));
+        state.init_db().await.unwrap();
+        let state = Arc::new(Mutex::new(state));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = CollectorConfig {
+            listen: format!("127.0.0.1:{}", port),
+            cert_file: dir.join("cert.pem"),
+            key_file: dir.join("key.pem"),
+            ca_cert_file: None,
+            ca_key_file: None,
+            require_enrollment: false,
+        };
+        let (events_tx, _events_rx) = mpsc::channel(1000);
+        tokio::spawn(run(config, state.clone(), events_tx));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let mut stream = connect(port, client).await;
+        introduce(&mut stream, "web-9").await;
+
+        // One event past the default quota of 600 a minute
+        let limit = QuotaConfig::default().events_per_minute;
+        for seq in 0..=limit {
+            write_frame(&mut stream, &forwarded(seq, "web-9").1)
+                .await
+                .unwrap();
+            assert!(matches!(
+                read_frame(&mut stream).await.unwrap(),
+                Some(Frame::Ack { .. })
+            ));
+        }
+        match read_frame(&mut stream).await.unwrap() {
+            Some(Frame::Control(ControlCommand::SetSampling { rate })) => assert_eq!(rate, 2),
+            other => panic!("expected a sampling change, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
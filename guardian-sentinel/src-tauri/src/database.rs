@@ -1,237 +1,888 @@
-use anyhow::Result;
-use guardian_common::LogEvent;
-use sqlx::{sqlite::SqlitePoolOptions, Row, SqlitePool};
+use chrono::{DateTime, Utc};
+use guardian_common::error::Result;
+use guardian_common::store::{self, EventRow, PayloadEncoder};
+use guardian_common::{
+    AgentHello, CompiledRule, ForwardStats, GuardianError, LogEvent, RuleTest, SearchFilter,
+    Severity, StatsRollup,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+use sqlx::{Row, SqlitePool};
 use std::path::Path;
-use tracing::info;
+use std::time::Duration;
+use tracing::{error, info, warn};
 
-/// Initialize the SQLite database
+/// How long a writer waits for another connection's lock before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bytes of the database file mapped into memory for reads
+const MMAP_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Connection settings shared by every connection to the event database.
+/// WAL lets readers and the bridge proceed while Sentinel writes. With a key
+/// the file is encrypted by SQLCipher, which needs the `sqlcipher` feature.
+pub fn connect_options(db_path: &Path, key: Option<&str>) -> SqliteConnectOptions {
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(BUSY_TIMEOUT)
+        .pragma("mmap_size", MMAP_SIZE.to_string());
+    match key {
+        Some(key) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+        None => options,
+    }
+}
+
+/// Initialize the SQLite database, encrypted if a key is configured
 pub async fn init_database(db_path: &Path) -> Result<SqlitePool> {
+    let key = guardian_common::dbkey::database_key().map_err(|e| {
+        GuardianError::storage(format!("Failed to read the database encryption key: {}", e))
+    })?;
+    open_database(db_path, key.as_deref()).await
+}
+
+/// Open the SQLite database with an explicit encryption key
+pub async fn open_database(db_path: &Path, key: Option<&str>) -> Result<SqlitePool> {
     // Ensure parent directory exists
     if let Some(parent) = db_path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(GuardianError::storage)?;
     }
 
-    let db_url = format!("sqlite://{}?mode=rwc", db_path.display());
-    info!("Connecting to database: {}", db_url);
-
-    let pool = SqlitePoolOptions::new()
+    info!("Connecting to database: {}", db_path.display());
+    let pool = store::pool_options()
         .max_connections(5)
-        .connect(&db_url)
+        .connect_with(connect_options(db_path, key))
+        .await
+        .map_err(|e| {
+            GuardianError::storage(match key {
+                Some(_) => format!(
+                    "Failed to open the encrypted database; is the key right? {}",
+                    e
+                ),
+                None => format!("Failed to open the database: {}", e),
+            })
+        })?;
+    if key.is_some() {
+        require_sqlcipher(&pool).await?;
+    }
+    check_integrity(&pool).await?;
+
+    // Schema changes ship as numbered files in `migrations/`; each runs once
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .map_err(GuardianError::storage)?;
+
+    info!("Database initialized successfully");
+
+    Ok(pool)
+}
+
+/// Plain SQLite ignores `PRAGMA key`, which would leave a database the
+/// operator asked to encrypt in plaintext
+async fn require_sqlcipher(pool: &SqlitePool) -> Result<()> {
+    let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(pool)
         .await?;
+    match version {
+        Some(version) => {
+            info!("Database encrypted with SQLCipher {}", version);
+            Ok(())
+        }
+        None => Err(GuardianError::storage(
+            "A database key is configured but this build lacks SQLCipher; \
+             rebuild with the `sqlcipher` feature",
+        )),
+    }
+}
 
-    // Run migrations
-    sqlx::query(
+/// Report corruption found by SQLite's quick check. The database stays usable
+/// so the operator can export what is still readable.
+async fn check_integrity(pool: &SqlitePool) -> Result<()> {
+    let problems: Vec<String> = sqlx::query_scalar("PRAGMA quick_check")
+        .fetch_all(pool)
+        .await?;
+    if problems != ["ok"] {
+        for problem in &problems {
+            error!("Database integrity check: {}", problem);
+        }
+    }
+    Ok(())
+}
+
+/// Insert a log event into the database
+pub async fn insert_event(pool: &SqlitePool, event: &LogEvent) -> Result<()> {
+    store_row(pool, EventRow::new(event)?).await
+}
+
+/// Insert a log event with its payload compressed by `encoder`, which
+/// first trains a new dictionary when one is due
+pub async fn insert_compressed_event(
+    pool: &SqlitePool,
+    event: &LogEvent,
+    encoder: &PayloadEncoder,
+) -> Result<()> {
+    if let Err(e) = encoder.maintain(pool).await {
+        warn!("Failed to update the payload dictionary: {}", e);
+    }
+    let row = EventRow::new(event)?
+        .compress(encoder)
+        .map_err(GuardianError::storage)?;
+    store_row(pool, row).await
+}
+
+async fn store_row(pool: &SqlitePool, row: EventRow<'_>) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    row.insert(&mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Store an agent rollup, replacing any earlier copy of the same period
+pub async fn insert_rollup(pool: &SqlitePool, rollup: &StatsRollup) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    store::insert_rollup(&mut tx, rollup).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Event count for one hour and severity across the fleet
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TrendPoint {
+    pub bucket: String,
+    pub severity: String,
+    pub count: i64,
+}
+
+/// Hourly fleet-wide event counts by severity.
+/// Hosts that shipped a rollup for an hour are counted from the rollup; raw
+/// events are only counted for host-hours without one, so nothing is doubled.
+pub async fn get_fleet_trends(
+    pool: &SqlitePool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<TrendPoint>> {
+    let rows = sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS events (
-            id TEXT PRIMARY KEY NOT NULL,
-            timestamp TEXT NOT NULL,
-            severity TEXT NOT NULL,
-            event_type TEXT NOT NULL,
-            event_data TEXT NOT NULL,
-            hostname TEXT NOT NULL,
-            tags TEXT NOT NULL,
-            rule_triggered INTEGER NOT NULL DEFAULT 0,
-            rule_name TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        WITH rolled AS (
+            SELECT hostname, period_start AS bucket, severity, SUM(count) AS count
+            FROM event_rollups
+            WHERE period_start >= ? AND period_start < ?
+            GROUP BY hostname, bucket, severity
+        ),
+        raw AS (
+            SELECT hostname, strftime('%Y-%m-%dT%H:00:00+00:00', timestamp) AS bucket,
+                   severity, COUNT(*) AS count
+            FROM events
+            WHERE timestamp >= ? AND timestamp < ?
+            GROUP BY hostname, bucket, severity
         )
+        SELECT bucket, severity, SUM(count) AS count
+        FROM (
+            SELECT * FROM rolled
+            UNION ALL
+            SELECT * FROM raw r
+            WHERE NOT EXISTS (
+                SELECT 1 FROM rolled x WHERE x.hostname = r.hostname AND x.bucket = r.bucket
+            )
+        )
+        GROUP BY bucket, severity
+        ORDER BY bucket, severity
         "#,
     )
-    .execute(&pool)
+    .bind(from.to_rfc3339())
+    .bind(to.to_rfc3339())
+    .bind(from.to_rfc3339())
+    .bind(to.to_rfc3339())
+    .fetch_all(pool)
     .await?;
 
-    // Create indexes for common queries
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_timestamp ON events(timestamp DESC)")
-        .execute(&pool)
-        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| TrendPoint {
+            bucket: row.get("bucket"),
+            severity: row.get("severity"),
+            count: row.get("count"),
+        })
+        .collect())
+}
 
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_severity ON events(severity)")
-        .execute(&pool)
+/// Which events a heat map or timeline counts
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HeatmapFilter {
+    /// Substring matched against event data, hostname and tags
+    pub query: Option<String>,
+    pub severity: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// Event and alert counts by day of week (Monday first) and hour of day
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Heatmap {
+    /// `events[day][hour]`
+    pub events: Vec<Vec<i64>>,
+    /// `alerts[day][hour]`, counting rule-triggered events only
+    pub alerts: Vec<Vec<i64>>,
+}
+
+/// Activity heat map for `[from, to)`, bucketed in local time at `utc_offset_minutes`
+pub async fn get_activity_heatmap(
+    pool: &SqlitePool,
+    filter: &HeatmapFilter,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    utc_offset_minutes: i32,
+) -> Result<Heatmap> {
+    let mut sql = String::from(
+        r#"
+        SELECT (CAST(strftime('%w', timestamp, ?1) AS INTEGER) + 6) % 7 AS day,
+               CAST(strftime('%H', timestamp, ?1) AS INTEGER) AS hour,
+               COUNT(*) AS events,
+               SUM(rule_triggered) AS alerts
+        FROM events
+        WHERE timestamp >= ?2 AND timestamp < ?3
+        "#,
+    );
+    if filter.query.is_some() {
+        sql.push_str(" AND (event_json(event_data, event_payload) LIKE ?4 OR hostname LIKE ?4 OR tags LIKE ?4)");
+    }
+    if filter.severity.is_some() {
+        sql.push_str(" AND severity = ?5");
+    }
+    if filter.hostname.is_some() {
+        sql.push_str(" AND hostname = ?6");
+    }
+    sql.push_str(" GROUP BY day, hour");
+
+    let rows = sqlx::query(&sql)
+        .bind(format!("{} minutes", utc_offset_minutes))
+        .bind(from.to_rfc3339())
+        .bind(to.to_rfc3339())
+        .bind(filter.query.as_ref().map(|q| format!("%{}%", q)))
+        .bind(&filter.severity)
+        .bind(&filter.hostname)
+        .fetch_all(pool)
         .await?;
 
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_rule_triggered ON events(rule_triggered)")
-        .execute(&pool)
+    let mut heatmap = Heatmap {
+        events: vec![vec![0; 24]; 7],
+        alerts: vec![vec![0; 24]; 7],
+    };
+    for row in rows {
+        let day = row.get::<i64, _>("day") as usize;
+        let hour = row.get::<i64, _>("hour") as usize;
+        heatmap.events[day][hour] = row.get("events");
+        heatmap.alerts[day][hour] = row.get("alerts");
+    }
+
+    Ok(heatmap)
+}
+
+/// Width of the buckets in an event timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineInterval {
+    Minute,
+    Hour,
+}
+
+impl TimelineInterval {
+    fn duration(self) -> chrono::Duration {
+        match self {
+            TimelineInterval::Minute => chrono::Duration::minutes(1),
+            TimelineInterval::Hour => chrono::Duration::hours(1),
+        }
+    }
+
+    /// Bucket label, as strftime and chrono format strings
+    fn format(self) -> &'static str {
+        match self {
+            TimelineInterval::Minute => "%Y-%m-%dT%H:%M:00+00:00",
+            TimelineInterval::Hour => "%Y-%m-%dT%H:00:00+00:00",
+        }
+    }
+}
+
+/// Most buckets one timeline query may return
+pub const MAX_TIMELINE_BUCKETS: i64 = 10_000;
+
+/// Event counts for one timeline bucket
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TimelineBucket {
+    /// Start of the bucket in UTC
+    pub bucket: String,
+    pub total: i64,
+    /// Counts keyed by severity; severities without events are left out
+    pub by_severity: std::collections::BTreeMap<String, i64>,
+}
+
+/// Raw event counts per bucket and severity over `[from, to)`, with a bucket
+/// for every interval so charts need no gap filling. Rollups are not included.
+pub async fn get_event_timeline(
+    pool: &SqlitePool,
+    interval: TimelineInterval,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: &HeatmapFilter,
+) -> Result<Vec<TimelineBucket>> {
+    let step = interval.duration();
+    let buckets = (to - from).num_seconds() / step.num_seconds() + 1;
+    if to <= from {
+        return Err(GuardianError::invalid_input("Timeline range is empty"));
+    }
+    if buckets > MAX_TIMELINE_BUCKETS {
+        return Err(GuardianError::invalid_input(format!(
+            "Timeline would have {} buckets (at most {}); use a wider interval or a shorter range",
+            buckets, MAX_TIMELINE_BUCKETS
+        )));
+    }
+
+    let mut sql = String::from(
+        r#"
+        SELECT strftime(?1, timestamp) AS bucket, severity, COUNT(*) AS count
+        FROM events
+        WHERE timestamp >= ?2 AND timestamp < ?3
+        "#,
+    );
+    if filter.query.is_some() {
+        sql.push_str(" AND (event_json(event_data, event_payload) LIKE ?4 OR hostname LIKE ?4 OR tags LIKE ?4)");
+    }
+    if filter.severity.is_some() {
+        sql.push_str(" AND severity = ?5");
+    }
+    if filter.hostname.is_some() {
+        sql.push_str(" AND hostname = ?6");
+    }
+    sql.push_str(" GROUP BY bucket, severity");
+
+    let rows = sqlx::query(&sql)
+        .bind(interval.format())
+        .bind(from.to_rfc3339())
+        .bind(to.to_rfc3339())
+        .bind(filter.query.as_ref().map(|q| format!("%{}%", q)))
+        .bind(&filter.severity)
+        .bind(&filter.hostname)
+        .fetch_all(pool)
         .await?;
 
-    info!("Database initialized successfully");
+    // One bucket per interval from the one containing `from`
+    let start =
+        chrono::DurationRound::duration_trunc(from, step).map_err(GuardianError::storage)?;
+    let mut timeline: Vec<TimelineBucket> = std::iter::successors(Some(start), |t| Some(*t + step))
+        .take_while(|t| *t < to)
+        .map(|t| TimelineBucket {
+            bucket: t.format(interval.format()).to_string(),
+            total: 0,
+            by_severity: Default::default(),
+        })
+        .collect();
+    for row in rows {
+        let bucket: String = row.get("bucket");
+        let count: i64 = row.get("count");
+        if let Ok(i) = timeline.binary_search_by(|b| b.bucket.cmp(&bucket)) {
+            timeline[i].total += count;
+            timeline[i].by_severity.insert(row.get("severity"), count);
+        }
+    }
 
-    Ok(pool)
+    Ok(timeline)
 }
 
-/// Insert a log event into the database
-pub async fn insert_event(pool: &SqlitePool, event: &LogEvent) -> Result<()> {
-    let event_type = serde_json::to_string(&event.event_type)?;
-    let tags = serde_json::to_string(&event.tags)?;
+/// What a top-N query ranks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopDimension {
+    /// Hosts by event count
+    Hosts,
+    /// Rules by number of events they triggered on
+    Rules,
+    /// Paths by number of file integrity events
+    Paths,
+    /// Remote `address:port` endpoints by number of socket events
+    RemoteAddresses,
+}
+
+impl TopDimension {
+    /// Grouping expression and the condition for events that have it
+    fn sql(self) -> (&'static str, &'static str) {
+        match self {
+            TopDimension::Hosts => ("hostname", "1"),
+            TopDimension::Rules => ("rule_name", "rule_triggered = 1 AND rule_name IS NOT NULL"),
+            TopDimension::Paths => (
+                "json_extract(event_json(event_data, event_payload), '$.path')",
+                "event_type = 'file_integrity'",
+            ),
+            TopDimension::RemoteAddresses => (
+                "json_extract(event_json(event_data, event_payload), '$.remote_addr')",
+                "event_type = 'network_socket' AND json_extract(event_json(event_data, event_payload), '$.remote_addr') IS NOT NULL",
+            ),
+        }
+    }
+}
+
+/// Most entries one top-N query may return
+pub const MAX_TOP_N: u32 = 100;
+
+/// One ranked value and its event count
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TopEntry {
+    pub key: String,
+    pub count: i64,
+}
+
+/// The `limit` most frequent values of `dimension` among events in `[from, to)`,
+/// most frequent first
+pub async fn get_top_n(
+    pool: &SqlitePool,
+    dimension: TopDimension,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: u32,
+) -> Result<Vec<TopEntry>> {
+    let (key, condition) = dimension.sql();
+    let sql = format!(
+        r#"
+        SELECT {key} AS key, COUNT(*) AS count
+        FROM events
+        WHERE timestamp >= ?1 AND timestamp < ?2 AND {condition}
+        GROUP BY key
+        ORDER BY count DESC, key
+        LIMIT ?3
+        "#
+    );
 
+    let rows = sqlx::query(&sql)
+        .bind(from.to_rfc3339())
+        .bind(to.to_rfc3339())
+        .bind(limit.min(MAX_TOP_N))
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| TopEntry {
+            key: row.get("key"),
+            count: row.get("count"),
+        })
+        .collect())
+}
+
+/// Record that `principal` acknowledged an alert, moving an open alert to
+/// acknowledged
+pub async fn acknowledge_event(pool: &SqlitePool, event_id: &str, principal: &str) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
     sqlx::query(
         r#"
-        INSERT INTO events (id, timestamp, severity, event_type, event_data, hostname, tags, rule_triggered, rule_name)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT OR IGNORE INTO alert_acks (event_id, principal, acknowledged_at)
+        VALUES (?, ?, ?)
         "#,
     )
-    .bind(event.id.to_string())
-    .bind(event.timestamp.to_rfc3339())
-    .bind(serde_json::to_string(&event.severity).unwrap_or_default().trim_matches('"').to_string())
-    .bind(serde_json::to_string(&event.event_type)?)
-    .bind(event_type)
-    .bind(&event.hostname)
-    .bind(tags)
-    .bind(event.rule_triggered as i32)
-    .bind(&event.rule_name)
-    .execute(pool)
+    .bind(event_id)
+    .bind(principal)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE alerts
+        SET status = 'acknowledged', acknowledged_by = ?1, acknowledged_at = ?2, updated_at = ?2
+        WHERE event_id = ?3 AND status = 'open'
+        "#,
+    )
+    .bind(principal)
+    .bind(&now)
+    .bind(event_id)
+    .execute(&mut *tx)
     .await?;
 
+    tx.commit().await?;
     Ok(())
 }
 
-/// Get recent events
-pub async fn get_recent_events(pool: &SqlitePool, limit: i64) -> Result<Vec<LogEvent>> {
-    let rows = sqlx::query(
+/// Mark an alert resolved; the first resolution wins
+pub async fn resolve_event(pool: &SqlitePool, event_id: &str, principal: &str) -> Result<()> {
+    close_alert(pool, event_id, principal, None).await
+}
+
+/// Where an alert is in triage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Open,
+    Acknowledged,
+    Closed,
+}
+
+impl AlertStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertStatus::Open => "open",
+            AlertStatus::Acknowledged => "acknowledged",
+            AlertStatus::Closed => "closed",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(serde_json::from_value(serde_json::json!(s))?)
+    }
+}
+
+/// A rule-triggered event in the alert queue
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub event: LogEvent,
+    pub status: AlertStatus,
+    pub assignee: Option<String>,
+    /// Why the alert was closed, e.g. `false positive`
+    pub close_reason: Option<String>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<String>,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<String>,
+    pub updated_at: String,
+}
+
+/// Close an alert, recording its resolution for SLA metrics. Closing a
+/// closed alert changes nothing.
+pub async fn close_alert(
+    pool: &SqlitePool,
+    event_id: &str,
+    principal: &str,
+    reason: Option<&str>,
+) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
         r#"
-        SELECT id, timestamp, severity, event_data, hostname, tags, rule_triggered, rule_name
-        FROM events
-        ORDER BY timestamp DESC
-        LIMIT ?
+        INSERT OR IGNORE INTO alert_resolutions (event_id, resolved_by, resolved_at)
+        VALUES (?, ?, ?)
         "#,
     )
-    .bind(limit)
-    .fetch_all(pool)
+    .bind(event_id)
+    .bind(principal)
+    .bind(&now)
+    .execute(&mut *tx)
     .await?;
 
-    let mut events = Vec::new();
-    for row in rows {
-        let event_json = format!(
-            r#"{{
-                "id": "{}",
-                "timestamp": "{}",
-                "severity": "{}",
-                {},
-                "hostname": "{}",
-                "tags": {},
-                "rule_triggered": {},
-                "rule_name": {}
-            }}"#,
-            row.get::<String, _>("id"),
-            row.get::<String, _>("timestamp"),
-            row.get::<String, _>("severity"),
-            row.get::<String, _>("event_data")
-                .trim_start_matches('{')
-                .trim_end_matches('}'),
-            row.get::<String, _>("hostname"),
-            row.get::<String, _>("tags"),
-            row.get::<i32, _>("rule_triggered") != 0,
-            row.get::<Option<String>, _>("rule_name")
-                .map(|s| format!("\"{}\"", s))
-                .unwrap_or_else(|| "null".to_string())
-        );
-
-        if let Ok(event) = serde_json::from_str::<LogEvent>(&event_json) {
-            events.push(event);
-        }
+    sqlx::query(
+        r#"
+        UPDATE alerts
+        SET status = 'closed', closed_by = ?1, closed_at = ?2, close_reason = ?3, updated_at = ?2
+        WHERE event_id = ?4 AND status != 'closed'
+        "#,
+    )
+    .bind(principal)
+    .bind(&now)
+    .bind(reason)
+    .bind(event_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Put a closed alert back in the queue as open; false if it was not closed
+pub async fn reopen_alert(pool: &SqlitePool, event_id: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let reopened = sqlx::query(
+        r#"
+        UPDATE alerts
+        SET status = 'open', closed_by = NULL, closed_at = NULL, close_reason = NULL, updated_at = ?
+        WHERE event_id = ? AND status = 'closed'
+        "#,
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(event_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if reopened {
+        sqlx::query("DELETE FROM alert_resolutions WHERE event_id = ?")
+            .bind(event_id)
+            .execute(&mut *tx)
+            .await?;
     }
 
-    Ok(events)
+    tx.commit().await?;
+    Ok(reopened)
 }
 
-/// Get event statistics
-pub async fn get_event_stats(pool: &SqlitePool) -> Result<serde_json::Value> {
-    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
-        .fetch_one(pool)
+/// Assign an alert to an analyst, or unassign it; false if there is no such alert
+pub async fn assign_alert(
+    pool: &SqlitePool,
+    event_id: &str,
+    assignee: Option<&str>,
+) -> Result<bool> {
+    let result = sqlx::query("UPDATE alerts SET assignee = ?, updated_at = ? WHERE event_id = ?")
+        .bind(assignee)
+        .bind(Utc::now().to_rfc3339())
+        .bind(event_id)
+        .execute(pool)
         .await?;
+    Ok(result.rows_affected() > 0)
+}
 
-    let by_severity = sqlx::query(
+/// The alert queue, newest first, optionally only one status or assignee
+pub async fn get_alerts(
+    pool: &SqlitePool,
+    status: Option<AlertStatus>,
+    assignee: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Alert>> {
+    let rows = sqlx::query(
         r#"
-        SELECT severity, COUNT(*) as count
-        FROM events
-        GROUP BY severity
+        SELECT e.id, e.timestamp, e.severity, event_json(e.event_data, e.event_payload) AS event_data, e.hostname, e.tags, e.rule_triggered, e.rule_name, e.rules_matched, e.schema_version,
+               a.status, a.assignee, a.close_reason, a.acknowledged_by, a.acknowledged_at,
+               a.closed_by, a.closed_at, a.updated_at
+        FROM alerts a
+        JOIN events e ON e.id = a.event_id
+        WHERE (?1 IS NULL OR a.status = ?1) AND (?2 IS NULL OR a.assignee = ?2)
+        ORDER BY e.timestamp DESC, e.id DESC
+        LIMIT ?3
         "#,
     )
+    .bind(status.map(AlertStatus::as_str))
+    .bind(assignee)
+    .bind(limit)
     .fetch_all(pool)
     .await?;
 
-    let mut severity_counts = serde_json::Map::new();
-    for row in by_severity {
-        severity_counts.insert(
-            row.get::<String, _>("severity"),
-            serde_json::json!(row.get::<i64, _>("count")),
-        );
+    let mut alerts = Vec::new();
+    for row in rows {
+        let event = match store::event_from_row(&row) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::error!("Failed to deserialize event: {}", e);
+                continue;
+            }
+        };
+        alerts.push(Alert {
+            event,
+            status: AlertStatus::parse(row.get("status"))?,
+            assignee: row.get("assignee"),
+            close_reason: row.get("close_reason"),
+            acknowledged_by: row.get("acknowledged_by"),
+            acknowledged_at: row.get("acknowledged_at"),
+            closed_by: row.get("closed_by"),
+            closed_at: row.get("closed_at"),
+            updated_at: row.get("updated_at"),
+        });
     }
 
-    let rules_triggered: i64 =
-        sqlx::query_scalar("SELECT COUNT(*) FROM events WHERE rule_triggered = 1")
-            .fetch_one(pool)
-            .await?;
+    Ok(alerts)
+}
 
-    Ok(serde_json::json!({
-        "total": total,
-        "by_severity": severity_counts,
-        "rules_triggered": rules_triggered
-    }))
+/// A group of related events under investigation
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Incident {
+    pub id: String,
+    pub title: String,
+    /// `open` or `closed`
+    pub status: String,
+    /// While open, rule hits with this key (`rule@hostname`) join automatically
+    pub correlation_key: Option<String>,
+    pub created_by: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub event_count: i64,
 }
 
-/// Search events
-pub async fn search_events(
+const INCIDENT_COLUMNS: &str = r#"
+    i.id, i.title, i.status, i.correlation_key, i.created_by, i.created_at, i.updated_at,
+    i.closed_at, (SELECT COUNT(*) FROM incident_events ie WHERE ie.incident_id = i.id) AS event_count
+"#;
+
+fn incident_from_row(row: &sqlx::sqlite::SqliteRow) -> Incident {
+    Incident {
+        id: row.get("id"),
+        title: row.get("title"),
+        status: row.get("status"),
+        correlation_key: row.get("correlation_key"),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        closed_at: row.get("closed_at"),
+        event_count: row.get("event_count"),
+    }
+}
+
+/// Attach events within a transaction, skipping unknown and attached ones
+async fn attach_incident_events(
+    tx: &mut sqlx::SqliteConnection,
+    incident_id: &str,
+    event_ids: &[String],
+    added_by: &str,
+) -> Result<u64> {
+    let now = Utc::now().to_rfc3339();
+    let mut attached = 0;
+    for event_id in event_ids {
+        attached += sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO incident_events (incident_id, event_id, added_by, added_at)
+            SELECT ?, id, ?, ? FROM events WHERE id = ?
+            "#,
+        )
+        .bind(incident_id)
+        .bind(added_by)
+        .bind(&now)
+        .bind(event_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    Ok(attached)
+}
+
+/// Open an incident holding `event_ids`
+pub async fn create_incident(
     pool: &SqlitePool,
-    query: &str,
-    severity: Option<&str>,
-    limit: i64,
-    offset: i64,
-) -> Result<Vec<LogEvent>> {
-    let mut sql = String::from(
+    title: &str,
+    correlation_key: Option<&str>,
+    event_ids: &[String],
+    created_by: &str,
+) -> Result<Incident> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
         r#"
-        SELECT id, timestamp, severity, event_data, hostname, tags, rule_triggered, rule_name
-        FROM events
-        WHERE (event_data LIKE ? OR hostname LIKE ? OR tags LIKE ?)
+        INSERT INTO incidents (id, title, status, correlation_key, created_by, created_at, updated_at)
+        VALUES (?1, ?2, 'open', ?3, ?4, ?5, ?5)
         "#,
-    );
+    )
+    .bind(&id)
+    .bind(title)
+    .bind(correlation_key)
+    .bind(created_by)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => GuardianError::storage(format!(
+            "An open incident already collects {:?}",
+            correlation_key.unwrap_or_default()
+        )),
+        _ => e.into(),
+    })?;
+    attach_incident_events(&mut tx, &id, event_ids, created_by).await?;
+    tx.commit().await?;
 
-    if severity.is_some() {
-        sql.push_str(" AND severity = ?");
-    }
+    get_incident(pool, &id)
+        .await?
+        .ok_or_else(|| GuardianError::storage(format!("Incident {} vanished", id)))
+}
+
+pub async fn get_incident(pool: &SqlitePool, id: &str) -> Result<Option<Incident>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM incidents i WHERE i.id = ?",
+        INCIDENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.as_ref().map(incident_from_row))
+}
 
-    sql.push_str(" ORDER BY timestamp DESC LIMIT ? OFFSET ?");
+/// Incidents, most recently changed first, optionally one status only
+pub async fn get_incidents(pool: &SqlitePool, status: Option<&str>) -> Result<Vec<Incident>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM incidents i WHERE ?1 IS NULL OR i.status = ?1 ORDER BY i.updated_at DESC",
+        INCIDENT_COLUMNS
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(incident_from_row).collect())
+}
 
-    let search_pattern = format!("%{}%", query);
-    let mut query_builder = sqlx::query(&sql)
-        .bind(&search_pattern)
-        .bind(&search_pattern)
-        .bind(&search_pattern); // Bind for tags
+/// Add events to an incident; returns how many were newly attached
+pub async fn attach_events(
+    pool: &SqlitePool,
+    incident_id: &str,
+    event_ids: &[String],
+    added_by: &str,
+) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let attached = attach_incident_events(&mut tx, incident_id, event_ids, added_by).await?;
+    touch_incident(&mut tx, incident_id).await?;
+    tx.commit().await?;
+    Ok(attached)
+}
 
-    if let Some(sev) = severity {
-        query_builder = query_builder.bind(sev);
+/// Remove events from an incident; returns how many were attached
+pub async fn detach_events(
+    pool: &SqlitePool,
+    incident_id: &str,
+    event_ids: &[String],
+) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let mut detached = 0;
+    for event_id in event_ids {
+        detached +=
+            sqlx::query("DELETE FROM incident_events WHERE incident_id = ? AND event_id = ?")
+                .bind(incident_id)
+                .bind(event_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
     }
+    touch_incident(&mut tx, incident_id).await?;
+    tx.commit().await?;
+    Ok(detached)
+}
+
+async fn touch_incident(tx: &mut sqlx::SqliteConnection, incident_id: &str) -> Result<()> {
+    sqlx::query("UPDATE incidents SET updated_at = ? WHERE id = ?")
+        .bind(Utc::now().to_rfc3339())
+        .bind(incident_id)
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
 
-    query_builder = query_builder.bind(limit).bind(offset);
+/// Close an incident, which also stops it collecting correlated events;
+/// false if it was not open
+pub async fn close_incident(pool: &SqlitePool, id: &str) -> Result<bool> {
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "UPDATE incidents SET status = 'closed', closed_at = ?1, updated_at = ?1 WHERE id = ?2 AND status = 'open'",
+    )
+    .bind(&now)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
 
-    let rows = query_builder.fetch_all(pool).await?;
+/// Events in an incident, oldest first
+pub async fn get_incident_events(pool: &SqlitePool, incident_id: &str) -> Result<Vec<LogEvent>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT e.id, e.timestamp, e.severity, event_json(e.event_data, e.event_payload) AS event_data, e.hostname, e.tags, e.rule_triggered, e.rule_name, e.rules_matched, e.schema_version
+        FROM incident_events ie
+        JOIN events e ON e.id = ie.event_id
+        WHERE ie.incident_id = ?
+        ORDER BY e.timestamp, e.id
+        "#,
+    )
+    .bind(incident_id)
+    .fetch_all(pool)
+    .await?;
 
     let mut events = Vec::new();
     for row in rows {
-        let event_json = format!(
-            r#"{{
-                "id": "{}",
-                "timestamp": "{}",
-                "severity": "{}",
-                {},
-                "hostname": "{}",
-                "tags": {},
-                "rule_triggered": {},
-                "rule_name": {}
-            }}"#,
-            row.get::<String, _>("id"),
-            row.get::<String, _>("timestamp"),
-            row.get::<String, _>("severity"),
-            row.get::<String, _>("event_data")
-                .trim_start_matches('{')
-                .trim_end_matches('}'),
-            row.get::<String, _>("hostname"),
-            row.get::<String, _>("tags"),
-            row.get::<i32, _>("rule_triggered") != 0,
-            row.get::<Option<String>, _>("rule_name")
-                .map(|s| format!("\"{}\"", s))
-                .unwrap_or_else(|| "null".to_string())
-        );
-
-        match serde_json::from_str::<LogEvent>(&event_json) {
+        match store::event_from_row(&row) {
             Ok(event) => events.push(event),
             Err(e) => tracing::error!("Failed to deserialize event: {}", e),
         }
@@ -239,3 +890,2048 @@ pub async fn search_events(
 
     Ok(events)
 }
+
+/// Handling milestones of one alert
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertTiming {
+    pub event_id: String,
+    pub severity: Severity,
+    pub raised_at: DateTime<Utc>,
+    /// First acknowledgement by anyone
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Handling milestones for alerts raised in `[from, to)`
+pub async fn get_alert_timings(
+    pool: &SqlitePool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<AlertTiming>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT e.id, e.severity, e.timestamp,
+               (SELECT MIN(a.acknowledged_at) FROM alert_acks a WHERE a.event_id = e.id) AS acknowledged_at,
+               r.resolved_at
+        FROM events e
+        LEFT JOIN alert_resolutions r ON r.event_id = e.id
+        WHERE e.rule_triggered = 1 AND e.timestamp >= ? AND e.timestamp < ?
+        "#,
+    )
+    .bind(from.to_rfc3339())
+    .bind(to.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    let parse = |s: &str| {
+        DateTime::parse_from_rfc3339(s)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(GuardianError::storage)
+    };
+    let mut timings = Vec::new();
+    for row in rows {
+        let severity: Severity =
+            serde_json::from_value(serde_json::json!(row.get::<String, _>("severity")))?;
+        timings.push(AlertTiming {
+            event_id: row.get("id"),
+            severity,
+            raised_at: parse(&row.get::<String, _>("timestamp"))?,
+            acknowledged_at: row
+                .get::<Option<String>, _>("acknowledged_at")
+                .map(|s| parse(&s))
+                .transpose()?,
+            resolved_at: row
+                .get::<Option<String>, _>("resolved_at")
+                .map(|s| parse(&s))
+                .transpose()?,
+        });
+    }
+
+    Ok(timings)
+}
+
+/// Record an SLA breach; returns false if it was already recorded
+pub async fn record_sla_breach(pool: &SqlitePool, event_id: &str, kind: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO sla_breaches (event_id, kind, breached_at) VALUES (?, ?, ?)",
+    )
+    .bind(event_id)
+    .bind(kind)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Alerts raised in `[from, to)` that `principal` has not acknowledged
+pub async fn get_unacknowledged_alerts(
+    pool: &SqlitePool,
+    principal: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<LogEvent>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, timestamp, severity, event_json(event_data, event_payload) AS event_data, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version
+        FROM events
+        WHERE rule_triggered = 1
+          AND timestamp >= ? AND timestamp < ?
+          AND id NOT IN (SELECT event_id FROM alert_acks WHERE principal = ?)
+        ORDER BY timestamp
+        "#,
+    )
+    .bind(from.to_rfc3339())
+    .bind(to.to_rfc3339())
+    .bind(principal)
+    .fetch_all(pool)
+    .await?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        match store::event_from_row(&row) {
+            Ok(event) => events.push(event),
+            Err(e) => tracing::error!("Failed to deserialize event: {}", e),
+        }
+    }
+
+    Ok(events)
+}
+
+/// When a subscriber's digest was last delivered on a channel
+pub async fn get_digest_last_sent(
+    pool: &SqlitePool,
+    subscriber: &str,
+    channel: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let last_sent: Option<String> = sqlx::query_scalar(
+        "SELECT last_sent FROM digest_state WHERE subscriber = ? AND channel = ?",
+    )
+    .bind(subscriber)
+    .bind(channel)
+    .fetch_optional(pool)
+    .await?;
+
+    last_sent
+        .map(|s| DateTime::parse_from_rfc3339(&s).map(|t| t.with_timezone(&Utc)))
+        .transpose()
+        .map_err(GuardianError::storage)
+}
+
+/// Record a delivered digest
+pub async fn set_digest_last_sent(
+    pool: &SqlitePool,
+    subscriber: &str,
+    channel: &str,
+    last_sent: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO digest_state (subscriber, channel, last_sent) VALUES (?, ?, ?)
+        ON CONFLICT (subscriber, channel) DO UPDATE SET last_sent = excluded.last_sent
+        "#,
+    )
+    .bind(subscriber)
+    .bind(channel)
+    .bind(last_sent.to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// One page of events, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct EventPage {
+    pub events: Vec<LogEvent>,
+    /// Pass back to fetch the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Encode the position after a row as an opaque cursor
+fn encode_cursor(timestamp: &str, id: &str) -> String {
+    format!("{}|{}", timestamp, id)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The `(timestamp, id)` a cursor points after
+fn decode_cursor(cursor: &str) -> Result<(String, String)> {
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| {
+            cursor
+                .get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>();
+    bytes
+        .and_then(|b| String::from_utf8(b).ok())
+        .and_then(|s| {
+            s.split_once('|')
+                .map(|(ts, id)| (ts.to_string(), id.to_string()))
+        })
+        .ok_or_else(|| GuardianError::invalid_input("Invalid page cursor"))
+}
+
+/// Build a page from up to `limit + 1` rows ordered by `timestamp DESC, id DESC`
+fn event_page(rows: Vec<sqlx::sqlite::SqliteRow>, limit: i64) -> EventPage {
+    let more = rows.len() as i64 > limit;
+    let rows = &rows[..rows.len().min(limit.max(0) as usize)];
+    // The cursor follows the last row even if its event fails to parse
+    let next_cursor = rows.last().filter(|_| more).map(|row| {
+        encode_cursor(
+            &row.get::<String, _>("timestamp"),
+            &row.get::<String, _>("id"),
+        )
+    });
+
+    let mut events = Vec::new();
+    for row in rows {
+        match store::event_from_row(row) {
+            Ok(event) => events.push(event),
+            Err(e) => tracing::error!("Failed to deserialize event: {}", e),
+        }
+    }
+
+    EventPage {
+        events,
+        next_cursor,
+    }
+}
+
+/// Get recent events, optionally from one host only, starting after `cursor`.
+/// Keyset pagination keeps pages stable while new events arrive.
+pub async fn get_recent_events(
+    pool: &SqlitePool,
+    limit: i64,
+    hostname: Option<&str>,
+    cursor: Option<&str>,
+) -> Result<EventPage> {
+    let (after_ts, after_id) = cursor.map(decode_cursor).transpose()?.unzip();
+    let rows = sqlx::query(
+        r#"
+        SELECT id, timestamp, severity, event_json(event_data, event_payload) AS event_data, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version
+        FROM events
+        WHERE (?1 IS NULL OR hostname = ?1)
+          AND (?3 IS NULL OR timestamp < ?3 OR (timestamp = ?3 AND id < ?4))
+        ORDER BY timestamp DESC, id DESC
+        LIMIT ?2
+        "#,
+    )
+    .bind(hostname)
+    .bind(limit + 1)
+    .bind(after_ts)
+    .bind(after_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(event_page(rows, limit))
+}
+
+/// Raw events an alert references through `related_event_ids`, oldest first
+pub async fn get_related_events(pool: &SqlitePool, alert_id: &str) -> Result<Vec<LogEvent>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, timestamp, severity, event_json(event_data, event_payload) AS event_data, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version
+        FROM events
+        WHERE id IN (
+            SELECT value FROM json_each((
+                SELECT json_extract(event_json(event_data, event_payload), '$.related_event_ids')
+                FROM events
+                WHERE id = ? AND json_extract(event_json(event_data, event_payload), '$.type') = 'alert'
+            ))
+        )
+        ORDER BY timestamp ASC
+        "#,
+    )
+    .bind(alert_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        if let Ok(event) = store::event_from_row(&row) {
+            events.push(event);
+        }
+    }
+
+    Ok(events)
+}
+
+/// A version of a protected file the daemon keeps and can restore
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FileVersion {
+    /// SHA-256 of the contents, passed back to restore it
+    pub hash: String,
+    /// First file event with these contents; absent for versions the daemon
+    /// stored before it reported a change
+    pub first_seen: Option<String>,
+    /// Whether the file held these contents at its last event
+    pub current: bool,
+}
+
+/// Versions of `path` kept on `hostname`, oldest first, as listed by the
+/// `snapshot:<hash>` tags of its newest snapshot event
+pub async fn get_file_versions(
+    pool: &SqlitePool,
+    hostname: &str,
+    path: &str,
+) -> Result<Vec<FileVersion>> {
+    let latest = sqlx::query(
+        r#"
+        SELECT tags, json_extract(event_json(event_data, event_payload), '$.hash') AS hash
+        FROM events
+        WHERE hostname = ? AND event_type = 'file_integrity'
+          AND json_extract(event_json(event_data, event_payload), '$.path') = ?
+          AND id IN (SELECT event_id FROM event_tags WHERE tag = 'snapshot')
+        ORDER BY timestamp DESC, id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(hostname)
+    .bind(path)
+    .fetch_optional(pool)
+    .await?;
+    let Some(latest) = latest else {
+        return Ok(Vec::new());
+    };
+
+    let tags: Vec<String> = serde_json::from_str(&latest.get::<String, _>("tags"))?;
+    let current: Option<String> = latest.get("hash");
+    let mut versions = Vec::new();
+    for hash in tags.iter().filter_map(|tag| tag.strip_prefix("snapshot:")) {
+        let first_seen: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT MIN(timestamp) FROM events
+            WHERE hostname = ? AND event_type = 'file_integrity'
+              AND json_extract(event_json(event_data, event_payload), '$.path') = ?
+              AND json_extract(event_json(event_data, event_payload), '$.hash') = ?
+            "#,
+        )
+        .bind(hostname)
+        .bind(path)
+        .bind(hash)
+        .fetch_one(pool)
+        .await?;
+        versions.push(FileVersion {
+            hash: hash.to_string(),
+            first_seen,
+            current: current.as_deref() == Some(hash),
+        });
+    }
+
+    Ok(versions)
+}
+
+/// Get event statistics, optionally for one host only
+pub async fn get_event_stats(
+    pool: &SqlitePool,
+    hostname: Option<&str>,
+) -> Result<serde_json::Value> {
+    let total: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM events WHERE ?1 IS NULL OR hostname = ?1")
+            .bind(hostname)
+            .fetch_one(pool)
+            .await?;
+
+    let by_severity = sqlx::query(
+        r#"
+        SELECT severity, COUNT(*) as count
+        FROM events
+        WHERE ?1 IS NULL OR hostname = ?1
+        GROUP BY severity
+        "#,
+    )
+    .bind(hostname)
+    .fetch_all(pool)
+    .await?;
+
+    let mut severity_counts = serde_json::Map::new();
+    for row in by_severity {
+        severity_counts.insert(
+            row.get::<String, _>("severity"),
+            serde_json::json!(row.get::<i64, _>("count")),
+        );
+    }
+
+    let rules_triggered: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM events WHERE rule_triggered = 1 AND (?1 IS NULL OR hostname = ?1)",
+    )
+    .bind(hostname)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(serde_json::json!({
+        "total": total,
+        "by_severity": severity_counts,
+        "rules_triggered": rules_triggered
+    }))
+}
+
+/// Search events matching every criterion in `filter`, starting after `cursor`
+pub async fn search_events(
+    pool: &SqlitePool,
+    filter: &SearchFilter,
+    limit: i64,
+    cursor: Option<&str>,
+) -> Result<EventPage> {
+    let after = cursor.map(decode_cursor).transpose()?;
+    let mut sql = String::from(
+        r#"
+        SELECT id, timestamp, severity, event_json(event_data, event_payload) AS event_data, hostname, tags, rule_triggered, rule_name, rules_matched, schema_version
+        FROM events
+        WHERE 1 = 1
+        "#,
+    );
+    store::push_filter(&mut sql, filter);
+
+    if after.is_some() {
+        sql.push_str(" AND (timestamp < ? OR (timestamp = ? AND id < ?))");
+    }
+
+    sql.push_str(" ORDER BY timestamp DESC, id DESC LIMIT ?");
+
+    let mut query_builder = store::bind_filter(sqlx::query(&sql), filter);
+
+    if let Some((timestamp, id)) = &after {
+        query_builder = query_builder.bind(timestamp).bind(timestamp).bind(id);
+    }
+
+    query_builder = query_builder.bind(limit + 1);
+
+    let rows = query_builder.fetch_all(pool).await?;
+
+    Ok(event_page(rows, limit))
+}
+
+/// Events read per query when trying a rule
+const RULE_TEST_BATCH: i64 = 1000;
+
+/// Evaluate a candidate rule against the events stored from `from` up to
+/// `to`, newest first, without changing them. Only events of the rule's
+/// type carrying its first tag are read.
+pub async fn test_rule(
+    pool: &SqlitePool,
+    rule: &CompiledRule,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    max_samples: usize,
+) -> Result<RuleTest> {
+    let filter = SearchFilter {
+        from: Some(from),
+        to: Some(to),
+        event_type: rule.definition.event_type.clone(),
+        tag: rule.definition.tags.first().cloned(),
+        ..SearchFilter::default()
+    };
+    let mut test = RuleTest::new(max_samples);
+    let mut cursor = None;
+    loop {
+        let page = search_events(pool, &filter, RULE_TEST_BATCH, cursor.as_deref()).await?;
+        for event in &page.events {
+            test.observe(rule, event);
+        }
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    Ok(test)
+}
+
+/// Number of matching events carrying one tag
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// Tags of the events matching `filter`, most used first
+pub async fn get_tag_counts(
+    pool: &SqlitePool,
+    filter: &SearchFilter,
+    limit: i64,
+) -> Result<Vec<TagCount>> {
+    let mut sql = String::from(
+        r#"
+        SELECT event_tags.tag AS tag, COUNT(*) AS count
+        FROM events
+        JOIN event_tags ON event_tags.event_id = events.id
+        WHERE 1 = 1
+        "#,
+    );
+    store::push_filter(&mut sql, filter);
+    sql.push_str(" GROUP BY event_tags.tag ORDER BY count DESC, event_tags.tag LIMIT ?");
+
+    let rows = store::bind_filter(sqlx::query(&sql), filter)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| TagCount {
+            tag: row.get("tag"),
+            count: row.get("count"),
+        })
+        .collect())
+}
+
+/// A named search preset
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub filter: SearchFilter,
+    /// Search the last this many seconds, counted from when the search runs,
+    /// instead of the filter's `from`
+    pub window_secs: Option<i64>,
+    pub created_by: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl SavedSearch {
+    /// The filter to run at `now`
+    pub fn filter_at(&self, now: DateTime<Utc>) -> SearchFilter {
+        let mut filter = self.filter.clone();
+        if let Some(secs) = self.window_secs {
+            filter.from = Some(now - chrono::Duration::seconds(secs));
+        }
+        filter
+    }
+}
+
+const SAVED_SEARCH_COLUMNS: &str =
+    "id, name, filter, window_secs, created_by, created_at, updated_at";
+
+fn saved_search_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<SavedSearch> {
+    Ok(SavedSearch {
+        id: row.get("id"),
+        name: row.get("name"),
+        filter: serde_json::from_str(row.get("filter"))?,
+        window_secs: row.get("window_secs"),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+/// Saved search names are unique, so a clash gets a readable error
+fn saved_search_name_taken(e: sqlx::Error, name: &str) -> GuardianError {
+    match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            GuardianError::storage(format!("A saved search named {:?} already exists", name))
+        }
+        _ => e.into(),
+    }
+}
+
+pub async fn create_saved_search(
+    pool: &SqlitePool,
+    name: &str,
+    filter: &SearchFilter,
+    window_secs: Option<i64>,
+    created_by: &str,
+) -> Result<SavedSearch> {
+    let now = Utc::now().to_rfc3339();
+    let search = SavedSearch {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        filter: filter.clone(),
+        window_secs,
+        created_by: created_by.to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO saved_searches (id, name, filter, window_secs, created_by, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&search.id)
+    .bind(&search.name)
+    .bind(serde_json::to_string(&search.filter)?)
+    .bind(search.window_secs)
+    .bind(&search.created_by)
+    .bind(&search.created_at)
+    .bind(&search.updated_at)
+    .execute(pool)
+    .await
+    .map_err(|e| saved_search_name_taken(e, name))?;
+    Ok(search)
+}
+
+/// Every saved search, by name
+pub async fn get_saved_searches(pool: &SqlitePool) -> Result<Vec<SavedSearch>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM saved_searches ORDER BY name COLLATE NOCASE",
+        SAVED_SEARCH_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(saved_search_from_row).collect()
+}
+
+pub async fn get_saved_search(pool: &SqlitePool, id: &str) -> Result<Option<SavedSearch>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM saved_searches WHERE id = ?",
+        SAVED_SEARCH_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(saved_search_from_row).transpose()
+}
+
+/// Replace a saved search's name, filter and window; false if it does not exist
+pub async fn update_saved_search(
+    pool: &SqlitePool,
+    id: &str,
+    name: &str,
+    filter: &SearchFilter,
+    window_secs: Option<i64>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE saved_searches SET name = ?, filter = ?, window_secs = ?, updated_at = ? WHERE id = ?",
+    )
+    .bind(name)
+    .bind(serde_json::to_string(filter)?)
+    .bind(window_secs)
+    .bind(Utc::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| saved_search_name_taken(e, name))?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Remove a saved search; false if it does not exist
+pub async fn delete_saved_search(pool: &SqlitePool, id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM saved_searches WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// A reporting agent, as shown in the fleet host list
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HostSummary {
+    pub hostname: String,
+    /// Unset for hosts known only from their events
+    pub agent_version: Option<String>,
+    /// Peer address of the last connection, or `local` for the bundled daemon
+    pub address: Option<String>,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    pub events: i64,
+    pub last_event: Option<String>,
+    /// Unset for agents that do not send heartbeats
+    pub last_heartbeat: Option<String>,
+    pub uptime_secs: Option<i64>,
+    /// Collector queue reported in the last heartbeat
+    pub forward_pending: Option<i64>,
+    pub forward_dropped: Option<i64>,
+    /// Set while the agent is overdue
+    pub offline_since: Option<String>,
+}
+
+/// Record an agent's announcement
+pub async fn upsert_host(pool: &SqlitePool, hello: &AgentHello, address: &str) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        INSERT INTO hosts (hostname, agent_version, address, capabilities, first_seen, last_seen)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+        ON CONFLICT(hostname) DO UPDATE SET
+            agent_version = excluded.agent_version,
+            address = excluded.address,
+            capabilities = excluded.capabilities,
+            last_seen = excluded.last_seen
+        "#,
+    )
+    .bind(&hello.hostname)
+    .bind(&hello.agent_version)
+    .bind(address)
+    .bind(serde_json::to_string(&hello.capabilities)?)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Every host that announced itself or has stored events, busiest first
+pub async fn get_hosts(pool: &SqlitePool) -> Result<Vec<HostSummary>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT names.hostname, COALESCE(h.agent_version, b.agent_version) AS agent_version,
+               h.address, h.first_seen, h.last_seen,
+               COALESCE(e.events, 0) AS events, e.last_event,
+               b.last_heartbeat, b.uptime_secs, b.forward_pending, b.forward_dropped,
+               b.offline_since
+        FROM (
+            SELECT hostname FROM hosts
+            UNION SELECT hostname FROM heartbeats
+            UNION SELECT DISTINCT hostname FROM events
+        ) AS names
+        LEFT JOIN hosts h ON h.hostname = names.hostname
+        LEFT JOIN heartbeats b ON b.hostname = names.hostname
+        LEFT JOIN (
+            SELECT hostname, COUNT(*) AS events, MAX(timestamp) AS last_event
+            FROM events
+            GROUP BY hostname
+        ) e ON e.hostname = names.hostname
+        ORDER BY events DESC, names.hostname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| HostSummary {
+            hostname: row.get("hostname"),
+            agent_version: row.get("agent_version"),
+            address: row.get("address"),
+            first_seen: row.get("first_seen"),
+            last_seen: row.get("last_seen"),
+            events: row.get("events"),
+            last_event: row.get("last_event"),
+            last_heartbeat: row.get("last_heartbeat"),
+            uptime_secs: row.get("uptime_secs"),
+            forward_pending: row.get("forward_pending"),
+            forward_dropped: row.get("forward_dropped"),
+            offline_since: row.get("offline_since"),
+        })
+        .collect())
+}
+
+/// Record a heartbeat received at `now`, due again by `expected_by`. Returns
+/// when the host had been marked offline, if it was.
+pub async fn record_heartbeat(
+    pool: &SqlitePool,
+    hostname: &str,
+    agent_version: &str,
+    uptime_secs: u64,
+    forward: Option<ForwardStats>,
+    now: DateTime<Utc>,
+    expected_by: DateTime<Utc>,
+) -> Result<Option<String>> {
+    let offline_since: Option<String> =
+        sqlx::query_scalar("SELECT offline_since FROM heartbeats WHERE hostname = ?")
+            .bind(hostname)
+            .fetch_optional(pool)
+            .await?
+            .flatten();
+
+    sqlx::query(
+        r#"
+        INSERT INTO heartbeats
+            (hostname, agent_version, uptime_secs, forward_pending, forward_dropped,
+             last_heartbeat, expected_by, offline_since)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, NULL)
+        ON CONFLICT(hostname) DO UPDATE SET
+            agent_version = excluded.agent_version,
+            uptime_secs = excluded.uptime_secs,
+            forward_pending = excluded.forward_pending,
+            forward_dropped = excluded.forward_dropped,
+            last_heartbeat = excluded.last_heartbeat,
+            expected_by = excluded.expected_by,
+            offline_since = NULL
+        "#,
+    )
+    .bind(hostname)
+    .bind(agent_version)
+    .bind(uptime_secs as i64)
+    .bind(forward.map(|f| f.pending as i64))
+    .bind(forward.map(|f| f.dropped as i64))
+    .bind(now.to_rfc3339())
+    .bind(expected_by.to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(offline_since)
+}
+
+/// Mark hosts whose heartbeat is overdue at `now` as offline, returning each
+/// newly offline host with the time of its last heartbeat
+pub async fn mark_offline_hosts(
+    pool: &SqlitePool,
+    now: DateTime<Utc>,
+) -> Result<Vec<(String, String)>> {
+    let rows = sqlx::query(
+        r#"
+        UPDATE heartbeats SET offline_since = ?1
+        WHERE offline_since IS NULL AND expected_by < ?1
+        RETURNING hostname, last_heartbeat
+        "#,
+    )
+    .bind(now.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("hostname"), row.get("last_heartbeat")))
+        .collect())
+}
+
+/// Enrollment state of one collector agent
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Enrollment {
+    pub hostname: String,
+    /// `pending`, `enrolled` or `revoked`
+    pub status: String,
+    pub created_at: String,
+    /// When an unused enrollment token stops working
+    pub token_expires: String,
+    pub enrolled_at: Option<String>,
+    pub revoked_at: Option<String>,
+    /// SHA-256 of the certificate issued to the agent
+    pub fingerprint: Option<String>,
+}
+
+/// Start (or restart) enrollment for a host. Any certificate issued to it
+/// before stops being accepted.
+pub async fn create_enrollment(
+    pool: &SqlitePool,
+    hostname: &str,
+    token_hash: &str,
+    expires: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO enrollments (hostname, token_hash, token_expires, status, created_at)
+        VALUES (?1, ?2, ?3, 'pending', ?4)
+        ON CONFLICT(hostname) DO UPDATE SET
+            token_hash = excluded.token_hash,
+            token_expires = excluded.token_expires,
+            status = 'pending',
+            fingerprint = NULL,
+            created_at = excluded.created_at,
+            enrolled_at = NULL,
+            revoked_at = NULL
+        "#,
+    )
+    .bind(hostname)
+    .bind(token_hash)
+    .bind(expires.to_rfc3339())
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Host a still valid enrollment token was issued for
+pub async fn find_pending_enrollment(
+    pool: &SqlitePool,
+    token_hash: &str,
+) -> Result<Option<String>> {
+    let row = sqlx::query(
+        "SELECT hostname FROM enrollments WHERE token_hash = ? AND status = 'pending' AND token_expires > ?",
+    )
+    .bind(token_hash)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| row.get("hostname")))
+}
+
+/// Bind the issued certificate to the host and burn the token. Returns false
+/// if the token was used or replaced in the meantime.
+pub async fn complete_enrollment(
+    pool: &SqlitePool,
+    hostname: &str,
+    token_hash: &str,
+    fingerprint: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE enrollments
+        SET status = 'enrolled', token_hash = NULL, fingerprint = ?3, enrolled_at = ?4
+        WHERE hostname = ?1 AND token_hash = ?2 AND status = 'pending'
+        "#,
+    )
+    .bind(hostname)
+    .bind(token_hash)
+    .bind(fingerprint)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Hostname and status of the agent holding the certificate `fingerprint`
+pub async fn enrollment_by_fingerprint(
+    pool: &SqlitePool,
+    fingerprint: &str,
+) -> Result<Option<(String, String)>> {
+    let row = sqlx::query("SELECT hostname, status FROM enrollments WHERE fingerprint = ?")
+        .bind(fingerprint)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|row| (row.get("hostname"), row.get("status"))))
+}
+
+/// Stop accepting a host's certificate and any unused token. Returns false
+/// for unknown hosts.
+pub async fn revoke_enrollment(pool: &SqlitePool, hostname: &str) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE enrollments
+        SET status = 'revoked', token_hash = NULL, revoked_at = ?2
+        WHERE hostname = ?1 AND status != 'revoked'
+        "#,
+    )
+    .bind(hostname)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// All enrollments, newest first
+pub async fn get_enrollments(pool: &SqlitePool) -> Result<Vec<Enrollment>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT hostname, status, created_at, token_expires, enrolled_at, revoked_at, fingerprint
+        FROM enrollments
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Enrollment {
+            hostname: row.get("hostname"),
+            status: row.get("status"),
+            created_at: row.get("created_at"),
+            token_expires: row.get("token_expires"),
+            enrolled_at: row.get("enrolled_at"),
+            revoked_at: row.get("revoked_at"),
+            fingerprint: row.get("fingerprint"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use guardian_common::rollup::RollupCount;
+    use guardian_common::EventType;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn test_pool() -> SqlitePool {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        init_database(&path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connections_use_wal_and_busy_timeout() {
+        let pool = test_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+        let timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(timeout, BUSY_TIMEOUT.as_millis() as i64);
+        // NORMAL
+        let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(synchronous, 1);
+    }
+
+    #[tokio::test]
+    async fn test_encryption_key_requires_sqlcipher() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let result = open_database(&path, Some("it's secret")).await;
+        if cfg!(feature = "sqlcipher") {
+            let pool = result.unwrap();
+            insert_event(&pool, &event_at("web-1", Utc::now()))
+                .await
+                .unwrap();
+            pool.close().await;
+
+            assert!(open_database(&path, None).await.is_err());
+            assert!(open_database(&path, Some("wrong")).await.is_err());
+            let pool = open_database(&path, Some("it's secret")).await.unwrap();
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+        } else {
+            // Never fall back to writing plaintext
+            assert!(format!("{:#}", result.unwrap_err()).contains("SQLCipher"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrates_database_from_untracked_schema() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let event = event_at("web-1", Utc::now());
+        let event_data = serde_json::to_string(&event.event_type).unwrap();
+
+        // A database written before migrations, with the whole type in `event_type`
+        let legacy = SqlitePoolOptions::new()
+            .connect(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            CREATE TABLE events (
+                id TEXT PRIMARY KEY NOT NULL,
+                timestamp TEXT NOT NULL,
+                severity TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                event_data TEXT NOT NULL,
+                hostname TEXT NOT NULL,
+                tags TEXT NOT NULL,
+                rule_triggered INTEGER NOT NULL DEFAULT 0,
+                rule_name TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&legacy)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO events (id, timestamp, severity, event_type, event_data, hostname, tags, rule_triggered, rule_name) VALUES (?, ?, 'INFO', ?, ?, 'web-1', '[\"legacy\"]', 1, 'legacy_rule')",
+        )
+        .bind(event.id.to_string())
+        .bind(event.timestamp.to_rfc3339())
+        .bind(&event_data)
+        .bind(&event_data)
+        .execute(&legacy)
+        .await
+        .unwrap();
+        legacy.close().await;
+
+        let pool = init_database(&path).await.unwrap();
+        let kind: String = sqlx::query_scalar("SELECT event_type FROM events")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(kind, "system_log");
+        // Existing tags are copied into the tags table
+        let legacy_tag = SearchFilter {
+            tag: Some("legacy".to_string()),
+            ..SearchFilter::default()
+        };
+        assert_eq!(
+            search_events(&pool, &legacy_tag, 10, None)
+                .await
+                .unwrap()
+                .events
+                .len(),
+            1
+        );
+        // Rows from before versioning are upgraded from version 1
+        let stored = get_recent_events(&pool, 10, None, None)
+            .await
+            .unwrap()
+            .events
+            .remove(0);
+        assert_eq!(stored.id, event.id);
+        assert_eq!(stored.schema_version, guardian_common::SCHEMA_VERSION);
+        assert_eq!(stored.rules_matched, vec!["legacy_rule"]);
+
+        // Opening again applies nothing new
+        pool.close().await;
+        let pool = init_database(&path).await.unwrap();
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            applied as usize,
+            sqlx::migrate!("./migrations").iter().count()
+        );
+    }
+
+    fn event_at(hostname: &str, timestamp: DateTime<Utc>) -> LogEvent {
+        let mut event = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: "hello".to_string(),
+            },
+            hostname.to_string(),
+        );
+        event.timestamp = timestamp;
+        event
+    }
+
+    #[tokio::test]
+    async fn test_fleet_trends_prefer_rollups_over_raw_events() {
+        let pool = test_pool().await;
+        let hour = "2026-01-05T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        // web-1 ships raw events and a rollup for the same hour; db-1 only raw events
+        for minute in [5, 20] {
+            let at = hour + Duration::minutes(minute);
+            insert_event(&pool, &event_at("web-1", at)).await.unwrap();
+            insert_event(&pool, &event_at("db-1", at)).await.unwrap();
+        }
+
+        let rollup = StatsRollup {
+            hostname: "web-1".to_string(),
+            period_start: hour,
+            period_secs: 3600,
+            counts: vec![RollupCount {
+                event_type: "system_log".to_string(),
+                severity: Severity::Info,
+                count: 50,
+            }],
+            top_paths: Vec::new(),
+            signature: None,
+        };
+        insert_rollup(&pool, &rollup).await.unwrap();
+        // Re-sending the same period replaces it rather than adding up
+        insert_rollup(&pool, &rollup).await.unwrap();
+
+        let trends = get_fleet_trends(&pool, hour, hour + Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            trends,
+            vec![TrendPoint {
+                bucket: hour.to_rfc3339(),
+                severity: "INFO".to_string(),
+                count: 52,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_alerts_exclude_own_acks() {
+        let pool = test_pool().await;
+        let hour = "2026-01-05T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let first = event_at("web-1", hour).with_rule("high_cpu".to_string());
+        let second =
+            event_at("web-1", hour + Duration::minutes(1)).with_rule("high_cpu".to_string());
+        insert_event(&pool, &first).await.unwrap();
+        insert_event(&pool, &second).await.unwrap();
+        insert_event(&pool, &event_at("web-1", hour)).await.unwrap();
+
+        acknowledge_event(&pool, &first.id.to_string(), "alice")
+            .await
+            .unwrap();
+
+        let to = hour + Duration::hours(1);
+        let alice = get_unacknowledged_alerts(&pool, "alice", hour, to)
+            .await
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].id, second.id);
+
+        let bob = get_unacknowledged_alerts(&pool, "bob", hour, to)
+            .await
+            .unwrap();
+        assert_eq!(bob.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_alert_timings_use_first_ack() {
+        let pool = test_pool().await;
+        let hour = Utc::now() - Duration::hours(1);
+
+        let alert = event_at("web-1", hour).with_rule("high_cpu".to_string());
+        insert_event(&pool, &alert).await.unwrap();
+        insert_event(&pool, &event_at("web-1", hour)).await.unwrap();
+
+        let id = alert.id.to_string();
+        acknowledge_event(&pool, &id, "alice").await.unwrap();
+        acknowledge_event(&pool, &id, "bob").await.unwrap();
+        resolve_event(&pool, &id, "bob").await.unwrap();
+
+        let timings = get_alert_timings(&pool, hour, Utc::now()).await.unwrap();
+        assert_eq!(timings.len(), 1);
+        assert_eq!(timings[0].severity, Severity::Info);
+        assert!(timings[0].acknowledged_at.unwrap() <= timings[0].resolved_at.unwrap());
+
+        assert!(record_sla_breach(&pool, &id, "ack").await.unwrap());
+        assert!(!record_sla_breach(&pool, &id, "ack").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_stay_stable_as_events_arrive() {
+        let pool = test_pool().await;
+        let start = Utc::now() - Duration::minutes(10);
+        // Two events share each timestamp so ties are broken by id
+        let mut ids = Vec::new();
+        for i in 0..6 {
+            let event = event_at("web-1", start + Duration::seconds(i / 2));
+            ids.push(event.id);
+            insert_event(&pool, &event).await.unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = get_recent_events(&pool, 4, None, cursor.as_deref())
+                .await
+                .unwrap();
+            seen.extend(page.events.iter().map(|e| e.id));
+            // Newer events must not shift later pages
+            insert_event(&pool, &event_at("web-1", Utc::now()))
+                .await
+                .unwrap();
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen.len(), 6);
+        seen.sort();
+        ids.sort();
+        assert_eq!(seen, ids);
+
+        let web = SearchFilter {
+            hostname: Some("web-1".to_string()),
+            ..SearchFilter::default()
+        };
+        let first = search_events(&pool, &web, 2, None).await.unwrap();
+        let second = search_events(&pool, &web, 2, first.next_cursor.as_deref())
+            .await
+            .unwrap();
+        assert!(second.events[0].timestamp <= first.events[1].timestamp);
+        assert_ne!(second.events[0].id, first.events[1].id);
+        assert!(get_recent_events(&pool, 2, None, Some("not-a-cursor"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_events_read_back_with_quotes_in_fields() {
+        let pool = test_pool().await;
+        let event = event_at("web \"1\"", Utc::now()).with_rule("say \"hi\"\\");
+        insert_event(&pool, &event).await.unwrap();
+
+        let page = get_recent_events(&pool, 10, None, None).await.unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].hostname, event.hostname);
+        assert_eq!(page.events[0].rule_name, event.rule_name);
+    }
+
+    #[tokio::test]
+    async fn test_top_n_ranks_each_dimension() {
+        let pool = test_pool().await;
+        let now = Utc::now();
+        let socket = |remote: &str| {
+            let mut event = event_at("web-1", now);
+            event.event_type = EventType::NetworkSocket {
+                local_addr: "10.0.0.5:51000".to_string(),
+                remote_addr: Some(remote.to_string()),
+                remote_host: None,
+                protocol: "tcp".to_string(),
+                state: "ESTABLISHED".to_string(),
+                traffic: None,
+                process: None,
+            };
+            event
+        };
+        let file = |path: &str| {
+            let mut event = event_at("db-1", now);
+            event.event_type = EventType::FileIntegrity {
+                path: path.to_string(),
+                operation: guardian_common::FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            };
+            event.with_rule("Critical File Modified".to_string())
+        };
+        for event in [
+            socket("203.0.113.9:443"),
+            socket("203.0.113.9:443"),
+            socket("198.51.100.1:22"),
+            file("/etc/passwd"),
+            file("/etc/passwd"),
+            file("/etc/shadow"),
+            event_at("db-1", now),
+            // Outside the range
+            event_at("old-1", now - chrono::Duration::days(2)),
+        ] {
+            insert_event(&pool, &event).await.unwrap();
+        }
+
+        let from = now - chrono::Duration::hours(1);
+        let to = now + chrono::Duration::seconds(1);
+        let top = |dimension| get_top_n(&pool, dimension, from, to, 10);
+        let ranked = |entries: Vec<TopEntry>| {
+            entries
+                .into_iter()
+                .map(|e| (e.key, e.count))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ranked(top(TopDimension::Hosts).await.unwrap()),
+            [("db-1".to_string(), 4), ("web-1".to_string(), 3)]
+        );
+        assert_eq!(
+            ranked(top(TopDimension::Rules).await.unwrap()),
+            [("Critical File Modified".to_string(), 3)]
+        );
+        assert_eq!(
+            ranked(top(TopDimension::Paths).await.unwrap()),
+            [
+                ("/etc/passwd".to_string(), 2),
+                ("/etc/shadow".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            ranked(top(TopDimension::RemoteAddresses).await.unwrap()),
+            [
+                ("203.0.113.9:443".to_string(), 2),
+                ("198.51.100.1:22".to_string(), 1)
+            ]
+        );
+        let first = get_top_n(&pool, TopDimension::Paths, from, to, 1)
+            .await
+            .unwrap();
+        assert_eq!(first.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_event_timeline_buckets_by_severity() {
+        let pool = test_pool().await;
+        let from = "2026-03-02T10:00:30Z".parse::<DateTime<Utc>>().unwrap();
+        let minute = chrono::Duration::minutes(1);
+
+        let mut high = event_at("web-1", from + chrono::Duration::seconds(10));
+        high.severity = Severity::High;
+        insert_event(&pool, &high).await.unwrap();
+        insert_event(
+            &pool,
+            &event_at("web-1", from + chrono::Duration::seconds(20)),
+        )
+        .await
+        .unwrap();
+        insert_event(&pool, &event_at("db-1", from + minute * 2))
+            .await
+            .unwrap();
+        // Outside the range
+        insert_event(&pool, &event_at("web-1", from + minute * 10))
+            .await
+            .unwrap();
+
+        let timeline = get_event_timeline(
+            &pool,
+            TimelineInterval::Minute,
+            from,
+            from + minute * 3,
+            &HeatmapFilter::default(),
+        )
+        .await
+        .unwrap();
+        let buckets: Vec<_> = timeline.iter().map(|b| b.bucket.as_str()).collect();
+        assert_eq!(
+            buckets,
+            [
+                "2026-03-02T10:00:00+00:00",
+                "2026-03-02T10:01:00+00:00",
+                "2026-03-02T10:02:00+00:00",
+                "2026-03-02T10:03:00+00:00"
+            ]
+        );
+        assert_eq!(timeline[0].total, 2);
+        assert_eq!(timeline[0].by_severity["HIGH"], 1);
+        assert_eq!(timeline[0].by_severity["INFO"], 1);
+        assert_eq!(timeline[1].total, 0);
+        assert_eq!(timeline[2].total, 1);
+
+        let filter = HeatmapFilter {
+            hostname: Some("db-1".to_string()),
+            ..HeatmapFilter::default()
+        };
+        let hourly = get_event_timeline(
+            &pool,
+            TimelineInterval::Hour,
+            from,
+            from + minute * 3,
+            &filter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].total, 1);
+
+        let too_long = get_event_timeline(
+            &pool,
+            TimelineInterval::Minute,
+            from,
+            from + chrono::Duration::days(30),
+            &filter,
+        )
+        .await;
+        assert!(too_long.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_activity_heatmap() {
+        let pool = test_pool().await;
+        // 2026-01-05 is a Monday
+        let monday_late = "2026-01-05T23:30:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        insert_event(&pool, &event_at("web-1", monday_late))
+            .await
+            .unwrap();
+        insert_event(
+            &pool,
+            &event_at("web-1", monday_late).with_rule("high_cpu".to_string()),
+        )
+        .await
+        .unwrap();
+        insert_event(&pool, &event_at("db-1", monday_late))
+            .await
+            .unwrap();
+
+        let from = monday_late - Duration::days(1);
+        let to = monday_late + Duration::days(1);
+
+        let heatmap = get_activity_heatmap(&pool, &HeatmapFilter::default(), from, to, 0)
+            .await
+            .unwrap();
+        assert_eq!(heatmap.events[0][23], 3);
+        assert_eq!(heatmap.alerts[0][23], 1);
+
+        // At UTC+2 the same events fall on Tuesday 01:00
+        let filter = HeatmapFilter {
+            hostname: Some("web-1".to_string()),
+            ..Default::default()
+        };
+        let heatmap = get_activity_heatmap(&pool, &filter, from, to, 120)
+            .await
+            .unwrap();
+        assert_eq!(heatmap.events[1][1], 2);
+        assert_eq!(heatmap.events.iter().flatten().sum::<i64>(), 2);
+    }
+
+    fn by_user(user: &str) -> SearchFilter {
+        SearchFilter {
+            user: Some(user.to_string()),
+            ..SearchFilter::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_events_by_user() {
+        let pool = test_pool().await;
+        for (path, uid, username) in [
+            ("/srv/www/index.php", 33, "www-data"),
+            ("/root/.bashrc", 0, "root"),
+        ] {
+            let event = LogEvent::new(
+                Severity::Low,
+                EventType::FileIntegrity {
+                    path: path.to_string(),
+                    operation: guardian_common::FileOperation::Modify,
+                    hash: None,
+                    user: Some(guardian_common::EventUser {
+                        uid,
+                        username: Some(username.to_string()),
+                    }),
+                    executable: None,
+                    process: None,
+                    diff: None,
+                },
+                "web-1".to_string(),
+            );
+            insert_event(&pool, &event).await.unwrap();
+        }
+        insert_event(&pool, &event_at("web-1", Utc::now()))
+            .await
+            .unwrap();
+
+        let by_name = search_events(&pool, &by_user("www-data"), 10, None)
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(by_name.len(), 1);
+        assert!(matches!(
+            &by_name[0].event_type,
+            EventType::FileIntegrity { user: Some(user), .. } if user.uid == 33
+        ));
+
+        let by_uid = search_events(&pool, &by_user("0"), 10, None)
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(by_uid.len(), 1);
+        assert_eq!(
+            search_events(&pool, &SearchFilter::default(), 10, None)
+                .await
+                .unwrap()
+                .events
+                .len(),
+            3
+        );
+
+        // Authentication events name the account directly
+        let login = LogEvent::new(
+            Severity::Info,
+            EventType::AuthEvent {
+                user: "root".to_string(),
+                source_ip: Some("203.0.113.5".to_string()),
+                method: "publickey".to_string(),
+                outcome: guardian_common::AuthOutcome::Success,
+                tty: None,
+            },
+            "web-1".to_string(),
+        );
+        insert_event(&pool, &login).await.unwrap();
+        let by_name = search_events(&pool, &by_user("root"), 10, None)
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(by_name.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_payloads_read_back_and_stay_searchable() {
+        let pool = test_pool().await;
+        let file_event = |path: &str| {
+            LogEvent::new(
+                Severity::Low,
+                EventType::FileIntegrity {
+                    path: path.to_string(),
+                    operation: guardian_common::FileOperation::Modify,
+                    hash: Some(format!("{:064x}", path.len())),
+                    user: None,
+                    executable: None,
+                    process: None,
+                    diff: None,
+                },
+                "web-1".to_string(),
+            )
+        };
+
+        // A fresh database has nothing to train on, so the first events are
+        // compressed without a dictionary until enough are stored
+        let encoder = PayloadEncoder::new();
+        insert_compressed_event(&pool, &file_event("/etc/passwd"), &encoder)
+            .await
+            .unwrap();
+        assert!(!encoder.has_dictionary());
+        let start = Utc::now() - Duration::hours(1);
+        for i in 0..300 {
+            let mut event = event_at("web-1", start + Duration::seconds(i));
+            event.event_type = EventType::SystemLog {
+                source: format!("unit-{}", i % 7),
+                level: "info".to_string(),
+                message: format!("Started session {} of user {}", i, i % 13),
+            };
+            insert_compressed_event(&pool, &event, &encoder)
+                .await
+                .unwrap();
+        }
+        assert!(encoder.has_dictionary());
+
+        let mut log = event_at("web-1", Utc::now());
+        log.event_type = EventType::SystemLog {
+            source: "sshd".to_string(),
+            level: "warning".to_string(),
+            message: "Connection closed by 203.0.113.5".to_string(),
+        };
+        insert_compressed_event(&pool, &log, &encoder)
+            .await
+            .unwrap();
+        insert_compressed_event(&pool, &file_event("/etc/hosts"), &encoder)
+            .await
+            .unwrap();
+        insert_event(&pool, &file_event("/etc/shadow"))
+            .await
+            .unwrap();
+
+        // Compressed rows keep the payload only once
+        let leftover: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM events WHERE event_payload IS NOT NULL AND event_data != ''",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(leftover, 0);
+
+        let events = get_recent_events(&pool, 3, None, None)
+            .await
+            .unwrap()
+            .events;
+        let read = events.iter().find(|e| e.id == log.id).unwrap();
+        assert_eq!(
+            serde_json::to_value(&read.event_type).unwrap(),
+            serde_json::to_value(&log.event_type).unwrap()
+        );
+
+        // Free text matches anywhere in a compressed payload
+        let search = |query: &str| {
+            let filter = SearchFilter {
+                query: Some(query.to_string()),
+                ..SearchFilter::default()
+            };
+            let pool = pool.clone();
+            async move {
+                search_events(&pool, &filter, 10, None)
+                    .await
+                    .unwrap()
+                    .events
+            }
+        };
+        let by_message = search("closed by 203.0.113").await;
+        assert_eq!(by_message.len(), 1);
+        assert_eq!(by_message[0].id, log.id);
+
+        // Compressed and uncompressed rows are filtered alike
+        let by_path = search("/etc/").await;
+        assert_eq!(by_path.len(), 3);
+        assert!(by_path.iter().all(|e| matches!(
+            &e.event_type,
+            EventType::FileIntegrity { hash: Some(_), .. }
+        )));
+
+        // A restarted console picks up the stored dictionary
+        let restarted = PayloadEncoder::new();
+        insert_compressed_event(&pool, &file_event("/etc/group"), &restarted)
+            .await
+            .unwrap();
+        assert!(restarted.has_dictionary());
+        assert_eq!(search("/etc/group").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_events_by_range_type_tag_and_rule() {
+        let pool = test_pool().await;
+        let start = "2026-04-01T08:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        for minutes in 0..4 {
+            let mut event = event_at("web-1", start + Duration::minutes(minutes))
+                .with_tag(if minutes % 2 == 0 { "even" } else { "odd" });
+            if minutes == 2 {
+                event = event.with_rules([
+                    "Odd Hours Login".to_string(),
+                    "high_severity_alert".to_string(),
+                ]);
+                event.severity = Severity::High;
+            }
+            insert_event(&pool, &event).await.unwrap();
+        }
+        let mut file = event_at("db-1", start + Duration::minutes(1));
+        file.event_type = EventType::FileIntegrity {
+            path: "/etc/hosts".to_string(),
+            operation: guardian_common::FileOperation::Modify,
+            hash: None,
+            user: None,
+            executable: None,
+            process: None,
+            diff: None,
+        };
+        insert_event(&pool, &file).await.unwrap();
+
+        let count = |filter: SearchFilter| {
+            let pool = pool.clone();
+            async move {
+                search_events(&pool, &filter, 50, None)
+                    .await
+                    .unwrap()
+                    .events
+                    .len()
+            }
+        };
+        let range = SearchFilter {
+            from: Some(start + Duration::minutes(1)),
+            to: Some(start + Duration::minutes(3)),
+            ..SearchFilter::default()
+        };
+        assert_eq!(count(range.clone()).await, 3);
+        assert_eq!(
+            count(SearchFilter {
+                event_type: Some("file_integrity".to_string()),
+                ..range.clone()
+            })
+            .await,
+            1
+        );
+        assert_eq!(
+            count(SearchFilter {
+                tag: Some("even".to_string()),
+                ..range.clone()
+            })
+            .await,
+            1
+        );
+        // Tags match whole, not as substrings of the JSON array
+        assert_eq!(
+            count(SearchFilter {
+                tag: Some("eve".to_string()),
+                ..SearchFilter::default()
+            })
+            .await,
+            0
+        );
+        let rule = SearchFilter {
+            rule_name: Some("Odd Hours Login".to_string()),
+            severity: Some(Severity::High),
+            query: Some("HELLO".to_string()),
+            ..SearchFilter::default()
+        };
+        assert_eq!(count(rule.clone()).await, 1);
+        let found = search_events(&pool, &rule, 50, None).await.unwrap().events;
+        assert_eq!(
+            found[0].rules_matched,
+            ["Odd Hours Login", "high_severity_alert"]
+        );
+        assert_eq!(
+            count(SearchFilter {
+                severity: Some(Severity::Low),
+                ..rule
+            })
+            .await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alert_lifecycle() {
+        let pool = test_pool().await;
+        let now = Utc::now();
+        let hit = event_at("web-1", now).with_rule("Critical File Modified");
+        let id = hit.id.to_string();
+        insert_event(&pool, &hit).await.unwrap();
+        // Resent events do not queue a second alert, and raw events none at all
+        insert_event(&pool, &hit).await.unwrap();
+        insert_event(&pool, &event_at("web-1", now)).await.unwrap();
+
+        let open = get_alerts(&pool, Some(AlertStatus::Open), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].event.id, hit.id);
+
+        acknowledge_event(&pool, &id, "alice").await.unwrap();
+        acknowledge_event(&pool, &id, "bob").await.unwrap();
+        assert!(assign_alert(&pool, &id, Some("bob")).await.unwrap());
+        assert!(!assign_alert(&pool, "missing", Some("bob")).await.unwrap());
+        let alert = get_alerts(&pool, None, Some("bob"), 10)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(alert.status, AlertStatus::Acknowledged);
+        assert_eq!(alert.acknowledged_by.as_deref(), Some("alice"));
+
+        close_alert(&pool, &id, "bob", Some("false positive"))
+            .await
+            .unwrap();
+        let alert = get_alerts(&pool, Some(AlertStatus::Closed), None, 10)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(alert.close_reason.as_deref(), Some("false positive"));
+        assert_eq!(alert.closed_by.as_deref(), Some("bob"));
+        // SLA metrics see the closure as the resolution
+        let timings = get_alert_timings(&pool, now - Duration::hours(1), now + Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(timings[0].resolved_at.is_some());
+
+        assert!(reopen_alert(&pool, &id).await.unwrap());
+        assert!(!reopen_alert(&pool, &id).await.unwrap());
+        let alert = get_alerts(&pool, None, None, 10).await.unwrap().remove(0);
+        assert_eq!(alert.status, AlertStatus::Open);
+        assert!(alert.close_reason.is_none());
+        let timings = get_alert_timings(&pool, now - Duration::hours(1), now + Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(timings[0].resolved_at.is_none());
+
+        // Resolving is closing without a reason
+        resolve_event(&pool, &id, "alice").await.unwrap();
+        assert_eq!(
+            get_alerts(&pool, Some(AlertStatus::Closed), None, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_saved_searches_round_trip() {
+        let pool = test_pool().await;
+        let critical_network = SearchFilter {
+            severity: Some(Severity::Critical),
+            event_type: Some("network_socket".to_string()),
+            ..SearchFilter::default()
+        };
+        let saved = create_saved_search(
+            &pool,
+            "Critical network events, last 24h",
+            &critical_network,
+            Some(86_400),
+            "alice",
+        )
+        .await
+        .unwrap();
+        create_saved_search(&pool, "all", &SearchFilter::default(), None, "bob")
+            .await
+            .unwrap();
+
+        let duplicate = create_saved_search(&pool, "all", &SearchFilter::default(), None, "bob")
+            .await
+            .unwrap_err();
+        assert!(duplicate.to_string().contains("already exists"));
+
+        let names: Vec<_> = get_saved_searches(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, ["all", "Critical network events, last 24h"]);
+        let loaded = get_saved_search(&pool, &saved.id).await.unwrap().unwrap();
+        assert_eq!(loaded, saved);
+
+        // The window is applied when the search runs
+        let now = Utc::now();
+        let filter = loaded.filter_at(now);
+        assert_eq!(filter.from, Some(now - Duration::hours(24)));
+        assert_eq!(filter.event_type.as_deref(), Some("network_socket"));
+
+        assert!(update_saved_search(
+            &pool,
+            &saved.id,
+            "Critical network",
+            &critical_network,
+            None
+        )
+        .await
+        .unwrap());
+        let updated = get_saved_search(&pool, &saved.id).await.unwrap().unwrap();
+        assert_eq!(updated.name, "Critical network");
+        assert_eq!(updated.window_secs, None);
+        assert_eq!(updated.created_by, "alice");
+        assert!(
+            update_saved_search(&pool, &saved.id, "all", &critical_network, None)
+                .await
+                .is_err()
+        );
+
+        assert!(delete_saved_search(&pool, &saved.id).await.unwrap());
+        assert!(!delete_saved_search(&pool, &saved.id).await.unwrap());
+        assert!(get_saved_search(&pool, &saved.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tag_counts_follow_filter() {
+        let pool = test_pool().await;
+        let now = Utc::now();
+        let tagged = |hostname: &str, tags: &[&str]| {
+            tags.iter()
+                .fold(event_at(hostname, now), |event, tag| event.with_tag(*tag))
+        };
+        let repeated = tagged("web-1", &["fim", "critical"]);
+        for event in [
+            repeated.clone(),
+            // A resent event keeps its tags counted once
+            repeated,
+            tagged("web-1", &["fim"]),
+            tagged("db-1", &["fim", "auth"]),
+            tagged("db-1", &[]),
+        ] {
+            insert_event(&pool, &event).await.unwrap();
+        }
+
+        let counts = |filter: SearchFilter| {
+            let pool = pool.clone();
+            async move {
+                get_tag_counts(&pool, &filter, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|c| (c.tag, c.count))
+                    .collect::<Vec<_>>()
+            }
+        };
+        let owned = |pairs: &[(&str, i64)]| {
+            pairs
+                .iter()
+                .map(|(tag, count)| (tag.to_string(), *count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            counts(SearchFilter::default()).await,
+            owned(&[("fim", 3), ("auth", 1), ("critical", 1)])
+        );
+        assert_eq!(
+            counts(SearchFilter {
+                hostname: Some("web-1".to_string()),
+                ..SearchFilter::default()
+            })
+            .await,
+            owned(&[("fim", 2), ("critical", 1)])
+        );
+        // Facets of events that carry a tag
+        assert_eq!(
+            counts(SearchFilter {
+                tag: Some("critical".to_string()),
+                ..SearchFilter::default()
+            })
+            .await,
+            owned(&[("critical", 1), ("fim", 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rule_against_stored_events() {
+        let pool = test_pool().await;
+        let start = "2026-03-01T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let log = |minutes: i64, hostname: &str, source: &str| {
+            let mut event = event_at(hostname, start + Duration::minutes(minutes));
+            event.event_type = EventType::SystemLog {
+                source: source.to_string(),
+                level: "warning".to_string(),
+                message: "disk quota exceeded".to_string(),
+            };
+            event
+        };
+        for event in [
+            log(1, "web-1", "kernel"),
+            log(2, "web-1", "cron"),
+            log(3, "db-1", "kernel"),
+            // Outside the range
+            log(90, "db-1", "kernel"),
+        ] {
+            insert_event(&pool, &event).await.unwrap();
+        }
+
+        let rule = CompiledRule::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "kernel_quota",
+                "event_type": "system_log",
+                "fields": [{"field": "source", "equals": "kernel"}]
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        let test = test_rule(&pool, &rule, start, start + Duration::hours(1), 1)
+            .await
+            .unwrap();
+        assert_eq!(test.evaluated, 3);
+        assert_eq!(test.matched, 2);
+        assert_eq!(test.matched_by_host.get("web-1"), Some(&1));
+        assert_eq!(test.matched_by_host.get("db-1"), Some(&1));
+        // Newest first
+        assert_eq!(test.samples.len(), 1);
+        assert_eq!(test.samples[0].hostname, "db-1");
+    }
+
+    #[tokio::test]
+    async fn test_file_versions_follow_latest_snapshot() {
+        let pool = test_pool().await;
+        let start = "2026-03-01T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let change = |minutes: i64, hash: &str, versions: &[&str]| {
+            let mut event = event_at("web-1", start + Duration::minutes(minutes));
+            event.event_type = EventType::FileIntegrity {
+                path: "/etc/sudoers".to_string(),
+                operation: guardian_common::FileOperation::Modify,
+                hash: Some(hash.to_string()),
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            };
+            versions
+                .iter()
+                .fold(event.with_tag("snapshot"), |event, version| {
+                    event.with_tag(format!("snapshot:{}", version))
+                })
+        };
+        // "aa" was stored at startup, before any event reported it
+        for event in [
+            change(1, "bb", &["aa", "bb"]),
+            change(2, "cc", &["aa", "bb", "cc"]),
+            change(3, "bb", &["aa", "cc", "bb"]),
+        ] {
+            insert_event(&pool, &event).await.unwrap();
+        }
+
+        let versions = get_file_versions(&pool, "web-1", "/etc/sudoers")
+            .await
+            .unwrap();
+        let listed: Vec<_> = versions
+            .iter()
+            .map(|v| (v.hash.as_str(), v.first_seen.is_some(), v.current))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("aa", false, false),
+                ("cc", true, false),
+                ("bb", true, true)
+            ]
+        );
+        assert_eq!(
+            versions[2].first_seen.as_deref(),
+            Some((start + Duration::minutes(1)).to_rfc3339().as_str())
+        );
+        assert!(get_file_versions(&pool, "db-1", "/etc/sudoers")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_related_events_of_alert() {
+        let pool = test_pool().await;
+        let start = "2026-01-05T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let first = event_at("web-1", start);
+        let second = event_at("web-1", start + Duration::minutes(1));
+        let unrelated = event_at("web-1", start + Duration::minutes(2));
+        for event in [&second, &first, &unrelated] {
+            insert_event(&pool, event).await.unwrap();
+        }
+
+        let alert = LogEvent::new(
+            Severity::High,
+            EventType::Alert {
+                title: "Correlated".to_string(),
+                description: "two related events".to_string(),
+                related_event_ids: vec![second.id, first.id],
+                technique: None,
+            },
+            "web-1".to_string(),
+        );
+        insert_event(&pool, &alert).await.unwrap();
+
+        let related = get_related_events(&pool, &alert.id.to_string())
+            .await
+            .unwrap();
+        let ids: Vec<_> = related.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![first.id, second.id]);
+
+        // Raw events have no related events of their own
+        assert!(get_related_events(&pool, &first.id.to_string())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::database;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use guardian_common::{LogEvent, Severity};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// Notification settings, read from TOML
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// `sendmail`-compatible binary used for email delivery
+    pub sendmail: PathBuf,
+    /// Sender address for digest emails
+    pub from: String,
+    pub subscribers: Vec<Subscriber>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            sendmail: PathBuf::from("/usr/sbin/sendmail"),
+            from: "guardian@localhost".to_string(),
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+/// A user receiving digests; `name` is the principal name used for acknowledgements
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subscriber {
+    pub name: String,
+    /// IANA timezone the schedules are evaluated in
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Alerts above this severity are urgent and left out of digests
+    #[serde(default = "default_max_severity")]
+    pub max_severity: Severity,
+    pub channels: Vec<ChannelConfig>,
+}
+
+/// A delivery channel and its schedule
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    #[serde(flatten)]
+    pub channel: Channel,
+    pub schedule: Schedule,
+    /// Local time of delivery (`HH:MM`); only the minute is used for hourly digests
+    #[serde(default = "default_at")]
+    pub at: String,
+    /// Day of delivery for weekly digests
+    #[serde(default)]
+    pub weekday: Option<Weekday>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Channel {
+    Email { to: String },
+    Webhook { url: String },
+}
+
+impl Channel {
+    /// Stable key for delivery bookkeeping
+    fn key(&self) -> String {
+        match self {
+            Channel::Email { to } => format!("email:{}", to),
+            Channel::Webhook { url } => format!("webhook:{}", url),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_max_severity() -> Severity {
+    Severity::Medium
+}
+
+fn default_at() -> String {
+    "08:00".to_string()
+}
+
+impl NotifyConfig {
+    /// Load from `GUARDIAN_NOTIFY_CONFIG`, or `notifications.toml` next to the database.
+    /// Returns `None` when no notification config exists.
+    pub fn load(data_dir: Option<&Path>) -> Result<Option<Self>> {
+        let path = match std::env::var("GUARDIAN_NOTIFY_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => match data_dir.map(|d| d.join("notifications.toml")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+
+        info!("Loading notification config from {}", path.display());
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read notification config {}", path.display()))?;
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse notification config {}", path.display()))?;
+
+        // Reject bad timezones and times up front rather than at delivery time
+        for subscriber in &config.subscribers {
+            subscriber.tz()?;
+            for channel in &subscriber.channels {
+                channel.time()?;
+            }
+        }
+
+        Ok(Some(config))
+    }
+}
+
+impl Subscriber {
+    fn tz(&self) -> Result<Tz> {
+        self.timezone
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Unknown timezone {} for {}", self.timezone, self.name))
+    }
+}
+
+impl ChannelConfig {
+    fn time(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.at, "%H:%M")
+            .with_context(|| format!("Invalid digest time {}", self.at))
+    }
+
+    /// Most recent scheduled delivery at or before `now`
+    pub fn previous_run(&self, tz: Tz, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let at = self.time()?;
+        let local = now.with_timezone(&tz);
+
+        let (candidate, step) = match self.schedule {
+            Schedule::Hourly => {
+                let time = NaiveTime::from_hms_opt(local.hour(), at.minute(), 0)
+                    .context("Invalid digest time")?;
+                (local.date_naive().and_time(time), Duration::hours(1))
+            }
+            Schedule::Daily => (local.date_naive().and_time(at), Duration::days(1)),
+            Schedule::Weekly => {
+                let weekday = self.weekday.unwrap_or(Weekday::Mon);
+                let back = (7 + local.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                (
+                    (local.date_naive() - Duration::days(back as i64)).and_time(at),
+                    Duration::weeks(1),
+                )
+            }
+        };
+
+        let mut candidate = candidate;
+        for _ in 0..3 {
+            // Times skipped by a DST change fire an hour later
+            let resolved = tz
+                .from_local_datetime(&candidate)
+                .earliest()
+                .or_else(|| {
+                    tz.from_local_datetime(&(candidate + Duration::hours(1)))
+                        .earliest()
+                })
+                .map(|t| t.with_timezone(&Utc));
+
+            match resolved {
+                Some(t) if t <= now => return Ok(t),
+                _ => candidate -= step,
+            }
+        }
+        bail!("Could not resolve digest schedule in {}", tz)
+    }
+}
+
+/// One deduplicated line of a digest
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DigestEntry {
+    pub rule_name: String,
+    pub hostname: String,
+    pub severity: Severity,
+    pub count: usize,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Alerts for one subscriber over one period
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub subscriber: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub entries: Vec<DigestEntry>,
+}
+
+impl Digest {
+    /// Collapse repeated alerts for the same rule and host into one entry
+    pub fn build(
+        subscriber: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        max_severity: Severity,
+        alerts: &[LogEvent],
+    ) -> Self {
+        let mut entries: BTreeMap<(String, String), DigestEntry> = BTreeMap::new();
+
+        for alert in alerts.iter().filter(|a| a.severity <= max_severity) {
+            let rule_name = alert.rule_name.clone().unwrap_or_default();
+            entries
+                .entry((rule_name.clone(), alert.hostname.clone()))
+                .and_modify(|e| {
+                    e.count += 1;
+                    e.severity = e.severity.max(alert.severity);
+                    e.first_seen = e.first_seen.min(alert.timestamp);
+                    e.last_seen = e.last_seen.max(alert.timestamp);
+                })
+                .or_insert_with(|| DigestEntry {
+                    rule_name,
+                    hostname: alert.hostname.clone(),
+                    severity: alert.severity,
+                    count: 1,
+                    first_seen: alert.timestamp,
+                    last_seen: alert.timestamp,
+                });
+        }
+
+        let mut entries: Vec<_> = entries.into_values().collect();
+        entries.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.count.cmp(&a.count)));
+
+        Self {
+            subscriber: subscriber.to_string(),
+            from,
+            to,
+            entries,
+        }
+    }
+
+    /// Plain-text rendering for email
+    pub fn to_text(&self, tz: Tz) -> String {
+        let mut text = format!(
+            "Guardian alert digest for {}\n{} - {}\n\n",
+            self.subscriber,
+            self.from.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z"),
+            self.to.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z"),
+        );
+        for entry in &self.entries {
+            text.push_str(&format!(
+                "[{:?}] {} on {} x{} (last {})\n",
+                entry.severity,
+                entry.rule_name,
+                entry.hostname,
+                entry.count,
+                entry.last_seen.with_timezone(&tz).format("%H:%M"),
+            ));
+        }
+        text
+    }
+}
+
+/// Periodically builds and delivers digests
+pub struct DigestScheduler {
+    pool: SqlitePool,
+    config: NotifyConfig,
+    http: reqwest::Client,
+}
+
+impl DigestScheduler {
+    pub fn new(pool: SqlitePool, config: NotifyConfig) -> Self {
+        Self {
+            pool,
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Check schedules once a minute, forever
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let now = Utc::now();
+            for subscriber in &self.config.subscribers {
+                for channel in &subscriber.channels {
+                    if let Err(e) = self.deliver_if_due(subscriber, channel, now).await {
+                        warn!(
+                            "Digest for {} via {} failed: {:#}",
+                            subscriber.name,
+                            channel.channel.key(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    async fn deliver_if_due(
+        &self,
+        subscriber: &Subscriber,
+        channel: &ChannelConfig,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let key = channel.channel.key();
+        let tz = subscriber.tz()?;
+        let due = channel.previous_run(tz, now)?;
+
+        let Some(last_sent) =
+            database::get_digest_last_sent(&self.pool, &subscriber.name, &key).await?
+        else {
+            // First run: start collecting from the latest slot rather than replaying history
+            return Ok(
+                database::set_digest_last_sent(&self.pool, &subscriber.name, &key, due).await?,
+            );
+        };
+        if due <= last_sent {
+            return Ok(());
+        }
+
+        let alerts =
+            database::get_unacknowledged_alerts(&self.pool, &subscriber.name, last_sent, due)
+                .await?;
+        let digest = Digest::build(
+            &subscriber.name,
+            last_sent,
+            due,
+            subscriber.max_severity,
+            &alerts,
+        );
+
+        if !digest.entries.is_empty() {
+            match &channel.channel {
+                Channel::Email { to } => self.send_email(to, &digest, tz).await?,
+                Channel::Webhook { url } => {
+                    self.http
+                        .post(url)
+                        .json(&digest)
+                        .send()
+                        .await?
+                        .error_for_status()?;
+                }
+            }
+            info!(
+                "Delivered digest with {} entries to {} via {}",
+                digest.entries.len(),
+                subscriber.name,
+                key
+            );
+        }
+
+        Ok(database::set_digest_last_sent(&self.pool, &subscriber.name, &key, due).await?)
+    }
+
+    async fn send_email(&self, to: &str, digest: &Digest, tz: Tz) -> Result<()> {
+        let message = format!(
+            "From: {}\nTo: {}\nSubject: Guardian digest: {} alert groups\nContent-Type: text/plain; charset=utf-8\n\n{}",
+            self.config.from,
+            to,
+            digest.entries.len(),
+            digest.to_text(tz)
+        );
+
+        let mut child = tokio::process::Command::new(&self.config.sendmail)
+            .args(["-t", "-i"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.config.sendmail.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(message.as_bytes()).await?;
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            bail!("sendmail exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::EventType;
+
+    fn channel(schedule: Schedule, at: &str, weekday: Option<Weekday>) -> ChannelConfig {
+        ChannelConfig {
+            channel: Channel::Webhook {
+                url: "http://localhost/hook".to_string(),
+            },
+            schedule,
+            at: at.to_string(),
+            weekday,
+        }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_previous_run_respects_timezone() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let daily = channel(Schedule::Daily, "08:00", None);
+
+        // 08:00 CEST is 06:00 UTC
+        assert_eq!(
+            daily.previous_run(tz, utc("2026-06-10T07:00:00Z")).unwrap(),
+            utc("2026-06-10T06:00:00Z")
+        );
+        // Before today's slot the previous run was yesterday
+        assert_eq!(
+            daily.previous_run(tz, utc("2026-06-10T05:59:00Z")).unwrap(),
+            utc("2026-06-09T06:00:00Z")
+        );
+
+        let hourly = channel(Schedule::Hourly, "00:15", None);
+        assert_eq!(
+            hourly
+                .previous_run(tz, utc("2026-06-10T07:10:00Z"))
+                .unwrap(),
+            utc("2026-06-10T06:15:00Z")
+        );
+
+        // 2026-06-10 is a Wednesday
+        let weekly = channel(Schedule::Weekly, "09:30", Some(Weekday::Mon));
+        assert_eq!(
+            weekly
+                .previous_run(Tz::UTC, utc("2026-06-10T12:00:00Z"))
+                .unwrap(),
+            utc("2026-06-08T09:30:00Z")
+        );
+    }
+
+    #[test]
+    fn test_digest_dedupes_and_drops_urgent_alerts() {
+        let alert = |rule: &str, severity: Severity, minute: i64| {
+            let mut event = LogEvent::new(
+                severity,
+                EventType::SystemLog {
+                    source: "test".to_string(),
+                    level: "info".to_string(),
+                    message: "x".to_string(),
+                },
+                "web-1".to_string(),
+            )
+            .with_rule(rule.to_string());
+            event.timestamp = utc("2026-06-10T06:00:00Z") + Duration::minutes(minute);
+            event
+        };
+        let alerts = vec![
+            alert("high_cpu", Severity::Low, 1),
+            alert("high_cpu", Severity::Medium, 5),
+            alert("ssh_bruteforce", Severity::Low, 3),
+            alert("critical_file", Severity::Critical, 4),
+        ];
+
+        let digest = Digest::build(
+            "alice",
+            utc("2026-06-10T06:00:00Z"),
+            utc("2026-06-10T07:00:00Z"),
+            Severity::Medium,
+            &alerts,
+        );
+
+        assert_eq!(digest.entries.len(), 2);
+        assert_eq!(digest.entries[0].rule_name, "high_cpu");
+        assert_eq!(digest.entries[0].count, 2);
+        assert_eq!(digest.entries[0].severity, Severity::Medium);
+        assert_eq!(digest.entries[0].first_seen, utc("2026-06-10T06:01:00Z"));
+    }
+}
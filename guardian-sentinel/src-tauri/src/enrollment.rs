@@ -0,0 +1,183 @@
+//! Agent enrollment for collector mode: a local CA issues one client
+//! certificate per agent in exchange for a one-time token, so forwarders
+//! authenticate with mutual TLS and can be revoked individually.
+
+use crate::auth::sha256_hex;
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateSigningRequestParams, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SerialNumber,
+};
+use std::path::Path;
+use tokio_rustls::rustls::RootCertStore;
+use tracing::info;
+
+/// How long an issued agent certificate stays valid
+const AGENT_CERT_DAYS: i64 = 365;
+
+/// How long the agent CA stays valid
+const CA_CERT_DAYS: i64 = 3650;
+
+/// The CA that signs agent certificates
+pub struct AgentCa {
+    cert: rcgen::Certificate,
+    key: KeyPair,
+}
+
+impl AgentCa {
+    /// Load the CA from `cert_path` and `key_path`, creating both on first use
+    pub fn load_or_create(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        if cert_path.exists() {
+            let cert_pem = std::fs::read_to_string(cert_path)
+                .with_context(|| format!("Failed to read {}", cert_path.display()))?;
+            let key_pem = std::fs::read_to_string(key_path)
+                .with_context(|| format!("Failed to read {}", key_path.display()))?;
+            let key = KeyPair::from_pem(&key_pem)
+                .with_context(|| format!("Invalid CA key in {}", key_path.display()))?;
+            let params = CertificateParams::from_ca_cert_pem(&cert_pem)
+                .with_context(|| format!("Invalid CA certificate in {}", cert_path.display()))?;
+            // Re-signing yields the same subject and key, which is all issuing needs
+            let cert = params.self_signed(&key)?;
+            return Ok(Self { cert, key });
+        }
+
+        info!("Creating agent CA in {}", cert_path.display());
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Guardian agent CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        set_validity(&mut params, CA_CERT_DAYS);
+        let cert = params.self_signed(&key)?;
+
+        if let Some(dir) = cert_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_private(key_path, &key.serialize_pem())?;
+        std::fs::write(cert_path, cert.pem())
+            .with_context(|| format!("Failed to write {}", cert_path.display()))?;
+        Ok(Self { cert, key })
+    }
+
+    /// Trust anchors for verifying agent certificates
+    pub fn roots(&self) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.der().clone())?;
+        Ok(roots)
+    }
+
+    /// Sign an agent's request for `hostname`, returning the PEM certificate and
+    /// its fingerprint. Only the key is taken from the request; the name is the
+    /// one the token was issued for.
+    pub fn issue(&self, csr_pem: &str, hostname: &str) -> Result<(String, String)> {
+        let mut csr = CertificateSigningRequestParams::from_pem(csr_pem)
+            .context("Invalid certificate signing request")?;
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name.push(DnType::CommonName, hostname);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.serial_number = Some(SerialNumber::from_slice(uuid::Uuid::new_v4().as_bytes()));
+        params.use_authority_key_identifier_extension = true;
+        set_validity(&mut params, AGENT_CERT_DAYS);
+        csr.params = params;
+
+        let cert = csr.signed_by(&self.cert, &self.key)?;
+        Ok((cert.pem(), fingerprint(cert.der())))
+    }
+}
+
+/// New one-time enrollment token
+pub fn new_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Tokens are stored hashed so the database alone cannot enroll an agent
+pub fn hash_token(token: &str) -> String {
+    sha256_hex(token.trim().as_bytes())
+}
+
+/// Identity of a DER certificate as stored in the enrollments table
+pub fn fingerprint(der: &[u8]) -> String {
+    sha256_hex(der)
+}
+
+/// Valid from yesterday, to tolerate clock skew, for `days`
+fn set_validity(params: &mut CertificateParams, days: i64) {
+    let today = Utc::now().date_naive();
+    let date = |d: NaiveDate| rcgen::date_time_ymd(d.year(), d.month() as u8, d.day() as u8);
+    params.not_before = date(today - Duration::days(1));
+    params.not_after = date(today + Duration::days(days));
+}
+
+/// Write a file readable by the owner only
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, UnixTime};
+    use tokio_rustls::rustls::server::WebPkiClientVerifier;
+
+    #[test]
+    fn test_reloaded_ca_issues_certificates_trusted_by_original() {
+        let dir = std::env::temp_dir().join(format!("guardian-ca-{}", uuid::Uuid::new_v4()));
+        let (cert_path, key_path) = (dir.join("agent-ca.pem"), dir.join("agent-ca-key.pem"));
+        let created = AgentCa::load_or_create(&cert_path, &key_path).unwrap();
+        let reloaded = AgentCa::load_or_create(&cert_path, &key_path).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let csr = CertificateParams::new(vec!["claimed".to_string()])
+            .unwrap()
+            .serialize_request(&key)
+            .unwrap()
+            .pem()
+            .unwrap();
+        let (pem, fp) = reloaded.issue(&csr, "web-1").unwrap();
+        let der: CertificateDer = rustls_pemfile::certs(&mut pem.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(fp, fingerprint(&der));
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(created.roots().unwrap()))
+            .build()
+            .unwrap();
+        verifier
+            .verify_client_cert(&der, &[], UnixTime::now())
+            .unwrap();
+        assert!(std::fs::read_to_string(&key_path)
+            .unwrap()
+            .contains("PRIVATE KEY"));
+
+        assert_eq!(hash_token(" abc\n"), hash_token("abc"));
+        assert_ne!(new_token(), new_token());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,205 @@
+//! Offline detection: agents send heartbeats, and a host that misses its
+//! deadline raises a High event once until it reports again.
+
+use crate::database;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+/// Heartbeats an agent may miss before it counts as offline
+const MISSED_HEARTBEATS: i64 = 3;
+
+/// How often overdue hosts are looked for
+const CHECK_INTERVAL_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Default)]
+pub struct HeartbeatConfig {
+    /// Seconds of silence after which an agent is offline; by default three
+    /// of the agent's own heartbeat intervals
+    pub offline_after_secs: Option<u64>,
+}
+
+impl HeartbeatConfig {
+    /// Read `GUARDIAN_OFFLINE_AFTER_SECS`
+    pub fn from_env() -> Self {
+        Self {
+            offline_after_secs: std::env::var("GUARDIAN_OFFLINE_AFTER_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        }
+    }
+
+    fn allowance(&self, interval_secs: u64) -> Duration {
+        match self.offline_after_secs {
+            Some(secs) => Duration::seconds(secs as i64),
+            None => Duration::seconds(interval_secs.max(1) as i64 * MISSED_HEARTBEATS),
+        }
+    }
+}
+
+/// Record a heartbeat received at `now`. Returns the event to store when it
+/// brings an offline host back.
+pub async fn record(
+    pool: &SqlitePool,
+    config: &HeartbeatConfig,
+    event: &LogEvent,
+    now: DateTime<Utc>,
+) -> Result<Option<LogEvent>> {
+    let EventType::Heartbeat {
+        agent_version,
+        uptime_secs,
+        interval_secs,
+        forward,
+    } = &event.event_type
+    else {
+        return Ok(None);
+    };
+
+    let offline_since = database::record_heartbeat(
+        pool,
+        &event.hostname,
+        agent_version,
+        *uptime_secs,
+        *forward,
+        now,
+        now + config.allowance(*interval_secs),
+    )
+    .await?;
+
+    Ok(offline_since.map(|since| {
+        info!("Agent {} is reporting again", event.hostname);
+        status_event(
+            &event.hostname,
+            Severity::Low,
+            "agent_online",
+            format!(
+                "Agent {} is reporting again after being offline since {}",
+                event.hostname, since
+            ),
+        )
+    }))
+}
+
+/// Raise an event for every host that went silent since the last check
+pub async fn check_offline(pool: &SqlitePool, now: DateTime<Utc>) -> Result<usize> {
+    let offline = database::mark_offline_hosts(pool, now).await?;
+    for (hostname, last_heartbeat) in &offline {
+        warn!("Agent {} stopped sending heartbeats", hostname);
+        let event = status_event(
+            hostname,
+            Severity::High,
+            "agent_offline",
+            format!(
+                "Agent {} has not sent a heartbeat since {}",
+                hostname, last_heartbeat
+            ),
+        );
+        database::insert_event(pool, &event).await?;
+    }
+    Ok(offline.len())
+}
+
+pub async fn run_offline_monitor(pool: SqlitePool) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        if let Err(e) = check_offline(&pool, Utc::now()).await {
+            warn!("Offline check failed: {}", e);
+        }
+    }
+}
+
+/// Events are attributed to the agent they are about
+fn status_event(hostname: &str, severity: Severity, rule: &str, message: String) -> LogEvent {
+    let level = if severity >= Severity::High {
+        "warn"
+    } else {
+        "info"
+    };
+    LogEvent::new(
+        severity,
+        EventType::SystemLog {
+            source: "sentinel".to_string(),
+            level: level.to_string(),
+            message,
+        },
+        hostname.to_string(),
+    )
+    .with_tag("agent_health")
+    .with_rule(rule.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(hostname: &str) -> LogEvent {
+        LogEvent::new(
+            Severity::Info,
+            EventType::Heartbeat {
+                agent_version: "0.1.0".to_string(),
+                uptime_secs: 120,
+                interval_secs: 60,
+                forward: None,
+            },
+            hostname.to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_silent_agent_goes_offline_once_and_recovers() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let pool = database::init_database(&path).await.unwrap();
+        let config = HeartbeatConfig::default();
+        let start = Utc::now();
+
+        assert!(record(&pool, &config, &heartbeat("web-1"), start)
+            .await
+            .unwrap()
+            .is_none());
+        // Three missed 60s heartbeats are tolerated
+        assert_eq!(
+            check_offline(&pool, start + Duration::seconds(170))
+                .await
+                .unwrap(),
+            0
+        );
+        let late = start + Duration::seconds(190);
+        assert_eq!(check_offline(&pool, late).await.unwrap(), 1);
+        assert_eq!(check_offline(&pool, late).await.unwrap(), 0);
+
+        let events = database::get_recent_events(&pool, 10, Some("web-1"), None)
+            .await
+            .unwrap()
+            .events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].severity, Severity::High);
+        let hosts = database::get_hosts(&pool).await.unwrap();
+        assert!(hosts[0].offline_since.is_some());
+        assert_eq!(hosts[0].uptime_secs, Some(120));
+
+        let back = record(&pool, &config, &heartbeat("web-1"), late)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(back.severity, Severity::Low);
+        let hosts = database::get_hosts(&pool).await.unwrap();
+        assert!(hosts[0].offline_since.is_none());
+
+        // An explicit threshold overrides the agent's interval
+        let strict = HeartbeatConfig {
+            offline_after_secs: Some(10),
+        };
+        record(&pool, &strict, &heartbeat("web-1"), late)
+            .await
+            .unwrap();
+        assert_eq!(
+            check_offline(&pool, late + Duration::seconds(11))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Incident summaries for handing an investigation to someone else or
+//! attaching it to a ticket.
+
+use crate::database::{self, Incident};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use guardian_common::{LogEvent, Severity};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// An incident with its events and what they have in common
+#[derive(Debug, Clone, Serialize)]
+pub struct IncidentSummary {
+    pub incident: Incident,
+    pub severity: Option<Severity>,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub hosts: BTreeSet<String>,
+    pub rules: BTreeSet<String>,
+    /// Oldest first
+    pub events: Vec<LogEvent>,
+}
+
+impl IncidentSummary {
+    pub fn new(incident: Incident, events: Vec<LogEvent>) -> Self {
+        Self {
+            severity: events.iter().map(|e| e.severity).max(),
+            first_seen: events.iter().map(|e| e.timestamp).min(),
+            last_seen: events.iter().map(|e| e.timestamp).max(),
+            hosts: events.iter().map(|e| e.hostname.clone()).collect(),
+            rules: events.iter().filter_map(|e| e.rule_name.clone()).collect(),
+            incident,
+            events,
+        }
+    }
+
+    /// Markdown rendering with an event timeline
+    pub fn to_markdown(&self) -> String {
+        let incident = &self.incident;
+        let time = |t: Option<DateTime<Utc>>| {
+            t.map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let list = |items: &BTreeSet<String>| {
+            if items.is_empty() {
+                "-".to_string()
+            } else {
+                items.iter().cloned().collect::<Vec<_>>().join(", ")
+            }
+        };
+
+        let mut text = format!("# Incident: {}\n\n", incident.title);
+        let _ = writeln!(text, "- Status: {}", incident.status);
+        if let Some(severity) = self.severity {
+            let _ = writeln!(text, "- Severity: {:?}", severity);
+        }
+        let _ = writeln!(
+            text,
+            "- Opened by {} at {}",
+            incident.created_by, incident.created_at
+        );
+        if let Some(closed_at) = &incident.closed_at {
+            let _ = writeln!(text, "- Closed at {}", closed_at);
+        }
+        let _ = writeln!(
+            text,
+            "- Activity: {} to {}",
+            time(self.first_seen),
+            time(self.last_seen)
+        );
+        let _ = writeln!(text, "- Hosts: {}", list(&self.hosts));
+        let _ = writeln!(text, "- Rules: {}", list(&self.rules));
+
+        let _ = writeln!(text, "\n## Timeline ({} events)\n", self.events.len());
+        let _ = writeln!(text, "| Time | Host | Severity | Rule | Type |");
+        let _ = writeln!(text, "|---|---|---|---|---|");
+        for event in &self.events {
+            let _ = writeln!(
+                text,
+                "| {} | {} | {:?} | {} | {} |",
+                event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                event.hostname,
+                event.severity,
+                event.rule_name.as_deref().unwrap_or("-"),
+                event.event_type.kind(),
+            );
+        }
+        text
+    }
+}
+
+/// Load an incident and its events; `None` if there is no such incident
+pub async fn summarize(pool: &SqlitePool, id: &str) -> Result<Option<IncidentSummary>> {
+    let Some(incident) = database::get_incident(pool, id).await? else {
+        return Ok(None);
+    };
+    let events = database::get_incident_events(pool, id).await?;
+    Ok(Some(IncidentSummary::new(incident, events)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::EventType;
+
+    fn hit(hostname: &str, rule: &str, severity: Severity) -> LogEvent {
+        LogEvent::new(
+            severity,
+            EventType::AuthEvent {
+                user: "root".to_string(),
+                source_ip: Some("203.0.113.5".to_string()),
+                method: "password".to_string(),
+                outcome: guardian_common::AuthOutcome::Failure,
+                tty: None,
+            },
+            hostname.to_string(),
+        )
+        .with_rule(rule)
+    }
+
+    #[tokio::test]
+    async fn test_incident_collects_correlated_hits_and_exports() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let pool = database::init_database(&path).await.unwrap();
+
+        let first = hit("web-1", "SSH Brute Force", Severity::Medium);
+        let unrelated = hit("db-1", "SSH Brute Force", Severity::Low);
+        for event in [&first, &unrelated] {
+            database::insert_event(&pool, event).await.unwrap();
+        }
+        let key = first.correlation_key().unwrap();
+        let incident = database::create_incident(
+            &pool,
+            "Brute force on web-1",
+            Some(&key),
+            &[first.id.to_string(), "missing".to_string()],
+            "alice",
+        )
+        .await
+        .unwrap();
+        assert_eq!(incident.event_count, 1);
+        assert!(
+            database::create_incident(&pool, "Duplicate", Some(&key), &[], "bob")
+                .await
+                .is_err()
+        );
+
+        // Later hits of the same rule on the same host join the open incident
+        let second = hit("web-1", "SSH Brute Force", Severity::High);
+        database::insert_event(&pool, &second).await.unwrap();
+        assert_eq!(
+            database::attach_events(&pool, &incident.id, &[unrelated.id.to_string()], "alice")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            database::detach_events(&pool, &incident.id, &[unrelated.id.to_string()])
+                .await
+                .unwrap(),
+            1
+        );
+
+        let summary = summarize(&pool, &incident.id).await.unwrap().unwrap();
+        assert_eq!(summary.events.len(), 2);
+        assert_eq!(summary.severity, Some(Severity::High));
+        assert_eq!(summary.hosts.iter().collect::<Vec<_>>(), ["web-1"]);
+        let markdown = summary.to_markdown();
+        assert!(markdown.starts_with("# Incident: Brute force on web-1"));
+        assert!(markdown.contains("## Timeline (2 events)"));
+        assert!(markdown.contains("| web-1 | High | SSH Brute Force | auth_event |"));
+
+        // Closed incidents stop collecting, and the key can be reused
+        assert!(database::close_incident(&pool, &incident.id).await.unwrap());
+        database::insert_event(&pool, &hit("web-1", "SSH Brute Force", Severity::High))
+            .await
+            .unwrap();
+        assert_eq!(
+            database::get_incident(&pool, &incident.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .event_count,
+            2
+        );
+        database::create_incident(&pool, "Again", Some(&key), &[], "bob")
+            .await
+            .unwrap();
+        assert!(summarize(&pool, "missing").await.unwrap().is_none());
+    }
+}
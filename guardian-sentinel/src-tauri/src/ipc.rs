@@ -0,0 +1,288 @@
+use anyhow::{bail, Result};
+use guardian_common::ipc::{
+    read_frame, read_frame_as, write_frame, write_frame_as, Encoding, Frame, PROTOCOL_VERSION,
+};
+use guardian_common::{ControlCommand, EventSigner};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// First and longest wait between connection attempts
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Socket the console asks its daemon to listen on: `GUARDIAN_SOCKET`, or one
+/// in the app data directory (a per-user named pipe on Windows)
+pub fn socket_path(data_dir: &Path) -> PathBuf {
+    if let Some(path) = std::env::var_os("GUARDIAN_SOCKET") {
+        return PathBuf::from(path);
+    }
+    if cfg!(windows) {
+        let user = std::env::var("USERNAME").unwrap_or_default();
+        PathBuf::from(format!(r"\\.\pipe\guardian-sentinel-{}", user))
+    } else {
+        data_dir.join("daemon.sock")
+    }
+}
+
+#[cfg(unix)]
+async fn connect(path: &Path) -> std::io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(path).await
+}
+
+#[cfg(windows)]
+async fn connect(path: &Path) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(path)
+}
+
+/// Keep a connection to the daemon at `path`, reconnecting with backoff.
+/// Received frames (starting with the daemon's hello) go to `frames`; commands
+/// from `control` are sent as they arrive, and wait while disconnected.
+/// With a `signer`, events and rollups without its signature are dropped, and
+/// a daemon whose hello lacks it is disconnected. Returns once
+/// `frames` is closed.
+pub async fn run(
+    path: PathBuf,
+    mut control: mpsc::Receiver<ControlCommand>,
+    frames: mpsc::Sender<Frame>,
+    signer: Option<EventSigner>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    while !frames.is_closed() {
+        match connect(&path).await {
+            Ok(stream) => {
+                info!("Connected to daemon at {}", path.display());
+                backoff = MIN_BACKOFF;
+                match session(stream, &mut control, &frames, signer.as_ref()).await {
+                    Ok(()) => info!("Daemon closed the connection"),
+                    Err(e) => warn!("Daemon connection lost: {:#}", e),
+                }
+            }
+            Err(e) => debug!("Daemon not reachable at {}: {}", path.display(), e),
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Handshake, then relay frames both ways until either side goes away. The
+/// handshake is JSON; after it both sides use CBOR if the daemon writes it.
+async fn session<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    control: &mut mpsc::Receiver<ControlCommand>,
+    frames: &mpsc::Sender<Frame>,
+    signer: Option<&EventSigner>,
+) -> Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+
+    let hello = Frame::ClientHello {
+        protocol_version: PROTOCOL_VERSION,
+        client: "sentinel".to_string(),
+        encodings: Encoding::supported(),
+    };
+    write_frame(&mut writer, &hello).await?;
+    let encoding = match read_frame(&mut reader).await? {
+        Some(Frame::Hello(agent)) => {
+            if let Some(signer) = signer {
+                signer.verify(&agent)?;
+            }
+            let encoding =
+                Encoding::negotiate(&Encoding::supported(), &agent.capabilities.encodings);
+            if frames.send(Frame::Hello(agent)).await.is_err() {
+                return Ok(());
+            }
+            encoding
+        }
+        Some(Frame::Reject { reason }) => bail!("Daemon rejected the connection: {}", reason),
+        Some(_) => bail!("Daemon did not answer the handshake"),
+        None => bail!("Daemon closed the connection during the handshake"),
+    };
+
+    // Each direction is one long-lived future: reads must not be cancelled midway
+    let reading = async {
+        while let Some(frame) = read_frame_as(&mut reader, encoding).await? {
+            let verified = match (signer, &frame) {
+                (Some(signer), Frame::Event(event)) => signer.verify(event),
+                (Some(signer), Frame::Rollup(rollup)) => signer.verify(rollup),
+                _ => Ok(()),
+            };
+            if let Err(e) = verified {
+                warn!("Dropping frame from the daemon socket: {}", e);
+                continue;
+            }
+            if frames.send(frame).await.is_err() {
+                break;
+            }
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    let writing = async {
+        while let Some(command) = control.recv().await {
+            write_frame_as(&mut writer, &Frame::Control(command), encoding).await?;
+        }
+        // No more commands will come; keep receiving events
+        std::future::pending::<()>().await;
+        Ok::<_, anyhow::Error>(())
+    };
+
+    tokio::select! {
+        result = reading => result,
+        result = writing => result,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use guardian_common::{AgentHello, Capabilities, EventType, LogEvent, Severity, StatsRollup};
+    use tokio::net::UnixListener;
+
+    /// Answer the console's hello as a daemon writing `encodings`, signing
+    /// the hello with `signer`
+    async fn expect_client_hello(
+        stream: &mut tokio::net::UnixStream,
+        encodings: Vec<String>,
+        signer: Option<&EventSigner>,
+    ) {
+        match read_frame(stream).await.unwrap() {
+            Some(Frame::ClientHello {
+                protocol_version: PROTOCOL_VERSION,
+                encodings: offered,
+                ..
+            }) => assert_eq!(offered, Encoding::supported()),
+            other => panic!("expected a client hello, got {:?}", other),
+        }
+        let mut hello = AgentHello {
+            hostname: "web-1".to_string(),
+            agent_version: "0.1.0".to_string(),
+            schema_version: guardian_common::SCHEMA_VERSION,
+            capabilities: Capabilities {
+                encodings,
+                ..Capabilities::default()
+            },
+            signature: None,
+        };
+        if let Some(signer) = signer {
+            signer.sign(&mut hello).unwrap();
+        }
+        write_frame(stream, &Frame::Hello(hello)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_and_relays_frames() {
+        let path = std::env::temp_dir().join(format!("sentinel-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (control_tx, control_rx) = mpsc::channel(4);
+        let (frames_tx, mut frames_rx) = mpsc::channel(16);
+        tokio::spawn(run(path.clone(), control_rx, frames_tx, None));
+
+        // First connection, to a daemon writing only JSON, delivers one
+        // event, then the daemon goes away
+        let (mut stream, _) = listener.accept().await.unwrap();
+        expect_client_hello(&mut stream, Vec::new(), None).await;
+        let event = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "test".to_string(),
+                level: "info".to_string(),
+                message: "first".to_string(),
+            },
+            "web-1".to_string(),
+        );
+        write_frame(&mut stream, &Frame::Event(event.clone()))
+            .await
+            .unwrap();
+        drop(stream);
+
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Hello(_))));
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Event(e)) if e.id == event.id));
+
+        // A command queued while disconnected is sent after reconnecting
+        let command = ControlCommand::SetSampling { rate: 2 };
+        control_tx.send(command.clone()).await.unwrap();
+
+        // The new daemon writes CBOR, so the command is sent in it
+        let (mut stream, _) = listener.accept().await.unwrap();
+        expect_client_hello(&mut stream, Encoding::supported(), None).await;
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Hello(_))));
+        match read_frame_as(&mut stream, Encoding::Cbor).await.unwrap() {
+            Some(Frame::Control(received)) => assert_eq!(received, command),
+            other => panic!("expected a control frame, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_drops_frames_without_a_valid_signature() {
+        let path = std::env::temp_dir().join(format!("sentinel-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let signer = EventSigner::new(b"0123456789abcdef").unwrap();
+
+        let (_control_tx, control_rx) = mpsc::channel(4);
+        let (frames_tx, mut frames_rx) = mpsc::channel(16);
+        tokio::spawn(run(
+            path.clone(),
+            control_rx,
+            frames_tx,
+            Some(signer.clone()),
+        ));
+
+        // A daemon whose hello is unsigned is not listened to
+        let (mut stream, _) = listener.accept().await.unwrap();
+        expect_client_hello(&mut stream, Vec::new(), None).await;
+        assert!(read_frame(&mut stream).await.unwrap().is_none());
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        expect_client_hello(&mut stream, Vec::new(), Some(&signer)).await;
+        let event = |message: &str| {
+            LogEvent::new(
+                Severity::High,
+                EventType::SystemLog {
+                    source: "test".to_string(),
+                    level: "crit".to_string(),
+                    message: message.to_string(),
+                },
+                "web-1".to_string(),
+            )
+        };
+        let injected = event("unsigned");
+        let mut tampered = event("tampered");
+        signer.sign(&mut tampered).unwrap();
+        tampered.severity = Severity::Info;
+        let mut signed = event("signed");
+        signer.sign(&mut signed).unwrap();
+        for event in [&injected, &tampered, &signed] {
+            write_frame(&mut stream, &Frame::Event(event.clone()))
+                .await
+                .unwrap();
+        }
+        let rollup = StatsRollup {
+            hostname: "web-1".to_string(),
+            period_start: chrono::Utc::now(),
+            period_secs: 3600,
+            counts: Vec::new(),
+            top_paths: Vec::new(),
+            signature: None,
+        };
+        write_frame(&mut stream, &Frame::Rollup(rollup.clone()))
+            .await
+            .unwrap();
+        let mut signed_rollup = rollup;
+        signed_rollup.period_secs = 60;
+        signer.sign(&mut signed_rollup).unwrap();
+        write_frame(&mut stream, &Frame::Rollup(signed_rollup))
+            .await
+            .unwrap();
+
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Hello(_))));
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Event(e)) if e.id == signed.id));
+        assert!(matches!(frames_rx.recv().await, Some(Frame::Rollup(r)) if r.period_secs == 60));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,61 +1,986 @@
+pub mod audit;
+pub mod auth;
+pub mod collector;
 pub mod database;
+pub mod digest;
+pub mod enrollment;
+pub mod heartbeat;
+pub mod incident;
+pub mod ipc;
+pub mod quota;
+pub mod rbac;
+pub mod report;
+pub mod response;
+pub mod sla;
+pub mod storage;
 
-use anyhow::Result;
-use guardian_common::LogEvent;
+use anyhow::{bail, Result};
+use audit::{AuditEntry, AuditFilter, AuditOutcome};
+use auth::{Authenticator, Principal};
+use digest::{DigestScheduler, NotifyConfig};
+use guardian_common::error::Result as GuardianResult;
+use guardian_common::store::{self, PayloadEncoder};
+use guardian_common::{
+    AgentHello, CompiledRule, ControlCommand, GuardianError, LogEvent, RuleDefinition,
+    RuleStatsReport, RuleTest, SearchFilter, StatsRollup,
+};
+use heartbeat::HeartbeatConfig;
+use quota::{AgentQuotaStatus, QuotaConfig, QuotaTracker};
+use rbac::Permission;
+use response::{IsolationChallenge, PendingIsolation};
+use sla::{SlaConfig, SlaMetrics};
 use sqlx::SqlitePool;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use storage::{PrunePolicy, PrunePreview, StorageReport};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Collector connection of a remote agent
+struct Forwarder {
+    /// Control channel to the agent
+    tx: mpsc::Sender<ControlCommand>,
+    /// Collector address the agent connected to
+    collector: SocketAddr,
+}
 
 /// Application state
 pub struct AppState {
     db_path: PathBuf,
     pool: Option<SqlitePool>,
+    /// Set when `GUARDIAN_COMPRESS_PAYLOADS` asks for compressed payloads
+    payload_encoder: Option<PayloadEncoder>,
+    hostname: String,
+    control_tx: Option<mpsc::Sender<ControlCommand>>,
+    /// Agents connected through the collector, by hostname
+    forwarders: HashMap<String, Forwarder>,
+    agent: Option<AgentHello>,
+    /// Latest rule hit counts by agent hostname
+    rule_stats: BTreeMap<String, RuleStatsReport>,
+    pending_isolation: Option<PendingIsolation>,
+    quotas: QuotaTracker,
+    authenticator: Arc<Authenticator>,
+    principal: Option<Principal>,
+    notify: Option<NotifyConfig>,
+    sla: SlaConfig,
+    heartbeat: HeartbeatConfig,
 }
 
 impl AppState {
     pub fn new(db_path: PathBuf) -> Self {
+        let hostname = hostname::get()
+            .unwrap_or_else(|_| "unknown".into())
+            .to_string_lossy()
+            .to_string();
+
+        let authenticator = Arc::new(Authenticator::load(db_path.parent()));
+        let principal = (!authenticator.is_enabled()).then(Principal::local);
+        let notify = NotifyConfig::load(db_path.parent()).unwrap_or_else(|e| {
+            tracing::warn!("Invalid notification config, digests disabled: {:#}", e);
+            None
+        });
+
         Self {
             db_path,
             pool: None,
+            payload_encoder: None,
+            hostname,
+            control_tx: None,
+            forwarders: HashMap::new(),
+            agent: None,
+            rule_stats: BTreeMap::new(),
+            pending_isolation: None,
+            quotas: QuotaTracker::new(QuotaConfig::from_env()),
+            authenticator,
+            principal,
+            notify,
+            sla: SlaConfig::from_env(),
+            heartbeat: HeartbeatConfig::from_env(),
+        }
+    }
+
+    /// The authenticator, to check credentials without holding the state:
+    /// password hashing is deliberately slow
+    pub fn authenticator(&self) -> Arc<Authenticator> {
+        Arc::clone(&self.authenticator)
+    }
+
+    /// Start the session of a principal the authenticator accepted
+    pub fn login(&mut self, principal: Principal) -> Principal {
+        tracing::info!(
+            "Authenticated {} ({:?}) with roles {:?}",
+            principal.name,
+            principal.method,
+            principal.roles
+        );
+        self.principal = Some(principal.clone());
+        principal
+    }
+
+    /// End the session; without auth configured this reverts to the local principal
+    pub fn logout(&mut self) {
+        self.principal = (!self.authenticator.is_enabled()).then(Principal::local);
+    }
+
+    /// The authenticated principal, if any
+    pub fn principal(&self) -> Option<&Principal> {
+        self.principal.as_ref()
+    }
+
+    /// Ensure the session's roles grant `permission`; every command checks here first.
+    /// Denials are recorded as audit events.
+    pub async fn authorize(&self, permission: Permission) -> Result<&Principal> {
+        if let Some(principal) = self
+            .principal
+            .as_ref()
+            .filter(|p| self.authenticator.rbac().permits(p, permission))
+        {
+            return Ok(principal);
+        }
+
+        let actor = self.actor();
+        tracing::warn!("Denied {:?} to {}", permission, actor);
+        let event = rbac::denial_event(&self.hostname, &actor, permission);
+        if let Err(e) = self.store_event(&event).await {
+            tracing::warn!("Failed to record access denial: {}", e);
+        }
+        self.record_audit(
+            "authorize",
+            Some(permission.as_str()),
+            AuditOutcome::Denied,
+            None,
+        )
+        .await;
+
+        match self.principal {
+            Some(_) => bail!("Permission denied: {:?} required", permission),
+            None => bail!("Not authenticated"),
         }
     }
 
+    /// Record the outcome of a state-changing command in the audit log,
+    /// passing the result through
+    pub async fn audit<T, E: std::fmt::Display>(
+        &self,
+        action: &str,
+        target: Option<&str>,
+        result: Result<T, E>,
+    ) -> Result<T, E> {
+        match &result {
+            Ok(_) => {
+                self.record_audit(action, target, AuditOutcome::Success, None)
+                    .await
+            }
+            Err(e) => {
+                self.record_audit(action, target, AuditOutcome::Failure, Some(&e.to_string()))
+                    .await
+            }
+        }
+        result
+    }
+
+    async fn record_audit(
+        &self,
+        action: &str,
+        target: Option<&str>,
+        outcome: AuditOutcome,
+        error: Option<&str>,
+    ) {
+        let Ok(pool) = self.pool() else {
+            tracing::warn!("Database not initialized, {} went unaudited", action);
+            return;
+        };
+        if let Err(e) = audit::record(pool, &self.actor(), action, target, outcome, error).await {
+            tracing::warn!("Failed to record {} in the audit log: {}", action, e);
+        }
+    }
+
+    /// Audit log entries matching `filter`, newest first
+    pub async fn get_audit_log(&self, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEntry>> {
+        audit::get_entries(self.pool()?, filter, limit).await
+    }
+
+    /// Name recorded as the actor of operator actions
+    fn actor(&self) -> String {
+        self.principal
+            .as_ref()
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| "anonymous".to_string())
+    }
+
+    /// Attach the control channel of the running daemon
+    pub fn set_control_channel(&mut self, tx: mpsc::Sender<ControlCommand>) {
+        self.control_tx = Some(tx);
+    }
+
+    /// Send commands for `hostname` over its collector connection, which
+    /// the agent reached at `collector`
+    pub fn register_forwarder(
+        &mut self,
+        hostname: &str,
+        tx: mpsc::Sender<ControlCommand>,
+        collector: SocketAddr,
+    ) {
+        self.forwarders
+            .insert(hostname.to_string(), Forwarder { tx, collector });
+    }
+
+    /// Forget a forwarder's control channel once its connection ends, unless
+    /// a newer connection from the same host has replaced it
+    pub fn unregister_forwarder(&mut self, hostname: &str, tx: &mpsc::Sender<ControlCommand>) {
+        if self
+            .forwarders
+            .get(hostname)
+            .is_some_and(|current| current.tx.same_channel(tx))
+        {
+            self.forwarders.remove(hostname);
+        }
+    }
+
+    /// Record the capabilities the daemon announced on startup
+    pub fn set_agent_hello(&mut self, hello: AgentHello) {
+        self.agent = Some(hello);
+    }
+
+    /// Capabilities of the running daemon, if it has announced them
+    pub fn agent_hello(&self) -> Option<&AgentHello> {
+        self.agent.as_ref()
+    }
+
+    /// Keep the latest rule hit counts an agent reported
+    pub fn set_rule_stats(&mut self, report: RuleStatsReport) {
+        self.rule_stats.insert(report.hostname.clone(), report);
+    }
+
+    /// Latest rule hit counts of every agent that reported them
+    pub fn rule_stats(&self) -> Vec<RuleStatsReport> {
+        self.rule_stats.values().cloned().collect()
+    }
+
+    /// Ask the daemon for its rule hit counts now; they arrive as a
+    /// `rule-stats` event
+    pub async fn request_rule_stats(&self) -> Result<()> {
+        self.require_command("report_rule_stats")?;
+        self.send_control(ControlCommand::ReportRuleStats).await
+    }
+
+    /// Ask the daemon to apply its config file again; the outcome arrives as
+    /// a `config_reload` event
+    pub async fn reload_agent_config(&self) -> Result<()> {
+        self.require_command("reload_config")?;
+        self.send_control(ControlCommand::ReloadConfig {
+            requested_by: self.actor(),
+        })
+        .await
+    }
+
+    /// Fail early when the daemon has announced it cannot run `command`.
+    /// Daemons predating the hello line are assumed to support everything.
+    fn require_command(&self, command: &str) -> Result<()> {
+        match &self.agent {
+            Some(agent) if !agent.capabilities.supports_command(command) => {
+                bail!("Agent {} does not support {}", agent.hostname, command)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a command to the daemon
+    async fn send_control(&self, command: ControlCommand) -> Result<()> {
+        let tx = self
+            .control_tx
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Daemon control channel not available"))?;
+        tx.send(command)
+            .await
+            .map_err(|_| anyhow::anyhow!("Daemon control channel closed"))
+    }
+
     /// Initialize the database connection
     pub async fn init_db(&mut self) -> Result<()> {
         let pool = database::init_database(&self.db_path).await?;
+        if store::compression_enabled() {
+            tracing::info!("Compressing event payloads");
+            self.payload_encoder = Some(PayloadEncoder::new());
+        }
         self.pool = Some(pool);
         Ok(())
     }
 
     /// Get the database pool
-    fn pool(&self) -> Result<&SqlitePool> {
+    fn pool(&self) -> GuardianResult<&SqlitePool> {
         self.pool
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Database not initialized"))
+            .ok_or_else(|| GuardianError::storage("Database not initialized"))
+    }
+
+    /// Digest scheduler for the configured subscribers, once the database is ready
+    pub fn digest_scheduler(&self) -> Option<DigestScheduler> {
+        let config = self.notify.clone()?;
+        let pool = self.pool.clone()?;
+        Some(DigestScheduler::new(pool, config))
+    }
+
+    /// Start SLA breach monitoring and the metrics endpoint, once the database is ready
+    pub fn start_sla_tasks(&self) {
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+
+        tokio::spawn(sla::run_breach_monitor(
+            pool.clone(),
+            self.sla.clone(),
+            self.hostname.clone(),
+        ));
+
+        if let Some(addr) = self.sla.metrics_addr.clone() {
+            let config = self.sla.clone();
+            tokio::spawn(async move {
+                if let Err(e) = sla::serve_metrics(addr, pool, config).await {
+                    tracing::error!("Metrics endpoint failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Watch agent heartbeats and raise an event when one goes silent
+    pub fn start_offline_monitor(&self) {
+        if let Some(pool) = self.pool.clone() {
+            tokio::spawn(heartbeat::run_offline_monitor(pool));
+        }
+    }
+
+    /// Track an agent heartbeat; heartbeats are not stored as events
+    pub async fn record_heartbeat(&self, event: &LogEvent) -> Result<()> {
+        let pool = self.pool()?;
+        if let Some(back) =
+            heartbeat::record(pool, &self.heartbeat, event, chrono::Utc::now()).await?
+        {
+            database::insert_event(pool, &back).await?;
+        }
+        Ok(())
+    }
+
+    /// Acknowledge an alert on behalf of the session's principal
+    pub async fn acknowledge_alert(&self, event_id: &str) -> GuardianResult<()> {
+        database::acknowledge_event(self.pool()?, event_id, &self.actor()).await
+    }
+
+    /// Resolve an alert on behalf of the session's principal
+    pub async fn resolve_alert(&self, event_id: &str) -> GuardianResult<()> {
+        database::resolve_event(self.pool()?, event_id, &self.actor()).await
+    }
+
+    /// The alert queue, optionally one status or assignee only
+    pub async fn get_alerts(
+        &self,
+        status: Option<database::AlertStatus>,
+        assignee: Option<&str>,
+        limit: i64,
+    ) -> GuardianResult<Vec<database::Alert>> {
+        database::get_alerts(self.pool()?, status, assignee, limit).await
+    }
+
+    /// Assign an alert to an analyst, or unassign it with `None`
+    pub async fn assign_alert(&self, event_id: &str, assignee: Option<&str>) -> Result<()> {
+        let assignee = assignee.map(str::trim).filter(|a| !a.is_empty());
+        if !database::assign_alert(self.pool()?, event_id, assignee).await? {
+            bail!("Alert {} does not exist", event_id);
+        }
+        Ok(())
+    }
+
+    /// Close an alert, with an optional reason such as `false positive`
+    pub async fn close_alert(&self, event_id: &str, reason: Option<&str>) -> GuardianResult<()> {
+        let reason = reason.map(str::trim).filter(|r| !r.is_empty());
+        database::close_alert(self.pool()?, event_id, &self.actor(), reason).await
+    }
+
+    /// Put a closed alert back in the queue
+    pub async fn reopen_alert(&self, event_id: &str) -> Result<()> {
+        if !database::reopen_alert(self.pool()?, event_id).await? {
+            bail!("Alert {} is not closed", event_id);
+        }
+        Ok(())
+    }
+
+    /// Open an incident from selected events. With a correlation key
+    /// (`rule@hostname`) it also collects matching rule hits while open.
+    pub async fn create_incident(
+        &self,
+        title: &str,
+        event_ids: &[String],
+        correlation_key: Option<&str>,
+    ) -> GuardianResult<database::Incident> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(GuardianError::invalid_input("Incident title is required"));
+        }
+        let correlation_key = correlation_key.map(str::trim).filter(|k| !k.is_empty());
+        database::create_incident(
+            self.pool()?,
+            title,
+            correlation_key,
+            event_ids,
+            &self.actor(),
+        )
+        .await
+    }
+
+    /// Incidents, optionally `open` or `closed` ones only
+    pub async fn get_incidents(
+        &self,
+        status: Option<&str>,
+    ) -> GuardianResult<Vec<database::Incident>> {
+        database::get_incidents(self.pool()?, status).await
+    }
+
+    /// Events in an incident, oldest first
+    pub async fn get_incident_events(&self, incident_id: &str) -> GuardianResult<Vec<LogEvent>> {
+        database::get_incident_events(self.pool()?, incident_id).await
+    }
+
+    /// Add events to an incident, returning how many were new to it
+    pub async fn attach_incident_events(
+        &self,
+        incident_id: &str,
+        event_ids: &[String],
+    ) -> GuardianResult<u64> {
+        let pool = self.pool()?;
+        if database::get_incident(pool, incident_id).await?.is_none() {
+            return Err(GuardianError::invalid_input(format!(
+                "Incident {} does not exist",
+                incident_id
+            )));
+        }
+        database::attach_events(pool, incident_id, event_ids, &self.actor()).await
+    }
+
+    /// Remove events from an incident, returning how many were in it
+    pub async fn detach_incident_events(
+        &self,
+        incident_id: &str,
+        event_ids: &[String],
+    ) -> GuardianResult<u64> {
+        database::detach_events(self.pool()?, incident_id, event_ids).await
+    }
+
+    /// Close an incident
+    pub async fn close_incident(&self, incident_id: &str) -> Result<()> {
+        if !database::close_incident(self.pool()?, incident_id).await? {
+            bail!("Incident {} is not open", incident_id);
+        }
+        Ok(())
+    }
+
+    /// Markdown summary of an incident and its timeline
+    pub async fn export_incident(&self, incident_id: &str) -> Result<String> {
+        match incident::summarize(self.pool()?, incident_id).await? {
+            Some(summary) => Ok(summary.to_markdown()),
+            None => bail!("Incident {} does not exist", incident_id),
+        }
+    }
+
+    /// Time-to-acknowledge and time-to-resolve metrics per severity
+    pub async fn get_sla_metrics(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<SlaMetrics>> {
+        let timings = database::get_alert_timings(self.pool()?, from, to).await?;
+        Ok(sla::compute_metrics(
+            &timings,
+            &self.sla,
+            chrono::Utc::now(),
+        ))
+    }
+
+    /// Account an incoming event against its agent's quota.
+    /// Returns whether it should be stored; over-quota agents are told to sample harder.
+    pub async fn admit_event(&mut self, event: &LogEvent, bytes: usize) -> bool {
+        let outcome = self.quotas.record(
+            &event.hostname,
+            bytes as u64,
+            event.severity,
+            chrono::Utc::now(),
+        );
+
+        // Collector-fed agents are told over their connection, the local
+        // daemon over its socket
+        if let Some(rate) = outcome.sampling_change {
+            let command = ControlCommand::SetSampling { rate };
+            let local = self.is_local_agent(&event.hostname);
+            let sent = match self.forwarders.get(&event.hostname) {
+                Some(forwarder) => forwarder
+                    .tx
+                    .try_send(command)
+                    .map_err(|_| anyhow::anyhow!("Collector connection is busy or closed")),
+                None if local => self.send_control(command).await,
+                None => Ok(()),
+            };
+            if let Err(e) = sent {
+                tracing::warn!("Failed to adjust sampling for {}: {}", event.hostname, e);
+            }
+        }
+
+        outcome.admitted
+    }
+
+    /// Quota usage per agent
+    pub fn quota_status(&self) -> Vec<AgentQuotaStatus> {
+        self.quotas.status()
     }
 
     /// Store an event in the database
-    pub async fn store_event(&self, event: &LogEvent) -> Result<()> {
-        database::insert_event(self.pool()?, event).await
+    pub async fn store_event(&self, event: &LogEvent) -> GuardianResult<()> {
+        let pool = self.pool()?;
+        match &self.payload_encoder {
+            Some(encoder) => database::insert_compressed_event(pool, event, encoder).await,
+            None => database::insert_event(pool, event).await,
+        }
+    }
+
+    /// Store an agent stats rollup
+    pub async fn store_rollup(&self, rollup: &StatsRollup) -> GuardianResult<()> {
+        database::insert_rollup(self.pool()?, rollup).await
+    }
+
+    /// Get hourly fleet trends merged from rollups and raw events
+    pub async fn get_fleet_trends(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> GuardianResult<Vec<database::TrendPoint>> {
+        database::get_fleet_trends(self.pool()?, from, to).await
+    }
+
+    /// Get event and alert counts by day of week and hour of day
+    pub async fn get_activity_heatmap(
+        &self,
+        filter: &database::HeatmapFilter,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        utc_offset_minutes: i32,
+    ) -> GuardianResult<database::Heatmap> {
+        database::get_activity_heatmap(self.pool()?, filter, from, to, utc_offset_minutes).await
+    }
+
+    /// Get event counts per minute or hour bucket by severity
+    pub async fn get_event_timeline(
+        &self,
+        interval: database::TimelineInterval,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        filter: &database::HeatmapFilter,
+    ) -> GuardianResult<Vec<database::TimelineBucket>> {
+        database::get_event_timeline(self.pool()?, interval, from, to, filter).await
     }
 
-    /// Get recent events
-    pub async fn get_recent_events(&self, limit: i64) -> Result<Vec<LogEvent>> {
-        database::get_recent_events(self.pool()?, limit).await
+    /// Get the most frequent hosts, rules, paths or remote addresses
+    pub async fn get_top_n(
+        &self,
+        dimension: database::TopDimension,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        limit: u32,
+    ) -> GuardianResult<Vec<database::TopEntry>> {
+        database::get_top_n(self.pool()?, dimension, from, to, limit).await
+    }
+
+    /// Write an HTML summary report of `range` to `path`
+    /// Write a report into the `reports` directory beside the database, and
+    /// return where it went
+    pub async fn generate_report(&self, range: report::ReportRange, name: &str) -> Result<PathBuf> {
+        let dir = self
+            .db_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("reports");
+        let path = report::report_path(&dir, name)?;
+        report::generate_report(self.pool()?, range, &path, &self.hostname).await?;
+        Ok(path)
     }
 
-    /// Get event statistics
-    pub async fn get_event_stats(&self) -> Result<serde_json::Value> {
-        database::get_event_stats(self.pool()?).await
+    /// Database size broken down by event type, severity and agent, with projected growth
+    pub async fn get_storage_report(&self) -> Result<StorageReport> {
+        storage::storage_report(self.pool()?, chrono::Utc::now()).await
     }
 
-    /// Search events
+    /// Preview what a retention policy would reclaim
+    pub async fn preview_prune(&self, policy: &PrunePolicy) -> Result<PrunePreview> {
+        storage::preview_prune(self.pool()?, policy, chrono::Utc::now()).await
+    }
+
+    /// Get a page of recent events, optionally from one host
+    pub async fn get_recent_events(
+        &self,
+        limit: i64,
+        hostname: Option<&str>,
+        cursor: Option<&str>,
+    ) -> GuardianResult<database::EventPage> {
+        database::get_recent_events(self.pool()?, limit, hostname, cursor).await
+    }
+
+    /// Get event statistics, optionally for one host
+    pub async fn get_event_stats(
+        &self,
+        hostname: Option<&str>,
+    ) -> GuardianResult<serde_json::Value> {
+        database::get_event_stats(self.pool()?, hostname).await
+    }
+
+    /// Search events matching a filter
     pub async fn search_events(
         &self,
-        query: &str,
-        severity: Option<&str>,
+        filter: &SearchFilter,
         limit: i64,
-        offset: i64,
-    ) -> anyhow::Result<Vec<LogEvent>> {
-        database::search_events(self.pool()?, query, severity, limit, offset).await
+        cursor: Option<&str>,
+    ) -> GuardianResult<database::EventPage> {
+        database::search_events(self.pool()?, filter, limit, cursor).await
+    }
+
+    /// Save a named search as the current operator
+    pub async fn create_saved_search(
+        &self,
+        name: &str,
+        filter: &SearchFilter,
+        window_secs: Option<i64>,
+    ) -> GuardianResult<database::SavedSearch> {
+        let name = validate_saved_search(name, window_secs)?;
+        database::create_saved_search(self.pool()?, name, filter, window_secs, &self.actor()).await
+    }
+
+    /// Every saved search, by name
+    pub async fn get_saved_searches(&self) -> GuardianResult<Vec<database::SavedSearch>> {
+        database::get_saved_searches(self.pool()?).await
+    }
+
+    /// Replace a saved search's name, filter and window
+    pub async fn update_saved_search(
+        &self,
+        id: &str,
+        name: &str,
+        filter: &SearchFilter,
+        window_secs: Option<i64>,
+    ) -> Result<database::SavedSearch> {
+        let name = validate_saved_search(name, window_secs)?;
+        let pool = self.pool()?;
+        if !database::update_saved_search(pool, id, name, filter, window_secs).await? {
+            bail!("Saved search {} does not exist", id);
+        }
+        database::get_saved_search(pool, id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Saved search {} does not exist", id))
+    }
+
+    /// Delete a saved search
+    pub async fn delete_saved_search(&self, id: &str) -> Result<()> {
+        if !database::delete_saved_search(self.pool()?, id).await? {
+            bail!("Saved search {} does not exist", id);
+        }
+        Ok(())
+    }
+
+    /// Run a saved search, with a relative window counted from now
+    pub async fn run_saved_search(
+        &self,
+        id: &str,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> GuardianResult<database::EventPage> {
+        let pool = self.pool()?;
+        let Some(search) = database::get_saved_search(pool, id).await? else {
+            return Err(GuardianError::invalid_input(format!(
+                "Saved search {} does not exist",
+                id
+            )));
+        };
+        database::search_events(pool, &search.filter_at(chrono::Utc::now()), limit, cursor).await
+    }
+
+    /// Count the tags of events matching a filter
+    pub async fn get_tag_counts(
+        &self,
+        filter: &SearchFilter,
+        limit: i64,
+    ) -> GuardianResult<Vec<database::TagCount>> {
+        database::get_tag_counts(self.pool()?, filter, limit).await
+    }
+
+    /// Record an agent announcement from `address` in the host list
+    pub async fn record_host(&self, hello: &AgentHello, address: &str) -> GuardianResult<()> {
+        database::upsert_host(self.pool()?, hello, address).await
+    }
+
+    /// Hosts reporting to this console with their event counts
+    pub async fn get_hosts(&self) -> GuardianResult<Vec<database::HostSummary>> {
+        database::get_hosts(self.pool()?).await
+    }
+
+    /// Issue a one-time token `hostname` can trade for a collector client
+    /// certificate. Re-enrolling a host invalidates its previous certificate.
+    pub async fn create_enrollment_token(
+        &self,
+        hostname: &str,
+        ttl_hours: Option<u32>,
+    ) -> Result<String> {
+        let hostname = hostname.trim();
+        if hostname.is_empty() {
+            bail!("Hostname is required");
+        }
+        let token = enrollment::new_token();
+        let expires = chrono::Utc::now() + chrono::Duration::hours(ttl_hours.unwrap_or(24) as i64);
+        database::create_enrollment(
+            self.pool()?,
+            hostname,
+            &enrollment::hash_token(&token),
+            expires,
+        )
+        .await?;
+
+        let message = format!(
+            "Operator {} issued an enrollment token for {}",
+            self.actor(),
+            hostname
+        );
+        self.store_event(&response::audit_event(&self.hostname, message))
+            .await?;
+        Ok(token)
+    }
+
+    /// Stop accepting an agent's certificate; it is disconnected on its next frame
+    pub async fn revoke_agent(&self, hostname: &str) -> Result<()> {
+        if !database::revoke_enrollment(self.pool()?, hostname).await? {
+            bail!("{} is not enrolled", hostname);
+        }
+        let message = format!("Operator {} revoked agent {}", self.actor(), hostname);
+        self.store_event(&response::audit_event(&self.hostname, message))
+            .await?;
+        Ok(())
+    }
+
+    /// Enrollment state of every collector agent
+    pub async fn get_enrollments(&self) -> GuardianResult<Vec<database::Enrollment>> {
+        database::get_enrollments(self.pool()?).await
+    }
+
+    /// Host an unused, unexpired enrollment token was issued for
+    pub async fn enrollment_for_token(&self, token: &str) -> GuardianResult<Option<String>> {
+        database::find_pending_enrollment(self.pool()?, &enrollment::hash_token(token)).await
+    }
+
+    /// Record the certificate issued for an enrollment token; false when the
+    /// token was used or replaced meanwhile
+    pub async fn complete_enrollment(
+        &self,
+        hostname: &str,
+        token: &str,
+        fingerprint: &str,
+    ) -> GuardianResult<bool> {
+        database::complete_enrollment(
+            self.pool()?,
+            hostname,
+            &enrollment::hash_token(token),
+            fingerprint,
+        )
+        .await
+    }
+
+    /// Hostname and status of the agent presenting the certificate `fingerprint`
+    pub async fn agent_enrollment(
+        &self,
+        fingerprint: &str,
+    ) -> GuardianResult<Option<(String, String)>> {
+        database::enrollment_by_fingerprint(self.pool()?, fingerprint).await
+    }
+
+    /// Raw events an alert was derived from
+    pub async fn get_related_events(&self, alert_id: &str) -> GuardianResult<Vec<LogEvent>> {
+        database::get_related_events(self.pool()?, alert_id).await
+    }
+
+    /// Whether `hostname` is the daemon on this machine, reached over the
+    /// control socket rather than the collector
+    fn is_local_agent(&self, hostname: &str) -> bool {
+        self.agent
+            .as_ref()
+            .is_none_or(|agent| agent.hostname == hostname)
+    }
+
+    /// Send a command to `hostname`: over its collector connection when it
+    /// forwards to this console, otherwise to the local daemon
+    async fn send_to_agent(&self, hostname: &str, command: ControlCommand) -> Result<()> {
+        match self.forwarders.get(hostname) {
+            Some(forwarder) => forwarder
+                .tx
+                .send(command)
+                .await
+                .map_err(|_| anyhow::anyhow!("Collector connection to {} closed", hostname)),
+            None if self.is_local_agent(hostname) => self.send_control(command).await,
+            None => bail!("Agent {} is not connected", hostname),
+        }
+    }
+
+    /// Stage an isolation of `hostname` that must be confirmed before it is
+    /// sent. An agent connected through the collector always keeps the
+    /// collector address it connected to, so it can still be released.
+    pub fn request_isolation(
+        &mut self,
+        hostname: &str,
+        mut allow: Vec<String>,
+        duration_secs: u64,
+    ) -> Result<IsolationChallenge> {
+        self.require_command("isolate_host")?;
+        match self.forwarders.get(hostname) {
+            Some(forwarder) => {
+                let collector = forwarder.collector.to_string();
+                if !allow.contains(&collector) {
+                    allow.push(collector);
+                }
+            }
+            None if self.is_local_agent(hostname) => {}
+            None => bail!("Agent {} is not connected", hostname),
+        }
+        let pending = PendingIsolation::new(hostname, allow, duration_secs)?;
+        let challenge = pending.challenge();
+        self.pending_isolation = Some(pending);
+        Ok(challenge)
+    }
+
+    /// Confirm a staged isolation request and instruct the agent to apply it
+    pub async fn confirm_isolation(&mut self, token: &str) -> Result<()> {
+        let pending = self
+            .pending_isolation
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No isolation request pending"))?;
+        pending.verify(token)?;
+        let pending = self.pending_isolation.take().expect("checked above");
+
+        self.send_to_agent(
+            &pending.hostname,
+            ControlCommand::IsolateHost {
+                allow: pending.allow.clone(),
+                duration_secs: pending.duration_secs,
+                requested_by: self.actor(),
+            },
+        )
+        .await?;
+
+        let message = format!(
+            "Operator {} confirmed isolation of {} for {}s (allow: {:?})",
+            self.actor(),
+            pending.hostname,
+            pending.duration_secs,
+            pending.allow
+        );
+        self.store_event(&response::audit_event(&self.hostname, message))
+            .await?;
+        Ok(())
+    }
+
+    /// Instruct `hostname` to lift host isolation
+    pub async fn release_isolation(&mut self, hostname: &str) -> Result<()> {
+        self.require_command("release_host")?;
+        if self
+            .pending_isolation
+            .as_ref()
+            .is_some_and(|pending| pending.hostname == hostname)
+        {
+            self.pending_isolation = None;
+        }
+        self.send_to_agent(
+            hostname,
+            ControlCommand::ReleaseHost {
+                requested_by: self.actor(),
+            },
+        )
+        .await?;
+
+        let message = format!(
+            "Operator {} released the isolation of {}",
+            self.actor(),
+            hostname
+        );
+        self.store_event(&response::audit_event(&self.hostname, message))
+            .await?;
+        Ok(())
+    }
+
+    /// Instruct the daemon to scan everything under `path` now. Returns the
+    /// scan's id; its events arrive tagged `scan:<id>`.
+    pub async fn scan_path(&self, path: &str) -> Result<Uuid> {
+        self.require_command("scan_path")?;
+        if !Path::new(path).is_absolute() {
+            bail!("Scan path must be absolute");
+        }
+
+        let scan_id = Uuid::new_v4();
+        self.send_control(ControlCommand::ScanPath {
+            scan_id,
+            path: path.to_string(),
+            requested_by: self.actor(),
+        })
+        .await?;
+        Ok(scan_id)
+    }
+
+    /// Evaluate a candidate rule against the events stored in `range`,
+    /// keeping up to `max_samples` matches
+    pub async fn test_rule(
+        &self,
+        rule: &RuleDefinition,
+        range: report::ReportRange,
+        max_samples: usize,
+    ) -> GuardianResult<RuleTest> {
+        let rule = CompiledRule::new(rule.clone())?;
+        if range.from >= range.to {
+            return Err(GuardianError::invalid_input(
+                "Rule test range must end after it starts",
+            ));
+        }
+        database::test_rule(self.pool()?, &rule, range.from, range.to, max_samples).await
+    }
+
+    /// Versions of `path` the daemon keeps and can restore, oldest first
+    pub async fn get_file_versions(&self, path: &str) -> Result<Vec<database::FileVersion>> {
+        let agent = self
+            .agent
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Daemon has not announced itself yet"))?;
+        Ok(database::get_file_versions(self.pool()?, &agent.hostname, path).await?)
+    }
+
+    /// Instruct the daemon to write the version of `path` with `hash` back
+    /// over it. The outcome arrives as a `file_restore` event.
+    pub async fn restore_file_version(&self, path: &str, hash: &str) -> Result<()> {
+        self.require_command("restore_file")?;
+        if !hash.chars().all(|c| c.is_ascii_hexdigit()) || hash.len() != 64 {
+            bail!("Version hash must be a SHA-256 hex digest");
+        }
+
+        self.send_control(ControlCommand::RestoreFile {
+            path: path.to_string(),
+            hash: hash.to_string(),
+            requested_by: self.actor(),
+        })
+        .await
+    }
+}
+
+/// Trimmed name of a saved search, rejecting empty names and windows
+fn validate_saved_search(name: &str, window_secs: Option<i64>) -> GuardianResult<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(GuardianError::invalid_input(
+            "Saved search name is required",
+        ));
+    }
+    if window_secs.is_some_and(|secs| secs <= 0) {
+        return Err(GuardianError::invalid_input(
+            "Saved search window must be positive",
+        ));
     }
+    Ok(name)
 }
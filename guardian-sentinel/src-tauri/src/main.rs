@@ -1,14 +1,26 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use guardian_common::LogEvent;
+use guardian_common::ipc::Frame;
+use guardian_common::{
+    AgentHello, ControlCommand, LogEvent, RuleDefinition, RuleStatsReport, RuleTest, SearchFilter,
+};
+use guardian_sentinel_lib::audit::{AuditEntry, AuditFilter};
+use guardian_sentinel_lib::auth::{Credentials, Principal};
+use guardian_sentinel_lib::collector::{self, CollectorConfig};
+use guardian_sentinel_lib::database::{
+    Alert, AlertStatus, Enrollment, EventPage, HostSummary, Incident, SavedSearch,
+};
+use guardian_sentinel_lib::ipc;
+use guardian_sentinel_lib::rbac::Permission;
+use guardian_sentinel_lib::report::ReportRange;
 use guardian_sentinel_lib::AppState;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tauri::{Emitter, Manager};
-use tracing::{error, info};
 #[allow(unused_imports)]
 use tauri_plugin_shell::ShellExt;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, error, info};
 
 use tracing_subscriber::EnvFilter;
 
@@ -34,6 +46,7 @@ async fn main() {
                 .join("guardian.db");
 
             info!("Database path: {:?}", db_path);
+            let db_path_parent = db_path.parent().map(|p| p.to_path_buf());
 
             // Create app state
             let state = Arc::new(Mutex::new(AppState::new(db_path)));
@@ -45,9 +58,38 @@ async fn main() {
                 let mut state = init_state.lock().await;
                 if let Err(e) = state.init_db().await {
                     error!("Failed to initialize database: {}", e);
+                } else {
+                    if let Some(digests) = state.digest_scheduler() {
+                        tauri::async_runtime::spawn(digests.run());
+                    }
+                    state.start_sla_tasks();
+                    state.start_offline_monitor();
                 }
             });
 
+            // Accept events forwarded by remote daemons when collector mode is configured
+            match CollectorConfig::load(db_path_parent.as_deref()) {
+                Ok(Some(config)) => {
+                    let (events_tx, mut events_rx) = mpsc::channel::<LogEvent>(1000);
+                    let collector_state = state.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = collector::run(config, collector_state, events_tx).await {
+                            error!("Collector error: {:#}", e);
+                        }
+                    });
+                    let emit_handle = handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        while let Some(log_event) = events_rx.recv().await {
+                            if let Err(e) = emit_handle.emit("realtime-event", &log_event) {
+                                error!("Failed to emit event: {}", e);
+                            }
+                        }
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => error!("Invalid collector config, collector disabled: {:#}", e),
+            }
+
             // Spawn the guardian daemon sidecar
             tauri::async_runtime::spawn(async move {
                 if let Err(e) = spawn_daemon(handle, state).await {
@@ -58,65 +100,100 @@ async fn main() {
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            authenticate,
+            logout,
+            current_principal,
             get_recent_events,
             get_event_stats,
-            search_events
+            search_events,
+            get_tag_counts,
+            create_saved_search,
+            get_saved_searches,
+            update_saved_search,
+            delete_saved_search,
+            run_saved_search,
+            get_related_events,
+            request_host_isolation,
+            confirm_host_isolation,
+            release_host_isolation,
+            scan_path,
+            test_rule,
+            get_file_versions,
+            restore_file_version,
+            get_quota_status,
+            get_fleet_trends,
+            get_activity_heatmap,
+            get_event_timeline,
+            get_top_n,
+            acknowledge_alert,
+            resolve_alert,
+            get_alerts,
+            assign_alert,
+            close_alert,
+            reopen_alert,
+            create_incident,
+            get_incidents,
+            get_incident_events,
+            attach_incident_events,
+            detach_incident_events,
+            close_incident,
+            export_incident,
+            get_sla_metrics,
+            generate_report,
+            get_storage_report,
+            preview_prune,
+            get_agent_capabilities,
+            get_rule_stats,
+            refresh_rule_stats,
+            reload_agent_config,
+            get_hosts,
+            get_enrollments,
+            create_enrollment_token,
+            revoke_agent,
+            get_audit_log
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-/// Spawn the guardian daemon and process its output
+/// Spawn the guardian daemon and exchange frames with it over its local socket
 async fn spawn_daemon(
     app: tauri::AppHandle,
     state: Arc<Mutex<AppState>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("Spawning guardian daemon...");
 
+    // The daemon listens where the console tells it to
+    let socket = ipc::socket_path(&app.path().app_data_dir()?);
+
     // Get the path to the sidecar binary
     // In dev: cargo run --bin guardian-daemon
     // In prod: bundled sidecar
     // For this prototype, we'll try to run the binary directly from target/debug for simplicity in dev mode
     // In a real Tauri app, you'd use the sidecar feature properly
-    
-    let mut cmd = tauri_plugin_shell::ShellExt::shell(&app)
+
+    let cmd = tauri_plugin_shell::ShellExt::shell(&app)
         .sidecar("guardian-daemon")
         .or_else(|_| {
             // Fallback for dev mode if sidecar isn't configured in tauri.conf.json
             // We'll run the binary directly relative to the project root
-             Ok::<_, tauri_plugin_shell::Error>(tauri_plugin_shell::ShellExt::shell(&app)
-                .command("../../target/debug/guardian-daemon"))
-        })?;
+            Ok::<_, tauri_plugin_shell::Error>(
+                tauri_plugin_shell::ShellExt::shell(&app)
+                    .command("../../target/debug/guardian-daemon"),
+            )
+        })?
+        .env("GUARDIAN_SOCKET", &socket);
 
-    let (mut rx, _child) = cmd.spawn()?;
+    let (mut rx, child) = cmd.spawn()?;
 
-    // Process output in background
+    // Events arrive over the socket; the daemon's own output is only logged
     tauri::async_runtime::spawn(async move {
+        let _child = child;
         while let Some(event) = rx.recv().await {
             match event {
                 tauri_plugin_shell::process::CommandEvent::Stdout(line_bytes) => {
                     let line = String::from_utf8_lossy(&line_bytes);
-                    for event_str in line.lines() {
-                        if event_str.trim().is_empty() { continue; }
-                        
-                        // Try to parse as LogEvent
-                        if let Ok(log_event) = serde_json::from_str::<LogEvent>(event_str) {
-                            // Store in DB
-                            let state_lock = state.lock().await;
-                            if let Err(e) = state_lock.store_event(&log_event).await {
-                                error!("Failed to store event: {}", e);
-                            }
-                            drop(state_lock);
-                            
-                            // Emit to frontend
-                            if let Err(e) = app.emit("realtime-event", &log_event) {
-                                error!("Failed to emit event: {}", e);
-                            }
-                        } else {
-                            // Log raw output if it's not JSON
-                             info!("Daemon: {}", event_str);
-                        }
-                    }
+                    debug!("Daemon stdout: {}", line.trim());
                 }
                 tauri_plugin_shell::process::CommandEvent::Stderr(line_bytes) => {
                     let line = String::from_utf8_lossy(&line_bytes);
@@ -127,20 +204,145 @@ async fn spawn_daemon(
         }
     });
 
+    // Keep a connection to the daemon; control commands queue while it is down
+    let (control_tx, control_rx) = mpsc::channel::<ControlCommand>(16);
+    let (frames_tx, mut frames_rx) = mpsc::channel::<Frame>(1000);
+    state.lock().await.set_control_channel(control_tx);
+    // With a signing key set, events the daemon didn't sign are dropped
+    let signer = guardian_common::EventSigner::from_env()?;
+    if signer.is_some() {
+        info!("Verifying event signatures");
+    }
+    tauri::async_runtime::spawn(ipc::run(socket, control_rx, frames_tx, signer));
+
+    // Process frames in background
+    tauri::async_runtime::spawn(async move {
+        while let Some(frame) = frames_rx.recv().await {
+            match frame {
+                Frame::Event(log_event) if log_event.event_type.kind() == "heartbeat" => {
+                    if let Err(e) = state.lock().await.record_heartbeat(&log_event).await {
+                        error!("Failed to record heartbeat: {}", e);
+                    }
+                }
+                Frame::Event(log_event) if log_event.event_type.kind() == "scan_progress" => {
+                    // Only useful while the scan runs; the summary is what gets stored
+                    if let Err(e) = app.emit("scan-progress", &log_event) {
+                        error!("Failed to emit scan progress: {}", e);
+                    }
+                }
+                Frame::Event(log_event) => {
+                    // Enforce agent quota, then store in DB
+                    let bytes = log_event.to_json().map(|json| json.len()).unwrap_or(0);
+                    let mut state_lock = state.lock().await;
+                    if state_lock.admit_event(&log_event, bytes).await {
+                        if let Err(e) = state_lock.store_event(&log_event).await {
+                            error!("Failed to store event: {}", e);
+                        }
+                    }
+                    drop(state_lock);
+
+                    // Emit to frontend
+                    if let Err(e) = app.emit("realtime-event", &log_event) {
+                        error!("Failed to emit event: {}", e);
+                    }
+                    // On-demand scans also stream to whoever started them
+                    if log_event.tags.iter().any(|tag| tag == "scan") {
+                        if let Err(e) = app.emit("scan-progress", &log_event) {
+                            error!("Failed to emit scan progress: {}", e);
+                        }
+                    }
+                }
+                Frame::Rollup(rollup) => {
+                    // Hourly rollup: store for fleet trend queries
+                    let state_lock = state.lock().await;
+                    if let Err(e) = state_lock.store_rollup(&rollup).await {
+                        error!("Failed to store rollup: {}", e);
+                    }
+                }
+                Frame::RuleStats(report) => {
+                    // Rule hit counts: keep the latest for tuning noisy rules
+                    state.lock().await.set_rule_stats(report.clone());
+                    if let Err(e) = app.emit("rule-stats", &report) {
+                        error!("Failed to emit rule stats: {}", e);
+                    }
+                }
+                Frame::Hello(hello) => {
+                    // Capability announcement: lets the UI hide unsupported actions
+                    info!(
+                        "Daemon {} v{} connected",
+                        hello.hostname, hello.agent_version
+                    );
+                    let mut state_lock = state.lock().await;
+                    if let Err(e) = state_lock.record_host(&hello, "local").await {
+                        error!("Failed to record host: {}", e);
+                    }
+                    state_lock.set_agent_hello(hello.clone());
+                    drop(state_lock);
+                    if let Err(e) = app.emit("agent-hello", &hello) {
+                        error!("Failed to emit agent hello: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
     info!("Guardian daemon started");
 
     Ok(())
 }
 
-/// Tauri command to get recent events
+/// Tauri command to authenticate the session
+#[tauri::command]
+async fn authenticate(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    credentials: Credentials,
+) -> Result<Principal, String> {
+    let authenticator = state.lock().await.authenticator();
+    let result = tokio::task::spawn_blocking(move || authenticator.authenticate(&credentials))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut state = state.lock().await;
+    let result = result.map(|principal| state.login(principal));
+    state
+        .audit("authenticate", None, result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to end the session
+#[tauri::command]
+async fn logout(state: tauri::State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let mut state = state.lock().await;
+    state.audit("logout", None, Ok(())).await.ok();
+    state.logout();
+    Ok(())
+}
+
+/// Tauri command to get the authenticated principal, if any
+#[tauri::command]
+async fn current_principal(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Option<Principal>, String> {
+    Ok(state.lock().await.principal().cloned())
+}
+
+/// Tauri command to get a page of recent events
 #[tauri::command]
 async fn get_recent_events(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     limit: Option<i64>,
-) -> Result<Vec<LogEvent>, String> {
+    hostname: Option<String>,
+    cursor: Option<String>,
+) -> Result<EventPage, String> {
     let state = state.lock().await;
     state
-        .get_recent_events(limit.unwrap_or(100))
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_recent_events(limit.unwrap_or(100), hostname.as_deref(), cursor.as_deref())
         .await
         .map_err(|e| e.to_string())
 }
@@ -149,28 +351,868 @@ async fn get_recent_events(
 #[tauri::command]
 async fn get_event_stats(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    hostname: Option<String>,
 ) -> Result<serde_json::Value, String> {
     let state = state.lock().await;
-    state.get_event_stats().await.map_err(|e| e.to_string())
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_event_stats(hostname.as_deref())
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Tauri command to search events
+/// Tauri command to search events, a page at a time
 #[tauri::command]
 async fn search_events(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
-    query: String,
-    severity: Option<String>,
+    filter: Option<SearchFilter>,
     limit: Option<i64>,
-    offset: Option<i64>,
-) -> Result<Vec<LogEvent>, String> {
+    cursor: Option<String>,
+) -> Result<EventPage, String> {
     let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
     state
         .search_events(
-            &query,
-            severity.as_deref(),
+            &filter.unwrap_or_default(),
             limit.unwrap_or(100),
-            offset.unwrap_or(0),
+            cursor.as_deref(),
         )
         .await
         .map_err(|e| e.to_string())
 }
+
+/// Tauri command to save a named search
+#[tauri::command]
+async fn create_saved_search(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    name: String,
+    filter: SearchFilter,
+    window_secs: Option<i64>,
+) -> Result<SavedSearch, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.create_saved_search(&name, &filter, window_secs).await;
+    state
+        .audit("create_saved_search", Some(&name), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list saved searches
+#[tauri::command]
+async fn get_saved_searches(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<SavedSearch>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.get_saved_searches().await.map_err(|e| e.to_string())
+}
+
+/// Tauri command to change a saved search
+#[tauri::command]
+async fn update_saved_search(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    id: String,
+    name: String,
+    filter: SearchFilter,
+    window_secs: Option<i64>,
+) -> Result<SavedSearch, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state
+        .update_saved_search(&id, &name, &filter, window_secs)
+        .await;
+    state
+        .audit("update_saved_search", Some(&id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to delete a saved search
+#[tauri::command]
+async fn delete_saved_search(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    id: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.delete_saved_search(&id).await;
+    state
+        .audit("delete_saved_search", Some(&id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to run a saved search, a page at a time
+#[tauri::command]
+async fn run_saved_search(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    id: String,
+    limit: Option<i64>,
+    cursor: Option<String>,
+) -> Result<EventPage, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .run_saved_search(&id, limit.unwrap_or(100), cursor.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to count the tags of matching events, for search facets
+#[tauri::command]
+async fn get_tag_counts(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<SearchFilter>,
+    limit: Option<i64>,
+) -> Result<Vec<guardian_sentinel_lib::database::TagCount>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_tag_counts(&filter.unwrap_or_default(), limit.unwrap_or(20))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get the raw events behind an alert
+#[tauri::command]
+async fn get_related_events(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    alert_id: String,
+) -> Result<Vec<LogEvent>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_related_events(&alert_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to stage the isolation of an agent; returns a challenge to
+/// confirm
+#[tauri::command]
+async fn request_host_isolation(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    hostname: String,
+    allow: Vec<String>,
+    duration_secs: Option<u64>,
+) -> Result<guardian_sentinel_lib::response::IsolationChallenge, String> {
+    let mut state = state.lock().await;
+    state
+        .authorize(Permission::RunResponse)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.request_isolation(&hostname, allow, duration_secs.unwrap_or(3600));
+    state
+        .audit("request_host_isolation", Some(&hostname), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to confirm a staged host isolation
+#[tauri::command]
+async fn confirm_host_isolation(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    token: String,
+) -> Result<(), String> {
+    let mut state = state.lock().await;
+    state
+        .authorize(Permission::RunResponse)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.confirm_isolation(&token).await;
+    state
+        .audit("confirm_host_isolation", None, result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to lift the isolation of an agent
+#[tauri::command]
+async fn release_host_isolation(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    hostname: String,
+) -> Result<(), String> {
+    let mut state = state.lock().await;
+    state
+        .authorize(Permission::RunResponse)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.release_isolation(&hostname).await;
+    state
+        .audit("release_host_isolation", Some(&hostname), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to scan a path on the daemon's host now; progress and
+/// matches arrive as `scan-progress` events
+#[tauri::command]
+async fn scan_path(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    path: String,
+) -> Result<uuid::Uuid, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::RunResponse)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.scan_path(&path).await;
+    state
+        .audit("scan_path", Some(&path), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to try a candidate rule against stored events, over the
+/// last 7 days unless a range is given
+#[tauri::command]
+async fn test_rule(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    rule: RuleDefinition,
+    range: Option<ReportRange>,
+    samples: Option<usize>,
+) -> Result<RuleTest, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let range = range.unwrap_or_else(|| ReportRange::last_days(chrono::Utc::now(), 7));
+    state
+        .test_rule(&rule, range, samples.unwrap_or(20))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list the versions of a protected file the daemon can
+/// restore
+#[tauri::command]
+async fn get_file_versions(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    path: String,
+) -> Result<Vec<guardian_sentinel_lib::database::FileVersion>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_file_versions(&path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to restore a protected file to a stored version
+#[tauri::command]
+async fn restore_file_version(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    path: String,
+    hash: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::RunResponse)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.restore_file_version(&path, &hash).await;
+    state
+        .audit("restore_file_version", Some(&path), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get ingestion quota usage per agent
+#[tauri::command]
+async fn get_quota_status(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<guardian_sentinel_lib::quota::AgentQuotaStatus>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(state.quota_status())
+}
+
+/// Tauri command to get hourly fleet trends (defaults to the last 24 hours)
+#[tauri::command]
+async fn get_fleet_trends(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<guardian_sentinel_lib::database::TrendPoint>, String> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::hours(24));
+
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_fleet_trends(from, to)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to acknowledge an alert, excluding it from the caller's digests
+#[tauri::command]
+async fn acknowledge_alert(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    event_id: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.acknowledge_alert(&event_id).await;
+    state
+        .audit("acknowledge_alert", Some(&event_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to resolve an alert
+#[tauri::command]
+async fn resolve_alert(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    event_id: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.resolve_alert(&event_id).await;
+    state
+        .audit("resolve_alert", Some(&event_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list the alert queue, newest first
+#[tauri::command]
+async fn get_alerts(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    status: Option<AlertStatus>,
+    assignee: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<Alert>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_alerts(status, assignee.as_deref(), limit.unwrap_or(100))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to assign an alert, or unassign it without an assignee
+#[tauri::command]
+async fn assign_alert(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    event_id: String,
+    assignee: Option<String>,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.assign_alert(&event_id, assignee.as_deref()).await;
+    state
+        .audit("assign_alert", Some(&event_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to close an alert with an optional reason
+#[tauri::command]
+async fn close_alert(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    event_id: String,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.close_alert(&event_id, reason.as_deref()).await;
+    state
+        .audit("close_alert", Some(&event_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to reopen a closed alert
+#[tauri::command]
+async fn reopen_alert(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    event_id: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.reopen_alert(&event_id).await;
+    state
+        .audit("reopen_alert", Some(&event_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to open an incident from selected events
+#[tauri::command]
+async fn create_incident(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    title: String,
+    event_ids: Vec<String>,
+    correlation_key: Option<String>,
+) -> Result<Incident, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state
+        .create_incident(&title, &event_ids, correlation_key.as_deref())
+        .await;
+    state
+        .audit("create_incident", Some(&title), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list incidents, optionally `open` or `closed` only
+#[tauri::command]
+async fn get_incidents(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    status: Option<String>,
+) -> Result<Vec<Incident>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_incidents(status.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get the events in an incident
+#[tauri::command]
+async fn get_incident_events(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    incident_id: String,
+) -> Result<Vec<LogEvent>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_incident_events(&incident_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to add events to an incident
+#[tauri::command]
+async fn attach_incident_events(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    incident_id: String,
+    event_ids: Vec<String>,
+) -> Result<u64, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.attach_incident_events(&incident_id, &event_ids).await;
+    state
+        .audit("attach_incident_events", Some(&incident_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to remove events from an incident
+#[tauri::command]
+async fn detach_incident_events(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    incident_id: String,
+    event_ids: Vec<String>,
+) -> Result<u64, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.detach_incident_events(&incident_id, &event_ids).await;
+    state
+        .audit("detach_incident_events", Some(&incident_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to close an incident
+#[tauri::command]
+async fn close_incident(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    incident_id: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::Acknowledge)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.close_incident(&incident_id).await;
+    state
+        .audit("close_incident", Some(&incident_id), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to export an incident summary as Markdown
+#[tauri::command]
+async fn export_incident(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    incident_id: String,
+) -> Result<String, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .export_incident(&incident_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get alert handling SLA metrics (defaults to the last 7 days)
+#[tauri::command]
+async fn get_sla_metrics(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<guardian_sentinel_lib::sla::SlaMetrics>, String> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::days(7));
+
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_sla_metrics(from, to)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get an hour-of-day by day-of-week activity heat map
+/// (defaults to the last 30 days, bucketed in UTC)
+#[tauri::command]
+async fn get_activity_heatmap(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<guardian_sentinel_lib::database::HeatmapFilter>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    utc_offset_minutes: Option<i32>,
+) -> Result<guardian_sentinel_lib::database::Heatmap, String> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::days(30));
+
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_activity_heatmap(
+            &filter.unwrap_or_default(),
+            from,
+            to,
+            utc_offset_minutes.unwrap_or(0),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get event counts per time bucket by severity for trend
+/// charts (defaults to hourly buckets over the last 24 hours)
+#[tauri::command]
+async fn get_event_timeline(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    interval: Option<guardian_sentinel_lib::database::TimelineInterval>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    filter: Option<guardian_sentinel_lib::database::HeatmapFilter>,
+) -> Result<Vec<guardian_sentinel_lib::database::TimelineBucket>, String> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::hours(24));
+
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_event_timeline(
+            interval.unwrap_or(guardian_sentinel_lib::database::TimelineInterval::Hour),
+            from,
+            to,
+            &filter.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to rank hosts, rules, paths or remote addresses by event
+/// count for dashboards (defaults to the top 10 over the last 24 hours)
+#[tauri::command]
+async fn get_top_n(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    dimension: guardian_sentinel_lib::database::TopDimension,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<u32>,
+) -> Result<Vec<guardian_sentinel_lib::database::TopEntry>, String> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::hours(24));
+
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_top_n(dimension, from, to, limit.unwrap_or(10))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to write an HTML summary report (defaults to the last 7
+/// days) into the reports directory; returns the file's path
+#[tauri::command]
+async fn generate_report(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    range: Option<ReportRange>,
+    name: String,
+) -> Result<String, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ExportReports)
+        .await
+        .map_err(|e| e.to_string())?;
+    let range = range.unwrap_or_else(|| ReportRange::last_days(chrono::Utc::now(), 7));
+    let result = state.generate_report(range, &name).await;
+    state
+        .audit("generate_report", Some(&name), result)
+        .await
+        .map(|path| path.display().to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get database storage usage and projected growth
+#[tauri::command]
+async fn get_storage_report(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<guardian_sentinel_lib::storage::StorageReport, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.get_storage_report().await.map_err(|e| e.to_string())
+}
+
+/// Tauri command to preview how much a retention policy would reclaim
+#[tauri::command]
+async fn preview_prune(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    policy: guardian_sentinel_lib::storage::PrunePolicy,
+) -> Result<guardian_sentinel_lib::storage::PrunePreview, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .preview_prune(&policy)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to get what the connected daemon supports
+#[tauri::command]
+async fn get_agent_capabilities(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Option<AgentHello>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(state.agent_hello().cloned())
+}
+
+/// Tauri command to get the latest rule hit counts of every agent
+#[tauri::command]
+async fn get_rule_stats(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<RuleStatsReport>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(state.rule_stats())
+}
+
+/// Tauri command to ask the daemon for its rule hit counts now; they arrive
+/// as a `rule-stats` event
+#[tauri::command]
+async fn refresh_rule_stats(state: tauri::State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.request_rule_stats().await;
+    state
+        .audit("refresh_rule_stats", None, result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to make the daemon apply its config file again; the outcome
+/// arrives as a `config_reload` event
+#[tauri::command]
+async fn reload_agent_config(state: tauri::State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ManageAgents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.reload_agent_config().await;
+    state
+        .audit("reload_agent_config", None, result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list reporting hosts with their event counts
+#[tauri::command]
+async fn get_hosts(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<HostSummary>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.get_hosts().await.map_err(|e| e.to_string())
+}
+
+/// Tauri command to list collector agent enrollments
+#[tauri::command]
+async fn get_enrollments(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<Enrollment>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewEvents)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.get_enrollments().await.map_err(|e| e.to_string())
+}
+
+/// Tauri command to issue a one-time enrollment token for an agent
+#[tauri::command]
+async fn create_enrollment_token(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    hostname: String,
+    ttl_hours: Option<u32>,
+) -> Result<String, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ManageAgents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.create_enrollment_token(&hostname, ttl_hours).await;
+    state
+        .audit("create_enrollment_token", Some(&hostname), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to revoke an enrolled agent
+#[tauri::command]
+async fn revoke_agent(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    hostname: String,
+) -> Result<(), String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ManageAgents)
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = state.revoke_agent(&hostname).await;
+    state
+        .audit("revoke_agent", Some(&hostname), result)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command to list operator audit log entries, newest first
+#[tauri::command]
+async fn get_audit_log(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<AuditFilter>,
+    limit: Option<i64>,
+) -> Result<Vec<AuditEntry>, String> {
+    let state = state.lock().await;
+    state
+        .authorize(Permission::ViewAudit)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .get_audit_log(&filter.unwrap_or_default(), limit.unwrap_or(100))
+        .await
+        .map_err(|e| e.to_string())
+}
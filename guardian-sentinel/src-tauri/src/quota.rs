@@ -0,0 +1,251 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use guardian_common::Severity;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Highest sampling rate (keep 1 in N low-value events) an agent is asked to apply
+const MAX_SAMPLING_RATE: u32 = 64;
+
+/// Per-agent ingestion limits
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaConfig {
+    pub events_per_minute: u64,
+    pub bytes_per_day: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            events_per_minute: 600,
+            bytes_per_day: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl QuotaConfig {
+    /// Load limits from `GUARDIAN_QUOTA_EVENTS_PER_MINUTE` / `GUARDIAN_QUOTA_BYTES_PER_DAY`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let read = |key: &str, fallback: u64| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(fallback)
+        };
+
+        Self {
+            events_per_minute: read(
+                "GUARDIAN_QUOTA_EVENTS_PER_MINUTE",
+                default.events_per_minute,
+            ),
+            bytes_per_day: read("GUARDIAN_QUOTA_BYTES_PER_DAY", default.bytes_per_day),
+        }
+    }
+}
+
+/// Result of accounting one incoming event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaOutcome {
+    /// Whether the event should be stored
+    pub admitted: bool,
+    /// New sampling rate the agent should be told to apply, if it changed
+    pub sampling_change: Option<u32>,
+}
+
+/// Quota state for one agent, as shown in fleet views
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentQuotaStatus {
+    pub hostname: String,
+    pub events_this_minute: u64,
+    pub events_per_minute_limit: u64,
+    pub bytes_today: u64,
+    pub bytes_per_day_limit: u64,
+    pub dropped: u64,
+    pub sampling_rate: u32,
+    pub over_quota: bool,
+}
+
+#[derive(Debug)]
+struct AgentUsage {
+    minute_start: DateTime<Utc>,
+    minute_events: u64,
+    escalated_this_minute: bool,
+    day: NaiveDate,
+    day_bytes: u64,
+    dropped: u64,
+    sampling_rate: u32,
+}
+
+impl AgentUsage {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            minute_start: now,
+            minute_events: 0,
+            escalated_this_minute: false,
+            day: now.date_naive(),
+            day_bytes: 0,
+            dropped: 0,
+            sampling_rate: 1,
+        }
+    }
+
+    fn over_quota(&self, config: &QuotaConfig) -> bool {
+        self.minute_events > config.events_per_minute || self.day_bytes > config.bytes_per_day
+    }
+}
+
+/// Tracks ingestion per agent and decides which events are admitted
+#[derive(Debug, Default)]
+pub struct QuotaTracker {
+    config: QuotaConfig,
+    agents: HashMap<String, AgentUsage>,
+}
+
+impl QuotaTracker {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            agents: HashMap::new(),
+        }
+    }
+
+    /// Account an event of `bytes` serialized size from `hostname`.
+    /// High and Critical events are always admitted so quotas never hide alerts.
+    pub fn record(
+        &mut self,
+        hostname: &str,
+        bytes: u64,
+        severity: Severity,
+        now: DateTime<Utc>,
+    ) -> QuotaOutcome {
+        let config = self.config;
+        let usage = self
+            .agents
+            .entry(hostname.to_string())
+            .or_insert_with(|| AgentUsage::new(now));
+
+        let mut sampling_change = None;
+
+        if now - usage.minute_start >= Duration::minutes(1) {
+            // Relax sampling once a full window stays well under the limit
+            if usage.sampling_rate > 1 && usage.minute_events < config.events_per_minute / 2 {
+                usage.sampling_rate /= 2;
+                sampling_change = Some(usage.sampling_rate);
+            }
+            usage.minute_start = now;
+            usage.minute_events = 0;
+            usage.escalated_this_minute = false;
+        }
+
+        if now.date_naive() != usage.day {
+            usage.day = now.date_naive();
+            usage.day_bytes = 0;
+        }
+
+        usage.minute_events += 1;
+        usage.day_bytes += bytes;
+
+        let over_quota = usage.over_quota(&config);
+        if over_quota && !usage.escalated_this_minute && usage.sampling_rate < MAX_SAMPLING_RATE {
+            usage.sampling_rate *= 2;
+            usage.escalated_this_minute = true;
+            sampling_change = Some(usage.sampling_rate);
+        }
+
+        let admitted = !over_quota || severity >= Severity::High;
+        if !admitted {
+            usage.dropped += 1;
+        }
+
+        QuotaOutcome {
+            admitted,
+            sampling_change,
+        }
+    }
+
+    /// Current quota state for every agent seen so far
+    pub fn status(&self) -> Vec<AgentQuotaStatus> {
+        let mut status: Vec<_> = self
+            .agents
+            .iter()
+            .map(|(hostname, usage)| AgentQuotaStatus {
+                hostname: hostname.clone(),
+                events_this_minute: usage.minute_events,
+                events_per_minute_limit: self.config.events_per_minute,
+                bytes_today: usage.day_bytes,
+                bytes_per_day_limit: self.config.bytes_per_day,
+                dropped: usage.dropped,
+                sampling_rate: usage.sampling_rate,
+                over_quota: usage.over_quota(&self.config),
+            })
+            .collect();
+        status.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> QuotaTracker {
+        QuotaTracker::new(QuotaConfig {
+            events_per_minute: 4,
+            bytes_per_day: 1_000,
+        })
+    }
+
+    #[test]
+    fn test_over_quota_drops_low_severity_and_escalates_sampling() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+
+        for _ in 0..4 {
+            assert!(tracker.record("web-1", 10, Severity::Info, now).admitted);
+        }
+
+        let outcome = tracker.record("web-1", 10, Severity::Info, now);
+        assert!(!outcome.admitted);
+        assert_eq!(outcome.sampling_change, Some(2));
+
+        // Alerts still get through, and sampling is only escalated once per window
+        let outcome = tracker.record("web-1", 10, Severity::Critical, now);
+        assert!(outcome.admitted);
+        assert_eq!(outcome.sampling_change, None);
+
+        // Other agents are unaffected
+        assert!(tracker.record("db-1", 10, Severity::Info, now).admitted);
+
+        let status = tracker.status();
+        assert_eq!(status[1].hostname, "web-1");
+        assert_eq!(status[1].dropped, 1);
+        assert!(status[1].over_quota);
+    }
+
+    #[test]
+    fn test_quiet_window_relaxes_sampling() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+
+        for _ in 0..5 {
+            tracker.record("web-1", 10, Severity::Info, now);
+        }
+
+        let later = now + Duration::minutes(2);
+        tracker.record("web-1", 10, Severity::Info, later);
+
+        let later = later + Duration::minutes(1);
+        let outcome = tracker.record("web-1", 10, Severity::Info, later);
+        assert!(outcome.admitted);
+        assert_eq!(outcome.sampling_change, Some(1));
+    }
+
+    #[test]
+    fn test_daily_byte_quota() {
+        let mut tracker = tracker();
+        let now = Utc::now();
+
+        assert!(tracker.record("web-1", 900, Severity::Low, now).admitted);
+        assert!(!tracker.record("web-1", 200, Severity::Low, now).admitted);
+    }
+}
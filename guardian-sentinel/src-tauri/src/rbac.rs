@@ -0,0 +1,171 @@
+use crate::auth::{Principal, Scope};
+use guardian_common::{EventType, LogEvent, Severity};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// An operation guarded by role-based access control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Read events, stats, trends and fleet status
+    ViewEvents,
+    /// Acknowledge and resolve alerts
+    Acknowledge,
+    /// Write reports to disk
+    ExportReports,
+    /// Create, change or delete detection rules
+    EditRules,
+    /// Run response actions such as host isolation
+    RunResponse,
+    /// Enroll agents with the collector and revoke them
+    ManageAgents,
+    /// Read the operator audit log
+    ViewAudit,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 7] = [
+        Permission::ViewEvents,
+        Permission::Acknowledge,
+        Permission::ExportReports,
+        Permission::EditRules,
+        Permission::RunResponse,
+        Permission::ManageAgents,
+        Permission::ViewAudit,
+    ];
+
+    /// Name as it appears in role configuration
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::ViewEvents => "view_events",
+            Permission::Acknowledge => "acknowledge",
+            Permission::ExportReports => "export_reports",
+            Permission::EditRules => "edit_rules",
+            Permission::RunResponse => "run_response",
+            Permission::ManageAgents => "manage_agents",
+            Permission::ViewAudit => "view_audit",
+        }
+    }
+}
+
+impl Scope {
+    /// Name of the built-in role every principal with this scope holds
+    pub fn role_name(self) -> &'static str {
+        match self {
+            Scope::ReadOnly => "read_only",
+            Scope::Triage => "triage",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+/// Role definitions; anything not granted by one of a principal's roles is denied
+#[derive(Debug, Clone)]
+pub struct Rbac {
+    roles: HashMap<String, HashSet<Permission>>,
+}
+
+impl Default for Rbac {
+    fn default() -> Self {
+        Self::new(&HashMap::new())
+    }
+}
+
+impl Rbac {
+    /// Built-in roles for each scope, plus (or overridden by) configured roles
+    pub fn new(custom: &HashMap<String, Vec<Permission>>) -> Self {
+        let mut roles: HashMap<String, HashSet<Permission>> = HashMap::from([
+            (
+                Scope::ReadOnly.role_name().to_string(),
+                HashSet::from([Permission::ViewEvents]),
+            ),
+            (
+                Scope::Triage.role_name().to_string(),
+                HashSet::from([
+                    Permission::ViewEvents,
+                    Permission::Acknowledge,
+                    Permission::ExportReports,
+                ]),
+            ),
+            (
+                Scope::Admin.role_name().to_string(),
+                HashSet::from(Permission::ALL),
+            ),
+        ]);
+
+        for (name, permissions) in custom {
+            roles.insert(name.clone(), permissions.iter().copied().collect());
+        }
+
+        Self { roles }
+    }
+
+    /// Whether any of the principal's roles grants `permission`
+    pub fn permits(&self, principal: &Principal, permission: Permission) -> bool {
+        principal
+            .roles
+            .iter()
+            .filter_map(|role| self.roles.get(role))
+            .any(|granted| granted.contains(&permission))
+    }
+}
+
+/// Build an audit event recording a denied operation
+pub fn denial_event(hostname: &str, actor: &str, permission: Permission) -> LogEvent {
+    LogEvent::new(
+        Severity::Medium,
+        EventType::SystemLog {
+            source: "sentinel".to_string(),
+            level: "warn".to_string(),
+            message: format!("Denied {:?} to {}", permission, actor),
+        },
+        hostname.to_string(),
+    )
+    .with_tag("audit")
+    .with_tag("access_denied")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthMethod;
+
+    fn principal(roles: &[&str]) -> Principal {
+        Principal {
+            name: "alice".to_string(),
+            scope: Scope::ReadOnly,
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            method: AuthMethod::Token,
+        }
+    }
+
+    #[test]
+    fn test_builtin_roles() {
+        let rbac = Rbac::default();
+
+        let viewer = principal(&["read_only"]);
+        assert!(rbac.permits(&viewer, Permission::ViewEvents));
+        assert!(!rbac.permits(&viewer, Permission::Acknowledge));
+        assert!(!rbac.permits(&viewer, Permission::ExportReports));
+        assert!(!rbac.permits(&principal(&["triage"]), Permission::ViewAudit));
+
+        let admin = Principal::local();
+        assert!(Permission::ALL.iter().all(|p| rbac.permits(&admin, *p)));
+    }
+
+    #[test]
+    fn test_custom_roles_and_deny_by_default() {
+        let rbac = Rbac::new(&HashMap::from([(
+            "rule_author".to_string(),
+            vec![Permission::EditRules],
+        )]));
+
+        let author = principal(&["read_only", "rule_author"]);
+        assert!(rbac.permits(&author, Permission::EditRules));
+        assert!(!rbac.permits(&author, Permission::RunResponse));
+
+        // Unknown roles grant nothing
+        let stranger = principal(&["does_not_exist"]);
+        assert!(Permission::ALL.iter().all(|p| !rbac.permits(&stranger, *p)));
+    }
+}
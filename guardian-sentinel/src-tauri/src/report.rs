@@ -0,0 +1,507 @@
+//! Summary reports for compliance reviews, rendered as a self-contained HTML
+//! page that also prints cleanly.
+
+use crate::database::{self, TopDimension, TopEntry};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, SearchFilter, Severity};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Most critical findings and file changes listed one by one
+const MAX_LISTED: i64 = 100;
+
+/// Length of the ranked rule and host tables
+const TOP_N: u32 = 10;
+
+/// Period a report covers, `from` inclusive and `to` exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReportRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl ReportRange {
+    /// The `days` days up to `now`
+    pub fn last_days(now: DateTime<Utc>, days: i64) -> Self {
+        Self {
+            from: now - Duration::days(days),
+            to: now,
+        }
+    }
+}
+
+/// Events stored on one UTC day
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DailyCount {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub count: i64,
+}
+
+/// Database aggregates for one reporting period
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub range: ReportRange,
+    pub generated_at: DateTime<Utc>,
+    /// Console the report was generated on
+    pub console: String,
+    pub total_events: i64,
+    pub by_severity: BTreeMap<Severity, i64>,
+    pub daily: Vec<DailyCount>,
+    pub alerts_raised: i64,
+    pub alerts_closed: i64,
+    pub top_rules: Vec<TopEntry>,
+    pub top_hosts: Vec<TopEntry>,
+    /// Newest first, at most `MAX_LISTED`
+    pub critical: Vec<LogEvent>,
+    /// File integrity events per operation, e.g. `modify`
+    pub fim_by_operation: BTreeMap<String, i64>,
+    /// Newest first, at most `MAX_LISTED`
+    pub fim_changes: Vec<LogEvent>,
+}
+
+impl Report {
+    /// Aggregate the events stored in `range`
+    pub async fn build(pool: &SqlitePool, range: ReportRange, console: &str) -> Result<Self> {
+        if range.from >= range.to {
+            bail!("Report range must end after it starts");
+        }
+        let (from, to) = (range.from.to_rfc3339(), range.to.to_rfc3339());
+
+        let mut by_severity = BTreeMap::new();
+        let rows = sqlx::query(
+            "SELECT severity, COUNT(*) AS count FROM events
+             WHERE timestamp >= ? AND timestamp < ? GROUP BY severity",
+        )
+        .bind(&from)
+        .bind(&to)
+        .fetch_all(pool)
+        .await?;
+        for row in rows {
+            let severity: String = row.get("severity");
+            if let Ok(severity) = serde_json::from_value(serde_json::json!(severity)) {
+                by_severity.insert(severity, row.get("count"));
+            }
+        }
+
+        let daily = sqlx::query(
+            "SELECT substr(timestamp, 1, 10) AS day, COUNT(*) AS count FROM events
+             WHERE timestamp >= ? AND timestamp < ? GROUP BY day ORDER BY day",
+        )
+        .bind(&from)
+        .bind(&to)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| DailyCount {
+            day: row.get("day"),
+            count: row.get("count"),
+        })
+        .collect();
+
+        let (alerts_raised, alerts_closed): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COUNT(a.closed_at) FROM alerts a JOIN events e ON e.id = a.event_id
+             WHERE e.timestamp >= ? AND e.timestamp < ?",
+        )
+        .bind(&from)
+        .bind(&to)
+        .fetch_one(pool)
+        .await?;
+
+        let fim_by_operation = sqlx::query(
+            "SELECT json_extract(event_json(event_data, event_payload), '$.operation') AS operation, COUNT(*) AS count
+             FROM events
+             WHERE event_type = 'file_integrity' AND timestamp >= ? AND timestamp < ?
+             GROUP BY operation",
+        )
+        .bind(&from)
+        .bind(&to)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| {
+            let operation: Option<String> = row.get("operation");
+            (operation.unwrap_or_default(), row.get("count"))
+        })
+        .collect();
+
+        let in_range = SearchFilter {
+            from: Some(range.from),
+            to: Some(range.to),
+            ..SearchFilter::default()
+        };
+        let critical = SearchFilter {
+            severity: Some(Severity::Critical),
+            ..in_range.clone()
+        };
+        let fim = SearchFilter {
+            event_type: Some("file_integrity".to_string()),
+            ..in_range
+        };
+
+        Ok(Self {
+            range,
+            generated_at: Utc::now(),
+            console: console.to_string(),
+            total_events: by_severity.values().sum(),
+            by_severity,
+            daily,
+            alerts_raised,
+            alerts_closed,
+            top_rules: database::get_top_n(pool, TopDimension::Rules, range.from, range.to, TOP_N)
+                .await?,
+            top_hosts: database::get_top_n(pool, TopDimension::Hosts, range.from, range.to, TOP_N)
+                .await?,
+            critical: database::search_events(pool, &critical, MAX_LISTED, None)
+                .await?
+                .events,
+            fim_by_operation,
+            fim_changes: database::search_events(pool, &fim, MAX_LISTED, None)
+                .await?
+                .events,
+        })
+    }
+
+    /// Standalone HTML page; print styles keep tables intact on paper
+    pub fn to_html(&self) -> String {
+        let time = |t: &DateTime<Utc>| t.format("%Y-%m-%d %H:%M UTC").to_string();
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Guardian report {from} to {to}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; color: #1a1a1a; margin: 2em; }}
+h1 {{ margin-bottom: 0; }}
+.meta {{ color: #555; }}
+table {{ border-collapse: collapse; margin: 0.5em 0 1.5em; width: 100%; }}
+th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; font-size: 0.9em; }}
+th {{ background: #f0f0f0; }}
+td.num {{ text-align: right; }}
+.critical {{ color: #b00020; font-weight: bold; }}
+@media print {{
+  body {{ margin: 0; }}
+  section {{ break-inside: avoid-page; }}
+  tr {{ break-inside: avoid; }}
+}}
+</style>
+</head>
+<body>
+<h1>Guardian Security Report</h1>
+<p class="meta">{console} &middot; {from} to {to} &middot; generated {generated}</p>
+"#,
+            console = escape(&self.console),
+            from = time(&self.range.from),
+            to = time(&self.range.to),
+            generated = time(&self.generated_at),
+        );
+
+        html.push_str("<section>\n<h2>Summary</h2>\n<table>\n");
+        let _ = writeln!(
+            html,
+            "<tr><th>Events</th><td class=\"num\">{}</td></tr>",
+            self.total_events
+        );
+        for severity in [
+            Severity::Critical,
+            Severity::High,
+            Severity::Medium,
+            Severity::Low,
+            Severity::Info,
+        ] {
+            let _ = writeln!(
+                html,
+                "<tr><th>{:?}</th><td class=\"num\">{}</td></tr>",
+                severity,
+                self.by_severity.get(&severity).copied().unwrap_or(0)
+            );
+        }
+        let _ = writeln!(
+            html,
+            "<tr><th>Alerts raised</th><td class=\"num\">{}</td></tr>\n\
+             <tr><th>Alerts closed</th><td class=\"num\">{}</td></tr>",
+            self.alerts_raised, self.alerts_closed
+        );
+        html.push_str("</table>\n</section>\n");
+
+        html.push_str("<section>\n<h2>Event Volume</h2>\n");
+        table(
+            &mut html,
+            &["Day", "Events"],
+            self.daily
+                .iter()
+                .map(|d| vec![escape(&d.day), d.count.to_string()]),
+        );
+        html.push_str("</section>\n");
+
+        for (title, header, entries) in [
+            ("Top Rules", "Rule", &self.top_rules),
+            ("Top Hosts", "Host", &self.top_hosts),
+        ] {
+            let _ = writeln!(html, "<section>\n<h2>{}</h2>", title);
+            table(
+                &mut html,
+                &[header, "Events"],
+                entries
+                    .iter()
+                    .map(|e| vec![escape(&e.key), e.count.to_string()]),
+            );
+            html.push_str("</section>\n");
+        }
+
+        let _ = writeln!(
+            html,
+            "<section>\n<h2 class=\"critical\">Critical Findings</h2>{}",
+            listed_note(
+                self.critical.len(),
+                self.by_severity.get(&Severity::Critical)
+            )
+        );
+        table(
+            &mut html,
+            &["Time", "Host", "Rule", "Details"],
+            self.critical.iter().map(|e| {
+                vec![
+                    time(&e.timestamp),
+                    escape(&e.hostname),
+                    escape(e.rule_name.as_deref().unwrap_or("-")),
+                    escape(&describe(e)),
+                ]
+            }),
+        );
+        html.push_str("</section>\n");
+
+        let fim_total: i64 = self.fim_by_operation.values().sum();
+        let _ = writeln!(
+            html,
+            "<section>\n<h2>File Integrity Changes</h2>{}",
+            listed_note(self.fim_changes.len(), Some(&fim_total))
+        );
+        table(
+            &mut html,
+            &["Operation", "Changes"],
+            self.fim_by_operation
+                .iter()
+                .map(|(operation, count)| vec![escape(operation), count.to_string()]),
+        );
+        table(
+            &mut html,
+            &["Time", "Host", "Severity", "Change"],
+            self.fim_changes.iter().map(|e| {
+                vec![
+                    time(&e.timestamp),
+                    escape(&e.hostname),
+                    format!("{:?}", e.severity),
+                    escape(&describe(e)),
+                ]
+            }),
+        );
+        html.push_str("</section>\n</body>\n</html>\n");
+        html
+    }
+}
+
+/// Where a report named `name` is written: directly in `dir`, as HTML. Names
+/// that would leave `dir` are refused.
+pub fn report_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    let (Some(Component::Normal(file)), None) = (components.next(), components.next()) else {
+        bail!("Report name {:?} must be a plain file name", name);
+    };
+    let mut path = dir.join(file);
+    if path.extension().is_none_or(|ext| ext != "html") {
+        path.as_mut_os_string().push(".html");
+    }
+    Ok(path)
+}
+
+/// Build the report for `range` and write it as HTML to `path`
+pub async fn generate_report(
+    pool: &SqlitePool,
+    range: ReportRange,
+    path: &Path,
+    console: &str,
+) -> Result<Report> {
+    let report = Report::build(pool, range, console).await?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, report.to_html()).await?;
+    Ok(report)
+}
+
+/// Note under a heading when only the newest events are listed
+fn listed_note(listed: usize, total: Option<&i64>) -> String {
+    match total {
+        Some(&total) if total > listed as i64 => {
+            format!(
+                "\n<p class=\"meta\">Newest {} of {} shown.</p>",
+                listed, total
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+/// Append a table, or a placeholder when there are no rows
+fn table(html: &mut String, headers: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+    let mut rows = rows.peekable();
+    if rows.peek().is_none() {
+        html.push_str("<p class=\"meta\">None in this period.</p>\n");
+        return;
+    }
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", header);
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{}</td>", cell);
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+/// One-line description of what an event is about
+fn describe(event: &LogEvent) -> String {
+    match &event.event_type {
+        EventType::FileIntegrity {
+            path, operation, ..
+        } => format!("{:?} {}", operation, path),
+        EventType::ProcessMonitor { pid, name, .. } => format!("Process {} ({})", name, pid),
+        EventType::NetworkSocket {
+            local_addr,
+            remote_addr,
+            protocol,
+            ..
+        } => format!(
+            "{} {} -> {}",
+            protocol,
+            local_addr,
+            remote_addr.as_deref().unwrap_or("-")
+        ),
+        EventType::AuthEvent {
+            user,
+            source_ip,
+            outcome,
+            ..
+        } => format!(
+            "{:?} login for {} from {}",
+            outcome,
+            user,
+            source_ip.as_deref().unwrap_or("local")
+        ),
+        EventType::SystemLog { message, .. } => message.clone(),
+        EventType::Alert { title, .. } => title.clone(),
+        other => other.kind().to_string(),
+    }
+}
+
+/// Escape text for HTML element content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guardian_common::FileOperation;
+
+    #[tokio::test]
+    async fn test_report_aggregates_and_renders() {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let pool = database::init_database(&path).await.unwrap();
+
+        let change = LogEvent::new(
+            Severity::Critical,
+            EventType::FileIntegrity {
+                path: "/etc/<shadow>".to_string(),
+                operation: FileOperation::Modify,
+                hash: None,
+                user: None,
+                executable: None,
+                process: None,
+                diff: None,
+            },
+            "web-1".to_string(),
+        )
+        .with_rule("Critical File Modified");
+        let log = LogEvent::new(
+            Severity::Info,
+            EventType::SystemLog {
+                source: "cron".to_string(),
+                level: "info".to_string(),
+                message: "job ran".to_string(),
+            },
+            "db-1".to_string(),
+        );
+        for event in [&change, &log] {
+            database::insert_event(&pool, event).await.unwrap();
+        }
+
+        let range = ReportRange::last_days(Utc::now() + Duration::minutes(1), 7);
+        let out = std::env::temp_dir()
+            .join(format!("guardian-report-{}", uuid::Uuid::new_v4()))
+            .join("report.html");
+        let report = generate_report(&pool, range, &out, "console-1")
+            .await
+            .unwrap();
+
+        assert_eq!(report.total_events, 2);
+        assert_eq!(report.by_severity.get(&Severity::Critical), Some(&1));
+        assert_eq!(report.alerts_raised, 1);
+        assert_eq!(report.alerts_closed, 0);
+        assert_eq!(report.top_rules[0].key, "Critical File Modified");
+        assert_eq!(report.critical.len(), 1);
+        assert_eq!(report.fim_by_operation.get("modify"), Some(&1));
+
+        let html = std::fs::read_to_string(&out).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("@media print"));
+        assert!(html.contains("<td>Modify /etc/&lt;shadow&gt;</td>"));
+        assert!(!html.contains("<shadow>"));
+
+        let empty = ReportRange {
+            from: range.to,
+            to: range.from,
+        };
+        assert!(Report::build(&pool, empty, "console-1").await.is_err());
+    }
+
+    #[test]
+    fn test_report_path_stays_in_reports_dir() {
+        let dir = Path::new("/data/reports");
+        assert_eq!(
+            report_path(dir, "2026-03").unwrap(),
+            dir.join("2026-03.html")
+        );
+        assert_eq!(
+            report_path(dir, "march.html").unwrap(),
+            dir.join("march.html")
+        );
+        for name in ["", "..", "../march.html", "/etc/cron.d/x", "sub/march.html"] {
+            assert!(report_path(dir, name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// How long an operator has to confirm a requested isolation
+const CONFIRMATION_WINDOW_SECS: i64 = 60;
+
+pub use guardian_common::control::MAX_ISOLATION_SECS;
+
+/// Isolation requested by an operator that still needs confirmation
+#[derive(Debug, Clone)]
+pub struct PendingIsolation {
+    token: String,
+    pub hostname: String,
+    pub allow: Vec<String>,
+    pub duration_secs: u64,
+    expires_at: DateTime<Utc>,
+}
+
+/// Confirmation challenge returned to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct IsolationChallenge {
+    pub token: String,
+    pub hostname: String,
+    pub allow: Vec<String>,
+    pub duration_secs: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PendingIsolation {
+    /// Create a pending isolation of `hostname` with a fresh confirmation
+    /// token. Without an allowed peer the agent could not even be released.
+    pub fn new(hostname: &str, allow: Vec<String>, duration_secs: u64) -> Result<Self> {
+        if allow.is_empty() {
+            bail!("Isolation needs at least one allowed peer");
+        }
+        if duration_secs == 0 || duration_secs > MAX_ISOLATION_SECS {
+            bail!(
+                "Isolation duration must be between 1 and {} seconds",
+                MAX_ISOLATION_SECS
+            );
+        }
+
+        Ok(Self {
+            token: Uuid::new_v4().to_string(),
+            hostname: hostname.to_string(),
+            allow,
+            duration_secs,
+            expires_at: Utc::now() + Duration::seconds(CONFIRMATION_WINDOW_SECS),
+        })
+    }
+
+    /// Challenge the operator has to echo back
+    pub fn challenge(&self) -> IsolationChallenge {
+        IsolationChallenge {
+            token: self.token.clone(),
+            hostname: self.hostname.clone(),
+            allow: self.allow.clone(),
+            duration_secs: self.duration_secs,
+            expires_at: self.expires_at,
+        }
+    }
+
+    /// Check a confirmation token against this request
+    pub fn verify(&self, token: &str) -> Result<()> {
+        if Utc::now() > self.expires_at {
+            bail!("Isolation request expired, request it again");
+        }
+        if token != self.token {
+            bail!("Confirmation token does not match the pending isolation request");
+        }
+        Ok(())
+    }
+}
+
+/// Build an audit event recording an operator response action
+pub fn audit_event(hostname: &str, message: String) -> LogEvent {
+    LogEvent::new(
+        Severity::Medium,
+        EventType::SystemLog {
+            source: "sentinel".to_string(),
+            level: "info".to_string(),
+            message,
+        },
+        hostname.to_string(),
+    )
+    .with_tag("audit")
+    .with_tag("response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isolation_needs_an_allowed_peer() {
+        assert!(PendingIsolation::new("web-1", Vec::new(), 3600).is_err());
+
+        let pending =
+            PendingIsolation::new("web-1", vec!["10.0.0.5:7443".to_string()], 3600).unwrap();
+        assert_eq!(pending.challenge().hostname, "web-1");
+    }
+
+    #[test]
+    fn test_isolation_duration_is_bounded() {
+        let allow = vec!["10.0.0.5:7443".to_string()];
+        assert!(PendingIsolation::new("web-1", allow.clone(), 0).is_err());
+        assert!(PendingIsolation::new("web-1", allow.clone(), MAX_ISOLATION_SECS + 1).is_err());
+        assert!(PendingIsolation::new("web-1", allow, MAX_ISOLATION_SECS).is_ok());
+    }
+}
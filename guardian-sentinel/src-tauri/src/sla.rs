@@ -0,0 +1,418 @@
+use crate::database::{self, AlertTiming};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::{EventType, LogEvent, Severity};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::fmt::Write as _;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+/// Window the Prometheus endpoint and breach checks look back over
+const METRICS_WINDOW_DAYS: i64 = 7;
+
+/// Quantiles reported for handling times
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// SLA targets for Critical alerts
+#[derive(Debug, Clone, Default)]
+pub struct SlaConfig {
+    /// Seconds a Critical alert may stay unacknowledged
+    pub critical_ack_secs: Option<u64>,
+
+    /// Seconds a Critical alert may stay unresolved
+    pub critical_resolve_secs: Option<u64>,
+
+    /// Address serving Prometheus metrics, e.g. `127.0.0.1:9464`
+    pub metrics_addr: Option<String>,
+}
+
+impl SlaConfig {
+    /// Read `GUARDIAN_SLA_CRITICAL_ACK_SECS`, `GUARDIAN_SLA_CRITICAL_RESOLVE_SECS`
+    /// and `GUARDIAN_METRICS_ADDR`; unset values disable the feature
+    pub fn from_env() -> Self {
+        let secs = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+        Self {
+            critical_ack_secs: secs("GUARDIAN_SLA_CRITICAL_ACK_SECS"),
+            critical_resolve_secs: secs("GUARDIAN_SLA_CRITICAL_RESOLVE_SECS"),
+            metrics_addr: std::env::var("GUARDIAN_METRICS_ADDR").ok(),
+        }
+    }
+}
+
+/// Handling-time percentiles in seconds
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct Percentiles {
+    pub count: usize,
+    pub sum: f64,
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+impl Percentiles {
+    fn from_samples(mut samples: Vec<f64>) -> Self {
+        samples.sort_by(|a, b| a.total_cmp(b));
+        let at = |q: f64| percentile(&samples, q);
+        Self {
+            count: samples.len(),
+            sum: samples.iter().sum(),
+            p50: at(QUANTILES[0]),
+            p90: at(QUANTILES[1]),
+            p99: at(QUANTILES[2]),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Alert handling metrics for one severity
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SlaMetrics {
+    pub severity: Severity,
+    pub alerts: usize,
+    pub open: usize,
+    pub time_to_ack: Percentiles,
+    pub time_to_resolve: Percentiles,
+    /// Alerts that missed the configured ack or resolve target (Critical only)
+    pub breaches: usize,
+}
+
+/// Compute per-severity metrics from alert timings
+pub fn compute_metrics(
+    timings: &[AlertTiming],
+    config: &SlaConfig,
+    now: DateTime<Utc>,
+) -> Vec<SlaMetrics> {
+    let secs =
+        |from: DateTime<Utc>, to: DateTime<Utc>| (to - from).num_milliseconds() as f64 / 1000.0;
+
+    [
+        Severity::Critical,
+        Severity::High,
+        Severity::Medium,
+        Severity::Low,
+        Severity::Info,
+    ]
+    .into_iter()
+    .filter_map(|severity| {
+        let alerts: Vec<_> = timings.iter().filter(|t| t.severity == severity).collect();
+        if alerts.is_empty() {
+            return None;
+        }
+
+        // Resolving an alert counts as acknowledging it
+        let ack_times = alerts
+            .iter()
+            .filter_map(|t| first_handled(t).map(|at| secs(t.raised_at, at)))
+            .collect();
+        let resolve_times = alerts
+            .iter()
+            .filter_map(|t| t.resolved_at.map(|at| secs(t.raised_at, at)))
+            .collect();
+
+        Some(SlaMetrics {
+            severity,
+            alerts: alerts.len(),
+            open: alerts.iter().filter(|t| t.resolved_at.is_none()).count(),
+            time_to_ack: Percentiles::from_samples(ack_times),
+            time_to_resolve: Percentiles::from_samples(resolve_times),
+            breaches: alerts
+                .iter()
+                .filter(|t| !breaches(t, config, now).is_empty())
+                .count(),
+        })
+    })
+    .collect()
+}
+
+/// Earliest of acknowledgement and resolution
+fn first_handled(timing: &AlertTiming) -> Option<DateTime<Utc>> {
+    match (timing.acknowledged_at, timing.resolved_at) {
+        (Some(a), Some(r)) => Some(a.min(r)),
+        (a, r) => a.or(r),
+    }
+}
+
+/// SLA kinds (`ack`, `resolve`) a Critical alert has missed as of `now`
+fn breaches(timing: &AlertTiming, config: &SlaConfig, now: DateTime<Utc>) -> Vec<&'static str> {
+    if timing.severity != Severity::Critical {
+        return Vec::new();
+    }
+
+    let missed = |target: Option<u64>, done: Option<DateTime<Utc>>| {
+        target.is_some_and(|secs| {
+            let deadline = timing.raised_at + Duration::seconds(secs as i64);
+            done.unwrap_or(now) > deadline
+        })
+    };
+
+    let mut kinds = Vec::new();
+    if missed(config.critical_ack_secs, first_handled(timing)) {
+        kinds.push("ack");
+    }
+    if missed(config.critical_resolve_secs, timing.resolved_at) {
+        kinds.push("resolve");
+    }
+    kinds
+}
+
+/// Render metrics in the Prometheus text exposition format
+pub fn render_prometheus(metrics: &[SlaMetrics]) -> String {
+    let mut out = String::new();
+    let label = |m: &SlaMetrics| format!("{:?}", m.severity).to_uppercase();
+
+    let _ = writeln!(
+        out,
+        "# HELP guardian_alerts Alerts raised in the last {} days",
+        METRICS_WINDOW_DAYS
+    );
+    let _ = writeln!(out, "# TYPE guardian_alerts gauge");
+    for m in metrics {
+        let _ = writeln!(
+            out,
+            "guardian_alerts{{severity=\"{}\"}} {}",
+            label(m),
+            m.alerts
+        );
+    }
+
+    let _ = writeln!(out, "# HELP guardian_alerts_open Unresolved alerts");
+    let _ = writeln!(out, "# TYPE guardian_alerts_open gauge");
+    for m in metrics {
+        let _ = writeln!(
+            out,
+            "guardian_alerts_open{{severity=\"{}\"}} {}",
+            label(m),
+            m.open
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP guardian_alert_sla_breaches Alerts that missed their SLA"
+    );
+    let _ = writeln!(out, "# TYPE guardian_alert_sla_breaches gauge");
+    for m in metrics {
+        let _ = writeln!(
+            out,
+            "guardian_alert_sla_breaches{{severity=\"{}\"}} {}",
+            label(m),
+            m.breaches
+        );
+    }
+
+    for (name, help, pick) in [
+        (
+            "guardian_alert_time_to_ack_seconds",
+            "Time from alert to first acknowledgement",
+            (|m: &SlaMetrics| &m.time_to_ack) as fn(&SlaMetrics) -> &Percentiles,
+        ),
+        (
+            "guardian_alert_time_to_resolve_seconds",
+            "Time from alert to resolution",
+            |m: &SlaMetrics| &m.time_to_resolve,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for m in metrics {
+            let p = pick(m);
+            let severity = label(m);
+            for (q, value) in QUANTILES.iter().zip([p.p50, p.p90, p.p99]) {
+                if let Some(value) = value {
+                    let _ = writeln!(
+                        out,
+                        "{}{{severity=\"{}\",quantile=\"{}\"}} {}",
+                        name, severity, q, value
+                    );
+                }
+            }
+            let _ = writeln!(out, "{}_sum{{severity=\"{}\"}} {}", name, severity, p.sum);
+            let _ = writeln!(
+                out,
+                "{}_count{{severity=\"{}\"}} {}",
+                name, severity, p.count
+            );
+        }
+    }
+
+    out
+}
+
+/// Metrics over the standard look-back window
+pub async fn current_metrics(pool: &SqlitePool, config: &SlaConfig) -> Result<Vec<SlaMetrics>> {
+    let now = Utc::now();
+    let timings =
+        database::get_alert_timings(pool, now - Duration::days(METRICS_WINDOW_DAYS), now).await?;
+    Ok(compute_metrics(&timings, config, now))
+}
+
+/// Build the alert raised when a Critical alert misses its SLA
+fn breach_event(hostname: &str, timing: &AlertTiming, kind: &str) -> LogEvent {
+    LogEvent::new(
+        Severity::High,
+        EventType::SystemLog {
+            source: "sentinel".to_string(),
+            level: "warn".to_string(),
+            message: format!(
+                "Critical alert {} raised at {} breached its {} SLA",
+                timing.event_id,
+                timing.raised_at.to_rfc3339(),
+                kind
+            ),
+        },
+        hostname.to_string(),
+    )
+    .with_tag("sla_breach")
+    .with_rule(format!("sla_{}_breach", kind))
+}
+
+/// Raise an alert, once, for every Critical alert that misses a configured SLA
+pub async fn run_breach_monitor(pool: SqlitePool, config: SlaConfig, hostname: String) {
+    if config.critical_ack_secs.is_none() && config.critical_resolve_secs.is_none() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = check_breaches(&pool, &config, &hostname).await {
+            warn!("SLA breach check failed: {}", e);
+        }
+    }
+}
+
+async fn check_breaches(pool: &SqlitePool, config: &SlaConfig, hostname: &str) -> Result<()> {
+    let now = Utc::now();
+    let timings =
+        database::get_alert_timings(pool, now - Duration::days(METRICS_WINDOW_DAYS), now).await?;
+
+    for timing in &timings {
+        for kind in breaches(timing, config, now) {
+            if database::record_sla_breach(pool, &timing.event_id, kind).await? {
+                warn!(
+                    "Critical alert {} breached its {} SLA",
+                    timing.event_id, kind
+                );
+                database::insert_event(pool, &breach_event(hostname, timing, kind)).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Serve `GET /metrics` in Prometheus format
+pub async fn serve_metrics(addr: String, pool: SqlitePool, config: SlaConfig) -> Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Serving Prometheus metrics on http://{}/metrics", addr);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let pool = pool.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+
+            let response = if request.starts_with("GET /metrics ") {
+                match current_metrics(&pool, &config).await {
+                    Ok(metrics) => {
+                        let body = render_prometheus(&metrics);
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    Err(e) => {
+                        warn!("Failed to compute metrics: {}", e);
+                        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                    }
+                }
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(
+        severity: Severity,
+        ack_after: Option<i64>,
+        resolve_after: Option<i64>,
+    ) -> AlertTiming {
+        let raised_at = "2026-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        AlertTiming {
+            event_id: uuid::Uuid::new_v4().to_string(),
+            severity,
+            raised_at,
+            acknowledged_at: ack_after.map(|s| raised_at + Duration::seconds(s)),
+            resolved_at: resolve_after.map(|s| raised_at + Duration::seconds(s)),
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        let p = Percentiles::from_samples(samples);
+        assert_eq!(p.p50, Some(50.0));
+        assert_eq!(p.p90, Some(90.0));
+        assert_eq!(p.p99, Some(99.0));
+        assert_eq!(Percentiles::from_samples(Vec::new()).p50, None);
+    }
+
+    #[test]
+    fn test_metrics_and_breaches() {
+        let config = SlaConfig {
+            critical_ack_secs: Some(300),
+            critical_resolve_secs: Some(3600),
+            metrics_addr: None,
+        };
+        let timings = vec![
+            timing(Severity::Critical, Some(60), Some(600)),
+            // Acknowledged late, never resolved
+            timing(Severity::Critical, Some(900), None),
+            // Resolved without an explicit ack counts as acknowledged
+            timing(Severity::Low, None, Some(120)),
+        ];
+        let now = "2026-03-01T14:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let metrics = compute_metrics(&timings, &config, now);
+        assert_eq!(metrics.len(), 2);
+
+        let critical = &metrics[0];
+        assert_eq!(critical.severity, Severity::Critical);
+        assert_eq!(critical.open, 1);
+        assert_eq!(critical.time_to_ack.count, 2);
+        assert_eq!(critical.time_to_ack.p90, Some(900.0));
+        assert_eq!(critical.breaches, 1);
+        assert_eq!(breaches(&timings[1], &config, now), vec!["ack", "resolve"]);
+
+        let low = &metrics[1];
+        assert_eq!(low.time_to_ack.p50, Some(120.0));
+        assert_eq!(low.breaches, 0);
+
+        let text = render_prometheus(&metrics);
+        assert!(text.contains(
+            "guardian_alert_time_to_ack_seconds{severity=\"CRITICAL\",quantile=\"0.5\"} 60"
+        ));
+        assert!(text.contains("guardian_alerts_open{severity=\"CRITICAL\"} 1"));
+    }
+}
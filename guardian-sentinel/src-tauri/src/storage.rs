@@ -0,0 +1,357 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use guardian_common::Severity;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+/// Days of recent history used to estimate growth
+const GROWTH_WINDOW_DAYS: i64 = 7;
+
+/// Days ahead the growth projection covers
+const PROJECTION_DAYS: i64 = 30;
+
+/// Approximate stored size of an events row, in bytes
+const ROW_BYTES: &str = "length(id) + length(timestamp) + length(severity) + length(event_type) \
+     + length(event_data) + COALESCE(length(event_payload), 0) + length(hostname) + length(tags) \
+     + COALESCE(length(rule_name), 0)";
+
+/// On-disk size of a table or index
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ObjectUsage {
+    pub name: String,
+    /// `table` or `index`
+    pub kind: String,
+    /// Table the object belongs to
+    pub table: String,
+    pub bytes: i64,
+}
+
+/// Rows and approximate bytes for one group of events
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UsageBucket {
+    pub key: String,
+    pub rows: i64,
+    pub bytes: i64,
+}
+
+/// Recent ingestion rate and where it leads
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GrowthEstimate {
+    /// Days of history the rate is based on (shorter for young databases)
+    pub window_days: f64,
+    pub rows_per_day: f64,
+    pub bytes_per_day: f64,
+    pub projection_days: i64,
+    pub projected_bytes: i64,
+}
+
+/// Where the database's space goes
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StorageReport {
+    pub database_bytes: i64,
+    /// Pages on the freelist, reusable without growing the file
+    pub free_bytes: i64,
+    /// Per table and index; empty if the SQLite build lacks the `dbstat` table
+    pub objects: Vec<ObjectUsage>,
+    pub by_event_type: Vec<UsageBucket>,
+    pub by_severity: Vec<UsageBucket>,
+    pub by_agent: Vec<UsageBucket>,
+    pub growth: GrowthEstimate,
+}
+
+/// A retention policy to preview; events matching every set field would be pruned
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PrunePolicy {
+    pub older_than_days: Option<u32>,
+    /// Only prune events at or below this severity
+    pub max_severity: Option<Severity>,
+    pub hostname: Option<String>,
+    /// Never prune events that triggered a rule
+    pub keep_alerts: bool,
+}
+
+/// What a prune policy would remove
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PrunePreview {
+    pub rows: i64,
+    /// Row data plus the matching share of the events table's index space
+    pub bytes: i64,
+    pub remaining_rows: i64,
+    pub remaining_bytes: i64,
+}
+
+fn severity_label(severity: Severity) -> String {
+    serde_json::to_string(&severity)
+        .unwrap_or_default()
+        .trim_matches('"')
+        .to_string()
+}
+
+async fn pragma(pool: &SqlitePool, name: &str) -> Result<i64> {
+    Ok(sqlx::query_scalar(&format!("PRAGMA {}", name))
+        .fetch_one(pool)
+        .await?)
+}
+
+/// Sizes of tables and indexes from the `dbstat` virtual table
+async fn object_usage(pool: &SqlitePool) -> Result<Vec<ObjectUsage>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT s.name AS name, COALESCE(m.type, 'table') AS kind,
+               COALESCE(m.tbl_name, s.name) AS tbl, SUM(s.pgsize) AS bytes
+        FROM dbstat s
+        LEFT JOIN sqlite_master m ON m.name = s.name
+        GROUP BY s.name
+        ORDER BY bytes DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ObjectUsage {
+            name: row.get("name"),
+            kind: row.get("kind"),
+            table: row.get("tbl"),
+            bytes: row.get("bytes"),
+        })
+        .collect())
+}
+
+async fn breakdown(pool: &SqlitePool, key: &str) -> Result<Vec<UsageBucket>> {
+    let sql = format!(
+        "SELECT COALESCE({key}, 'unknown') AS key, COUNT(*) AS rows, SUM({ROW_BYTES}) AS bytes \
+         FROM events GROUP BY 1 ORDER BY bytes DESC"
+    );
+    let rows = sqlx::query(&sql).fetch_all(pool).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UsageBucket {
+            key: row.get("key"),
+            rows: row.get("rows"),
+            bytes: row.get("bytes"),
+        })
+        .collect())
+}
+
+async fn growth(pool: &SqlitePool, now: DateTime<Utc>) -> Result<GrowthEstimate> {
+    let since = now - Duration::days(GROWTH_WINDOW_DAYS);
+    let row = sqlx::query(&format!(
+        "SELECT COUNT(*) AS rows, COALESCE(SUM({ROW_BYTES}), 0) AS bytes, \
+         (SELECT MIN(timestamp) FROM events) AS oldest \
+         FROM events WHERE timestamp >= ?"
+    ))
+    .bind(since.to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    let rows: i64 = row.get("rows");
+    let bytes: i64 = row.get("bytes");
+    let oldest = row
+        .get::<Option<String>, _>("oldest")
+        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+        .map(|t| t.with_timezone(&Utc));
+
+    // A database younger than the window is measured over its actual age, but at
+    // least an hour so a handful of fresh events does not extrapolate wildly
+    let window = oldest.map_or(Duration::days(GROWTH_WINDOW_DAYS), |oldest| {
+        (now - oldest).clamp(Duration::hours(1), Duration::days(GROWTH_WINDOW_DAYS))
+    });
+    let window_days = window.num_seconds() as f64 / 86_400.0;
+    let bytes_per_day = bytes as f64 / window_days;
+
+    Ok(GrowthEstimate {
+        window_days,
+        rows_per_day: rows as f64 / window_days,
+        bytes_per_day,
+        projection_days: PROJECTION_DAYS,
+        projected_bytes: (bytes_per_day * PROJECTION_DAYS as f64) as i64,
+    })
+}
+
+/// Build the storage breakdown
+pub async fn storage_report(pool: &SqlitePool, now: DateTime<Utc>) -> Result<StorageReport> {
+    let page_size = pragma(pool, "page_size").await?;
+
+    let objects = object_usage(pool).await.unwrap_or_else(|e| {
+        tracing::debug!("Per-object sizes unavailable: {}", e);
+        Vec::new()
+    });
+
+    Ok(StorageReport {
+        database_bytes: pragma(pool, "page_count").await? * page_size,
+        free_bytes: pragma(pool, "freelist_count").await? * page_size,
+        objects,
+        by_event_type: breakdown(pool, "event_type").await?,
+        by_severity: breakdown(pool, "severity").await?,
+        by_agent: breakdown(pool, "hostname").await?,
+        growth: growth(pool, now).await?,
+    })
+}
+
+/// Preview how much `policy` would reclaim without deleting anything
+pub async fn preview_prune(
+    pool: &SqlitePool,
+    policy: &PrunePolicy,
+    now: DateTime<Utc>,
+) -> Result<PrunePreview> {
+    let mut conditions = vec!["1 = 1".to_string()];
+    if policy.older_than_days.is_some() {
+        conditions.push("timestamp < ?1".to_string());
+    }
+    if let Some(max) = policy.max_severity {
+        let allowed: Vec<String> = [
+            Severity::Info,
+            Severity::Low,
+            Severity::Medium,
+            Severity::High,
+            Severity::Critical,
+        ]
+        .into_iter()
+        .filter(|s| *s <= max)
+        .map(|s| format!("'{}'", severity_label(s)))
+        .collect();
+        conditions.push(format!("severity IN ({})", allowed.join(", ")));
+    }
+    if policy.hostname.is_some() {
+        conditions.push("hostname = ?2".to_string());
+    }
+    if policy.keep_alerts {
+        conditions.push("rule_triggered = 0".to_string());
+    }
+
+    let sql = format!(
+        "SELECT COUNT(*) AS total_rows, COALESCE(SUM({ROW_BYTES}), 0) AS total_bytes, \
+         COALESCE(SUM(CASE WHEN {cond} THEN 1 ELSE 0 END), 0) AS rows, \
+         COALESCE(SUM(CASE WHEN {cond} THEN {ROW_BYTES} ELSE 0 END), 0) AS bytes \
+         FROM events",
+        cond = conditions.join(" AND ")
+    );
+    let cutoff = policy
+        .older_than_days
+        .map(|days| (now - Duration::days(days as i64)).to_rfc3339());
+    let row = sqlx::query(&sql)
+        .bind(cutoff)
+        .bind(&policy.hostname)
+        .fetch_one(pool)
+        .await?;
+
+    let total_rows: i64 = row.get("total_rows");
+    let total_bytes: i64 = row.get("total_bytes");
+    let rows: i64 = row.get("rows");
+    let data_bytes: i64 = row.get("bytes");
+
+    // Index entries go with their rows, so attribute index space proportionally
+    let index_bytes: i64 = object_usage(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|o| o.kind == "index" && o.table == "events")
+        .map(|o| o.bytes)
+        .sum();
+    let index_share = if total_rows > 0 {
+        index_bytes * rows / total_rows
+    } else {
+        0
+    };
+
+    Ok(PrunePreview {
+        rows,
+        bytes: data_bytes + index_share,
+        remaining_rows: total_rows - rows,
+        remaining_bytes: total_bytes - data_bytes + index_bytes - index_share,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{init_database, insert_event};
+    use guardian_common::{EventType, LogEvent};
+
+    async fn seeded_pool(now: DateTime<Utc>) -> SqlitePool {
+        let path = std::env::temp_dir().join(format!("guardian-test-{}.db", uuid::Uuid::new_v4()));
+        let pool = init_database(&path).await.unwrap();
+
+        for (host, severity, age_days, alert) in [
+            ("web-1", Severity::Info, 40, false),
+            ("web-1", Severity::Info, 1, false),
+            ("web-1", Severity::High, 40, true),
+            ("db-1", Severity::Low, 2, false),
+        ] {
+            let mut event = LogEvent::new(
+                severity,
+                EventType::SystemLog {
+                    source: "test".to_string(),
+                    level: "info".to_string(),
+                    message: "hello".to_string(),
+                },
+                host.to_string(),
+            );
+            event.timestamp = now - Duration::days(age_days);
+            event.rule_triggered = alert;
+            insert_event(&pool, &event).await.unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_storage_report_breakdown() {
+        let now = Utc::now();
+        let pool = seeded_pool(now).await;
+
+        let report = storage_report(&pool, now).await.unwrap();
+        assert!(report.database_bytes > 0);
+        assert!(report
+            .objects
+            .iter()
+            .any(|o| o.name == "idx_timestamp" && o.kind == "index"));
+
+        assert_eq!(report.by_event_type.len(), 1);
+        assert_eq!(report.by_event_type[0].key, "system_log");
+        assert_eq!(report.by_event_type[0].rows, 4);
+
+        let web = report.by_agent.iter().find(|b| b.key == "web-1").unwrap();
+        assert_eq!(web.rows, 3);
+        assert!(report
+            .by_severity
+            .iter()
+            .any(|b| b.key == "HIGH" && b.rows == 1));
+
+        // Two events in the last week, measured over the full window
+        assert_eq!(report.growth.window_days, GROWTH_WINDOW_DAYS as f64);
+        assert!((report.growth.rows_per_day - 2.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_preview_prune() {
+        let now = Utc::now();
+        let pool = seeded_pool(now).await;
+
+        let policy = PrunePolicy {
+            older_than_days: Some(30),
+            ..Default::default()
+        };
+        let preview = preview_prune(&pool, &policy, now).await.unwrap();
+        assert_eq!(preview.rows, 2);
+        assert_eq!(preview.remaining_rows, 2);
+        assert!(preview.bytes > 0);
+
+        let policy = PrunePolicy {
+            older_than_days: Some(30),
+            keep_alerts: true,
+            ..Default::default()
+        };
+        assert_eq!(preview_prune(&pool, &policy, now).await.unwrap().rows, 1);
+
+        let policy = PrunePolicy {
+            max_severity: Some(Severity::Low),
+            hostname: Some("db-1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(preview_prune(&pool, &policy, now).await.unwrap().rows, 1);
+    }
+}
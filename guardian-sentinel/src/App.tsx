@@ -1,10 +1,13 @@
 import { useState } from "react";
 import { useEvents } from "./hooks/useEvents";
+import { useAgentCapabilities } from "./hooks/useAgentCapabilities";
+import { useHosts } from "./hooks/useHosts";
 import { Sidebar } from "./components/layout/Sidebar";
 
 import { MonitoringDashboard } from "./components/dashboard/MonitoringDashboard";
 import { EventsTable } from "./components/events/EventsTable";
 import { SearchBar } from "./components/common/SearchBar";
+import { HostSelect } from "./components/common/HostSelect";
 import { Pagination } from "./components/common/Pagination";
 import "./components/layout/Sidebar.css";
 import "./components/events/EventsTable.css";
@@ -13,7 +16,20 @@ import "./components/common/Pagination.css";
 import "./App.css";
 
 function App() {
-  const { events, query, setQuery, page, setPage, limit, total } = useEvents();
+  const {
+    events,
+    query,
+    setQuery,
+    hostname,
+    setHostname,
+    page,
+    setPage,
+    limit,
+    total,
+    hasNextPage,
+  } = useEvents();
+  const { agent, supports } = useAgentCapabilities();
+  const { hosts } = useHosts();
   const [currentView, setView] = useState("dashboard");
 
   return (
@@ -35,11 +51,34 @@ function App() {
                   setPage(1);
                 }}
               />
+              {hosts.length > 1 && (
+                <HostSelect
+                  hosts={hosts}
+                  value={hostname}
+                  onChange={(h) => {
+                    setHostname(h);
+                    setPage(1);
+                  }}
+                />
+              )}
             </div>
           )}
 
           <div className="header-actions">
             <span className="live-badge">● Live</span>
+            {agent && (
+              <span className="agent-badge">
+                {agent.hostname} v{agent.agent_version}
+              </span>
+            )}
+            {!supports("isolate_host") && (
+              <span
+                className="agent-badge muted"
+                title="nft is not available on this agent"
+              >
+                Isolation unavailable
+              </span>
+            )}
           </div>
         </header>
 
@@ -61,6 +100,7 @@ function App() {
                 page={page}
                 total={total}
                 limit={limit}
+                hasNext={hasNextPage}
                 onPageChange={setPage}
               />
             </div>
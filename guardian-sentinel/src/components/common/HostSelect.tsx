@@ -0,0 +1,29 @@
+import React from "react";
+import { HostSummary } from "../../types";
+
+interface HostSelectProps {
+  hosts: HostSummary[];
+  value: string;
+  onChange: (hostname: string) => void;
+}
+
+export const HostSelect: React.FC<HostSelectProps> = ({
+  hosts,
+  value,
+  onChange,
+}) => {
+  return (
+    <select
+      className="host-select"
+      value={value}
+      onChange={(e) => onChange(e.target.value)}
+    >
+      <option value="">All hosts</option>
+      {hosts.map((host) => (
+        <option key={host.hostname} value={host.hostname}>
+          {host.hostname} ({host.events}){host.offline_since ? " - offline" : ""}
+        </option>
+      ))}
+    </select>
+  );
+};
@@ -4,6 +4,8 @@ interface PaginationProps {
   page: number;
   total: number;
   limit: number;
+  /** Whether the server reported a further page */
+  hasNext?: boolean;
   onPageChange: (page: number) => void;
 }
 
@@ -11,6 +13,7 @@ export const Pagination: React.FC<PaginationProps> = ({
   page,
   total,
   limit,
+  hasNext,
   onPageChange,
 }) => {
   const totalPages = Math.ceil(total / limit);
@@ -30,7 +33,7 @@ export const Pagination: React.FC<PaginationProps> = ({
         Page {page} of {totalPages}
       </span>
       <button
-        disabled={page >= totalPages}
+        disabled={page >= totalPages || hasNext === false}
         onClick={() => onPageChange(page + 1)}
         className="page-btn"
       >
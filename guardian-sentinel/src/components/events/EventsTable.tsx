@@ -1,5 +1,5 @@
 import React from "react";
-import { LogEvent } from "../../types";
+import { EventUser, LogEvent } from "../../types";
 import { getSeverityColor, formatDate } from "../../utils/formatting";
 
 interface EventsTableProps {
@@ -71,12 +71,26 @@ export const EventsTable: React.FC<EventsTableProps> = ({ events }) => {
   );
 };
 
+const formatUser = (user: EventUser) =>
+  user.username ? `${user.username} (${user.uid})` : `uid ${user.uid}`;
+
 const EventDetails: React.FC<{ event: LogEvent }> = ({ event }) => {
   if (event.type === "file_integrity") {
     return (
       <span className="event-detail">
         <span className="op">{event.operation}</span> on{" "}
         <span className="path">{event.path}</span>
+        {event.user && <> (owner {formatUser(event.user)})</>}
+        {event.executable && (
+          <span title={event.executable.libraries?.join(", ")}>
+            {" "}
+            [{event.executable.format.toUpperCase()} {event.executable.arch}
+            {event.executable.signed === false && (
+              <span className="warn"> unsigned</span>
+            )}
+            ]
+          </span>
+        )}
       </span>
     );
   }
@@ -86,6 +100,7 @@ const EventDetails: React.FC<{ event: LogEvent }> = ({ event }) => {
       <span className="event-detail">
         Process <span className="highlight">{event.name}</span> (PID:{" "}
         {event.pid}) using <span className="warn">{event.cpu_usage}% CPU</span>
+        {event.user && <> as {formatUser(event.user)}</>}
       </span>
     );
   }
@@ -100,6 +115,56 @@ const EventDetails: React.FC<{ event: LogEvent }> = ({ event }) => {
     );
   }
 
+  if (event.type === "auth_event") {
+    return (
+      <span className="event-detail">
+        {event.outcome === "success" ? "Login" : "Failed login"} by{" "}
+        <span className="highlight">{event.user}</span> via {event.method}
+        {event.source_ip && <> from {event.source_ip}</>}
+        {event.tty && <> on {event.tty}</>}
+      </span>
+    );
+  }
+
+  if (event.type === "package_change") {
+    return (
+      <span className="event-detail">
+        {event.manager} {event.action}:{" "}
+        <span className="highlight">{event.name}</span>
+        {event.version && <> {event.version}</>}
+      </span>
+    );
+  }
+
+  if (event.type === "alert") {
+    return (
+      <span className="event-detail" title={event.description}>
+        <span className="highlight">{event.title}</span>
+        {event.technique && <> [{event.technique}]</>} (
+        {event.related_event_ids.length} related events)
+      </span>
+    );
+  }
+
+  if (event.type === "scan_summary") {
+    return (
+      <span className="event-detail" title={event.error}>
+        Scan of <span className="path">{event.path}</span>{" "}
+        {event.outcome === "failed" ? (
+          <span className="warn">failed</span>
+        ) : (
+          <>
+            covered {event.files_scanned} files,{" "}
+            <span className={event.files_matched ? "warn" : "highlight"}>
+              {event.files_matched} matched
+            </span>
+          </>
+        )}{" "}
+        ({event.requested_by})
+      </span>
+    );
+  }
+
   if (event.type === "system_log") {
     return (
       <span className="event-detail">
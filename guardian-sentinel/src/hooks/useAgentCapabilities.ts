@@ -0,0 +1,29 @@
+import { useState, useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { AgentHello } from "../types";
+import { EventService } from "../services/eventService";
+
+export const useAgentCapabilities = () => {
+  const [agent, setAgent] = useState<AgentHello | null>(null);
+
+  useEffect(() => {
+    EventService.getAgentCapabilities()
+      .then(setAgent)
+      .catch((error) => console.error("Failed to load capabilities:", error));
+
+    // The daemon announces itself again whenever it restarts
+    const unlisten = listen<AgentHello>("agent-hello", (event) => {
+      setAgent(event.payload);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Agents that never announced capabilities are assumed to support everything
+  const supports = (command: string) =>
+    !agent || agent.capabilities.commands.includes(command);
+
+  return { agent, supports };
+};
@@ -7,9 +7,12 @@ export const useEvents = () => {
   const [events, setEvents] = useState<LogEvent[]>([]);
   const [stats, setStats] = useState<AppStats | null>(null);
   const [query, setQuery] = useState("");
+  const [hostname, setHostname] = useState("");
   const [page, setPage] = useState(1);
   const [limit] = useState(50);
   const [total, setTotal] = useState(0); // Approximate from stats
+  // Cursor each page starts after, filled in as pages are visited
+  const [cursors, setCursors] = useState<(string | null)[]>([null]);
 
   useEffect(() => {
     loadData();
@@ -17,7 +20,11 @@ export const useEvents = () => {
     // Listen for real-time events
     const unlisten = listen<LogEvent>("log-event", (event) => {
       // Only prepend if we are on the first page and not searching
-      if (page === 1 && !query) {
+      if (
+        page === 1 &&
+        !query &&
+        (!hostname || event.payload.hostname === hostname)
+      ) {
         setEvents((prev) => [event.payload, ...prev].slice(0, limit));
       }
       loadStats();
@@ -26,7 +33,7 @@ export const useEvents = () => {
     return () => {
       unlisten.then((fn) => fn());
     };
-  }, [page, query, limit]); // Reload when these change
+  }, [page, query, hostname, limit]); // Reload when these change
 
   const loadData = async () => {
     await Promise.all([loadEvents(), loadStats()]);
@@ -34,18 +41,18 @@ export const useEvents = () => {
 
   const loadEvents = async () => {
     try {
-      const offset = (page - 1) * limit;
-      let data: LogEvent[] = [];
-
-      if (query) {
-        data = await EventService.searchEvents(query, undefined, limit, offset);
-      } else {
-        // Use search with empty query to support pagination if getRecentEvents doesn't
-        // Or assume getRecentEvents supports limit, but not offset?
-        // Let's use searchEvents with empty query for consistent pagination
-        data = await EventService.searchEvents("", undefined, limit, offset);
-      }
-      setEvents(data);
+      // Keyset pages stay stable while new events arrive
+      const data = await EventService.searchEvents(
+        { query: query || undefined, hostname: hostname || undefined },
+        limit,
+        cursors[page - 1] ?? undefined
+      );
+      setEvents(data.events);
+      setCursors((prev) => {
+        const next = prev.slice(0, page);
+        next[page] = data.next_cursor;
+        return next;
+      });
     } catch (error) {
       console.error("Failed to load events:", error);
     }
@@ -53,7 +60,7 @@ export const useEvents = () => {
 
   const loadStats = async () => {
     try {
-      const data = await EventService.getStats();
+      const data = await EventService.getStats(hostname);
       setStats(data);
       if (data && data.total) {
         setTotal(data.total);
@@ -70,9 +77,12 @@ export const useEvents = () => {
     loadStats,
     query,
     setQuery,
+    hostname,
+    setHostname,
     page,
     setPage,
     limit,
     total,
+    hasNextPage: cursors[page] != null,
   };
 };
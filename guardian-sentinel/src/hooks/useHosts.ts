@@ -0,0 +1,21 @@
+import { useState, useEffect } from "react";
+import { HostSummary } from "../types";
+import { EventService } from "../services/eventService";
+
+// Hosts reporting to this console, refreshed periodically as agents connect
+export const useHosts = (refreshMs: number = 30000) => {
+  const [hosts, setHosts] = useState<HostSummary[]>([]);
+
+  useEffect(() => {
+    const load = () =>
+      EventService.getHosts()
+        .then(setHosts)
+        .catch((error) => console.error("Failed to load hosts:", error));
+
+    load();
+    const timer = setInterval(load, refreshMs);
+    return () => clearInterval(timer);
+  }, [refreshMs]);
+
+  return { hosts };
+};
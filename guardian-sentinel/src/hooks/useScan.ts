@@ -0,0 +1,44 @@
+import { useState, useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { LogEvent } from "../types";
+import { EventService } from "../services/eventService";
+
+export const useScan = () => {
+  const [scanId, setScanId] = useState<string | null>(null);
+  const [received, setReceived] = useState<LogEvent[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    // Listen before any scan starts: the daemon can answer before
+    // scan_path returns the id
+    const unlisten = listen<LogEvent>("scan-progress", (event) => {
+      setReceived((prev) => [...prev, event.payload]);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const start = async (path: string) => {
+    setReceived([]);
+    setError(null);
+    try {
+      setScanId(await EventService.scanPath(path));
+    } catch (e) {
+      setScanId(null);
+      setError(String(e));
+    }
+  };
+
+  // Every event of one scan carries its id as a tag
+  const events = scanId
+    ? received.filter((event) => event.tags.includes(`scan:${scanId}`))
+    : [];
+  const matches = events.filter((event) => event.rule_triggered);
+  const progress = events.filter((event) => event.type === "scan_progress").pop();
+  const summary = events.find((event) => event.type === "scan_summary");
+  const running = scanId !== null && !summary;
+
+  return { scanId, events, matches, progress, summary, running, error, start };
+};
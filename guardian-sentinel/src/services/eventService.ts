@@ -1,26 +1,232 @@
 import { invoke } from "@tauri-apps/api/core";
-import { LogEvent } from "../types";
+import {
+  AgentHello,
+  Alert,
+  AlertStatus,
+  AuditEntry,
+  AuditFilter,
+  Enrollment,
+  EventPage,
+  FileVersion,
+  HostSummary,
+  Incident,
+  IncidentStatus,
+  LogEvent,
+  ReportRange,
+  RuleDefinition,
+  RuleStatsReport,
+  RuleTest,
+  SavedSearch,
+  SearchFilter,
+  TagCount,
+  TimelineBucket,
+  TimelineFilter,
+  TimelineInterval,
+  TopDimension,
+  TopEntry,
+} from "../types";
 
 export const EventService = {
-  async getRecentEvents(limit: number = 100): Promise<LogEvent[]> {
-    return invoke<LogEvent[]>("get_recent_events", { limit });
+  async getRecentEvents(
+    limit: number = 100,
+    hostname?: string,
+    cursor?: string
+  ): Promise<EventPage> {
+    return invoke<EventPage>("get_recent_events", {
+      limit,
+      hostname: hostname || null,
+      cursor: cursor || null,
+    });
   },
 
-  async getStats(): Promise<any> {
-    return invoke("get_event_stats");
+  async getStats(hostname?: string): Promise<any> {
+    return invoke("get_event_stats", { hostname: hostname || null });
   },
 
   async searchEvents(
-    query: string,
-    severity?: string,
+    filter: SearchFilter,
     limit: number = 100,
-    offset: number = 0
-  ): Promise<LogEvent[]> {
-    return invoke("search_events", {
-      query,
-      severity: severity || null,
+    cursor?: string
+  ): Promise<EventPage> {
+    return invoke<EventPage>("search_events", {
+      filter,
+      limit,
+      cursor: cursor || null,
+    });
+  },
+
+  async getTagCounts(filter: SearchFilter, limit?: number): Promise<TagCount[]> {
+    return invoke<TagCount[]>("get_tag_counts", { filter, limit });
+  },
+
+  async getSavedSearches(): Promise<SavedSearch[]> {
+    return invoke<SavedSearch[]>("get_saved_searches");
+  },
+
+  async createSavedSearch(
+    name: string,
+    filter: SearchFilter,
+    windowSecs?: number
+  ): Promise<SavedSearch> {
+    return invoke<SavedSearch>("create_saved_search", { name, filter, windowSecs });
+  },
+
+  async updateSavedSearch(
+    id: string,
+    name: string,
+    filter: SearchFilter,
+    windowSecs?: number
+  ): Promise<SavedSearch> {
+    return invoke<SavedSearch>("update_saved_search", { id, name, filter, windowSecs });
+  },
+
+  async deleteSavedSearch(id: string): Promise<void> {
+    return invoke("delete_saved_search", { id });
+  },
+
+  async runSavedSearch(id: string, limit?: number, cursor?: string): Promise<EventPage> {
+    return invoke<EventPage>("run_saved_search", { id, limit, cursor: cursor || null });
+  },
+
+  async getAlerts(status?: AlertStatus, assignee?: string, limit?: number): Promise<Alert[]> {
+    return invoke<Alert[]>("get_alerts", {
+      status: status || null,
+      assignee: assignee || null,
       limit,
-      offset,
     });
   },
+
+  async acknowledgeAlert(eventId: string): Promise<void> {
+    return invoke("acknowledge_alert", { eventId });
+  },
+
+  async assignAlert(eventId: string, assignee?: string): Promise<void> {
+    return invoke("assign_alert", { eventId, assignee: assignee || null });
+  },
+
+  async closeAlert(eventId: string, reason?: string): Promise<void> {
+    return invoke("close_alert", { eventId, reason: reason || null });
+  },
+
+  async reopenAlert(eventId: string): Promise<void> {
+    return invoke("reopen_alert", { eventId });
+  },
+
+  async createIncident(
+    title: string,
+    eventIds: string[],
+    correlationKey?: string
+  ): Promise<Incident> {
+    return invoke<Incident>("create_incident", {
+      title,
+      eventIds,
+      correlationKey: correlationKey || null,
+    });
+  },
+
+  async getIncidents(status?: IncidentStatus): Promise<Incident[]> {
+    return invoke<Incident[]>("get_incidents", { status: status || null });
+  },
+
+  async getIncidentEvents(incidentId: string): Promise<LogEvent[]> {
+    return invoke<LogEvent[]>("get_incident_events", { incidentId });
+  },
+
+  async attachIncidentEvents(incidentId: string, eventIds: string[]): Promise<number> {
+    return invoke<number>("attach_incident_events", { incidentId, eventIds });
+  },
+
+  async detachIncidentEvents(incidentId: string, eventIds: string[]): Promise<number> {
+    return invoke<number>("detach_incident_events", { incidentId, eventIds });
+  },
+
+  async closeIncident(incidentId: string): Promise<void> {
+    return invoke("close_incident", { incidentId });
+  },
+
+  async exportIncident(incidentId: string): Promise<string> {
+    return invoke<string>("export_incident", { incidentId });
+  },
+
+  async getRelatedEvents(alertId: string): Promise<LogEvent[]> {
+    return invoke<LogEvent[]>("get_related_events", { alertId });
+  },
+
+  async getAgentCapabilities(): Promise<AgentHello | null> {
+    return invoke<AgentHello | null>("get_agent_capabilities");
+  },
+
+  async getHosts(): Promise<HostSummary[]> {
+    return invoke<HostSummary[]>("get_hosts");
+  },
+
+  async getEventTimeline(
+    interval: TimelineInterval,
+    from?: string,
+    to?: string,
+    filter?: TimelineFilter
+  ): Promise<TimelineBucket[]> {
+    return invoke<TimelineBucket[]>("get_event_timeline", { interval, from, to, filter });
+  },
+
+  async getTopN(
+    dimension: TopDimension,
+    limit?: number,
+    from?: string,
+    to?: string
+  ): Promise<TopEntry[]> {
+    return invoke<TopEntry[]>("get_top_n", { dimension, from, to, limit });
+  },
+
+  async generateReport(name: string, range?: ReportRange): Promise<string> {
+    return invoke("generate_report", { name, range: range || null });
+  },
+
+  async getEnrollments(): Promise<Enrollment[]> {
+    return invoke<Enrollment[]>("get_enrollments");
+  },
+
+  async createEnrollmentToken(hostname: string, ttlHours?: number): Promise<string> {
+    return invoke<string>("create_enrollment_token", { hostname, ttlHours });
+  },
+
+  async revokeAgent(hostname: string): Promise<void> {
+    return invoke("revoke_agent", { hostname });
+  },
+
+  async scanPath(path: string): Promise<string> {
+    return invoke<string>("scan_path", { path });
+  },
+
+  async testRule(
+    rule: RuleDefinition,
+    range?: ReportRange,
+    samples?: number
+  ): Promise<RuleTest> {
+    return invoke<RuleTest>("test_rule", { rule, range, samples });
+  },
+
+  async getRuleStats(): Promise<RuleStatsReport[]> {
+    return invoke<RuleStatsReport[]>("get_rule_stats");
+  },
+
+  async refreshRuleStats(): Promise<void> {
+    return invoke("refresh_rule_stats");
+  },
+
+  async reloadAgentConfig(): Promise<void> {
+    return invoke("reload_agent_config");
+  },
+
+  async getFileVersions(path: string): Promise<FileVersion[]> {
+    return invoke<FileVersion[]>("get_file_versions", { path });
+  },
+
+  async restoreFileVersion(path: string, hash: string): Promise<void> {
+    return invoke("restore_file_version", { path, hash });
+  },
+
+  async getAuditLog(filter: AuditFilter = {}, limit?: number): Promise<AuditEntry[]> {
+    return invoke<AuditEntry[]>("get_audit_log", { filter, limit });
+  },
 };
@@ -1,11 +1,35 @@
+export interface EventUser {
+  uid: number;
+  username?: string;
+}
+
+export interface ExecutableInfo {
+  format: "elf" | "pe";
+  arch: string;
+  library: boolean;
+  libraries?: string[];
+  sections?: string[];
+  signed?: boolean;
+  entropy: number;
+}
+
 export type EventType =
-  | { type: "file_integrity"; path: string; operation: string; hash?: string }
+  | {
+      type: "file_integrity";
+      path: string;
+      operation: string;
+      hash?: string;
+      user?: EventUser;
+      executable?: ExecutableInfo;
+      process?: { pid: number; name: string };
+    }
   | {
       type: "process_monitor";
       pid: number;
       name: string;
       cpu_usage: number;
       memory_usage: number;
+      user?: EventUser;
     }
   | {
       type: "network_socket";
@@ -13,17 +37,80 @@ export type EventType =
       remote_addr?: string;
       protocol: string;
       state: string;
+      traffic?: {
+        bytes_sent: number;
+        bytes_received: number;
+        packets: number;
+        sni?: string;
+        http_host?: string;
+      };
+    }
+  | { type: "system_log"; source: string; level: string; message: string }
+  | {
+      type: "auth_event";
+      user: string;
+      source_ip?: string;
+      method: string;
+      outcome: "success" | "failure";
+      tty?: string;
+    }
+  | {
+      type: "package_change";
+      name: string;
+      version?: string;
+      action: "install" | "upgrade" | "downgrade" | "remove";
+      manager: string;
     }
-  | { type: "system_log"; source: string; level: string; message: string };
+  | {
+      type: "heartbeat";
+      agent_version: string;
+      uptime_secs: number;
+      interval_secs: number;
+      forward?: { pending: number; dropped: number };
+    }
+  | {
+      type: "alert";
+      title: string;
+      description: string;
+      related_event_ids: string[];
+      technique?: string;
+    }
+  | {
+      type: "scan_progress";
+      scan_id: string;
+      path: string;
+      files_scanned: number;
+      files_total?: number;
+      current_path?: string;
+      percent?: number;
+    }
+  | {
+      type: "scan_summary";
+      scan_id: string;
+      path: string;
+      requested_by: string;
+      outcome: "completed" | "failed";
+      files_scanned: number;
+      files_matched: number;
+      dirs_skipped: number;
+      duration_ms: number;
+      error?: string;
+    };
 
 export type LogEvent = {
+  schema_version: number;
   id: string;
   timestamp: string;
   severity: string;
   hostname: string;
   tags: string[];
   rule_triggered: boolean;
+  /** The most severe of `rules_matched` */
   rule_name?: string;
+  /** Every rule the event triggered, most severe first */
+  rules_matched?: string[];
+  /** HMAC-SHA256 of the rest of the event in hex, when the agent signs events */
+  signature?: string;
 } & EventType;
 
 export interface AppStats {
@@ -31,3 +118,232 @@ export interface AppStats {
   by_severity: Record<string, number>;
   rules_triggered: number;
 }
+
+export interface AgentCapabilities {
+  collectors: string[];
+  sinks: string[];
+  event_types: string[];
+  commands: string[];
+  encodings: string[];
+}
+
+export interface AgentHello {
+  hostname: string;
+  agent_version: string;
+  schema_version: number;
+  capabilities: AgentCapabilities;
+  /** HMAC-SHA256 of the rest of the hello in hex, when the agent signs what it emits */
+  signature?: string;
+}
+
+/** Search criteria; every field that is set must match */
+export interface SearchFilter {
+  query?: string;
+  severity?: string;
+  /** RFC 3339, inclusive */
+  from?: string;
+  /** RFC 3339, exclusive */
+  to?: string;
+  event_type?: string;
+  hostname?: string;
+  tag?: string;
+  rule_name?: string;
+  /** Username or numeric uid */
+  user?: string;
+}
+
+export type AlertStatus = "open" | "acknowledged" | "closed";
+
+export interface Alert {
+  event: LogEvent;
+  status: AlertStatus;
+  assignee: string | null;
+  close_reason: string | null;
+  acknowledged_by: string | null;
+  acknowledged_at: string | null;
+  closed_by: string | null;
+  closed_at: string | null;
+  updated_at: string;
+}
+
+export type IncidentStatus = "open" | "closed";
+
+export interface Incident {
+  id: string;
+  title: string;
+  status: IncidentStatus;
+  /** `rule@hostname`; later hits of that rule on that host join while open */
+  correlation_key: string | null;
+  created_by: string;
+  created_at: string;
+  updated_at: string;
+  closed_at: string | null;
+  event_count: number;
+}
+
+export interface SavedSearch {
+  id: string;
+  name: string;
+  filter: SearchFilter;
+  /** Searches the last this many seconds, counted from each run */
+  window_secs: number | null;
+  created_by: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface EventPage {
+  events: LogEvent[];
+  /** Opaque position to fetch the next page from; null on the last page */
+  next_cursor: string | null;
+}
+
+export interface TagCount {
+  tag: string;
+  count: number;
+}
+
+export interface FileVersion {
+  hash: string;
+  first_seen: string | null;
+  current: boolean;
+}
+
+export interface HostSummary {
+  hostname: string;
+  agent_version: string | null;
+  address: string | null;
+  first_seen: string | null;
+  last_seen: string | null;
+  events: number;
+  last_event: string | null;
+  last_heartbeat: string | null;
+  uptime_secs: number | null;
+  forward_pending: number | null;
+  forward_dropped: number | null;
+  offline_since: string | null;
+}
+
+export type TimelineInterval = "minute" | "hour";
+
+export interface TimelineFilter {
+  query?: string;
+  severity?: string;
+  hostname?: string;
+}
+
+export interface TimelineBucket {
+  bucket: string;
+  total: number;
+  by_severity: Record<string, number>;
+}
+
+export type TopDimension = "hosts" | "rules" | "paths" | "remote_addresses";
+
+export interface TopEntry {
+  key: string;
+  count: number;
+}
+
+export type EnrollmentStatus = "pending" | "enrolled" | "revoked";
+
+export interface Enrollment {
+  hostname: string;
+  status: EnrollmentStatus;
+  created_at: string;
+  token_expires: string;
+  enrolled_at: string | null;
+  revoked_at: string | null;
+  fingerprint: string | null;
+}
+
+export type AuditOutcome = "success" | "failure" | "denied";
+
+export interface AuditEntry {
+  id: number;
+  timestamp: string;
+  actor: string;
+  /** Command name, e.g. `close_alert` */
+  action: string;
+  target: string | null;
+  outcome: AuditOutcome;
+  error: string | null;
+}
+
+export interface AuditFilter {
+  actor?: string;
+  action?: string;
+  from?: string;
+  to?: string;
+}
+
+/** Period a report covers; `to` is exclusive */
+export interface ReportRange {
+  from: string;
+  to: string;
+}
+
+export interface FieldCondition {
+  field: string;
+  equals?: unknown;
+  one_of?: unknown[];
+  contains?: string;
+  starts_with?: string;
+  ends_with?: string;
+  exists?: boolean;
+  matches?: string;
+}
+
+export interface RuleDefinition {
+  name: string;
+  event_type?: string;
+  min_severity?: string;
+  tags?: string[];
+  fields?: FieldCondition[];
+  cel?: string;
+  script?: string;
+  schedule?: RuleSchedule;
+  actions?: RuleActions;
+}
+
+/** Times a rule applies at; `from`/`to` as "HH:MM", days as "mon".."sun", in `timezone` or local time */
+export interface RuleSchedule {
+  from?: string;
+  to?: string;
+  days?: string[];
+  /** IANA zone, e.g. "Europe/Berlin" */
+  timezone?: string;
+}
+
+export type RuleResponse =
+  | { action: "kill_process" }
+  | { action: "isolate_host"; allow?: string[]; duration_secs: number };
+
+export interface RuleActions {
+  set_severity?: string;
+  add_tags?: string[];
+  suppress?: boolean;
+  respond?: RuleResponse;
+}
+
+export interface RuleStats {
+  rule: string;
+  evaluated: number;
+  matched: number;
+}
+
+/** Rule hit counts of one agent, as kept from its latest report */
+export interface RuleStatsReport {
+  hostname: string;
+  since: string;
+  until: string;
+  rules: RuleStats[];
+}
+
+export interface RuleTest {
+  evaluated: number;
+  matched: number;
+  already_triggered: number;
+  matched_by_host: Record<string, number>;
+  samples: LogEvent[];
+}